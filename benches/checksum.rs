@@ -0,0 +1,38 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rouilledb::common::{checksum, hash64};
+
+/// The sizes of the inputs: a small value, a page and a block of a backup.
+const SIZES: [usize; 3] = [64, 4096, 64 * 1024];
+
+/// A function measured, returning its checksum or hash widened to 64 bits.
+type Function = fn(&[u8]) -> u64;
+
+/// The time each function is measured for, at each size.
+const DURATION: Duration = Duration::from_millis(500);
+
+/// Measures the throughput of the checksums and hashes of `common`, run with `cargo bench`.
+fn main() {
+    let data: Vec<u8> = (0..SIZES[SIZES.len() - 1])
+        .map(|i| (i * 31) as u8)
+        .collect();
+    let functions: [(&str, Function); 3] = [
+        ("crc32c", |bytes| u64::from(checksum::crc32c(bytes))),
+        ("xxhash64", |bytes| checksum::xxhash64(bytes, 0)),
+        ("hash64", hash64),
+    ];
+
+    for size in SIZES {
+        for (name, function) in functions {
+            let start = Instant::now();
+            let mut bytes = 0;
+            while start.elapsed() < DURATION {
+                black_box(function(black_box(&data[..size])));
+                bytes += size;
+            }
+            let throughput = bytes as f64 / start.elapsed().as_secs_f64() / (1 << 30) as f64;
+            println!("{name:>8} {size:>6} bytes: {throughput:>6.2} GiB/s");
+        }
+    }
+}
@@ -0,0 +1,329 @@
+/// Number of children of a [Children::Node4].
+const NODE4_CAPACITY: usize = 4;
+
+/// Number of children of a [Children::Node16].
+const NODE16_CAPACITY: usize = 16;
+
+/// Number of children of a [Children::Node48].
+const NODE48_CAPACITY: usize = 48;
+
+/// Number of children below which a [Children::Node256] shrinks to a [Children::Node48]. The
+/// thresholds are lower than the capacity of the smaller kind so a node that alternates between
+/// an insertion and a removal is not converted every time.
+const NODE256_SHRINK: usize = 40;
+
+/// Number of children below which a [Children::Node48] shrinks to a [Children::Node16].
+const NODE48_SHRINK: usize = 12;
+
+/// Number of children below which a [Children::Node16] shrinks to a [Children::Node4].
+const NODE16_SHRINK: usize = 3;
+
+/// The children of an inner node of an [Art](super::Art), indexed by the next byte of the key.
+///
+/// The representation adapts to the number of children, so sparse nodes stay small while dense
+/// nodes are indexed directly:
+/// - a `Node4` or a `Node16` keeps the bytes sorted in an array next to the children
+/// - a `Node48` maps each of the 256 bytes to the slot of its child
+/// - a `Node256` stores a child for each of the 256 bytes
+pub(super) enum Children<T> {
+    Node4 {
+        bytes: Vec<u8>,
+        children: Vec<T>,
+    },
+    Node16 {
+        bytes: Vec<u8>,
+        children: Vec<T>,
+    },
+    Node48 {
+        slots: Box<[u8; 256]>,
+        children: Vec<T>,
+    },
+    Node256 {
+        children: Box<[Option<T>; 256]>,
+        len: usize,
+    },
+}
+
+impl<T> Children<T> {
+    /// Creates an empty `Node4`.
+    pub(super) fn new() -> Self {
+        Children::Node4 {
+            bytes: Vec::with_capacity(NODE4_CAPACITY),
+            children: Vec::with_capacity(NODE4_CAPACITY),
+        }
+    }
+
+    /// Returns the number of children.
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Children::Node4 { children, .. }
+            | Children::Node16 { children, .. }
+            | Children::Node48 { children, .. } => children.len(),
+            Children::Node256 { len, .. } => *len,
+        }
+    }
+
+    /// Returns the child of a byte.
+    pub(super) fn get(&self, byte: u8) -> Option<&T> {
+        match self {
+            Children::Node4 { bytes, children } | Children::Node16 { bytes, children } => {
+                let index = bytes.binary_search(&byte).ok()?;
+                Some(&children[index])
+            }
+            Children::Node48 { slots, children } => match slots[byte as usize] {
+                0 => None,
+                slot => Some(&children[slot as usize - 1]),
+            },
+            Children::Node256 { children, .. } => children[byte as usize].as_ref(),
+        }
+    }
+
+    /// Returns a mutable reference to the child of a byte.
+    pub(super) fn get_mut(&mut self, byte: u8) -> Option<&mut T> {
+        match self {
+            Children::Node4 { bytes, children } | Children::Node16 { bytes, children } => {
+                let index = bytes.binary_search(&byte).ok()?;
+                Some(&mut children[index])
+            }
+            Children::Node48 { slots, children } => match slots[byte as usize] {
+                0 => None,
+                slot => Some(&mut children[slot as usize - 1]),
+            },
+            Children::Node256 { children, .. } => children[byte as usize].as_mut(),
+        }
+    }
+
+    /// Adds the child of a byte that has no child yet, growing to the next kind if the node is
+    /// full.
+    pub(super) fn insert(&mut self, byte: u8, child: T) {
+        debug_assert!(self.get(byte).is_none());
+        self.grow_if_full();
+        match self {
+            Children::Node4 { bytes, children } | Children::Node16 { bytes, children } => {
+                let index = bytes.binary_search(&byte).unwrap_err();
+                bytes.insert(index, byte);
+                children.insert(index, child);
+            }
+            Children::Node48 { slots, children } => {
+                children.push(child);
+                slots[byte as usize] = children.len() as u8;
+            }
+            Children::Node256 { children, len } => {
+                children[byte as usize] = Some(child);
+                *len += 1;
+            }
+        }
+    }
+
+    /// Removes the child of a byte and returns it, shrinking to the previous kind if the node
+    /// became sparse.
+    pub(super) fn remove(&mut self, byte: u8) -> Option<T> {
+        let child = match self {
+            Children::Node4 { bytes, children } | Children::Node16 { bytes, children } => {
+                let index = bytes.binary_search(&byte).ok()?;
+                bytes.remove(index);
+                children.remove(index)
+            }
+            Children::Node48 { slots, children } => {
+                let slot = match slots[byte as usize] {
+                    0 => return None,
+                    slot => slot as usize - 1,
+                };
+                slots[byte as usize] = 0;
+                // The last child moves to the freed slot.
+                let moved = children.len() as u8;
+                if let Some(other) = slots.iter_mut().find(|other| **other == moved) {
+                    *other = slot as u8 + 1;
+                }
+                children.swap_remove(slot)
+            }
+            Children::Node256 { children, len } => {
+                let child = children[byte as usize].take()?;
+                *len -= 1;
+                child
+            }
+        };
+        self.shrink_if_sparse();
+        Some(child)
+    }
+
+    /// Returns the children with their bytes, in byte order.
+    pub(super) fn iter(&self) -> Vec<(u8, &T)> {
+        match self {
+            Children::Node4 { bytes, children } | Children::Node16 { bytes, children } => {
+                bytes.iter().copied().zip(children).collect()
+            }
+            Children::Node48 { slots, children } => (0..=u8::MAX)
+                .filter(|&byte| slots[byte as usize] != 0)
+                .map(|byte| (byte, &children[slots[byte as usize] as usize - 1]))
+                .collect(),
+            Children::Node256 { children, .. } => (0..=u8::MAX)
+                .filter_map(|byte| Some((byte, children[byte as usize].as_ref()?)))
+                .collect(),
+        }
+    }
+
+    /// Removes all the children and returns them with their bytes, in byte order.
+    pub(super) fn drain(&mut self) -> Vec<(u8, T)> {
+        match std::mem::replace(self, Children::new()) {
+            Children::Node4 { bytes, children } | Children::Node16 { bytes, children } => {
+                bytes.into_iter().zip(children).collect()
+            }
+            Children::Node48 { slots, children } => {
+                let mut children: Vec<Option<T>> = children.into_iter().map(Some).collect();
+                (0..=u8::MAX)
+                    .filter(|&byte| slots[byte as usize] != 0)
+                    .filter_map(|byte| {
+                        let child = children[slots[byte as usize] as usize - 1].take()?;
+                        Some((byte, child))
+                    })
+                    .collect()
+            }
+            Children::Node256 { children, .. } => (0..=u8::MAX)
+                .zip(*children)
+                .filter_map(|(byte, child)| Some((byte, child?)))
+                .collect(),
+        }
+    }
+
+    /// Converts a full node to the next kind.
+    fn grow_if_full(&mut self) {
+        let grown = match self {
+            Children::Node4 { children, .. } if children.len() == NODE4_CAPACITY => {
+                Self::sorted(NODE16_CAPACITY, self.drain())
+            }
+            Children::Node16 { children, .. } if children.len() == NODE16_CAPACITY => {
+                Self::indexed(self.drain())
+            }
+            Children::Node48 { children, .. } if children.len() == NODE48_CAPACITY => {
+                Self::direct(self.drain())
+            }
+            _ => return,
+        };
+        *self = grown;
+    }
+
+    /// Converts a sparse node to the previous kind.
+    fn shrink_if_sparse(&mut self) {
+        let shrunk = match self {
+            Children::Node16 { children, .. } if children.len() <= NODE16_SHRINK => {
+                Self::sorted(NODE4_CAPACITY, self.drain())
+            }
+            Children::Node48 { children, .. } if children.len() <= NODE48_SHRINK => {
+                Self::sorted(NODE16_CAPACITY, self.drain())
+            }
+            Children::Node256 { len, .. } if *len <= NODE256_SHRINK => Self::indexed(self.drain()),
+            _ => return,
+        };
+        *self = shrunk;
+    }
+
+    /// Builds a `Node4` or a `Node16` from children sorted by byte.
+    fn sorted(capacity: usize, entries: Vec<(u8, T)>) -> Self {
+        let mut bytes = Vec::with_capacity(capacity);
+        let mut children = Vec::with_capacity(capacity);
+        for (byte, child) in entries {
+            bytes.push(byte);
+            children.push(child);
+        }
+        if capacity == NODE4_CAPACITY {
+            Children::Node4 { bytes, children }
+        } else {
+            Children::Node16 { bytes, children }
+        }
+    }
+
+    /// Builds a `Node48` from children sorted by byte.
+    fn indexed(entries: Vec<(u8, T)>) -> Self {
+        let mut slots = Box::new([0; 256]);
+        let mut children = Vec::with_capacity(NODE48_CAPACITY);
+        for (byte, child) in entries {
+            children.push(child);
+            slots[byte as usize] = children.len() as u8;
+        }
+        Children::Node48 { slots, children }
+    }
+
+    /// Builds a `Node256` from children sorted by byte.
+    fn direct(entries: Vec<(u8, T)>) -> Self {
+        let mut children = Box::new(std::array::from_fn(|_| None));
+        let len = entries.len();
+        for (byte, child) in entries {
+            children[byte as usize] = Some(child);
+        }
+        Children::Node256 { children, len }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind<T>(children: &Children<T>) -> &'static str {
+        match children {
+            Children::Node4 { .. } => "Node4",
+            Children::Node16 { .. } => "Node16",
+            Children::Node48 { .. } => "Node48",
+            Children::Node256 { .. } => "Node256",
+        }
+    }
+
+    /// A node grows through every kind as children are added and keeps them in byte order.
+    #[test]
+    fn insert_grows_through_kinds() {
+        let mut children = Children::new();
+        let mut kinds = Vec::new();
+
+        for byte in (0..=u8::MAX).rev() {
+            children.insert(byte, byte as usize);
+            if kinds.last() != Some(&kind(&children)) {
+                kinds.push(kind(&children));
+            }
+        }
+
+        assert_eq!(kinds, vec!["Node4", "Node16", "Node48", "Node256"]);
+        let iterated: Vec<(u8, usize)> = children
+            .iter()
+            .into_iter()
+            .map(|(byte, child)| (byte, *child))
+            .collect();
+        let expected: Vec<(u8, usize)> = (0..=u8::MAX).map(|byte| (byte, byte as usize)).collect();
+        assert_eq!(iterated, expected);
+        assert_eq!(children.len(), 256);
+    }
+
+    /// A node shrinks back to a `Node4` as children are removed, and the remaining children are
+    /// still found.
+    #[test]
+    fn remove_shrinks_through_kinds() {
+        let mut children = Children::new();
+        for byte in 0..=u8::MAX {
+            children.insert(byte, byte as usize);
+        }
+
+        for byte in 0..254 {
+            assert_eq!(children.remove(byte), Some(byte as usize));
+        }
+
+        assert_eq!(kind(&children), "Node4");
+        assert_eq!(children.iter(), vec![(254, &254), (255, &255)]);
+        assert_eq!(children.remove(0), None);
+    }
+
+    /// Removing a child from a `Node48` keeps the other children addressable.
+    #[test]
+    fn remove_from_node48_moves_last_child() {
+        let mut children = Children::new();
+        for byte in 0..30 {
+            children.insert(byte * 3, byte as usize);
+        }
+
+        children.remove(3);
+
+        assert_eq!(kind(&children), "Node48");
+        assert_eq!(children.get(3), None);
+        for byte in (0..30).filter(|&byte| byte != 1) {
+            assert_eq!(children.get(byte * 3), Some(&(byte as usize)));
+        }
+    }
+}
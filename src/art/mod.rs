@@ -0,0 +1,3 @@
+mod children;
+mod tree;
+pub use tree::{Art, Iter};
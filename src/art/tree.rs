@@ -0,0 +1,484 @@
+use super::children::Children;
+
+/// A key and its value, stored at the end of a path of the tree.
+struct Leaf<V> {
+    key: Vec<u8>,
+    value: V,
+}
+
+/// An inner node: the bytes shared by all the keys below it, the entry whose key ends at the node
+/// and the children indexed by the next byte.
+struct Inner<V> {
+    prefix: Vec<u8>,
+    end: Option<Leaf<V>>,
+    children: Children<Node<V>>,
+}
+
+enum Node<V> {
+    Leaf(Leaf<V>),
+    Inner(Box<Inner<V>>),
+}
+
+/// Represents an in-memory adaptive radix tree (ART) mapping byte-string keys to values, ordered
+/// bytewise.
+///
+/// Each inner node consumes one byte of the key, and the size of its array of children adapts to
+/// the number of children (see the node kinds of the ART paper: `Node4`, `Node16`, `Node48` and
+/// `Node256`). The bytes shared by all the keys of a subtree are stored once in its root (path
+/// compression), and a subtree holding a single key is replaced by a leaf (lazy expansion). A
+/// lookup therefore reads at most one node per distinct byte of the key, whatever the number of
+/// keys, and keys sharing long prefixes take little space. Finding the keys that start with a
+/// prefix only visits the subtree of the prefix.
+///
+/// The tree can serve as a cache index in front of the on-disk structures, or to hold the
+/// entries of a memtable when scans by prefix dominate.
+pub struct Art<V> {
+    root: Option<Node<V>>,
+    len: usize,
+}
+
+impl<V> Art<V> {
+    /// Creates an empty tree.
+    pub fn new() -> Self {
+        Art { root: None, len: 0 }
+    }
+
+    /// Returns the number of entries in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the tree does not hold any entry.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the value associated with a key.
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let mut node = self.root.as_ref()?;
+        let mut depth = 0;
+        loop {
+            match node {
+                Node::Leaf(leaf) => return (leaf.key == key).then_some(&leaf.value),
+                Node::Inner(inner) => {
+                    if !key[depth..].starts_with(&inner.prefix) {
+                        return None;
+                    }
+                    depth += inner.prefix.len();
+                    let Some(&byte) = key.get(depth) else {
+                        return inner.end.as_ref().map(|leaf| &leaf.value);
+                    };
+                    node = inner.children.get(byte)?;
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the value associated with a key.
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        let mut node = self.root.as_mut()?;
+        let mut depth = 0;
+        loop {
+            match node {
+                Node::Leaf(leaf) => return (leaf.key == key).then_some(&mut leaf.value),
+                Node::Inner(inner) => {
+                    if !key[depth..].starts_with(&inner.prefix) {
+                        return None;
+                    }
+                    depth += inner.prefix.len();
+                    let Some(&byte) = key.get(depth) else {
+                        return inner.end.as_mut().map(|leaf| &mut leaf.value);
+                    };
+                    node = inner.children.get_mut(byte)?;
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the tree holds a value for a key.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts a key-value pair in the tree, replacing the previous value of the key. Returns the
+    /// previous value, if there was one.
+    pub fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        let leaf = Leaf {
+            key: key.to_vec(),
+            value,
+        };
+        let previous = match &mut self.root {
+            Some(root) => insert(root, leaf, 0),
+            None => {
+                self.root = Some(Node::Leaf(leaf));
+                None
+            }
+        };
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    /// Removes a key from the tree. Returns the value of the key, if it was present.
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let root = self.root.as_mut()?;
+        let value = match root {
+            Node::Leaf(leaf) if leaf.key == key => match self.root.take() {
+                Some(Node::Leaf(leaf)) => leaf.value,
+                _ => unreachable!("the root should be a leaf"),
+            },
+            Node::Leaf(_) => return None,
+            Node::Inner(inner) => {
+                let value = remove(inner, key, 0)?;
+                if collapse(root) {
+                    self.root = None;
+                }
+                value
+            }
+        };
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Removes all the entries of the tree.
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// Returns an iterator over all the entries of the tree, in key order.
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter::new(self.root.as_ref())
+    }
+
+    /// Returns an iterator over the entries whose keys start with `prefix`, in key order. Only
+    /// the subtree of the prefix is visited.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::art::Art;
+    ///
+    /// let mut tree = Art::new();
+    /// tree.insert(b"user:1:name", "Alice");
+    /// tree.insert(b"user:1:city", "Paris");
+    /// tree.insert(b"user:2:name", "Bob");
+    ///
+    /// let keys: Vec<Vec<u8>> = tree.prefix(b"user:1:").map(|(key, _)| key.to_vec()).collect();
+    ///
+    /// assert_eq!(keys, vec![b"user:1:city".to_vec(), b"user:1:name".to_vec()]);
+    /// ```
+    pub fn prefix(&self, prefix: &[u8]) -> Iter<'_, V> {
+        let mut node = self.root.as_ref();
+        let mut depth = 0;
+        while let Some(Node::Inner(inner)) = node {
+            let rest = &prefix[depth..];
+            if rest.len() <= inner.prefix.len() {
+                if !inner.prefix.starts_with(rest) {
+                    node = None;
+                }
+                break;
+            }
+            if !rest.starts_with(&inner.prefix) {
+                node = None;
+                break;
+            }
+            depth += inner.prefix.len();
+            node = inner.children.get(prefix[depth]);
+            depth += 1;
+        }
+        match node {
+            Some(Node::Leaf(leaf)) if !leaf.key.starts_with(prefix) => Iter::new(None),
+            node => Iter::new(node),
+        }
+    }
+}
+
+impl<V> Default for Art<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Inserts a leaf in the subtree of `node`, whose keys share their first `depth` bytes with the
+/// key of the leaf. Returns the previous value of the key, if there was one.
+fn insert<V>(node: &mut Node<V>, leaf: Leaf<V>, depth: usize) -> Option<V> {
+    match node {
+        Node::Leaf(existing) => {
+            if existing.key == leaf.key {
+                return Some(std::mem::replace(&mut existing.value, leaf.value));
+            }
+            // The leaf becomes an inner node holding both keys after their common bytes.
+            let common = common_prefix_len(&existing.key[depth..], &leaf.key[depth..]);
+            let split = Inner::new(leaf.key[depth..depth + common].to_vec());
+            let Node::Leaf(existing) = std::mem::replace(node, Node::Inner(Box::new(split))) else {
+                unreachable!("the node should be a leaf");
+            };
+            let Node::Inner(split) = node else {
+                unreachable!("the node should be an inner node");
+            };
+            split.add(existing, depth + common);
+            split.add(leaf, depth + common);
+            None
+        }
+        Node::Inner(inner) => {
+            let common = common_prefix_len(&inner.prefix, &leaf.key[depth..]);
+            if common < inner.prefix.len() {
+                // The key leaves the prefix of the node: a new node holds the common bytes, with
+                // the old node and the leaf as children.
+                let split = Inner::new(inner.prefix[..common].to_vec());
+                let Node::Inner(mut old) = std::mem::replace(node, Node::Inner(Box::new(split)))
+                else {
+                    unreachable!("the node should be an inner node");
+                };
+                let Node::Inner(split) = node else {
+                    unreachable!("the node should be an inner node");
+                };
+                let byte = old.prefix[common];
+                old.prefix.drain(..=common);
+                split.children.insert(byte, Node::Inner(old));
+                split.add(leaf, depth + common);
+                return None;
+            }
+
+            let depth = depth + inner.prefix.len();
+            let Some(&byte) = leaf.key.get(depth) else {
+                return inner.end.replace(leaf).map(|previous| previous.value);
+            };
+            match inner.children.get_mut(byte) {
+                Some(child) => insert(child, leaf, depth + 1),
+                None => {
+                    inner.children.insert(byte, Node::Leaf(leaf));
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Removes a key from the subtree of an inner node, whose keys share their first `depth` bytes
+/// with the key. Returns the value of the key, if it was present. The children left with a single
+/// entry are collapsed, but the node itself must be collapsed by the caller.
+fn remove<V>(inner: &mut Inner<V>, key: &[u8], depth: usize) -> Option<V> {
+    if !key[depth..].starts_with(&inner.prefix) {
+        return None;
+    }
+    let depth = depth + inner.prefix.len();
+    let Some(&byte) = key.get(depth) else {
+        return inner.end.take().map(|leaf| leaf.value);
+    };
+
+    let child = inner.children.get_mut(byte)?;
+    match child {
+        Node::Inner(child_inner) => {
+            let value = remove(child_inner, key, depth + 1)?;
+            if collapse(child) {
+                inner.children.remove(byte);
+            }
+            Some(value)
+        }
+        Node::Leaf(leaf) if leaf.key == key => match inner.children.remove(byte) {
+            Some(Node::Leaf(leaf)) => Some(leaf.value),
+            _ => unreachable!("the child should be a leaf"),
+        },
+        Node::Leaf(_) => None,
+    }
+}
+
+/// Simplifies an inner node after a removal: a node with a single entry becomes a leaf and a node
+/// with a single child is merged with it. Returns `true` if the node is empty and must be removed.
+fn collapse<V>(node: &mut Node<V>) -> bool {
+    let Node::Inner(inner) = node else {
+        return false;
+    };
+    match (inner.children.len(), inner.end.is_some()) {
+        (0, false) => true,
+        (0, true) => {
+            let leaf = inner.end.take().expect("the node should end a key");
+            *node = Node::Leaf(leaf);
+            false
+        }
+        (1, false) => {
+            let (byte, child) = inner
+                .children
+                .drain()
+                .pop()
+                .expect("the node should have a child");
+            *node = match child {
+                Node::Leaf(leaf) => Node::Leaf(leaf),
+                Node::Inner(mut child) => {
+                    let mut prefix = std::mem::take(&mut inner.prefix);
+                    prefix.push(byte);
+                    prefix.append(&mut child.prefix);
+                    child.prefix = prefix;
+                    Node::Inner(child)
+                }
+            };
+            false
+        }
+        _ => false,
+    }
+}
+
+impl<V> Inner<V> {
+    fn new(prefix: Vec<u8>) -> Self {
+        Inner {
+            prefix,
+            end: None,
+            children: Children::new(),
+        }
+    }
+
+    /// Adds a leaf whose key is not in the node and shares its first `depth` bytes with the keys
+    /// of the node, including the prefix of the node.
+    fn add(&mut self, leaf: Leaf<V>, depth: usize) {
+        match leaf.key.get(depth) {
+            Some(&byte) => self.children.insert(byte, Node::Leaf(leaf)),
+            None => self.end = Some(leaf),
+        }
+    }
+}
+
+/// Returns the number of bytes at the start of both slices that are equal.
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// A node of the tree that remains to be visited by an [Iter].
+enum Pending<'a, V> {
+    Node(&'a Node<V>),
+    Leaf(&'a Leaf<V>),
+}
+
+/// An iterator over the entries of an [Art], in key order.
+pub struct Iter<'a, V> {
+    stack: Vec<Pending<'a, V>>,
+}
+
+impl<'a, V> Iter<'a, V> {
+    /// Creates an iterator over the entries of the subtree of `node`.
+    fn new(node: Option<&'a Node<V>>) -> Self {
+        Iter {
+            stack: node.map(Pending::Node).into_iter().collect(),
+        }
+    }
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (&'a [u8], &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.pop()? {
+                Pending::Leaf(leaf) | Pending::Node(Node::Leaf(leaf)) => {
+                    return Some((&leaf.key, &leaf.value));
+                }
+                Pending::Node(Node::Inner(inner)) => {
+                    for (_, child) in inner.children.iter().into_iter().rev() {
+                        self.stack.push(Pending::Node(child));
+                    }
+                    // A key ending at the node is smaller than the keys of its children.
+                    if let Some(leaf) = &inner.end {
+                        self.stack.push(Pending::Leaf(leaf));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rand::Rng;
+
+    use super::*;
+
+    /// Random insertions and removals of keys with long shared prefixes return the same entries
+    /// as a [BTreeMap].
+    #[test]
+    fn random_operations_match_reference() {
+        let mut rng = rand::thread_rng();
+        let mut tree = Art::new();
+        let mut reference = BTreeMap::new();
+
+        for i in 0..20_000 {
+            let key =
+                format!("key/{}/{}", rng.gen_range(0..20), rng.gen_range(0..300)).into_bytes();
+            let key = &key[..rng.gen_range(4..=key.len())];
+            if rng.gen_bool(0.3) {
+                assert_eq!(tree.remove(key), reference.remove(key));
+            } else {
+                assert_eq!(tree.insert(key, i), reference.insert(key.to_vec(), i));
+            }
+        }
+
+        assert_eq!(tree.len(), reference.len());
+        let entries: Vec<(Vec<u8>, i32)> = tree
+            .iter()
+            .map(|(key, value)| (key.to_vec(), *value))
+            .collect();
+        assert_eq!(entries, reference.into_iter().collect::<Vec<_>>());
+    }
+
+    /// A key that is a prefix of other keys is kept apart from them.
+    #[test]
+    fn get_distinguishes_prefix_keys() {
+        let mut tree = Art::new();
+        tree.insert(b"abc", 1);
+        tree.insert(b"ab", 2);
+        tree.insert(b"abcd", 3);
+        tree.insert(b"", 4);
+
+        tree.remove(b"abc");
+
+        assert_eq!(tree.get(b"ab"), Some(&2));
+        assert_eq!(tree.get(b"abc"), None);
+        assert_eq!(tree.get(b"abcd"), Some(&3));
+        assert_eq!(tree.get(b""), Some(&4));
+        assert_eq!(tree.get(b"a"), None);
+    }
+
+    /// A prefix scan returns the keys starting with the prefix, whether the prefix ends inside
+    /// the prefix of a node, at a node or at a leaf.
+    #[test]
+    fn prefix_returns_matching_keys() {
+        let mut tree = Art::new();
+        for key in ["apple", "applet", "apply", "banana", "band", "bandana"] {
+            tree.insert(key.as_bytes(), ());
+        }
+        let scan = |prefix: &str| -> Vec<String> {
+            tree.prefix(prefix.as_bytes())
+                .map(|(key, _)| String::from_utf8(key.to_vec()).expect("the key should be utf-8"))
+                .collect()
+        };
+
+        assert_eq!(scan("appl"), vec!["apple", "applet", "apply"]);
+        assert_eq!(scan("apple"), vec!["apple", "applet"]);
+        assert_eq!(scan("ban"), vec!["banana", "band", "bandana"]);
+        assert_eq!(scan("bandan"), vec!["bandana"]);
+        assert_eq!(scan("bandanas"), Vec::<String>::new());
+        assert_eq!(scan("c"), Vec::<String>::new());
+        assert_eq!(scan("").len(), 6);
+    }
+
+    /// Removing every key leaves an empty tree.
+    #[test]
+    fn remove_all_keys_empties_tree() {
+        let mut tree = Art::new();
+        for i in 0..1000u32 {
+            tree.insert(&i.to_be_bytes(), i);
+        }
+
+        for i in 0..1000u32 {
+            assert_eq!(tree.remove(&i.to_be_bytes()), Some(i));
+        }
+
+        assert!(tree.is_empty());
+        assert!(tree.root.is_none());
+    }
+}
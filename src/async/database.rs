@@ -0,0 +1,231 @@
+use std::ops::RangeBounds;
+use std::panic;
+use std::path::PathBuf;
+
+use tokio::task::JoinError;
+
+use crate::db::{DatabaseError, Options, SharedDatabase, WriteBatch};
+
+use super::ScanStream;
+
+/// Represents a [Database](crate::db::Database) used from asynchronous code, on a tokio runtime.
+///
+/// The files of the database are read and written with blocking calls, and a read can decode and
+/// decompress many pages, so every operation runs on the blocking threads of the runtime with
+/// `spawn_blocking`, and the reactor threads never wait for the database. The handle is cheap to
+/// clone, and its clones share the database as [SharedDatabase] does: their reads run in parallel
+/// and their writes one at a time.
+///
+/// The methods must be awaited within a tokio runtime.
+#[derive(Clone)]
+pub struct Database {
+    shared: SharedDatabase,
+}
+
+impl Database {
+    /// Opens the database in the directory at `path` as [Database::open](crate::db::Database::open)
+    /// does, on a blocking thread.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of
+    /// [Database::open](crate::db::Database::open).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::Options;
+    /// use rouilledb::r#async::Database;
+    ///
+    /// let runtime = tokio::runtime::Builder::new_current_thread()
+    ///     .build()
+    ///     .expect("build should not fail");
+    /// let directory = TempDir::new();
+    ///
+    /// runtime.block_on(async {
+    ///     let database = Database::open(directory.path(), Options::new())
+    ///         .await
+    ///         .expect("open should not fail");
+    ///     database
+    ///         .put(b"key".to_vec(), b"value".to_vec())
+    ///         .await
+    ///         .expect("put should not fail");
+    ///
+    ///     let value = database
+    ///         .get(b"key".to_vec())
+    ///         .await
+    ///         .expect("get should not fail");
+    ///     assert_eq!(value, Some(b"value".to_vec()));
+    ///     database.close().await.expect("close should not fail");
+    /// });
+    /// ```
+    pub async fn open(path: impl Into<PathBuf>, options: Options) -> Result<Self, DatabaseError> {
+        let path = path.into();
+        let shared = blocking(move || SharedDatabase::open(path, options)).await?;
+        Ok(Database { shared })
+    }
+
+    /// Creates an asynchronous handle to a shared database, so it can be used both from
+    /// asynchronous code and from threads.
+    pub fn new(shared: SharedDatabase) -> Self {
+        Database { shared }
+    }
+
+    /// Returns the shared database, to be used from threads.
+    pub fn shared(&self) -> &SharedDatabase {
+        &self.shared
+    }
+
+    /// Returns the value associated with a key, or `None` if the key is not present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [Database::get](crate::db::Database::get).
+    pub async fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>, DatabaseError> {
+        let shared = self.shared.clone();
+        blocking(move || shared.get(&key)).await
+    }
+
+    /// Returns a stream over the entries whose keys are within `range`, in key order, read from a
+    /// single version of the database by prefetched batches (see [ScanStream]).
+    pub fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> ScanStream {
+        ScanStream::new(
+            self.shared.clone(),
+            range.start_bound().cloned(),
+            range.end_bound().cloned(),
+        )
+    }
+
+    /// Sets the value of a key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [Database::put](crate::db::Database::put).
+    pub async fn put(&self, key: Vec<u8>, value: Vec<u8>) -> Result<(), DatabaseError> {
+        let shared = self.shared.clone();
+        blocking(move || shared.put(&key, &value)).await
+    }
+
+    /// Removes a key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of
+    /// [Database::delete](crate::db::Database::delete).
+    pub async fn delete(&self, key: Vec<u8>) -> Result<(), DatabaseError> {
+        let shared = self.shared.clone();
+        blocking(move || shared.delete(&key)).await
+    }
+
+    /// Writes the writes of a batch in a single commit.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of
+    /// [Database::write](crate::db::Database::write). None of the writes is then applied.
+    pub async fn commit(&self, batch: WriteBatch) -> Result<(), DatabaseError> {
+        let shared = self.shared.clone();
+        blocking(move || shared.write(batch)).await
+    }
+
+    /// Drops the handle, and closes the database if it was the last handle to it, as
+    /// [Database::close](crate::db::Database::close) does.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of
+    /// [Database::close](crate::db::Database::close).
+    pub async fn close(self) -> Result<(), DatabaseError> {
+        match self.shared.into_inner() {
+            Ok(database) => blocking(move || database.close()).await,
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+/// Runs a blocking operation on a blocking thread of the runtime and returns its result, resuming
+/// its panic if it panicked.
+async fn blocking<T, F>(operation: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    tokio::task::spawn_blocking(operation)
+        .await
+        .unwrap_or_else(resume)
+}
+
+/// Resumes the panic of a blocking operation.
+pub(super) fn resume<T>(error: JoinError) -> T {
+    panic::resume_unwind(error.into_panic())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use crate::common::TempDir;
+
+    use super::*;
+
+    fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("build should not fail")
+            .block_on(future)
+    }
+
+    /// The writes and removals made through the asynchronous handle are seen by a scan, which
+    /// reads the version of the database when it started across several batches.
+    #[test]
+    fn scan_returns_committed_entries() {
+        let directory = TempDir::new();
+
+        let (entries, all) = block_on(async {
+            let database = Database::open(directory.path(), Options::new())
+                .await
+                .expect("open should not fail");
+            let mut batch = WriteBatch::new();
+            for i in 0..1000u32 {
+                batch.put(&i.to_be_bytes(), &[1]);
+            }
+            for i in 0..10u8 {
+                batch.put(&[i], &[i * 2]);
+            }
+            database
+                .commit(batch)
+                .await
+                .expect("commit should not fail");
+            database
+                .delete(vec![3])
+                .await
+                .expect("delete should not fail");
+            let mut scan = database.scan((Bound::Included(vec![2]), Bound::Excluded(vec![6])));
+            let mut entries = Vec::new();
+            while let Some(entry) = scan.next().await {
+                entries.push(entry.expect("next should not fail"));
+            }
+            let mut scan = database.scan(..);
+            let mut all = 0;
+            while let Some(entry) = scan.next().await {
+                entry.expect("next should not fail");
+                if all == 300 {
+                    database
+                        .put(b"new".to_vec(), b"value".to_vec())
+                        .await
+                        .expect("put should not fail");
+                }
+                all += 1;
+            }
+            database.close().await.expect("close should not fail");
+            (entries, all)
+        });
+
+        assert_eq!(
+            entries,
+            vec![(vec![2], vec![4]), (vec![4], vec![8]), (vec![5], vec![10])]
+        );
+        assert_eq!(all, 1009);
+    }
+}
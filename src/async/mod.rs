@@ -0,0 +1,4 @@
+mod database;
+mod scan;
+pub use database::Database;
+pub use scan::ScanStream;
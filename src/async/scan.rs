@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::ops::Bound;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use futures_core::Stream;
+use tokio::task::JoinHandle;
+
+use crate::db::{DatabaseError, DatabaseSnapshot, ReadOptions, SharedDatabase};
+
+use super::database::resume;
+
+/// A key and its value.
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// The snapshot read by a scan and the next batch of its entries.
+type Batch = (Arc<DatabaseSnapshot>, Vec<Entry>);
+
+/// The number of entries read at once by a [ScanStream].
+const BATCH_SIZE: usize = 256;
+
+/// A [Stream] over the entries of a database within a range of keys, in key order, returned by
+/// [Database::scan](super::Database::scan).
+///
+/// The entries are read by batches of [BATCH_SIZE] on the blocking threads of the runtime, from a
+/// snapshot taken when the first batch is read, so the stream sees a single version of the
+/// database. The next batch is read as soon as the previous one is returned, while its entries are
+/// consumed, but no further: a consumer that stops polling the stream stops the reads. The
+/// database is only held while a batch is read, so the writes are not delayed by a slow consumer.
+/// After an error is returned, the stream ends.
+pub struct ScanStream {
+    shared: SharedDatabase,
+    snapshot: Option<Arc<DatabaseSnapshot>>,
+    /// The start of the next batch, or `None` once the last batch was read.
+    next: Option<Bound<Vec<u8>>>,
+    end: Bound<Vec<u8>>,
+    entries: VecDeque<Entry>,
+    pending: Option<JoinHandle<Result<Batch, DatabaseError>>>,
+}
+
+impl ScanStream {
+    pub(super) fn new(shared: SharedDatabase, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> Self {
+        ScanStream {
+            shared,
+            snapshot: None,
+            next: Some(start),
+            end,
+            entries: VecDeque::new(),
+            pending: None,
+        }
+    }
+
+    /// Returns the next entry of the stream, or `None` once it has ended.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub async fn next(&mut self) -> Option<Result<Entry, DatabaseError>> {
+        std::future::poll_fn(|context| Pin::new(&mut *self).poll_next(context)).await
+    }
+
+    /// Starts reading the next batch on a blocking thread, unless it is being read or the last
+    /// batch was read.
+    fn prefetch(&mut self) {
+        if self.pending.is_some() {
+            return;
+        }
+        let Some(start) = self.next.take() else {
+            return;
+        };
+        let shared = self.shared.clone();
+        let snapshot = self.snapshot.clone();
+        let end = self.end.clone();
+        self.pending = Some(tokio::task::spawn_blocking(move || {
+            let database = shared.read();
+            let snapshot = snapshot.unwrap_or_else(|| Arc::new(database.snapshot()));
+            let options = ReadOptions::new().snapshot(&snapshot);
+            let entries = database
+                .iter_with_options((start, end), &options)
+                .take(BATCH_SIZE)
+                .collect::<Result<_, _>>()?;
+            Ok((snapshot, entries))
+        }));
+    }
+}
+
+impl Stream for ScanStream {
+    type Item = Result<Entry, DatabaseError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(entry) = self.entries.pop_front() {
+                return Poll::Ready(Some(Ok(entry)));
+            }
+            self.prefetch();
+            let Some(pending) = self.pending.as_mut() else {
+                return Poll::Ready(None);
+            };
+            let batch = ready!(Pin::new(pending).poll(context)).unwrap_or_else(resume);
+            self.pending = None;
+            let (snapshot, entries) = match batch {
+                Ok(batch) => batch,
+                Err(error) => return Poll::Ready(Some(Err(error))),
+            };
+            if entries.len() == BATCH_SIZE {
+                let (last, _) = entries.last().expect("the batch should not be empty");
+                self.next = Some(Bound::Excluded(last.clone()));
+            }
+            self.snapshot = Some(snapshot);
+            self.entries = entries.into();
+            self.prefetch();
+        }
+    }
+}
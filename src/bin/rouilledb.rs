@@ -0,0 +1,491 @@
+use std::io::{self, Write};
+use std::ops::Bound;
+use std::process::ExitCode;
+
+use thiserror::Error;
+
+use rouilledb::common::KeyRange;
+use rouilledb::db::{Database, DatabaseError, Options};
+
+/// Help printed by `rouilledb --help` and after a usage error.
+const USAGE: &str = "\
+Usage: rouilledb [OPTIONS] <PATH> <COMMAND> [ARGS]
+
+Commands:
+  get <KEY>            Prints the value of a key
+  put <KEY> <VALUE>    Sets the value of a key
+  delete <KEY>         Removes a key
+  scan                 Prints the entries, one per line, as the key and the value split by a tab
+  count                Prints the number of entries
+
+Options:
+  --keys <utf8|hex>        How the keys are given and printed [default: utf8]
+  --values <utf8|hex>      How the values are given and printed [default: utf8]
+  --comparator <NAME>      The comparator of the database [default: bytewise]
+  --create                 Creates the database if it is missing
+  --prefix <KEY>           Only scans or counts the keys starting with a prefix
+  --start <KEY>            Only scans or counts the keys from a key, included
+  --end <KEY>              Only scans or counts the keys before a key, excluded
+  --limit <COUNT>          Scans at most a number of entries
+  -h, --help               Prints this help
+
+In utf8, a backslash is written \\\\, a tab \\t, a line feed \\n and a carriage return \\r. Any other
+byte that is not printable text is written \\x and two hexadecimal digits, in the arguments as in
+the output.";
+
+/// Represents errors that can occur while running a command.
+#[derive(Error, Debug)]
+enum CliError {
+    /// Indicates that the arguments are not a valid command.
+    ///
+    /// # Fields
+    /// - `0` - Why the arguments are invalid.
+    #[error("{0}")]
+    Usage(String),
+
+    /// Indicates that a key or a value given in hexadecimal is not valid.
+    ///
+    /// # Fields
+    /// - `0` - The invalid text.
+    #[error("\"{0}\" is not an even number of hexadecimal digits.")]
+    InvalidHex(String),
+
+    /// Indicates that a key or a value given as text has an invalid escape sequence.
+    ///
+    /// # Fields
+    /// - `0` - The invalid text.
+    #[error(
+        "\"{0}\" has an invalid escape sequence. A backslash must be followed by \\, t, n, r or x and \
+         two hexadecimal digits."
+    )]
+    InvalidEscape(String),
+
+    /// Indicates that the key read by `get` is not in the database.
+    #[error("The key is not in the database.")]
+    KeyNotFound,
+
+    /// Indicates that an operation on the database failed.
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+
+    /// Indicates that the output can't be written.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// How the keys or the values are given on the command line and printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// As text. A backslash, the control characters and the bytes that are not UTF-8 are escaped,
+    /// the same way in the arguments and in the output.
+    Utf8,
+    /// As hexadecimal digits, two per byte.
+    Hex,
+}
+
+impl Format {
+    /// Returns the format named `name`.
+    fn parse(name: &str) -> Result<Self, CliError> {
+        match name {
+            "utf8" => Ok(Format::Utf8),
+            "hex" => Ok(Format::Hex),
+            _ => Err(CliError::Usage(format!(
+                "The format \"{name}\" is unknown. It must be \"utf8\" or \"hex\"."
+            ))),
+        }
+    }
+
+    /// Returns the bytes of a key or a value given on the command line.
+    fn decode(self, text: &str) -> Result<Vec<u8>, CliError> {
+        match self {
+            Format::Utf8 => unescape(text).ok_or_else(|| CliError::InvalidEscape(text.to_string())),
+            Format::Hex => {
+                let digits = text.as_bytes();
+                if !digits.len().is_multiple_of(2) {
+                    return Err(CliError::InvalidHex(text.to_string()));
+                }
+                digits
+                    .chunks(2)
+                    .map(|pair| hex_byte(pair[0], pair[1]))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| CliError::InvalidHex(text.to_string()))
+            }
+        }
+    }
+
+    /// Returns the text printed for a key or a value.
+    fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            Format::Utf8 => escape(bytes),
+            Format::Hex => bytes.iter().map(|byte| format!("{byte:02x}")).collect(),
+        }
+    }
+}
+
+/// Returns the byte of two hexadecimal digits, or `None` if one is not an ASCII hexadecimal digit.
+fn hex_byte(high: u8, low: u8) -> Option<u8> {
+    let digit = |digit: u8| char::from(digit).to_digit(16);
+    Some((digit(high)? << 4 | digit(low)?) as u8)
+}
+
+/// Returns the text of `bytes` with a backslash, the control characters and the bytes that are not
+/// UTF-8 escaped.
+fn escape(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.utf8_chunks() {
+        for character in chunk.valid().chars() {
+            match character {
+                '\\' => text.push_str("\\\\"),
+                '\t' => text.push_str("\\t"),
+                '\n' => text.push_str("\\n"),
+                '\r' => text.push_str("\\r"),
+                control if control.is_control() => {
+                    let mut encoded = [0; 4];
+                    for byte in control.encode_utf8(&mut encoded).bytes() {
+                        text.push_str(&format!("\\x{byte:02x}"));
+                    }
+                }
+                character => text.push(character),
+            }
+        }
+        for byte in chunk.invalid() {
+            text.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    text
+}
+
+/// Returns the bytes of a text escaped like [escape], or `None` if an escape sequence is invalid.
+fn unescape(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut input = text.bytes();
+    while let Some(byte) = input.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        bytes.push(match input.next()? {
+            b'\\' => b'\\',
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b'x' => hex_byte(input.next()?, input.next()?)?,
+            _ => return None,
+        });
+    }
+    Some(bytes)
+}
+
+/// The arguments of a command.
+#[derive(Debug)]
+struct Arguments {
+    path: String,
+    command: String,
+    operands: Vec<String>,
+    keys: Format,
+    values: Format,
+    comparator: Option<String>,
+    create: bool,
+    prefix: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+    limit: Option<usize>,
+}
+
+impl Arguments {
+    /// Parses the arguments following the name of the program. Returns `None` if the help is
+    /// requested.
+    fn parse(arguments: &[String]) -> Result<Option<Self>, CliError> {
+        let mut positionals = Vec::new();
+        let (mut keys, mut values) = (Format::Utf8, Format::Utf8);
+        let (mut comparator, mut create, mut limit) = (None, false, None);
+        let (mut prefix, mut start, mut end) = (None, None, None);
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            let mut value = || {
+                arguments
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| CliError::Usage(format!("The option {argument} needs a value.")))
+            };
+            match argument.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--keys" => keys = Format::parse(&value()?)?,
+                "--values" => values = Format::parse(&value()?)?,
+                "--comparator" => comparator = Some(value()?),
+                "--create" => create = true,
+                "--prefix" => prefix = Some(value()?),
+                "--start" => start = Some(value()?),
+                "--end" => end = Some(value()?),
+                "--limit" => {
+                    let count = value()?;
+                    limit = Some(count.parse().map_err(|_| {
+                        CliError::Usage(format!("The limit \"{count}\" is not a number."))
+                    })?);
+                }
+                option if option.starts_with("--") => {
+                    return Err(CliError::Usage(format!("The option {option} is unknown.")));
+                }
+                _ => positionals.push(argument.clone()),
+            }
+        }
+        let mut positionals = positionals.into_iter();
+        let (Some(path), Some(command)) = (positionals.next(), positionals.next()) else {
+            return Err(CliError::Usage(
+                "A path and a command are required.".to_string(),
+            ));
+        };
+        Ok(Some(Arguments {
+            path,
+            command,
+            operands: positionals.collect(),
+            keys,
+            values,
+            comparator,
+            create,
+            prefix,
+            start,
+            end,
+            limit,
+        }))
+    }
+
+    /// Returns the operands of the command, checking that there are `count` of them.
+    fn operands(&self, count: usize) -> Result<&[String], CliError> {
+        if self.operands.len() != count {
+            return Err(CliError::Usage(format!(
+                "The command {} takes {count} arguments, but {} were given.",
+                self.command,
+                self.operands.len()
+            )));
+        }
+        Ok(&self.operands)
+    }
+
+    /// Returns the range of the keys scanned or counted: the keys with the prefix, within the
+    /// start and the end. Returns `None` if no key is within it.
+    fn range(&self) -> Result<Option<KeyRange>, CliError> {
+        let bound = |key: &Option<String>, bound: fn(Vec<u8>) -> Bound<Vec<u8>>| {
+            Ok::<_, CliError>(match key {
+                Some(key) => bound(self.keys.decode(key)?),
+                None => Bound::Unbounded,
+            })
+        };
+        let range = KeyRange::new(
+            bound(&self.start, Bound::Included)?,
+            bound(&self.end, Bound::Excluded)?,
+        );
+        Ok(match &self.prefix {
+            Some(prefix) => KeyRange::prefix(&self.keys.decode(prefix)?).intersection(&range),
+            None => Some(range),
+        })
+    }
+
+    /// Returns the options opening the database: for reading only, unless the command writes.
+    fn options(&self) -> Options {
+        let writes = matches!(self.command.as_str(), "put" | "delete");
+        let mut options = Options::new()
+            .create_if_missing(self.create && writes)
+            .read_only(!writes);
+        if let Some(comparator) = &self.comparator {
+            options = options.comparator(comparator.as_str());
+        }
+        options
+    }
+}
+
+/// Runs the command of the arguments, writing its result to `output`.
+fn run(arguments: &Arguments, output: &mut dyn Write) -> Result<(), CliError> {
+    let operand_count = match arguments.command.as_str() {
+        "scan" | "count" => 0,
+        "get" | "delete" => 1,
+        "put" => 2,
+        command => {
+            return Err(CliError::Usage(format!(
+                "The command {command} is unknown."
+            )));
+        }
+    };
+    let operands = arguments.operands(operand_count)?;
+    let mut database = Database::open(&arguments.path, arguments.options())?;
+    match arguments.command.as_str() {
+        "get" => {
+            let key = arguments.keys.decode(&operands[0])?;
+            let value = database.get(&key)?.ok_or(CliError::KeyNotFound)?;
+            writeln!(output, "{}", arguments.values.encode(&value))?;
+        }
+        "put" => {
+            let key = arguments.keys.decode(&operands[0])?;
+            let value = arguments.values.decode(&operands[1])?;
+            database.put(&key, &value)?;
+            database.close()?;
+        }
+        "delete" => {
+            let key = arguments.keys.decode(&operands[0])?;
+            database.delete(&key)?;
+            database.close()?;
+        }
+        "scan" => {
+            let entries = arguments
+                .range()?
+                .into_iter()
+                .flat_map(|range| database.iter(range))
+                .take(arguments.limit.unwrap_or(usize::MAX));
+            for entry in entries {
+                let (key, value) = entry?;
+                let (key, value) = (arguments.keys.encode(&key), arguments.values.encode(&value));
+                writeln!(output, "{key}\t{value}")?;
+            }
+        }
+        _ => {
+            let mut count = 0;
+            let entries = arguments
+                .range()?
+                .into_iter()
+                .flat_map(|range| database.iter(range));
+            for entry in entries {
+                entry?;
+                count += 1;
+            }
+            writeln!(output, "{count}")?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    let result = Arguments::parse(&arguments).and_then(|parsed| match parsed {
+        Some(parsed) => run(&parsed, &mut io::stdout().lock()),
+        None => {
+            println!("{USAGE}");
+            Ok(())
+        }
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(message)) => {
+            eprintln!("{message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rouilledb::common::TempDir;
+
+    use super::*;
+
+    /// Runs a command with the arguments, returning its output.
+    fn run_command(arguments: &[&str]) -> Result<String, CliError> {
+        let arguments: Vec<String> = arguments
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect();
+        let parsed = Arguments::parse(&arguments)?.expect("the help should not be requested");
+        let mut output = Vec::new();
+        run(&parsed, &mut output)?;
+        Ok(String::from_utf8(output).expect("the output should be UTF-8"))
+    }
+
+    /// The keys and values put are read back, scanned and counted within a range, and a deleted
+    /// key is no longer found.
+    #[test]
+    fn run_commands_reads_back_writes() {
+        let directory = TempDir::new();
+        let path = directory.path().to_str().expect("the path should be UTF-8");
+        for (key, value) in [("user:1", "alice"), ("user:2", "bob"), ("zone", "eu")] {
+            run_command(&["--create", path, "put", key, value]).expect("put should not fail");
+        }
+
+        let value = run_command(&[path, "get", "user:2"]).expect("get should not fail");
+        let scan = run_command(&[path, "scan", "--prefix", "user:", "--start", "user:2"])
+            .expect("scan should not fail");
+        let limited = run_command(&[path, "scan", "--limit", "1"]).expect("scan should not fail");
+        let count = run_command(&[path, "count", "--end", "zone"]).expect("count should not fail");
+        run_command(&[path, "delete", "user:2"]).expect("delete should not fail");
+        let deleted = run_command(&[path, "get", "user:2"]);
+
+        assert_eq!(value, "bob\n");
+        assert_eq!(scan, "user:2\tbob\n");
+        assert_eq!(limited, "user:1\talice\n");
+        assert_eq!(count, "2\n");
+        assert!(matches!(deleted, Err(CliError::KeyNotFound)));
+    }
+
+    /// The keys and values given and printed in hexadecimal are the bytes of their digits, and
+    /// the bytes that are not UTF-8 are escaped when printed as text.
+    #[test]
+    fn run_commands_handles_hex() {
+        let directory = TempDir::new();
+        let path = directory.path().to_str().expect("the path should be UTF-8");
+        run_command(&["--create", "--keys", "hex", path, "put", "00ff", "binary"])
+            .expect("put should not fail");
+
+        let value = run_command(&["--keys", "hex", "--values", "hex", path, "get", "00FF"])
+            .expect("get should not fail");
+        let scan = run_command(&[path, "scan"]).expect("scan should not fail");
+        let invalid = run_command(&["--keys", "hex", path, "get", "0g"]);
+
+        assert_eq!(value, "62696e617279\n");
+        assert_eq!(scan, "\\x00\\xff\tbinary\n");
+        assert!(matches!(invalid, Err(CliError::InvalidHex(text)) if text == "0g"));
+        assert!(matches!(
+            Format::Hex.decode("+f"),
+            Err(CliError::InvalidHex(_))
+        ));
+    }
+
+    /// The text printed for a key or a value is read back as the same bytes: only a backslash, the
+    /// control characters and the bytes that are not UTF-8 are escaped.
+    #[test]
+    fn encode_text_round_trips() {
+        let bytes = b"it's \"caf\xc3\xa9\" \\ \t\x1b\xff";
+
+        let text = Format::Utf8.encode(bytes);
+
+        assert_eq!(text, "it's \"caf\u{e9}\" \\\\ \\t\\x1b\\xff");
+        assert_eq!(
+            Format::Utf8.decode(&text).expect("decode should not fail"),
+            bytes
+        );
+        for invalid in ["\\q", "\\x4", "\\xg0", "trailing\\"] {
+            assert!(matches!(
+                Format::Utf8.decode(invalid),
+                Err(CliError::InvalidEscape(_))
+            ));
+        }
+    }
+
+    /// Invalid arguments are usage errors, and a missing database is not created by a read.
+    #[test]
+    fn parse_invalid_arguments_fails() {
+        let directory = TempDir::new();
+        let path = directory.path().join("missing");
+        let path = path.to_str().expect("the path should be UTF-8");
+
+        let missing_command = run_command(&[path]);
+        let unknown_option = run_command(&["--verbose", path, "get", "key"]);
+        let extra_operand = run_command(&["--create", path, "delete", "key", "more"]);
+        let unknown_format = run_command(&["--keys", "base64", path, "get", "key"]);
+        let missing_database = run_command(&[path, "get", "key"]);
+
+        for result in [
+            missing_command,
+            unknown_option,
+            extra_operand,
+            unknown_format,
+        ] {
+            assert!(matches!(result, Err(CliError::Usage(_))));
+        }
+        assert!(matches!(missing_database, Err(CliError::Database(_))));
+        assert!(Arguments::parse(&["--help".to_string()])
+            .expect("parse should not fail")
+            .is_none());
+    }
+}
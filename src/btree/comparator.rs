@@ -0,0 +1,181 @@
+use std::cmp::Ordering;
+
+/// Defines the order of the keys in a [BTree](super::BTree).
+///
+/// The name of the comparator is stored in the file when a tree is created, and a tree can only
+/// be opened with a comparator of the same name. A comparator must therefore always order the
+/// keys the same way for a given name: changing the order requires a new name.
+///
+/// Two keys for which [KeyComparator::compare] returns [Ordering::Equal] are the same key.
+pub trait KeyComparator: Send + Sync {
+    /// Returns the unique name of the comparator. The name must be at most 63 bytes long.
+    fn name(&self) -> &str;
+
+    /// Compares two keys.
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
+
+    /// Returns a key that is greater than `left` and smaller or equal to `right`, ideally shorter
+    /// than `right`. It is used as the separator between two leaves when a leaf is split.
+    ///
+    /// `left` is always smaller than `right`. The default implementation returns `right`.
+    fn shortest_separator(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        let _ = left;
+        right.to_vec()
+    }
+}
+
+/// Orders the keys bytewise. This is the default order of a [BTree](super::BTree).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BytewiseComparator;
+
+impl KeyComparator for BytewiseComparator {
+    fn name(&self) -> &str {
+        "rouilledb.bytewise"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        a.cmp(b)
+    }
+
+    /// Returns the shortest prefix of `right` that is greater than `left`.
+    fn shortest_separator(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        let common = left.iter().zip(right).take_while(|(a, b)| a == b).count();
+        right[..common + 1].to_vec()
+    }
+}
+
+/// Orders the keys in reverse bytewise order.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReverseComparator;
+
+impl KeyComparator for ReverseComparator {
+    fn name(&self) -> &str {
+        "rouilledb.reverse-bytewise"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+/// Orders the keys bytewise, ignoring the case of ASCII letters.
+///
+/// Keys that only differ by the case of their ASCII letters are the same key: inserting `"KEY"`
+/// replaces the value of `"key"`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CaseInsensitiveComparator;
+
+impl KeyComparator for CaseInsensitiveComparator {
+    fn name(&self) -> &str {
+        "rouilledb.ascii-case-insensitive"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        a.iter()
+            .map(u8::to_ascii_lowercase)
+            .cmp(b.iter().map(u8::to_ascii_lowercase))
+    }
+}
+
+/// Orders the keys as unsigned big-endian integers of any length.
+///
+/// Leading zero bytes are ignored: `[0, 1]` and `[1]` are the same key. The empty key is zero.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NumericComparator;
+
+impl KeyComparator for NumericComparator {
+    fn name(&self) -> &str {
+        "rouilledb.numeric-big-endian"
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        let a = &a[a.iter().take_while(|&&byte| byte == 0).count()..];
+        let b = &b[b.iter().take_while(|&&byte| byte == 0).count()..];
+        a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+    }
+}
+
+/// Returns the comparator of this module whose [KeyComparator::name] is `name`, or `None` if
+/// there is none, so that a comparator can be chosen by its name, from a configuration file for
+/// example.
+pub fn comparator_by_name(name: &str) -> Option<Box<dyn KeyComparator>> {
+    let comparators: [Box<dyn KeyComparator>; 4] = [
+        Box::new(BytewiseComparator),
+        Box::new(ReverseComparator),
+        Box::new(CaseInsensitiveComparator),
+        Box::new(NumericComparator),
+    ];
+    comparators
+        .into_iter()
+        .find(|comparator| comparator.name() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The bytewise comparator orders keys like slices.
+    #[test]
+    fn bytewise_compare_orders_like_slices() {
+        assert_eq!(BytewiseComparator.compare(b"a", b"b"), Ordering::Less);
+        assert_eq!(BytewiseComparator.compare(b"ab", b"a"), Ordering::Greater);
+        assert_eq!(BytewiseComparator.compare(b"a", b"a"), Ordering::Equal);
+    }
+
+    /// The bytewise separator is the shortest prefix of the right key greater than the left key.
+    #[test]
+    fn bytewise_shortest_separator_truncates_right_key() {
+        let comparator = BytewiseComparator;
+
+        assert_eq!(comparator.shortest_separator(b"apple", b"banana"), b"b");
+        assert_eq!(
+            comparator.shortest_separator(b"table/1/abc", b"table/2/abc"),
+            b"table/2"
+        );
+        assert_eq!(comparator.shortest_separator(b"abc", b"abcdef"), b"abcd");
+        assert_eq!(comparator.shortest_separator(b"", b"a"), b"a");
+    }
+
+    /// The reverse comparator inverts the bytewise order.
+    #[test]
+    fn reverse_compare_inverts_order() {
+        assert_eq!(ReverseComparator.compare(b"a", b"b"), Ordering::Greater);
+        assert_eq!(ReverseComparator.compare(b"a", b"a"), Ordering::Equal);
+    }
+
+    /// The case-insensitive comparator ignores the case of ASCII letters.
+    #[test]
+    fn case_insensitive_compare_ignores_case() {
+        let comparator = CaseInsensitiveComparator;
+
+        assert_eq!(comparator.compare(b"Apple", b"apple"), Ordering::Equal);
+        assert_eq!(comparator.compare(b"apple", b"BANANA"), Ordering::Less);
+        assert_eq!(comparator.compare(b"Zebra", b"apple"), Ordering::Greater);
+    }
+
+    /// The numeric comparator orders keys by their value as big-endian integers.
+    #[test]
+    fn numeric_compare_orders_by_value() {
+        let comparator = NumericComparator;
+
+        assert_eq!(comparator.compare(&[2], &[1, 0]), Ordering::Less);
+        assert_eq!(comparator.compare(&[0, 0, 5], &[5]), Ordering::Equal);
+        assert_eq!(comparator.compare(&[], &[0]), Ordering::Equal);
+        assert_eq!(
+            comparator.compare(&[1, 0, 0], &[255, 255]),
+            Ordering::Greater
+        );
+    }
+
+    /// The comparators of the module are found by their name.
+    #[test]
+    fn comparator_by_name_finds_comparators() {
+        let comparator = comparator_by_name("rouilledb.reverse-bytewise");
+
+        assert_eq!(
+            comparator.map(|comparator| comparator.compare(b"a", b"b")),
+            Some(Ordering::Greater)
+        );
+        assert!(comparator_by_name("rouilledb.unknown").is_none());
+    }
+}
@@ -0,0 +1,1542 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::common::checksum::crc32c;
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+use super::defragment::{pack, DEFRAGMENT_FILL};
+use super::estimate;
+use super::node::Node;
+use super::salvage;
+use super::tree::{child_index, separator_before, split_node};
+use super::{BTree, BTreeError, BytewiseComparator, KeyComparator, SalvageReport};
+
+/// Magic bytes at the start of the meta page of a copy-on-write tree.
+const META_MAGIC: [u8; 8] = *b"ROUICOW1";
+
+/// A key and its value.
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// The separator key and the page of the right node of a split.
+type Split = Option<(Vec<u8>, PageId)>;
+
+/// The number of open snapshots of each version.
+type Readers = Arc<Mutex<BTreeMap<u64, usize>>>;
+
+/// Represents a copy-on-write B+tree: a committed page is never modified, so a [Snapshot] keeps
+/// reading the version of the tree it was taken from while the tree is modified.
+///
+/// A modification copies the nodes on the path from the root to the modified leaf to new pages,
+/// which gives a new root. The pages copied since the last commit are modified in place, since no
+/// snapshot can see them. [CowTree::commit] publishes the new root in the meta page of the tree,
+/// which never moves and identifies the tree, and [CowTree::rollback] returns to the last commit.
+///
+/// The pages replaced by a commit are returned to the pager once no snapshot of an older version
+/// is open. The pages still waiting for a snapshot when the tree is dropped are not freed. Snapshots
+/// only take a lock when they are created and dropped, never to read.
+///
+/// The leaves are not linked together, since linking them would require copying the previous leaf
+/// of each modified leaf. The keys are ordered bytewise, unless the tree is created with another
+/// [KeyComparator]. A node left empty by a deletion is removed, but nodes are not merged with their
+/// siblings: [CowTree::compact_range] packs the leaves left partly empty into fewer pages.
+pub struct CowTree {
+    meta: PageId,
+    root: PageId,
+    committed_root: PageId,
+    version: u64,
+    /// The pages written since the last commit, which no snapshot can see.
+    dirty: HashSet<PageId>,
+    /// The committed pages replaced since the last commit.
+    replaced: Vec<PageId>,
+    /// The pages replaced by each commit, by the version of the commit.
+    retired: Vec<(u64, Vec<PageId>)>,
+    readers: Readers,
+    comparator: Arc<dyn KeyComparator>,
+}
+
+impl CowTree {
+    /// Creates a new, empty, tree with its meta page and its root in newly allocated pages.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if a page can't be
+    /// allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::btree::CowTree;
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut tree = CowTree::create(&mut pager).expect("create should not fail");
+    /// tree.insert(&mut pager, b"key", b"old").expect("insert should not fail");
+    /// tree.commit(&mut pager).expect("commit should not fail");
+    /// let snapshot = tree.snapshot();
+    /// tree.insert(&mut pager, b"key", b"new").expect("insert should not fail");
+    /// tree.commit(&mut pager).expect("commit should not fail");
+    ///
+    /// let value = snapshot.get(&pager, b"key").expect("get should not fail");
+    /// assert_eq!(value, Some(b"old".to_vec()));
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, BTreeError> {
+        Self::create_with_comparator(pager, Box::new(BytewiseComparator))
+    }
+
+    /// Creates a new, empty, tree whose keys are ordered by `comparator`. If no tree was created
+    /// in the file yet, the name of the comparator is stored in its header.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the file is ordered by another comparator
+    /// - the name of the comparator is too long to be stored in the header
+    /// - a page can't be allocated or written
+    pub fn create_with_comparator<F: File>(
+        pager: &mut Pager<F>,
+        comparator: Box<dyn KeyComparator>,
+    ) -> Result<Self, BTreeError> {
+        if pager.comparator().is_empty() {
+            pager.set_comparator(comparator.name())?;
+        }
+        check_comparator(pager, comparator.as_ref())?;
+        Self::create_unchecked(pager, comparator)
+    }
+
+    /// Creates a new, empty, tree whose keys are ordered by `comparator`, without comparing it
+    /// with the comparator of the file, so the trees of a file can be ordered differently. The
+    /// comparator must be recorded by the caller, as the catalog of a
+    /// [Database](crate::db::Database) does, and given again to [CowTree::open_unchecked].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be allocated or written.
+    pub fn create_unchecked<F: File>(
+        pager: &mut Pager<F>,
+        comparator: Box<dyn KeyComparator>,
+    ) -> Result<Self, BTreeError> {
+        let meta = pager.allocate_page()?;
+        let root = pager.allocate_page()?;
+        pager.write_page(root, &Node::empty_leaf().encode(pager.page_size()))?;
+        let tree = CowTree {
+            meta,
+            root,
+            committed_root: root,
+            version: 0,
+            dirty: HashSet::new(),
+            replaced: Vec::new(),
+            retired: Vec::new(),
+            readers: Readers::default(),
+            comparator: comparator.into(),
+        };
+        tree.write_meta(pager)?;
+        Ok(tree)
+    }
+
+    /// Opens a tree previously created with [CowTree::create], at its last committed version.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if the meta page
+    /// can't be read or is corrupted.
+    pub fn open<F: File>(pager: &Pager<F>, meta: PageId) -> Result<Self, BTreeError> {
+        Self::open_with_comparator(pager, meta, Box::new(BytewiseComparator))
+    }
+
+    /// Opens a tree previously created with [CowTree::create_with_comparator], at its last
+    /// committed version.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is ordered by another comparator or if the
+    /// meta page can't be read or is corrupted.
+    pub fn open_with_comparator<F: File>(
+        pager: &Pager<F>,
+        meta: PageId,
+        comparator: Box<dyn KeyComparator>,
+    ) -> Result<Self, BTreeError> {
+        check_comparator(pager, comparator.as_ref())?;
+        Self::open_unchecked(pager, meta, comparator)
+    }
+
+    /// Opens a tree previously created with [CowTree::create_unchecked], at its last committed
+    /// version, without comparing `comparator` with the comparator of the file.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the meta page can't be read or is corrupted.
+    pub fn open_unchecked<F: File>(
+        pager: &Pager<F>,
+        meta: PageId,
+        comparator: Box<dyn KeyComparator>,
+    ) -> Result<Self, BTreeError> {
+        let (root, version) = read_meta(pager, meta)?;
+        Ok(CowTree {
+            meta,
+            root,
+            committed_root: root,
+            version,
+            dirty: HashSet::new(),
+            replaced: Vec::new(),
+            retired: Vec::new(),
+            readers: Readers::default(),
+            comparator: comparator.into(),
+        })
+    }
+
+    /// Collects the entries of the last committed version of the tree whose meta page is `meta`,
+    /// from a file that may be damaged. The pages that don't match their checksum or don't contain
+    /// a valid node are skipped, with the subtrees below them, and listed in the report. If the
+    /// meta page itself is damaged, no entry can be found.
+    ///
+    /// The entries are not checked against the comparator, so those of a damaged node may be out
+    /// of order.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a page can't be read.
+    pub fn salvage<F: File>(pager: &Pager<F>, meta: PageId) -> Result<SalvageReport, BTreeError> {
+        match read_meta(pager, meta) {
+            Ok((root, _)) => salvage::salvage(pager, root),
+            Err(BTreeError::CorruptedPage(_) | BTreeError::ChecksumMismatch(_)) => {
+                Ok(SalvageReport {
+                    entries: Vec::new(),
+                    damaged_pages: vec![meta],
+                })
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Returns the comparator ordering the keys of the tree.
+    pub fn comparator(&self) -> &dyn KeyComparator {
+        self.comparator.as_ref()
+    }
+
+    /// Returns the identifier of the meta page of the tree, which identifies it.
+    pub fn meta(&self) -> PageId {
+        self.meta
+    }
+
+    /// Returns the version of the last commit. A new tree is at version `0`.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns the number of pages replaced by commits that are not freed yet, because a snapshot
+    /// of an older version may still read them.
+    pub fn retired_page_count(&self) -> usize {
+        self.retired.iter().map(|(_, pages)| pages.len()).sum()
+    }
+
+    /// Returns the number of versions whose replaced pages are not freed yet: first those no
+    /// open snapshot can see anymore, which [CowTree::reclaim] frees, then those an open snapshot
+    /// may still see.
+    pub fn retired_versions(&self) -> (usize, usize) {
+        let oldest_reader = self.oldest_reader();
+        let reclaimable = self
+            .retired
+            .iter()
+            .filter(|(version, _)| is_reclaimable(*version, oldest_reader))
+            .count();
+        (reclaimable, self.retired.len() - reclaimable)
+    }
+
+    /// Returns the number of pages replaced by commits that are kept because an open snapshot may
+    /// still read them.
+    pub fn pinned_page_count(&self) -> usize {
+        let oldest_reader = self.oldest_reader();
+        self.retired
+            .iter()
+            .filter(|(version, _)| !is_reclaimable(*version, oldest_reader))
+            .map(|(_, pages)| pages.len())
+            .sum()
+    }
+
+    /// Returns a snapshot of the last committed version of the tree. The modifications that are
+    /// not committed yet are not visible to the snapshot.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut readers = self.readers.lock().unwrap_or_else(PoisonError::into_inner);
+        *readers.entry(self.version).or_default() += 1;
+        Snapshot {
+            root: self.committed_root,
+            version: self.version,
+            readers: Arc::clone(&self.readers),
+            comparator: Arc::clone(&self.comparator),
+        }
+    }
+
+    /// Returns the value associated with a key, including the modifications that are not
+    /// committed yet.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, BTreeError> {
+        get(pager, self.root, self.comparator.as_ref(), key)
+    }
+
+    /// Returns the values associated with several keys, in the order of the keys, including the
+    /// modifications that are not committed yet. The keys are sorted and looked up in a single
+    /// descent of the tree, so the nodes on the path to several keys are only read once.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn multi_get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Vec<u8>>>, BTreeError> {
+        multi_get(pager, self.root, self.comparator.as_ref(), keys)
+    }
+
+    /// Inserts a key-value pair in the tree, replacing the previous value of the key. Returns the
+    /// previous value, if there was one. The modification is only visible to the snapshots taken
+    /// after the next commit.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the key or the value is too large (see [BTree::max_key_size] and
+    ///   [BTree::max_entry_size])
+    /// - a page can't be read, written or allocated
+    pub fn insert<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>, BTreeError> {
+        let page_size = pager.page_size();
+        let max_key_size = BTree::max_key_size(page_size);
+        let max_entry_size = BTree::max_entry_size(page_size);
+        if key.len() > max_key_size || key.len() + value.len() > max_entry_size {
+            return Err(BTreeError::EntryTooLarge {
+                key_size: key.len(),
+                value_size: value.len(),
+                max_key_size,
+                max_entry_size,
+            });
+        }
+
+        let (previous, root, split) = self.insert_into(pager, self.root, key, value)?;
+        self.root = root;
+        if let Some((separator, right)) = split {
+            let root = self.allocate(pager)?;
+            let node = Node::Interior {
+                keys: vec![separator],
+                children: vec![self.root, right],
+            };
+            pager.write_page(root, &node.encode(page_size))?;
+            self.root = root;
+        }
+        Ok(previous)
+    }
+
+    /// Removes a key from the tree. Returns the value of the key, if it was present. The
+    /// modification is only visible to the snapshots taken after the next commit.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read, written, allocated or freed.
+    pub fn delete<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, BTreeError> {
+        let (previous, root) = self.delete_from(pager, self.root, key)?;
+        self.root = match root {
+            Some(root) => root,
+            None => {
+                let root = self.allocate(pager)?;
+                pager.write_page(root, &Node::empty_leaf().encode(pager.page_size()))?;
+                root
+            }
+        };
+
+        // A root with a single child is replaced by its child.
+        while let Node::Interior { keys, children } = Node::read(pager, self.root)? {
+            if !keys.is_empty() {
+                break;
+            }
+            self.retire(pager, self.root)?;
+            self.root = children[0];
+        }
+        Ok(previous)
+    }
+
+    /// Packs the consecutive leaves holding keys within `start..end` into as few leaves as
+    /// possible, filled to 90% of a page, which reclaims the space left free by deletions. The
+    /// leaves are copied, as by any modification, so the snapshots keep reading the former
+    /// leaves, and the modification is only visible after the next commit. Returns the number of
+    /// leaves removed from the tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read, written, allocated or freed.
+    pub fn compact_range<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<usize, BTreeError> {
+        let mut compaction = Compaction {
+            start,
+            end,
+            removed: 0,
+        };
+        self.root = self.compact_node(pager, self.root, None, None, &mut compaction)?;
+
+        // A root with a single child is replaced by its child.
+        while let Node::Interior { keys, children } = Node::read(pager, self.root)? {
+            if !keys.is_empty() {
+                break;
+            }
+            self.retire(pager, self.root)?;
+            self.root = children[0];
+        }
+        Ok(compaction.removed)
+    }
+
+    /// Publishes the modifications made since the last commit as a new version of the tree.
+    /// Returns the version, which is unchanged if there was nothing to commit. The pages replaced
+    /// by the modifications are freed once no snapshot of an older version is open.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the meta page can't be written or a page can't be
+    /// freed.
+    pub fn commit<F: File>(&mut self, pager: &mut Pager<F>) -> Result<u64, BTreeError> {
+        if self.dirty.is_empty() && self.replaced.is_empty() {
+            return Ok(self.version);
+        }
+
+        self.version += 1;
+        self.committed_root = self.root;
+        self.write_meta(pager)?;
+        self.dirty.clear();
+        let replaced = std::mem::take(&mut self.replaced);
+        if !replaced.is_empty() {
+            self.retired.push((self.version, replaced));
+        }
+        self.reclaim(pager)?;
+        Ok(self.version)
+    }
+
+    /// Discards the modifications made since the last commit and frees the pages they wrote.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be freed.
+    pub fn rollback<F: File>(&mut self, pager: &mut Pager<F>) -> Result<(), BTreeError> {
+        for page in self.dirty.drain() {
+            pager.free_page(page)?;
+        }
+        self.replaced.clear();
+        self.root = self.committed_root;
+        Ok(())
+    }
+
+    /// Frees all the pages of the tree, including its meta page, after discarding the modifications
+    /// made since the last commit.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or freed.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if a snapshot of the tree is still open.
+    pub fn destroy<F: File>(mut self, pager: &mut Pager<F>) -> Result<(), BTreeError> {
+        assert!(
+            self.oldest_reader().is_none(),
+            "the snapshots of a tree should be dropped before it is destroyed"
+        );
+        self.rollback(pager)?;
+        self.reclaim(pager)?;
+        let mut pending = vec![self.root];
+        while let Some(id) = pending.pop() {
+            if let Node::Interior { children, .. } = Node::read(pager, id)? {
+                pending.extend(children);
+            }
+            pager.free_page(id)?;
+        }
+        pager.free_page(self.meta)?;
+        Ok(())
+    }
+
+    /// Frees the pages replaced by the commits that no open snapshot can see anymore. This is done
+    /// by every commit, but can be called after snapshots are dropped to free their pages sooner.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be freed.
+    pub fn reclaim<F: File>(&mut self, pager: &mut Pager<F>) -> Result<(), BTreeError> {
+        let oldest_reader = self.oldest_reader();
+        let (reclaimed, retired) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .partition(|(version, _)| is_reclaimable(*version, oldest_reader));
+        self.retired = retired;
+        for (_, pages) in reclaimed {
+            for page in pages {
+                pager.free_page(page)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the oldest version read by an open snapshot, if any.
+    fn oldest_reader(&self) -> Option<u64> {
+        self.readers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .next()
+            .copied()
+    }
+
+    /// Inserts a key-value pair in the subtree rooted at `id`. Returns the previous value of the
+    /// key, the page the node was written to and, if it was split, the separator key and the page
+    /// of the new right sibling.
+    fn insert_into<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        id: PageId,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(Option<Vec<u8>>, PageId, Split), BTreeError> {
+        let (previous, node) = match Node::read(pager, id)? {
+            Node::Leaf { mut entries, .. } => {
+                let previous =
+                    match entries.binary_search_by(|(k, _)| self.comparator.compare(k, key)) {
+                        Ok(index) => Some(std::mem::replace(&mut entries[index].1, value.to_vec())),
+                        Err(index) => {
+                            entries.insert(index, (key.to_vec(), value.to_vec()));
+                            None
+                        }
+                    };
+                (
+                    previous,
+                    Node::Leaf {
+                        entries,
+                        next: None,
+                    },
+                )
+            }
+            Node::Interior {
+                mut keys,
+                mut children,
+            } => {
+                let index = child_index(self.comparator.as_ref(), &keys, key);
+                let (previous, child, split) =
+                    self.insert_into(pager, children[index], key, value)?;
+                children[index] = child;
+                if let Some((separator, right)) = split {
+                    keys.insert(index, separator);
+                    children.insert(index + 1, right);
+                }
+                (previous, Node::Interior { keys, children })
+            }
+        };
+
+        let id = self.writable(id, pager)?;
+        let page_size = pager.page_size();
+        if node.encoded_size() <= page_size {
+            pager.write_page(id, &node.encode(page_size))?;
+            return Ok((previous, id, None));
+        }
+        let right_id = self.allocate(pager)?;
+        let (left, separator, right) = split_node(
+            self.comparator.as_ref(),
+            node,
+            right_id,
+            BTree::DEFAULT_FILL_FACTOR,
+            page_size,
+        );
+        // The leaves are not linked together.
+        let left = match left {
+            Node::Leaf { entries, .. } => Node::Leaf {
+                entries,
+                next: None,
+            },
+            interior => interior,
+        };
+        pager.write_page(id, &left.encode(page_size))?;
+        pager.write_page(right_id, &right.encode(page_size))?;
+        Ok((previous, id, Some((separator, right_id))))
+    }
+
+    /// Removes a key from the subtree rooted at `id`. Returns the value of the key and the page
+    /// the node was written to, or `None` if the node was left empty and removed.
+    fn delete_from<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        id: PageId,
+        key: &[u8],
+    ) -> Result<(Option<Vec<u8>>, Option<PageId>), BTreeError> {
+        let (previous, node) = match Node::read(pager, id)? {
+            Node::Leaf { mut entries, .. } => {
+                let Ok(index) = entries.binary_search_by(|(k, _)| self.comparator.compare(k, key))
+                else {
+                    return Ok((None, Some(id)));
+                };
+                let (_, previous) = entries.remove(index);
+                let node = (!entries.is_empty()).then_some(Node::Leaf {
+                    entries,
+                    next: None,
+                });
+                (previous, node)
+            }
+            Node::Interior {
+                mut keys,
+                mut children,
+            } => {
+                let index = child_index(self.comparator.as_ref(), &keys, key);
+                let (previous, child) = self.delete_from(pager, children[index], key)?;
+                let Some(previous) = previous else {
+                    return Ok((None, Some(id)));
+                };
+                match child {
+                    Some(child) => children[index] = child,
+                    None => {
+                        children.remove(index);
+                        if !keys.is_empty() {
+                            keys.remove(index.saturating_sub(1));
+                        }
+                    }
+                }
+                let node = (!children.is_empty()).then_some(Node::Interior { keys, children });
+                (previous, node)
+            }
+        };
+
+        let Some(node) = node else {
+            self.retire(pager, id)?;
+            return Ok((Some(previous), None));
+        };
+        let id = self.writable(id, pager)?;
+        pager.write_page(id, &node.encode(pager.page_size()))?;
+        Ok((Some(previous), Some(id)))
+    }
+
+    /// Compacts the leaves of the subtree rooted at `id`, whose keys are at least `low` and
+    /// smaller than `high`. Returns the page the node was written to.
+    fn compact_node<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        id: PageId,
+        low: Option<&[u8]>,
+        high: Option<&[u8]>,
+        compaction: &mut Compaction,
+    ) -> Result<PageId, BTreeError> {
+        // A tree made of a single leaf can't be compacted.
+        let Node::Interior { keys, children } = Node::read(pager, id)? else {
+            return Ok(id);
+        };
+        let bounds: Vec<Bounds> = (0..children.len())
+            .map(|index| {
+                let child_low = index.checked_sub(1).map(|i| keys[i].as_slice()).or(low);
+                (child_low, keys.get(index).map(Vec::as_slice).or(high))
+            })
+            .collect();
+        let overlapping: Vec<usize> = (0..children.len())
+            .filter(|&index| compaction.overlaps(self.comparator.as_ref(), bounds[index]))
+            .collect();
+        let (Some(&first), Some(&last)) = (overlapping.first(), overlapping.last()) else {
+            return Ok(id);
+        };
+
+        let node = if let Node::Interior { .. } = Node::read(pager, children[first])? {
+            let mut compacted = children.clone();
+            for index in first..=last {
+                let (child_low, child_high) = bounds[index];
+                compacted[index] =
+                    self.compact_node(pager, children[index], child_low, child_high, compaction)?;
+            }
+            if compacted == children {
+                return Ok(id);
+            }
+            Node::Interior {
+                keys,
+                children: compacted,
+            }
+        } else {
+            match self.pack_leaves(pager, &keys, &children, first..last + 1, compaction)? {
+                Some(node) => node,
+                None => return Ok(id),
+            }
+        };
+        let id = self.writable(id, pager)?;
+        pager.write_page(id, &node.encode(pager.page_size()))?;
+        Ok(id)
+    }
+
+    /// Packs the leaves `children[leaves]` of an interior node into new leaves, and returns the
+    /// node pointing to them, or `None` if this would not remove a leaf.
+    fn pack_leaves<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        keys: &[Vec<u8>],
+        children: &[PageId],
+        leaves: std::ops::Range<usize>,
+        compaction: &mut Compaction,
+    ) -> Result<Option<Node>, BTreeError> {
+        let page_size = pager.page_size();
+        let mut entries = Vec::new();
+        for &child in &children[leaves.clone()] {
+            let Node::Leaf {
+                entries: child_entries,
+                ..
+            } = Node::read(pager, child)?
+            else {
+                return Err(BTreeError::CorruptedPage(child));
+            };
+            entries.extend(child_entries);
+        }
+        let groups = pack(&entries, (page_size as f64 * DEFRAGMENT_FILL) as usize);
+        if groups.len() >= leaves.len() {
+            return Ok(None);
+        }
+
+        let separators = groups.windows(2).map(|pair| {
+            let last = &entries[pair[0].end - 1].0;
+            separator_before(
+                self.comparator.as_ref(),
+                Some(last),
+                &entries[pair[1].start].0,
+            )
+        });
+        let mut new_keys = keys[..leaves.start].to_vec();
+        new_keys.extend(separators);
+        new_keys.extend_from_slice(&keys[leaves.end - 1..]);
+        let child_count = children.len() - leaves.len() + groups.len();
+        let parent_size = Node::Interior {
+            keys: new_keys.clone(),
+            children: vec![0; child_count],
+        }
+        .encoded_size();
+        if parent_size > page_size {
+            return Ok(None);
+        }
+        let mut new_children = children[..leaves.start].to_vec();
+        let mut entries = entries.into_iter();
+        for group in &groups {
+            let leaf = Node::Leaf {
+                entries: entries.by_ref().take(group.len()).collect(),
+                next: None,
+            };
+            let page = self.allocate(pager)?;
+            pager.write_page(page, &leaf.encode(page_size))?;
+            new_children.push(page);
+        }
+        new_children.extend_from_slice(&children[leaves.end..]);
+        for &child in &children[leaves.clone()] {
+            self.retire(pager, child)?;
+        }
+        compaction.removed += leaves.len() - groups.len();
+        Ok(Some(Node::Interior {
+            keys: new_keys,
+            children: new_children,
+        }))
+    }
+
+    /// Returns the page a modified node can be written to: the node itself if it was written since
+    /// the last commit, or a new page.
+    fn writable<F: File>(
+        &mut self,
+        id: PageId,
+        pager: &mut Pager<F>,
+    ) -> Result<PageId, BTreeError> {
+        if self.dirty.contains(&id) {
+            return Ok(id);
+        }
+        self.replaced.push(id);
+        self.allocate(pager)
+    }
+
+    /// Allocates a page written before the next commit.
+    fn allocate<F: File>(&mut self, pager: &mut Pager<F>) -> Result<PageId, BTreeError> {
+        let id = pager.allocate_page()?;
+        self.dirty.insert(id);
+        Ok(id)
+    }
+
+    /// Removes a page from the tree. A page written since the last commit is freed right away.
+    fn retire<F: File>(&mut self, pager: &mut Pager<F>, id: PageId) -> Result<(), BTreeError> {
+        if self.dirty.remove(&id) {
+            pager.free_page(id)?;
+        } else {
+            self.replaced.push(id);
+        }
+        Ok(())
+    }
+
+    fn write_meta<F: File>(&self, pager: &mut Pager<F>) -> Result<(), BTreeError> {
+        let mut page = Vec::with_capacity(pager.page_size());
+        page.extend_from_slice(&META_MAGIC);
+        page.extend_from_slice(&self.committed_root.to_le_bytes());
+        page.extend_from_slice(&self.version.to_le_bytes());
+        page.extend_from_slice(&crc32c(&page).to_le_bytes());
+        page.resize(pager.page_size(), 0);
+        pager.write_page(self.meta, &page)?;
+        Ok(())
+    }
+}
+
+/// Reads the meta page of a tree: its magic bytes, its committed root and version, and the CRC32C
+/// of these. Returns the root and the version.
+///
+/// # Errors
+///
+/// This function will return an error if the page can't be read, does not start with the magic
+/// bytes or does not match its checksum.
+fn read_meta<F: File>(pager: &Pager<F>, meta: PageId) -> Result<(PageId, u64), BTreeError> {
+    let page = pager.read_page(meta)?;
+    if page[..META_MAGIC.len()] != META_MAGIC {
+        return Err(BTreeError::CorruptedPage(meta));
+    }
+    let checksum = u32::from_le_bytes(page[20..24].try_into().expect("slice should be 4 bytes"));
+    if checksum != crc32c(&page[..20]) {
+        return Err(BTreeError::ChecksumMismatch(meta));
+    }
+    let root = u32::from_le_bytes(page[8..12].try_into().expect("slice should be 4 bytes"));
+    let version = u64::from_le_bytes(page[12..20].try_into().expect("slice should be 8 bytes"));
+    Ok((root, version))
+}
+
+/// The smallest key of a subtree and the key its keys are smaller than, `None` when unbounded.
+type Bounds<'a> = (Option<&'a [u8]>, Option<&'a [u8]>);
+
+/// The range compacted by [CowTree::compact_range] and the leaves it removed.
+struct Compaction<'a> {
+    start: Option<&'a [u8]>,
+    end: Option<&'a [u8]>,
+    removed: usize,
+}
+
+impl Compaction<'_> {
+    /// Returns `true` if the keys at least `low` and smaller than `high` may be in the range.
+    fn overlaps(&self, comparator: &dyn KeyComparator, (low, high): Bounds) -> bool {
+        let before_start = matches!((self.start, high), (Some(start), Some(high))
+            if comparator.compare(high, start).is_le());
+        let after_end = matches!((self.end, low), (Some(end), Some(low))
+            if comparator.compare(low, end).is_ge());
+        !before_start && !after_end
+    }
+}
+
+/// Represents a committed version of a [CowTree]. The version stays readable, and its pages are
+/// not freed, until the snapshot is dropped.
+pub struct Snapshot {
+    root: PageId,
+    version: u64,
+    readers: Readers,
+    comparator: Arc<dyn KeyComparator>,
+}
+
+impl Snapshot {
+    /// Returns the version of the tree seen by the snapshot.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns the value associated with a key in this version of the tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, BTreeError> {
+        get(pager, self.root, self.comparator.as_ref(), key)
+    }
+
+    /// Returns the values associated with several keys in this version of the tree, in the order
+    /// of the keys. The nodes on the path to several keys are only read once (see
+    /// [CowTree::multi_get]).
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn multi_get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Vec<u8>>>, BTreeError> {
+        multi_get(pager, self.root, self.comparator.as_ref(), keys)
+    }
+
+    /// Returns an estimate of the bytes of the file used by the entries of this version of the
+    /// tree within a range of keys, without iterating over the range: the size of the leaves they
+    /// fill, estimated like [BTree::estimate_size].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn estimate_disk_size<F, K, R>(&self, pager: &Pager<F>, range: R) -> Result<u64, BTreeError>
+    where
+        F: File,
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let estimate = estimate::estimate(
+            pager,
+            self.root,
+            self.comparator.as_ref(),
+            range.start_bound().map(AsRef::as_ref),
+            range.end_bound().map(AsRef::as_ref),
+        )?;
+        Ok((estimate.leaves * pager.page_size() as f64).round() as u64)
+    }
+
+    /// Returns an iterator over the entries of this version of the tree, in key order.
+    pub fn iter<'a, F: File>(&self, pager: &'a Pager<F>) -> SnapshotIter<'a, F> {
+        self.range::<F, [u8], _>(pager, ..)
+    }
+
+    /// Returns an iterator over the entries of this version of the tree whose keys are within
+    /// `range`, in key order. Only the nodes that may hold keys of the range are read.
+    pub fn range<'a, F, K, R>(&self, pager: &'a Pager<F>, range: R) -> SnapshotIter<'a, F>
+    where
+        F: File,
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        SnapshotIter {
+            pager,
+            pending: vec![self.root],
+            entries: Vec::new().into_iter(),
+            start: range.start_bound().map(|key| key.as_ref().to_vec()),
+            end: range.end_bound().map(|key| key.as_ref().to_vec()),
+            comparator: Arc::clone(&self.comparator),
+        }
+    }
+}
+
+impl Clone for Snapshot {
+    /// Returns another snapshot of the same version, which keeps it readable until both are
+    /// dropped.
+    fn clone(&self) -> Self {
+        let mut readers = self.readers.lock().unwrap_or_else(PoisonError::into_inner);
+        *readers.entry(self.version).or_default() += 1;
+        Snapshot {
+            root: self.root,
+            version: self.version,
+            readers: Arc::clone(&self.readers),
+            comparator: Arc::clone(&self.comparator),
+        }
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let mut readers = self.readers.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(count) = readers.get_mut(&self.version) {
+            *count -= 1;
+            if *count == 0 {
+                readers.remove(&self.version);
+            }
+        }
+    }
+}
+
+/// An iterator over the entries of a [Snapshot] within a range of keys, in key order.
+///
+/// The pages are read lazily, one leaf at a time, and the iterator only keeps the entries of the
+/// current leaf and the nodes left to visit on the path to it. After an error is returned, the
+/// iterator does not return any more items.
+pub struct SnapshotIter<'a, F: File> {
+    pager: &'a Pager<F>,
+    /// The nodes left to visit, the next one last.
+    pending: Vec<PageId>,
+    entries: std::vec::IntoIter<Entry>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    comparator: Arc<dyn KeyComparator>,
+}
+
+impl<F: File> SnapshotIter<'_, F> {
+    /// Returns `true` if a key is before the start of the range.
+    fn before_start(&self, key: &[u8]) -> bool {
+        match &self.start {
+            Bound::Included(start) => self.comparator.compare(key, start).is_lt(),
+            Bound::Excluded(start) => self.comparator.compare(key, start).is_le(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    /// Returns `true` if a key is after the end of the range.
+    fn after_end(&self, key: &[u8]) -> bool {
+        match &self.end {
+            Bound::Included(end) => self.comparator.compare(key, end).is_gt(),
+            Bound::Excluded(end) => self.comparator.compare(key, end).is_ge(),
+            Bound::Unbounded => false,
+        }
+    }
+}
+
+impl<F: File> Iterator for SnapshotIter<'_, F> {
+    type Item = Result<Entry, BTreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                if self.after_end(&entry.0) {
+                    self.pending.clear();
+                    self.entries = Vec::new().into_iter();
+                    return None;
+                }
+                return Some(Ok(entry));
+            }
+            let id = self.pending.pop()?;
+            match Node::read(self.pager, id) {
+                Ok(Node::Leaf { mut entries, .. }) => {
+                    let first = entries.partition_point(|(key, _)| self.before_start(key));
+                    self.entries = entries.split_off(first).into_iter();
+                }
+                Ok(Node::Interior { keys, children }) => {
+                    // The children before the one holding the start of the range, and after the
+                    // one holding its end, are skipped.
+                    let first = match &self.start {
+                        Bound::Included(key) | Bound::Excluded(key) => {
+                            child_index(self.comparator.as_ref(), &keys, key)
+                        }
+                        Bound::Unbounded => 0,
+                    };
+                    let last = match &self.end {
+                        Bound::Included(key) | Bound::Excluded(key) => {
+                            child_index(self.comparator.as_ref(), &keys, key)
+                        }
+                        Bound::Unbounded => children.len() - 1,
+                    };
+                    self.pending
+                        .extend(children[first..=last.max(first)].iter().rev());
+                }
+                Err(error) => {
+                    self.pending.clear();
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+/// Returns the value associated with a key in the tree rooted at `root`.
+fn get<F: File>(
+    pager: &Pager<F>,
+    root: PageId,
+    comparator: &dyn KeyComparator,
+    key: &[u8],
+) -> Result<Option<Vec<u8>>, BTreeError> {
+    let mut id = root;
+    loop {
+        match Node::read(pager, id)? {
+            Node::Leaf { mut entries, .. } => {
+                return Ok(entries
+                    .binary_search_by(|(k, _)| comparator.compare(k, key))
+                    .ok()
+                    .map(|index| entries.swap_remove(index).1));
+            }
+            Node::Interior { keys, children } => {
+                id = children[child_index(comparator, &keys, key)];
+            }
+        }
+    }
+}
+
+/// Returns the values associated with keys in the tree rooted at `root`, in the order of the keys.
+fn multi_get<F: File>(
+    pager: &Pager<F>,
+    root: PageId,
+    comparator: &dyn KeyComparator,
+    keys: &[&[u8]],
+) -> Result<Vec<Option<Vec<u8>>>, BTreeError> {
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by(|&a, &b| comparator.compare(keys[a], keys[b]));
+    let mut values = vec![None; keys.len()];
+    // The nodes left to visit, with the range of `order` holding the keys to look up in them.
+    let mut pending = vec![(root, 0..order.len())];
+    while let Some((id, range)) = pending.pop() {
+        match Node::read(pager, id)? {
+            Node::Leaf { entries, .. } => {
+                for &index in &order[range] {
+                    if let Ok(position) =
+                        entries.binary_search_by(|(k, _)| comparator.compare(k, keys[index]))
+                    {
+                        values[index] = Some(entries[position].1.clone());
+                    }
+                }
+            }
+            Node::Interior {
+                keys: separators,
+                children,
+            } => {
+                // The sorted keys going to the same child are consecutive.
+                let mut start = range.start;
+                while start < range.end {
+                    let child = child_index(comparator, &separators, keys[order[start]]);
+                    let end = start
+                        + order[start..range.end].partition_point(|&index| {
+                            child_index(comparator, &separators, keys[index]) == child
+                        });
+                    pending.push((children[child], start..end));
+                    start = end;
+                }
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Checks that the keys of the file are ordered by `comparator`.
+fn check_comparator<F: File>(
+    pager: &Pager<F>,
+    comparator: &dyn KeyComparator,
+) -> Result<(), BTreeError> {
+    if pager.comparator() != comparator.name() {
+        return Err(BTreeError::ComparatorMismatch {
+            expected: pager.comparator().to_string(),
+            actual: comparator.name().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Returns `true` if the pages replaced by the commit of `version` can be freed: they are only
+/// seen by the older versions, so by no snapshot if the oldest one reads `version` or a later one.
+fn is_reclaimable(version: u64, oldest_reader: Option<u64>) -> bool {
+    oldest_reader.is_none_or(|oldest| oldest >= version)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap as Reference;
+
+    use rand::seq::SliceRandom;
+
+    use crate::btree::ReverseComparator;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_tree() -> (Pager<MemoryFile>, CowTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let tree = CowTree::create(&mut pager).expect("create should not fail");
+        (pager, tree)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:06}").into_bytes()
+    }
+
+    fn entries(pager: &Pager<MemoryFile>, snapshot: &Snapshot) -> Vec<Entry> {
+        snapshot
+            .iter(pager)
+            .map(|entry| entry.expect("iteration should not fail"))
+            .collect()
+    }
+
+    /// A tree ordered differently from its file is reopened with its own comparator, and
+    /// destroying it frees every page it used.
+    #[test]
+    fn destroy_unchecked_tree_frees_pages() {
+        let (mut pager, _) = create_tree();
+        let free_before = pager
+            .free_pages()
+            .expect("free_pages should not fail")
+            .len();
+        let page_count = pager.page_count();
+        let mut tree = CowTree::create_unchecked(&mut pager, Box::new(ReverseComparator))
+            .expect("create_unchecked should not fail");
+        for index in 0..200 {
+            tree.insert(&mut pager, &key(index), b"value")
+                .expect("insert should not fail");
+        }
+        tree.commit(&mut pager).expect("commit should not fail");
+
+        let tree = CowTree::open_unchecked(&pager, tree.meta(), Box::new(ReverseComparator))
+            .expect("open_unchecked should not fail");
+        let first = tree
+            .snapshot()
+            .iter(&pager)
+            .next()
+            .map(|entry| entry.expect("iteration should not fail").0);
+        let added = (pager.page_count() - page_count) as usize;
+        tree.destroy(&mut pager).expect("destroy should not fail");
+
+        assert_eq!(first, Some(key(199)));
+        assert_eq!(
+            pager
+                .free_pages()
+                .expect("free_pages should not fail")
+                .len(),
+            free_before + added
+        );
+    }
+
+    /// A snapshot keeps seeing its version while the tree is modified and committed.
+    #[test]
+    fn snapshot_sees_its_version() {
+        let (mut pager, mut tree) = create_tree();
+        let mut reference = Reference::new();
+        for index in 0..500 {
+            tree.insert(&mut pager, &key(index), b"first")
+                .expect("insert should not fail");
+            reference.insert(key(index), b"first".to_vec());
+        }
+        tree.commit(&mut pager).expect("commit should not fail");
+        let snapshot = tree.snapshot();
+
+        for index in (0..500).step_by(2) {
+            tree.delete(&mut pager, &key(index))
+                .expect("delete should not fail");
+        }
+        for index in 500..700 {
+            tree.insert(&mut pager, &key(index), b"second")
+                .expect("insert should not fail");
+        }
+        let version = tree.commit(&mut pager).expect("commit should not fail");
+        let latest = tree.snapshot();
+
+        assert_eq!(snapshot.version(), 1);
+        assert_eq!(version, 2);
+        assert_eq!(
+            entries(&pager, &snapshot),
+            reference.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(entries(&pager, &latest).len(), 450);
+        assert_eq!(
+            latest.get(&pager, &key(600)).expect("get should not fail"),
+            Some(b"second".to_vec())
+        );
+        assert_eq!(
+            snapshot
+                .get(&pager, &key(600))
+                .expect("get should not fail"),
+            None
+        );
+    }
+
+    /// The pages replaced by commits are reused once the snapshots that can see them are
+    /// dropped.
+    #[test]
+    fn commit_reclaims_pages_after_snapshots_are_dropped() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..500 {
+            tree.insert(&mut pager, &key(index), b"value")
+                .expect("insert should not fail");
+        }
+        tree.commit(&mut pager).expect("commit should not fail");
+
+        let snapshot = tree.snapshot();
+        for round in 0..20 {
+            tree.insert(&mut pager, &key(round), b"updated")
+                .expect("insert should not fail");
+            tree.commit(&mut pager).expect("commit should not fail");
+        }
+        let free_with_snapshot = pager
+            .free_pages()
+            .expect("free_pages should not fail")
+            .len();
+        let versions_with_snapshot = tree.retired_versions();
+        drop(snapshot);
+        let versions_without_snapshot = tree.retired_versions();
+        tree.reclaim(&mut pager).expect("reclaim should not fail");
+        let page_count = pager.page_count();
+        for round in 0..20 {
+            tree.insert(&mut pager, &key(round), b"again")
+                .expect("insert should not fail");
+            tree.commit(&mut pager).expect("commit should not fail");
+        }
+
+        assert_eq!(free_with_snapshot, 0);
+        assert_eq!(versions_with_snapshot, (0, 20));
+        assert_eq!(versions_without_snapshot, (20, 0));
+        assert_eq!(tree.retired_versions(), (0, 0));
+        assert_eq!(pager.page_count(), page_count);
+    }
+
+    /// A rollback discards the modifications and frees the pages they wrote.
+    #[test]
+    fn rollback_discards_modifications() {
+        let (mut pager, mut tree) = create_tree();
+        tree.insert(&mut pager, b"kept", b"value")
+            .expect("insert should not fail");
+        tree.commit(&mut pager).expect("commit should not fail");
+
+        let mut indexes: Vec<usize> = (0..300).collect();
+        indexes.shuffle(&mut rand::thread_rng());
+        for index in indexes {
+            tree.insert(&mut pager, &key(index), b"value")
+                .expect("insert should not fail");
+        }
+        tree.delete(&mut pager, b"kept")
+            .expect("delete should not fail");
+        let page_count = pager.page_count() as usize;
+        tree.rollback(&mut pager).expect("rollback should not fail");
+
+        assert_eq!(
+            tree.get(&pager, b"kept").expect("get should not fail"),
+            Some(b"value".to_vec())
+        );
+        assert_eq!(
+            tree.get(&pager, &key(0)).expect("get should not fail"),
+            None
+        );
+        // Only the header, the meta page and the root remain used.
+        assert_eq!(
+            pager
+                .free_pages()
+                .expect("free_pages should not fail")
+                .len(),
+            page_count - 3
+        );
+    }
+
+    /// A tree reopened from its meta page is at its last committed version.
+    #[test]
+    fn open_existing_tree_reads_last_commit() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..200 {
+            tree.insert(&mut pager, &key(index), b"value")
+                .expect("insert should not fail");
+        }
+        tree.commit(&mut pager).expect("commit should not fail");
+        tree.delete(&mut pager, &key(5))
+            .expect("delete should not fail");
+
+        let opened = CowTree::open(&pager, tree.meta()).expect("open should not fail");
+        let snapshot = opened.snapshot();
+
+        assert_eq!(opened.version(), 1);
+        assert_eq!(entries(&pager, &snapshot).len(), 200);
+    }
+
+    /// A tree whose meta page was damaged since its last commit can't be opened, and nothing can
+    /// be salvaged from it.
+    #[test]
+    fn open_damaged_meta_fails() {
+        let (mut pager, mut tree) = create_tree();
+        tree.insert(&mut pager, &key(1), b"value")
+            .expect("insert should not fail");
+        tree.commit(&mut pager).expect("commit should not fail");
+        let mut page = pager.read_page(tree.meta()).expect("read should not fail");
+        page[12] ^= 1;
+        pager
+            .write_page(tree.meta(), &page)
+            .expect("write should not fail");
+
+        let opened = CowTree::open(&pager, tree.meta());
+        let report = CowTree::salvage(&pager, tree.meta()).expect("salvage should not fail");
+
+        assert!(matches!(opened, Err(BTreeError::ChecksumMismatch(meta)) if meta == tree.meta()));
+        assert_eq!(report.damaged_pages, vec![tree.meta()]);
+    }
+
+    /// A range of a snapshot returns the entries within its bounds, and only reads the nodes that
+    /// may hold them.
+    #[test]
+    fn snapshot_range_returns_entries_within_bounds() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..500 {
+            tree.insert(&mut pager, &key(index), b"value")
+                .expect("insert should not fail");
+        }
+        tree.commit(&mut pager).expect("commit should not fail");
+        let snapshot = tree.snapshot();
+        let keys = |range: (Bound<&[u8]>, Bound<&[u8]>)| -> Vec<Vec<u8>> {
+            snapshot
+                .range::<_, [u8], _>(&pager, range)
+                .map(|entry| entry.expect("iteration should not fail").0)
+                .collect()
+        };
+        let (start, end) = (key(100), key(200));
+
+        let included = keys((
+            Bound::Included(start.as_slice()),
+            Bound::Included(end.as_slice()),
+        ));
+        let excluded = keys((
+            Bound::Excluded(start.as_slice()),
+            Bound::Excluded(end.as_slice()),
+        ));
+        let after = keys((Bound::Excluded(b"key-000499".as_slice()), Bound::Unbounded));
+        let before = keys((Bound::Unbounded, Bound::Excluded(b"key-000000".as_slice())));
+
+        assert_eq!(included, (100..=200).map(key).collect::<Vec<_>>());
+        assert_eq!(excluded, (101..200).map(key).collect::<Vec<_>>());
+        assert!(after.is_empty());
+        assert!(before.is_empty());
+        assert_eq!(
+            keys((
+                Bound::Included(b"key-0001".as_slice()),
+                Bound::Excluded(b"key-0002".as_slice())
+            ))
+            .len(),
+            100
+        );
+    }
+
+    /// A tree created with another comparator orders its keys, and its ranges, by that
+    /// comparator, and can only be reopened with it.
+    #[test]
+    fn create_with_comparator_orders_keys() {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = CowTree::create_with_comparator(&mut pager, Box::new(ReverseComparator))
+            .expect("create_with_comparator should not fail");
+        for index in 0..300 {
+            tree.insert(&mut pager, &key(index), b"value")
+                .expect("insert should not fail");
+        }
+        tree.commit(&mut pager).expect("commit should not fail");
+        let meta = tree.meta();
+
+        let snapshot = tree.snapshot();
+        let keys: Vec<Vec<u8>> = snapshot
+            .range(&pager, key(200).as_slice()..key(100).as_slice())
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+        let reopened = CowTree::open(&pager, meta);
+
+        assert_eq!(keys, (101..=200).rev().map(key).collect::<Vec<_>>());
+        assert_eq!(
+            entries(&pager, &snapshot)
+                .first()
+                .map(|entry| entry.0.clone()),
+            Some(key(299))
+        );
+        assert_eq!(
+            tree.get(&pager, &key(42)).expect("get should not fail"),
+            Some(b"value".to_vec())
+        );
+        assert!(matches!(
+            reopened,
+            Err(BTreeError::ComparatorMismatch { .. })
+        ));
+    }
+
+    /// A multi-get returns the same values as a get of each key, in the order of the keys, with
+    /// missing and repeated keys.
+    #[test]
+    fn multi_get_returns_values_in_key_order() {
+        let (mut pager, mut tree) = create_tree();
+        for index in (0..1000).step_by(2) {
+            tree.insert(&mut pager, &key(index), &index.to_le_bytes())
+                .expect("insert should not fail");
+        }
+        tree.commit(&mut pager).expect("commit should not fail");
+        let snapshot = tree.snapshot();
+        tree.delete(&mut pager, &key(500))
+            .expect("delete should not fail");
+        let mut keys: Vec<Vec<u8>> = (0..1000).step_by(7).map(key).collect();
+        keys.push(key(998));
+        keys.push(key(500));
+        keys.push(b"missing".to_vec());
+        keys.shuffle(&mut rand::thread_rng());
+        let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+
+        let values = tree
+            .multi_get(&pager, &keys)
+            .expect("multi_get should not fail");
+        let snapshot_values = snapshot
+            .multi_get(&pager, &keys)
+            .expect("multi_get should not fail");
+
+        for (index, key) in keys.iter().enumerate() {
+            assert_eq!(
+                values[index],
+                tree.get(&pager, key).expect("get should not fail")
+            );
+            assert_eq!(
+                snapshot_values[index],
+                snapshot.get(&pager, key).expect("get should not fail")
+            );
+        }
+        assert!(values.iter().any(Option::is_some));
+        assert_eq!(
+            snapshot_values.iter().flatten().count(),
+            values.iter().flatten().count() + 1
+        );
+    }
+
+    /// The estimated size of the file used by the whole tree is close to the size of the file, and
+    /// the estimates of two halves add up to about the estimate of the whole range.
+    #[test]
+    fn estimate_disk_size_is_close() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..2000 {
+            tree.insert(&mut pager, &key(index), &[7; 40])
+                .expect("insert should not fail");
+        }
+        tree.commit(&mut pager).expect("commit should not fail");
+        let snapshot = tree.snapshot();
+        let file_size = (pager.page_count() as usize * pager.page_size()) as f64;
+
+        let whole = snapshot
+            .estimate_disk_size::<_, [u8], _>(&pager, ..)
+            .expect("estimate_disk_size should not fail") as f64;
+        let first = snapshot
+            .estimate_disk_size(&pager, ..key(1000))
+            .expect("estimate_disk_size should not fail") as f64;
+        let second = snapshot
+            .estimate_disk_size(&pager, key(1000)..)
+            .expect("estimate_disk_size should not fail") as f64;
+        let empty = snapshot
+            .estimate_disk_size(&pager, key(5000)..)
+            .expect("estimate_disk_size should not fail");
+
+        assert!((whole - file_size).abs() < file_size * 0.2);
+        assert!((first + second - whole).abs() < whole * 0.2);
+        assert!((first - second).abs() < whole * 0.2);
+        assert_eq!(empty, 0);
+    }
+
+    /// A compaction packs the leaves of the range left underfull by deletions, keeps every entry
+    /// and leaves the snapshots reading the former leaves.
+    #[test]
+    fn compact_range_packs_underfull_leaves() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..2000 {
+            tree.insert(&mut pager, &key(index), b"value")
+                .expect("insert should not fail");
+        }
+        for index in (0..2000).filter(|index| index % 10 != 0) {
+            tree.delete(&mut pager, &key(index))
+                .expect("delete should not fail");
+        }
+        tree.commit(&mut pager).expect("commit should not fail");
+        let snapshot = tree.snapshot();
+        let before = entries(&pager, &snapshot);
+
+        let removed = tree
+            .compact_range(&mut pager, Some(&key(500)), None)
+            .expect("compact_range should not fail");
+        tree.commit(&mut pager).expect("commit should not fail");
+        let compacted = tree.snapshot();
+
+        assert!(removed > 0);
+        assert_eq!(entries(&pager, &compacted), before);
+        assert_eq!(entries(&pager, &snapshot), before);
+        assert_eq!(
+            tree.compact_range(&mut pager, Some(&key(500)), None)
+                .expect("compact_range should not fail"),
+            0
+        );
+    }
+}
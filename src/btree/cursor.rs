@@ -0,0 +1,1021 @@
+use std::cmp::Ordering;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+use super::node::Node;
+use super::tree::child_index;
+use super::{BTree, BTreeError, KeyComparator};
+
+/// Represents a position in a [BTree](super::BTree) that can be moved forward and backward.
+///
+/// A cursor is either positioned on an entry of the tree or invalid (before the first entry or
+/// after the last one). A newly created cursor is invalid until one of the seek methods is called.
+///
+/// The cursor keeps a copy of the leaf it is positioned on and the path from the root to that
+/// leaf. It does not keep any page borrowed from the pager between calls.
+pub struct Cursor<'a, F: File> {
+    pager: &'a Pager<F>,
+    root: PageId,
+    comparator: &'a dyn KeyComparator,
+    position: Position,
+}
+
+impl<'a, F: File> Cursor<'a, F> {
+    /// Creates a new, invalid, cursor on the tree rooted at `root` and ordered by `comparator`.
+    pub(super) fn new(
+        pager: &'a Pager<F>,
+        root: PageId,
+        comparator: &'a dyn KeyComparator,
+    ) -> Self {
+        Cursor {
+            pager,
+            root,
+            comparator,
+            position: Position::default(),
+        }
+    }
+
+    /// Returns `true` if the cursor is positioned on an entry.
+    pub fn is_valid(&self) -> bool {
+        self.position.is_valid()
+    }
+
+    /// Returns the key of the entry the cursor is positioned on.
+    pub fn key(&self) -> Option<&[u8]> {
+        self.position.key()
+    }
+
+    /// Returns the value of the entry the cursor is positioned on.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.position.value()
+    }
+
+    /// Positions the cursor on the first entry whose key is greater or equal to `key`. The cursor
+    /// is invalid if there is no such entry.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek(&mut self, key: &[u8]) -> Result<(), BTreeError> {
+        self.position
+            .seek(self.pager, self.root, self.comparator, key)
+    }
+
+    /// Positions the cursor on the entry whose key is `key`. The cursor is invalid if the key is
+    /// not in the tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_exact(&mut self, key: &[u8]) -> Result<(), BTreeError> {
+        self.position
+            .seek_exact(self.pager, self.root, self.comparator, key)
+    }
+
+    /// Positions the cursor on the last entry whose key is smaller or equal to `key`. The cursor
+    /// is invalid if there is no such entry.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_for_prev(&mut self, key: &[u8]) -> Result<(), BTreeError> {
+        self.position
+            .seek_for_prev(self.pager, self.root, self.comparator, key)
+    }
+
+    /// Positions the cursor on the first entry of the tree. The cursor is invalid if the tree is
+    /// empty.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_to_first(&mut self) -> Result<(), BTreeError> {
+        self.position.seek_to_first(self.pager, self.root)
+    }
+
+    /// Positions the cursor on the last entry of the tree. The cursor is invalid if the tree is
+    /// empty.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_to_last(&mut self) -> Result<(), BTreeError> {
+        self.position.seek_to_last(self.pager, self.root)
+    }
+
+    /// Moves the cursor to the next entry. The cursor becomes invalid if it was positioned on the
+    /// last entry. Does nothing if the cursor is invalid.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<(), BTreeError> {
+        self.position.next(self.pager)
+    }
+
+    /// Moves the cursor to the previous entry. The cursor becomes invalid if it was positioned on
+    /// the first entry. Does nothing if the cursor is invalid.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn prev(&mut self) -> Result<(), BTreeError> {
+        self.position.prev(self.pager)
+    }
+
+    /// Compares two keys in the order of the tree.
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        self.comparator.compare(a, b)
+    }
+}
+
+/// Represents a position in a [BTree] that can be moved like a [Cursor] and used to modify the
+/// entry it is positioned on.
+///
+/// The cursor borrows the tree and the pager mutably for its whole life. Like a [Cursor], it keeps
+/// a copy of the leaf it is positioned on and the path to that leaf, so its moves only read the
+/// pages a [Cursor] would. The entry is only looked up again from the root after a modification
+/// that split or merged the leaf.
+pub struct CursorMut<'a, F: File> {
+    pager: &'a mut Pager<F>,
+    tree: &'a mut BTree,
+    position: Position,
+}
+
+impl<'a, F: File> CursorMut<'a, F> {
+    /// Creates a new, invalid, cursor on a tree.
+    pub(super) fn new(pager: &'a mut Pager<F>, tree: &'a mut BTree) -> Self {
+        CursorMut {
+            pager,
+            tree,
+            position: Position::default(),
+        }
+    }
+
+    /// Returns `true` if the cursor is positioned on an entry.
+    pub fn is_valid(&self) -> bool {
+        self.position.is_valid()
+    }
+
+    /// Returns the key of the entry the cursor is positioned on.
+    pub fn key(&self) -> Option<&[u8]> {
+        self.position.key()
+    }
+
+    /// Returns the value of the entry the cursor is positioned on.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.position.value()
+    }
+
+    /// Positions the cursor on the first entry whose key is greater or equal to `key`. See
+    /// [Cursor::seek].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek(&mut self, key: &[u8]) -> Result<(), BTreeError> {
+        let root = self.tree.root();
+        self.position
+            .seek(self.pager, root, self.tree.comparator(), key)
+    }
+
+    /// Positions the cursor on the entry whose key is `key`. See [Cursor::seek_exact].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_exact(&mut self, key: &[u8]) -> Result<(), BTreeError> {
+        let root = self.tree.root();
+        self.position
+            .seek_exact(self.pager, root, self.tree.comparator(), key)
+    }
+
+    /// Positions the cursor on the last entry whose key is smaller or equal to `key`. See
+    /// [Cursor::seek_for_prev].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_for_prev(&mut self, key: &[u8]) -> Result<(), BTreeError> {
+        let root = self.tree.root();
+        self.position
+            .seek_for_prev(self.pager, root, self.tree.comparator(), key)
+    }
+
+    /// Positions the cursor on the first entry of the tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_to_first(&mut self) -> Result<(), BTreeError> {
+        self.position.seek_to_first(self.pager, self.tree.root())
+    }
+
+    /// Positions the cursor on the last entry of the tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_to_last(&mut self) -> Result<(), BTreeError> {
+        self.position.seek_to_last(self.pager, self.tree.root())
+    }
+
+    /// Moves the cursor to the next entry. The cursor becomes invalid if it was positioned on the
+    /// last entry. Does nothing if the cursor is invalid.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<(), BTreeError> {
+        self.position.next(self.pager)
+    }
+
+    /// Moves the cursor to the previous entry. The cursor becomes invalid if it was positioned on
+    /// the first entry. Does nothing if the cursor is invalid.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn prev(&mut self) -> Result<(), BTreeError> {
+        self.position.prev(self.pager)
+    }
+
+    /// Replaces the value of the entry the cursor is positioned on. The cursor stays on the
+    /// entry.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the cursor is not positioned on an entry
+    /// - the value is too large (see [BTree::max_entry_size])
+    /// - a page can't be read, written or allocated
+    pub fn put_current(&mut self, value: &[u8]) -> Result<(), BTreeError> {
+        let Some(index) = self.position.index else {
+            return Err(BTreeError::InvalidCursor);
+        };
+        self.tree
+            .insert(self.pager, &self.position.entries[index].0, value)?;
+
+        // The copy of the leaf is kept if the insertion left its entries in place. Otherwise the
+        // leaf was split, and the path to the entry changed with it.
+        let leaf = self.position.leaf(self.tree.root());
+        if let Node::Leaf { entries, .. } = Node::read(self.pager, leaf)? {
+            if entries.len() == self.position.entries.len()
+                && entries[index].0 == self.position.entries[index].0
+            {
+                self.position.entries = entries;
+                return Ok(());
+            }
+        }
+        let key = self.position.entries[index].0.clone();
+        self.seek(&key)
+    }
+
+    /// Removes the entry the cursor is positioned on and returns its value. The cursor moves to
+    /// the next entry, or becomes invalid if the removed entry was the last one.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the cursor is not positioned on an entry or if a page
+    /// can't be read or written.
+    pub fn delete_current(&mut self) -> Result<Vec<u8>, BTreeError> {
+        let Some(index) = self.position.index else {
+            return Err(BTreeError::InvalidCursor);
+        };
+        let (key, value) = mem::take(&mut self.position.entries[index]);
+        self.position.invalidate();
+        self.tree.delete(self.pager, &key)?;
+        // The deletion may have merged the leaf with a sibling, so the next entry is looked up
+        // from the root.
+        self.seek(&key)?;
+        Ok(value)
+    }
+}
+
+/// The position of a [Cursor] or a [CursorMut]: a copy of the leaf it is positioned on, the index
+/// of the entry in that leaf, and the path from the root to that leaf.
+#[derive(Default)]
+struct Position {
+    path: Vec<(Vec<PageId>, usize)>,
+    entries: Vec<Entry>,
+    index: Option<usize>,
+}
+
+impl Position {
+    fn is_valid(&self) -> bool {
+        self.index.is_some()
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        self.index.map(|index| self.entries[index].0.as_slice())
+    }
+
+    fn value(&self) -> Option<&[u8]> {
+        self.index.map(|index| self.entries[index].1.as_slice())
+    }
+
+    /// Returns the page of the leaf the position is in, in the tree rooted at `root`.
+    fn leaf(&self, root: PageId) -> PageId {
+        self.path
+            .last()
+            .map_or(root, |(children, index)| children[*index])
+    }
+
+    fn seek<F: File>(
+        &mut self,
+        pager: &Pager<F>,
+        root: PageId,
+        comparator: &dyn KeyComparator,
+        key: &[u8],
+    ) -> Result<(), BTreeError> {
+        self.path.clear();
+        let mut id = root;
+        loop {
+            match Node::read(pager, id)? {
+                Node::Leaf { entries, .. } => {
+                    let index =
+                        entries.partition_point(|(k, _)| comparator.compare(k, key).is_lt());
+                    self.entries = entries;
+                    if index < self.entries.len() {
+                        self.index = Some(index);
+                        return Ok(());
+                    }
+                    return self.next_leaf(pager);
+                }
+                Node::Interior { keys, children } => {
+                    let index = child_index(comparator, &keys, key);
+                    id = children[index];
+                    self.path.push((children, index));
+                }
+            }
+        }
+    }
+
+    fn seek_exact<F: File>(
+        &mut self,
+        pager: &Pager<F>,
+        root: PageId,
+        comparator: &dyn KeyComparator,
+        key: &[u8],
+    ) -> Result<(), BTreeError> {
+        self.seek(pager, root, comparator, key)?;
+        if self
+            .key()
+            .is_some_and(|k| comparator.compare(k, key).is_ne())
+        {
+            self.invalidate();
+        }
+        Ok(())
+    }
+
+    fn seek_for_prev<F: File>(
+        &mut self,
+        pager: &Pager<F>,
+        root: PageId,
+        comparator: &dyn KeyComparator,
+        key: &[u8],
+    ) -> Result<(), BTreeError> {
+        self.seek(pager, root, comparator, key)?;
+        match self.key() {
+            Some(k) if comparator.compare(k, key).is_eq() => Ok(()),
+            Some(_) => self.prev(pager),
+            None => self.seek_to_last(pager, root),
+        }
+    }
+
+    fn seek_to_first<F: File>(&mut self, pager: &Pager<F>, root: PageId) -> Result<(), BTreeError> {
+        self.path.clear();
+        self.descend(pager, root, Direction::Forward)
+    }
+
+    fn seek_to_last<F: File>(&mut self, pager: &Pager<F>, root: PageId) -> Result<(), BTreeError> {
+        self.path.clear();
+        self.descend(pager, root, Direction::Backward)
+    }
+
+    fn next<F: File>(&mut self, pager: &Pager<F>) -> Result<(), BTreeError> {
+        match self.index {
+            Some(index) if index + 1 < self.entries.len() => {
+                self.index = Some(index + 1);
+                Ok(())
+            }
+            Some(_) => self.next_leaf(pager),
+            None => Ok(()),
+        }
+    }
+
+    fn prev<F: File>(&mut self, pager: &Pager<F>) -> Result<(), BTreeError> {
+        match self.index {
+            Some(index) if index > 0 => {
+                self.index = Some(index - 1);
+                Ok(())
+            }
+            Some(_) => self.previous_leaf(pager),
+            None => Ok(()),
+        }
+    }
+
+    /// Positions the cursor on the first entry of the leaf following the current one.
+    fn next_leaf<F: File>(&mut self, pager: &Pager<F>) -> Result<(), BTreeError> {
+        while let Some((children, index)) = self.path.pop() {
+            if index + 1 < children.len() {
+                let child = children[index + 1];
+                self.path.push((children, index + 1));
+                return self.descend(pager, child, Direction::Forward);
+            }
+        }
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Positions the cursor on the last entry of the leaf preceding the current one.
+    fn previous_leaf<F: File>(&mut self, pager: &Pager<F>) -> Result<(), BTreeError> {
+        while let Some((children, index)) = self.path.pop() {
+            if index > 0 {
+                let child = children[index - 1];
+                self.path.push((children, index - 1));
+                return self.descend(pager, child, Direction::Backward);
+            }
+        }
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Positions the cursor on the first (forward) or last (backward) entry of the subtree rooted
+    /// at `id`. Empty leaves are skipped.
+    fn descend<F: File>(
+        &mut self,
+        pager: &Pager<F>,
+        mut id: PageId,
+        direction: Direction,
+    ) -> Result<(), BTreeError> {
+        loop {
+            match Node::read(pager, id)? {
+                Node::Leaf { entries, .. } => {
+                    self.entries = entries;
+                    if self.entries.is_empty() {
+                        return match direction {
+                            Direction::Forward => self.next_leaf(pager),
+                            Direction::Backward => self.previous_leaf(pager),
+                        };
+                    }
+                    self.index = Some(match direction {
+                        Direction::Forward => 0,
+                        Direction::Backward => self.entries.len() - 1,
+                    });
+                    return Ok(());
+                }
+                Node::Interior { children, .. } => {
+                    let index = match direction {
+                        Direction::Forward => 0,
+                        Direction::Backward => children.len() - 1,
+                    };
+                    id = children[index];
+                    self.path.push((children, index));
+                }
+            }
+        }
+    }
+
+    fn invalidate(&mut self) {
+        self.path.clear();
+        self.entries.clear();
+        self.index = None;
+    }
+}
+
+/// A key-value pair returned by a [Range] or copied by a [Cursor].
+type Entry = (Vec<u8>, Vec<u8>);
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Forward,
+    Backward,
+}
+
+/// An iterator over the entries of a [BTree](super::BTree) within a range of keys, in key order.
+///
+/// The iterator can be consumed from both ends. The pages are read lazily, as the iteration
+/// progresses. After an error is returned, the iterator does not return any more items.
+pub struct Range<'a, F: File> {
+    front: Cursor<'a, F>,
+    back: Cursor<'a, F>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    front_started: bool,
+    back_started: bool,
+    last_front: Option<Vec<u8>>,
+    last_back: Option<Vec<u8>>,
+    finished: bool,
+}
+
+impl<'a, F: File> Range<'a, F> {
+    /// Creates an iterator over the entries of the tree rooted at `root` and ordered by
+    /// `comparator` within `range`.
+    pub(super) fn new<K, R>(
+        pager: &'a Pager<F>,
+        root: PageId,
+        comparator: &'a dyn KeyComparator,
+        range: R,
+    ) -> Self
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        Range {
+            front: Cursor::new(pager, root, comparator),
+            back: Cursor::new(pager, root, comparator),
+            start: range.start_bound().map(|key| key.as_ref().to_vec()),
+            end: range.end_bound().map(|key| key.as_ref().to_vec()),
+            front_started: false,
+            back_started: false,
+            last_front: None,
+            last_back: None,
+            finished: false,
+        }
+    }
+
+    fn advance_front(&mut self) -> Result<Option<Entry>, BTreeError> {
+        if self.front_started {
+            self.front.next()?;
+        } else {
+            self.front_started = true;
+            match &self.start {
+                Bound::Included(key) => self.front.seek(key)?,
+                Bound::Excluded(key) => {
+                    self.front.seek(key)?;
+                    if self
+                        .front
+                        .key()
+                        .is_some_and(|k| self.front.compare(k, key).is_eq())
+                    {
+                        self.front.next()?;
+                    }
+                }
+                Bound::Unbounded => self.front.seek_to_first()?,
+            }
+        }
+
+        let Some(key) = self.front.key() else {
+            return Ok(None);
+        };
+        let before_end = match &self.end {
+            Bound::Included(end) => self.front.compare(key, end).is_le(),
+            Bound::Excluded(end) => self.front.compare(key, end).is_lt(),
+            Bound::Unbounded => true,
+        };
+        let before_back = self
+            .last_back
+            .as_deref()
+            .is_none_or(|back| self.front.compare(key, back).is_lt());
+        if !before_end || !before_back {
+            return Ok(None);
+        }
+
+        let entry = (
+            key.to_vec(),
+            self.front.value().unwrap_or_default().to_vec(),
+        );
+        self.last_front = Some(entry.0.clone());
+        Ok(Some(entry))
+    }
+
+    fn advance_back(&mut self) -> Result<Option<Entry>, BTreeError> {
+        if self.back_started {
+            self.back.prev()?;
+        } else {
+            self.back_started = true;
+            match &self.end {
+                Bound::Included(key) | Bound::Excluded(key) => {
+                    self.back.seek(key)?;
+                    let inclusive = matches!(self.end, Bound::Included(_));
+                    if !self.back.is_valid() {
+                        self.back.seek_to_last()?;
+                    } else if !(inclusive
+                        && self
+                            .back
+                            .key()
+                            .is_some_and(|k| self.back.compare(k, key).is_eq()))
+                    {
+                        self.back.prev()?;
+                    }
+                }
+                Bound::Unbounded => self.back.seek_to_last()?,
+            }
+        }
+
+        let Some(key) = self.back.key() else {
+            return Ok(None);
+        };
+        let after_start = match &self.start {
+            Bound::Included(start) => self.back.compare(key, start).is_ge(),
+            Bound::Excluded(start) => self.back.compare(key, start).is_gt(),
+            Bound::Unbounded => true,
+        };
+        let after_front = self
+            .last_front
+            .as_deref()
+            .is_none_or(|front| self.back.compare(key, front).is_gt());
+        if !after_start || !after_front {
+            return Ok(None);
+        }
+
+        let entry = (key.to_vec(), self.back.value().unwrap_or_default().to_vec());
+        self.last_back = Some(entry.0.clone());
+        Ok(Some(entry))
+    }
+}
+
+impl<F: File> Iterator for Range<'_, F> {
+    type Item = Result<Entry, BTreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let result = self.advance_front().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.finished = true;
+        }
+        result
+    }
+}
+
+impl<F: File> DoubleEndedIterator for Range<'_, F> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let result = self.advance_back().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.finished = true;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::BTree;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    const KEY_COUNT: usize = 1000;
+
+    /// Creates a tree holding the even keys from `0` to `2 * (KEY_COUNT - 1)`.
+    fn create_tree() -> (Pager<MemoryFile>, BTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = BTree::create(&mut pager).expect("create should not fail");
+        for index in 0..KEY_COUNT {
+            tree.insert(&mut pager, &key(index * 2), &value(index * 2))
+                .expect("insert should not fail");
+        }
+        (pager, tree)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:06}").into_bytes()
+    }
+
+    fn value(index: usize) -> Vec<u8> {
+        format!("value-{index}").into_bytes()
+    }
+
+    fn collect_keys(
+        range: impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), BTreeError>>,
+    ) -> Vec<Vec<u8>> {
+        range
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect()
+    }
+
+    /// A new cursor is not positioned on an entry.
+    #[test]
+    fn new_cursor_is_invalid() {
+        let (pager, tree) = create_tree();
+
+        let cursor = tree.cursor(&pager);
+
+        assert!(!cursor.is_valid());
+        assert_eq!(cursor.key(), None);
+    }
+
+    /// Seeking an existing key positions the cursor on it.
+    #[test]
+    fn seek_existing_key_positions_on_key() {
+        let (pager, tree) = create_tree();
+        let mut cursor = tree.cursor(&pager);
+
+        cursor.seek(&key(500)).expect("seek should not fail");
+
+        assert_eq!(cursor.key(), Some(key(500).as_slice()));
+        assert_eq!(cursor.value(), Some(value(500).as_slice()));
+    }
+
+    /// Seeking a missing key positions the cursor on the next key.
+    #[test]
+    fn seek_missing_key_positions_on_next_key() {
+        let (pager, tree) = create_tree();
+        let mut cursor = tree.cursor(&pager);
+
+        cursor.seek(&key(501)).expect("seek should not fail");
+
+        assert_eq!(cursor.key(), Some(key(502).as_slice()));
+    }
+
+    /// Seeking past the last key invalidates the cursor.
+    #[test]
+    fn seek_past_last_key_is_invalid() {
+        let (pager, tree) = create_tree();
+        let mut cursor = tree.cursor(&pager);
+
+        cursor.seek(b"zzz").expect("seek should not fail");
+
+        assert!(!cursor.is_valid());
+    }
+
+    /// Moving forward from the first entry visits every key in order.
+    #[test]
+    fn next_from_first_visits_all_keys_in_order() {
+        let (pager, tree) = create_tree();
+        let mut cursor = tree.cursor(&pager);
+        let mut keys = Vec::new();
+
+        cursor.seek_to_first().expect("seek should not fail");
+        while let Some(key) = cursor.key() {
+            keys.push(key.to_vec());
+            cursor.next().expect("next should not fail");
+        }
+
+        let expected: Vec<Vec<u8>> = (0..KEY_COUNT).map(|index| key(index * 2)).collect();
+        assert_eq!(keys, expected);
+    }
+
+    /// Moving backward from the last entry visits every key in reverse order.
+    #[test]
+    fn prev_from_last_visits_all_keys_in_reverse_order() {
+        let (pager, tree) = create_tree();
+        let mut cursor = tree.cursor(&pager);
+        let mut keys = Vec::new();
+
+        cursor.seek_to_last().expect("seek should not fail");
+        while let Some(key) = cursor.key() {
+            keys.push(key.to_vec());
+            cursor.prev().expect("prev should not fail");
+        }
+
+        let expected: Vec<Vec<u8>> = (0..KEY_COUNT).rev().map(|index| key(index * 2)).collect();
+        assert_eq!(keys, expected);
+    }
+
+    /// Moving forward then backward returns to the same entry, across leaf boundaries.
+    #[test]
+    fn next_then_prev_returns_to_same_entry() {
+        let (pager, tree) = create_tree();
+        let mut cursor = tree.cursor(&pager);
+        cursor.seek(&key(100)).expect("seek should not fail");
+
+        for _ in 0..50 {
+            cursor.next().expect("next should not fail");
+        }
+        for _ in 0..50 {
+            cursor.prev().expect("prev should not fail");
+        }
+
+        assert_eq!(cursor.key(), Some(key(100).as_slice()));
+    }
+
+    /// Empty leaves left by deletions are skipped in both directions.
+    #[test]
+    fn cursor_skips_empty_leaves() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 100..400 {
+            tree.delete(&mut pager, &key(index * 2))
+                .expect("delete should not fail");
+        }
+        let mut cursor = tree.cursor(&pager);
+
+        cursor.seek(&key(200)).expect("seek should not fail");
+        assert_eq!(cursor.key(), Some(key(800).as_slice()));
+        cursor.prev().expect("prev should not fail");
+        assert_eq!(cursor.key(), Some(key(198).as_slice()));
+    }
+
+    /// Iterating the whole tree returns every entry.
+    #[test]
+    fn iter_returns_all_entries() {
+        let (pager, tree) = create_tree();
+
+        let keys = collect_keys(tree.iter(&pager));
+
+        assert_eq!(keys.len(), KEY_COUNT);
+    }
+
+    /// A half-open range includes its start and excludes its end.
+    #[test]
+    fn range_half_open_excludes_end() {
+        let (pager, tree) = create_tree();
+        let (start, end) = (key(10), key(20));
+
+        let keys = collect_keys(tree.range(&pager, start..end));
+
+        assert_eq!(keys, vec![key(10), key(12), key(14), key(16), key(18)]);
+    }
+
+    /// An inclusive range includes both of its ends.
+    #[test]
+    fn range_inclusive_includes_end() {
+        let (pager, tree) = create_tree();
+        let (start, end) = (key(10), key(20));
+
+        let keys = collect_keys(tree.range(&pager, start..=end));
+
+        assert_eq!(
+            keys,
+            vec![key(10), key(12), key(14), key(16), key(18), key(20)]
+        );
+    }
+
+    /// A range with an excluded start skips its start.
+    #[test]
+    fn range_excluded_start_skips_start() {
+        let (pager, tree) = create_tree();
+        let range = (Bound::Excluded(key(10)), Bound::Included(key(14)));
+
+        let keys = collect_keys(tree.range(&pager, range));
+
+        assert_eq!(keys, vec![key(12), key(14)]);
+    }
+
+    /// A reversed range returns the entries in reverse order.
+    #[test]
+    fn range_rev_returns_reverse_order() {
+        let (pager, tree) = create_tree();
+        let (start, end) = (key(11), key(19));
+
+        let keys = collect_keys(tree.range(&pager, start..end).rev());
+
+        assert_eq!(keys, vec![key(18), key(16), key(14), key(12)]);
+    }
+
+    /// Consuming a range from both ends returns each entry exactly once.
+    #[test]
+    fn range_both_ends_meet_without_duplicates() {
+        let (pager, tree) = create_tree();
+        let (start, end) = (key(0), key(20));
+        let mut range = tree.range(&pager, start..end);
+        let mut keys = Vec::new();
+
+        while let Some(entry) = range.next() {
+            keys.push(entry.expect("iteration should not fail").0);
+            if let Some(entry) = range.next_back() {
+                keys.push(entry.expect("iteration should not fail").0);
+            }
+        }
+
+        keys.sort();
+        assert_eq!(
+            keys,
+            (0..10).map(|index| key(index * 2)).collect::<Vec<_>>()
+        );
+    }
+
+    /// A range whose start is after its end is empty.
+    #[test]
+    fn range_start_after_end_is_empty() {
+        let (pager, tree) = create_tree();
+        let (start, end) = (key(20), key(10));
+
+        let keys = collect_keys(tree.range(&pager, start..end));
+
+        assert!(keys.is_empty());
+    }
+
+    /// Seeking an exact key only finds keys that are in the tree.
+    #[test]
+    fn seek_exact_finds_only_existing_keys() {
+        let (pager, tree) = create_tree();
+        let mut cursor = tree.cursor(&pager);
+
+        cursor
+            .seek_exact(&key(42))
+            .expect("seek_exact should not fail");
+        let found = cursor.key().map(<[u8]>::to_vec);
+        cursor
+            .seek_exact(&key(43))
+            .expect("seek_exact should not fail");
+
+        assert_eq!(found, Some(key(42)));
+        assert!(!cursor.is_valid());
+    }
+
+    /// Seeking for the previous entry finds the last key smaller or equal to the sought key.
+    #[test]
+    fn seek_for_prev_finds_smaller_or_equal_key() {
+        let (pager, tree) = create_tree();
+        let mut cursor = tree.cursor(&pager);
+        let mut found = Vec::new();
+
+        for sought in [key(42), key(43), key(99_999), b"a".to_vec()] {
+            cursor
+                .seek_for_prev(&sought)
+                .expect("seek_for_prev should not fail");
+            found.push(cursor.key().map(<[u8]>::to_vec));
+        }
+
+        assert_eq!(
+            found,
+            vec![
+                Some(key(42)),
+                Some(key(42)),
+                Some(key(2 * (KEY_COUNT - 1))),
+                None
+            ]
+        );
+    }
+
+    /// Deleting every other entry through a cursor keeps the cursor moving forward.
+    #[test]
+    fn delete_current_moves_to_next_entry() {
+        let (mut pager, mut tree) = create_tree();
+        let mut cursor = tree.cursor_mut(&mut pager);
+
+        cursor
+            .seek_to_first()
+            .expect("seek_to_first should not fail");
+        while cursor.is_valid() {
+            cursor
+                .delete_current()
+                .expect("delete_current should not fail");
+            cursor.next().expect("next should not fail");
+        }
+
+        let keys = collect_keys(tree.iter(&pager));
+        assert_eq!(
+            keys,
+            (0..KEY_COUNT / 2)
+                .map(|index| key(index * 4 + 2))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Replacing values through a cursor while moving backward updates every entry.
+    #[test]
+    fn put_current_replaces_values() {
+        let (mut pager, mut tree) = create_tree();
+        let mut cursor = tree.cursor_mut(&mut pager);
+
+        cursor.seek_to_last().expect("seek_to_last should not fail");
+        while let Some(current) = cursor.value().map(<[u8]>::to_vec) {
+            cursor
+                .put_current(&[current.as_slice(); 3].concat())
+                .expect("put_current should not fail");
+            cursor.prev().expect("prev should not fail");
+        }
+        let result = cursor.put_current(b"value");
+
+        assert!(matches!(result, Err(BTreeError::InvalidCursor)));
+        let stored = tree.get(&pager, &key(10)).expect("get should not fail");
+        assert_eq!(stored, Some(value(10).repeat(3)));
+    }
+
+    /// Growing every value during a forward scan splits the leaves under the cursor, and the scan
+    /// still visits each entry once, in order.
+    #[test]
+    fn put_current_splitting_leaves_keeps_order() {
+        let (mut pager, mut tree) = create_tree();
+        let mut cursor = tree.cursor_mut(&mut pager);
+
+        let mut visited = Vec::new();
+        cursor
+            .seek_to_first()
+            .expect("seek_to_first should not fail");
+        while let Some(current) = cursor.value().map(<[u8]>::to_vec) {
+            visited.push(cursor.key().unwrap_or_default().to_vec());
+            cursor
+                .put_current(&[current.as_slice(); 3].concat())
+                .expect("put_current should not fail");
+            cursor.next().expect("next should not fail");
+        }
+
+        let expected: Vec<_> = (0..KEY_COUNT).map(|index| key(index * 2)).collect();
+        assert_eq!(visited, expected);
+        for entry in tree.iter(&pager) {
+            let (key, stored) = entry.expect("iteration should not fail");
+            let index: usize = String::from_utf8_lossy(&key[4..])
+                .parse()
+                .expect("the key should end with its index");
+            assert_eq!(stored, value(index).repeat(3));
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::cmp::Ordering;
+
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+use super::comparator::KeyComparator;
+use super::node::{self, Node};
+use super::tree::{separator_before, NodeBuilder};
+use super::BTreeError;
+
+/// Fraction of a page filled by the leaves rewritten by a defragmentation. Some space is left free
+/// so the next insertions do not split the leaves right away.
+pub(super) const DEFRAGMENT_FILL: f64 = 0.9;
+
+/// Describes the work done by a call to [BTree::defragment](super::BTree::defragment).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DefragmentReport {
+    /// The number of leaves that were examined.
+    pub examined_leaves: usize,
+    /// The number of leaves that were rewritten.
+    pub rewritten_leaves: usize,
+    /// The number of pages returned to the pager.
+    pub freed_pages: usize,
+    /// The key to pass to the next call to continue where this one stopped, or `None` if the end
+    /// of the tree was reached.
+    pub resume_key: Option<Vec<u8>>,
+}
+
+/// Rewrites the fragmented leaves of the tree rooted at `root`, starting with the leaves that
+/// may hold `from`, until at least `max_leaves` leaves were examined.
+///
+/// # Errors
+///
+/// This function will return an error if a page can't be read, written or freed.
+pub(super) fn defragment<F: File>(
+    pager: &mut Pager<F>,
+    root: PageId,
+    comparator: &dyn KeyComparator,
+    from: Option<&[u8]>,
+    max_leaves: usize,
+) -> Result<DefragmentReport, BTreeError> {
+    let mut defragmenter = Defragmenter {
+        pager,
+        root,
+        comparator,
+        from,
+        max_leaves,
+        report: DefragmentReport::default(),
+    };
+    defragmenter.visit(root, None)?;
+    Ok(defragmenter.report)
+}
+
+/// The state of a defragmentation pass.
+struct Defragmenter<'a, F: File> {
+    pager: &'a mut Pager<F>,
+    root: PageId,
+    comparator: &'a dyn KeyComparator,
+    from: Option<&'a [u8]>,
+    max_leaves: usize,
+    report: DefragmentReport,
+}
+
+impl<F: File> Defragmenter<'_, F> {
+    /// Defragments the leaves of the subtree rooted at `id`, whose keys are smaller than `high`.
+    /// Returns `true` if the pass stopped because enough leaves were examined.
+    fn visit(&mut self, id: PageId, high: Option<&[u8]>) -> Result<bool, BTreeError> {
+        let Node::Interior { keys, children } = Node::read(self.pager, id)? else {
+            // A tree made of a single leaf can't be fragmented.
+            self.report.examined_leaves += 1;
+            return Ok(false);
+        };
+
+        if let Node::Interior { .. } = Node::read(self.pager, children[0])? {
+            for (index, child) in children.iter().enumerate() {
+                let child_high = keys.get(index).map(Vec::as_slice).or(high);
+                if self.is_before_start(child_high) {
+                    continue;
+                }
+                if self.visit(*child, child_high)? {
+                    return Ok(true);
+                }
+            }
+            return Ok(false);
+        }
+
+        self.report.examined_leaves += children.len();
+        self.rewrite_leaves(id, children)?;
+        if self.report.examined_leaves >= self.max_leaves {
+            self.report.resume_key = high.map(<[u8]>::to_vec);
+            return Ok(high.is_some());
+        }
+        Ok(false)
+    }
+
+    /// Returns `true` if all the keys smaller than `high` are before the key the pass starts
+    /// from.
+    fn is_before_start(&self, high: Option<&[u8]>) -> bool {
+        match (self.from, high) {
+            (Some(from), Some(high)) => self.comparator.compare(high, from) != Ordering::Greater,
+            _ => false,
+        }
+    }
+
+    /// Packs the leaves children of the node `id` into as few leaves as possible, in increasing
+    /// page order. The leaves are left untouched if this does not free a page nor reorder them.
+    ///
+    /// The first leaf keeps its page, so the link from the previous leaf stays valid, and the
+    /// node is only rewritten if its new separators fit in its page.
+    fn rewrite_leaves(&mut self, id: PageId, children: Vec<PageId>) -> Result<(), BTreeError> {
+        let page_size = self.pager.page_size();
+        let mut entries = Vec::new();
+        let mut last_next = None;
+        for child in &children {
+            let Node::Leaf {
+                entries: child_entries,
+                next,
+            } = Node::read(self.pager, *child)?
+            else {
+                return Err(BTreeError::CorruptedPage(*child));
+            };
+            entries.extend(child_entries);
+            last_next = next;
+        }
+
+        let mut groups = pack(&entries, (page_size as f64 * DEFRAGMENT_FILL) as usize);
+        if groups.len() == 1 && id != self.root {
+            // An interior node other than the root needs two children.
+            groups = pack(&entries, node::leaf_size(&entries) / 2 + 1);
+            if groups.len() == 1 {
+                return Ok(());
+            }
+        }
+        let in_order = children[1..].windows(2).all(|pair| pair[0] < pair[1]);
+        if groups.len() >= children.len() && in_order {
+            return Ok(());
+        }
+
+        let mut pages = children[1..].to_vec();
+        pages.sort_unstable();
+        pages.insert(0, children[0]);
+        let separators: Vec<Vec<u8>> = groups
+            .windows(2)
+            .map(|pair| {
+                let last = &entries[pair[0].end - 1].0;
+                separator_before(self.comparator, Some(last), &entries[pair[1].start].0)
+            })
+            .collect();
+        let parent = Node::Interior {
+            keys: separators,
+            children: pages[..groups.len()].to_vec(),
+        };
+        if groups.len() > 1 && parent.encoded_size() > page_size {
+            return Ok(());
+        }
+
+        let mut entries = entries.into_iter();
+        let group_count = groups.len();
+        for (index, group) in groups.into_iter().enumerate() {
+            let next = if index + 1 < group_count {
+                Some(pages[index + 1])
+            } else {
+                last_next
+            };
+            let leaf = Node::Leaf {
+                entries: entries.by_ref().take(group.len()).collect(),
+                next,
+            };
+            // When the root only has one child left, the leaf takes the place of the root.
+            let target = if group_count == 1 { id } else { pages[index] };
+            self.pager.write_page(target, &leaf.encode(page_size))?;
+        }
+        if group_count > 1 {
+            self.pager.write_page(id, &parent.encode(page_size))?;
+        }
+
+        let freed = if group_count == 1 {
+            &pages[..]
+        } else {
+            &pages[group_count..]
+        };
+        for page in freed {
+            self.pager.free_page(*page)?;
+        }
+        self.report.rewritten_leaves += children.len();
+        self.report.freed_pages += freed.len();
+        Ok(())
+    }
+}
+
+/// Divides entries sorted by key into consecutive groups whose encoded leaves are at most
+/// `target_size` bytes, except for groups of a single entry. Returns the ranges of the groups.
+pub(super) fn pack(
+    entries: &[(Vec<u8>, Vec<u8>)],
+    target_size: usize,
+) -> Vec<std::ops::Range<usize>> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    let mut builder = NodeBuilder::new(node::LEAF_CELL_OVERHEAD);
+    for (index, (key, value)) in entries.iter().enumerate() {
+        if index > start && builder.size_with(key, value.len()) > target_size {
+            groups.push(start..index);
+            start = index;
+            builder = NodeBuilder::new(node::LEAF_CELL_OVERHEAD);
+        }
+        builder.push(key, value.len());
+    }
+    groups.push(start..entries.len());
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::seq::SliceRandom;
+
+    use crate::btree::BTree;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    /// Creates a tree by inserting keys in random order, which leaves the leaves partly empty.
+    fn create_tree(count: usize) -> (Pager<MemoryFile>, BTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = BTree::create(&mut pager).expect("create should not fail");
+        let mut indexes: Vec<usize> = (0..count).collect();
+        indexes.shuffle(&mut rand::thread_rng());
+        for i in indexes {
+            tree.insert(&mut pager, &key(i), &[0; 20])
+                .expect("insert should not fail");
+        }
+        (pager, tree)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:06}").into_bytes()
+    }
+
+    /// Runs steps of defragmentation until the end of the tree. Returns the number of steps and
+    /// the number of freed pages.
+    fn defragment_all(
+        pager: &mut Pager<MemoryFile>,
+        tree: &mut BTree,
+        max_leaves: usize,
+    ) -> (usize, usize) {
+        let mut from = None;
+        let mut steps = 0;
+        let mut freed_pages = 0;
+        loop {
+            let report = tree
+                .defragment(pager, from.as_deref(), max_leaves)
+                .expect("defragment should not fail");
+            steps += 1;
+            freed_pages += report.freed_pages;
+            match report.resume_key {
+                Some(key) => from = Some(key),
+                None => return (steps, freed_pages),
+            }
+        }
+    }
+
+    /// Defragmenting frees pages and keeps every entry and the consistency of the tree.
+    #[test]
+    fn defragment_packs_leaves() {
+        let (mut pager, mut tree) = create_tree(3000);
+        let before = tree.verify(&pager).expect("verify should not fail");
+
+        let (_, freed_pages) = defragment_all(&mut pager, &mut tree, usize::MAX);
+
+        let after = tree.verify(&pager).expect("verify should not fail");
+        assert!(after.is_valid(), "{:?}", after.violations);
+        assert!(freed_pages > 0);
+        assert!(after.leaf_count < before.leaf_count);
+        assert_eq!(after.entry_count, 3000);
+        for i in 0..3000 {
+            assert!(tree
+                .get(&pager, &key(i))
+                .expect("get should not fail")
+                .is_some());
+        }
+    }
+
+    /// A defragmentation done in small steps visits the whole tree.
+    #[test]
+    fn defragment_in_steps_resumes_from_key() {
+        let (mut pager, mut tree) = create_tree(3000);
+
+        let (steps, freed_pages) = defragment_all(&mut pager, &mut tree, 10);
+        let (_, freed_again) = defragment_all(&mut pager, &mut tree, usize::MAX);
+
+        assert!(steps > 1);
+        assert!(freed_pages > 0);
+        assert_eq!(freed_again, 0);
+        assert!(tree
+            .verify(&pager)
+            .expect("verify should not fail")
+            .is_valid());
+    }
+
+    /// A tree whose leaves all fit in one leaf after a deletion becomes a single leaf again.
+    #[test]
+    fn defragment_small_tree_collapses_root() {
+        let (mut pager, mut tree) = create_tree(100);
+        for i in 10..100 {
+            tree.delete(&mut pager, &key(i))
+                .expect("delete should not fail");
+        }
+
+        defragment_all(&mut pager, &mut tree, usize::MAX);
+
+        let report = tree.verify(&pager).expect("verify should not fail");
+        assert!(report.is_valid(), "{:?}", report.violations);
+        assert_eq!(report.depth, 1);
+        assert_eq!(report.entry_count, 10);
+    }
+}
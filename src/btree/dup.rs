@@ -0,0 +1,801 @@
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+use super::cursor::Cursor;
+use super::{BTree, BTreeError, BytewiseComparator, KeyComparator};
+
+/// Tag of a set of values stored inline in the entry of its key.
+const INLINE_TAG: u8 = 0;
+
+/// Tag of a set of values stored in a nested tree.
+const NESTED_TAG: u8 = 1;
+
+/// Size of the header of an inline set: the tag and the number of values.
+const INLINE_HEADER_SIZE: usize = 3;
+
+/// Size of the length stored before each value of an inline set.
+const INLINE_VALUE_OVERHEAD: usize = 2;
+
+/// Size of the reference to a nested tree: the tag, the root page and the number of values.
+const NESTED_SIZE: usize = 13;
+
+/// The values of a key, as stored in the entry of the key.
+enum Values {
+    /// The values, sorted, stored in the entry itself.
+    Inline(Vec<Vec<u8>>),
+
+    /// The values are the keys of a nested tree.
+    Nested { root: PageId, count: u64 },
+}
+
+impl Values {
+    /// Decodes the values of `key`.
+    fn decode(key: &[u8], bytes: &[u8]) -> Result<Self, BTreeError> {
+        let invalid = || BTreeError::InvalidDuplicates(key.to_vec());
+        match bytes.first() {
+            Some(&INLINE_TAG) => {
+                let count =
+                    u16::from_le_bytes(bytes.get(1..3).ok_or_else(invalid)?.try_into().unwrap());
+                let mut offset = INLINE_HEADER_SIZE;
+                let mut values = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let len = bytes
+                        .get(offset..offset + INLINE_VALUE_OVERHEAD)
+                        .ok_or_else(invalid)?;
+                    let len = u16::from_le_bytes(len.try_into().unwrap()) as usize;
+                    offset += INLINE_VALUE_OVERHEAD;
+                    values.push(
+                        bytes
+                            .get(offset..offset + len)
+                            .ok_or_else(invalid)?
+                            .to_vec(),
+                    );
+                    offset += len;
+                }
+                Ok(Values::Inline(values))
+            }
+            Some(&NESTED_TAG) if bytes.len() == NESTED_SIZE => Ok(Values::Nested {
+                root: u32::from_le_bytes(bytes[1..5].try_into().unwrap()),
+                count: u64::from_le_bytes(bytes[5..13].try_into().unwrap()),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Values::Inline(values) => {
+                let mut bytes = vec![INLINE_TAG];
+                bytes.extend_from_slice(&(values.len() as u16).to_le_bytes());
+                for value in values {
+                    bytes.extend_from_slice(&(value.len() as u16).to_le_bytes());
+                    bytes.extend_from_slice(value);
+                }
+                bytes
+            }
+            Values::Nested { root, count } => {
+                let mut bytes = vec![NESTED_TAG];
+                bytes.extend_from_slice(&root.to_le_bytes());
+                bytes.extend_from_slice(&count.to_le_bytes());
+                bytes
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            Values::Inline(values) => values.len(),
+            Values::Nested { count, .. } => *count as usize,
+        }
+    }
+}
+
+/// Represents a [BTree] in which a key maps to a sorted set of values instead of a single value.
+///
+/// The values of a key are sorted by the comparator of the tree, like the keys. While they are
+/// few, the values are stored in the entry of their key. When they no longer fit in an entry, they
+/// are moved to a nested tree whose keys are the values, and the entry only keeps the root page of
+/// this tree. The nested tree is freed when its last value is removed.
+///
+/// This is the structure needed by non-unique secondary indexes, where many primary keys share
+/// the same secondary key. A [DupCursor] can move through all the pairs, or through the values of
+/// a single key.
+pub struct DupTree {
+    tree: BTree,
+}
+
+impl DupTree {
+    /// Creates a new, empty, tree ordered bytewise.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is ordered by another comparator or if the
+    /// root page can't be allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::btree::DupTree;
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut tree = DupTree::create(&mut pager).expect("create should not fail");
+    /// tree.insert(&mut pager, b"paris", b"bob").expect("insert should not fail");
+    /// tree.insert(&mut pager, b"paris", b"alice").expect("insert should not fail");
+    ///
+    /// let values = tree.get(&pager, b"paris").expect("get should not fail");
+    /// assert_eq!(values, vec![b"alice".to_vec(), b"bob".to_vec()]);
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, BTreeError> {
+        Self::create_with_comparator(pager, Box::new(BytewiseComparator))
+    }
+
+    /// Creates a new, empty, tree whose keys and values are ordered by `comparator`. See
+    /// [BTree::create_with_comparator].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is ordered by another comparator or if the
+    /// root page can't be allocated or written.
+    pub fn create_with_comparator<F: File>(
+        pager: &mut Pager<F>,
+        comparator: Box<dyn KeyComparator>,
+    ) -> Result<Self, BTreeError> {
+        Ok(DupTree {
+            tree: BTree::create_with_comparator(pager, comparator)?,
+        })
+    }
+
+    /// Opens a tree previously created with [DupTree::create].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if the root page
+    /// can't be read or does not contain a valid node.
+    pub fn open<F: File>(pager: &Pager<F>, root: PageId) -> Result<Self, BTreeError> {
+        Self::open_with_comparator(pager, root, Box::new(BytewiseComparator))
+    }
+
+    /// Opens a tree previously created with [DupTree::create_with_comparator].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is ordered by another comparator or if the
+    /// root page can't be read or does not contain a valid node.
+    pub fn open_with_comparator<F: File>(
+        pager: &Pager<F>,
+        root: PageId,
+        comparator: Box<dyn KeyComparator>,
+    ) -> Result<Self, BTreeError> {
+        Ok(DupTree {
+            tree: BTree::open_with_comparator(pager, root, comparator)?,
+        })
+    }
+
+    /// Returns the identifier of the root page of the tree.
+    pub fn root(&self) -> PageId {
+        self.tree.root()
+    }
+
+    /// Returns the largest value that can be stored in a tree using pages of the given size.
+    pub fn max_value_size(page_size: usize) -> usize {
+        BTree::max_key_size(page_size)
+    }
+
+    /// Returns the values of a key, in order. The list is empty if the key is not in the tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(&self, pager: &Pager<F>, key: &[u8]) -> Result<Vec<Vec<u8>>, BTreeError> {
+        match self.values(pager, key)? {
+            None => Ok(Vec::new()),
+            Some(Values::Inline(values)) => Ok(values),
+            Some(Values::Nested { root, .. }) => self
+                .tree
+                .sibling(root)
+                .iter(pager)
+                .map(|entry| entry.map(|(value, _)| value))
+                .collect(),
+        }
+    }
+
+    /// Returns the number of values of a key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn value_count<F: File>(&self, pager: &Pager<F>, key: &[u8]) -> Result<usize, BTreeError> {
+        Ok(self.values(pager, key)?.map_or(0, |values| values.count()))
+    }
+
+    /// Returns `true` if `value` is one of the values of `key`.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn contains<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<bool, BTreeError> {
+        match self.values(pager, key)? {
+            None => Ok(false),
+            Some(Values::Inline(values)) => Ok(self.position(&values, value).is_ok()),
+            Some(Values::Nested { root, .. }) => {
+                Ok(self.tree.sibling(root).get(pager, value)?.is_some())
+            }
+        }
+    }
+
+    /// Returns a new [DupCursor] on the tree. The cursor is not positioned on any pair until one
+    /// of its seek methods is called.
+    pub fn cursor<'a, F: File>(&'a self, pager: &'a Pager<F>) -> DupCursor<'a, F> {
+        DupCursor::new(pager, &self.tree)
+    }
+
+    /// Adds a value to the values of a key. Returns `false` if the key already had this value.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the key or the value is too large (see [BTree::max_key_size] and
+    ///   [DupTree::max_value_size])
+    /// - a page can't be read, written or allocated
+    pub fn insert<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<bool, BTreeError> {
+        let page_size = pager.page_size();
+        let max_key_size = BTree::max_key_size(page_size);
+        let max_entry_size = BTree::max_entry_size(page_size);
+        if key.len() > max_key_size
+            || key.len() + NESTED_SIZE > max_entry_size
+            || value.len() > Self::max_value_size(page_size)
+        {
+            return Err(BTreeError::EntryTooLarge {
+                key_size: key.len(),
+                value_size: value.len(),
+                max_key_size,
+                max_entry_size,
+            });
+        }
+
+        let values = match self.values(pager, key)? {
+            None => vec![value.to_vec()],
+            Some(Values::Inline(mut values)) => match self.position(&values, value) {
+                Ok(_) => return Ok(false),
+                Err(index) => {
+                    values.insert(index, value.to_vec());
+                    values
+                }
+            },
+            Some(Values::Nested { root, count }) => {
+                if self.tree.sibling(root).insert(pager, value, &[])?.is_some() {
+                    return Ok(false);
+                }
+                let nested = Values::Nested {
+                    root,
+                    count: count + 1,
+                };
+                self.tree.insert(pager, key, &nested.encode())?;
+                return Ok(true);
+            }
+        };
+
+        let inline = Values::Inline(values);
+        let encoded = inline.encode();
+        if key.len() + encoded.len() <= max_entry_size {
+            self.tree.insert(pager, key, &encoded)?;
+            return Ok(true);
+        }
+        // The values do not fit in the entry anymore: they are moved to a nested tree.
+        let Values::Inline(values) = inline else {
+            unreachable!("the values should be inline");
+        };
+        let mut nested = self.tree.create_sibling(pager)?;
+        for value in &values {
+            nested.insert(pager, value, &[])?;
+        }
+        let nested = Values::Nested {
+            root: nested.root(),
+            count: values.len() as u64,
+        };
+        self.tree.insert(pager, key, &nested.encode())?;
+        Ok(true)
+    }
+
+    /// Removes a value from the values of a key. The key is removed with its last value. Returns
+    /// `false` if the key did not have this value.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read, written or freed.
+    pub fn delete<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<bool, BTreeError> {
+        match self.values(pager, key)? {
+            None => Ok(false),
+            Some(Values::Inline(mut values)) => {
+                let Ok(index) = self.position(&values, value) else {
+                    return Ok(false);
+                };
+                values.remove(index);
+                if values.is_empty() {
+                    self.tree.delete(pager, key)?;
+                } else {
+                    self.tree
+                        .insert(pager, key, &Values::Inline(values).encode())?;
+                }
+                Ok(true)
+            }
+            Some(Values::Nested { root, count }) => {
+                let mut nested = self.tree.sibling(root);
+                if nested.delete(pager, value)?.is_none() {
+                    return Ok(false);
+                }
+                if count == 1 {
+                    nested.destroy(pager)?;
+                    self.tree.delete(pager, key)?;
+                } else {
+                    let values = Values::Nested {
+                        root,
+                        count: count - 1,
+                    };
+                    self.tree.insert(pager, key, &values.encode())?;
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// Removes a key with all its values. Returns the number of values removed.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read, written or freed.
+    pub fn delete_all<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+    ) -> Result<usize, BTreeError> {
+        let Some(values) = self.values(pager, key)? else {
+            return Ok(0);
+        };
+        if let Values::Nested { root, .. } = values {
+            self.tree.sibling(root).destroy(pager)?;
+        }
+        self.tree.delete(pager, key)?;
+        Ok(values.count())
+    }
+
+    /// Reads and decodes the values of a key.
+    fn values<F: File>(&self, pager: &Pager<F>, key: &[u8]) -> Result<Option<Values>, BTreeError> {
+        self.tree
+            .get(pager, key)?
+            .map(|bytes| Values::decode(key, &bytes))
+            .transpose()
+    }
+
+    /// Searches a value in sorted inline values, like [slice::binary_search].
+    fn position(&self, values: &[Vec<u8>], value: &[u8]) -> Result<usize, usize> {
+        values.binary_search_by(|v| self.tree.comparator().compare(v, value))
+    }
+}
+
+/// The position of a [DupCursor] among the values of its current key.
+enum Position<'a, F: File> {
+    Invalid,
+    Inline { values: Vec<Vec<u8>>, index: usize },
+    Nested { cursor: Cursor<'a, F>, count: usize },
+}
+
+/// Represents a position on a pair of a [DupTree] that can be moved forward and backward, through
+/// all the pairs or through the values of the current key.
+///
+/// The pairs are visited in key order, and the values of a key in value order. Like a [Cursor],
+/// the cursor is either positioned on a pair or invalid, and a newly created cursor is invalid
+/// until one of the seek methods is called.
+pub struct DupCursor<'a, F: File> {
+    pager: &'a Pager<F>,
+    comparator: &'a dyn KeyComparator,
+    keys: Cursor<'a, F>,
+    position: Position<'a, F>,
+}
+
+impl<'a, F: File> DupCursor<'a, F> {
+    fn new(pager: &'a Pager<F>, tree: &'a BTree) -> Self {
+        DupCursor {
+            pager,
+            comparator: tree.comparator(),
+            keys: tree.cursor(pager),
+            position: Position::Invalid,
+        }
+    }
+
+    /// Returns `true` if the cursor is positioned on a pair.
+    pub fn is_valid(&self) -> bool {
+        !matches!(self.position, Position::Invalid)
+    }
+
+    /// Returns the key of the pair the cursor is positioned on.
+    pub fn key(&self) -> Option<&[u8]> {
+        match self.position {
+            Position::Invalid => None,
+            _ => self.keys.key(),
+        }
+    }
+
+    /// Returns the value of the pair the cursor is positioned on.
+    pub fn value(&self) -> Option<&[u8]> {
+        match &self.position {
+            Position::Invalid => None,
+            Position::Inline { values, index } => Some(&values[*index]),
+            Position::Nested { cursor, .. } => cursor.key(),
+        }
+    }
+
+    /// Returns the number of values of the key the cursor is positioned on, or `0` if the cursor
+    /// is invalid.
+    pub fn value_count(&self) -> usize {
+        match &self.position {
+            Position::Invalid => 0,
+            Position::Inline { values, .. } => values.len(),
+            Position::Nested { count, .. } => *count,
+        }
+    }
+
+    /// Positions the cursor on the first value of the first key greater or equal to `key`.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek(&mut self, key: &[u8]) -> Result<(), BTreeError> {
+        self.keys.seek(key)?;
+        self.load(false)
+    }
+
+    /// Positions the cursor on the first value of `key`. The cursor is invalid if the key is not
+    /// in the tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_exact(&mut self, key: &[u8]) -> Result<(), BTreeError> {
+        self.keys.seek_exact(key)?;
+        self.load(false)
+    }
+
+    /// Positions the cursor on the first value of `key` greater or equal to `value`. The cursor is
+    /// invalid if the key is not in the tree or has no such value.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_value(&mut self, key: &[u8], value: &[u8]) -> Result<(), BTreeError> {
+        self.seek_exact(key)?;
+        match &mut self.position {
+            Position::Invalid => {}
+            Position::Inline { values, index } => {
+                *index = values.partition_point(|v| self.comparator.compare(v, value).is_lt());
+                if *index == values.len() {
+                    self.position = Position::Invalid;
+                }
+            }
+            Position::Nested { cursor, .. } => {
+                cursor.seek(value)?;
+                if !cursor.is_valid() {
+                    self.position = Position::Invalid;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Positions the cursor on the first value of the first key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_to_first(&mut self) -> Result<(), BTreeError> {
+        self.keys.seek_to_first()?;
+        self.load(false)
+    }
+
+    /// Positions the cursor on the last value of the last key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_to_last(&mut self) -> Result<(), BTreeError> {
+        self.keys.seek_to_last()?;
+        self.load(true)
+    }
+
+    /// Moves the cursor to the next pair: the next value of the current key, or the first value
+    /// of the next key. Does nothing if the cursor is invalid.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<(), BTreeError> {
+        if !self.is_valid() || self.step(true)? {
+            return Ok(());
+        }
+        self.keys.next()?;
+        self.load(false)
+    }
+
+    /// Moves the cursor to the previous pair: the previous value of the current key, or the last
+    /// value of the previous key. Does nothing if the cursor is invalid.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn prev(&mut self) -> Result<(), BTreeError> {
+        if !self.is_valid() || self.step(false)? {
+            return Ok(());
+        }
+        self.keys.prev()?;
+        self.load(true)
+    }
+
+    /// Moves the cursor to the next value of the current key. The cursor becomes invalid if it was
+    /// positioned on the last value of the key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn next_dup(&mut self) -> Result<(), BTreeError> {
+        if self.is_valid() && !self.step(true)? {
+            self.position = Position::Invalid;
+        }
+        Ok(())
+    }
+
+    /// Moves the cursor to the previous value of the current key. The cursor becomes invalid if it
+    /// was positioned on the first value of the key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn prev_dup(&mut self) -> Result<(), BTreeError> {
+        if self.is_valid() && !self.step(false)? {
+            self.position = Position::Invalid;
+        }
+        Ok(())
+    }
+
+    /// Moves the cursor to the first value of the next key, skipping the remaining values of the
+    /// current key. Does nothing if the cursor is invalid.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn next_key(&mut self) -> Result<(), BTreeError> {
+        if !self.is_valid() {
+            return Ok(());
+        }
+        self.keys.next()?;
+        self.load(false)
+    }
+
+    /// Moves the cursor among the values of the current key. Returns `false`, leaving the
+    /// position unspecified, if there is no value in that direction.
+    fn step(&mut self, forward: bool) -> Result<bool, BTreeError> {
+        match &mut self.position {
+            Position::Invalid => Ok(false),
+            Position::Inline { values, index } => {
+                if forward && *index + 1 < values.len() {
+                    *index += 1;
+                    Ok(true)
+                } else if !forward && *index > 0 {
+                    *index -= 1;
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            Position::Nested { cursor, .. } => {
+                if forward {
+                    cursor.next()?;
+                } else {
+                    cursor.prev()?;
+                }
+                Ok(cursor.is_valid())
+            }
+        }
+    }
+
+    /// Positions the cursor on the first or the last value of the key the key cursor is on.
+    fn load(&mut self, last: bool) -> Result<(), BTreeError> {
+        let (Some(key), Some(bytes)) = (self.keys.key(), self.keys.value()) else {
+            self.position = Position::Invalid;
+            return Ok(());
+        };
+        self.position = match Values::decode(key, bytes)? {
+            Values::Inline(values) if values.is_empty() => Position::Invalid,
+            Values::Inline(values) => Position::Inline {
+                index: if last { values.len() - 1 } else { 0 },
+                values,
+            },
+            Values::Nested { root, count } => {
+                let mut cursor = Cursor::new(self.pager, root, self.comparator);
+                if last {
+                    cursor.seek_to_last()?;
+                } else {
+                    cursor.seek_to_first()?;
+                }
+                Position::Nested {
+                    cursor,
+                    count: count as usize,
+                }
+            }
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_tree() -> (Pager<MemoryFile>, DupTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let tree = DupTree::create(&mut pager).expect("create should not fail");
+        (pager, tree)
+    }
+
+    fn value(index: usize) -> Vec<u8> {
+        format!("value-{index:06}").into_bytes()
+    }
+
+    /// Many values of a key are moved to a nested tree and can all be read back and removed,
+    /// which frees the nested tree.
+    #[test]
+    fn insert_many_values_uses_nested_tree() {
+        let (mut pager, mut tree) = create_tree();
+        let page_count = pager.page_count();
+        for i in (0..500).rev() {
+            assert!(tree
+                .insert(&mut pager, b"key", &value(i))
+                .expect("insert should not fail"));
+        }
+        let duplicate = tree
+            .insert(&mut pager, b"key", &value(42))
+            .expect("insert should not fail");
+
+        let values = tree.get(&pager, b"key").expect("get should not fail");
+        let count = tree
+            .value_count(&pager, b"key")
+            .expect("value_count should not fail");
+        for i in 0..500 {
+            assert!(tree
+                .delete(&mut pager, b"key", &value(i))
+                .expect("delete should not fail"));
+        }
+
+        assert!(!duplicate);
+        assert_eq!(values, (0..500).map(value).collect::<Vec<_>>());
+        assert_eq!(count, 500);
+        assert!(tree
+            .get(&pager, b"key")
+            .expect("get should not fail")
+            .is_empty());
+        assert_eq!(
+            pager
+                .free_pages()
+                .expect("free_pages should not fail")
+                .len() as u32,
+            pager.page_count() - page_count
+        );
+    }
+
+    /// A few values stay inline and are removed one by one, the key going away with the last one.
+    #[test]
+    fn delete_last_inline_value_removes_key() {
+        let (mut pager, mut tree) = create_tree();
+        tree.insert(&mut pager, b"key", b"b")
+            .expect("insert should not fail");
+        tree.insert(&mut pager, b"key", b"a")
+            .expect("insert should not fail");
+
+        let missing = tree
+            .delete(&mut pager, b"key", b"c")
+            .expect("delete should not fail");
+        tree.delete(&mut pager, b"key", b"a")
+            .expect("delete should not fail");
+        let remaining = tree.get(&pager, b"key").expect("get should not fail");
+        let removed = tree
+            .delete_all(&mut pager, b"key")
+            .expect("delete_all should not fail");
+
+        assert!(!missing);
+        assert_eq!(remaining, vec![b"b".to_vec()]);
+        assert_eq!(removed, 1);
+        assert!(!tree
+            .contains(&pager, b"key", b"b")
+            .expect("contains should not fail"));
+    }
+
+    /// The cursor visits every pair in order, across inline and nested values, in both
+    /// directions.
+    #[test]
+    fn cursor_visits_all_pairs() {
+        let (mut pager, mut tree) = create_tree();
+        let mut expected = Vec::new();
+        for (key, count) in [(b"a", 3), (b"b", 300), (b"c", 1)] {
+            for i in 0..count {
+                tree.insert(&mut pager, key, &value(i))
+                    .expect("insert should not fail");
+                expected.push((key.to_vec(), value(i)));
+            }
+        }
+        expected.sort();
+        let mut cursor = tree.cursor(&pager);
+        let mut forward = Vec::new();
+        let mut backward = Vec::new();
+
+        cursor
+            .seek_to_first()
+            .expect("seek_to_first should not fail");
+        while let (Some(key), Some(value)) = (cursor.key(), cursor.value()) {
+            forward.push((key.to_vec(), value.to_vec()));
+            cursor.next().expect("next should not fail");
+        }
+        cursor.seek_to_last().expect("seek_to_last should not fail");
+        while let (Some(key), Some(value)) = (cursor.key(), cursor.value()) {
+            backward.push((key.to_vec(), value.to_vec()));
+            cursor.prev().expect("prev should not fail");
+        }
+
+        backward.reverse();
+        assert_eq!(forward, expected);
+        assert_eq!(backward, expected);
+    }
+
+    /// Moving through the duplicates stops at the end of the key, and moving to the next key
+    /// skips the remaining values.
+    #[test]
+    fn cursor_next_dup_stays_on_key() {
+        let (mut pager, mut tree) = create_tree();
+        for key in [b"a", b"b"] {
+            for i in 0..3 {
+                tree.insert(&mut pager, key, &value(i))
+                    .expect("insert should not fail");
+            }
+        }
+        let mut cursor = tree.cursor(&pager);
+        let mut dups = Vec::new();
+
+        cursor
+            .seek_value(b"a", &value(1))
+            .expect("seek_value should not fail");
+        while let Some(value) = cursor.value() {
+            dups.push(value.to_vec());
+            cursor.next_dup().expect("next_dup should not fail");
+        }
+        cursor.seek_exact(b"a").expect("seek_exact should not fail");
+        cursor.next_key().expect("next_key should not fail");
+
+        assert_eq!(dups, vec![value(1), value(2)]);
+        assert_eq!(cursor.key(), Some(&b"b"[..]));
+        assert_eq!(cursor.value(), Some(value(0).as_slice()));
+        assert_eq!(cursor.value_count(), 3);
+    }
+}
@@ -0,0 +1,220 @@
+use std::ops::Bound;
+
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+use super::comparator::KeyComparator;
+use super::node::Node;
+use super::tree::child_index;
+use super::BTreeError;
+
+/// An estimate of the entries of a range of keys.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(super) struct RangeEstimate {
+    /// The estimated number of entries in the range.
+    pub(super) count: f64,
+    /// The estimated total size of the keys and values of the entries in the range.
+    pub(super) size: f64,
+    /// The estimated number of leaves filled by the entries in the range.
+    pub(super) leaves: f64,
+}
+
+/// Estimates the entries of the tree rooted at `root` within the bounds.
+///
+/// Only the nodes on the paths to the two bounds are read. The entries of the leaves at the ends
+/// of the range are counted exactly, and each subtree entirely within the range is assumed to look
+/// like the nodes read at the same depth: the same number of children per interior node and the
+/// same entries per leaf.
+///
+/// # Errors
+///
+/// This function will return an error if a page can't be read or is corrupted.
+pub(super) fn estimate<F: File>(
+    pager: &Pager<F>,
+    root: PageId,
+    comparator: &dyn KeyComparator,
+    start: Bound<&[u8]>,
+    end: Bound<&[u8]>,
+) -> Result<RangeEstimate, BTreeError> {
+    let mut estimator = Estimator {
+        pager,
+        comparator,
+        fanouts: Vec::new(),
+        leaves: Sample::default(),
+        skipped: Vec::new(),
+        exact: RangeEstimate::default(),
+    };
+    estimator.visit(root, start, end, 0)?;
+
+    let leaf_count = estimator.leaves.nodes.max(1) as f64;
+    let leaf_entries = estimator.leaves.count as f64 / leaf_count;
+    let leaf_size = estimator.leaves.size as f64 / leaf_count;
+    let mut estimate = estimator.exact;
+    for (depth, subtrees) in estimator.skipped.iter().enumerate() {
+        if *subtrees == 0 {
+            continue;
+        }
+        // A subtree rooted at this depth has the average fanout of each deeper interior level.
+        let leaves: f64 = estimator.fanouts[depth..]
+            .iter()
+            .map(|fanout| fanout.count as f64 / fanout.nodes.max(1) as f64)
+            .product();
+        estimate.count += *subtrees as f64 * leaves * leaf_entries;
+        estimate.size += *subtrees as f64 * leaves * leaf_size;
+    }
+    if leaf_size > 0.0 {
+        estimate.leaves = estimate.size / leaf_size;
+    }
+    Ok(estimate)
+}
+
+/// The nodes read at a depth and what they hold: their children or their entries.
+#[derive(Debug, Default, Clone, Copy)]
+struct Sample {
+    nodes: usize,
+    count: usize,
+    size: usize,
+}
+
+/// The state of an estimation.
+struct Estimator<'a, F: File> {
+    pager: &'a Pager<F>,
+    comparator: &'a dyn KeyComparator,
+    /// The interior nodes read at each depth, and their children.
+    fanouts: Vec<Sample>,
+    /// The leaves read, and their entries.
+    leaves: Sample,
+    /// The number of subtrees entirely within the range, by the depth of their root.
+    skipped: Vec<usize>,
+    /// The entries of the leaves read that are within the range.
+    exact: RangeEstimate,
+}
+
+impl<F: File> Estimator<'_, F> {
+    fn visit(
+        &mut self,
+        id: PageId,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        depth: usize,
+    ) -> Result<(), BTreeError> {
+        match Node::read(self.pager, id)? {
+            Node::Leaf { entries, .. } => {
+                self.leaves.nodes += 1;
+                for (key, value) in &entries {
+                    self.leaves.count += 1;
+                    self.leaves.size += key.len() + value.len();
+                    if self.contains(key, start, end) {
+                        self.exact.count += 1.0;
+                        self.exact.size += (key.len() + value.len()) as f64;
+                    }
+                }
+            }
+            Node::Interior { keys, children } => {
+                if self.fanouts.len() <= depth {
+                    self.fanouts.resize(depth + 1, Sample::default());
+                    self.skipped.resize(depth + 2, 0);
+                }
+                self.fanouts[depth].nodes += 1;
+                self.fanouts[depth].count += children.len();
+
+                let first = match start {
+                    Bound::Included(key) | Bound::Excluded(key) => {
+                        child_index(self.comparator, &keys, key)
+                    }
+                    Bound::Unbounded => 0,
+                };
+                let last = match end {
+                    Bound::Included(key) | Bound::Excluded(key) => {
+                        child_index(self.comparator, &keys, key)
+                    }
+                    Bound::Unbounded => children.len() - 1,
+                };
+                if first == last {
+                    return self.visit(children[first], start, end, depth + 1);
+                }
+                if first < last {
+                    self.skipped[depth + 1] += last - first - 1;
+                    self.visit(children[first], start, Bound::Unbounded, depth + 1)?;
+                    self.visit(children[last], Bound::Unbounded, end, depth + 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the key is within the bounds.
+    fn contains(&self, key: &[u8], start: Bound<&[u8]>, end: Bound<&[u8]>) -> bool {
+        let after_start = match start {
+            Bound::Included(start) => self.comparator.compare(key, start).is_ge(),
+            Bound::Excluded(start) => self.comparator.compare(key, start).is_gt(),
+            Bound::Unbounded => true,
+        };
+        let before_end = match end {
+            Bound::Included(end) => self.comparator.compare(key, end).is_le(),
+            Bound::Excluded(end) => self.comparator.compare(key, end).is_lt(),
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::BTree;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_tree(count: usize) -> (Pager<MemoryFile>, BTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = BTree::create(&mut pager).expect("create should not fail");
+        for index in 0..count {
+            tree.insert(&mut pager, &key(index), &[0; 20])
+                .expect("insert should not fail");
+        }
+        (pager, tree)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:06}").into_bytes()
+    }
+
+    /// A range within a single leaf is counted exactly.
+    #[test]
+    fn estimate_small_range_is_exact() {
+        let (pager, tree) = create_tree(5000);
+
+        let count = tree
+            .estimate_count(&pager, key(100)..key(105))
+            .expect("estimate_count should not fail");
+        let size = tree
+            .estimate_size(&pager, key(100)..=key(104))
+            .expect("estimate_size should not fail");
+
+        assert_eq!(count, 5);
+        assert_eq!(size, 5 * 30);
+    }
+
+    /// The estimate of large ranges is within 20% of their number of entries.
+    #[test]
+    fn estimate_large_ranges_is_close() {
+        let (pager, tree) = create_tree(20_000);
+
+        let all = tree
+            .estimate_count::<_, [u8], _>(&pager, ..)
+            .expect("estimate_count should not fail");
+        let half = tree
+            .estimate_count(&pager, key(5000)..=key(14_999))
+            .expect("estimate_count should not fail");
+        let empty = tree
+            .estimate_count(&pager, key(30_000)..)
+            .expect("estimate_count should not fail");
+
+        assert!(all.abs_diff(20_000) < 4000, "{all}");
+        assert!(half.abs_diff(10_000) < 2000, "{half}");
+        assert_eq!(empty, 0);
+    }
+}
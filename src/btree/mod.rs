@@ -0,0 +1,23 @@
+mod comparator;
+mod cow;
+mod cursor;
+mod defragment;
+mod dup;
+mod estimate;
+mod node;
+mod salvage;
+mod stats;
+mod tree;
+mod verify;
+pub use comparator::{
+    comparator_by_name, BytewiseComparator, CaseInsensitiveComparator, KeyComparator,
+    NumericComparator, ReverseComparator,
+};
+pub use cow::{CowTree, Snapshot, SnapshotIter};
+pub use cursor::{Cursor, CursorMut, Range};
+pub use defragment::DefragmentReport;
+pub use dup::{DupCursor, DupTree};
+pub use salvage::SalvageReport;
+pub use stats::TreeStats;
+pub use tree::{BTree, BTreeError};
+pub use verify::{VerifyReport, Violation};
@@ -0,0 +1,409 @@
+use crate::common::checksum::crc32c;
+use crate::common::layout::Layout;
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+use crate::{assert_layout_size, disk_layout};
+
+use super::BTreeError;
+
+const LEAF_TYPE: u8 = 1;
+const INTERIOR_TYPE: u8 = 2;
+
+disk_layout! {
+    /// The header at the start of every node, followed by the prefix shared by all the keys of the
+    /// node, then by the cells.
+    struct NodeHeader {
+        /// The CRC32C of the rest of the page, including the unused bytes at its end.
+        checksum: u32,
+        node_type: u8,
+        count: u16,
+        /// The next leaf for leaves, or `0` for the last one, and the right-most child for
+        /// interior nodes.
+        link: PageId,
+        prefix_len: u16,
+    }
+}
+
+assert_layout_size!(NodeHeader, 13);
+
+disk_layout! {
+    /// The fixed part of a leaf cell, followed by the key suffix and the value.
+    struct LeafCell {
+        suffix_len: u16,
+        value_len: u16,
+    }
+}
+
+assert_layout_size!(LeafCell, 4);
+
+disk_layout! {
+    /// The fixed part of an interior cell, followed by the key suffix.
+    struct InteriorCell {
+        child: PageId,
+        suffix_len: u16,
+    }
+}
+
+assert_layout_size!(InteriorCell, 6);
+
+/// Size of the header at the start of every node.
+pub(super) const NODE_HEADER_SIZE: usize = NodeHeader::SIZE;
+
+/// Size of the checksum at the start of every node.
+const CHECKSUM_SIZE: usize = 4;
+
+/// Size of the fixed part of a leaf cell: the key suffix length and the value length.
+pub(super) const LEAF_CELL_OVERHEAD: usize = LeafCell::SIZE;
+
+/// Size of the fixed part of an interior cell: the child page and the key suffix length.
+pub(super) const INTERIOR_CELL_OVERHEAD: usize = InteriorCell::SIZE;
+
+/// Represents the decoded content of a B+tree page.
+///
+/// The keys are always complete in memory. In the page, the prefix shared by all the keys of the
+/// node is stored once, after the header, and each cell only stores the rest of its key.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Node {
+    /// A leaf holds the key-value pairs in key order and a link to the next leaf.
+    Leaf {
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        next: Option<PageId>,
+    },
+
+    /// An interior node holds `keys.len() + 1` children. All the keys in `children[i]` are
+    /// smaller than `keys[i]` and all the keys in `children[i + 1]` are greater or equal to
+    /// `keys[i]`.
+    Interior {
+        keys: Vec<Vec<u8>>,
+        children: Vec<PageId>,
+    },
+}
+
+impl Node {
+    /// Creates an empty leaf without a next leaf.
+    pub(super) fn empty_leaf() -> Self {
+        Node::Leaf {
+            entries: Vec::new(),
+            next: None,
+        }
+    }
+
+    /// Reads and decodes the node stored in a page.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the page can't be read or does not contain a valid
+    /// node.
+    pub(super) fn read<F: File>(pager: &Pager<F>, id: PageId) -> Result<Self, BTreeError> {
+        let page = pager.read_page(id)?;
+        Node::decode(id, &page)
+    }
+
+    /// Returns the number of bytes needed to encode the node.
+    pub(super) fn encoded_size(&self) -> usize {
+        match self {
+            Node::Leaf { entries, .. } => leaf_size(entries),
+            Node::Interior { keys, .. } => interior_size(keys),
+        }
+    }
+
+    /// Encodes the node into a page, starting with the checksum of the rest of the page.
+    ///
+    /// The node must fit in the page. This is the responsibility of the caller.
+    pub(super) fn encode(&self, page_size: usize) -> Vec<u8> {
+        debug_assert!(self.encoded_size() <= page_size);
+
+        let mut page = Vec::with_capacity(page_size);
+        match self {
+            Node::Leaf { entries, next } => {
+                let prefix = common_prefix(entries.iter().map(|(k, _)| k.as_slice()));
+                let header = NodeHeader {
+                    checksum: 0,
+                    node_type: LEAF_TYPE,
+                    count: entries.len() as u16,
+                    link: next.unwrap_or(0),
+                    prefix_len: prefix.len() as u16,
+                };
+                header.append(&mut page);
+                page.extend_from_slice(prefix);
+                for (key, value) in entries {
+                    let suffix = &key[prefix.len()..];
+                    let cell = LeafCell {
+                        suffix_len: suffix.len() as u16,
+                        value_len: value.len() as u16,
+                    };
+                    cell.append(&mut page);
+                    page.extend_from_slice(suffix);
+                    page.extend_from_slice(value);
+                }
+            }
+            Node::Interior { keys, children } => {
+                let prefix = common_prefix(keys.iter().map(Vec::as_slice));
+                let header = NodeHeader {
+                    checksum: 0,
+                    node_type: INTERIOR_TYPE,
+                    count: keys.len() as u16,
+                    link: children[keys.len()],
+                    prefix_len: prefix.len() as u16,
+                };
+                header.append(&mut page);
+                page.extend_from_slice(prefix);
+                for (key, &child) in keys.iter().zip(children) {
+                    let suffix = &key[prefix.len()..];
+                    let cell = InteriorCell {
+                        child,
+                        suffix_len: suffix.len() as u16,
+                    };
+                    cell.append(&mut page);
+                    page.extend_from_slice(suffix);
+                }
+            }
+        }
+        page.resize(page_size, 0);
+        let checksum = crc32c(&page[CHECKSUM_SIZE..]);
+        page[..CHECKSUM_SIZE].copy_from_slice(&checksum.to_le_bytes());
+        page
+    }
+
+    /// Decodes the node stored in a page.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the page does not match its checksum or does not
+    /// contain a valid node.
+    pub(super) fn decode(id: PageId, page: &[u8]) -> Result<Self, BTreeError> {
+        let corrupted = || BTreeError::CorruptedPage(id);
+        let mut reader = Reader { page, offset: 0 };
+
+        let header: NodeHeader = reader.read_layout().ok_or_else(corrupted)?;
+        if header.checksum != crc32c(&page[CHECKSUM_SIZE..]) {
+            return Err(BTreeError::ChecksumMismatch(id));
+        }
+        let count = usize::from(header.count);
+        let link = header.link;
+        let prefix = reader
+            .read(usize::from(header.prefix_len))
+            .ok_or_else(corrupted)?;
+        match header.node_type {
+            LEAF_TYPE => {
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let cell: LeafCell = reader.read_layout().ok_or_else(corrupted)?;
+                    let suffix = reader
+                        .read(usize::from(cell.suffix_len))
+                        .ok_or_else(corrupted)?;
+                    let key = [prefix, suffix].concat();
+                    let value = reader
+                        .read(usize::from(cell.value_len))
+                        .ok_or_else(corrupted)?
+                        .to_vec();
+                    entries.push((key, value));
+                }
+                let next = if link == 0 { None } else { Some(link) };
+                Ok(Node::Leaf { entries, next })
+            }
+            INTERIOR_TYPE => {
+                let mut keys = Vec::with_capacity(count);
+                let mut children = Vec::with_capacity(count + 1);
+                for _ in 0..count {
+                    let cell: InteriorCell = reader.read_layout().ok_or_else(corrupted)?;
+                    children.push(cell.child);
+                    let suffix = reader
+                        .read(usize::from(cell.suffix_len))
+                        .ok_or_else(corrupted)?;
+                    keys.push([prefix, suffix].concat());
+                }
+                children.push(link);
+                Ok(Node::Interior { keys, children })
+            }
+            _ => Err(corrupted()),
+        }
+    }
+}
+
+/// Returns the number of bytes needed to encode a leaf holding `entries`.
+pub(super) fn leaf_size(entries: &[(Vec<u8>, Vec<u8>)]) -> usize {
+    let prefix_len = common_prefix(entries.iter().map(|(k, _)| k.as_slice())).len();
+    NODE_HEADER_SIZE
+        + prefix_len
+        + entries
+            .iter()
+            .map(|(key, value)| leaf_cell_size(key, value) - prefix_len)
+            .sum::<usize>()
+}
+
+/// Returns the number of bytes needed to encode an interior node holding `keys`.
+pub(super) fn interior_size(keys: &[Vec<u8>]) -> usize {
+    let prefix_len = common_prefix(keys.iter().map(Vec::as_slice)).len();
+    NODE_HEADER_SIZE
+        + prefix_len
+        + keys
+            .iter()
+            .map(|key| interior_cell_size(key) - prefix_len)
+            .sum::<usize>()
+}
+
+/// Returns the prefix shared by all the keys of a node. Every key is compared with the first one,
+/// since the keys are not necessarily sorted bytewise.
+fn common_prefix<'a>(mut keys: impl Iterator<Item = &'a [u8]>) -> &'a [u8] {
+    let Some(first) = keys.next() else {
+        return &[];
+    };
+    let len = keys.fold(first.len(), |len, key| {
+        len.min(common_prefix_len(&first[..len], key))
+    });
+    &first[..len]
+}
+
+/// Returns the length of the longest prefix shared by two keys.
+pub(super) fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Returns the number of bytes used by a key-value pair in a leaf, without prefix compression.
+pub(super) fn leaf_cell_size(key: &[u8], value: &[u8]) -> usize {
+    LEAF_CELL_OVERHEAD + key.len() + value.len()
+}
+
+/// Returns the number of bytes used by a separator key in an interior node, without prefix
+/// compression.
+pub(super) fn interior_cell_size(key: &[u8]) -> usize {
+    INTERIOR_CELL_OVERHEAD + key.len()
+}
+
+struct Reader<'a> {
+    page: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, len: usize) -> Option<&'a [u8]> {
+        let data = self.page.get(self.offset..self.offset + len)?;
+        self.offset += len;
+        Some(data)
+    }
+
+    fn read_layout<T: Layout>(&mut self) -> Option<T> {
+        T::read(self.read(T::SIZE)?).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A leaf is decoded exactly as it was encoded.
+    #[test]
+    fn encode_decode_leaf_round_trips() {
+        let node = Node::Leaf {
+            entries: vec![
+                (b"apple".to_vec(), b"red".to_vec()),
+                (b"banana".to_vec(), b"".to_vec()),
+            ],
+            next: Some(12),
+        };
+
+        let page = node.encode(512);
+        let decoded = Node::decode(1, &page).expect("decode should not fail");
+
+        assert_eq!(page.len(), 512);
+        assert_eq!(decoded, node);
+    }
+
+    /// An interior node is decoded exactly as it was encoded.
+    #[test]
+    fn encode_decode_interior_round_trips() {
+        let node = Node::Interior {
+            keys: vec![b"m".to_vec(), b"t".to_vec()],
+            children: vec![3, 4, 5],
+        };
+
+        let page = node.encode(512);
+        let decoded = Node::decode(1, &page).expect("decode should not fail");
+
+        assert_eq!(decoded, node);
+    }
+
+    /// The encoded size matches the number of meaningful bytes written to the page.
+    #[test]
+    fn encoded_size_matches_encoding() {
+        let node = Node::Leaf {
+            entries: vec![(vec![1u8; 10], vec![2u8; 20])],
+            next: None,
+        };
+
+        assert_eq!(
+            node.encoded_size(),
+            NODE_HEADER_SIZE + LEAF_CELL_OVERHEAD + 30
+        );
+    }
+
+    /// The prefix shared by the keys of a node is only stored once.
+    #[test]
+    fn encoded_size_stores_common_prefix_once() {
+        let node = Node::Interior {
+            keys: vec![b"table/users/1".to_vec(), b"table/users/2".to_vec()],
+            children: vec![3, 4, 5],
+        };
+
+        assert_eq!(
+            node.encoded_size(),
+            NODE_HEADER_SIZE + 12 + 2 * (INTERIOR_CELL_OVERHEAD + 1)
+        );
+    }
+
+    /// Keys sharing a prefix are decoded back to their complete form.
+    #[test]
+    fn encode_decode_common_prefix_round_trips() {
+        let node = Node::Leaf {
+            entries: vec![
+                (b"table/users".to_vec(), b"1".to_vec()),
+                (b"table/users/1".to_vec(), b"2".to_vec()),
+                (b"table/users/2".to_vec(), b"3".to_vec()),
+            ],
+            next: None,
+        };
+
+        let page = node.encode(512);
+        let decoded = Node::decode(1, &page).expect("decode should not fail");
+
+        assert_eq!(
+            &page[NODE_HEADER_SIZE..NODE_HEADER_SIZE + 11],
+            b"table/users"
+        );
+        assert_eq!(decoded, node);
+    }
+
+    /// Decoding a page with an unknown node type fails.
+    #[test]
+    fn decode_unknown_type_fails() {
+        let mut page = vec![0u8; 512];
+        let checksum = crc32c(&page[CHECKSUM_SIZE..]);
+        page[..CHECKSUM_SIZE].copy_from_slice(&checksum.to_le_bytes());
+
+        let result = Node::decode(7, &page);
+
+        assert!(matches!(result, Err(BTreeError::CorruptedPage(7))));
+    }
+
+    /// Decoding a page whose bytes changed since it was encoded fails, even when the change is in
+    /// its unused bytes.
+    #[test]
+    fn decode_damaged_page_fails() {
+        let node = Node::Leaf {
+            entries: vec![(b"key".to_vec(), b"value".to_vec())],
+            next: None,
+        };
+        let mut damaged_cell = node.encode(512);
+        damaged_cell[NODE_HEADER_SIZE + LEAF_CELL_OVERHEAD] ^= 1;
+        let mut damaged_end = node.encode(512);
+        damaged_end[511] = 1;
+
+        for damaged in [damaged_cell, damaged_end] {
+            assert!(matches!(
+                Node::decode(3, &damaged),
+                Err(BTreeError::ChecksumMismatch(3))
+            ));
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+use super::node::Node;
+use super::BTreeError;
+
+/// Describes the result of [CowTree::salvage](super::CowTree::salvage): the entries that could be
+/// read and the pages that could not.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SalvageReport {
+    /// The entries of the leaves that could be decoded, in the order they were visited.
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The pages that are not valid data pages, do not match their checksum or do not contain a
+    /// valid node, in the order they were visited. The entries of the subtrees below them are lost.
+    pub damaged_pages: Vec<PageId>,
+}
+
+/// Collects the entries of the tree rooted at `root`, skipping the pages that can't be decoded
+/// and the pages reached more than once.
+///
+/// # Errors
+///
+/// This function will return an error if a page can't be read.
+pub(super) fn salvage<F: File>(
+    pager: &Pager<F>,
+    root: PageId,
+) -> Result<SalvageReport, BTreeError> {
+    let mut report = SalvageReport::default();
+    let mut visited = HashSet::new();
+    let mut pending = vec![root];
+    while let Some(id) = pending.pop() {
+        if id == 0 || id >= pager.page_count() {
+            report.damaged_pages.push(id);
+            continue;
+        }
+        if !visited.insert(id) {
+            continue;
+        }
+        match Node::read(pager, id) {
+            Ok(Node::Leaf { entries, .. }) => report.entries.extend(entries),
+            // The children are pushed in reverse so they are visited in key order.
+            Ok(Node::Interior { children, .. }) => pending.extend(children.into_iter().rev()),
+            Err(BTreeError::CorruptedPage(_) | BTreeError::ChecksumMismatch(_)) => {
+                report.damaged_pages.push(id)
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::CowTree;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    /// Returns the leaves of the tree, in key order.
+    fn leaves(pager: &Pager<MemoryFile>, id: PageId) -> Vec<PageId> {
+        match Node::read(pager, id).expect("read should not fail") {
+            Node::Leaf { .. } => vec![id],
+            Node::Interior { children, .. } => children
+                .into_iter()
+                .flat_map(|child| leaves(pager, child))
+                .collect(),
+        }
+    }
+
+    /// The entries of the damaged leaves are lost, and every other entry is salvaged in key order.
+    #[test]
+    fn salvage_skips_damaged_pages() {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = CowTree::create(&mut pager).expect("create should not fail");
+        for i in 0..500 {
+            tree.insert(&mut pager, format!("key-{i:06}").as_bytes(), &[1; 20])
+                .expect("insert should not fail");
+        }
+        tree.commit(&mut pager).expect("commit should not fail");
+        let meta = pager.read_page(tree.meta()).expect("read should not fail");
+        let root = u32::from_le_bytes(meta[8..12].try_into().expect("slice should be 4 bytes"));
+        let damaged = leaves(&pager, root)[1];
+        let Node::Leaf { entries: lost, .. } =
+            Node::read(&pager, damaged).expect("read should not fail")
+        else {
+            panic!("the page should be a leaf");
+        };
+
+        pager
+            .write_page(damaged, &[0xff; 512])
+            .expect("write should not fail");
+        let report = CowTree::salvage(&pager, tree.meta()).expect("salvage should not fail");
+
+        assert_eq!(report.damaged_pages, vec![damaged]);
+        assert_eq!(report.entries.len(), 500 - lost.len());
+        assert!(report.entries.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(lost.iter().all(|entry| !report.entries.contains(entry)));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+use super::node::Node;
+use super::BTreeError;
+
+/// Describes the shape and the content of a tree, as measured by
+/// [BTree::analyze](super::BTree::analyze).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TreeStats {
+    /// The number of levels of the tree, including the leaves.
+    pub depth: usize,
+    /// The number of interior nodes.
+    pub interior_pages: usize,
+    /// The number of leaves.
+    pub leaf_pages: usize,
+    /// The number of key-value pairs.
+    pub entry_count: usize,
+    /// The total size of the keys of the key-value pairs.
+    pub key_bytes: usize,
+    /// The total size of the values.
+    pub value_bytes: usize,
+    /// The number of bytes used by the encoded nodes, headers included.
+    pub used_bytes: usize,
+    /// The size of the pages of the tree.
+    pub page_size: usize,
+}
+
+impl TreeStats {
+    /// Returns the number of pages of the tree.
+    pub fn page_count(&self) -> usize {
+        self.interior_pages + self.leaf_pages
+    }
+
+    /// Returns the fraction of the pages of the tree used by the nodes, between `0.0` and `1.0`.
+    pub fn average_fill(&self) -> f64 {
+        if self.page_count() == 0 {
+            return 0.0;
+        }
+        self.used_bytes as f64 / (self.page_count() * self.page_size) as f64
+    }
+}
+
+/// Walks the tree rooted at `root` and measures it.
+///
+/// # Errors
+///
+/// This function will return an error if a page can't be read or is corrupted.
+pub(super) fn analyze<F: File>(pager: &Pager<F>, root: PageId) -> Result<TreeStats, BTreeError> {
+    let mut stats = TreeStats {
+        page_size: pager.page_size(),
+        ..TreeStats::default()
+    };
+    let mut level = vec![root];
+    while !level.is_empty() {
+        stats.depth += 1;
+        let mut next_level = Vec::new();
+        for id in level {
+            let node = Node::read(pager, id)?;
+            stats.used_bytes += node.encoded_size();
+            match node {
+                Node::Leaf { entries, .. } => {
+                    stats.leaf_pages += 1;
+                    stats.entry_count += entries.len();
+                    for (key, value) in entries {
+                        stats.key_bytes += key.len();
+                        stats.value_bytes += value.len();
+                    }
+                }
+                Node::Interior { children, .. } => {
+                    stats.interior_pages += 1;
+                    next_level.extend(children);
+                }
+            }
+        }
+        level = next_level;
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::BTree;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_tree() -> (Pager<MemoryFile>, BTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let tree = BTree::create(&mut pager).expect("create should not fail");
+        (pager, tree)
+    }
+
+    /// The statistics of an empty tree describe a single empty leaf.
+    #[test]
+    fn analyze_empty_tree() {
+        let (pager, tree) = create_tree();
+
+        let stats = tree.analyze(&pager).expect("analyze should not fail");
+
+        assert_eq!(stats.depth, 1);
+        assert_eq!(stats.leaf_pages, 1);
+        assert_eq!(stats.interior_pages, 0);
+        assert_eq!(stats.entry_count, 0);
+    }
+
+    /// The statistics count the entries and their bytes, and agree with the shape found by a
+    /// verification.
+    #[test]
+    fn analyze_counts_entries_and_pages() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..1000 {
+            let key = format!("key-{index:06}");
+            tree.insert(&mut pager, key.as_bytes(), &[0; 20])
+                .expect("insert should not fail");
+        }
+
+        let stats = tree.analyze(&pager).expect("analyze should not fail");
+        let report = tree.verify(&pager).expect("verify should not fail");
+
+        assert_eq!(stats.entry_count, 1000);
+        assert_eq!(stats.key_bytes, 10_000);
+        assert_eq!(stats.value_bytes, 20_000);
+        assert_eq!(stats.depth, report.depth);
+        assert_eq!(stats.leaf_pages, report.leaf_count);
+        assert_eq!(stats.page_count(), report.page_count);
+        assert!(stats.average_fill() > 0.3 && stats.average_fill() <= 1.0);
+    }
+}
@@ -0,0 +1,1936 @@
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::fs::File;
+use crate::pager::{PageId, Pager, PagerError};
+
+use super::comparator::{BytewiseComparator, KeyComparator};
+use super::cursor::{Cursor, CursorMut, Range};
+use super::defragment::{self, DefragmentReport};
+use super::estimate::{self, RangeEstimate};
+use super::node::{self, Node, INTERIOR_CELL_OVERHEAD, NODE_HEADER_SIZE};
+use super::stats::{self, TreeStats};
+use super::verify::{self, VerifyReport};
+
+/// Represents errors that can occur during B+tree operations.
+#[derive(Error, Debug)]
+pub enum BTreeError {
+    /// Indicates that an operation on the underlying pager failed.
+    #[error(transparent)]
+    Pager(#[from] PagerError),
+
+    /// Indicates that a page does not contain a valid B+tree node.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page that could not be decoded.
+    #[error("The page ({0}) does not contain a valid B+tree node.")]
+    CorruptedPage(PageId),
+
+    /// Indicates that a page does not match its checksum: it was damaged since it was written.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the damaged page.
+    #[error("The page ({0}) does not match its checksum.")]
+    ChecksumMismatch(PageId),
+
+    /// Indicates that a fill factor outside of the supported range was requested.
+    ///
+    /// # Fields
+    /// - `0` - The fill factor that was requested.
+    #[error("The fill factor ({0}) must be greater than 0 and at most 1.")]
+    InvalidFillFactor(f64),
+
+    /// Indicates that the entries given to [BTree::bulk_load] are not sorted by strictly
+    /// increasing keys.
+    #[error("The entries to bulk load are not sorted by strictly increasing keys.")]
+    UnsortedEntries,
+
+    /// Indicates that a tree is opened with a comparator other than the one of the file.
+    ///
+    /// # Fields
+    /// - `expected` - The name of the comparator stored in the file.
+    /// - `actual` - The name of the comparator given to open the tree.
+    #[error("The file is ordered by the comparator \"{expected}\", but the comparator \"{actual}\" was given.")]
+    ComparatorMismatch { expected: String, actual: String },
+
+    /// Indicates that an operation on the current entry of a [CursorMut] was called while the
+    /// cursor is not positioned on an entry.
+    #[error("The cursor is not positioned on an entry.")]
+    InvalidCursor,
+
+    /// Indicates that the value of a key of a [DupTree](super::DupTree) is not a valid set of
+    /// values.
+    ///
+    /// # Fields
+    /// - `0` - The key whose value could not be decoded.
+    #[error("The values of the key {0:?} could not be decoded.")]
+    InvalidDuplicates(Vec<u8>),
+
+    /// Indicates that a key-value pair is too large to be stored in a node.
+    #[error("The entry is too large. The key is {key_size} bytes and the value is {value_size} bytes, but key and value must be at most {max_key_size} and {max_entry_size} bytes.")]
+    EntryTooLarge {
+        key_size: usize,
+        value_size: usize,
+        max_key_size: usize,
+        max_entry_size: usize,
+    },
+}
+
+/// The separator key and the page of the new right sibling produced when a node is split.
+type Split = (Vec<u8>, PageId);
+
+/// The right-most leaf of a tree, remembered between insertions.
+struct RightmostLeaf {
+    /// The identifier of the page of the leaf.
+    page: PageId,
+    /// The separator key above the leaf: every key greater or equal to it belongs to the leaf.
+    /// `None` when the leaf is the root.
+    low: Option<Vec<u8>>,
+}
+
+/// Represents a B+tree stored in the pages of a [Pager].
+///
+/// The tree maps byte-string keys to byte-string values, ordered by a [KeyComparator] (bytewise by
+/// default). The key-value pairs are stored in the leaves, which are linked together in key order.
+/// Interior nodes only hold separator keys.
+///
+/// The name of the comparator is stored in the header of the file by the first tree created in
+/// it. All the trees of a file must use the same comparator, and opening a tree with another
+/// comparator fails.
+///
+/// A [BTree] is only a handle on the root page of the tree: the pager is passed to each operation
+/// so several trees can share the same file. The root page never changes during the lifetime of
+/// the tree. When the root is split, its content is moved to new pages and the root becomes their
+/// parent. The root page identifier can therefore be stored to later reopen the tree with
+/// [BTree::open].
+///
+/// When a node is less than half full after a deletion, it is merged with a sibling or, if both
+/// do not fit in a single page, the entries of both nodes are redistributed evenly. How full the
+/// left node is left after a split is controlled by the fill factor (see
+/// [BTree::set_fill_factor]).
+///
+/// The handle remembers the right-most leaf of the tree. A key that belongs to this leaf, like the
+/// keys inserted in increasing order, is inserted directly in the leaf without descending from
+/// the root, unless the leaf has to be split. The tree must therefore only be modified through a
+/// single handle at a time.
+pub struct BTree {
+    root: PageId,
+    fill_factor: f64,
+    comparator: Arc<dyn KeyComparator>,
+    rightmost: Option<RightmostLeaf>,
+}
+
+impl BTree {
+    /// The fill factor used when none is set: split nodes are divided in two equal halves.
+    pub const DEFAULT_FILL_FACTOR: f64 = 0.5;
+
+    /// Creates a new, empty, tree in newly allocated page.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the root page can't be allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::btree::BTree;
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut tree = BTree::create(&mut pager).expect("create should not fail");
+    /// tree.insert(&mut pager, b"key", b"value").expect("insert should not fail");
+    ///
+    /// let value = tree.get(&pager, b"key").expect("get should not fail");
+    /// assert_eq!(value, Some(b"value".to_vec()));
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, BTreeError> {
+        Self::create_with_comparator(pager, Box::new(BytewiseComparator))
+    }
+
+    /// Creates a new, empty, tree whose keys are ordered by `comparator`. If no tree was created
+    /// in the file yet, the name of the comparator is stored in its header.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the file is ordered by another comparator
+    /// - the name of the comparator is too long to be stored in the header
+    /// - the root page can't be allocated or written
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::btree::{BTree, ReverseComparator};
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut tree = BTree::create_with_comparator(&mut pager, Box::new(ReverseComparator))
+    ///     .expect("create_with_comparator should not fail");
+    /// tree.insert(&mut pager, b"a", b"").expect("insert should not fail");
+    /// tree.insert(&mut pager, b"b", b"").expect("insert should not fail");
+    ///
+    /// let first = tree.iter(&pager).next().expect("the tree should not be empty");
+    /// assert_eq!(first.expect("iteration should not fail").0, b"b".to_vec());
+    /// ```
+    pub fn create_with_comparator<F: File>(
+        pager: &mut Pager<F>,
+        comparator: Box<dyn KeyComparator>,
+    ) -> Result<Self, BTreeError> {
+        if pager.comparator().is_empty() {
+            pager.set_comparator(comparator.name())?;
+        }
+        Self::check_comparator(pager, comparator.as_ref())?;
+
+        let root = pager.allocate_page()?;
+        let tree = BTree {
+            root,
+            fill_factor: Self::DEFAULT_FILL_FACTOR,
+            comparator: comparator.into(),
+            rightmost: None,
+        };
+        tree.write_node(pager, root, &Node::empty_leaf())?;
+        Ok(tree)
+    }
+
+    /// Opens a tree previously created with [BTree::create].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if the root page
+    /// can't be read or does not contain a valid node.
+    pub fn open<F: File>(pager: &Pager<F>, root: PageId) -> Result<Self, BTreeError> {
+        Self::open_with_comparator(pager, root, Box::new(BytewiseComparator))
+    }
+
+    /// Opens a tree previously created with [BTree::create_with_comparator].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is ordered by another comparator or if the
+    /// root page can't be read or does not contain a valid node.
+    pub fn open_with_comparator<F: File>(
+        pager: &Pager<F>,
+        root: PageId,
+        comparator: Box<dyn KeyComparator>,
+    ) -> Result<Self, BTreeError> {
+        Self::check_comparator(pager, comparator.as_ref())?;
+
+        let tree = BTree {
+            root,
+            fill_factor: Self::DEFAULT_FILL_FACTOR,
+            comparator: comparator.into(),
+            rightmost: None,
+        };
+        tree.read_node(pager, root)?;
+        Ok(tree)
+    }
+
+    /// Returns the identifier of the root page of the tree.
+    pub fn root(&self) -> PageId {
+        self.root
+    }
+
+    /// Returns the comparator ordering the keys of the tree.
+    pub fn comparator(&self) -> &dyn KeyComparator {
+        self.comparator.as_ref()
+    }
+
+    /// Creates a new tree from entries sorted by strictly increasing keys, in bytewise order.
+    ///
+    /// Instead of inserting the entries one by one, the leaves are filled in order, up to
+    /// `fill_factor` of a page, and the interior nodes are built bottom-up above them. Each page is
+    /// written once and the entries are consumed as the leaves are written. The returned tree
+    /// uses `fill_factor` for its later splits.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the fill factor is not greater than 0 and at most 1
+    /// - the keys are not strictly increasing
+    /// - an entry is too large (see [BTree::max_key_size] and [BTree::max_entry_size])
+    /// - a page can't be allocated or written
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::btree::BTree;
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    /// let entries = (0..1000u32).map(|index| (index.to_be_bytes(), b"value"));
+    ///
+    /// let tree = BTree::bulk_load(&mut pager, 0.9, entries).expect("bulk_load should not fail");
+    ///
+    /// let value = tree.get(&pager, &42u32.to_be_bytes()).expect("get should not fail");
+    /// assert_eq!(value, Some(b"value".to_vec()));
+    /// ```
+    pub fn bulk_load<F, I, K, V>(
+        pager: &mut Pager<F>,
+        fill_factor: f64,
+        entries: I,
+    ) -> Result<Self, BTreeError>
+    where
+        F: File,
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        Self::bulk_load_with_comparator(pager, Box::new(BytewiseComparator), fill_factor, entries)
+    }
+
+    /// Creates a new tree ordered by `comparator` from entries sorted by strictly increasing keys
+    /// in that order. See [BTree::bulk_load] and [BTree::create_with_comparator].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the file is ordered by another comparator
+    /// - the fill factor is not greater than 0 and at most 1
+    /// - the keys are not strictly increasing
+    /// - an entry is too large (see [BTree::max_key_size] and [BTree::max_entry_size])
+    /// - a page can't be allocated or written
+    pub fn bulk_load_with_comparator<F, I, K, V>(
+        pager: &mut Pager<F>,
+        comparator: Box<dyn KeyComparator>,
+        fill_factor: f64,
+        entries: I,
+    ) -> Result<Self, BTreeError>
+    where
+        F: File,
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut tree = BTree::create_with_comparator(pager, comparator)?;
+        tree.set_fill_factor(fill_factor)?;
+        let page_size = pager.page_size();
+        let target_size = (page_size as f64 * fill_factor) as usize;
+
+        // Fill the leaves in order. A leaf is written when the next entry does not fit in it
+        // anymore, at which point the page of the next leaf is known.
+        let mut level: Vec<(Vec<u8>, PageId)> = Vec::new();
+        let mut leaf = NodeBuilder::new(node::LEAF_CELL_OVERHEAD);
+        let mut entries_of_leaf: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        let mut previous_last_key: Option<Vec<u8>> = None;
+        let mut leaf_id = pager.allocate_page()?;
+        for (key, value) in entries {
+            let (key, value) = (key.as_ref(), value.as_ref());
+            Self::check_entry_size(page_size, key, value)?;
+            let last_key = entries_of_leaf
+                .last()
+                .map(|(k, _)| k)
+                .or(previous_last_key.as_ref());
+            if last_key.is_some_and(|last| tree.comparator.compare(last, key).is_ge()) {
+                return Err(BTreeError::UnsortedEntries);
+            }
+
+            if !entries_of_leaf.is_empty() && leaf.size_with(key, value.len()) > target_size {
+                let next_id = pager.allocate_page()?;
+                let entries = std::mem::take(&mut entries_of_leaf);
+                let separator = separator_before(
+                    tree.comparator.as_ref(),
+                    previous_last_key.as_deref(),
+                    &entries[0].0,
+                );
+                previous_last_key = entries.last().map(|(k, _)| k.clone());
+                let node = Node::Leaf {
+                    entries,
+                    next: Some(next_id),
+                };
+                tree.write_node(pager, leaf_id, &node)?;
+                level.push((separator, leaf_id));
+                leaf = NodeBuilder::new(node::LEAF_CELL_OVERHEAD);
+                leaf_id = next_id;
+            }
+            leaf.push(key, value.len());
+            entries_of_leaf.push((key.to_vec(), value.to_vec()));
+        }
+        let separator = match entries_of_leaf.first() {
+            Some((first, _)) => separator_before(
+                tree.comparator.as_ref(),
+                previous_last_key.as_deref(),
+                first,
+            ),
+            None => Vec::new(),
+        };
+        let node = Node::Leaf {
+            entries: entries_of_leaf,
+            next: None,
+        };
+        tree.write_node(pager, leaf_id, &node)?;
+        level.push((separator, leaf_id));
+
+        // Build each level of interior nodes above the previous one until a single node remains.
+        // The separator of the first child of a node is not stored in the node: it becomes the
+        // separator of the node itself in the level above.
+        while level.len() > 1 {
+            let mut groups: Vec<Vec<(Vec<u8>, PageId)>> = vec![Vec::new()];
+            let mut builder = NodeBuilder::new(node::INTERIOR_CELL_OVERHEAD);
+            for (separator, child) in level {
+                let group = groups.last_mut().expect("there should be a group");
+                if group.len() > 1 && builder.size_with(&separator, 0) > target_size {
+                    groups.push(Vec::new());
+                    builder = NodeBuilder::new(node::INTERIOR_CELL_OVERHEAD);
+                } else if !group.is_empty() {
+                    builder.push(&separator, 0);
+                }
+                groups
+                    .last_mut()
+                    .expect("there should be a group")
+                    .push((separator, child));
+            }
+            // An interior node needs at least two children. A last child left alone joins the
+            // previous node if it fits, or takes the last child of the previous node otherwise.
+            if groups.len() > 1 && groups.last().is_some_and(|group| group.len() == 1) {
+                let last = groups.pop().expect("there should be a group");
+                let previous = groups.last_mut().expect("there should be a group");
+                let keys: Vec<Vec<u8>> = previous[1..]
+                    .iter()
+                    .chain(&last)
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                if node::interior_size(&keys) <= page_size {
+                    previous.extend(last);
+                } else {
+                    let moved = previous.pop().expect("the group should not be empty");
+                    groups.push([vec![moved], last].concat());
+                }
+            }
+
+            level = Vec::with_capacity(groups.len());
+            for mut group in groups {
+                let id = pager.allocate_page()?;
+                let separator = std::mem::take(&mut group[0].0);
+                let node = Node::Interior {
+                    keys: group[1..].iter().map(|(key, _)| key.clone()).collect(),
+                    children: group.iter().map(|(_, child)| *child).collect(),
+                };
+                tree.write_node(pager, id, &node)?;
+                level.push((separator, id));
+            }
+        }
+
+        // The root page must not move: the top node is moved to the root page.
+        let (_, top) = level[0];
+        let node = tree.read_node(pager, top)?;
+        tree.write_node(pager, tree.root, &node)?;
+        pager.free_page(top)?;
+
+        Ok(tree)
+    }
+
+    /// Returns the fill factor used when splitting nodes.
+    pub fn fill_factor(&self) -> f64 {
+        self.fill_factor
+    }
+
+    /// Sets the fraction of the bytes of a full node that stays in the left node when it is split.
+    ///
+    /// The default, [BTree::DEFAULT_FILL_FACTOR], splits nodes evenly, which suits random
+    /// insertions. When keys are mostly inserted in increasing order, the left node is never
+    /// modified again after a split. A fill factor close to `1` then leaves the nodes almost full
+    /// instead of half empty. The fill factor is not stored in the file and must be set again
+    /// after the tree is opened.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the fill factor is not greater than 0 and at most 1.
+    pub fn set_fill_factor(&mut self, fill_factor: f64) -> Result<(), BTreeError> {
+        if !(fill_factor > 0.0 && fill_factor <= 1.0) {
+            return Err(BTreeError::InvalidFillFactor(fill_factor));
+        }
+        self.fill_factor = fill_factor;
+        Ok(())
+    }
+
+    /// Returns the largest key that can be stored in a tree using pages of the given size.
+    pub fn max_key_size(page_size: usize) -> usize {
+        Self::max_cell_size(page_size) - INTERIOR_CELL_OVERHEAD
+    }
+
+    /// Returns the largest combined size of a key and its value that can be stored in a tree
+    /// using pages of the given size.
+    pub fn max_entry_size(page_size: usize) -> usize {
+        Self::max_cell_size(page_size) - node::LEAF_CELL_OVERHEAD
+    }
+
+    /// Returns the value associated with a key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, BTreeError> {
+        let mut id = self.root;
+        loop {
+            match self.read_node(pager, id)? {
+                Node::Leaf { entries, .. } => {
+                    return Ok(entries
+                        .binary_search_by(|(k, _)| self.comparator.compare(k, key))
+                        .ok()
+                        .map(|index| entries[index].1.clone()));
+                }
+                Node::Interior { keys, children } => {
+                    id = children[child_index(self.comparator.as_ref(), &keys, key)];
+                }
+            }
+        }
+    }
+
+    /// Returns a new [Cursor] on the tree. The cursor is not positioned on any entry until one of
+    /// its seek methods is called.
+    pub fn cursor<'a, F: File>(&'a self, pager: &'a Pager<F>) -> Cursor<'a, F> {
+        Cursor::new(pager, self.root, self.comparator.as_ref())
+    }
+
+    /// Returns a new [CursorMut] on the tree, which can replace or remove the entry it is
+    /// positioned on. The cursor is not positioned on any entry until one of its seek methods is
+    /// called.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::btree::BTree;
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    /// let mut tree = BTree::create(&mut pager).expect("create should not fail");
+    /// for key in [b"a", b"b", b"c"] {
+    ///     tree.insert(&mut pager, key, b"old").expect("insert should not fail");
+    /// }
+    ///
+    /// let mut cursor = tree.cursor_mut(&mut pager);
+    /// cursor.seek_to_first().expect("seek_to_first should not fail");
+    /// cursor.delete_current().expect("delete_current should not fail");
+    /// cursor.put_current(b"new").expect("put_current should not fail");
+    ///
+    /// assert_eq!(tree.get(&pager, b"a").expect("get should not fail"), None);
+    /// assert_eq!(tree.get(&pager, b"b").expect("get should not fail"), Some(b"new".to_vec()));
+    /// ```
+    pub fn cursor_mut<'a, F: File>(&'a mut self, pager: &'a mut Pager<F>) -> CursorMut<'a, F> {
+        CursorMut::new(pager, self)
+    }
+
+    /// Returns an iterator over the entries whose keys are within `range`, in key order. The
+    /// iterator can also be consumed in reverse order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::btree::BTree;
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    /// let mut tree = BTree::create(&mut pager).expect("create should not fail");
+    /// for key in [b"a", b"b", b"c", b"d"] {
+    ///     tree.insert(&mut pager, key, b"").expect("insert should not fail");
+    /// }
+    ///
+    /// let keys: Vec<Vec<u8>> = tree
+    ///     .range(&pager, b"b".as_slice()..=b"c".as_slice())
+    ///     .rev()
+    ///     .map(|entry| entry.expect("iteration should not fail").0)
+    ///     .collect();
+    ///
+    /// assert_eq!(keys, vec![b"c".to_vec(), b"b".to_vec()]);
+    /// ```
+    pub fn range<'a, F, K, R>(&'a self, pager: &'a Pager<F>, range: R) -> Range<'a, F>
+    where
+        F: File,
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        Range::new(pager, self.root, self.comparator.as_ref(), range)
+    }
+
+    /// Returns an estimate of the number of entries within a range of keys, without iterating
+    /// over the range.
+    ///
+    /// Only the nodes on the paths to the bounds of the range are read: the entries of the leaves
+    /// at the ends of the range are counted, and the number of entries of the subtrees in between
+    /// is deduced from the number of children of the interior nodes read and the number of
+    /// entries of the leaves read. The estimate is exact for a range within a single leaf.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn estimate_count<F, K, R>(&self, pager: &Pager<F>, range: R) -> Result<u64, BTreeError>
+    where
+        F: File,
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        Ok(self.estimate_range(pager, range)?.count.round() as u64)
+    }
+
+    /// Returns an estimate of the total size of the keys and values within a range of keys,
+    /// without iterating over the range. The estimate is made like [BTree::estimate_count].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn estimate_size<F, K, R>(&self, pager: &Pager<F>, range: R) -> Result<u64, BTreeError>
+    where
+        F: File,
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        Ok(self.estimate_range(pager, range)?.size.round() as u64)
+    }
+
+    /// Returns an iterator over all the entries of the tree, in key order.
+    pub fn iter<'a, F: File>(&'a self, pager: &'a Pager<F>) -> Range<'a, F> {
+        Range::new::<[u8], _>(pager, self.root, self.comparator.as_ref(), ..)
+    }
+
+    /// Inserts a key-value pair in the tree, replacing the previous value of the key. Returns the
+    /// previous value, if there was one.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the key or the value is too large (see [BTree::max_key_size] and
+    ///   [BTree::max_entry_size])
+    /// - a page can't be read, written or allocated
+    pub fn insert<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>, BTreeError> {
+        Self::check_entry_size(pager.page_size(), key, value)?;
+
+        if let Some(previous) = self.insert_rightmost(pager, key, value)? {
+            return Ok(previous);
+        }
+
+        let (previous, split) = self.insert_into(pager, self.root, key, value)?;
+        if let Some(split) = split {
+            self.grow_root(pager, split)?;
+        }
+
+        Ok(previous)
+    }
+
+    /// Removes a key from the tree. Returns the value of the key, if it was present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or written.
+    pub fn delete<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, BTreeError> {
+        self.rightmost = None;
+        let (previous, split) = self.delete_from(pager, self.root, key)?;
+        if let Some(split) = split {
+            self.grow_root(pager, split)?;
+        }
+
+        // The root page must not move: when the root only has one child left, the content of the
+        // child is moved to the root.
+        while let Node::Interior { keys, children } = self.read_node(pager, self.root)? {
+            if !keys.is_empty() {
+                break;
+            }
+            let child = self.read_node(pager, children[0])?;
+            self.write_node(pager, self.root, &child)?;
+            pager.free_page(children[0])?;
+        }
+
+        Ok(previous)
+    }
+
+    /// Frees all the pages of the tree, including its root page.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or freed.
+    pub fn destroy<F: File>(self, pager: &mut Pager<F>) -> Result<(), BTreeError> {
+        let mut pending = vec![self.root];
+        while let Some(id) = pending.pop() {
+            if let Node::Interior { children, .. } = self.read_node(pager, id)? {
+                pending.extend(children);
+            }
+            pager.free_page(id)?;
+        }
+        Ok(())
+    }
+
+    /// Creates a new, empty, tree ordered by the same comparator as this one.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the root page can't be allocated or written.
+    pub(super) fn create_sibling<F: File>(&self, pager: &mut Pager<F>) -> Result<Self, BTreeError> {
+        let tree = self.sibling(pager.allocate_page()?);
+        tree.write_node(pager, tree.root, &Node::empty_leaf())?;
+        Ok(tree)
+    }
+
+    /// Returns a handle on the tree rooted at `root`, ordered by the same comparator as this one.
+    pub(super) fn sibling(&self, root: PageId) -> Self {
+        BTree {
+            root,
+            fill_factor: self.fill_factor,
+            comparator: Arc::clone(&self.comparator),
+            rightmost: None,
+        }
+    }
+
+    /// Walks the whole tree and checks its consistency: the keys of each node are sorted and
+    /// within the range given by the separators of its parent, every leaf is at the same depth,
+    /// the leaves are linked in key order, no page is reached twice, no page of the tree is in
+    /// the list of free pages and every page matches its checksum.
+    ///
+    /// The inconsistencies are listed in the returned report instead of stopping the walk.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read.
+    pub fn verify<F: File>(&self, pager: &Pager<F>) -> Result<VerifyReport, BTreeError> {
+        verify::verify(pager, self.root, self.comparator.as_ref())
+    }
+
+    /// Walks the whole tree and measures it: its depth, its pages, its entries and their size, and
+    /// how full its pages are. The statistics are not kept up to date, so the walk is only done
+    /// when this method is called.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn analyze<F: File>(&self, pager: &Pager<F>) -> Result<TreeStats, BTreeError> {
+        stats::analyze(pager, self.root)
+    }
+
+    /// Rewrites the leaves fragmented by splits and deletions, a few at a time. The leaves sharing
+    /// a parent are packed into as few leaves as possible, up to 90% of a page, and moved to
+    /// pages in increasing order so a scan reads the file sequentially. The pages left empty are
+    /// returned to the pager.
+    ///
+    /// The work is done in small steps so it can be interleaved with other operations on the
+    /// tree: the leaves are visited in key order from the leaves that may hold `from` (or from
+    /// the first leaf) and the call returns once at least `max_leaves` leaves were examined. The
+    /// returned [DefragmentReport::resume_key] is the `from` of the next step.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read, written or freed.
+    pub fn defragment<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        from: Option<&[u8]>,
+        max_leaves: usize,
+    ) -> Result<DefragmentReport, BTreeError> {
+        self.rightmost = None;
+        defragment::defragment(pager, self.root, self.comparator.as_ref(), from, max_leaves)
+    }
+
+    fn estimate_range<F, K, R>(
+        &self,
+        pager: &Pager<F>,
+        range: R,
+    ) -> Result<RangeEstimate, BTreeError>
+    where
+        F: File,
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        estimate::estimate(
+            pager,
+            self.root,
+            self.comparator.as_ref(),
+            range.start_bound().map(AsRef::as_ref),
+            range.end_bound().map(AsRef::as_ref),
+        )
+    }
+
+    /// Inserts a key-value pair in the right-most leaf if the key belongs to it and the leaf does
+    /// not need to be split. Returns `None` if the pair must be inserted from the root, or the
+    /// previous value of the key.
+    ///
+    /// The leaf is found by a descent the first time, and again each time it is split or the tree
+    /// is rebalanced.
+    fn insert_rightmost<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<Option<Vec<u8>>>, BTreeError> {
+        let rightmost = match self.rightmost.take() {
+            Some(rightmost) => rightmost,
+            None => self.find_rightmost(pager)?,
+        };
+        if let Some(low) = &rightmost.low {
+            if self.comparator.compare(key, low).is_lt() {
+                self.rightmost = Some(rightmost);
+                return Ok(None);
+            }
+        }
+
+        // The right-most leaf is the only one without a next leaf.
+        let Node::Leaf {
+            mut entries,
+            next: None,
+        } = self.read_node(pager, rightmost.page)?
+        else {
+            return Ok(None);
+        };
+        let previous = match entries.binary_search_by(|(k, _)| self.comparator.compare(k, key)) {
+            Ok(index) => Some(std::mem::replace(&mut entries[index].1, value.to_vec())),
+            Err(index) => {
+                entries.insert(index, (key.to_vec(), value.to_vec()));
+                None
+            }
+        };
+        let node = Node::Leaf {
+            entries,
+            next: None,
+        };
+        if node.encoded_size() > pager.page_size() {
+            // The leaf is split by a descent from the root, after which it must be found again.
+            return Ok(None);
+        }
+        self.write_node(pager, rightmost.page, &node)?;
+        self.rightmost = Some(rightmost);
+        Ok(Some(previous))
+    }
+
+    /// Descends from the root to the right-most leaf.
+    fn find_rightmost<F: File>(&self, pager: &Pager<F>) -> Result<RightmostLeaf, BTreeError> {
+        let mut rightmost = RightmostLeaf {
+            page: self.root,
+            low: None,
+        };
+        while let Node::Interior { mut keys, children } = self.read_node(pager, rightmost.page)? {
+            rightmost.page = *children
+                .last()
+                .ok_or(BTreeError::CorruptedPage(rightmost.page))?;
+            if let Some(key) = keys.pop() {
+                rightmost.low = Some(key);
+            }
+        }
+        Ok(rightmost)
+    }
+
+    /// Inserts a key-value pair in the subtree rooted at `id`. Returns the previous value of the
+    /// key and, if the node was split, the separator key and the page of the new right sibling.
+    fn insert_into<F: File>(
+        &self,
+        pager: &mut Pager<F>,
+        id: PageId,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(Option<Vec<u8>>, Option<Split>), BTreeError> {
+        match self.read_node(pager, id)? {
+            Node::Leaf { mut entries, next } => {
+                let previous =
+                    match entries.binary_search_by(|(k, _)| self.comparator.compare(k, key)) {
+                        Ok(index) => Some(std::mem::replace(&mut entries[index].1, value.to_vec())),
+                        Err(index) => {
+                            entries.insert(index, (key.to_vec(), value.to_vec()));
+                            None
+                        }
+                    };
+
+                let node = Node::Leaf { entries, next };
+                let split = self.write_or_split(pager, id, node)?;
+                Ok((previous, split))
+            }
+            Node::Interior {
+                mut keys,
+                mut children,
+            } => {
+                let index = child_index(self.comparator.as_ref(), &keys, key);
+                let (previous, child_split) =
+                    self.insert_into(pager, children[index], key, value)?;
+                let Some((separator, right)) = child_split else {
+                    return Ok((previous, None));
+                };
+
+                keys.insert(index, separator);
+                children.insert(index + 1, right);
+                let split = self.write_or_split(pager, id, Node::Interior { keys, children })?;
+                Ok((previous, split))
+            }
+        }
+    }
+
+    /// Removes a key from the subtree rooted at `id`. Returns the value of the key and, if the node
+    /// had to be split, the separator key and the page of the new right sibling.
+    ///
+    /// A node only needs to be split after a deletion when rebalancing its children replaced a
+    /// separator key by a longer one.
+    fn delete_from<F: File>(
+        &self,
+        pager: &mut Pager<F>,
+        id: PageId,
+        key: &[u8],
+    ) -> Result<(Option<Vec<u8>>, Option<Split>), BTreeError> {
+        match self.read_node(pager, id)? {
+            Node::Leaf { mut entries, next } => {
+                let Ok(index) = entries.binary_search_by(|(k, _)| self.comparator.compare(k, key))
+                else {
+                    return Ok((None, None));
+                };
+                let (_, value) = entries.remove(index);
+                self.write_node(pager, id, &Node::Leaf { entries, next })?;
+                Ok((Some(value), None))
+            }
+            Node::Interior {
+                mut keys,
+                mut children,
+            } => {
+                let index = child_index(self.comparator.as_ref(), &keys, key);
+                let (previous, child_split) = self.delete_from(pager, children[index], key)?;
+                if previous.is_none() {
+                    return Ok((None, None));
+                }
+
+                if let Some((separator, right)) = child_split {
+                    keys.insert(index, separator);
+                    children.insert(index + 1, right);
+                }
+                self.rebalance_child(pager, &mut keys, &mut children, index)?;
+                let split = self.write_or_split(pager, id, Node::Interior { keys, children })?;
+                Ok((previous, split))
+            }
+        }
+    }
+
+    /// Merges the child at `index` with one of its siblings, or redistributes their entries, if the
+    /// child is less than half full. The keys and children of the parent are updated accordingly.
+    fn rebalance_child<F: File>(
+        &self,
+        pager: &mut Pager<F>,
+        keys: &mut Vec<Vec<u8>>,
+        children: &mut Vec<PageId>,
+        index: usize,
+    ) -> Result<(), BTreeError> {
+        let page_size = pager.page_size();
+        if children.len() < 2
+            || self.read_node(pager, children[index])?.encoded_size()
+                >= Self::min_node_size(page_size)
+        {
+            return Ok(());
+        }
+
+        let left_index = if index > 0 { index - 1 } else { index };
+        let (left_id, right_id) = (children[left_index], children[left_index + 1]);
+        let left = self.read_node(pager, left_id)?;
+        let right = self.read_node(pager, right_id)?;
+        let merged = match (left, right) {
+            (
+                Node::Leaf { mut entries, .. },
+                Node::Leaf {
+                    entries: right_entries,
+                    next,
+                },
+            ) => {
+                entries.extend(right_entries);
+                Node::Leaf { entries, next }
+            }
+            (
+                Node::Interior {
+                    keys: mut left_keys,
+                    children: mut left_children,
+                },
+                Node::Interior {
+                    keys: right_keys,
+                    children: right_children,
+                },
+            ) => {
+                left_keys.push(keys[left_index].clone());
+                left_keys.extend(right_keys);
+                left_children.extend(right_children);
+                Node::Interior {
+                    keys: left_keys,
+                    children: left_children,
+                }
+            }
+            _ => return Err(BTreeError::CorruptedPage(right_id)),
+        };
+
+        if merged.encoded_size() <= page_size {
+            self.write_node(pager, left_id, &merged)?;
+            pager.free_page(right_id)?;
+            keys.remove(left_index);
+            children.remove(left_index + 1);
+        } else {
+            let (left, separator, right) = split_node(
+                self.comparator.as_ref(),
+                merged,
+                right_id,
+                Self::DEFAULT_FILL_FACTOR,
+                page_size,
+            );
+            self.write_node(pager, left_id, &left)?;
+            self.write_node(pager, right_id, &right)?;
+            keys[left_index] = separator;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the content of the root to a new page and makes the root the parent of this page and
+    /// of the right sibling produced by a split. This keeps the root page from moving.
+    fn grow_root<F: File>(&self, pager: &mut Pager<F>, split: Split) -> Result<(), BTreeError> {
+        let (separator, right) = split;
+        let left = pager.allocate_page()?;
+        let left_node = self.read_node(pager, self.root)?;
+        self.write_node(pager, left, &left_node)?;
+        let root_node = Node::Interior {
+            keys: vec![separator],
+            children: vec![left, right],
+        };
+        self.write_node(pager, self.root, &root_node)
+    }
+
+    /// Writes a node to its page, splitting it in two if it does not fit. When the node is split,
+    /// the left half stays in the page and the right half is written to a new page. Returns the
+    /// separator key and the page of the right half.
+    fn write_or_split<F: File>(
+        &self,
+        pager: &mut Pager<F>,
+        id: PageId,
+        node: Node,
+    ) -> Result<Option<Split>, BTreeError> {
+        if node.encoded_size() <= pager.page_size() {
+            self.write_node(pager, id, &node)?;
+            return Ok(None);
+        }
+
+        let right_id = pager.allocate_page()?;
+        let (left, separator, right) = split_node(
+            self.comparator.as_ref(),
+            node,
+            right_id,
+            self.fill_factor,
+            pager.page_size(),
+        );
+        self.write_node(pager, id, &left)?;
+        self.write_node(pager, right_id, &right)?;
+        Ok(Some((separator, right_id)))
+    }
+
+    fn read_node<F: File>(&self, pager: &Pager<F>, id: PageId) -> Result<Node, BTreeError> {
+        Node::read(pager, id)
+    }
+
+    fn write_node<F: File>(
+        &self,
+        pager: &mut Pager<F>,
+        id: PageId,
+        node: &Node,
+    ) -> Result<(), BTreeError> {
+        pager.write_page(id, &node.encode(pager.page_size()))?;
+        Ok(())
+    }
+
+    /// Checks that the comparator has the name stored in the header of the file.
+    fn check_comparator<F: File>(
+        pager: &Pager<F>,
+        comparator: &dyn KeyComparator,
+    ) -> Result<(), BTreeError> {
+        if pager.comparator() != comparator.name() {
+            return Err(BTreeError::ComparatorMismatch {
+                expected: pager.comparator().to_string(),
+                actual: comparator.name().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that a key-value pair is small enough to be stored in a tree using pages of the given
+    /// size.
+    fn check_entry_size(page_size: usize, key: &[u8], value: &[u8]) -> Result<(), BTreeError> {
+        if key.len() > Self::max_key_size(page_size)
+            || key.len() + value.len() > Self::max_entry_size(page_size)
+        {
+            return Err(BTreeError::EntryTooLarge {
+                key_size: key.len(),
+                value_size: value.len(),
+                max_key_size: Self::max_key_size(page_size),
+                max_entry_size: Self::max_entry_size(page_size),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the largest cell that can be stored in a node. Limiting cells to a quarter of a page
+    /// guarantees that both halves of a split node fit in their page.
+    fn max_cell_size(page_size: usize) -> usize {
+        (page_size - NODE_HEADER_SIZE) / 4
+    }
+
+    /// Returns the size under which a node is rebalanced after a deletion.
+    fn min_node_size(page_size: usize) -> usize {
+        page_size / 2
+    }
+}
+
+/// Returns the index of the child of an interior node that may contain the key.
+pub(super) fn child_index(comparator: &dyn KeyComparator, keys: &[Vec<u8>], key: &[u8]) -> usize {
+    keys.partition_point(|k| comparator.compare(k, key).is_le())
+}
+
+/// Splits a node that does not fit in a page in two. The right half is meant to be written to
+/// `right_id`. Returns the left half, the separator key and the right half.
+pub(super) fn split_node(
+    comparator: &dyn KeyComparator,
+    node: Node,
+    right_id: PageId,
+    fill_factor: f64,
+    page_size: usize,
+) -> (Node, Vec<u8>, Node) {
+    match node {
+        Node::Leaf { mut entries, next } => {
+            let sizes: Vec<usize> = entries
+                .iter()
+                .map(|(key, value)| node::leaf_cell_size(key, value))
+                .collect();
+            let index = split_index(&sizes, fill_factor, |index| {
+                node::leaf_size(&entries[..index]) <= page_size
+                    && node::leaf_size(&entries[index..]) <= page_size
+            });
+            let right_entries = entries.split_off(index);
+            let separator =
+                comparator.shortest_separator(&entries[index - 1].0, &right_entries[0].0);
+            let left = Node::Leaf {
+                entries,
+                next: Some(right_id),
+            };
+            let right = Node::Leaf {
+                entries: right_entries,
+                next,
+            };
+            (left, separator, right)
+        }
+        Node::Interior {
+            mut keys,
+            mut children,
+        } => {
+            let sizes: Vec<usize> = keys
+                .iter()
+                .map(|key| node::interior_cell_size(key))
+                .collect();
+            let middle = split_index(&sizes, fill_factor, |index| {
+                node::interior_size(&keys[..index]) <= page_size
+                    && node::interior_size(&keys[index + 1..]) <= page_size
+            });
+            let right_keys = keys.split_off(middle + 1);
+            let separator = keys.pop().expect("the middle key should exist");
+            let right_children = children.split_off(middle + 1);
+            let left = Node::Interior { keys, children };
+            let right = Node::Interior {
+                keys: right_keys,
+                children: right_children,
+            };
+            (left, separator, right)
+        }
+    }
+}
+
+/// Returns the separator to store before a node whose first key is `first`, given the last key of
+/// the previous node, if there is one.
+pub(super) fn separator_before(
+    comparator: &dyn KeyComparator,
+    previous_last_key: Option<&[u8]>,
+    first: &[u8],
+) -> Vec<u8> {
+    match previous_last_key {
+        Some(previous) => comparator.shortest_separator(previous, first),
+        None => Vec::new(),
+    }
+}
+
+/// Tracks the encoded size of a node while keys are appended to it.
+pub(super) struct NodeBuilder {
+    cell_overhead: usize,
+    first_key: Option<Vec<u8>>,
+    prefix_len: usize,
+    cells_size: usize,
+    count: usize,
+}
+
+impl NodeBuilder {
+    pub(super) fn new(cell_overhead: usize) -> Self {
+        NodeBuilder {
+            cell_overhead,
+            first_key: None,
+            prefix_len: 0,
+            cells_size: 0,
+            count: 0,
+        }
+    }
+
+    /// Returns the encoded size the node would have if `key` and a value of `value_len` bytes were
+    /// appended to it.
+    pub(super) fn size_with(&self, key: &[u8], value_len: usize) -> usize {
+        let prefix_len = match &self.first_key {
+            Some(first) => self.prefix_len.min(node::common_prefix_len(first, key)),
+            None => key.len(),
+        };
+        NODE_HEADER_SIZE + prefix_len + self.cells_size + self.cell_overhead + key.len() + value_len
+            - (self.count + 1) * prefix_len
+    }
+
+    /// Appends `key` and a value of `value_len` bytes to the node.
+    pub(super) fn push(&mut self, key: &[u8], value_len: usize) {
+        match &self.first_key {
+            Some(first) => {
+                self.prefix_len = self.prefix_len.min(node::common_prefix_len(first, key));
+            }
+            None => {
+                self.first_key = Some(key.to_vec());
+                self.prefix_len = key.len();
+            }
+        }
+        self.cells_size += self.cell_overhead + key.len() + value_len;
+        self.count += 1;
+    }
+}
+
+/// Returns the index at which cells should be split so that about `fill_factor` of the bytes stay
+/// on the left. The index is always between `1` and `sizes.len() - 1`.
+///
+/// Because of prefix compression, the size of each half depends on the prefix shared by its own
+/// keys. The indexes closest to the target are tried in turn until `fits` accepts one.
+fn split_index(sizes: &[usize], fill_factor: f64, fits: impl Fn(usize) -> bool) -> usize {
+    let total: usize = sizes.iter().sum();
+    let target = (total as f64 * fill_factor) as usize;
+    let mut accumulated = 0;
+    let mut index = 0;
+    while index < sizes.len() && accumulated < target {
+        accumulated += sizes[index];
+        index += 1;
+    }
+
+    let (min, max) = (1, sizes.len() - 1);
+    let index = index.clamp(min, max);
+    (0..sizes.len())
+        .flat_map(|distance| [index.checked_sub(distance), Some(index + distance)])
+        .flatten()
+        .filter(|&candidate| (min..=max).contains(&candidate))
+        .find(|&candidate| fits(candidate))
+        .unwrap_or(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::seq::SliceRandom;
+
+    use crate::btree::{CaseInsensitiveComparator, NumericComparator, ReverseComparator};
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_tree() -> (Pager<MemoryFile>, BTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let tree = BTree::create(&mut pager).expect("create should not fail");
+        (pager, tree)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:06}").into_bytes()
+    }
+
+    fn value(index: usize) -> Vec<u8> {
+        format!("value-{index}").into_bytes()
+    }
+
+    /// Getting a key from an empty tree returns nothing.
+    #[test]
+    fn get_empty_tree_returns_none() {
+        let (pager, tree) = create_tree();
+
+        let result = tree.get(&pager, b"missing");
+
+        assert!(matches!(result, Ok(None)));
+    }
+
+    /// An inserted value can be read back.
+    #[test]
+    fn insert_then_get_returns_value() {
+        let (mut pager, mut tree) = create_tree();
+
+        tree.insert(&mut pager, b"key", b"value")
+            .expect("insert should not fail");
+        let result = tree.get(&pager, b"key");
+
+        assert!(matches!(result, Ok(Some(v)) if v == b"value"));
+    }
+
+    /// Inserting an existing key replaces and returns the previous value.
+    #[test]
+    fn insert_existing_key_replaces_value() {
+        let (mut pager, mut tree) = create_tree();
+        tree.insert(&mut pager, b"key", b"first")
+            .expect("insert should not fail");
+
+        let previous = tree.insert(&mut pager, b"key", b"second");
+
+        assert!(matches!(previous, Ok(Some(v)) if v == b"first"));
+        assert!(matches!(tree.get(&pager, b"key"), Ok(Some(v)) if v == b"second"));
+    }
+
+    /// Inserting many keys in random order splits the nodes and all keys can still be found.
+    #[test]
+    fn insert_many_keys_random_order_all_found() {
+        let (mut pager, mut tree) = create_tree();
+        let mut indexes: Vec<usize> = (0..2000).collect();
+        indexes.shuffle(&mut rand::thread_rng());
+
+        for &index in &indexes {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+
+        assert!(pager.page_count() > 10);
+        for index in 0..2000 {
+            let result = tree.get(&pager, &key(index));
+            assert!(matches!(result, Ok(Some(v)) if v == value(index)));
+        }
+        assert!(matches!(tree.get(&pager, b"key-999999"), Ok(None)));
+    }
+
+    /// The root page does not change when the tree grows.
+    #[test]
+    fn insert_many_keys_root_page_does_not_change() {
+        let (mut pager, mut tree) = create_tree();
+        let root = tree.root();
+
+        for index in 0..500 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+
+        assert_eq!(tree.root(), root);
+        assert!(matches!(
+            tree.read_node(&pager, root),
+            Ok(Node::Interior { .. })
+        ));
+    }
+
+    /// Inserting a key larger than the maximum fails.
+    #[test]
+    fn insert_key_too_large_fails() {
+        let (mut pager, mut tree) = create_tree();
+        let key = vec![0u8; BTree::max_key_size(512) + 1];
+
+        let result = tree.insert(&mut pager, &key, b"");
+
+        assert!(matches!(result, Err(BTreeError::EntryTooLarge { .. })));
+    }
+
+    /// Inserting a value larger than the maximum fails.
+    #[test]
+    fn insert_value_too_large_fails() {
+        let (mut pager, mut tree) = create_tree();
+        let value = vec![0u8; BTree::max_entry_size(512)];
+
+        let result = tree.insert(&mut pager, b"key", &value);
+
+        assert!(matches!(result, Err(BTreeError::EntryTooLarge { .. })));
+    }
+
+    /// Entries of the maximum size can be inserted and split.
+    #[test]
+    fn insert_max_size_entries_succeeds() {
+        let (mut pager, mut tree) = create_tree();
+        let value_size = BTree::max_entry_size(512) - key(0).len();
+
+        for index in 0..50 {
+            tree.insert(&mut pager, &key(index), &vec![index as u8; value_size])
+                .expect("insert should not fail");
+        }
+
+        for index in 0..50 {
+            let result = tree.get(&pager, &key(index));
+            assert!(matches!(result, Ok(Some(v)) if v == vec![index as u8; value_size]));
+        }
+    }
+
+    /// Deleting a key removes it and returns its value.
+    #[test]
+    fn delete_existing_key_removes_it() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..500 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+
+        let result = tree.delete(&mut pager, &key(250));
+
+        assert!(matches!(result, Ok(Some(v)) if v == value(250)));
+        assert!(matches!(tree.get(&pager, &key(250)), Ok(None)));
+        assert!(matches!(tree.get(&pager, &key(251)), Ok(Some(_))));
+    }
+
+    /// Deleting a missing key returns nothing.
+    #[test]
+    fn delete_missing_key_returns_none() {
+        let (mut pager, mut tree) = create_tree();
+
+        let result = tree.delete(&mut pager, b"missing");
+
+        assert!(matches!(result, Ok(None)));
+    }
+
+    /// Deleting every key leaves a valid tree in which keys can be inserted again.
+    #[test]
+    fn delete_all_keys_then_insert_succeeds() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..500 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+
+        for index in 0..500 {
+            tree.delete(&mut pager, &key(index))
+                .expect("delete should not fail");
+        }
+        tree.insert(&mut pager, &key(42), &value(42))
+            .expect("insert should not fail");
+
+        assert!(matches!(tree.get(&pager, &key(1)), Ok(None)));
+        assert!(matches!(tree.get(&pager, &key(42)), Ok(Some(v)) if v == value(42)));
+    }
+
+    /// A tree can be reopened from its root page after the file is reopened.
+    #[test]
+    fn open_existing_tree_finds_keys() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..500 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        let root = tree.root();
+
+        let pager = Pager::open(pager.into_file()).expect("open should not fail");
+        let tree = BTree::open(&pager, root).expect("open should not fail");
+
+        assert!(matches!(tree.get(&pager, &key(123)), Ok(Some(v)) if v == value(123)));
+    }
+
+    /// A tree created with the reverse comparator iterates its keys in decreasing order, across
+    /// several leaves.
+    #[test]
+    fn reverse_comparator_iterates_in_decreasing_order() {
+        let mut pager = create_pager();
+        let mut tree = BTree::create_with_comparator(&mut pager, Box::new(ReverseComparator))
+            .expect("create_with_comparator should not fail");
+        let mut indexes: Vec<usize> = (0..500).collect();
+        indexes.shuffle(&mut rand::thread_rng());
+        for &index in &indexes {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+
+        let keys: Vec<Vec<u8>> = tree
+            .iter(&pager)
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+
+        assert_eq!(keys, (0..500).rev().map(key).collect::<Vec<_>>());
+        assert!(matches!(tree.get(&pager, &key(321)), Ok(Some(v)) if v == value(321)));
+    }
+
+    /// With the case-insensitive comparator, keys differing only by case are the same key.
+    #[test]
+    fn case_insensitive_comparator_finds_key_in_any_case() {
+        let mut pager = create_pager();
+        let mut tree =
+            BTree::create_with_comparator(&mut pager, Box::new(CaseInsensitiveComparator))
+                .expect("create_with_comparator should not fail");
+
+        tree.insert(&mut pager, b"Apple", b"1")
+            .expect("insert should not fail");
+        let previous = tree.insert(&mut pager, b"APPLE", b"2");
+
+        assert!(matches!(previous, Ok(Some(v)) if v == b"1"));
+        assert!(matches!(tree.get(&pager, b"apple"), Ok(Some(v)) if v == b"2"));
+    }
+
+    /// With the numeric comparator, keys are ordered by value rather than bytewise.
+    #[test]
+    fn numeric_comparator_orders_by_value() {
+        let mut pager = create_pager();
+        let mut tree = BTree::create_with_comparator(&mut pager, Box::new(NumericComparator))
+            .expect("create_with_comparator should not fail");
+        for number in [300u32, 2, 70000, 1] {
+            let bytes = number.to_be_bytes();
+            let start = bytes.iter().take_while(|&&byte| byte == 0).count();
+            tree.insert(&mut pager, &bytes[start..], b"")
+                .expect("insert should not fail");
+        }
+
+        let keys: Vec<Vec<u8>> = tree
+            .range(&pager, [2u8].as_slice()..)
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+
+        assert_eq!(keys, vec![vec![2], vec![1, 44], vec![1, 17, 112]]);
+    }
+
+    /// A user-defined comparator orders the keys of the tree, including when they are bulk
+    /// loaded.
+    #[test]
+    fn user_defined_comparator_orders_keys() {
+        struct LengthComparator;
+
+        impl KeyComparator for LengthComparator {
+            fn name(&self) -> &str {
+                "test.length"
+            }
+
+            fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+                a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+            }
+        }
+
+        let mut pager = create_pager();
+        let entries = (1..100).map(|length| (vec![b'z'; length], value(length)));
+        let tree =
+            BTree::bulk_load_with_comparator(&mut pager, Box::new(LengthComparator), 0.9, entries)
+                .expect("bulk_load_with_comparator should not fail");
+
+        let result = tree.get(&pager, &[b'z'; 42]);
+
+        assert!(matches!(result, Ok(Some(v)) if v == value(42)));
+        assert_eq!(tree.iter(&pager).count(), 99);
+    }
+
+    /// The comparator of the first tree is stored in the file and a tree can't be opened or
+    /// created with another comparator.
+    #[test]
+    fn open_with_other_comparator_fails() {
+        let mut pager = create_pager();
+        let tree = BTree::create_with_comparator(&mut pager, Box::new(ReverseComparator))
+            .expect("create_with_comparator should not fail");
+        let root = tree.root();
+        let pager = Pager::open(pager.into_file()).expect("open should not fail");
+
+        let opened = BTree::open_with_comparator(&pager, root, Box::new(ReverseComparator));
+        let result = BTree::open(&pager, root);
+
+        assert!(opened.is_ok());
+        assert!(matches!(
+            result,
+            Err(BTreeError::ComparatorMismatch { expected, actual })
+                if expected == "rouilledb.reverse-bytewise" && actual == "rouilledb.bytewise"
+        ));
+    }
+
+    /// A second tree in a file must use the comparator of the first one.
+    #[test]
+    fn create_with_other_comparator_fails() {
+        let (mut pager, _) = create_tree();
+
+        let result = BTree::create_with_comparator(&mut pager, Box::new(NumericComparator));
+
+        assert!(matches!(result, Err(BTreeError::ComparatorMismatch { .. })));
+    }
+
+    /// The split index leaves at least one cell in each half.
+    #[test]
+    fn split_index_keeps_cells_on_both_sides() {
+        assert_eq!(split_index(&[100, 1], 0.5, |_| true), 1);
+        assert_eq!(split_index(&[1, 100], 0.5, |_| true), 1);
+        assert_eq!(split_index(&[10, 10, 10, 10], 0.5, |_| true), 2);
+        assert_eq!(split_index(&[10, 10, 10, 10], 1.0, |_| true), 3);
+    }
+
+    fn create_pager() -> Pager<MemoryFile> {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        Pager::create(file, 512).expect("create should not fail")
+    }
+
+    /// Every bulk loaded entry can be found and iterated in order.
+    #[test]
+    fn bulk_load_all_entries_found() {
+        let mut pager = create_pager();
+        let entries = (0..5000).map(|index| (key(index), value(index)));
+
+        let tree = BTree::bulk_load(&mut pager, 0.9, entries).expect("bulk_load should not fail");
+
+        for index in 0..5000 {
+            let result = tree.get(&pager, &key(index));
+            assert!(matches!(result, Ok(Some(v)) if v == value(index)));
+        }
+        let keys: Vec<Vec<u8>> = tree
+            .iter(&pager)
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+        assert_eq!(keys, (0..5000).map(key).collect::<Vec<_>>());
+    }
+
+    /// Bulk loading with a high fill factor uses fewer pages than inserting the same entries.
+    #[test]
+    fn bulk_load_uses_fewer_pages_than_insert() {
+        let (mut inserted_pager, mut inserted_tree) = create_tree();
+        for index in 0..2000 {
+            inserted_tree
+                .insert(&mut inserted_pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        let mut loaded_pager = create_pager();
+
+        BTree::bulk_load(
+            &mut loaded_pager,
+            1.0,
+            (0..2000).map(|index| (key(index), value(index))),
+        )
+        .expect("bulk_load should not fail");
+
+        assert!(loaded_pager.page_count() * 3 < inserted_pager.page_count() * 2);
+    }
+
+    /// Bulk loading no entries creates an empty tree.
+    #[test]
+    fn bulk_load_no_entries_creates_empty_tree() {
+        let mut pager = create_pager();
+
+        let tree = BTree::bulk_load(&mut pager, 0.9, Vec::<(Vec<u8>, Vec<u8>)>::new())
+            .expect("bulk_load should not fail");
+
+        assert_eq!(tree.iter(&pager).count(), 0);
+        assert_eq!(
+            tree.read_node(&pager, tree.root()).ok(),
+            Some(Node::empty_leaf())
+        );
+    }
+
+    /// Bulk loading with a tiny fill factor still builds a valid tree.
+    #[test]
+    fn bulk_load_tiny_fill_factor_succeeds() {
+        let mut pager = create_pager();
+        let entries = (0..300).map(|index| (key(index), value(index)));
+
+        let tree = BTree::bulk_load(&mut pager, 0.01, entries).expect("bulk_load should not fail");
+
+        assert_eq!(tree.iter(&pager).count(), 300);
+        assert!(matches!(tree.get(&pager, &key(299)), Ok(Some(v)) if v == value(299)));
+    }
+
+    /// Bulk loading entries that are not sorted fails.
+    #[test]
+    fn bulk_load_unsorted_entries_fails() {
+        let mut pager = create_pager();
+        let entries = vec![(key(2), value(2)), (key(1), value(1))];
+
+        let result = BTree::bulk_load(&mut pager, 0.9, entries);
+
+        assert!(matches!(result, Err(BTreeError::UnsortedEntries)));
+    }
+
+    /// Bulk loading duplicated keys fails.
+    #[test]
+    fn bulk_load_duplicated_keys_fails() {
+        let mut pager = create_pager();
+        let entries = (0..100).map(|index| (key(index.min(50)), value(index)));
+
+        let result = BTree::bulk_load(&mut pager, 0.9, entries);
+
+        assert!(matches!(result, Err(BTreeError::UnsortedEntries)));
+    }
+
+    /// A bulk loaded tree can be modified like any other tree.
+    #[test]
+    fn bulk_load_then_insert_and_delete_succeeds() {
+        let mut pager = create_pager();
+        let entries = (0..1000).map(|index| (key(index * 2), value(index * 2)));
+        let mut tree =
+            BTree::bulk_load(&mut pager, 1.0, entries).expect("bulk_load should not fail");
+
+        for index in 0..1000 {
+            tree.insert(&mut pager, &key(index * 2 + 1), &value(index * 2 + 1))
+                .expect("insert should not fail");
+        }
+        for index in 0..500 {
+            tree.delete(&mut pager, &key(index))
+                .expect("delete should not fail");
+        }
+
+        let keys: Vec<Vec<u8>> = tree
+            .iter(&pager)
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+        assert_eq!(keys, (500..2000).map(key).collect::<Vec<_>>());
+    }
+
+    /// The separators of a split leaf are truncated to the bytes needed to separate the leaves.
+    #[test]
+    fn insert_long_keys_separators_are_truncated() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..20u8 {
+            let key = [&[b'a' + index][..], &[b'x'; 50]].concat();
+            tree.insert(&mut pager, &key, b"")
+                .expect("insert should not fail");
+        }
+
+        let Ok(Node::Interior { keys, .. }) = tree.read_node(&pager, tree.root()) else {
+            panic!("the root should be an interior node");
+        };
+        assert!(keys.iter().all(|key| key.len() == 1));
+    }
+
+    /// The split index closest to the target that fits is chosen.
+    #[test]
+    fn split_index_closest_index_that_fits() {
+        assert_eq!(split_index(&[10, 10, 10, 10], 1.0, |index| index == 2), 2);
+        assert_eq!(split_index(&[10, 10, 10, 10], 0.1, |index| index == 2), 2);
+        assert_eq!(split_index(&[10; 8], 0.5, |index| index == 6), 6);
+    }
+
+    /// Inserting a key that does not share the prefix of a full leaf splits it correctly.
+    #[test]
+    fn insert_key_breaking_common_prefix_succeeds() {
+        let (mut pager, mut tree) = create_tree();
+        let prefix = vec![b'p'; 100];
+        let keys: Vec<Vec<u8>> = (0..60u8)
+            .map(|index| [prefix.as_slice(), &[index]].concat())
+            .collect();
+        for key in &keys {
+            tree.insert(&mut pager, key, b"")
+                .expect("insert should not fail");
+        }
+
+        tree.insert(&mut pager, b"a", b"")
+            .expect("insert should not fail");
+        tree.insert(&mut pager, b"z", b"")
+            .expect("insert should not fail");
+
+        for key in keys.iter().chain([b"a".to_vec(), b"z".to_vec()].iter()) {
+            assert!(matches!(tree.get(&pager, key), Ok(Some(_))));
+        }
+    }
+
+    /// Keys sharing a long prefix use fewer pages than the same amount of uncompressed data.
+    #[test]
+    fn insert_keys_with_common_prefix_uses_fewer_pages() {
+        let (mut prefixed_pager, mut prefixed_tree) = create_tree();
+        let (mut random_pager, mut random_tree) = create_tree();
+
+        for index in 0..1000 {
+            let prefixed = format!("table/users/{index:06}").into_bytes();
+            let reversed: Vec<u8> = prefixed.iter().rev().copied().collect();
+            prefixed_tree
+                .insert(&mut prefixed_pager, &prefixed, b"")
+                .expect("insert should not fail");
+            random_tree
+                .insert(&mut random_pager, &reversed, b"")
+                .expect("insert should not fail");
+        }
+
+        assert!(prefixed_pager.page_count() < random_pager.page_count());
+    }
+
+    /// Setting a fill factor outside of the supported range fails.
+    #[test]
+    fn set_fill_factor_out_of_range_fails() {
+        let (_, mut tree) = create_tree();
+
+        assert!(matches!(
+            tree.set_fill_factor(0.0),
+            Err(BTreeError::InvalidFillFactor(_))
+        ));
+        assert!(matches!(
+            tree.set_fill_factor(1.5),
+            Err(BTreeError::InvalidFillFactor(_))
+        ));
+        assert!(matches!(
+            tree.set_fill_factor(f64::NAN),
+            Err(BTreeError::InvalidFillFactor(_))
+        ));
+    }
+
+    /// Sequential insertions with a high fill factor use fewer pages than with even splits.
+    #[test]
+    fn insert_sequential_high_fill_factor_uses_fewer_pages() {
+        let (mut even_pager, mut even_tree) = create_tree();
+        let (mut full_pager, mut full_tree) = create_tree();
+        full_tree
+            .set_fill_factor(0.95)
+            .expect("set_fill_factor should not fail");
+
+        for index in 0..2000 {
+            even_tree
+                .insert(&mut even_pager, &key(index), &value(index))
+                .expect("insert should not fail");
+            full_tree
+                .insert(&mut full_pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+
+        assert!(full_pager.page_count() * 3 < even_pager.page_count() * 2);
+        for index in 0..2000 {
+            let result = full_tree.get(&full_pager, &key(index));
+            assert!(matches!(result, Ok(Some(v)) if v == value(index)));
+        }
+    }
+
+    /// Deleting most keys merges nodes and the freed pages are reused by later insertions.
+    #[test]
+    fn delete_most_keys_frees_pages() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..2000 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        let page_count = pager.page_count();
+
+        for index in 10..2000 {
+            tree.delete(&mut pager, &key(index))
+                .expect("delete should not fail");
+        }
+        for index in 0..1000 {
+            tree.insert(&mut pager, &key(index + 5000), &value(index))
+                .expect("insert should not fail");
+        }
+
+        assert_eq!(pager.page_count(), page_count);
+    }
+
+    /// Deleting every key collapses the tree back to a single leaf.
+    #[test]
+    fn delete_all_keys_collapses_root() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..2000 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+
+        for index in 0..2000 {
+            tree.delete(&mut pager, &key(index))
+                .expect("delete should not fail");
+        }
+
+        assert_eq!(
+            tree.read_node(&pager, tree.root()).ok(),
+            Some(Node::empty_leaf())
+        );
+    }
+
+    /// Deleting keys in random order keeps the remaining keys reachable and ordered.
+    #[test]
+    fn delete_random_keys_keeps_remaining_keys() {
+        let (mut pager, mut tree) = create_tree();
+        let mut indexes: Vec<usize> = (0..2000).collect();
+        for &index in &indexes {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        indexes.shuffle(&mut rand::thread_rng());
+        let (deleted, kept) = indexes.split_at(1500);
+
+        for &index in deleted {
+            let result = tree.delete(&mut pager, &key(index));
+            assert!(matches!(result, Ok(Some(v)) if v == value(index)));
+        }
+
+        let mut kept = kept.to_vec();
+        kept.sort();
+        let keys: Vec<Vec<u8>> = tree
+            .iter(&pager)
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+        assert_eq!(
+            keys,
+            kept.iter().map(|&index| key(index)).collect::<Vec<_>>()
+        );
+        for &index in deleted {
+            assert!(matches!(tree.get(&pager, &key(index)), Ok(None)));
+        }
+    }
+
+    /// Keys inserted in increasing order go to the remembered right-most leaf, which stays the
+    /// right-most leaf of the tree across its splits.
+    #[test]
+    fn insert_increasing_keys_uses_rightmost_leaf() {
+        let (mut pager, mut tree) = create_tree();
+        let mut remembered = 0;
+
+        for index in 0..3000 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+            // The leaf is forgotten when it is split, until the next insertion.
+            if let Some(rightmost) = &tree.rightmost {
+                let expected = tree
+                    .find_rightmost(&pager)
+                    .expect("find_rightmost should not fail");
+                assert_eq!(rightmost.page, expected.page);
+                assert_eq!(rightmost.low, expected.low);
+                remembered += 1;
+            }
+        }
+
+        assert!(remembered > 2800);
+        let report = tree.verify(&pager).expect("verify should not fail");
+        assert!(report.is_valid(), "{:?}", report.violations);
+        assert_eq!(report.entry_count, 3000);
+    }
+
+    /// Appends interleaved with deletions and insertions of smaller keys keep the tree
+    /// consistent.
+    #[test]
+    fn insert_appends_mixed_with_other_operations_keeps_tree_valid() {
+        let (mut pager, mut tree) = create_tree();
+        let mut expected = std::collections::BTreeMap::new();
+
+        for index in 0..3000 {
+            let appended = key(index * 2 + 10_000);
+            tree.insert(&mut pager, &appended, &value(index))
+                .expect("insert should not fail");
+            expected.insert(appended, value(index));
+            if index % 3 == 0 {
+                let smaller = key(index);
+                tree.insert(&mut pager, &smaller, &value(index))
+                    .expect("insert should not fail");
+                expected.insert(smaller, value(index));
+            }
+            if index % 7 == 0 {
+                let deleted = key(index + 10_000);
+                tree.delete(&mut pager, &deleted)
+                    .expect("delete should not fail");
+                expected.remove(&deleted);
+            }
+        }
+
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = tree
+            .iter(&pager)
+            .map(|entry| entry.expect("iteration should not fail"))
+            .collect();
+        assert_eq!(entries, expected.into_iter().collect::<Vec<_>>());
+        assert!(tree
+            .verify(&pager)
+            .expect("verify should not fail")
+            .is_valid());
+    }
+}
@@ -0,0 +1,367 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+use super::comparator::KeyComparator;
+use super::node::Node;
+use super::BTreeError;
+
+/// Describes an inconsistency found by [BTree::verify](super::BTree::verify).
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// Indicates that a node refers to the header page or to a page past the end of the file.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the invalid page.
+    #[error("The page {0} is not a valid data page.")]
+    InvalidPage(PageId),
+
+    /// Indicates that a page does not contain a valid node.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page.
+    #[error("The page {0} does not contain a valid B+tree node.")]
+    CorruptedPage(PageId),
+
+    /// Indicates that a page does not match its checksum: it was damaged since it was written.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page.
+    #[error("The page {0} does not match its checksum.")]
+    ChecksumMismatch(PageId),
+
+    /// Indicates that a page is reached more than once from the root.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page.
+    #[error("The page {0} is referenced more than once.")]
+    SharedPage(PageId),
+
+    /// Indicates that the keys of a node are not in strictly increasing order.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page.
+    #[error("The keys of the page {0} are not sorted.")]
+    UnsortedKeys(PageId),
+
+    /// Indicates that a node holds a key outside of the range given by the separator keys of its
+    /// parent.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page.
+    #[error("The page {0} holds a key outside of the range of its parent.")]
+    KeyOutOfRange(PageId),
+
+    /// Indicates that a leaf is not at the same depth as the first leaf.
+    ///
+    /// # Fields
+    /// - `page` - The identifier of the leaf.
+    /// - `depth` - The depth of the leaf.
+    /// - `expected` - The depth of the first leaf.
+    #[error("The leaf {page} is at depth {depth}, but the first leaf is at depth {expected}.")]
+    UnevenDepth {
+        page: PageId,
+        depth: usize,
+        expected: usize,
+    },
+
+    /// Indicates that the next leaf of a leaf is not the leaf that follows it in key order.
+    ///
+    /// # Fields
+    /// - `page` - The identifier of the leaf.
+    /// - `next` - The next leaf stored in the page.
+    /// - `expected` - The leaf that follows it in key order.
+    #[error("The leaf {page} links to {next:?}, but the next leaf is {expected:?}.")]
+    BrokenSiblingLink {
+        page: PageId,
+        next: Option<PageId>,
+        expected: Option<PageId>,
+    },
+
+    /// Indicates that a page of the tree is also in the list of free pages.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page.
+    #[error("The page {0} is used by the tree but is also free.")]
+    FreePage(PageId),
+}
+
+/// Describes the result of [BTree::verify](super::BTree::verify): the shape of the tree and the
+/// inconsistencies that were found.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VerifyReport {
+    /// The number of levels of the tree, including the leaves.
+    pub depth: usize,
+    /// The number of pages reached from the root.
+    pub page_count: usize,
+    /// The number of leaves reached from the root.
+    pub leaf_count: usize,
+    /// The number of entries in the leaves.
+    pub entry_count: usize,
+    /// The inconsistencies found, in the order the pages were visited.
+    pub violations: Vec<Violation>,
+}
+
+impl VerifyReport {
+    /// Returns `true` if no inconsistency was found.
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Walks the tree rooted at `root` and reports its inconsistencies.
+///
+/// # Errors
+///
+/// This function will return an error if a page can't be read.
+pub(super) fn verify<F: File>(
+    pager: &Pager<F>,
+    root: PageId,
+    comparator: &dyn KeyComparator,
+) -> Result<VerifyReport, BTreeError> {
+    let mut walker = Walker {
+        pager,
+        comparator,
+        report: VerifyReport::default(),
+        visited: HashSet::new(),
+        leaves: Vec::new(),
+    };
+    walker.visit(root, None, None, 1)?;
+
+    for (index, (page, next)) in walker.leaves.iter().enumerate() {
+        let expected = walker.leaves.get(index + 1).map(|(page, _)| *page);
+        if *next != expected {
+            walker.report.violations.push(Violation::BrokenSiblingLink {
+                page: *page,
+                next: *next,
+                expected,
+            });
+        }
+    }
+
+    let mut free_pages = pager.free_pages()?;
+    free_pages.sort_unstable();
+    let mut used: Vec<PageId> = walker.visited.iter().copied().collect();
+    used.sort_unstable();
+    for page in used {
+        if free_pages.binary_search(&page).is_ok() {
+            walker.report.violations.push(Violation::FreePage(page));
+        }
+    }
+
+    Ok(walker.report)
+}
+
+/// The state of a walk through a tree.
+struct Walker<'a, F: File> {
+    pager: &'a Pager<F>,
+    comparator: &'a dyn KeyComparator,
+    report: VerifyReport,
+    visited: HashSet<PageId>,
+    /// The leaves in key order, with the next leaf stored in each of them.
+    leaves: Vec<(PageId, Option<PageId>)>,
+}
+
+impl<F: File> Walker<'_, F> {
+    /// Checks the subtree rooted at `id`, whose keys must be greater or equal to `low` and smaller
+    /// than `high`. `depth` is the level of the node, the root being at level `1`.
+    fn visit(
+        &mut self,
+        id: PageId,
+        low: Option<&[u8]>,
+        high: Option<&[u8]>,
+        depth: usize,
+    ) -> Result<(), BTreeError> {
+        if id == 0 || id >= self.pager.page_count() {
+            self.report.violations.push(Violation::InvalidPage(id));
+            return Ok(());
+        }
+        if !self.visited.insert(id) {
+            self.report.violations.push(Violation::SharedPage(id));
+            return Ok(());
+        }
+        let node = match Node::read(self.pager, id) {
+            Ok(node) => node,
+            Err(BTreeError::CorruptedPage(_)) => {
+                self.report.violations.push(Violation::CorruptedPage(id));
+                return Ok(());
+            }
+            Err(BTreeError::ChecksumMismatch(_)) => {
+                self.report.violations.push(Violation::ChecksumMismatch(id));
+                return Ok(());
+            }
+            Err(error) => return Err(error),
+        };
+        self.report.page_count += 1;
+
+        let keys: Vec<&[u8]> = match &node {
+            Node::Leaf { entries, .. } => entries.iter().map(|(key, _)| key.as_slice()).collect(),
+            Node::Interior { keys, .. } => keys.iter().map(Vec::as_slice).collect(),
+        };
+        let sorted = keys
+            .windows(2)
+            .all(|pair| self.comparator.compare(pair[0], pair[1]) == Ordering::Less);
+        if !sorted {
+            self.report.violations.push(Violation::UnsortedKeys(id));
+        }
+        let in_range = keys.iter().all(|key| {
+            low.is_none_or(|low| self.comparator.compare(key, low) != Ordering::Less)
+                && high.is_none_or(|high| self.comparator.compare(key, high) == Ordering::Less)
+        });
+        if !in_range {
+            self.report.violations.push(Violation::KeyOutOfRange(id));
+        }
+
+        match node {
+            Node::Leaf { entries, next } => {
+                if self.report.leaf_count == 0 {
+                    self.report.depth = depth;
+                } else if depth != self.report.depth {
+                    self.report.violations.push(Violation::UnevenDepth {
+                        page: id,
+                        depth,
+                        expected: self.report.depth,
+                    });
+                }
+                self.report.leaf_count += 1;
+                self.report.entry_count += entries.len();
+                self.leaves.push((id, next));
+            }
+            Node::Interior { keys, children } => {
+                for (index, child) in children.iter().enumerate() {
+                    let child_low = if index == 0 {
+                        low
+                    } else {
+                        Some(keys[index - 1].as_slice())
+                    };
+                    let child_high = keys.get(index).map(Vec::as_slice).or(high);
+                    self.visit(*child, child_low, child_high, depth + 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::BTree;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_tree(count: usize) -> (Pager<MemoryFile>, BTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = BTree::create(&mut pager).expect("create should not fail");
+        for i in 0..count {
+            tree.insert(&mut pager, format!("key-{i:06}").as_bytes(), &[0; 20])
+                .expect("insert should not fail");
+        }
+        (pager, tree)
+    }
+
+    /// Returns the leaves of the tree, in key order.
+    fn leaves(pager: &Pager<MemoryFile>, id: PageId) -> Vec<PageId> {
+        match Node::read(pager, id).expect("read should not fail") {
+            Node::Leaf { .. } => vec![id],
+            Node::Interior { children, .. } => children
+                .into_iter()
+                .flat_map(|child| leaves(pager, child))
+                .collect(),
+        }
+    }
+
+    /// A tree built by insertions and deletions has no inconsistency.
+    #[test]
+    fn verify_valid_tree_reports_no_violation() {
+        let (mut pager, mut tree) = create_tree(2000);
+        for i in (0..2000).step_by(3) {
+            tree.delete(&mut pager, format!("key-{i:06}").as_bytes())
+                .expect("delete should not fail");
+        }
+
+        let report = tree.verify(&pager).expect("verify should not fail");
+
+        assert!(report.is_valid(), "{:?}", report.violations);
+        assert_eq!(report.entry_count, 1333);
+        assert_eq!(report.leaf_count, leaves(&pager, tree.root()).len());
+        assert!(report.depth > 1);
+    }
+
+    /// A leaf linking to the wrong sibling is reported.
+    #[test]
+    fn verify_broken_sibling_link_is_reported() {
+        let (mut pager, tree) = create_tree(500);
+        let leaves = leaves(&pager, tree.root());
+        let Node::Leaf { entries, .. } =
+            Node::read(&pager, leaves[0]).expect("read should not fail")
+        else {
+            panic!("the page should be a leaf");
+        };
+        let node = Node::Leaf {
+            entries,
+            next: Some(leaves[2]),
+        };
+        pager
+            .write_page(leaves[0], &node.encode(512))
+            .expect("write should not fail");
+
+        let report = tree.verify(&pager).expect("verify should not fail");
+
+        assert_eq!(
+            report.violations,
+            vec![Violation::BrokenSiblingLink {
+                page: leaves[0],
+                next: Some(leaves[2]),
+                expected: Some(leaves[1]),
+            }]
+        );
+    }
+
+    /// A leaf that was freed while still referenced by its parent is reported as damaged and
+    /// free.
+    #[test]
+    fn verify_freed_leaf_is_reported() {
+        let (mut pager, tree) = create_tree(500);
+        let leaf = leaves(&pager, tree.root())[1];
+
+        pager.free_page(leaf).expect("free should not fail");
+        let report = tree.verify(&pager).expect("verify should not fail");
+
+        assert!(report
+            .violations
+            .contains(&Violation::ChecksumMismatch(leaf)));
+        assert!(report.violations.contains(&Violation::FreePage(leaf)));
+    }
+
+    /// A page damaged since it was written is reported even if it still decodes to a valid node,
+    /// and the rest of the tree is still walked.
+    #[test]
+    fn verify_damaged_page_is_reported() {
+        let (mut pager, tree) = create_tree(500);
+        let leaves = leaves(&pager, tree.root());
+        let mut page = pager.read_page(leaves[1]).expect("read should not fail");
+        // The last byte of the page is unused, so the node still decodes without its checksum.
+        page[511] ^= 0xff;
+        pager
+            .write_page(leaves[1], &page)
+            .expect("write should not fail");
+
+        let report = tree.verify(&pager).expect("verify should not fail");
+
+        assert_eq!(
+            report.violations.first(),
+            Some(&Violation::ChecksumMismatch(leaves[1]))
+        );
+        assert!(!report
+            .violations
+            .contains(&Violation::CorruptedPage(leaves[1])));
+        assert_eq!(report.leaf_count, leaves.len() - 1);
+    }
+}
@@ -0,0 +1,253 @@
+/// The CRC32C polynomial (Castagnoli), in the reflected bit order.
+const CRC32C_POLYNOMIAL: u32 = 0x82f6_3b78;
+
+/// The CRC32C of each byte, used by the software implementation.
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const PRIME64_1: u64 = 0x9e37_79b1_85eb_ca87;
+const PRIME64_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const PRIME64_3: u64 = 0x1656_67b1_9e37_79f9;
+const PRIME64_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const PRIME64_5: u64 = 0x27d4_eb2f_1656_67c5;
+
+/// Returns the CRC32C (Castagnoli) checksum of a byte string, as computed by the `crc32`
+/// instructions of SSE 4.2 and of ARMv8, and by iSCSI, ext4 or RocksDB.
+///
+/// The checksum is computed with the CRC instructions of the processor when it has them, detected
+/// at run time, and with a lookup table otherwise. Both give the same checksum, so it can be
+/// stored in a file read on another machine.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::checksum;
+///
+/// assert_eq!(checksum::crc32c(b"123456789"), 0xe306_9283);
+/// assert_eq!(
+///     checksum::crc32c_extend(checksum::crc32c(b"1234"), b"56789"),
+///     checksum::crc32c(b"123456789")
+/// );
+/// ```
+pub fn crc32c(data: &[u8]) -> u32 {
+    crc32c_extend(0, data)
+}
+
+/// Returns the CRC32C checksum of the bytes whose checksum is `crc` followed by `data`, so a byte
+/// string can be checksummed in pieces.
+pub fn crc32c_extend(crc: u32, data: &[u8]) -> u32 {
+    !crc32c_update(!crc, data)
+}
+
+/// Updates the state of a CRC32C computation with `data`, with the fastest implementation
+/// available.
+fn crc32c_update(crc: u32, data: &[u8]) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    if std::is_x86_feature_detected!("sse4.2") {
+        // SAFETY: the processor supports the SSE 4.2 instructions.
+        return unsafe { crc32c_update_sse42(crc, data) };
+    }
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("crc") {
+        // SAFETY: the processor supports the CRC instructions.
+        return unsafe { crc32c_update_armv8(crc, data) };
+    }
+    crc32c_update_software(crc, data)
+}
+
+/// Updates the state of a CRC32C computation with the `crc32` instructions of SSE 4.2, 8 bytes at
+/// a time.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_update_sse42(crc: u32, data: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u64, _mm_crc32_u8};
+
+    let mut words = data.chunks_exact(8);
+    let mut crc = u64::from(crc);
+    for word in &mut words {
+        let word = u64::from_le_bytes(word.try_into().expect("slice should be 8 bytes"));
+        crc = _mm_crc32_u64(crc, word);
+    }
+    let mut crc = crc as u32;
+    for &byte in words.remainder() {
+        crc = _mm_crc32_u8(crc, byte);
+    }
+    crc
+}
+
+/// Updates the state of a CRC32C computation with the CRC instructions of ARMv8, 8 bytes at a
+/// time.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "crc")]
+unsafe fn crc32c_update_armv8(crc: u32, data: &[u8]) -> u32 {
+    use std::arch::aarch64::{__crc32cb, __crc32cd};
+
+    let mut words = data.chunks_exact(8);
+    let mut crc = crc;
+    for word in &mut words {
+        let word = u64::from_le_bytes(word.try_into().expect("slice should be 8 bytes"));
+        crc = __crc32cd(crc, word);
+    }
+    for &byte in words.remainder() {
+        crc = __crc32cb(crc, byte);
+    }
+    crc
+}
+
+/// Updates the state of a CRC32C computation a byte at a time, with a lookup table.
+fn crc32c_update_software(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, &byte| {
+        CRC32C_TABLE[usize::from(crc as u8 ^ byte)] ^ (crc >> 8)
+    })
+}
+
+/// Computes the CRC32C of each byte.
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC32C_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
+
+/// Returns the XXH64 hash of a byte string, with a `seed` giving independent hashes of the same
+/// bytes.
+///
+/// XXH64 reads 32 bytes at a time in four independent lanes, so it is much faster than
+/// [hash64](super::hash64) on long byte strings, such as the blocks of a backup. It is stable
+/// across runs and platforms, and matches the reference implementation.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::checksum;
+///
+/// assert_eq!(checksum::xxhash64(b"", 0), 0xef46_db37_51d8_e999);
+/// assert_ne!(checksum::xxhash64(b"block", 0), checksum::xxhash64(b"block", 1));
+/// ```
+pub fn xxhash64(data: &[u8], seed: u64) -> u64 {
+    let mut stripes = data.chunks_exact(32);
+    let mut hash = if data.len() >= 32 {
+        let mut lanes = [
+            seed.wrapping_add(PRIME64_1).wrapping_add(PRIME64_2),
+            seed.wrapping_add(PRIME64_2),
+            seed,
+            seed.wrapping_sub(PRIME64_1),
+        ];
+        for stripe in &mut stripes {
+            for (lane, word) in lanes.iter_mut().zip(stripe.chunks_exact(8)) {
+                *lane = xxhash64_round(*lane, read_u64(word));
+            }
+        }
+        let mut hash = lanes[0]
+            .rotate_left(1)
+            .wrapping_add(lanes[1].rotate_left(7))
+            .wrapping_add(lanes[2].rotate_left(12))
+            .wrapping_add(lanes[3].rotate_left(18));
+        for lane in lanes {
+            hash = (hash ^ xxhash64_round(0, lane))
+                .wrapping_mul(PRIME64_1)
+                .wrapping_add(PRIME64_4);
+        }
+        hash
+    } else {
+        seed.wrapping_add(PRIME64_5)
+    };
+    hash = hash.wrapping_add(data.len() as u64);
+
+    let mut words = stripes.remainder().chunks_exact(8);
+    for word in &mut words {
+        hash ^= xxhash64_round(0, read_u64(word));
+        hash = hash
+            .rotate_left(27)
+            .wrapping_mul(PRIME64_1)
+            .wrapping_add(PRIME64_4);
+    }
+    let mut bytes = words.remainder();
+    if bytes.len() >= 4 {
+        let word = u32::from_le_bytes(bytes[..4].try_into().expect("slice should be 4 bytes"));
+        hash ^= u64::from(word).wrapping_mul(PRIME64_1);
+        hash = hash
+            .rotate_left(23)
+            .wrapping_mul(PRIME64_2)
+            .wrapping_add(PRIME64_3);
+        bytes = &bytes[4..];
+    }
+    for &byte in bytes {
+        hash ^= u64::from(byte).wrapping_mul(PRIME64_5);
+        hash = hash.rotate_left(11).wrapping_mul(PRIME64_1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME64_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME64_3);
+    hash ^ (hash >> 32)
+}
+
+/// Mixes 8 bytes of input into a lane of XXH64.
+fn xxhash64_round(lane: u64, input: u64) -> u64 {
+    lane.wrapping_add(input.wrapping_mul(PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME64_1)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().expect("slice should be 8 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    /// The checksums match the published test vectors, since they may be stored in files.
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32c(b""), 0);
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c(&[0u8; 32]), 0x8a91_36aa);
+        assert_eq!(crc32c(&[0xffu8; 32]), 0x62a8_ab43);
+        assert_eq!(xxhash64(b"", 0), 0xef46_db37_51d8_e999);
+        assert_eq!(xxhash64(b"a", 0), 0xd24e_c4f1_a98c_6e5b);
+        assert_eq!(xxhash64(b"abc", 0), 0x44bc_2cf5_ad77_0999);
+        assert_eq!(
+            xxhash64(b"Nobody inspects the spammish repetition", 0),
+            0xfbce_a83c_8a37_8bf1
+        );
+    }
+
+    /// The checksum computed by the processor matches the software one, for every length and
+    /// alignment, and a checksum can be computed in pieces.
+    #[test]
+    fn crc32c_implementations_agree() {
+        let mut rng = rand::thread_rng();
+        let data: Vec<u8> = (0..1024).map(|_| rng.gen()).collect();
+
+        for _ in 0..1000 {
+            let start = rng.gen_range(0..data.len());
+            let end = rng.gen_range(start..=data.len());
+            let split = rng.gen_range(start..=end);
+            let bytes = &data[start..end];
+            let expected = !crc32c_update_software(!0, bytes);
+
+            assert_eq!(crc32c(bytes), expected);
+            assert_eq!(
+                crc32c_extend(crc32c(&data[start..split]), &data[split..end]),
+                expected
+            );
+        }
+    }
+}
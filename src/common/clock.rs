@@ -0,0 +1,225 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Gives the current time, in milliseconds since the Unix epoch.
+///
+/// The time may go backward, like the time of the system when it is adjusted: a [HybridClock]
+/// reading it still gives increasing timestamps. Any closure returning the time implements the
+/// trait, so a test can control the time a structure reads.
+pub trait Clock: Send + Sync {
+    /// Returns the current time, in milliseconds since the Unix epoch.
+    fn now(&self) -> u64;
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> u64 + Send + Sync,
+{
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// The [Clock] of the system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+}
+
+/// A [Clock] whose time only changes when it is set or advanced. Its clones share the same time,
+/// so a test can keep one to move the time of a structure reading another.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Creates a clock at the time `now`, in milliseconds since the Unix epoch.
+    pub fn new(now: u64) -> Self {
+        ManualClock {
+            now: Arc::new(AtomicU64::new(now)),
+        }
+    }
+
+    /// Sets the time of the clock, which may go backward.
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::Relaxed);
+    }
+
+    /// Moves the time of the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let millis = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        self.now
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |now| {
+                Some(now.saturating_add(millis))
+            })
+            .expect("the update should always return a value");
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::Relaxed)
+    }
+}
+
+/// Gives timestamps that always increase, even when the time of its [Clock] goes backward, like a
+/// hybrid logical clock.
+///
+/// A timestamp holds a physical time, in milliseconds since the Unix epoch, in its upper bits and
+/// a logical counter in its [HybridClock::LOGICAL_BITS] lower bits, so the timestamps are ordered
+/// by time. A new timestamp is the current time with a counter of `0` if that is later than the
+/// last timestamp, and the last timestamp plus one otherwise: when the time stands still or goes
+/// backward, the counter goes on from the last timestamp. A timestamp given by another source,
+/// such as the last commit stored in a file, is passed to [HybridClock::observe] so the next ones
+/// follow it.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::clock::{HybridClock, ManualClock};
+///
+/// let time = ManualClock::new(1_000);
+/// let clock = HybridClock::new(time.clone());
+/// let first = clock.timestamp();
+/// time.set(500);
+/// let second = clock.timestamp();
+///
+/// assert!(second > first);
+/// assert_eq!(HybridClock::physical(second), 1_000);
+/// assert_eq!(HybridClock::logical(second), 1);
+/// ```
+pub struct HybridClock {
+    clock: Box<dyn Clock>,
+    last: AtomicU64,
+}
+
+impl HybridClock {
+    /// Number of lower bits of a timestamp holding its logical counter.
+    pub const LOGICAL_BITS: u32 = 16;
+
+    /// Creates a clock reading the time of `clock`, that has given no timestamp yet.
+    pub fn new<C: Clock + 'static>(clock: C) -> Self {
+        HybridClock {
+            clock: Box::new(clock),
+            last: AtomicU64::new(0),
+        }
+    }
+
+    /// Replaces the clock giving the time. The next timestamps still follow the previous ones.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Returns a new timestamp, later than every timestamp given or observed before.
+    pub fn timestamp(&self) -> u64 {
+        let now = self.physical_now();
+        let previous = self
+            .last
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |last| {
+                Some(now.max(last.saturating_add(1)))
+            })
+            .expect("the update should always return a value");
+        now.max(previous.saturating_add(1))
+    }
+
+    /// Records a timestamp given by another source, so the next timestamps are later than it.
+    pub fn observe(&self, timestamp: u64) {
+        self.last.fetch_max(timestamp, Ordering::AcqRel);
+    }
+
+    /// Returns the last timestamp given or observed, `0` if there is none.
+    pub fn last(&self) -> u64 {
+        self.last.load(Ordering::Acquire)
+    }
+
+    /// Returns the current time, in milliseconds since the Unix epoch, never earlier than the time
+    /// of a previous timestamp or of a previous call, even if the time of the clock went backward.
+    pub fn now_millis(&self) -> u64 {
+        let now = self.physical_now();
+        let previous = self.last.fetch_max(now, Ordering::AcqRel);
+        Self::physical(now.max(previous))
+    }
+
+    /// Returns the physical time of a timestamp, in milliseconds since the Unix epoch.
+    pub fn physical(timestamp: u64) -> u64 {
+        timestamp >> Self::LOGICAL_BITS
+    }
+
+    /// Returns the logical counter of a timestamp.
+    pub fn logical(timestamp: u64) -> u64 {
+        timestamp & ((1 << Self::LOGICAL_BITS) - 1)
+    }
+
+    /// Returns the current time of the clock as a timestamp with a counter of `0`.
+    fn physical_now(&self) -> u64 {
+        let max_physical = u64::MAX >> Self::LOGICAL_BITS;
+        self.clock.now().min(max_physical) << Self::LOGICAL_BITS
+    }
+}
+
+impl Default for HybridClock {
+    /// Creates a clock reading the time of the [SystemClock].
+    fn default() -> Self {
+        HybridClock::new(SystemClock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The timestamps increase when the time stands still or goes backward, and are back to the
+    /// time of the clock with a counter of `0` once it has caught up.
+    #[test]
+    fn timestamp_increases_when_time_goes_backward() {
+        let time = ManualClock::new(10);
+        let clock = HybridClock::new(time.clone());
+
+        let first = clock.timestamp();
+        let second = clock.timestamp();
+        time.set(5);
+        let third = clock.timestamp();
+        let now = clock.now_millis();
+        time.advance(Duration::from_millis(10));
+        let fourth = clock.timestamp();
+
+        assert_eq!(
+            (HybridClock::physical(first), HybridClock::logical(first)),
+            (10, 0)
+        );
+        assert_eq!(
+            (HybridClock::physical(second), HybridClock::logical(second)),
+            (10, 1)
+        );
+        assert_eq!(
+            (HybridClock::physical(third), HybridClock::logical(third)),
+            (10, 2)
+        );
+        assert_eq!(now, 10);
+        assert_eq!(
+            (HybridClock::physical(fourth), HybridClock::logical(fourth)),
+            (15, 0)
+        );
+    }
+
+    /// The timestamps follow an observed timestamp, and a clock that always reads `0` counts from
+    /// it.
+    #[test]
+    fn timestamp_follows_observed_timestamps() {
+        let clock = HybridClock::new(|| 0);
+        clock.observe(41);
+        clock.observe(7);
+
+        assert_eq!(clock.last(), 41);
+        assert_eq!(clock.timestamp(), 42);
+        assert_eq!(clock.timestamp(), 43);
+    }
+}
@@ -0,0 +1,178 @@
+use super::varint;
+
+/// What a write records for a key: a live value, a deletion or a merge operand.
+///
+/// The envelope is shared by the structures recording writes, such as the memtable and the tables
+/// of an [LsmTree](crate::lsm::LsmTree), the versions of a [MvccTree](crate::mvcc::MvccTree) and
+/// the records of the prepared transactions of a [Database](crate::db::Database), so a deletion
+/// is a tombstone everywhere, and not a missing or empty value. Each kind is stored as the same
+/// tag in all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Envelope {
+    /// The key has this value.
+    Value(Vec<u8>),
+    /// The key was deleted. The tombstone hides the older values of the key.
+    Tombstone,
+    /// A merge operand to apply to the older value of the key. Consecutive operands are combined
+    /// into one.
+    Merge(Vec<u8>),
+}
+
+impl Envelope {
+    /// Tag of a [Envelope::Tombstone].
+    pub const TOMBSTONE_TAG: u8 = 0;
+
+    /// Tag of a [Envelope::Value].
+    pub const VALUE_TAG: u8 = 1;
+
+    /// Tag of a [Envelope::Merge].
+    pub const MERGE_TAG: u8 = 2;
+
+    /// Returns the tag storing the kind of the envelope.
+    pub fn tag(&self) -> u8 {
+        match self {
+            Envelope::Value(_) => Self::VALUE_TAG,
+            Envelope::Tombstone => Self::TOMBSTONE_TAG,
+            Envelope::Merge(_) => Self::MERGE_TAG,
+        }
+    }
+
+    /// Returns the envelope of a tag and its payload, or `None` if the tag is unknown or if a
+    /// tombstone has a payload.
+    pub fn from_tag(tag: u8, payload: Vec<u8>) -> Option<Self> {
+        match tag {
+            Self::VALUE_TAG => Some(Envelope::Value(payload)),
+            Self::TOMBSTONE_TAG if payload.is_empty() => Some(Envelope::Tombstone),
+            Self::MERGE_TAG => Some(Envelope::Merge(payload)),
+            _ => None,
+        }
+    }
+
+    /// Returns the bytes stored with the envelope: the value or the operand, or nothing for a
+    /// tombstone.
+    pub fn payload(&self) -> &[u8] {
+        match self {
+            Envelope::Value(bytes) | Envelope::Merge(bytes) => bytes,
+            Envelope::Tombstone => &[],
+        }
+    }
+
+    /// Returns `true` if the envelope records a deletion.
+    pub fn is_tombstone(&self) -> bool {
+        matches!(self, Envelope::Tombstone)
+    }
+}
+
+/// An [Envelope] numbered with the sequence number of its write. The writes of a key are ordered
+/// by their sequence numbers, which increase with each write, so the most recent one wins
+/// wherever the versions are merged, and a reader at a sequence number ignores the later writes.
+///
+/// A version is encoded as the tag of its envelope, its sequence number and the length of its
+/// payload, as varints, then the payload.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::envelope::{Envelope, Versioned};
+///
+/// let deleted = Versioned::new(7, Envelope::Tombstone);
+/// let mut bytes = Vec::new();
+/// deleted.encode(&mut bytes);
+/// assert_eq!(bytes, vec![Envelope::TOMBSTONE_TAG, 7, 0]);
+///
+/// let (decoded, length) = Versioned::decode(&bytes).expect("decode should not fail");
+/// assert_eq!((decoded, length), (deleted, 3));
+/// assert!(!Versioned::new(8, Envelope::Value(b"new".to_vec())).is_visible_at(7));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Versioned {
+    /// The sequence number of the write.
+    pub sequence: u64,
+    /// What the write records.
+    pub envelope: Envelope,
+}
+
+impl Versioned {
+    /// Creates the version written with the sequence number `sequence`.
+    pub fn new(sequence: u64, envelope: Envelope) -> Self {
+        Versioned { sequence, envelope }
+    }
+
+    /// Returns `true` if a reader at the sequence number `sequence` sees the version: if it was
+    /// written at or before it.
+    pub fn is_visible_at(&self, sequence: u64) -> bool {
+        self.sequence <= sequence
+    }
+
+    /// Returns the number of bytes of the encoded version.
+    pub fn encoded_len(&self) -> usize {
+        let payload = self.envelope.payload();
+        1 + varint::encoded_len(self.sequence)
+            + varint::encoded_len(payload.len() as u64)
+            + payload.len()
+    }
+
+    /// Appends the encoded version to `bytes`.
+    pub fn encode(&self, bytes: &mut Vec<u8>) {
+        let payload = self.envelope.payload();
+        bytes.push(self.envelope.tag());
+        varint::encode_u64(self.sequence, bytes);
+        varint::encode_u64(payload.len() as u64, bytes);
+        bytes.extend_from_slice(payload);
+    }
+
+    /// Decodes the version at the start of `bytes`, and returns it with the number of bytes it
+    /// took, or `None` if `bytes` ends before it does or if its tag is unknown.
+    pub fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+        let (&tag, rest) = bytes.split_first()?;
+        let (sequence, sequence_len) = varint::decode_u64(rest)?;
+        let (payload_len, payload_len_len) = varint::decode_u64(&rest[sequence_len..])?;
+        let start = 1 + sequence_len + payload_len_len;
+        let end = start.checked_add(usize::try_from(payload_len).ok()?)?;
+        let payload = bytes.get(start..end)?.to_vec();
+        let envelope = Envelope::from_tag(tag, payload)?;
+        Some((Versioned::new(sequence, envelope), end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each kind of envelope is decoded back as encoded, with its sequence number, and takes the
+    /// bytes it announces.
+    #[test]
+    fn decode_encoded_versions_round_trips() {
+        let versions = [
+            Versioned::new(0, Envelope::Value(b"value".to_vec())),
+            Versioned::new(300, Envelope::Tombstone),
+            Versioned::new(u64::MAX, Envelope::Merge(vec![7; 200])),
+        ];
+        let mut bytes = Vec::new();
+        for version in &versions {
+            version.encode(&mut bytes);
+        }
+
+        let mut offset = 0;
+        for version in &versions {
+            let (decoded, length) =
+                Versioned::decode(&bytes[offset..]).expect("decode should not fail");
+            assert_eq!(&decoded, version);
+            assert_eq!(length, version.encoded_len());
+            offset += length;
+        }
+        assert_eq!(offset, bytes.len());
+    }
+
+    /// A truncated version, an unknown tag or a tombstone with a payload is rejected.
+    #[test]
+    fn decode_invalid_versions_fails() {
+        let mut bytes = Vec::new();
+        Versioned::new(1, Envelope::Value(b"value".to_vec())).encode(&mut bytes);
+
+        assert_eq!(Versioned::decode(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(Versioned::decode(&[]), None);
+        assert_eq!(Versioned::decode(&[9, 1, 0]), None);
+        assert_eq!(Versioned::decode(&[Envelope::TOMBSTONE_TAG, 1, 1, 0]), None);
+    }
+}
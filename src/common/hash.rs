@@ -0,0 +1,28 @@
+/// Returns a 64 bits hash of a byte string that is stable across runs and platforms, so it can be
+/// stored in a file.
+///
+/// The bytes are hashed with FNV-1a, then the bits are mixed so that both halves of the hash
+/// depend on every byte.
+pub fn hash64(data: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &byte in data {
+        hash = (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The hash of a byte string never changes, since it may be stored in files.
+    #[test]
+    fn hash64_is_stable() {
+        assert_eq!(hash64(b""), 0xefd0_1f60_ba99_2926);
+        assert_ne!(hash64(b"a"), hash64(b"b"));
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+use rand::Rng;
+
+use super::clock::{Clock, HybridClock, SystemClock};
+
+/// Digits of the Crockford base 32 encoding of a [Ulid].
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Number of bits of the random part of a [Ulid].
+const RANDOM_BITS: u32 = 80;
+
+/// Largest time, in milliseconds since the Unix epoch, held by a [Ulid].
+const MAX_TIMESTAMP: u64 = (1 << 48) - 1;
+
+/// A universally unique lexicographically sortable identifier: a 128-bit identifier made of the
+/// time it was created at, in milliseconds since the Unix epoch, on 48 bits, followed by 80
+/// random bits.
+///
+/// The identifiers sort by time, both as numbers and as the keys returned by [Ulid::to_bytes],
+/// which are big-endian. Keys created in increasing order are always inserted after the largest
+/// key of a tree, which a [BTree](crate::btree::BTree) inserts into its right-most leaf without a
+/// descent and, with a high fill factor (see
+/// [BTree::set_fill_factor](crate::btree::BTree::set_fill_factor)), keeps almost full. A ULID is
+/// displayed as 26 characters of the Crockford base 32 encoding.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::idgen::{Ulid, UlidGenerator};
+///
+/// let ulid = Ulid::from_parts(1_469_918_176_385, 0);
+/// assert_eq!(ulid.to_string(), "01ARYZ6S410000000000000000");
+/// assert_eq!(Ulid::parse("01aryz6s410000000000000000"), Some(ulid));
+///
+/// let generator = UlidGenerator::new();
+/// let first = generator.generate();
+/// let second = generator.generate();
+/// assert!(first.to_bytes() < second.to_bytes());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ulid(u128);
+
+impl Ulid {
+    /// Size of the key of a ULID, in bytes.
+    pub const SIZE: usize = 16;
+
+    /// Number of characters of a ULID displayed in base 32.
+    pub const ENCODED_LEN: usize = 26;
+
+    /// Creates the ULID of a time, in milliseconds since the Unix epoch, and of random bits. Only
+    /// the lower 48 bits of the time and the lower 80 bits of `random` are kept.
+    pub fn from_parts(timestamp: u64, random: u128) -> Self {
+        let timestamp = u128::from(timestamp & MAX_TIMESTAMP);
+        Ulid(timestamp << RANDOM_BITS | random & ((1 << RANDOM_BITS) - 1))
+    }
+
+    /// Returns the time the ULID was created at, in milliseconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        (self.0 >> RANDOM_BITS) as u64
+    }
+
+    /// Returns the 80 random bits of the ULID.
+    pub fn random(&self) -> u128 {
+        self.0 & ((1 << RANDOM_BITS) - 1)
+    }
+
+    /// Returns the key of the ULID, in big-endian, which sorts like the ULID.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        self.0.to_be_bytes()
+    }
+
+    /// Returns the ULID of a key returned by [Ulid::to_bytes].
+    pub fn from_bytes(bytes: [u8; Self::SIZE]) -> Self {
+        Ulid(u128::from_be_bytes(bytes))
+    }
+
+    /// Parses a ULID displayed in base 32, ignoring the case. The letters `I` and `L` are read as
+    /// `1`, and `O` as `0`. Returns `None` if the text is not 26 digits or if it is larger than
+    /// 128 bits.
+    pub fn parse(text: &str) -> Option<Self> {
+        if text.len() != Self::ENCODED_LEN {
+            return None;
+        }
+        let mut value: u128 = 0;
+        for (index, character) in text.bytes().enumerate() {
+            let digit = match character.to_ascii_uppercase() {
+                b'I' | b'L' => 1,
+                b'O' => 0,
+                upper => ALPHABET.iter().position(|&digit| digit == upper)? as u128,
+            };
+            // The first digit only holds the 3 bits left by the 25 others.
+            if index == 0 && digit > 7 {
+                return None;
+            }
+            value = value << 5 | digit;
+        }
+        Some(Ulid(value))
+    }
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut digits = [0; Self::ENCODED_LEN];
+        for (index, digit) in digits.iter_mut().rev().enumerate() {
+            *digit = ALPHABET[(self.0 >> (5 * index) & 0x1f) as usize];
+        }
+        f.write_str(std::str::from_utf8(&digits).expect("the digits should be ASCII"))
+    }
+}
+
+/// Creates [Ulid]s that always increase: a ULID created in the same millisecond as the previous
+/// one, or while the time of the clock went backward, is the previous one plus one rather than a
+/// new random one. The generator can be shared by the threads of a process.
+pub struct UlidGenerator {
+    clock: Box<dyn Clock>,
+    last: Mutex<Ulid>,
+}
+
+impl UlidGenerator {
+    /// Creates a generator reading the time of the [SystemClock].
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+
+    /// Creates a generator reading the time of `clock`.
+    pub fn with_clock<C: Clock + 'static>(clock: C) -> Self {
+        UlidGenerator {
+            clock: Box::new(clock),
+            last: Mutex::new(Ulid(0)),
+        }
+    }
+
+    /// Returns a new ULID, greater than every ULID returned before by the generator.
+    pub fn generate(&self) -> Ulid {
+        let now = self.clock.now().min(MAX_TIMESTAMP);
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        *last = if now > last.timestamp() {
+            Ulid::from_parts(now, rand::thread_rng().gen())
+        } else {
+            Ulid(last.0.checked_add(1).expect("the ULIDs should not run out"))
+        };
+        *last
+    }
+}
+
+impl Default for UlidGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Creates sequence numbers that always increase, from a first one, and their keys, in
+/// big-endian, so the keys sort like the numbers. The generator can be shared by the threads of a
+/// process. Unlike [sequence_key], the numbers start over when the process restarts, so a
+/// generator numbering keys stored in a file is created after the largest one with
+/// [SequenceGenerator::after_key].
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::idgen::SequenceGenerator;
+///
+/// let generator = SequenceGenerator::new(1);
+/// assert_eq!(generator.generate(), 1);
+/// let key = generator.generate_key();
+///
+/// let resumed = SequenceGenerator::after_key(&key).expect("the key should be 8 bytes");
+/// assert_eq!(resumed.generate(), 3);
+/// ```
+#[derive(Debug)]
+pub struct SequenceGenerator {
+    next: AtomicU64,
+}
+
+impl SequenceGenerator {
+    /// Size of a sequence key, in bytes.
+    pub const KEY_SIZE: usize = 8;
+
+    /// Creates a generator whose first number is `first`.
+    pub fn new(first: u64) -> Self {
+        SequenceGenerator {
+            next: AtomicU64::new(first),
+        }
+    }
+
+    /// Creates a generator whose first number follows the one of a key returned by
+    /// [SequenceGenerator::generate_key], or `None` if the key is not 8 bytes or no number follows
+    /// it.
+    pub fn after_key(key: &[u8]) -> Option<Self> {
+        let last = u64::from_be_bytes(key.try_into().ok()?);
+        Some(Self::new(last.checked_add(1)?))
+    }
+
+    /// Returns the next number.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the numbers have run out: `u64::MAX` is kept as the end of the sequence and never
+    /// returned.
+    pub fn generate(&self) -> u64 {
+        self.next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| {
+                next.checked_add(1)
+            })
+            .expect("the sequence numbers should not run out")
+    }
+
+    /// Returns the key of the next number.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the numbers have run out, like [SequenceGenerator::generate].
+    pub fn generate_key(&self) -> [u8; Self::KEY_SIZE] {
+        self.generate().to_be_bytes()
+    }
+}
+
+/// Returns a key, on 8 bytes, greater than every key returned before by the process. The key is a
+/// timestamp of a [HybridClock] reading the system clock, so the keys also increase across
+/// restarts, unless the clock goes back before the last key of the previous process.
+pub fn sequence_key() -> [u8; SequenceGenerator::KEY_SIZE] {
+    static CLOCK: OnceLock<HybridClock> = OnceLock::new();
+    CLOCK
+        .get_or_init(HybridClock::default)
+        .timestamp()
+        .to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::clock::ManualClock;
+
+    use super::*;
+
+    /// A ULID is displayed with the digits of its time first, and parsed back, and the text of a
+    /// value larger than 128 bits or with an invalid digit is rejected.
+    #[test]
+    fn parse_displayed_ulid_round_trips() {
+        let ulid = Ulid::from_parts(1_469_918_176_385, u128::MAX);
+
+        let text = ulid.to_string();
+
+        assert_eq!(text, "01ARYZ6S41ZZZZZZZZZZZZZZZZ");
+        assert_eq!(Ulid::parse(&text), Some(ulid));
+        assert_eq!(ulid.timestamp(), 1_469_918_176_385);
+        assert_eq!(ulid.random(), (1 << 80) - 1);
+        assert_eq!(Ulid::from_bytes(ulid.to_bytes()), ulid);
+        assert_eq!(
+            Ulid::parse("7ZZZZZZZZZZZZZZZZZZZZZZZZZ"),
+            Some(Ulid(u128::MAX))
+        );
+        assert_eq!(Ulid::parse("80000000000000000000000000"), None);
+        assert_eq!(Ulid::parse("01ARYZ6S41000000000000000U"), None);
+        assert_eq!(Ulid::parse("01ARYZ6S41"), None);
+    }
+
+    /// The ULIDs created in the same millisecond, or while the clock goes backward, follow the
+    /// previous one, and a later millisecond starts again from its time.
+    #[test]
+    fn generate_increases_within_a_millisecond() {
+        let time = ManualClock::new(1_000);
+        let generator = UlidGenerator::with_clock(time.clone());
+
+        let first = generator.generate();
+        let second = generator.generate();
+        time.set(500);
+        let third = generator.generate();
+        time.set(2_000);
+        let fourth = generator.generate();
+
+        assert_eq!(first.timestamp(), 1_000);
+        assert_eq!(second.0, first.0 + 1);
+        assert_eq!(third.0, second.0 + 1);
+        assert_eq!(fourth.timestamp(), 2_000);
+        assert!(first.to_bytes() < second.to_bytes() && third.to_bytes() < fourth.to_bytes());
+    }
+
+    /// The sequence keys increase, and a generator resumed after a key continues from it.
+    #[test]
+    fn sequence_keys_increase() {
+        let generator = SequenceGenerator::new(255);
+        let keys = [
+            generator.generate_key(),
+            generator.generate_key(),
+            sequence_key(),
+            sequence_key(),
+        ];
+
+        let resumed = SequenceGenerator::after_key(&keys[1]).expect("after_key should not fail");
+
+        assert!(keys[0] < keys[1] && keys[2] < keys[3]);
+        assert_eq!(resumed.generate(), 257);
+        assert!(SequenceGenerator::after_key(b"short").is_none());
+        assert!(SequenceGenerator::after_key(&u64::MAX.to_be_bytes()).is_none());
+    }
+
+    /// A generator whose numbers have run out panics rather than starting over from `0`.
+    #[test]
+    #[should_panic(expected = "the sequence numbers should not run out")]
+    fn generate_panics_when_the_numbers_run_out() {
+        let generator = SequenceGenerator::new(u64::MAX - 1);
+        assert_eq!(generator.generate(), u64::MAX - 1);
+
+        generator.generate();
+    }
+}
@@ -0,0 +1,279 @@
+/// A value that can be part of a key encoded by a [KeyEncoder].
+///
+/// The encoding of a part is never a prefix of the encoding of another value of the same type,
+/// and the bytewise order of the encodings is the natural order of the values, so a key made of
+/// several parts is ordered as the tuple of its parts.
+pub trait KeyPart {
+    /// Appends the encoding of the value to `bytes`.
+    fn encode_key(&self, bytes: &mut Vec<u8>);
+}
+
+/// Encodes the integer in big-endian, so the most significant byte comes first.
+impl KeyPart for u64 {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+/// Encodes the integer in big-endian with its sign bit flipped, so the negative integers come
+/// before the positive ones.
+impl KeyPart for i64 {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        (*self as u64 ^ (1 << 63)).encode_key(bytes);
+    }
+}
+
+/// Encodes the bits of the float in big-endian, all flipped for a negative float and only its
+/// sign bit for a positive one, so the floats are ordered as by [f64::total_cmp]: `-0.0` comes
+/// before `0.0`, and the positive NaNs after the positive infinity.
+impl KeyPart for f64 {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        let bits = self.to_bits();
+        let ordered = if bits >> 63 == 1 {
+            !bits
+        } else {
+            bits ^ (1 << 63)
+        };
+        ordered.encode_key(bytes);
+    }
+}
+
+/// Encodes `false` as `0x00` and `true` as `0x01`.
+impl KeyPart for bool {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        bytes.push(u8::from(*self));
+    }
+}
+
+/// Encodes the bytes with each `0x00` byte escaped as `0x00 0xff`, followed by `0x00 0x01`, so
+/// the bytes come before the longer byte strings they start.
+impl KeyPart for [u8] {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        for &byte in self {
+            bytes.push(byte);
+            if byte == 0x00 {
+                bytes.push(0xff);
+            }
+        }
+        bytes.extend_from_slice(&[0x00, 0x01]);
+    }
+}
+
+/// Encodes the UTF-8 bytes of the string, whose order is the order of its characters.
+impl KeyPart for str {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        self.as_bytes().encode_key(bytes);
+    }
+}
+
+impl KeyPart for Vec<u8> {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        self.as_slice().encode_key(bytes);
+    }
+}
+
+impl KeyPart for String {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        self.as_str().encode_key(bytes);
+    }
+}
+
+/// Encodes `None` as `0x00`, and a value as `0x01` followed by its encoding, so `None` comes
+/// first.
+impl<T: KeyPart> KeyPart for Option<T> {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        match self {
+            None => bytes.push(0x00),
+            Some(value) => {
+                bytes.push(0x01);
+                value.encode_key(bytes);
+            }
+        }
+    }
+}
+
+impl<T: KeyPart + ?Sized> KeyPart for &T {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        (**self).encode_key(bytes);
+    }
+}
+
+/// A part of a key ordered in decreasing order, such as a timestamp listing the newest entries
+/// first.
+///
+/// Its encoding is the encoding of the value with all its bits flipped: since the encoding of a
+/// value is never a prefix of another, the first byte that differs decides the order, which the
+/// flip reverses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Descending<T>(pub T);
+
+impl<T: KeyPart> KeyPart for Descending<T> {
+    fn encode_key(&self, bytes: &mut Vec<u8>) {
+        let start = bytes.len();
+        self.0.encode_key(bytes);
+        for byte in &mut bytes[start..] {
+            *byte = !*byte;
+        }
+    }
+}
+
+/// Implements [KeyPart] for the tuples of parts, encoded as the concatenation of their parts.
+macro_rules! impl_key_part_for_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: KeyPart),+> KeyPart for ($($name,)+) {
+            fn encode_key(&self, bytes: &mut Vec<u8>) {
+                #[allow(non_snake_case)]
+                let ($($name,)+) = self;
+                $($name.encode_key(bytes);)+
+            }
+        }
+    };
+}
+
+impl_key_part_for_tuple!(A);
+impl_key_part_for_tuple!(A, B);
+impl_key_part_for_tuple!(A, B, C);
+impl_key_part_for_tuple!(A, B, C, D);
+impl_key_part_for_tuple!(A, B, C, D, E);
+impl_key_part_for_tuple!(A, B, C, D, E, F);
+impl_key_part_for_tuple!(A, B, C, D, E, F, G);
+impl_key_part_for_tuple!(A, B, C, D, E, F, G, H);
+
+/// Builds keys made of several parts, such as a table identifier, an indexed column and a row
+/// identifier, whose bytewise order is the order of the tuples of their parts.
+///
+/// Each part is a [KeyPart]: an integer, a float, a boolean, a string, bytes, an optional part, a
+/// tuple of parts or a [Descending] part. The keys sharing their first parts are contiguous, so
+/// they can be iterated with their encoded prefix.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::{Descending, KeyEncoder};
+///
+/// let key = |user: &str, time: i64| {
+///     KeyEncoder::new()
+///         .push("events")
+///         .push(user)
+///         .push(&Descending(time))
+///         .finish()
+/// };
+///
+/// assert!(key("alice", 10) > key("alice", 20));
+/// assert!(key("alice", -5) > key("alice", 10));
+/// assert!(key("alice", 10) < key("alice\0", 10));
+/// assert!(key("alice\0", 10) < key("bob", 30));
+/// assert_eq!(
+///     KeyEncoder::encode(&("events", "alice", Descending(10i64))),
+///     key("alice", 10)
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeyEncoder {
+    bytes: Vec<u8>,
+}
+
+impl KeyEncoder {
+    /// Creates an encoder of a key with no part.
+    pub fn new() -> Self {
+        KeyEncoder::default()
+    }
+
+    /// Returns the encoding of a single part, or of a tuple of parts.
+    pub fn encode<T: KeyPart + ?Sized>(part: &T) -> Vec<u8> {
+        KeyEncoder::new().push(part).finish()
+    }
+
+    /// Appends a part to the key, in increasing order.
+    pub fn push<T: KeyPart + ?Sized>(mut self, part: &T) -> Self {
+        part.encode_key(&mut self.bytes);
+        self
+    }
+
+    /// Appends a part to the key, in decreasing order (see [Descending]).
+    pub fn push_descending<T: KeyPart + ?Sized>(mut self, part: &T) -> Self {
+        Descending(part).encode_key(&mut self.bytes);
+        self
+    }
+
+    /// Returns the encoded key.
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use rand::seq::SliceRandom;
+    use rand::Rng;
+
+    use super::*;
+
+    type Tuple = (u64, i64, f64, String, Vec<u8>, bool, Option<i64>);
+
+    /// Orders the tuples as their parts, the floats by [f64::total_cmp].
+    fn compare(first: &Tuple, second: &Tuple) -> Ordering {
+        first
+            .0
+            .cmp(&second.0)
+            .then(first.1.cmp(&second.1))
+            .then(first.2.total_cmp(&second.2))
+            .then(first.3.cmp(&second.3))
+            .then(first.4.cmp(&second.4))
+            .then(first.5.cmp(&second.5))
+            .then(first.6.cmp(&second.6))
+    }
+
+    /// Random tuples drawn from few values per part, so they often share their first parts, are
+    /// ordered by their encodings as by their parts, in both directions.
+    #[test]
+    fn encode_keeps_tuple_order() {
+        let mut rng = rand::thread_rng();
+        let floats = [
+            f64::NEG_INFINITY,
+            -1.5,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            2.5,
+            f64::INFINITY,
+        ];
+        let strings = ["", "a", "a\0", "a\0b", "ab", "b", "é"];
+        let mut tuples: Vec<Tuple> = (0..2000)
+            .map(|_| {
+                (
+                    *[0, 1, u64::MAX]
+                        .choose(&mut rng)
+                        .expect("the slice is not empty"),
+                    *[i64::MIN, -1, 0, 1, i64::MAX]
+                        .choose(&mut rng)
+                        .expect("the slice is not empty"),
+                    *floats.choose(&mut rng).expect("the slice is not empty"),
+                    strings
+                        .choose(&mut rng)
+                        .expect("the slice is not empty")
+                        .to_string(),
+                    (0..rng.gen_range(0..3))
+                        .map(|_| rng.gen_range(0..2))
+                        .collect(),
+                    rng.gen(),
+                    rng.gen_bool(0.5).then(|| rng.gen_range(-1..=1)),
+                )
+            })
+            .collect();
+        tuples.sort_by(compare);
+
+        for pair in tuples.windows(2) {
+            let ascending = (KeyEncoder::encode(&pair[0]), KeyEncoder::encode(&pair[1]));
+            let descending = (
+                KeyEncoder::new().push_descending(&pair[0]).finish(),
+                KeyEncoder::new().push_descending(&pair[1]).finish(),
+            );
+
+            assert_eq!(ascending.0.cmp(&ascending.1), compare(&pair[0], &pair[1]));
+            assert_eq!(descending.1.cmp(&descending.0), compare(&pair[0], &pair[1]));
+        }
+    }
+}
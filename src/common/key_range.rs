@@ -0,0 +1,297 @@
+use std::cmp::Ordering;
+use std::ops::{Bound, RangeBounds};
+
+use super::prefix_end;
+
+/// A range of keys, whose start and end are each included, excluded or unbounded.
+///
+/// The keys are compared bytewise by default. The methods ending in `_by` compare them with a
+/// function instead, such as the comparator of a tree. A range whose end is before its start, or
+/// at its start when one of them is excluded, is empty.
+///
+/// # Example
+///
+/// ```
+/// use std::ops::Bound;
+///
+/// use rouilledb::common::KeyRange;
+///
+/// let users = KeyRange::prefix(b"user:");
+/// let scan = KeyRange::new(Bound::Included(b"user:m".to_vec()), Bound::Unbounded);
+/// let both = users.intersection(&scan).expect("the ranges should intersect");
+///
+/// assert!(both.contains(b"user:max"));
+/// assert!(!both.contains(b"user:alice"));
+/// assert_eq!(both.end(), Bound::Excluded(b"user;".as_slice()));
+/// assert!(users.contains_range(&both));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyRange {
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+impl KeyRange {
+    /// Creates the range from `start` to `end`.
+    pub fn new(start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> Self {
+        KeyRange { start, end }
+    }
+
+    /// Creates the range of all the keys.
+    pub fn all() -> Self {
+        KeyRange::new(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Creates the range holding only `key`.
+    pub fn point(key: &[u8]) -> Self {
+        KeyRange::new(Bound::Included(key.to_vec()), Bound::Included(key.to_vec()))
+    }
+
+    /// Creates the range of the keys starting with `prefix`, ordered bytewise. It ends before the
+    /// prefix with its trailing `0xff` bytes removed and its last byte incremented (see
+    /// [prefix_end]), and is unbounded if there is no such key.
+    pub fn prefix(prefix: &[u8]) -> Self {
+        let end = match prefix_end(prefix) {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        KeyRange::new(Bound::Included(prefix.to_vec()), end)
+    }
+
+    /// Creates the range of the bounds of `range`.
+    pub fn from_bounds<K, R>(range: R) -> Self
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        KeyRange::new(
+            range.start_bound().map(|key| key.as_ref().to_vec()),
+            range.end_bound().map(|key| key.as_ref().to_vec()),
+        )
+    }
+
+    /// Returns the start of the range.
+    pub fn start(&self) -> Bound<&[u8]> {
+        self.start.as_ref().map(Vec::as_slice)
+    }
+
+    /// Returns the end of the range.
+    pub fn end(&self) -> Bound<&[u8]> {
+        self.end.as_ref().map(Vec::as_slice)
+    }
+
+    /// Returns the start and the end of the range.
+    pub fn into_bounds(self) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+        (self.start, self.end)
+    }
+
+    /// Returns `true` if no key is within the range, ordered bytewise.
+    pub fn is_empty(&self) -> bool {
+        self.is_empty_by(Ord::cmp)
+    }
+
+    /// Returns `true` if no key is within the range, ordered by `compare`.
+    pub fn is_empty_by(&self, compare: impl Fn(&[u8], &[u8]) -> Ordering) -> bool {
+        ends_before(self.end(), self.start(), &compare)
+    }
+
+    /// Returns `true` if `key` is within the range, ordered bytewise.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.contains_by(key, Ord::cmp)
+    }
+
+    /// Returns `true` if `key` is within the range, ordered by `compare`.
+    pub fn contains_by(&self, key: &[u8], compare: impl Fn(&[u8], &[u8]) -> Ordering) -> bool {
+        let after_start = match self.start() {
+            Bound::Included(start) => compare(start, key).is_le(),
+            Bound::Excluded(start) => compare(start, key).is_lt(),
+            Bound::Unbounded => true,
+        };
+        let before_end = match self.end() {
+            Bound::Included(end) => compare(key, end).is_le(),
+            Bound::Excluded(end) => compare(key, end).is_lt(),
+            Bound::Unbounded => true,
+        };
+        after_start && before_end
+    }
+
+    /// Returns `true` if every key within `other` is within the range, ordered bytewise. An empty
+    /// range is within every range.
+    pub fn contains_range(&self, other: &KeyRange) -> bool {
+        other.is_empty()
+            || (compare_starts(self.start(), other.start(), &Ord::cmp).is_le()
+                && compare_ends(self.end(), other.end(), &Ord::cmp).is_ge())
+    }
+
+    /// Returns `true` if a key is within both the range and `other`, ordered bytewise.
+    pub fn intersects(&self, other: &KeyRange) -> bool {
+        self.intersects_by(other, Ord::cmp)
+    }
+
+    /// Returns `true` if a key is within both the range and `other`, ordered by `compare`.
+    pub fn intersects_by(
+        &self,
+        other: &KeyRange,
+        compare: impl Fn(&[u8], &[u8]) -> Ordering,
+    ) -> bool {
+        !ends_before(self.end(), other.start(), &compare)
+            && !ends_before(other.end(), self.start(), &compare)
+    }
+
+    /// Returns the range of the keys within both the range and `other`, ordered bytewise, or
+    /// `None` if there is none.
+    pub fn intersection(&self, other: &KeyRange) -> Option<KeyRange> {
+        let start = match compare_starts(self.start(), other.start(), &Ord::cmp) {
+            Ordering::Less => &other.start,
+            _ => &self.start,
+        };
+        let end = match compare_ends(self.end(), other.end(), &Ord::cmp) {
+            Ordering::Greater => &other.end,
+            _ => &self.end,
+        };
+        let intersection = KeyRange::new(start.clone(), end.clone());
+        (!intersection.is_empty()).then_some(intersection)
+    }
+}
+
+impl RangeBounds<[u8]> for KeyRange {
+    fn start_bound(&self) -> Bound<&[u8]> {
+        self.start()
+    }
+
+    fn end_bound(&self) -> Bound<&[u8]> {
+        self.end()
+    }
+}
+
+/// Returns `true` if a range ending at `end` ends before a range starting at `start`: no key can
+/// be in both.
+fn ends_before(
+    end: Bound<&[u8]>,
+    start: Bound<&[u8]>,
+    compare: &impl Fn(&[u8], &[u8]) -> Ordering,
+) -> bool {
+    match (end, start) {
+        (Bound::Unbounded, _) | (_, Bound::Unbounded) => false,
+        (Bound::Included(end), Bound::Included(start)) => compare(end, start).is_lt(),
+        (
+            Bound::Included(end) | Bound::Excluded(end),
+            Bound::Included(start) | Bound::Excluded(start),
+        ) => compare(end, start).is_le(),
+    }
+}
+
+/// Orders the starts of two ranges: the range starting first is less.
+fn compare_starts(
+    first: Bound<&[u8]>,
+    second: Bound<&[u8]>,
+    compare: &impl Fn(&[u8], &[u8]) -> Ordering,
+) -> Ordering {
+    match (first, second) {
+        (Bound::Unbounded, Bound::Unbounded) => Ordering::Equal,
+        (Bound::Unbounded, _) => Ordering::Less,
+        (_, Bound::Unbounded) => Ordering::Greater,
+        (Bound::Included(first), Bound::Excluded(second)) => {
+            compare(first, second).then(Ordering::Less)
+        }
+        (Bound::Excluded(first), Bound::Included(second)) => {
+            compare(first, second).then(Ordering::Greater)
+        }
+        (
+            Bound::Included(first) | Bound::Excluded(first),
+            Bound::Included(second) | Bound::Excluded(second),
+        ) => compare(first, second),
+    }
+}
+
+/// Orders the ends of two ranges: the range ending first is less.
+fn compare_ends(
+    first: Bound<&[u8]>,
+    second: Bound<&[u8]>,
+    compare: &impl Fn(&[u8], &[u8]) -> Ordering,
+) -> Ordering {
+    match (first, second) {
+        (Bound::Unbounded, Bound::Unbounded) => Ordering::Equal,
+        (Bound::Unbounded, _) => Ordering::Greater,
+        (_, Bound::Unbounded) => Ordering::Less,
+        (Bound::Included(first), Bound::Excluded(second)) => {
+            compare(first, second).then(Ordering::Greater)
+        }
+        (Bound::Excluded(first), Bound::Included(second)) => {
+            compare(first, second).then(Ordering::Less)
+        }
+        (
+            Bound::Included(first) | Bound::Excluded(first),
+            Bound::Included(second) | Bound::Excluded(second),
+        ) => compare(first, second),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: Bound<&str>, end: Bound<&str>) -> KeyRange {
+        KeyRange::new(
+            start.map(|key| key.as_bytes().to_vec()),
+            end.map(|key| key.as_bytes().to_vec()),
+        )
+    }
+
+    /// A key is within a range according to whether each bound is included, excluded or
+    /// unbounded, and the range of a prefix carries over its trailing `0xff` bytes.
+    #[test]
+    fn contains_respects_bounds() {
+        let closed = range(Bound::Included("b"), Bound::Excluded("d"));
+        let open = range(Bound::Excluded("b"), Bound::Unbounded);
+        let prefix = KeyRange::prefix(&[0x61, 0xff]);
+
+        assert!(closed.contains(b"b"));
+        assert!(closed.contains(b"c\xff"));
+        assert!(!closed.contains(b"d"));
+        assert!(!open.contains(b"b"));
+        assert!(open.contains(b"zzz"));
+        assert_eq!(prefix.end(), Bound::Excluded([0x62].as_slice()));
+        assert!(prefix.contains(&[0x61, 0xff, 0xff]));
+        assert!(!prefix.contains(&[0x61, 0xfe]));
+        assert_eq!(KeyRange::prefix(&[0xff]).end(), Bound::Unbounded);
+        assert!(KeyRange::all().contains(b""));
+    }
+
+    /// The intersection of two ranges keeps the later start and the earlier end, preferring the
+    /// excluded bound when both are at the same key, and ranges that only touch do not intersect.
+    #[test]
+    fn intersection_keeps_inner_bounds() {
+        let first = range(Bound::Included("b"), Bound::Included("m"));
+        let second = range(Bound::Excluded("b"), Bound::Excluded("x"));
+        let after = range(Bound::Excluded("m"), Bound::Unbounded);
+
+        let both = first
+            .intersection(&second)
+            .expect("the ranges should intersect");
+
+        assert_eq!(both, range(Bound::Excluded("b"), Bound::Included("m")));
+        assert!(first.intersects(&second));
+        assert!(!first.intersects(&after));
+        assert_eq!(first.intersection(&after), None);
+        assert!(range(Bound::Included("m"), Bound::Excluded("m")).is_empty());
+        assert!(!KeyRange::point(b"m").is_empty());
+        assert!(first.contains_range(&both));
+        assert!(!both.contains_range(&first));
+        assert!(KeyRange::all().contains_range(&after));
+        assert!(after.contains_range(&range(Bound::Included("c"), Bound::Included("a"))));
+    }
+
+    /// The methods ending in `_by` order the keys with the function given.
+    #[test]
+    fn intersects_by_uses_the_order_given() {
+        let reverse = |first: &[u8], second: &[u8]| second.cmp(first);
+        let descending = range(Bound::Included("m"), Bound::Included("b"));
+
+        assert!(descending.is_empty());
+        assert!(!descending.is_empty_by(reverse));
+        assert!(descending.contains_by(b"c", reverse));
+        assert!(descending.intersects_by(&KeyRange::point(b"d"), reverse));
+        assert!(!descending.intersects_by(&KeyRange::point(b"a"), reverse));
+    }
+}
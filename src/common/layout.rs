@@ -0,0 +1,375 @@
+use thiserror::Error;
+
+/// Represents errors that can occur while reading a [Layout].
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    /// Indicates that the bytes are shorter than the layout.
+    ///
+    /// # Fields
+    /// - `size` - The number of bytes read.
+    /// - `expected` - The size of the layout, in the version read.
+    #[error("The layout is {expected} bytes long, but only {size} bytes were read.")]
+    Truncated { size: usize, expected: usize },
+
+    /// Indicates that the version read is not a version of the layout.
+    ///
+    /// # Fields
+    /// - `version` - The version read.
+    /// - `latest` - The latest version of the layout.
+    #[error("The version {version} of the layout is not supported, the latest is {latest}.")]
+    UnsupportedVersion { version: u16, latest: u16 },
+}
+
+/// A value stored at a fixed size in a [Layout], in little-endian order.
+pub trait Field: Sized {
+    /// The number of bytes of the stored value.
+    const SIZE: usize;
+
+    /// The value read from [Field::SIZE] zero bytes, given to the fields missing from the older
+    /// versions of a layout.
+    const ZERO: Self;
+
+    /// Writes the value to the first [Field::SIZE] bytes of `bytes`.
+    fn write(&self, bytes: &mut [u8]);
+
+    /// Reads a value from the first [Field::SIZE] bytes of `bytes`.
+    fn read(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_field_for_integer {
+    ($($integer:ty),*) => {
+        $(
+            impl Field for $integer {
+                const SIZE: usize = std::mem::size_of::<$integer>();
+                const ZERO: Self = 0;
+
+                fn write(&self, bytes: &mut [u8]) {
+                    bytes[..Self::SIZE].copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn read(bytes: &[u8]) -> Self {
+                    <$integer>::from_le_bytes(
+                        bytes[..Self::SIZE]
+                            .try_into()
+                            .expect("slice should be the size of the integer"),
+                    )
+                }
+            }
+        )*
+    };
+}
+
+impl_field_for_integer!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl<const N: usize> Field for [u8; N] {
+    const SIZE: usize = N;
+    const ZERO: Self = [0; N];
+
+    fn write(&self, bytes: &mut [u8]) {
+        bytes[..N].copy_from_slice(self);
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        bytes[..N].try_into().expect("slice should be N bytes")
+    }
+}
+
+/// A struct stored as its fields one after the other, without padding, each as a [Field], such
+/// as the header of a file or of a page. Layouts are declared with
+/// [disk_layout](crate::disk_layout), and their size is checked at compile time with
+/// [assert_layout_size](crate::assert_layout_size).
+///
+/// A layout has versions, from 1 to [Layout::VERSION]: each field is stored since a version, so
+/// the bytes written by an older version are still read, the fields it did not store being zero.
+/// The version itself is not stored by the layout, but usually in a field of another one read
+/// first.
+pub trait Layout: Sized {
+    /// The latest version of the layout, the one written.
+    const VERSION: u16;
+
+    /// The number of bytes of the latest version of the layout.
+    const SIZE: usize;
+
+    /// Returns the number of bytes of a version of the layout, or `None` if it is not a version of
+    /// the layout.
+    fn size_of_version(version: u16) -> Option<usize>;
+
+    /// Writes the latest version of the layout to the first [Layout::SIZE] bytes of `bytes`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `bytes` is shorter than [Layout::SIZE].
+    fn write(&self, bytes: &mut [u8]);
+
+    /// Reads the layout from the first bytes of `bytes`, as written by `version`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `version` is not a version of the layout, or if
+    /// `bytes` is shorter than that version of the layout.
+    fn read_version(bytes: &[u8], version: u16) -> Result<Self, LayoutError>;
+
+    /// Reads the latest version of the layout from the first [Layout::SIZE] bytes of `bytes`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `bytes` is shorter than [Layout::SIZE].
+    fn read(bytes: &[u8]) -> Result<Self, LayoutError> {
+        Self::read_version(bytes, Self::VERSION)
+    }
+
+    /// Appends the latest version of the layout to `bytes`.
+    fn append(&self, bytes: &mut Vec<u8>) {
+        let start = bytes.len();
+        bytes.resize(start + Self::SIZE, 0);
+        self.write(&mut bytes[start..]);
+    }
+}
+
+/// Returns the version given to a [disk_layout](crate::disk_layout), or `1` if none is.
+#[doc(hidden)]
+pub const fn version_or_first(version: &[u16]) -> u16 {
+    match version {
+        [version] => *version,
+        _ => 1,
+    }
+}
+
+/// Returns the number of bytes of a field stored since `since` in a version of a layout.
+#[doc(hidden)]
+pub const fn field_size<T: Field>(version: u16, since: u16) -> usize {
+    if since <= version {
+        T::SIZE
+    } else {
+        0
+    }
+}
+
+/// Writes a field at `offset` and moves `offset` after it.
+#[doc(hidden)]
+pub fn write_field<T: Field>(field: &T, bytes: &mut [u8], offset: &mut usize) {
+    field.write(&mut bytes[*offset..*offset + T::SIZE]);
+    *offset += T::SIZE;
+}
+
+/// Reads a field stored since `since` at `offset`, and moves `offset` after it, or returns
+/// [Field::ZERO] if the version read does not store the field.
+#[doc(hidden)]
+pub fn read_field<T: Field>(bytes: &[u8], offset: &mut usize, version: u16, since: u16) -> T {
+    if since > version {
+        return T::ZERO;
+    }
+    let field = T::read(&bytes[*offset..*offset + T::SIZE]);
+    *offset += T::SIZE;
+    field
+}
+
+/// Declares a struct and implements [Layout](crate::common::layout::Layout) for it, storing its
+/// fields in the order they are declared.
+///
+/// The latest version of the layout follows the name of the struct, as `: version 3` or as
+/// `: version VERSION` with a constant, and is `1` when omitted. A field added by a later version
+/// is marked with `#[since(2)]`, after its doc comment, and is zero when an older version is read.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::layout::{Layout, LayoutError};
+/// use rouilledb::{assert_layout_size, disk_layout};
+///
+/// disk_layout! {
+///     /// The header of a file.
+///     #[derive(Debug, PartialEq)]
+///     pub struct Header: version 2 {
+///         /// The magic bytes of the file.
+///         pub magic: [u8; 4],
+///         /// The number of pages of the file.
+///         pub page_count: u32,
+///         /// The checksum of the pages, added by version 2.
+///         #[since(2)]
+///         pub checksum: u32,
+///     }
+/// }
+/// assert_layout_size!(Header, 12);
+///
+/// let header = Header { magic: *b"FILE", page_count: 3, checksum: 0xc0ffee };
+/// let mut bytes = Vec::new();
+/// header.append(&mut bytes);
+/// assert_eq!(bytes[4..8], 3u32.to_le_bytes());
+/// assert_eq!(Header::read(&bytes), Ok(header));
+///
+/// let old = Header::read_version(&bytes[..8], 1).expect("read_version should not fail");
+/// assert_eq!(old.checksum, 0);
+/// assert!(matches!(Header::read(&bytes[..8]), Err(LayoutError::Truncated { .. })));
+/// ```
+#[macro_export]
+macro_rules! disk_layout {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident $(: version $version:tt)? {
+            $(
+                $(#[doc = $doc:literal])*
+                $(#[since($since:tt)])?
+                $field_vis:vis $field:ident: $type:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(
+                $(#[doc = $doc])*
+                $field_vis $field: $type,
+            )*
+        }
+
+        impl $crate::common::layout::Layout for $name {
+            const VERSION: u16 = $crate::common::layout::version_or_first(&[$($version)?]);
+
+            const SIZE: usize = 0 $(+ <$type as $crate::common::layout::Field>::SIZE)*;
+
+            fn size_of_version(version: u16) -> Option<usize> {
+                if version == 0 || version > Self::VERSION {
+                    return None;
+                }
+                Some(
+                    0 $(+ $crate::common::layout::field_size::<$type>(
+                        version,
+                        $crate::common::layout::version_or_first(&[$($since)?]),
+                    ))*
+                )
+            }
+
+            fn write(&self, bytes: &mut [u8]) {
+                let mut offset = 0;
+                $($crate::common::layout::write_field(&self.$field, bytes, &mut offset);)*
+            }
+
+            fn read_version(
+                bytes: &[u8],
+                version: u16,
+            ) -> Result<Self, $crate::common::layout::LayoutError> {
+                let expected = Self::size_of_version(version).ok_or(
+                    $crate::common::layout::LayoutError::UnsupportedVersion {
+                        version,
+                        latest: Self::VERSION,
+                    },
+                )?;
+                if bytes.len() < expected {
+                    return Err($crate::common::layout::LayoutError::Truncated {
+                        size: bytes.len(),
+                        expected,
+                    });
+                }
+                let mut offset = 0;
+                Ok($name {
+                    $(
+                        $field: $crate::common::layout::read_field(
+                            bytes,
+                            &mut offset,
+                            version,
+                            $crate::common::layout::version_or_first(&[$($since)?]),
+                        ),
+                    )*
+                })
+            }
+        }
+    };
+}
+
+/// Fails to compile unless a [Layout](crate::common::layout::Layout) has the given size, so the
+/// size of a stored format does not change by accident.
+#[macro_export]
+macro_rules! assert_layout_size {
+    ($layout:ty, $size:expr) => {
+        const _: () = assert!(
+            <$layout as $crate::common::layout::Layout>::SIZE == $size,
+            concat!("the size of the layout ", stringify!($layout), " changed")
+        );
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::disk_layout! {
+        #[derive(Debug, Clone, PartialEq)]
+        struct Sample: version 3 {
+            magic: [u8; 3],
+            signed: i16,
+            #[since(2)]
+            count: u64,
+            flags: u8,
+            #[since(3)]
+            page: u32,
+        }
+    }
+
+    crate::assert_layout_size!(Sample, 18);
+
+    /// A layout is read back as written, each field in little-endian order right after the
+    /// previous one.
+    #[test]
+    fn read_written_layout_round_trips() {
+        let sample = Sample {
+            magic: *b"abc",
+            signed: -2,
+            count: 0x0102_0304_0506_0708,
+            flags: 9,
+            page: 10,
+        };
+        let mut bytes = vec![0xff];
+        sample.append(&mut bytes);
+
+        assert_eq!(
+            bytes,
+            [
+                &[0xff][..],
+                b"abc",
+                &[0xfe, 0xff],
+                &[8, 7, 6, 5, 4, 3, 2, 1],
+                &[9],
+                &[10, 0, 0, 0]
+            ]
+            .concat()
+        );
+        assert_eq!(Sample::read(&bytes[1..]), Ok(sample));
+    }
+
+    /// The older versions of a layout are read without the fields they did not store, and the
+    /// versions that don't exist or the truncated bytes are rejected.
+    #[test]
+    fn read_version_reads_older_versions() {
+        let version_1 = [b"abc".as_slice(), &[1, 0], &[7]].concat();
+
+        let sample = Sample::read_version(&version_1, 1).expect("read_version should not fail");
+
+        assert_eq!(Sample::size_of_version(1), Some(6));
+        assert_eq!(Sample::size_of_version(2), Some(14));
+        assert_eq!(Sample::size_of_version(4), None);
+        assert_eq!(
+            sample,
+            Sample {
+                magic: *b"abc",
+                signed: 1,
+                count: 0,
+                flags: 7,
+                page: 0,
+            }
+        );
+        assert_eq!(
+            Sample::read_version(&version_1, 2),
+            Err(LayoutError::Truncated {
+                size: 6,
+                expected: 14
+            })
+        );
+        assert_eq!(
+            Sample::read_version(&version_1, 0),
+            Err(LayoutError::UnsupportedVersion {
+                version: 0,
+                latest: 3
+            })
+        );
+    }
+}
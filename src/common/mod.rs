@@ -1,3 +1,19 @@
+pub mod checksum;
+pub mod clock;
+pub mod envelope;
+mod hash;
+pub mod idgen;
+mod key_encoder;
+mod key_range;
+pub mod layout;
+mod prefix;
 mod random_blob;
+mod temp_dir;
+pub mod varint;
 
+pub use hash::hash64;
+pub use key_encoder::{Descending, KeyEncoder, KeyPart};
+pub use key_range::KeyRange;
+pub use prefix::prefix_end;
 pub use random_blob::RandomBlob;
+pub use temp_dir::TempDir;
@@ -0,0 +1,42 @@
+/// Returns the smallest key greater than every key starting with `prefix`, the excluded end of the
+/// range of the keys with that prefix, or `None` if there is no such key and the range is
+/// unbounded.
+///
+/// The trailing `0xff` bytes of the prefix are removed, since no byte can follow them, and the new
+/// last byte is incremented. An empty prefix, or one made only of `0xff` bytes, has no end.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::prefix_end;
+///
+/// assert_eq!(prefix_end(b"ab"), Some(b"ac".to_vec()));
+/// assert_eq!(prefix_end(&[0x61, 0xff, 0xff]), Some(vec![0x62]));
+/// assert_eq!(prefix_end(&[0xff]), None);
+/// ```
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|&byte| byte != 0xff)?;
+    let mut end = prefix[..=last].to_vec();
+    end[last] += 1;
+    Some(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The end of a prefix is greater than the keys with the prefix, but not than the keys after
+    /// them.
+    #[test]
+    fn prefix_end_bounds_keys_with_prefix() {
+        let prefix = [0x10, 0xff];
+
+        let end = prefix_end(&prefix).expect("the prefix should have an end");
+
+        assert_eq!(end, vec![0x11]);
+        assert!([0x10, 0xff, 0xff, 0xff].as_slice() < end.as_slice());
+        assert!([0x11, 0x00].as_slice() >= end.as_slice());
+        assert_eq!(prefix_end(b""), None);
+        assert_eq!(prefix_end(&[0xff, 0xff]), None);
+    }
+}
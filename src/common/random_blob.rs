@@ -27,6 +27,11 @@ impl RandomBlob {
     pub fn len(&self) -> usize {
         self.data.len()
     }
+
+    /// Returns `true` if the [RandomBlob] contains no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
 }
 
 impl Default for RandomBlob {
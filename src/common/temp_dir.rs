@@ -0,0 +1,40 @@
+use std::path::{Path, PathBuf};
+
+/// Represents a new, empty, directory in the temporary directory of the system, removed with its
+/// content when dropped.
+///
+/// This can be used during testing when files must be written to the file system.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a directory with a random name in the temporary directory of the system.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the directory can't be created.
+    pub fn new() -> Self {
+        let name = format!("rouilledb-{:016x}", rand::random::<u64>());
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir(&path).expect("the temporary directory should be created");
+        TempDir { path }
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Default for TempDir {
+    fn default() -> Self {
+        TempDir::new()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
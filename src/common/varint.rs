@@ -0,0 +1,216 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+/// The largest number of bytes of an encoded `u64`.
+pub const MAX_LEN: usize = 10;
+
+/// Returns the number of bytes of the encoding of `value`.
+pub fn encoded_len(value: u64) -> usize {
+    let bits = (u64::BITS - value.leading_zeros()).max(1) as usize;
+    bits.div_ceil(7)
+}
+
+/// Appends the variable-length encoding of `value` to `bytes`, as in LEB128: the integer is split
+/// in groups of 7 bits, from the least significant, each stored in a byte whose high bit is set
+/// when another byte follows. Small integers, such as the lengths of most keys and values, take a
+/// single byte, and a `u64` takes at most [MAX_LEN] bytes.
+///
+/// The signed integers are first mapped to unsigned ones with the zigzag encoding (see
+/// [zigzag_encode]), so the integers close to zero take few bytes whatever their sign.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::varint;
+///
+/// let mut bytes = Vec::new();
+/// varint::encode_u64(300, &mut bytes);
+/// varint::encode_i64(-2, &mut bytes);
+/// assert_eq!(bytes, vec![0xac, 0x02, 0x03]);
+///
+/// let (value, length) = varint::decode_u64(&bytes).expect("decode_u64 should not fail");
+/// assert_eq!((value, length), (300, 2));
+/// let (value, length) = varint::decode_i64(&bytes[2..]).expect("decode_i64 should not fail");
+/// assert_eq!((value, length), (-2, 1));
+/// ```
+pub fn encode_u64(mut value: u64, bytes: &mut Vec<u8>) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Decodes the integer at the start of `bytes`, and returns it with the number of bytes it took,
+/// or `None` if `bytes` ends before it does or if it does not fit in a `u64`.
+pub fn decode_u64(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (index, &byte) in bytes.iter().enumerate().take(MAX_LEN) {
+        let bits = u64::from(byte & 0x7f);
+        // The last byte of a u64 only holds its highest bit.
+        if index == MAX_LEN - 1 && bits > 1 {
+            return None;
+        }
+        value |= bits << (7 * index);
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+    }
+    None
+}
+
+/// Appends the encoding of a signed `value`, zigzag encoded, to `bytes`.
+pub fn encode_i64(value: i64, bytes: &mut Vec<u8>) {
+    encode_u64(zigzag_encode(value), bytes);
+}
+
+/// Decodes the signed integer at the start of `bytes`, like [decode_u64].
+pub fn decode_i64(bytes: &[u8]) -> Option<(i64, usize)> {
+    let (value, length) = decode_u64(bytes)?;
+    Some((zigzag_decode(value), length))
+}
+
+/// Writes the encoding of `value` to `writer`, and returns the number of bytes written.
+///
+/// # Errors
+///
+/// This function will return an error if the writer fails.
+pub fn write_u64<W: Write + ?Sized>(writer: &mut W, value: u64) -> io::Result<usize> {
+    let mut bytes = Vec::with_capacity(MAX_LEN);
+    encode_u64(value, &mut bytes);
+    writer.write_all(&bytes)?;
+    Ok(bytes.len())
+}
+
+/// Reads an integer from `reader`, consuming only its bytes.
+///
+/// # Errors
+///
+/// This function will return an error of kind [ErrorKind::UnexpectedEof] if the reader ends
+/// before the integer does, of kind [ErrorKind::InvalidData] if the integer does not fit in a
+/// `u64`, or the error of the reader.
+pub fn read_u64<R: Read + ?Sized>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = Vec::with_capacity(MAX_LEN);
+    loop {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        bytes.push(byte[0]);
+        if byte[0] & 0x80 == 0 || bytes.len() == MAX_LEN {
+            break;
+        }
+    }
+    decode_u64(&bytes)
+        .map(|(value, _)| value)
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "the varint overflows a u64"))
+}
+
+/// Writes the encoding of a signed `value`, zigzag encoded, to `writer`, like [write_u64].
+///
+/// # Errors
+///
+/// This function will return an error if the writer fails.
+pub fn write_i64<W: Write + ?Sized>(writer: &mut W, value: i64) -> io::Result<usize> {
+    write_u64(writer, zigzag_encode(value))
+}
+
+/// Reads a signed integer from `reader`, like [read_u64].
+///
+/// # Errors
+///
+/// This function will return an error in the cases of [read_u64].
+pub fn read_i64<R: Read + ?Sized>(reader: &mut R) -> io::Result<i64> {
+    read_u64(reader).map(zigzag_decode)
+}
+
+/// Maps a signed integer to an unsigned one, interleaving the positive and the negative integers
+/// (`0`, `-1`, `1`, `-2`, ...), so the integers close to zero are mapped to small ones.
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Maps back an integer mapped by [zigzag_encode].
+pub fn zigzag_decode(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    /// Random integers of every length are decoded back from their encoding, from a slice as from
+    /// a reader, and take the number of bytes announced.
+    #[test]
+    fn decode_returns_encoded_integers() {
+        let mut rng = rand::thread_rng();
+        let mut values = vec![0, 1, 127, 128, 16_383, 16_384, u64::MAX];
+        values.extend((0..1000).map(|_| rng.gen::<u64>() >> rng.gen_range(0..64)));
+
+        for value in values {
+            let mut bytes = Vec::new();
+            encode_u64(value, &mut bytes);
+            let signed = value as i64;
+            let mut signed_bytes = Vec::new();
+            let written = write_i64(&mut signed_bytes, signed).expect("write_i64 should not fail");
+
+            assert_eq!(bytes.len(), encoded_len(value));
+            assert_eq!(decode_u64(&bytes), Some((value, bytes.len())));
+            assert_eq!(
+                read_u64(&mut bytes.as_slice()).expect("read_u64 should not fail"),
+                value
+            );
+            assert_eq!(decode_u64(&bytes[..bytes.len() - 1]), None);
+            assert_eq!(decode_i64(&signed_bytes), Some((signed, written)));
+            assert_eq!(
+                read_i64(&mut signed_bytes.as_slice()).expect("read_i64 should not fail"),
+                signed
+            );
+        }
+    }
+
+    /// Random bytes are decoded the same from a slice and from a reader, without panicking, and a
+    /// decoded integer is encoded back to the bytes it was decoded from, unless they padded it.
+    #[test]
+    fn decode_random_bytes_is_consistent() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..10_000 {
+            let length = rng.gen_range(0..=MAX_LEN + 2);
+            // Bytes with their high bit set make long integers more likely.
+            let bytes: Vec<u8> = (0..length)
+                .map(|_| rng.gen::<u8>() | if rng.gen_bool(0.8) { 0x80 } else { 0 })
+                .collect();
+
+            let decoded = decode_u64(&bytes);
+            let read = read_u64(&mut bytes.as_slice());
+
+            match decoded {
+                Some((value, length)) => {
+                    assert_eq!(read.expect("read_u64 should not fail"), value);
+                    let mut encoded = Vec::new();
+                    encode_u64(value, &mut encoded);
+                    if bytes[length - 1] != 0 || length == 1 {
+                        assert_eq!(encoded, bytes[..length]);
+                    }
+                }
+                None => assert!(read.is_err()),
+            }
+        }
+        assert_eq!(decode_u64(&[0xff; MAX_LEN]), None);
+        assert_eq!(decode_u64(&[0x80, 0x00]), Some((0, 2)));
+    }
+
+    /// The zigzag encoding maps the integers close to zero, whatever their sign, to small ones.
+    #[test]
+    fn zigzag_interleaves_signs() {
+        let encoded: Vec<_> = [0, -1, 1, -2, 2, i64::MAX, i64::MIN]
+            .into_iter()
+            .map(zigzag_encode)
+            .collect();
+
+        assert_eq!(encoded, vec![0, 1, 2, 3, 4, u64::MAX - 1, u64::MAX]);
+        for value in encoded {
+            assert_eq!(zigzag_encode(zigzag_decode(value)), value);
+        }
+    }
+}
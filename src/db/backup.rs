@@ -0,0 +1,609 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::checksum::xxhash64;
+use crate::fs::{File, OsFile};
+
+use super::database::{io_error, DATA_FILE_NAME, LOG_FILE_NAME};
+use super::{Database, DatabaseError};
+
+/// Name of the directory holding the blocks shared by the backups.
+const BLOCKS_DIRECTORY: &str = "blocks";
+
+/// Name of the directory holding the manifests of the backups.
+const MANIFESTS_DIRECTORY: &str = "backups";
+
+/// Size of the blocks the data files are divided into, a multiple of every page size.
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Describes a backup made by a [BackupEngine].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupInfo {
+    /// The identifier of the backup, which increases with each backup.
+    pub id: u32,
+    /// The time the backup was made, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The version of the database that was backed up (see [Database::version]).
+    pub version: u64,
+    /// The size of the data file that was backed up, in bytes.
+    pub size: u64,
+    /// The number of bytes of the blocks written by this backup, the others being shared with
+    /// older backups.
+    pub written: u64,
+}
+
+/// The description of a backup and the blocks of its data file, in order.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    info: BackupInfo,
+    blocks: Vec<String>,
+}
+
+/// Makes backups of databases in a directory, while the databases stay open.
+///
+/// Each backup is a copy of the data file of a database as of its last commit, divided into blocks
+/// of 64 KiB. The blocks are named after the XXH64 hash of their content and shared by all the
+/// backups of the directory, so a backup only writes the blocks that changed since the previous
+/// ones. The pages are read through the cache and the write-ahead log of the database, so a backup
+/// holds the commits not yet copied to the data file. The pages read do not fill the cache of the
+/// database.
+///
+/// The manifest of a backup, listing its blocks, is written last, so a backup interrupted by a
+/// crash is ignored. Its blocks are removed by [BackupEngine::purge_old_backups]. A backup is
+/// restored as a database with [BackupEngine::restore], and its blocks can be checked against their
+/// hash beforehand with [BackupEngine::verify_backup].
+pub struct BackupEngine {
+    directory: PathBuf,
+    backups: Vec<BackupInfo>,
+}
+
+impl BackupEngine {
+    /// Opens the backups in the directory at `path`, which is created if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the directories can't be created or read, or if the
+    /// manifest of a backup can't be read.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{BackupEngine, Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database = Database::open(directory.path().join("database"), Options::new())
+    ///     .expect("open should not fail");
+    /// database.put(b"key", b"value").expect("put should not fail");
+    ///
+    /// let mut engine =
+    ///     BackupEngine::open(directory.path().join("backups")).expect("open should not fail");
+    /// let first = engine
+    ///     .create_backup(&database)
+    ///     .expect("create_backup should not fail");
+    /// let second = engine
+    ///     .create_backup(&database)
+    ///     .expect("create_backup should not fail");
+    ///
+    /// assert_eq!(engine.backups().len(), 2);
+    /// assert_eq!(second.size, first.size);
+    /// assert_eq!(second.written, 0);
+    /// ```
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DatabaseError> {
+        let directory = path.as_ref().to_path_buf();
+        for subdirectory in [BLOCKS_DIRECTORY, MANIFESTS_DIRECTORY] {
+            let path = directory.join(subdirectory);
+            std::fs::create_dir_all(&path).map_err(|source| io_error(&path, source))?;
+        }
+        let mut engine = BackupEngine {
+            directory,
+            backups: Vec::new(),
+        };
+        for id in engine.manifest_ids()? {
+            engine.backups.push(engine.read_manifest(id)?.info);
+        }
+        Ok(engine)
+    }
+
+    /// Returns the directory of the backups.
+    pub fn path(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns the complete backups, from the oldest to the most recent.
+    pub fn backups(&self) -> &[BackupInfo] {
+        &self.backups
+    }
+
+    /// Makes a backup of a database as of its last commit, and returns its description. Only the
+    /// blocks that no other backup holds are written. The database stays open, and no write can
+    /// happen during the backup since it is borrowed.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page of the database can't be read, or if a block or
+    /// the manifest can't be written. The incomplete backup is then ignored.
+    pub fn create_backup(&mut self, database: &Database) -> Result<BackupInfo, DatabaseError> {
+        let id = self.backups.last().map_or(1, |backup| backup.id + 1);
+        let pager = database.pager();
+        let page_size = pager.page_size();
+        let size = pager.page_count() as usize * page_size;
+
+        let mut blocks = Vec::new();
+        let mut written = 0;
+        let mut block = Vec::with_capacity(BLOCK_SIZE);
+        let mut page = vec![0; page_size];
+        for offset in (0..size).step_by(page_size) {
+            pager.file().read_with(offset, &mut page, false)?;
+            block.extend_from_slice(&page);
+            if block.len() == BLOCK_SIZE || offset + page_size == size {
+                let (name, new) = self.store_block(&block)?;
+                if new {
+                    written += block.len() as u64;
+                }
+                blocks.push(name);
+                block.clear();
+            }
+        }
+
+        let info = BackupInfo {
+            id,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            version: database.version(),
+            size: size as u64,
+            written,
+        };
+        self.write_manifest(&Manifest {
+            info: info.clone(),
+            blocks,
+        })?;
+        self.backups.push(info.clone());
+        Ok(info)
+    }
+
+    /// Writes a backup as a new database in the directory at `path`, which is created if it does
+    /// not exist. Each block is checked against its hash as it is read. The data file is renamed
+    /// last, so a restore interrupted by a crash leaves no database.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the backup does not exist, if a database already
+    /// exists at `path`, if a block is missing or does not match its hash, or if the files of the
+    /// database can't be written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{BackupEngine, Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database = Database::open(directory.path().join("database"), Options::new())
+    ///     .expect("open should not fail");
+    /// database.put(b"key", b"value").expect("put should not fail");
+    /// let mut engine =
+    ///     BackupEngine::open(directory.path().join("backups")).expect("open should not fail");
+    /// let backup = engine
+    ///     .create_backup(&database)
+    ///     .expect("create_backup should not fail");
+    ///
+    /// engine
+    ///     .verify_backup(backup.id)
+    ///     .expect("verify_backup should not fail");
+    /// engine
+    ///     .restore(backup.id, directory.path().join("restored"))
+    ///     .expect("restore should not fail");
+    ///
+    /// let restored = Database::open(directory.path().join("restored"), Options::new())
+    ///     .expect("open should not fail");
+    /// assert_eq!(restored.get(b"key").expect("get should not fail"), Some(b"value".to_vec()));
+    /// ```
+    pub fn restore(&self, id: u32, path: impl AsRef<Path>) -> Result<(), DatabaseError> {
+        let manifest = self.manifest(id)?;
+        let path = path.as_ref();
+        std::fs::create_dir_all(path).map_err(|source| io_error(path, source))?;
+        let data_path = path.join(DATA_FILE_NAME);
+        if data_path.exists() {
+            return Err(DatabaseError::AlreadyExists(path.display().to_string()));
+        }
+
+        let temporary = data_path.with_extension("tmp");
+        let mut data = OsFile::new(&temporary);
+        data.create()?;
+        let mut offset = 0;
+        for name in &manifest.blocks {
+            let block = self.read_block(name)?;
+            data.write(offset, &block)?;
+            offset += block.len();
+        }
+        data.sync()?;
+        data.close()?;
+        if offset as u64 != manifest.info.size {
+            return Err(size_mismatch(&manifest, offset));
+        }
+
+        let log_path = path.join(LOG_FILE_NAME);
+        if !log_path.exists() {
+            write_file(&log_path, &[])?;
+        }
+        std::fs::rename(&temporary, &data_path).map_err(|source| io_error(&data_path, source))
+    }
+
+    /// Checks that every block of a backup exists and matches its hash, and that the blocks hold
+    /// the whole data file, so a damaged backup is found before it must be restored.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the backup does not exist, or if it is corrupted or
+    /// can't be read.
+    pub fn verify_backup(&self, id: u32) -> Result<(), DatabaseError> {
+        let manifest = self.manifest(id)?;
+        let mut size = 0;
+        for name in &manifest.blocks {
+            size += self.read_block(name)?.len();
+        }
+        if size as u64 != manifest.info.size {
+            return Err(size_mismatch(&manifest, size));
+        }
+        Ok(())
+    }
+
+    /// Removes the oldest backups, keeping the `keep` most recent ones, then the blocks that no
+    /// remaining backup holds, including those of interrupted backups.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a manifest can't be read, or if a manifest or a block
+    /// can't be removed.
+    pub fn purge_old_backups(&mut self, keep: usize) -> Result<(), DatabaseError> {
+        let removed = self.backups.len().saturating_sub(keep);
+        let removed: Vec<u32> = self.backups[..removed]
+            .iter()
+            .map(|backup| backup.id)
+            .collect();
+        for id in removed {
+            let path = self.manifest_path(id);
+            std::fs::remove_file(&path).map_err(|source| io_error(&path, source))?;
+            self.backups.remove(0);
+        }
+
+        let mut used = HashSet::new();
+        for backup in &self.backups {
+            used.extend(self.read_manifest(backup.id)?.blocks);
+        }
+        let blocks = self.directory.join(BLOCKS_DIRECTORY);
+        for entry in std::fs::read_dir(&blocks).map_err(|source| io_error(&blocks, source))? {
+            let path = entry.map_err(|source| io_error(&blocks, source))?.path();
+            let name = path.file_name().and_then(|name| name.to_str());
+            if name.is_none_or(|name| !used.contains(name)) {
+                std::fs::remove_file(&path).map_err(|source| io_error(&path, source))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a block, unless a block with the same content is already stored. Returns the name
+    /// of the block and whether it was written.
+    fn store_block(&self, block: &[u8]) -> Result<(String, bool), DatabaseError> {
+        let name = block_name(block);
+        let path = self.block_path(&name);
+        if path.is_file() {
+            let stored = std::fs::read(&path).map_err(|source| io_error(&path, source))?;
+            if stored != block {
+                return Err(DatabaseError::CorruptedBackup(format!(
+                    "the block {name} does not match its content"
+                )));
+            }
+            return Ok((name, false));
+        }
+        write_file(&path, block)?;
+        Ok((name, true))
+    }
+
+    /// Returns the content of a block, after checking it against its hash.
+    fn read_block(&self, name: &str) -> Result<Vec<u8>, DatabaseError> {
+        let path = self.block_path(name);
+        if !path.is_file() {
+            return Err(DatabaseError::CorruptedBackup(format!(
+                "the block {name} is missing"
+            )));
+        }
+        let block = std::fs::read(&path).map_err(|source| io_error(&path, source))?;
+        if block_name(&block) != name {
+            return Err(DatabaseError::CorruptedBackup(format!(
+                "the block {name} does not match its hash"
+            )));
+        }
+        Ok(block)
+    }
+
+    /// Returns the manifest of a complete backup.
+    fn manifest(&self, id: u32) -> Result<Manifest, DatabaseError> {
+        if !self.backups.iter().any(|backup| backup.id == id) {
+            return Err(DatabaseError::BackupNotFound(id));
+        }
+        self.read_manifest(id)
+    }
+
+    /// Writes the manifest of a backup to a temporary file, then renames it, so a manifest is
+    /// never partially written.
+    fn write_manifest(&self, manifest: &Manifest) -> Result<(), DatabaseError> {
+        let text = toml::to_string(manifest)
+            .map_err(|error| DatabaseError::CorruptedBackup(error.to_string()))?;
+        let path = self.manifest_path(manifest.info.id);
+        let temporary = path.with_extension("tmp");
+        write_file(&temporary, text.as_bytes())?;
+        std::fs::rename(&temporary, &path).map_err(|source| io_error(&path, source))
+    }
+
+    fn read_manifest(&self, id: u32) -> Result<Manifest, DatabaseError> {
+        let path = self.manifest_path(id);
+        let text = std::fs::read_to_string(&path).map_err(|source| io_error(&path, source))?;
+        toml::from_str(&text)
+            .map_err(|error| DatabaseError::CorruptedBackup(format!("{}: {error}", path.display())))
+    }
+
+    /// Returns the identifiers of the manifests in the directory, in increasing order.
+    fn manifest_ids(&self) -> Result<Vec<u32>, DatabaseError> {
+        let manifests = self.directory.join(MANIFESTS_DIRECTORY);
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(&manifests).map_err(|source| io_error(&manifests, source))? {
+            let path = entry.map_err(|source| io_error(&manifests, source))?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+            {
+                if let Some(id) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str()?.parse().ok())
+                {
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn manifest_path(&self, id: u32) -> PathBuf {
+        self.directory
+            .join(MANIFESTS_DIRECTORY)
+            .join(format!("{id}.toml"))
+    }
+
+    fn block_path(&self, name: &str) -> PathBuf {
+        self.directory.join(BLOCKS_DIRECTORY).join(name)
+    }
+}
+
+/// Writes a new file and syncs it.
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), DatabaseError> {
+    let mut file = OsFile::new(path);
+    file.create()?;
+    file.write(0, bytes)?;
+    file.sync()?;
+    file.close()?;
+    Ok(())
+}
+
+fn size_mismatch(manifest: &Manifest, size: usize) -> DatabaseError {
+    DatabaseError::CorruptedBackup(format!(
+        "the blocks of the backup {} hold {size} bytes instead of {}",
+        manifest.info.id, manifest.info.size
+    ))
+}
+
+/// Returns the name of a block: the XXH64 hash of its content, in hexadecimal.
+fn block_name(block: &[u8]) -> String {
+    format!("{:016x}", xxhash64(block, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::TempDir;
+    use crate::db::{Durability, Options};
+
+    use super::*;
+
+    fn open_database(directory: &TempDir) -> Database {
+        let options = Options::new().durability(Durability::Off);
+        Database::open(directory.path().join("database"), options).expect("open should not fail")
+    }
+
+    /// Returns the data file of a backup, assembled from its blocks.
+    fn backup_data(engine: &BackupEngine, id: u32) -> Vec<u8> {
+        let manifest = engine
+            .read_manifest(id)
+            .expect("read_manifest should not fail");
+        manifest
+            .blocks
+            .iter()
+            .flat_map(|name| std::fs::read(engine.block_path(name)).expect("read should not fail"))
+            .collect()
+    }
+
+    /// Returns the data file of a database as of its last commit.
+    fn database_data(database: &Database) -> Vec<u8> {
+        let file = database.pager().file();
+        let mut data = vec![0; file.size().expect("size should not fail")];
+        file.read(0, &mut data).expect("read should not fail");
+        data
+    }
+
+    /// A backup holds the database as of its last commit, including the commits that were not
+    /// copied to the data file or synced, and only writes the blocks that changed since the
+    /// previous backup.
+    #[test]
+    fn create_backup_writes_changed_blocks() {
+        let directory = TempDir::new();
+        let mut database = open_database(&directory);
+        for index in 0..2000u32 {
+            database
+                .put(&index.to_be_bytes(), &[index as u8; 100])
+                .expect("put should not fail");
+        }
+        let mut engine =
+            BackupEngine::open(directory.path().join("backups")).expect("open should not fail");
+
+        let first = engine
+            .create_backup(&database)
+            .expect("create_backup should not fail");
+        let first_data = database_data(&database);
+        database
+            .put(&1999u32.to_be_bytes(), b"changed")
+            .expect("put should not fail");
+        let second = engine
+            .create_backup(&database)
+            .expect("create_backup should not fail");
+        let reopened =
+            BackupEngine::open(directory.path().join("backups")).expect("open should not fail");
+
+        assert_eq!((first.id, second.id), (1, 2));
+        assert_eq!(first.version, 2000);
+        assert_eq!(first.written, first.size);
+        assert!(second.written > 0 && second.written < second.size / 2);
+        assert_eq!(reopened.backups(), &[first, second]);
+        assert_eq!(backup_data(&engine, 1), first_data);
+        assert_eq!(backup_data(&engine, 2), database_data(&database));
+    }
+
+    /// Purging removes the oldest backups and the blocks only they held, as well as the blocks of
+    /// interrupted backups.
+    #[test]
+    fn purge_old_backups_removes_unused_blocks() {
+        let directory = TempDir::new();
+        let mut database = open_database(&directory);
+        let mut engine =
+            BackupEngine::open(directory.path().join("backups")).expect("open should not fail");
+        for round in 0..3u8 {
+            for index in 0..1000u32 {
+                database
+                    .put(&index.to_be_bytes(), &[round; 100])
+                    .expect("put should not fail");
+            }
+            engine
+                .create_backup(&database)
+                .expect("create_backup should not fail");
+        }
+        engine
+            .store_block(b"interrupted")
+            .expect("store_block should not fail");
+
+        engine
+            .purge_old_backups(1)
+            .expect("purge_old_backups should not fail");
+        let blocks = std::fs::read_dir(directory.path().join("backups").join(BLOCKS_DIRECTORY))
+            .expect("read_dir should not fail")
+            .count();
+        let manifest = engine
+            .read_manifest(3)
+            .expect("read_manifest should not fail");
+
+        assert_eq!(
+            engine
+                .backups()
+                .iter()
+                .map(|backup| backup.id)
+                .collect::<Vec<_>>(),
+            vec![3]
+        );
+        assert!(engine.read_manifest(1).is_err());
+        assert_eq!(blocks, manifest.blocks.iter().collect::<HashSet<_>>().len());
+        assert_eq!(backup_data(&engine, 3), database_data(&database));
+    }
+
+    /// A restored backup is opened as the database it was made from, and a restore never
+    /// overwrites a database.
+    #[test]
+    fn restore_creates_database() {
+        let directory = TempDir::new();
+        let mut database = open_database(&directory);
+        for index in 0..2000u32 {
+            database
+                .put(&index.to_be_bytes(), &index.to_le_bytes())
+                .expect("put should not fail");
+        }
+        let mut engine =
+            BackupEngine::open(directory.path().join("backups")).expect("open should not fail");
+        let backup = engine
+            .create_backup(&database)
+            .expect("create_backup should not fail");
+        database
+            .put(b"after", b"backup")
+            .expect("put should not fail");
+
+        engine
+            .restore(backup.id, directory.path().join("restored"))
+            .expect("restore should not fail");
+        let again = engine.restore(backup.id, directory.path().join("restored"));
+        let missing = engine.restore(backup.id + 1, directory.path().join("missing"));
+        let restored = Database::open(directory.path().join("restored"), Options::new())
+            .expect("open should not fail");
+
+        assert_eq!(restored.version(), backup.version);
+        for index in 0..2000u32 {
+            assert_eq!(
+                restored
+                    .get(&index.to_be_bytes())
+                    .expect("get should not fail"),
+                Some(index.to_le_bytes().to_vec())
+            );
+        }
+        assert_eq!(restored.get(b"after").expect("get should not fail"), None);
+        assert!(matches!(again, Err(DatabaseError::AlreadyExists(_))));
+        assert!(matches!(missing, Err(DatabaseError::BackupNotFound(2))));
+    }
+
+    /// Verifying a backup detects its damaged and missing blocks.
+    #[test]
+    fn verify_backup_detects_corruption() {
+        let directory = TempDir::new();
+        let mut database = open_database(&directory);
+        for index in 0..2000u32 {
+            database
+                .put(&index.to_be_bytes(), &[1; 100])
+                .expect("put should not fail");
+        }
+        let mut engine =
+            BackupEngine::open(directory.path().join("backups")).expect("open should not fail");
+        let backup = engine
+            .create_backup(&database)
+            .expect("create_backup should not fail");
+        let blocks = engine
+            .read_manifest(backup.id)
+            .expect("read_manifest should not fail")
+            .blocks;
+
+        engine
+            .verify_backup(backup.id)
+            .expect("verify_backup should not fail");
+        let damaged_path = engine.block_path(&blocks[0]);
+        let mut damaged = std::fs::read(&damaged_path).expect("read should not fail");
+        damaged[100] ^= 1;
+        std::fs::write(&damaged_path, damaged).expect("write should not fail");
+        let with_damaged = engine.verify_backup(backup.id);
+        let restored = engine.restore(backup.id, directory.path().join("restored"));
+        std::fs::remove_file(&damaged_path).expect("remove_file should not fail");
+        let with_missing = engine.verify_backup(backup.id);
+
+        assert!(matches!(
+            with_damaged,
+            Err(DatabaseError::CorruptedBackup(_))
+        ));
+        assert!(matches!(restored, Err(DatabaseError::CorruptedBackup(_))));
+        assert!(matches!(
+            with_missing,
+            Err(DatabaseError::CorruptedBackup(_))
+        ));
+        assert!(!directory
+            .path()
+            .join("restored")
+            .join(DATA_FILE_NAME)
+            .exists());
+    }
+}
@@ -0,0 +1,440 @@
+use std::sync::Arc;
+
+use crate::btree::{comparator_by_name, CowTree};
+use crate::common::layout::Layout;
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+use crate::{assert_layout_size, disk_layout};
+
+use super::compressor::Compressor;
+#[cfg(test)]
+use super::compressor::MAX_COMPRESSOR_ID;
+use super::{Compression, DatabaseError, KeyspaceOptions};
+
+/// Magic bytes at the start of the page of the catalog.
+const CATALOG_MAGIC: [u8; 8] = *b"ROUICAT1";
+
+/// Version of the format of the catalog.
+const CATALOG_VERSION: u16 = 1;
+
+/// Longest name of a keyspace, in bytes.
+pub(super) const MAX_KEYSPACE_NAME_LEN: usize = u8::MAX as usize;
+
+disk_layout! {
+    /// The start of the page of the catalog, followed by its keyspaces.
+    struct CatalogHeader {
+        magic: [u8; 8],
+        version: u16,
+        count: u16,
+    }
+}
+
+assert_layout_size!(CatalogHeader, 12);
+
+disk_layout! {
+    /// The fixed part of a keyspace of the catalog, followed by its name and the name of its
+    /// comparator.
+    struct KeyspaceEntry: version CATALOG_VERSION {
+        meta: PageId,
+        /// The [Compression::id] of the compression of the values.
+        compression: u8,
+        name_len: u8,
+        comparator_len: u8,
+    }
+}
+
+assert_layout_size!(KeyspaceEntry, 7);
+
+/// Describes a keyspace of a [Database](super::Database), returned by
+/// [Database::keyspaces](super::Database::keyspaces): a tree of its own in the data file, with
+/// its own comparator and compression, recorded in the catalog of the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyspaceInfo {
+    name: String,
+    meta: PageId,
+    comparator: String,
+    compression: Compression,
+}
+
+impl KeyspaceInfo {
+    /// Returns the name of the keyspace.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the meta page of the tree of the keyspace, which records its root page.
+    pub fn meta_page(&self) -> PageId {
+        self.meta
+    }
+
+    /// Returns the name of the comparator ordering the keys of the keyspace.
+    pub fn comparator(&self) -> &str {
+        &self.comparator
+    }
+
+    /// Returns how the values of the keyspace are compressed.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+}
+
+/// A keyspace of the catalog and its open tree.
+pub(super) struct Keyspace {
+    pub(super) info: KeyspaceInfo,
+    pub(super) tree: CowTree,
+    /// The compressor of the values written, or `None` if they are stored as is.
+    pub(super) compressor: Option<Arc<dyn Compressor>>,
+}
+
+/// The keyspaces of a database, stored in a page of the data file recorded in its header (see
+/// [Pager::catalog]). A database created before the catalog existed has none.
+#[derive(Default)]
+pub(super) struct Catalog {
+    keyspaces: Vec<Keyspace>,
+}
+
+impl Catalog {
+    /// Reads the catalog of a file and opens the trees of its keyspaces, checking that it is
+    /// consistent with the file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the catalog is corrupted, if a keyspace uses a
+    /// comparator or a compression that is not available, or if its tree can't be opened.
+    pub(super) fn load<F: File>(pager: &Pager<F>) -> Result<Self, DatabaseError> {
+        let corrupted = |message: String| DatabaseError::CorruptedCatalog(message);
+        let page_id = pager.catalog();
+        if page_id == 0 {
+            return Ok(Catalog::default());
+        }
+        let page = pager.read_page(page_id)?;
+        let header = CatalogHeader::read(&page)
+            .map_err(|_| corrupted("the page is too small".to_string()))?;
+        if header.magic != CATALOG_MAGIC {
+            return Err(corrupted(format!("page {page_id} is not a catalog")));
+        }
+        let mut offset = CatalogHeader::SIZE;
+        let entry_size = KeyspaceEntry::size_of_version(header.version)
+            .ok_or_else(|| corrupted(format!("the version {} is unknown", header.version)))?;
+
+        let mut catalog = Catalog::default();
+        for _ in 0..header.count {
+            let truncated = || corrupted("a keyspace is truncated".to_string());
+            let entry = KeyspaceEntry::read_version(&page[offset..], header.version)
+                .map_err(|_| truncated())?;
+            offset += entry_size;
+            let mut read_name = |len: u8| {
+                let bytes = page
+                    .get(offset..offset + usize::from(len))
+                    .ok_or_else(truncated)?;
+                offset += usize::from(len);
+                String::from_utf8(bytes.to_vec())
+                    .map_err(|_| corrupted("a name is not valid UTF-8".to_string()))
+            };
+            let name = read_name(entry.name_len)?;
+            let comparator_name = read_name(entry.comparator_len)?;
+            if name.is_empty() || catalog.get(&name).is_some() {
+                return Err(corrupted(format!(
+                    "the keyspace name \"{name}\" is invalid"
+                )));
+            }
+            let comparator = comparator_by_name(&comparator_name).ok_or_else(|| {
+                corrupted(format!(
+                    "the comparator \"{comparator_name}\" of the keyspace \"{name}\" is unknown"
+                ))
+            })?;
+            let compression = Compression::from_id(entry.compression).ok_or_else(|| {
+                corrupted(format!(
+                    "the compression {} of the keyspace \"{name}\" is not available",
+                    entry.compression
+                ))
+            })?;
+            let tree = CowTree::open_unchecked(pager, entry.meta, comparator)?;
+            catalog.keyspaces.push(Keyspace {
+                info: KeyspaceInfo {
+                    name,
+                    meta: entry.meta,
+                    comparator: comparator_name,
+                    compression,
+                },
+                tree,
+                compressor: compression.compressor(),
+            });
+        }
+        Ok(catalog)
+    }
+
+    /// Returns the descriptions of the keyspaces, in the order they were created.
+    pub(super) fn infos(&self) -> impl Iterator<Item = &KeyspaceInfo> {
+        self.keyspaces.iter().map(|keyspace| &keyspace.info)
+    }
+
+    /// Returns the keyspace named `name`, if there is one.
+    pub(super) fn get(&self, name: &str) -> Option<&Keyspace> {
+        self.keyspaces
+            .iter()
+            .find(|keyspace| keyspace.info.name == name)
+    }
+
+    /// Returns the keyspace named `name`, if there is one.
+    pub(super) fn get_mut(&mut self, name: &str) -> Option<&mut Keyspace> {
+        self.keyspaces
+            .iter_mut()
+            .find(|keyspace| keyspace.info.name == name)
+    }
+
+    /// Creates a keyspace with an empty tree and writes the catalog. The caller syncs the pager
+    /// to commit it.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the name is empty, too long, or already the name of a keyspace
+    /// - the options name an unknown comparator
+    /// - the catalog would not fit in a page
+    /// - a page can't be allocated or written
+    pub(super) fn create<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        name: &str,
+        options: &KeyspaceOptions,
+    ) -> Result<(), DatabaseError> {
+        if name.is_empty() || name.len() > MAX_KEYSPACE_NAME_LEN {
+            return Err(DatabaseError::InvalidOptions(format!(
+                "the name of a keyspace must be 1 to {MAX_KEYSPACE_NAME_LEN} bytes long"
+            )));
+        }
+        if self.get(name).is_some() {
+            return Err(DatabaseError::KeyspaceExists(name.to_string()));
+        }
+        let comparator = options.validate()?;
+        let size =
+            self.encoded_size() + KeyspaceEntry::SIZE + name.len() + options.comparator.len();
+        if size > pager.page_size() {
+            return Err(DatabaseError::CatalogFull);
+        }
+
+        let tree = CowTree::create_unchecked(pager, comparator)?;
+        self.keyspaces.push(Keyspace {
+            info: KeyspaceInfo {
+                name: name.to_string(),
+                meta: tree.meta(),
+                comparator: options.comparator.clone(),
+                compression: options.compression,
+            },
+            tree,
+            compressor: options.compression.compressor(),
+        });
+        self.write(pager)
+    }
+
+    /// Removes a keyspace, frees the pages of its tree and writes the catalog. The caller syncs
+    /// the pager to commit it.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if there is no keyspace named `name`, or if a page can't
+    /// be read, written or freed.
+    pub(super) fn remove<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        name: &str,
+    ) -> Result<(), DatabaseError> {
+        let index = self
+            .keyspaces
+            .iter()
+            .position(|keyspace| keyspace.info.name == name)
+            .ok_or_else(|| DatabaseError::KeyspaceNotFound(name.to_string()))?;
+        let keyspace = self.keyspaces.remove(index);
+        keyspace.tree.destroy(pager)?;
+        self.write(pager)
+    }
+
+    /// Returns the number of bytes of the encoded catalog.
+    fn encoded_size(&self) -> usize {
+        CatalogHeader::SIZE
+            + self
+                .infos()
+                .map(|info| KeyspaceEntry::SIZE + info.name.len() + info.comparator.len())
+                .sum::<usize>()
+    }
+
+    /// Writes the catalog to its page, allocated the first time it is written.
+    fn write<F: File>(&self, pager: &mut Pager<F>) -> Result<(), DatabaseError> {
+        let mut page = Vec::with_capacity(pager.page_size());
+        let header = CatalogHeader {
+            magic: CATALOG_MAGIC,
+            version: CATALOG_VERSION,
+            count: self.keyspaces.len() as u16,
+        };
+        header.append(&mut page);
+        for info in self.infos() {
+            let entry = KeyspaceEntry {
+                meta: info.meta,
+                compression: info.compression.id(),
+                name_len: info.name.len() as u8,
+                comparator_len: info.comparator.len() as u8,
+            };
+            entry.append(&mut page);
+            page.extend_from_slice(info.name.as_bytes());
+            page.extend_from_slice(info.comparator.as_bytes());
+        }
+        debug_assert!(page.len() <= pager.page_size());
+        page.resize(pager.page_size(), 0);
+
+        let mut page_id = pager.catalog();
+        if page_id == 0 {
+            page_id = pager.allocate_page()?;
+            pager.set_catalog(page_id)?;
+        }
+        pager.write_page(page_id, &page)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    /// Returns a pager over a new file in memory, with small pages.
+    fn create_pager() -> Pager<MemoryFile> {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        Pager::create(file, 512).expect("create should not fail")
+    }
+
+    /// Writes `entry`, followed by `name` and `comparator`, as the only keyspace of a catalog.
+    fn write_catalog(
+        pager: &mut Pager<MemoryFile>,
+        entry: KeyspaceEntry,
+        name: &[u8],
+        comparator: &[u8],
+    ) {
+        let mut page = Vec::new();
+        let header = CatalogHeader {
+            magic: CATALOG_MAGIC,
+            version: CATALOG_VERSION,
+            count: 1,
+        };
+        header.append(&mut page);
+        entry.append(&mut page);
+        page.extend_from_slice(name);
+        page.extend_from_slice(comparator);
+        page.resize(pager.page_size(), 0);
+        let page_id = pager
+            .allocate_page()
+            .expect("allocate_page should not fail");
+        pager
+            .write_page(page_id, &page)
+            .expect("write_page should not fail");
+        pager
+            .set_catalog(page_id)
+            .expect("set_catalog should not fail");
+    }
+
+    /// The keyspaces created are loaded back from the file with their options, and the removed
+    /// ones are not.
+    #[test]
+    fn load_created_keyspaces_returns_keyspaces() {
+        let mut pager = create_pager();
+        let mut catalog = Catalog::load(&pager).expect("load should not fail");
+        let reverse = KeyspaceOptions::new()
+            .comparator("rouilledb.reverse-bytewise")
+            .compression(Compression::Lz);
+
+        catalog
+            .create(&mut pager, "users", &KeyspaceOptions::new())
+            .expect("create should not fail");
+        catalog
+            .create(&mut pager, "events", &reverse)
+            .expect("create should not fail");
+        catalog
+            .create(&mut pager, "logs", &KeyspaceOptions::new())
+            .expect("create should not fail");
+        catalog
+            .remove(&mut pager, "users")
+            .expect("remove should not fail");
+        let pager = Pager::open(pager.into_file()).expect("open should not fail");
+        let loaded = Catalog::load(&pager).expect("load should not fail");
+
+        assert_eq!(
+            loaded.infos().collect::<Vec<_>>(),
+            catalog.infos().collect::<Vec<_>>()
+        );
+        let events = loaded.get("events").expect("events should be loaded");
+        assert_eq!(events.info.comparator(), "rouilledb.reverse-bytewise");
+        assert_eq!(events.info.compression(), Compression::Lz);
+        assert!(loaded.get("users").is_none());
+    }
+
+    /// A keyspace with an invalid or existing name, an unknown comparator, or that does not fit
+    /// in the page of the catalog is not created.
+    #[test]
+    fn create_invalid_keyspace_fails() {
+        let mut pager = create_pager();
+        let mut catalog = Catalog::default();
+        catalog
+            .create(&mut pager, "users", &KeyspaceOptions::new())
+            .expect("create should not fail");
+        let long_name = "x".repeat(250);
+
+        let empty = catalog.create(&mut pager, "", &KeyspaceOptions::new());
+        let too_long = catalog.create(&mut pager, &"x".repeat(256), &KeyspaceOptions::new());
+        let existing = catalog.create(&mut pager, "users", &KeyspaceOptions::new());
+        let unknown = catalog.create(
+            &mut pager,
+            "events",
+            &KeyspaceOptions::new().comparator("unknown"),
+        );
+        let first = catalog.create(&mut pager, &long_name, &KeyspaceOptions::new());
+        let full = catalog.create(&mut pager, &long_name[1..], &KeyspaceOptions::new());
+        let missing = catalog.remove(&mut pager, "events");
+
+        assert!(matches!(empty, Err(DatabaseError::InvalidOptions(_))));
+        assert!(matches!(too_long, Err(DatabaseError::InvalidOptions(_))));
+        assert!(matches!(existing, Err(DatabaseError::KeyspaceExists(_))));
+        assert!(matches!(unknown, Err(DatabaseError::InvalidOptions(_))));
+        assert!(first.is_ok());
+        assert!(matches!(full, Err(DatabaseError::CatalogFull)));
+        assert!(matches!(missing, Err(DatabaseError::KeyspaceNotFound(_))));
+        assert_eq!(catalog.infos().count(), 2);
+    }
+
+    /// A catalog naming a comparator or a compression that is not available, or whose page is
+    /// not a catalog, is rejected.
+    #[test]
+    fn load_corrupted_catalog_fails() {
+        let comparator = b"rouilledb.bytewise";
+        let entry = |compression: u8, comparator_len: usize| KeyspaceEntry {
+            meta: 1,
+            compression,
+            name_len: 5,
+            comparator_len: comparator_len as u8,
+        };
+        let mut unknown_comparator = create_pager();
+        write_catalog(&mut unknown_comparator, entry(0, 7), b"users", b"unknown");
+        let mut unknown_compression = create_pager();
+        write_catalog(
+            &mut unknown_compression,
+            entry(MAX_COMPRESSOR_ID, comparator.len()),
+            b"users",
+            comparator,
+        );
+        let mut not_catalog = create_pager();
+        let page_id = not_catalog
+            .allocate_page()
+            .expect("allocate_page should not fail");
+        not_catalog
+            .set_catalog(page_id)
+            .expect("set_catalog should not fail");
+
+        for pager in [unknown_comparator, unknown_compression, not_catalog] {
+            assert!(matches!(
+                Catalog::load(&pager),
+                Err(DatabaseError::CorruptedCatalog(_))
+            ));
+        }
+    }
+}
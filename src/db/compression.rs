@@ -0,0 +1,387 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::checksum::crc32c;
+
+#[cfg(feature = "lz4")]
+use super::compressor::Lz4Compressor;
+#[cfg(feature = "snappy")]
+use super::compressor::SnappyCompressor;
+#[cfg(feature = "zstd")]
+use super::compressor::ZstdCompressor;
+use super::compressor::{Compressor, Compressors, LzCompressor, MAX_COMPRESSOR_ID};
+use super::DatabaseError;
+
+/// Tag of a value stored as is, which no [Compressor] can use. The tag of a compressed value is
+/// the identifier of its compressor.
+pub(super) const RAW_TAG: u8 = 0;
+
+/// Size of the header of a stored value: its tag and its checksum.
+pub(super) const HEADER_SIZE: usize = 5;
+
+/// Values shorter than this are never compressed, since they would hardly shrink.
+const MIN_COMPRESSED_SIZE: usize = 32;
+
+/// Shortest match copied from the previous bytes.
+const MIN_MATCH: usize = 4;
+
+/// Longest match copied from the previous bytes, the longest a match token can encode.
+const MAX_MATCH: usize = MIN_MATCH + 0x7f;
+
+/// Longest run of literal bytes a literal token can encode.
+const MAX_LITERALS: usize = 0x80;
+
+/// Farthest a match can be from the bytes it is copied to.
+const MAX_OFFSET: usize = u16::MAX as usize;
+
+/// Number of bits of the hashes of the sequences of [MIN_MATCH] bytes used to find matches.
+const HASH_BITS: u32 = 12;
+
+/// The format of a stored value, recorded in the first byte of its header, so the values written
+/// in a new format are told apart from the others: a version of the crate that does not know a
+/// format reports its values as unsupported instead of misreading them. The tags above
+/// [MAX_COMPRESSOR_ID] are reserved for the formats that are not compressions, such as encrypted
+/// values, merge operands or typed records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ValueFormat {
+    /// The value is stored as is.
+    Raw,
+    /// The value is compressed by the [Compressor] with this identifier.
+    Compressed(u8),
+}
+
+impl ValueFormat {
+    /// Returns the tag of the format.
+    pub(super) fn tag(self) -> u8 {
+        match self {
+            ValueFormat::Raw => RAW_TAG,
+            ValueFormat::Compressed(id) => id,
+        }
+    }
+
+    /// Returns the format of a tag, or `None` if the tag is reserved for a format to come.
+    pub(super) fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            RAW_TAG => Some(ValueFormat::Raw),
+            1..=MAX_COMPRESSOR_ID => Some(ValueFormat::Compressed(tag)),
+            _ => None,
+        }
+    }
+}
+
+/// How the values of a [Database](super::Database) are compressed before they are stored.
+///
+/// Each stored value starts with a tag of its format, the identifier of its [Compressor] if it is
+/// compressed, so the values written with any compression can always be read, whatever the
+/// current option, as long as the feature of their compressor is enabled. It is followed by a
+/// checksum of the stored bytes, verified by the reads unless their
+/// [ReadOptions](super::ReadOptions) disable it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compression {
+    /// The values are stored as is.
+    #[default]
+    None,
+    /// The values are compressed with a simple LZ77 scheme, which replaces the sequences of bytes
+    /// found earlier in the value by their position. A value is only stored compressed if it
+    /// becomes smaller.
+    Lz,
+    /// The values are compressed with LZ4, by an [Lz4Compressor].
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// The values are compressed with Snappy, by a [SnappyCompressor].
+    #[cfg(feature = "snappy")]
+    Snappy,
+    /// The values are compressed with Zstandard, at its default level, by a [ZstdCompressor].
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the compression whose [Compression::id] is `id`, or `None` if there is none, or
+    /// if its feature is not enabled.
+    pub(super) fn from_id(id: u8) -> Option<Self> {
+        let compressions = [
+            Compression::None,
+            Compression::Lz,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+            #[cfg(feature = "snappy")]
+            Compression::Snappy,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd,
+        ];
+        compressions
+            .into_iter()
+            .find(|compression| compression.id() == id)
+    }
+
+    /// Returns the identifier of the compressor of the values, or the tag of the values stored as
+    /// is, which stands for the compression where it is recorded.
+    pub(super) fn id(self) -> u8 {
+        self.compressor()
+            .map_or(RAW_TAG, |compressor| compressor.id())
+    }
+
+    /// Returns the compressor of the values, or `None` if they are stored as is.
+    pub(super) fn compressor(self) -> Option<Arc<dyn Compressor>> {
+        match self {
+            Compression::None => None,
+            Compression::Lz => Some(Arc::new(LzCompressor)),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Some(Arc::new(Lz4Compressor)),
+            #[cfg(feature = "snappy")]
+            Compression::Snappy => Some(Arc::new(SnappyCompressor)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Some(Arc::new(ZstdCompressor::new(ZstdCompressor::DEFAULT_LEVEL))),
+        }
+    }
+}
+
+/// Returns the bytes to store for a value, compressed by `compressor` if it shrinks: the tag of
+/// its [ValueFormat], the CRC32C, on 4 bytes, of the rest of the bytes, and the value.
+pub(super) fn encode_value(compressor: Option<&dyn Compressor>, value: &[u8]) -> Vec<u8> {
+    if let Some(compressor) = compressor.filter(|_| value.len() >= MIN_COMPRESSED_SIZE) {
+        if let Some(compressed) = compressor.compress(value) {
+            if compressed.len() < value.len() {
+                return with_header(ValueFormat::Compressed(compressor.id()), &compressed);
+            }
+        }
+    }
+    with_header(ValueFormat::Raw, value)
+}
+
+/// Returns the value stored as `stored` by [encode_value], read according to its [ValueFormat]:
+/// decompressed by the compressor of `compressors` registered with its tag, or as is. The checksum
+/// is only compared with the stored bytes if `verify_checksum` is set.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// - the stored bytes are not a valid encoded value
+/// - their checksum is verified and does not match
+/// - no compressor is registered with their tag
+/// - their format is reserved, or can't be read by this version of the crate
+pub(super) fn decode_value(
+    mut stored: Vec<u8>,
+    verify_checksum: bool,
+    compressors: &Compressors,
+) -> Result<Vec<u8>, DatabaseError> {
+    if stored.len() < HEADER_SIZE {
+        return Err(DatabaseError::CorruptedValue);
+    }
+    let checksum = u32::from_le_bytes(
+        stored[1..HEADER_SIZE]
+            .try_into()
+            .expect("slice should be 4 bytes"),
+    );
+    let tag = stored[0];
+    if verify_checksum && crc32c(&stored[HEADER_SIZE..]) != checksum {
+        return Err(DatabaseError::CorruptedValue);
+    }
+    match ValueFormat::from_tag(tag) {
+        Some(ValueFormat::Raw) => {
+            stored.drain(..HEADER_SIZE);
+            Ok(stored)
+        }
+        Some(ValueFormat::Compressed(id)) => compressors
+            .get(id)
+            .ok_or(DatabaseError::UnknownCompressor(id))?
+            .decompress(&stored[HEADER_SIZE..])
+            .ok_or(DatabaseError::CorruptedValue),
+        None => Err(DatabaseError::UnsupportedValueFormat(tag)),
+    }
+}
+
+/// Returns the header for `bytes` stored in a format, followed by the bytes.
+fn with_header(format: ValueFormat, bytes: &[u8]) -> Vec<u8> {
+    let mut stored = Vec::with_capacity(HEADER_SIZE + bytes.len());
+    stored.push(format.tag());
+    stored.extend_from_slice(&crc32c(bytes).to_le_bytes());
+    stored.extend_from_slice(bytes);
+    stored
+}
+
+/// Compresses bytes: their length, on 4 bytes, followed by tokens. A token byte below `0x80` is
+/// followed by that number plus one of literal bytes. Otherwise, its 7 low bits plus [MIN_MATCH]
+/// are the length of a match, and it is followed by the distance, on 2 bytes, back to the bytes
+/// to copy.
+pub(super) fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut literals_start = 0;
+    let mut position = 0;
+    while position + MIN_MATCH <= data.len() {
+        let slot = &mut table[sequence_hash(&data[position..position + MIN_MATCH])];
+        let candidate = std::mem::replace(slot, position);
+        let length = if candidate != usize::MAX && position - candidate <= MAX_OFFSET {
+            data[candidate..]
+                .iter()
+                .zip(&data[position..])
+                .take(MAX_MATCH)
+                .take_while(|(a, b)| a == b)
+                .count()
+        } else {
+            0
+        };
+        if length < MIN_MATCH {
+            position += 1;
+            continue;
+        }
+        push_literals(&mut output, &data[literals_start..position]);
+        output.push(0x80 | (length - MIN_MATCH) as u8);
+        output.extend_from_slice(&((position - candidate) as u16).to_le_bytes());
+        position += length;
+        literals_start = position;
+    }
+    push_literals(&mut output, &data[literals_start..]);
+    output
+}
+
+/// Returns the bytes compressed by [compress], or `None` if the compressed bytes are invalid.
+pub(super) fn decompress(compressed: &[u8]) -> Option<Vec<u8>> {
+    let (length, mut tokens) = compressed.split_first_chunk::<4>()?;
+    let length = u32::from_le_bytes(*length) as usize;
+    let mut data = Vec::with_capacity(length);
+    while let Some((&token, rest)) = tokens.split_first() {
+        if token < 0x80 {
+            let count = usize::from(token) + 1;
+            data.extend_from_slice(rest.get(..count)?);
+            tokens = &rest[count..];
+        } else {
+            let (offset, rest) = rest.split_first_chunk::<2>()?;
+            let offset = usize::from(u16::from_le_bytes(*offset));
+            let start = data.len().checked_sub(offset).filter(|_| offset > 0)?;
+            // The match may overlap the bytes it produces, so it is copied byte by byte.
+            for index in 0..usize::from(token & 0x7f) + MIN_MATCH {
+                data.push(data[start + index]);
+            }
+            tokens = rest;
+        }
+        if data.len() > length {
+            return None;
+        }
+    }
+    (data.len() == length).then_some(data)
+}
+
+/// Writes literal bytes as literal tokens.
+fn push_literals(output: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        output.push((chunk.len() - 1) as u8);
+        output.extend_from_slice(chunk);
+    }
+}
+
+/// Returns the slot of a sequence of [MIN_MATCH] bytes in the table of previous positions.
+fn sequence_hash(sequence: &[u8]) -> usize {
+    let word = u32::from_le_bytes(sequence.try_into().expect("slice should be 4 bytes"));
+    (word.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::RandomBlob;
+
+    use super::*;
+
+    /// Returns the compressors of a database storing its values as is.
+    fn compressors() -> Compressors {
+        Compressors::new(Compression::None)
+    }
+
+    /// Values are decoded back whether they are compressed or not, and repetitive values shrink.
+    #[test]
+    fn decode_encoded_value_returns_value() {
+        let repetitive: Vec<u8> = b"abcabcabd".repeat(200);
+        let random = RandomBlob::new(1000).data().to_vec();
+        let mut overlapping = vec![7; 300];
+        overlapping.extend_from_slice(b"end");
+
+        for value in [&repetitive, &random, &overlapping, &b"short".to_vec()] {
+            for compressor in [None, Some(&LzCompressor as &dyn Compressor)] {
+                let stored = encode_value(compressor, value);
+                let decoded = decode_value(stored, true, &compressors())
+                    .expect("decode_value should not fail");
+                assert_eq!(&decoded, value);
+            }
+        }
+        assert!(encode_value(Some(&LzCompressor), &repetitive).len() < repetitive.len() / 10);
+        assert_eq!(
+            encode_value(Some(&LzCompressor), &random).len(),
+            random.len() + HEADER_SIZE
+        );
+    }
+
+    /// Invalid stored bytes are reported as corrupted.
+    #[test]
+    fn decode_invalid_value_fails() {
+        let compressors = compressors();
+        let mut truncated = encode_value(Some(&LzCompressor), &[1; 100]);
+        truncated.truncate(truncated.len() - 1);
+        let invalid_match = with_header(
+            ValueFormat::Compressed(LzCompressor.id()),
+            &[5, 0, 0, 0, 0x80, 1, 0],
+        );
+
+        for invalid in [vec![], vec![RAW_TAG, 0, 0], invalid_match] {
+            assert!(matches!(
+                decode_value(invalid, true, &compressors),
+                Err(DatabaseError::CorruptedValue)
+            ));
+        }
+        assert!(matches!(
+            decode_value(truncated.clone(), true, &compressors),
+            Err(DatabaseError::CorruptedValue)
+        ));
+        assert!(matches!(
+            decode_value(truncated, false, &compressors),
+            Err(DatabaseError::CorruptedValue)
+        ));
+        assert!(matches!(
+            decode_value(
+                with_header(ValueFormat::Compressed(MAX_COMPRESSOR_ID), &[0]),
+                true,
+                &compressors
+            ),
+            Err(DatabaseError::UnknownCompressor(MAX_COMPRESSOR_ID))
+        ));
+    }
+
+    /// The values whose tag is reserved for a format to come are reported as unsupported, and
+    /// every tag of a known format is the tag of its format.
+    #[test]
+    fn decode_value_checks_format() {
+        let compressors = compressors();
+
+        for tag in [MAX_COMPRESSOR_ID + 1, 0xa0, u8::MAX] {
+            let mut stored = vec![tag];
+            stored.extend_from_slice(&crc32c(b"value").to_le_bytes());
+            stored.extend_from_slice(b"value");
+            assert!(matches!(
+                decode_value(stored, true, &compressors),
+                Err(DatabaseError::UnsupportedValueFormat(unsupported)) if unsupported == tag
+            ));
+        }
+        for tag in 0..=u8::MAX {
+            let format = ValueFormat::from_tag(tag);
+            assert_eq!(format.map(ValueFormat::tag), format.map(|_| tag));
+        }
+    }
+
+    /// A damaged value is only detected by its checksum when it is verified.
+    #[test]
+    fn decode_damaged_value_fails_with_verification() {
+        let mut stored = encode_value(None, b"value");
+        stored[HEADER_SIZE] = b'V';
+
+        let verified = decode_value(stored.clone(), true, &compressors());
+        let unverified =
+            decode_value(stored, false, &compressors()).expect("decode_value should not fail");
+
+        assert!(matches!(verified, Err(DatabaseError::CorruptedValue)));
+        assert_eq!(unverified, b"Value".to_vec());
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::compression;
+use super::DatabaseError;
+
+/// The smallest identifier of the [Compressor]s defined outside of this crate.
+pub const MIN_CUSTOM_COMPRESSOR_ID: u8 = 32;
+
+/// The largest identifier of a [Compressor]. The tags of the stored values above it are reserved
+/// for the formats that are not compressions.
+pub const MAX_COMPRESSOR_ID: u8 = 0x7f;
+
+/// Identifier of the [LzCompressor].
+const LZ_ID: u8 = 1;
+
+/// Identifier of the [Lz4Compressor].
+#[cfg(feature = "lz4")]
+const LZ4_ID: u8 = 2;
+
+/// Identifier of the [SnappyCompressor].
+#[cfg(feature = "snappy")]
+const SNAPPY_ID: u8 = 3;
+
+/// Identifier of the [ZstdCompressor] without a dictionary.
+#[cfg(feature = "zstd")]
+const ZSTD_ID: u8 = 4;
+
+/// Compresses the values of a [Database](super::Database) before they are stored.
+///
+/// The identifier of the compressor is recorded in the header of each value it compresses, so the
+/// values of a database can be compressed by different compressors: a value is decompressed by the
+/// compressor registered with its identifier, whichever compressor the database writes with. The
+/// compressors selected by [Compression](super::Compression) are always registered, and the others
+/// are registered with [Database::set_compressor](super::Database::set_compressor).
+///
+/// The identifiers go from 1 to [MAX_COMPRESSOR_ID]. Those below [MIN_CUSTOM_COMPRESSOR_ID] are
+/// reserved for the compressors of this crate.
+pub trait Compressor: Send + Sync {
+    /// Returns the identifier recorded with the values compressed by the compressor.
+    fn id(&self) -> u8;
+
+    /// Returns the compressed bytes, or `None` if the bytes should be stored as is. The bytes are
+    /// also stored as is if they do not shrink.
+    fn compress(&self, data: &[u8]) -> Option<Vec<u8>>;
+
+    /// Returns the bytes compressed by [Compressor::compress], or `None` if they are corrupted.
+    fn decompress(&self, compressed: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// A simple LZ77 compressor, written for this crate, selected by
+/// [Compression::Lz](super::Compression::Lz).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LzCompressor;
+
+impl Compressor for LzCompressor {
+    fn id(&self) -> u8 {
+        LZ_ID
+    }
+
+    fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        Some(compression::compress(data))
+    }
+
+    fn decompress(&self, compressed: &[u8]) -> Option<Vec<u8>> {
+        compression::decompress(compressed)
+    }
+}
+
+/// The LZ4 block format, very fast to compress and decompress, selected by
+/// [Compression::Lz4](super::Compression::Lz4).
+#[cfg(feature = "lz4")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Lz4Compressor;
+
+#[cfg(feature = "lz4")]
+impl Compressor for Lz4Compressor {
+    fn id(&self) -> u8 {
+        LZ4_ID
+    }
+
+    fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        Some(lz4_flex::compress_prepend_size(data))
+    }
+
+    fn decompress(&self, compressed: &[u8]) -> Option<Vec<u8>> {
+        lz4_flex::decompress_size_prepended(compressed).ok()
+    }
+}
+
+/// The Snappy raw format, selected by [Compression::Snappy](super::Compression::Snappy).
+#[cfg(feature = "snappy")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SnappyCompressor;
+
+#[cfg(feature = "snappy")]
+impl Compressor for SnappyCompressor {
+    fn id(&self) -> u8 {
+        SNAPPY_ID
+    }
+
+    fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        snap::raw::Encoder::new().compress_vec(data).ok()
+    }
+
+    fn decompress(&self, compressed: &[u8]) -> Option<Vec<u8>> {
+        snap::raw::Decoder::new().decompress_vec(compressed).ok()
+    }
+}
+
+/// Zstandard, which compresses better than the other compressors at a higher cost, selected by
+/// [Compression::Zstd](super::Compression::Zstd) with the default level.
+///
+/// Since the values are compressed one by one, the small values barely shrink without a
+/// dictionary: a sample of typical values, or a dictionary trained from them, that both the
+/// compression and the decompression start from. A compressor with a dictionary must have an
+/// identifier of its own, and stay registered as long as values compressed with its dictionary
+/// are stored.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::TempDir;
+/// use rouilledb::db::{Database, Options, ZstdCompressor, MIN_CUSTOM_COMPRESSOR_ID};
+///
+/// let directory = TempDir::new();
+/// let mut database =
+///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+/// let dictionary = br#"{"name": "", "email": "@example.com", "admin": false}"#.repeat(4);
+/// database
+///     .set_compressor(Box::new(
+///         ZstdCompressor::new(19).dictionary(MIN_CUSTOM_COMPRESSOR_ID, dictionary),
+///     ))
+///     .expect("set_compressor should not fail");
+///
+/// let user = br#"{"name": "alice", "email": "alice@example.com", "admin": false}"#;
+/// database.put(b"user-1", user).expect("put should not fail");
+/// assert_eq!(database.get(b"user-1").expect("get should not fail"), Some(user.to_vec()));
+/// ```
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone)]
+pub struct ZstdCompressor {
+    id: u8,
+    level: i32,
+    dictionary: Vec<u8>,
+}
+
+#[cfg(feature = "zstd")]
+impl ZstdCompressor {
+    /// The level of compression of [Compression::Zstd](super::Compression::Zstd).
+    pub const DEFAULT_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+    /// Creates a compressor without a dictionary compressing at `level`, from 1, the fastest, to
+    /// 22, the smallest. The level does not matter to the decompression.
+    pub fn new(level: i32) -> Self {
+        ZstdCompressor {
+            id: ZSTD_ID,
+            level,
+            dictionary: Vec::new(),
+        }
+    }
+
+    /// Compresses with a dictionary, with the identifier `id`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `id` is below [MIN_CUSTOM_COMPRESSOR_ID] or above
+    /// [MAX_COMPRESSOR_ID].
+    pub fn dictionary(mut self, id: u8, dictionary: Vec<u8>) -> Self {
+        assert!(
+            (MIN_CUSTOM_COMPRESSOR_ID..=MAX_COMPRESSOR_ID).contains(&id),
+            "the identifier of a dictionary should be between {MIN_CUSTOM_COMPRESSOR_ID} and \
+             {MAX_COMPRESSOR_ID}"
+        );
+        self.id = id;
+        self.dictionary = dictionary;
+        self
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Compressor for ZstdCompressor {
+    fn id(&self) -> u8 {
+        self.id
+    }
+
+    fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        zstd::bulk::Compressor::with_dictionary(self.level, &self.dictionary)
+            .and_then(|mut compressor| compressor.compress(data))
+            .ok()
+    }
+
+    fn decompress(&self, compressed: &[u8]) -> Option<Vec<u8>> {
+        let mut decoder =
+            zstd::stream::read::Decoder::with_dictionary(compressed, &self.dictionary).ok()?;
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut decoder, &mut data).ok()?;
+        Some(data)
+    }
+}
+
+/// The compressors of a database: those registered to decompress the values, by identifier, and
+/// the one compressing the values written.
+#[derive(Clone)]
+pub(super) struct Compressors {
+    registered: HashMap<u8, Arc<dyn Compressor>>,
+    writer: Option<Arc<dyn Compressor>>,
+}
+
+impl Compressors {
+    /// Registers the compressors of this crate, and writes with the one selected by `compression`.
+    pub(super) fn new(compression: super::Compression) -> Self {
+        let built_in: Vec<Arc<dyn Compressor>> = vec![
+            Arc::new(LzCompressor),
+            #[cfg(feature = "lz4")]
+            Arc::new(Lz4Compressor),
+            #[cfg(feature = "snappy")]
+            Arc::new(SnappyCompressor),
+            #[cfg(feature = "zstd")]
+            Arc::new(ZstdCompressor::new(ZstdCompressor::DEFAULT_LEVEL)),
+        ];
+        let registered = built_in
+            .into_iter()
+            .map(|compressor| (compressor.id(), compressor))
+            .collect();
+        Compressors {
+            registered,
+            writer: compression.compressor(),
+        }
+    }
+
+    /// Registers a compressor defined outside of this crate, replacing the one with the same
+    /// identifier, and writes with it.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the identifier of the compressor is below
+    /// [MIN_CUSTOM_COMPRESSOR_ID] or above [MAX_COMPRESSOR_ID].
+    pub(super) fn set(&mut self, compressor: Arc<dyn Compressor>) -> Result<(), DatabaseError> {
+        let id = compressor.id();
+        if !(MIN_CUSTOM_COMPRESSOR_ID..=MAX_COMPRESSOR_ID).contains(&id) {
+            return Err(DatabaseError::InvalidCompressorId(id));
+        }
+        self.registered.insert(id, Arc::clone(&compressor));
+        self.writer = Some(compressor);
+        Ok(())
+    }
+
+    /// Returns the compressor of the values written, or `None` if they are stored as is.
+    pub(super) fn writer(&self) -> Option<&dyn Compressor> {
+        self.writer.as_deref()
+    }
+
+    /// Returns the compressor registered with an identifier.
+    pub(super) fn get(&self, id: u8) -> Option<&dyn Compressor> {
+        self.registered.get(&id).map(Arc::as_ref)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{RandomBlob, TempDir};
+    use crate::db::{Compression, Database, DatabaseError, Options};
+
+    use super::*;
+
+    /// A compressor defined outside of the crate, compressing as the [LzCompressor].
+    #[derive(Clone, Copy)]
+    struct LzCustom(u8);
+
+    impl Compressor for LzCustom {
+        fn id(&self) -> u8 {
+            self.0
+        }
+
+        fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+            LzCompressor.compress(data)
+        }
+
+        fn decompress(&self, compressed: &[u8]) -> Option<Vec<u8>> {
+            LzCompressor.decompress(compressed)
+        }
+    }
+
+    /// Each compressor decompresses the bytes it compressed, shrinks the repetitive bytes and
+    /// rejects the bytes it did not compress.
+    #[test]
+    fn compressors_decompress_compressed_bytes() {
+        let repetitive = b"a value repeated many times, ".repeat(100);
+        let random = RandomBlob::new(1000).data().to_vec();
+        let compressors: Vec<Box<dyn Compressor>> = vec![
+            Box::new(LzCompressor),
+            #[cfg(feature = "lz4")]
+            Box::new(Lz4Compressor),
+            #[cfg(feature = "snappy")]
+            Box::new(SnappyCompressor),
+            #[cfg(feature = "zstd")]
+            Box::new(ZstdCompressor::new(ZstdCompressor::DEFAULT_LEVEL)),
+            #[cfg(feature = "zstd")]
+            Box::new(
+                ZstdCompressor::new(3).dictionary(MIN_CUSTOM_COMPRESSOR_ID, repetitive.clone()),
+            ),
+        ];
+
+        for compressor in compressors {
+            for data in [&repetitive, &random, &Vec::new()] {
+                let compressed = compressor.compress(data).expect("compress should not fail");
+                assert_eq!(compressor.decompress(&compressed).as_ref(), Some(data));
+            }
+            let compressed = compressor
+                .compress(&repetitive)
+                .expect("compress should not fail");
+            assert!(compressed.len() < repetitive.len() / 4);
+            assert_eq!(compressor.decompress(&[0xff; 3]), None);
+        }
+    }
+
+    /// The values written by different compressors are read back from the same database, as long
+    /// as their compressors are registered.
+    #[test]
+    fn database_reads_values_of_several_compressors() {
+        let directory = TempDir::new();
+        let custom = LzCustom(MIN_CUSTOM_COMPRESSOR_ID);
+        let value = b"a compressible value, a compressible value".to_vec();
+        let options = Options::new().compression(Compression::Lz);
+        let mut database =
+            Database::open(directory.path(), options.clone()).expect("open should not fail");
+        database.put(b"lz", &value).expect("put should not fail");
+        database
+            .set_compressor(Box::new(custom))
+            .expect("set_compressor should not fail");
+        database
+            .put(b"custom", &value)
+            .expect("put should not fail");
+        database.close().expect("close should not fail");
+
+        let mut database = Database::open(directory.path(), options).expect("open should not fail");
+        let lz = database.get(b"lz").expect("get should not fail");
+        let unregistered = database.get(b"custom");
+        database
+            .set_compressor(Box::new(custom))
+            .expect("set_compressor should not fail");
+        let registered = database.get(b"custom").expect("get should not fail");
+
+        assert_eq!(lz, Some(value.clone()));
+        assert!(matches!(
+            unregistered,
+            Err(DatabaseError::UnknownCompressor(MIN_CUSTOM_COMPRESSOR_ID))
+        ));
+        assert_eq!(registered, Some(value));
+    }
+
+    /// A compressor is not registered with an identifier reserved for the compressors of this
+    /// crate or above the largest one.
+    #[test]
+    fn set_compressor_rejects_reserved_identifiers() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+
+        let reserved = database.set_compressor(Box::new(LzCustom(LZ_ID)));
+        let too_large = database.set_compressor(Box::new(LzCustom(MAX_COMPRESSOR_ID + 1)));
+
+        assert!(matches!(
+            reserved,
+            Err(DatabaseError::InvalidCompressorId(LZ_ID))
+        ));
+        assert!(matches!(
+            too_large,
+            Err(DatabaseError::InvalidCompressorId(id)) if id == MAX_COMPRESSOR_ID + 1
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::mpsc::Receiver;
+
+use super::Database;
+use crate::db::{ChangeEvent, WriteBatch, WriteStall};
+
+impl Database {
+    /// Sets the listener called after each commit stalled because the writes not yet copied to
+    /// the data file passed a threshold of the options (see [WriteStall]).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Durability, Options, StallKind};
+    ///
+    /// let directory = TempDir::new();
+    /// let options = Options::new()
+    ///     .durability(Durability::Off)
+    ///     .slowdown_pending_size(16 * 1024)
+    ///     .stop_pending_size(64 * 1024);
+    /// let mut database = Database::open(directory.path(), options).expect("open should not fail");
+    /// let stalls = Arc::new(Mutex::new(Vec::new()));
+    /// let listened = Arc::clone(&stalls);
+    /// database.set_stall_listener(move |stall| {
+    ///     let mut stalls = listened.lock().expect("the lock should not be poisoned");
+    ///     stalls.push(stall.kind);
+    /// });
+    ///
+    /// for i in 0..100u32 {
+    ///     database.put(&i.to_be_bytes(), &[0; 1000]).expect("put should not fail");
+    /// }
+    ///
+    /// let stalls = stalls.lock().expect("the lock should not be poisoned");
+    /// assert!(stalls.contains(&StallKind::Slowdown));
+    /// assert!(stalls.contains(&StallKind::Stop));
+    /// ```
+    pub fn set_stall_listener<L>(&mut self, listener: L)
+    where
+        L: Fn(&WriteStall) + Send + Sync + 'static,
+    {
+        self.stall_listener = Some(Box::new(listener));
+    }
+
+    /// Sets the hook called after each commit of writes, once they are committed, with the
+    /// writes of the commit: the batch given to [Database::write], including those of the
+    /// transactions, or a batch of the single write of the other methods. The commits writing
+    /// nothing, such as [Database::compact_range], don't call it. The hook is called from the
+    /// thread making the commit, which it delays.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// let commits = Arc::new(Mutex::new(Vec::new()));
+    /// let published = Arc::clone(&commits);
+    /// database.set_commit_hook(move |batch| {
+    ///     let mut commits = published.lock().expect("the lock should not be poisoned");
+    ///     commits.push(batch.len());
+    /// });
+    ///
+    /// database.put(b"a", b"1").expect("put should not fail");
+    /// let mut transaction = database.begin_txn();
+    /// transaction.put(b"b", b"2").expect("put should not fail");
+    /// transaction.delete(b"a").expect("delete should not fail");
+    /// transaction.commit(&mut database).expect("commit should not fail");
+    ///
+    /// let commits = commits.lock().expect("the lock should not be poisoned");
+    /// assert_eq!(*commits, vec![1, 2]);
+    /// ```
+    pub fn set_commit_hook<H>(&mut self, hook: H)
+    where
+        H: Fn(&WriteBatch) + Send + Sync + 'static,
+    {
+        self.commit_hook = Some(Box::new(hook));
+    }
+
+    /// Subscribes to the writes of the keys starting with `prefix`, which are sent to the receiver
+    /// returned once they are committed, in the order they are committed. Each write is sent as
+    /// it was made, so a merge sends its operand. The empty prefix subscribes to every write.
+    ///
+    /// The writes are sent after the commit hook is called (see [Database::set_commit_hook]). The
+    /// receiver keeps the writes until they are received, without bound, and the subscription
+    /// ends once it is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Change, Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// let users = database.watch(b"user-");
+    ///
+    /// database.put(b"user-1", b"alice").expect("put should not fail");
+    /// database.put(b"order-1", b"book").expect("put should not fail");
+    /// database.delete(b"user-1").expect("delete should not fail");
+    ///
+    /// let changes: Vec<_> = users.try_iter().map(|event| event.change).collect();
+    /// assert_eq!(changes, vec![Change::Put(b"alice".to_vec()), Change::Delete]);
+    /// ```
+    pub fn watch(&self, prefix: &[u8]) -> Receiver<ChangeEvent> {
+        self.watchers.subscribe(prefix)
+    }
+
+    /// Records a committed write of a single key, setting its value or removing it if `value` is
+    /// `None`, and notifies the commit hook and the watchers of it.
+    pub(super) fn committed_write(&self, key: &[u8], value: Option<&[u8]>) {
+        self.counters.record_write(key, value);
+        if self.commit_hook.is_some() || !self.watchers.is_empty() {
+            let mut batch = WriteBatch::new();
+            match value {
+                Some(value) => batch.put(key, value),
+                None => batch.delete(key),
+            }
+            self.notify_commit(&batch);
+        }
+    }
+
+    /// Calls the commit hook with the writes of the last commit, then sends them to the watchers
+    /// of their keys.
+    pub(super) fn notify_commit(&self, batch: &WriteBatch) {
+        if let Some(hook) = &self.commit_hook {
+            hook(batch);
+        }
+        self.watchers.notify(self.version(), batch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::TempDir;
+
+    use super::*;
+    use crate::db::Options;
+
+    /// Deleting a missing key commits nothing and does not notify the watchers of the key.
+    #[test]
+    fn delete_missing_key_notifies_nothing() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"a", b"1").expect("put should not fail");
+        let changes = database.watch(b"");
+        let version = database.version();
+
+        database.delete(b"b").expect("delete should not fail");
+        database.delete(b"a").expect("delete should not fail");
+
+        let deleted: Vec<_> = changes.try_iter().map(|event| event.key).collect();
+        assert_eq!(deleted, vec![b"a".to_vec()]);
+        assert_eq!(database.version(), version + 1);
+    }
+
+    /// Swapping a missing key for no value succeeds without committing anything or notifying the
+    /// watchers of the key.
+    #[test]
+    fn compare_and_swap_missing_key_notifies_nothing() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let changes = database.watch(b"");
+        let version = database.version();
+
+        let swapped = database
+            .compare_and_swap(b"a", None, None)
+            .expect("compare_and_swap should not fail");
+
+        assert_eq!(swapped, Ok(()));
+        assert_eq!(changes.try_iter().count(), 0);
+        assert_eq!(database.version(), version);
+    }
+}
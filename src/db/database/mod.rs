@@ -0,0 +1,3291 @@
+mod hooks;
+mod transactions;
+mod two_phase;
+mod values;
+
+use std::collections::{HashMap, HashSet};
+use std::io::{ErrorKind, Read, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::btree::{comparator_by_name, BTreeError, CowTree, KeyComparator, SalvageReport};
+use crate::common::envelope::Envelope;
+use crate::common::KeyRange;
+use crate::fs::{File, FileError, OsFile};
+use crate::lsm::MergeOperator;
+use crate::pager::{with_fill_cache, BufferPool, PageId, Pager, PagerError};
+use crate::wal::WalFile;
+
+use super::catalog::{Catalog, Keyspace, KeyspaceInfo};
+use super::compression::{decode_value, encode_value};
+use super::compressor::Compressors;
+use super::dump::{DumpReader, DumpWriter};
+use super::env::Reservation;
+use super::lock::{DatabaseLock, LOCK_FILE_NAME};
+use super::prepared::{self, PREPARED_DIRECTORY};
+use super::snapshot::SnapshotSlot;
+use super::stats::Counters;
+use super::transaction::LockTable;
+use super::txn_ids::TxnIdAllocator;
+#[cfg(feature = "serde")]
+use super::typed_tree::{self, TypedTree};
+use super::watch::Watchers;
+use super::{
+    BatchIter, CommitHook, DatabaseIter, DatabaseSnapshot, DatabaseStats, Durability, GcReport,
+    KeyspaceOptions, Options, PreparedId, ReadOptions, StallKind, StallListener, WriteBatch,
+    WriteStall,
+};
+
+/// Name of the file holding the pages of a database, in its directory.
+pub(super) const DATA_FILE_NAME: &str = "data";
+
+/// Name of the write-ahead log of a database, in its directory or in the log directory of its
+/// options.
+pub(super) const LOG_FILE_NAME: &str = "wal";
+
+/// Number of entries committed together by [Database::import] and [Database::repair].
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// Extension of the files of a database moved aside by [Database::repair].
+const DAMAGED_EXTENSION: &str = "damaged";
+
+/// Meta page of the tree of a database: the first page allocated in a new file.
+const TREE_META: PageId = 1;
+
+/// The file the pager of a database reads and writes: the data file and its log, with a cache of
+/// pages.
+pub(super) type DatabaseFile = BufferPool<WalFile<OsFile, OsFile>>;
+
+/// Represents errors that can occur during database operations.
+#[derive(Error, Debug)]
+pub enum DatabaseError {
+    /// Indicates that an operation on a file of the database failed.
+    #[error(transparent)]
+    File(#[from] FileError),
+
+    /// Indicates that an operation on the pager of the database failed.
+    #[error(transparent)]
+    Pager(#[from] PagerError),
+
+    /// Indicates that an operation on the tree of the database failed.
+    #[error(transparent)]
+    BTree(#[from] BTreeError),
+
+    /// Indicates that the database does not exist and the options do not allow to create it.
+    ///
+    /// # Fields
+    /// - `0` - A string representing the path of the database.
+    #[error("The database ({0}) does not exist.")]
+    NotFound(String),
+
+    /// Indicates that the database already exists and the options require a new one.
+    ///
+    /// # Fields
+    /// - `0` - A string representing the path of the database.
+    #[error("The database ({0}) already exists.")]
+    AlreadyExists(String),
+
+    /// Indicates that the database is opened for writing by another [Database], in this process
+    /// or in another one.
+    ///
+    /// # Fields
+    /// - `0` - A string representing the path of the database.
+    #[error("The database ({0}) is locked by another writer.")]
+    Locked(String),
+
+    /// Indicates that merge operands must be applied but no [MergeOperator] was set with
+    /// [Database::set_merge_operator].
+    #[error("A merge operand must be applied, but no merge operator is set.")]
+    MissingMergeOperator,
+
+    /// Indicates that a [WriteBatch] without an index is read before it is written.
+    #[error("The write batch can't be read because it is not indexed.")]
+    UnindexedBatch,
+
+    /// Indicates that the options of a database are invalid.
+    ///
+    /// # Fields
+    /// - `0` - A string describing the invalid option.
+    #[error("The options are invalid: {0}.")]
+    InvalidOptions(String),
+
+    /// Indicates that a database opened for reading only is written to.
+    #[error("The database is opened for reading only.")]
+    ReadOnly,
+
+    /// Indicates that a backup can't be read.
+    ///
+    /// # Fields
+    /// - `0` - A string describing the problem.
+    #[error("The backup is corrupted: {0}.")]
+    CorruptedBackup(String),
+
+    /// Indicates that a backup does not exist.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the backup.
+    #[error("The backup {0} does not exist.")]
+    BackupNotFound(u32),
+
+    /// Indicates that a value incremented or decremented is not an integer of 8 bytes.
+    ///
+    /// # Fields
+    /// - `0` - The length of the value.
+    #[error("The value ({0} bytes) is not an integer of 8 bytes.")]
+    NotAnInteger(usize),
+
+    /// Indicates that reading or writing a stream failed.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Indicates that a dump can't be imported.
+    ///
+    /// # Fields
+    /// - `0` - A string describing the problem.
+    #[error("The dump is invalid: {0}.")]
+    InvalidDump(String),
+
+    /// Indicates that a transaction waited for a key locked by another transaction past the lock
+    /// timeout of the options.
+    #[error("The key is locked by another transaction.")]
+    LockTimeout,
+
+    /// Indicates that a transaction would wait for a key locked by a transaction waiting, directly
+    /// or not, for a key it locked. The transaction should be rolled back, which releases its
+    /// locks, and can be retried.
+    #[error("The transaction would wait for a transaction waiting for it.")]
+    Deadlock,
+
+    /// Indicates that a key written outside of a transaction is locked by a transaction, whose
+    /// commit would overwrite the write. The write can be retried once the transaction ends.
+    #[error("The key is locked by a transaction.")]
+    KeyLocked,
+
+    /// Indicates that a database can't be opened because its [Env](super::Env) can't grant the
+    /// files or the memory it needs.
+    ///
+    /// # Fields
+    /// - `0` - A string describing the resource exhausted.
+    #[error("The resources of the environment are exhausted: {0}.")]
+    ResourceLimit(String),
+
+    /// Indicates that an optimistic transaction can't be committed because a key it read or
+    /// wrote was modified since it started. The transaction can be retried.
+    #[error("A key of the transaction was modified since it started.")]
+    Conflict,
+
+    /// Indicates that a transaction has no savepoint with the given name.
+    ///
+    /// # Fields
+    /// - `0` - The name of the savepoint.
+    #[error("The transaction has no savepoint named \"{0}\".")]
+    SavepointNotFound(String),
+
+    /// Indicates that a key is larger than the options or the page size allow.
+    ///
+    /// # Fields
+    /// - `size` - The size of the key.
+    /// - `max_size` - The size of the largest key allowed.
+    #[error("The key is too large. It is {size} bytes, but must be at most {max_size} bytes.")]
+    KeyTooLarge { size: usize, max_size: usize },
+
+    /// Indicates that a value is larger than the options allow, or than the page size allows
+    /// beside its key.
+    ///
+    /// # Fields
+    /// - `size` - The size of the value, before compression.
+    /// - `max_size` - The size of the largest value allowed beside its key.
+    #[error("The value is too large. It is {size} bytes, but must be at most {max_size} bytes.")]
+    ValueTooLarge { size: usize, max_size: usize },
+
+    /// Indicates that a value stored in the database can't be decoded, or does not match its
+    /// checksum.
+    #[error("A value stored in the database is corrupted.")]
+    CorruptedValue,
+
+    /// Indicates that a value stored in the database was compressed by a compressor that is not
+    /// registered, because its feature is not enabled or it was not set again after the database
+    /// was opened.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the compressor.
+    #[error("No compressor is registered with the identifier {0}.")]
+    UnknownCompressor(u8),
+
+    /// Indicates that a compressor is registered with an identifier reserved for the compressors
+    /// of this crate, below [MIN_CUSTOM_COMPRESSOR_ID](super::MIN_CUSTOM_COMPRESSOR_ID), or above
+    /// [MAX_COMPRESSOR_ID](super::MAX_COMPRESSOR_ID).
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the compressor.
+    #[error("The identifier {0} can't be given to a compressor.")]
+    InvalidCompressorId(u8),
+
+    /// Indicates that a value is stored in a format this version of the crate can't read, such as
+    /// a format added by a later version.
+    ///
+    /// # Fields
+    /// - `0` - The tag of the format of the value.
+    #[error("The value is stored in the format {0}, which is not supported.")]
+    UnsupportedValueFormat(u8),
+
+    /// Indicates that a database has no keyspace with the given name.
+    ///
+    /// # Fields
+    /// - `0` - The name of the keyspace.
+    #[error("There is no keyspace named \"{0}\".")]
+    KeyspaceNotFound(String),
+
+    /// Indicates that a keyspace is created with the name of an existing keyspace.
+    ///
+    /// # Fields
+    /// - `0` - The name of the keyspace.
+    #[error("There is already a keyspace named \"{0}\".")]
+    KeyspaceExists(String),
+
+    /// Indicates that the catalog of the keyspaces can't be read, or does not match the file.
+    ///
+    /// # Fields
+    /// - `0` - A string describing the problem.
+    #[error("The catalog of the keyspaces is corrupted: {0}.")]
+    CorruptedCatalog(String),
+
+    /// Indicates that a keyspace can't be created because the catalog would not fit in a page.
+    #[error("The catalog of the keyspaces is full.")]
+    CatalogFull,
+
+    /// Indicates that no transaction is prepared with the given identifier, or that it was
+    /// already committed or rolled back.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the prepared transaction.
+    #[error("No transaction is prepared with the identifier {0}.")]
+    PreparedNotFound(PreparedId),
+
+    /// Indicates that the record of a prepared transaction can't be decoded, or does not match
+    /// its checksum.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the prepared transaction.
+    #[error("The record of the prepared transaction {0} is corrupted.")]
+    CorruptedPrepared(PreparedId),
+
+    /// Indicates that a snapshot was read after the database released it, because it was older
+    /// than [Options::max_snapshot_age]. The reads, or the optimistic transaction, holding the
+    /// snapshot should be retried with a new one.
+    ///
+    /// # Fields
+    /// - `0` - The age of the snapshot.
+    #[error("The snapshot was released because it is too old ({0:?}).")]
+    SnapshotTooOld(Duration),
+
+    /// Indicates that a transaction lived longer than the expiration of its
+    /// [TransactionOptions](super::TransactionOptions). Its locks may have been released, so it
+    /// can only be rolled back.
+    #[error("The transaction expired.")]
+    TransactionExpired,
+
+    /// Indicates that a key or a value could not be serialized.
+    #[cfg(feature = "serde")]
+    #[error(transparent)]
+    Serialize(#[from] rmp_serde::encode::Error),
+
+    /// Indicates that a value read from the database could not be deserialized as the requested
+    /// type.
+    #[cfg(feature = "serde")]
+    #[error(transparent)]
+    Deserialize(#[from] rmp_serde::decode::Error),
+}
+
+/// Represents a key-value store in a directory of the file system, the single entry point to the
+/// storage engine.
+///
+/// The directory holds the data file, divided into pages by a [Pager], and its write-ahead log,
+/// which makes each commit atomic and durable (see [WalFile]). The pages read most recently are
+/// kept in memory by a [BufferPool]. The keys and their values are stored in a [CowTree], ordered
+/// by the comparator of the [Options], and the values are compressed as the options require.
+///
+/// A database should be closed with [Database::close], which copies the log to the data file.
+/// A database that is dropped without being closed, or whose process crashes, loses nothing that
+/// was written to the log, as the [Durability] of the options requires: the log is recovered the
+/// next time the database is opened.
+pub struct Database {
+    path: PathBuf,
+    options: Options,
+    /// Identifies the database while it is open, so its snapshots are not read from another one.
+    id: u64,
+    pager: Pager<DatabaseFile>,
+    tree: CowTree,
+    /// The keyspaces of the database, beside its tree.
+    catalog: Catalog,
+    /// The compressors of the values, registered by identifier.
+    compressors: Compressors,
+    merge_operator: Option<Box<dyn MergeOperator>>,
+    stall_listener: Option<StallListener>,
+    commit_hook: Option<CommitHook>,
+    watchers: Watchers,
+    counters: Counters,
+    opened_at: Instant,
+    locks: Arc<LockTable>,
+    /// The keys locked by each prepared transaction, until it is committed or rolled back.
+    prepared: HashMap<PreparedId, HashSet<Vec<u8>>>,
+    /// The snapshots taken from the database, released once they are too old.
+    snapshots: Mutex<Vec<Weak<SnapshotSlot>>>,
+    txn_ids: TxnIdAllocator,
+    /// The commits written to the log since it was last synced, with
+    /// [Durability::EveryNCommits].
+    unsynced_commits: u32,
+    /// The files and the memory reserved in the environment of the options, if any.
+    _reservation: Option<Reservation>,
+    /// The lock of the directory, held while the database is opened for writing.
+    _lock: Option<DatabaseLock>,
+}
+
+impl Database {
+    /// Opens the database in the directory at `path`, or creates it, as allowed by `options`. The
+    /// commits found in the log of an existing database are recovered. A database opened for
+    /// reading only writes nothing to its files, and only reads its log as
+    /// [Options::replay_wal] requires.
+    ///
+    /// A database opened for writing holds the lock file of its directory until it is closed or
+    /// dropped, so no other writer opens it meanwhile. A database opened for reading only does not
+    /// take the lock.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the options are invalid
+    /// - the database does not exist and `create_if_missing` is not set, or `read_only` is set
+    /// - the database exists and `error_if_exists` is set
+    /// - the database is opened for writing and another writer holds its lock
+    /// - the directory or the files of the database can't be created or opened
+    /// - the database was created with another comparator
+    /// - the data file, the tree or the catalog of the keyspaces is corrupted
+    /// - a keyspace uses a comparator or a compression that is not available
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let path = directory.path().join("database");
+    ///
+    /// let database = Database::open(&path, Options::new()).expect("open should not fail");
+    /// database.close().expect("close should not fail");
+    ///
+    /// let options = Options::new().create_if_missing(false);
+    /// let database = Database::open(&path, options).expect("open should not fail");
+    /// database.close().expect("close should not fail");
+    /// ```
+    pub fn open(path: impl AsRef<Path>, options: Options) -> Result<Self, DatabaseError> {
+        Database::open_locked(path.as_ref(), options, None)
+    }
+
+    /// Opens the database as [Database::open], with the lock of its directory if it is already
+    /// held by the caller.
+    fn open_locked(
+        path: &Path,
+        options: Options,
+        lock: Option<DatabaseLock>,
+    ) -> Result<Self, DatabaseError> {
+        let comparator = options.validate()?;
+        let locks_comparator = comparator_by_name(comparator.name())
+            .expect("the comparator of valid options should be known")
+            .into();
+        let path = path.to_path_buf();
+        let name = path.display().to_string();
+        let data_path = path.join(DATA_FILE_NAME);
+        let exists = data_path.is_file();
+        if exists && options.error_if_exists {
+            return Err(DatabaseError::AlreadyExists(name));
+        }
+        if !exists && (!options.create_if_missing || options.read_only) {
+            return Err(DatabaseError::NotFound(name));
+        }
+        let lock = match lock {
+            Some(lock) => Some(lock),
+            None if options.read_only => None,
+            None => {
+                create_directory(&path)?;
+                Some(DatabaseLock::acquire(&path)?)
+            }
+        };
+        let reservation = match &options.env {
+            Some(env) => Some(env.reserve(2, options.cache_size)?),
+            None => None,
+        };
+
+        let wal_dir = options.wal_dir.clone().unwrap_or_else(|| path.clone());
+        let log_path = wal_dir.join(LOG_FILE_NAME);
+        let mut file = if options.read_only {
+            WalFile::new(
+                OsFile::new_read_only(data_path),
+                OsFile::new_read_only(&log_path),
+            )
+        } else {
+            WalFile::new(OsFile::new(data_path), OsFile::new(&log_path))
+        };
+        file.set_checkpoint_size(options.checkpoint_size);
+        let (pager, tree) = if exists {
+            if options.read_only {
+                // A missing log holds no commit to replay.
+                file.open_read_only(options.replay_wal && log_path.is_file())?;
+            } else {
+                file.open()?;
+            }
+            // The page size of the cached blocks is only known once the header is read.
+            let pager = Pager::open(file)?;
+            let page_size = pager.page_size();
+            let file =
+                BufferPool::new(pager.into_file(), page_size, options.cache_size / page_size);
+            let pager = Pager::open(file)?;
+            let tree = CowTree::open_with_comparator(&pager, TREE_META, comparator)?;
+            (pager, tree)
+        } else {
+            create_directory(&wal_dir)?;
+            file.create()?;
+            let file = BufferPool::new(
+                file,
+                options.page_size,
+                options.cache_size / options.page_size,
+            );
+            let mut pager = Pager::create(file, options.page_size)?;
+            let tree = CowTree::create_with_comparator(&mut pager, comparator)?;
+            debug_assert_eq!(tree.meta(), TREE_META);
+            pager.sync()?;
+            (pager, tree)
+        };
+
+        let catalog = Catalog::load(&pager)?;
+        let locks = LockTable::new(locks_comparator);
+        let reserved_txn_id = pager.reserved_txn_id();
+        let prepared = two_phase::relock_prepared(&path, &locks)?;
+        let first_txn_id = prepared
+            .keys()
+            .map(|&id| u64::from(id).saturating_add(1))
+            .fold(reserved_txn_id, u64::max);
+
+        let compressors = Compressors::new(options.compression);
+        let mut database = Database {
+            path,
+            options,
+            id: rand::random(),
+            pager,
+            tree,
+            catalog,
+            compressors,
+            merge_operator: None,
+            stall_listener: None,
+            commit_hook: None,
+            watchers: Watchers::default(),
+            counters: Counters::default(),
+            opened_at: Instant::now(),
+            locks: Arc::new(locks),
+            prepared,
+            snapshots: Mutex::default(),
+            txn_ids: TxnIdAllocator::new(first_txn_id, reserved_txn_id),
+            unsynced_commits: 0,
+            _reservation: reservation,
+            _lock: lock,
+        };
+        if !database.options.read_only {
+            database.reserve_txn_ids()?;
+        }
+        Ok(database)
+    }
+
+    /// Opens the database in the directory at `path` as a secondary: for reading only, while
+    /// another process, the primary, keeps it open for writing. The secondary reads the database
+    /// as of the last commit when it is opened, and sees the later commits of the primary once
+    /// [Database::try_catch_up] is called. The options are those of [Database::open], with
+    /// `read_only` and `replay_wal` set.
+    ///
+    /// The secondary does not coordinate with the primary: it should catch up regularly, at least
+    /// as often as the log of the primary reaches its checkpoint size, since the pages the primary
+    /// copies to the data file afterward may replace pages the secondary still reads.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error in the same cases as [Database::open].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut primary =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// primary.put(b"key", b"old").expect("put should not fail");
+    /// let mut secondary = Database::open_as_secondary(directory.path(), Options::new())
+    ///     .expect("open_as_secondary should not fail");
+    ///
+    /// primary.put(b"key", b"new").expect("put should not fail");
+    /// assert_eq!(secondary.get(b"key").expect("get should not fail"), Some(b"old".to_vec()));
+    ///
+    /// assert!(secondary.try_catch_up().expect("try_catch_up should not fail"));
+    /// assert_eq!(secondary.get(b"key").expect("get should not fail"), Some(b"new".to_vec()));
+    /// ```
+    pub fn open_as_secondary(
+        path: impl AsRef<Path>,
+        options: Options,
+    ) -> Result<Self, DatabaseError> {
+        Database::open(path, options.read_only(true).replay_wal(true))
+    }
+
+    /// Removes the files of the database in the directory at `path`: its data file, its log, in
+    /// the log directory of `options` if it has one, its lock file, the records of its prepared
+    /// transactions, and the files left by an interrupted [Database::checkpoint] or by
+    /// [Database::repair]. The directories are then removed if they are empty. Other files are
+    /// never removed, and a database that does not exist is ignored.
+    ///
+    /// The lock of the database is taken before any file is removed, so a database opened for
+    /// writing is not destroyed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return [DatabaseError::Locked] if the database is opened for writing,
+    /// or an error if a file or directory can't be removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let path = directory.path().join("database");
+    /// let database = Database::open(&path, Options::new()).expect("open should not fail");
+    /// database.close().expect("close should not fail");
+    ///
+    /// Database::destroy(&path, &Options::new()).expect("destroy should not fail");
+    /// assert!(!path.exists());
+    /// ```
+    pub fn destroy(path: impl AsRef<Path>, options: &Options) -> Result<(), DatabaseError> {
+        let path = path.as_ref();
+        let wal_dir = options.wal_dir.as_deref().unwrap_or(path);
+        let lock = path
+            .is_dir()
+            .then(|| DatabaseLock::acquire(path))
+            .transpose()?;
+        let data_path = path.join(DATA_FILE_NAME);
+        let log_path = wal_dir.join(LOG_FILE_NAME);
+        for file in [
+            data_path.with_extension(DAMAGED_EXTENSION),
+            log_path.with_extension(DAMAGED_EXTENSION),
+            data_path.with_extension("tmp"),
+            log_path,
+            data_path,
+        ] {
+            remove_file(&file)?;
+        }
+        for id in prepared::ids(path)? {
+            prepared::remove(path, id)?;
+        }
+        // The lock is released before its file is removed, which some systems refuse for an open
+        // file.
+        drop(lock);
+        remove_file(&path.join(LOCK_FILE_NAME))?;
+        for directory in [&path.join(PREPARED_DIRECTORY), wal_dir, path] {
+            let mut entries = match std::fs::read_dir(directory) {
+                Ok(entries) => entries,
+                Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                Err(error) => return Err(io_error(directory, error)),
+            };
+            if entries.next().is_none() {
+                std::fs::remove_dir(directory).map_err(|source| io_error(directory, source))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the damaged database in the directory at `path` with the entries that can still
+    /// be read, and returns their number. The entries of the last commit are collected from the
+    /// tree, skipping the pages that can't be decoded, and the values that don't match their
+    /// checksum are dropped (see [CowTree::salvage]). The damaged data file and log are kept
+    /// beside the new ones, with the `damaged` extension, until the database is destroyed.
+    ///
+    /// The new database is created with `options`. The lock of the database is held during the
+    /// repair, so a database opened for writing is not repaired.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the database does not exist or is opened for
+    /// writing, if the files of an earlier repair are still there, if the header of the data file
+    /// is damaged, or if the files can't be read, renamed or written.
+    pub fn repair(path: impl AsRef<Path>, options: Options) -> Result<u64, DatabaseError> {
+        let path = path.as_ref();
+        let wal_dir = options
+            .wal_dir
+            .clone()
+            .unwrap_or_else(|| path.to_path_buf());
+        let data_path = path.join(DATA_FILE_NAME);
+        let log_path = wal_dir.join(LOG_FILE_NAME);
+        if !data_path.is_file() {
+            return Err(DatabaseError::NotFound(path.display().to_string()));
+        }
+        let lock = DatabaseLock::acquire(path)?;
+        let damaged_data = data_path.with_extension(DAMAGED_EXTENSION);
+        let damaged_log = log_path.with_extension(DAMAGED_EXTENSION);
+        if damaged_data.exists() || damaged_log.exists() {
+            return Err(DatabaseError::AlreadyExists(
+                damaged_data.display().to_string(),
+            ));
+        }
+
+        let report = if log_path.is_file() {
+            salvage(WalFile::new(
+                OsFile::new(&data_path),
+                OsFile::new(&log_path),
+            ))?
+        } else {
+            salvage(OsFile::new(&data_path))?
+        };
+        std::fs::rename(&data_path, &damaged_data)
+            .map_err(|source| io_error(&damaged_data, source))?;
+        if log_path.is_file() {
+            std::fs::rename(&log_path, &damaged_log)
+                .map_err(|source| io_error(&damaged_log, source))?;
+        }
+
+        let mut database =
+            Database::open_locked(path, options.create_if_missing(true), Some(lock))?;
+        let mut batch = WriteBatch::new();
+        let mut count = 0;
+        for (key, stored) in report.entries {
+            let Ok(value) = decode_value(stored, true, &database.compressors) else {
+                continue;
+            };
+            batch.put(&key, &value);
+            count += 1;
+            if batch.len() == IMPORT_BATCH_SIZE {
+                database.write(std::mem::take(&mut batch))?;
+            }
+        }
+        if !batch.is_empty() {
+            database.write(batch)?;
+        }
+        database.close()?;
+        Ok(count)
+    }
+
+    /// Returns the path of the directory of the database.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the options the database was opened with.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Returns the comparator ordering the keys of the database.
+    pub fn comparator(&self) -> &dyn KeyComparator {
+        self.tree.comparator()
+    }
+
+    /// Returns the version of the last commit of the database. A new database is at version `0`.
+    pub fn version(&self) -> u64 {
+        self.tree.version()
+    }
+
+    /// Reads the files of a database opened for reading only again, to see the commits made since
+    /// by the process writing to it (see [Database::open_as_secondary]). Returns `true` if the
+    /// database moved to another version. A database opened for writing is always up to date, and
+    /// `false` is returned.
+    ///
+    /// The snapshots taken before should be dropped, since the pages they read may have been
+    /// reused by the writer.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the files can't be read or the header of the data file,
+    /// the tree or the catalog of the keyspaces is corrupted.
+    pub fn try_catch_up(&mut self) -> Result<bool, DatabaseError> {
+        if !self.options.read_only {
+            return Ok(false);
+        }
+        let file = self.pager.file();
+        file.inner().refresh()?;
+        file.clear();
+        self.pager.reload()?;
+        let comparator = self.options.validate()?;
+        let tree = CowTree::open_with_comparator(&self.pager, TREE_META, comparator)?;
+        self.catalog = Catalog::load(&self.pager)?;
+        let moved = tree.version() != self.tree.version();
+        self.tree = tree;
+        Ok(moved)
+    }
+
+    /// Returns a snapshot of the database, whose reads see the database as it is now, until the
+    /// snapshot is dropped, or released for being older than [Options::max_snapshot_age].
+    pub fn snapshot(&self) -> DatabaseSnapshot {
+        let snapshot = DatabaseSnapshot::new(self.id, self.tree.snapshot());
+        let mut snapshots = self.release_old_snapshots();
+        snapshots.push(Arc::downgrade(snapshot.slot()));
+        snapshot
+    }
+
+    /// Returns the value associated with a key, or `None` if the key is not present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted, or if the value
+    /// does not match its checksum.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DatabaseError> {
+        self.get_with_options(key, &ReadOptions::new())
+    }
+
+    /// Returns the value associated with a key, or `None` if the key is not present, read as
+    /// configured by `options`. The iteration bounds of the options do not apply.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the snapshot of the options was released for being too
+    /// old, if a page can't be read or is corrupted, or if the checksums are verified and the
+    /// value does not match its checksum.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the snapshot of the options was not taken from this database.
+    pub fn get_with_options(
+        &self,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> Result<Option<Vec<u8>>, DatabaseError> {
+        let snapshot = options
+            .snapshot
+            .map(|snapshot| snapshot.inner(self))
+            .transpose()?;
+        let stored = with_fill_cache(options.fill_cache, || match &snapshot {
+            Some(snapshot) => snapshot.get(&self.pager, key),
+            None => self.tree.get(&self.pager, key),
+        })?;
+        let value = stored
+            .map(|stored| decode_value(stored, options.verify_checksums, &self.compressors))
+            .transpose()?;
+        self.counters.record_read(value.as_deref());
+        Ok(value)
+    }
+
+    /// Returns the values associated with several keys, in the order of the keys, with `None` for
+    /// the keys that are not present. The keys are looked up together in a single descent of the
+    /// tree, which reads the pages shared by their paths once, so this is faster than a get of
+    /// each key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted, or if a value
+    /// does not match its checksum.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// database.put(b"a", b"1").expect("put should not fail");
+    /// database.put(b"c", b"3").expect("put should not fail");
+    ///
+    /// let values = database
+    ///     .multi_get(&[b"c", b"b", b"a"])
+    ///     .expect("multi_get should not fail");
+    ///
+    /// assert_eq!(values, vec![Some(b"3".to_vec()), None, Some(b"1".to_vec())]);
+    /// ```
+    pub fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, DatabaseError> {
+        self.multi_get_with_options(keys, &ReadOptions::new())
+    }
+
+    /// Returns the values associated with several keys, in the order of the keys, read together
+    /// as configured by `options` (see [Database::multi_get]). The iteration bounds of the
+    /// options do not apply.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the snapshot of the options was released for being too
+    /// old, if a page can't be read or is corrupted, or if the checksums are verified and a value
+    /// does not match its checksum.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the snapshot of the options was not taken from this database.
+    pub fn multi_get_with_options(
+        &self,
+        keys: &[&[u8]],
+        options: &ReadOptions,
+    ) -> Result<Vec<Option<Vec<u8>>>, DatabaseError> {
+        let snapshot = options
+            .snapshot
+            .map(|snapshot| snapshot.inner(self))
+            .transpose()?;
+        let stored = with_fill_cache(options.fill_cache, || match &snapshot {
+            Some(snapshot) => snapshot.multi_get(&self.pager, keys),
+            None => self.tree.multi_get(&self.pager, keys),
+        })?;
+        stored
+            .into_iter()
+            .map(|stored| {
+                let value = stored
+                    .map(|stored| decode_value(stored, options.verify_checksums, &self.compressors))
+                    .transpose()?;
+                self.counters.record_read(value.as_deref());
+                Ok(value)
+            })
+            .collect()
+    }
+
+    /// Sets the value of a key, replacing its previous value, and commits. With
+    /// [Durability::Always], the value is durable when the method returns.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is read-only, if the key is locked by a
+    /// transaction, if the key or the value is too large, or if a page can't be read, written or
+    /// allocated. Nothing is then modified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    ///
+    /// database.put(b"key", b"value").expect("put should not fail");
+    /// assert_eq!(database.get(b"key").expect("get should not fail"), Some(b"value".to_vec()));
+    ///
+    /// database.delete(b"key").expect("delete should not fail");
+    /// assert_eq!(database.get(b"key").expect("get should not fail"), None);
+    /// ```
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        self.locks.check_unlocked(key)?;
+        let result = self.insert(key, value);
+        self.commit_or_rollback(result)?;
+        self.committed_write(key, Some(value));
+        Ok(())
+    }
+
+    /// Serializes a value with serde and associates it with a key, as [Database::put]. The value
+    /// is encoded in MessagePack, a compact encoding that describes its own types, with the names
+    /// of the fields of the structs, so a field with a default value can later be added.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the value can't be serialized, or in the cases of
+    /// [Database::put].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    ///
+    /// database.put_serde(b"scores", &vec![("alice", 12.5)]).expect("put_serde should not fail");
+    /// let scores: Option<Vec<(String, f64)>> =
+    ///     database.get_serde(b"scores").expect("get_serde should not fail");
+    /// assert_eq!(scores, Some(vec![("alice".to_string(), 12.5)]));
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn put_serde<T: serde::Serialize + ?Sized>(
+        &mut self,
+        key: &[u8],
+        value: &T,
+    ) -> Result<(), DatabaseError> {
+        self.put(key, &typed_tree::encode(value)?)
+    }
+
+    /// Returns the value associated with a key deserialized with serde, as stored by
+    /// [Database::put_serde], or `None` if the key is not present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the value is not a valid `T`, or in the cases of
+    /// [Database::get].
+    #[cfg(feature = "serde")]
+    pub fn get_serde<T: serde::de::DeserializeOwned>(
+        &self,
+        key: &[u8],
+    ) -> Result<Option<T>, DatabaseError> {
+        self.get(key)?
+            .map(|value| typed_tree::decode(&value))
+            .transpose()
+    }
+
+    /// Returns a view of the entries whose keys start with `prefix` as keys of type `K` mapped to
+    /// values of type `V`, both serialized with serde (see [TypedTree]).
+    #[cfg(feature = "serde")]
+    pub fn typed_tree<K, V>(&mut self, prefix: &[u8]) -> TypedTree<'_, K, V>
+    where
+        K: serde::Serialize + serde::de::DeserializeOwned,
+        V: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        TypedTree::new(self, prefix)
+    }
+
+    /// Removes a key, if it is present, and commits. With [Durability::Always], the deletion is
+    /// durable when the method returns. Nothing is committed if the key is missing, and the
+    /// watchers are not notified.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is read-only, if the key is locked by a
+    /// transaction, or if a page can't be read, written, allocated or freed. Nothing is then
+    /// modified.
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        self.locks.check_unlocked(key)?;
+        let result = self.tree.delete(&mut self.pager, key);
+        if matches!(result, Ok(None)) {
+            // The key is missing: the tree is unchanged, so there is nothing to commit or notify.
+            return Ok(());
+        }
+        self.commit_or_rollback(result)?;
+        self.committed_write(key, None);
+        Ok(())
+    }
+
+    /// Sets the value of a key, or removes the key if `new` is `None`, and commits, only if its
+    /// current value is `expected`, where `None` expects the key to be missing. The value is read
+    /// and written in the same commit, so no other write can come in between.
+    ///
+    /// Returns `Ok(Ok(()))` if the value was swapped, and `Ok(Err(current))` with the current value
+    /// if it did not match, in which case nothing is written.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is read-only, if the key is locked by a
+    /// transaction, if the key or the new value is too large, if a page can't be read, written,
+    /// allocated or freed, or if the current value is corrupted. Nothing is then modified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    ///
+    /// let swapped = database
+    ///     .compare_and_swap(b"leader", None, Some(b"a"))
+    ///     .expect("compare_and_swap should not fail");
+    /// assert_eq!(swapped, Ok(()));
+    ///
+    /// let swapped = database
+    ///     .compare_and_swap(b"leader", None, Some(b"b"))
+    ///     .expect("compare_and_swap should not fail");
+    /// assert_eq!(swapped, Err(Some(b"a".to_vec())));
+    /// ```
+    pub fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<Result<(), Option<Vec<u8>>>, DatabaseError> {
+        self.check_writable()?;
+        self.locks.check_unlocked(key)?;
+        let current = self.get(key)?;
+        if current.as_deref() != expected {
+            return Ok(Err(current));
+        }
+        let result = match new {
+            Some(value) => self.insert(key, value),
+            None => match self.tree.delete(&mut self.pager, key) {
+                // The key is missing: the tree is unchanged, so there is nothing to commit or
+                // notify.
+                Ok(None) => return Ok(Ok(())),
+                result => result.map(|_| ()).map_err(DatabaseError::from),
+            },
+        };
+        self.commit_or_rollback(result)?;
+        self.committed_write(key, new);
+        Ok(Ok(()))
+    }
+
+    /// Adds `delta` to the integer value of a key, stored as 8 little-endian bytes, and commits. A
+    /// missing key counts as `0`. The value is read and written in the same commit, so concurrent
+    /// increments are never lost. The addition wraps around on overflow, like
+    /// [U64AddOperator](crate::lsm::U64AddOperator), so both can update the same counters.
+    ///
+    /// Returns the new value.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is read-only, if the key is locked by a
+    /// transaction, if the current value is not 8 bytes long or is corrupted, or if a page can't be
+    /// read, written or allocated. Nothing is then modified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    ///
+    /// database.increment(b"visits", 5).expect("increment should not fail");
+    /// let visits = database.decrement(b"visits", 2).expect("decrement should not fail");
+    ///
+    /// assert_eq!(visits, 3);
+    /// let stored = database.get(b"visits").expect("get should not fail");
+    /// assert_eq!(stored, Some(3i64.to_le_bytes().to_vec()));
+    /// ```
+    pub fn increment(&mut self, key: &[u8], delta: i64) -> Result<i64, DatabaseError> {
+        self.check_writable()?;
+        self.locks.check_unlocked(key)?;
+        let current = match self.get(key)? {
+            Some(value) => i64::from_le_bytes(
+                value
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| DatabaseError::NotAnInteger(value.len()))?,
+            ),
+            None => 0,
+        };
+        let value = current.wrapping_add(delta);
+        let result = self.insert(key, &value.to_le_bytes());
+        self.commit_or_rollback(result)?;
+        self.committed_write(key, Some(&value.to_le_bytes()));
+        Ok(value)
+    }
+
+    /// Subtracts `delta` from the integer value of a key, like [Database::increment] with the
+    /// opposite delta, and returns the new value.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the same cases as [Database::increment].
+    pub fn decrement(&mut self, key: &[u8], delta: i64) -> Result<i64, DatabaseError> {
+        self.increment(key, delta.wrapping_neg())
+    }
+
+    /// Returns an iterator over the entries whose keys are within `range`, in key order. The
+    /// iterator sees the database as it is when the method is called.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// for key in [b"a", b"b", b"c", b"d"] {
+    ///     database.put(key, b"").expect("put should not fail");
+    /// }
+    ///
+    /// let keys: Vec<Vec<u8>> = database
+    ///     .iter(b"b".as_slice()..b"d".as_slice())
+    ///     .map(|entry| entry.expect("iteration should not fail").0)
+    ///     .collect();
+    ///
+    /// assert_eq!(keys, vec![b"b".to_vec(), b"c".to_vec()]);
+    /// ```
+    pub fn iter<K, R>(&self, range: R) -> DatabaseIter<'_>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        self.iter_with_options(range, &ReadOptions::new())
+    }
+
+    /// Returns an iterator over the entries whose keys are within `range` and the iteration bounds
+    /// of `options`, in key order, read as configured by the options. The iterator sees the
+    /// database as it is when the method is called, or as the snapshot of the options. It returns
+    /// [DatabaseError::SnapshotTooOld] if the snapshot was released for being too old.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the snapshot of the options was not taken from this database.
+    pub fn iter_with_options<K, R>(&self, range: R, options: &ReadOptions) -> DatabaseIter<'_>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let snapshot = match options.snapshot.map(|snapshot| snapshot.inner(self)) {
+            Some(Ok(snapshot)) => snapshot,
+            Some(Err(error)) => {
+                return DatabaseIter::failed(&self.counters, &self.compressors, error)
+            }
+            None => self.tree.snapshot(),
+        };
+        let bounds = options.restrict(&range, self.comparator());
+        let entries = snapshot.range::<_, [u8], _>(&self.pager, bounds);
+        DatabaseIter::new(
+            &self.counters,
+            &self.compressors,
+            snapshot,
+            entries,
+            options.verify_checksums,
+            options.fill_cache,
+        )
+    }
+
+    /// Returns an iterator over the entries whose keys start with `prefix`, in key order. The
+    /// iterator sees the database as it is when the method is called.
+    ///
+    /// The range of the keys with the prefix ends before the prefix with its trailing `0xff`
+    /// bytes removed and its last byte incremented (see [KeyRange::prefix]), so the keys with the
+    /// prefix are only found this way in a database ordered bytewise.
+    pub fn iter_prefix(&self, prefix: &[u8]) -> DatabaseIter<'_> {
+        self.iter(KeyRange::prefix(prefix))
+    }
+
+    /// Creates a keyspace: a tree of its own in the data file, beside the tree of the database,
+    /// with its own comparator and compression. The keyspace is recorded with its options in the
+    /// catalog of the database, which is synced, and is opened again with the database. Its keys
+    /// are read and written with the methods ending in `_in`, such as [Database::put_in].
+    ///
+    /// Only the tree of the database is read by the snapshots and the transactions, exported,
+    /// watched and rebuilt by [Database::repair]; the keyspaces are not.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the database is opened for reading only
+    /// - the name is empty, longer than 255 bytes, or already the name of a keyspace
+    /// - the options name an unknown comparator
+    /// - the catalog is full
+    /// - a page can't be allocated, written or synced
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, KeyspaceOptions, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// let options = KeyspaceOptions::new().comparator("rouilledb.reverse-bytewise");
+    /// database.create_keyspace("recent", options).expect("create_keyspace should not fail");
+    ///
+    /// for key in [b"a", b"b", b"c"] {
+    ///     database.put_in("recent", key, b"").expect("put_in should not fail");
+    /// }
+    /// let keys: Vec<Vec<u8>> = database
+    ///     .iter_in::<[u8], _>("recent", ..)
+    ///     .map(|entry| entry.expect("iteration should not fail").0)
+    ///     .collect();
+    ///
+    /// assert_eq!(keys, vec![b"c".to_vec(), b"b".to_vec(), b"a".to_vec()]);
+    /// assert_eq!(database.get(b"a").expect("get should not fail"), None);
+    /// ```
+    pub fn create_keyspace(
+        &mut self,
+        name: &str,
+        options: KeyspaceOptions,
+    ) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        self.catalog.create(&mut self.pager, name, &options)?;
+        self.pager.sync()?;
+        Ok(())
+    }
+
+    /// Removes a keyspace from the catalog, which is synced, and frees the pages of its tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is opened for reading only, if there is
+    /// no keyspace named `name`, or if a page can't be read, written, freed or synced.
+    pub fn drop_keyspace(&mut self, name: &str) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        self.catalog.remove(&mut self.pager, name)?;
+        self.pager.sync()?;
+        Ok(())
+    }
+
+    /// Returns the keyspaces of the database, in the order they were created.
+    pub fn keyspaces(&self) -> impl Iterator<Item = &KeyspaceInfo> {
+        self.catalog.infos()
+    }
+
+    /// Returns the value associated with a key in a keyspace, or `None` if the key is not present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if there is no keyspace named `keyspace`, if a page can't
+    /// be read or is corrupted, or if the value does not match its checksum.
+    pub fn get_in(&self, keyspace: &str, key: &[u8]) -> Result<Option<Vec<u8>>, DatabaseError> {
+        let keyspace = self.keyspace(keyspace)?;
+        let value = keyspace
+            .tree
+            .get(&self.pager, key)?
+            .map(|stored| decode_value(stored, true, &self.compressors))
+            .transpose()?;
+        self.counters.record_read(value.as_deref());
+        Ok(value)
+    }
+
+    /// Associates a value with a key in a keyspace, compressed as its options require, and
+    /// commits, as [Database::put].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is read-only, if there is no keyspace
+    /// named `keyspace`, if the key or the value is too large, or if a page can't be read,
+    /// written or allocated. Nothing is then modified.
+    pub fn put_in(
+        &mut self,
+        keyspace: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        self.check_sizes(key, value)?;
+        let name = keyspace;
+        let keyspace = self
+            .catalog
+            .get_mut(name)
+            .ok_or_else(|| DatabaseError::KeyspaceNotFound(name.to_string()))?;
+        let stored = encode_value(keyspace.compressor.as_deref(), value);
+        let result = keyspace.tree.insert(&mut self.pager, key, &stored);
+        self.commit_or_rollback_in(name, result)?;
+        self.counters.record_write(key, Some(value));
+        Ok(())
+    }
+
+    /// Removes a key from a keyspace, if it is present, and commits, as [Database::delete].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is read-only, if there is no keyspace
+    /// named `keyspace`, or if a page can't be read, written, allocated or freed. Nothing is then
+    /// modified.
+    pub fn delete_in(&mut self, keyspace: &str, key: &[u8]) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        let name = keyspace;
+        let keyspace = self
+            .catalog
+            .get_mut(name)
+            .ok_or_else(|| DatabaseError::KeyspaceNotFound(name.to_string()))?;
+        let result = keyspace.tree.delete(&mut self.pager, key);
+        if matches!(result, Ok(None)) {
+            return Ok(());
+        }
+        self.commit_or_rollback_in(name, result)?;
+        self.counters.record_write(key, None);
+        Ok(())
+    }
+
+    /// Returns an iterator over the entries of a keyspace whose keys are within `range`, in the
+    /// order of the comparator of the keyspace. The iterator sees the keyspace as it is when the
+    /// method is called. It returns [DatabaseError::KeyspaceNotFound] if there is no keyspace
+    /// named `keyspace`.
+    pub fn iter_in<K, R>(&self, keyspace: &str, range: R) -> DatabaseIter<'_>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let keyspace = match self.keyspace(keyspace) {
+            Ok(keyspace) => keyspace,
+            Err(error) => return DatabaseIter::failed(&self.counters, &self.compressors, error),
+        };
+        let snapshot = keyspace.tree.snapshot();
+        let entries = snapshot.range(&self.pager, range);
+        DatabaseIter::new(
+            &self.counters,
+            &self.compressors,
+            snapshot,
+            entries,
+            true,
+            true,
+        )
+    }
+
+    /// Returns an estimate of the bytes of the data file used by the entries of each range of keys,
+    /// in the order of the ranges, without iterating over them. The values are counted as they
+    /// are stored, compressed or not. Only the pages on the paths to the bounds of each range are
+    /// read (see [BTree::estimate_size](crate::btree::BTree::estimate_size)), so the estimates can
+    /// be used to decide where to split a database.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// for index in 0..1000u32 {
+    ///     database.put(&index.to_be_bytes(), &[0; 100]).expect("put should not fail");
+    /// }
+    ///
+    /// let middle = 500u32.to_be_bytes();
+    /// let sizes = database
+    ///     .approximate_sizes(&[..middle.as_slice(), ..&[0xff; 4]])
+    ///     .expect("approximate_sizes should not fail");
+    ///
+    /// assert!(sizes[0] > 0 && sizes[0] < sizes[1]);
+    /// ```
+    pub fn approximate_sizes<K, R>(&self, ranges: &[R]) -> Result<Vec<u64>, DatabaseError>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let snapshot = self.tree.snapshot();
+        ranges
+            .iter()
+            .map(|range| {
+                let bounds = (
+                    range.start_bound().map(AsRef::as_ref),
+                    range.end_bound().map(AsRef::as_ref),
+                );
+                Ok(snapshot.estimate_disk_size::<_, [u8], _>(&self.pager, bounds)?)
+            })
+            .collect()
+    }
+
+    /// Returns the value a key would have once a batch is written: the writes of the batch are
+    /// applied to the value read from the database. Neither is modified.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the batch is not indexed (see [WriteBatch::indexed])
+    /// - the batch merges operands into the key but no merge operator is set
+    /// - a page can't be read or is corrupted
+    pub fn get_from_batch(
+        &self,
+        batch: &WriteBatch,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, DatabaseError> {
+        self.get_from_batch_with_options(batch, key, &ReadOptions::new())
+    }
+
+    /// Returns the value a key would have once a batch is written, applying the writes of the
+    /// batch to the value read from the database as `options` require.
+    pub(super) fn get_from_batch_with_options(
+        &self,
+        batch: &WriteBatch,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> Result<Option<Vec<u8>>, DatabaseError> {
+        let operations: Vec<_> = batch
+            .operations_of(key)
+            .ok_or(DatabaseError::UnindexedBatch)?
+            .collect();
+        // The writes before the last put or delete are hidden by it, and the database is only read
+        // if there is none.
+        let (mut value, operands) = match operations
+            .iter()
+            .rposition(|operation| !matches!(operation, Envelope::Merge(_)))
+        {
+            Some(position) => match operations[position] {
+                Envelope::Value(value) => (Some(value.clone()), &operations[position + 1..]),
+                _ => (None, &operations[position + 1..]),
+            },
+            None => (self.get_with_options(key, options)?, &operations[..]),
+        };
+        for operation in operands {
+            if let Envelope::Merge(operand) = operation {
+                value = Some(self.merge(key, value.as_deref(), operand)?);
+            }
+        }
+        Ok(value)
+    }
+
+    /// Returns an iterator over the entries whose keys are within `range`, in key order, as they
+    /// would be once a batch is written: the entries of the database are merged with the values
+    /// the keys of the batch would have, and the keys the batch removes are skipped. Neither is
+    /// modified.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [Database::get_from_batch], for a key of
+    /// the batch within the range.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options, WriteBatch};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// database.put(b"a", b"1").expect("put should not fail");
+    /// database.put(b"c", b"3").expect("put should not fail");
+    ///
+    /// let mut batch = WriteBatch::indexed();
+    /// batch.delete(b"a");
+    /// batch.put(b"b", b"2");
+    /// let entries: Vec<_> = database
+    ///     .iter_from_batch::<[u8], _>(&batch, ..)
+    ///     .expect("iter_from_batch should not fail")
+    ///     .map(|entry| entry.expect("iteration should not fail"))
+    ///     .collect();
+    ///
+    /// assert_eq!(entries, vec![(b"b".to_vec(), b"2".to_vec()), (b"c".to_vec(), b"3".to_vec())]);
+    /// ```
+    pub fn iter_from_batch<K, R>(
+        &self,
+        batch: &WriteBatch,
+        range: R,
+    ) -> Result<BatchIter<'_>, DatabaseError>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        self.iter_from_batch_with_options(batch, range, &ReadOptions::new())
+    }
+
+    /// Returns an iterator over the entries whose keys are within `range` and the iteration bounds
+    /// of `options`, as they would be once a batch is written, reading the database as `options`
+    /// require.
+    pub(super) fn iter_from_batch_with_options<K, R>(
+        &self,
+        batch: &WriteBatch,
+        range: R,
+        options: &ReadOptions,
+    ) -> Result<BatchIter<'_>, DatabaseError>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let comparator = self.comparator();
+        let bounds = options.restrict(&range, comparator);
+        let mut writes = Vec::new();
+        for key in batch.keys().ok_or(DatabaseError::UnindexedBatch)? {
+            if contains(comparator, key, bounds) {
+                let value = self.get_from_batch_with_options(batch, key, options)?;
+                writes.push((key.clone(), value));
+            }
+        }
+        // The index of the batch is ordered bytewise, which may not be the order of the database.
+        writes.sort_by(|(first, _), (second, _)| comparator.compare(first, second));
+        let entries = self.iter_with_options(range, options);
+        Ok(BatchIter::new(comparator, entries, writes.into()))
+    }
+
+    /// Writes a batch: its writes are applied in order and committed together. With
+    /// [Durability::Always], the batch is durable when the method returns.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the database is read-only
+    /// - the batch merges operands but no merge operator is set
+    /// - a key of the batch is locked by a transaction
+    /// - a key or a value is too large
+    /// - a page can't be read, written, allocated or freed
+    ///
+    /// None of the writes of the batch is then applied.
+    pub fn write(&mut self, batch: WriteBatch) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        for (key, _) in batch.operations() {
+            self.locks.check_unlocked(key)?;
+        }
+        self.commit_locked(batch)
+    }
+
+    /// Writes a batch as [Database::write] does, for the transaction holding the locks of its
+    /// keys.
+    pub(super) fn commit_locked(&mut self, batch: WriteBatch) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        let result = self.apply(&batch);
+        self.commit_or_rollback(result)?;
+        for (key, operation) in batch.operations() {
+            match operation {
+                Envelope::Value(value) | Envelope::Merge(value) => {
+                    self.counters.record_write(key, Some(value))
+                }
+                Envelope::Tombstone => self.counters.record_write(key, None),
+            }
+        }
+        self.notify_commit(&batch);
+        Ok(())
+    }
+
+    /// Returns the statistics of the database: the use of its cache and of its files, and the
+    /// reads and writes made since it was opened.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// database.put(b"key", b"value").expect("put should not fail");
+    /// database.get(b"key").expect("get should not fail");
+    ///
+    /// let stats = database.stats().expect("stats should not fail");
+    ///
+    /// assert_eq!((stats.keys_read, stats.keys_written), (1, 1));
+    /// assert!(stats.wal_size > 0);
+    /// ```
+    pub fn stats(&self) -> Result<DatabaseStats, DatabaseError> {
+        let (open_snapshots, oldest_snapshot_age) = {
+            let snapshots = self.release_old_snapshots();
+            let oldest = snapshots
+                .iter()
+                .filter_map(|slot| Some(slot.upgrade()?.age()))
+                .max();
+            (snapshots.len(), oldest.unwrap_or_default())
+        };
+        let file = self.pager.file();
+        let (reclaimable_versions, retained_versions) = self.tree.retired_versions();
+        let mut stats = DatabaseStats {
+            cache_hits: file.hits(),
+            cache_misses: file.misses(),
+            cached_pages: file.len(),
+            wal_size: file.inner().log_size(),
+            file_size: u64::from(self.pager.page_count()) * self.pager.page_size() as u64,
+            free_pages: self.pager.free_pages()?.len(),
+            retired_pages: self.tree.retired_page_count(),
+            reclaimable_versions,
+            retained_versions,
+            pinned_size: (self.tree.pinned_page_count() * self.pager.page_size()) as u64,
+            remaining_txn_ids: self.txn_ids.remaining(),
+            open_snapshots,
+            oldest_snapshot_age,
+            live_data_size: self.approximate_sizes::<[u8], _>(&[..])?[0],
+            uptime: self.opened_at.elapsed(),
+            ..DatabaseStats::default()
+        };
+        self.counters.fill(&mut stats);
+        Ok(stats)
+    }
+
+    /// Returns the value of a property of the database as text, or `None` if there is no property
+    /// with that name. The properties are the statistics returned by [Database::stats], named as
+    /// listed in [DatabaseStats::PROPERTIES], and `rouilledb.stats` holds all of them.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn property(&self, name: &str) -> Result<Option<String>, DatabaseError> {
+        Ok(self.stats()?.property(name))
+    }
+
+    /// Writes a copy of the database, as of its last commit, as a new database in the directory at
+    /// `path`, which is created if it does not exist. The pages are read through the cache and the
+    /// log, so the copy holds the commits not yet copied to the data file, and they do not fill the
+    /// cache. The data file is copied rather than linked, since the log is later copied into it in
+    /// place. It is renamed last, so a checkpoint interrupted by a crash leaves no database.
+    ///
+    /// The database stays open and can be read during the checkpoint, but it can't be written
+    /// since it is borrowed.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a database already exists at `path`, if a page can't
+    /// be read, or if the files of the copy can't be written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database = Database::open(directory.path().join("database"), Options::new())
+    ///     .expect("open should not fail");
+    /// database.put(b"key", b"value").expect("put should not fail");
+    ///
+    /// database
+    ///     .checkpoint(directory.path().join("copy"))
+    ///     .expect("checkpoint should not fail");
+    /// database.put(b"key", b"changed").expect("put should not fail");
+    ///
+    /// let copy = Database::open(directory.path().join("copy"), Options::new())
+    ///     .expect("open should not fail");
+    /// assert_eq!(copy.get(b"key").expect("get should not fail"), Some(b"value".to_vec()));
+    /// ```
+    pub fn checkpoint(&self, path: impl AsRef<Path>) -> Result<(), DatabaseError> {
+        let path = path.as_ref();
+        std::fs::create_dir_all(path).map_err(|source| io_error(path, source))?;
+        let data_path = path.join(DATA_FILE_NAME);
+        if data_path.exists() {
+            return Err(DatabaseError::AlreadyExists(path.display().to_string()));
+        }
+
+        let temporary = data_path.with_extension("tmp");
+        let mut data = OsFile::new(&temporary);
+        data.create()?;
+        let page_size = self.pager.page_size();
+        let mut page = vec![0; page_size];
+        for offset in (0..self.pager.page_count() as usize * page_size).step_by(page_size) {
+            self.pager.file().read_with(offset, &mut page, false)?;
+            data.write(offset, &page)?;
+        }
+        data.sync()?;
+        data.close()?;
+
+        let mut log = OsFile::new(path.join(LOG_FILE_NAME));
+        if !log.exists() {
+            log.create()?;
+            log.close()?;
+        }
+        std::fs::rename(&temporary, &data_path).map_err(|source| io_error(&data_path, source))
+    }
+
+    /// Writes every entry of the database, as of its last commit, to `writer` as a dump, and
+    /// returns the number of entries written. The dump is a stream of checksummed records that
+    /// does not depend on the page size, the compression or the architecture of the database, and
+    /// is read back by [Database::import].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted, or if the writer
+    /// fails.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database = Database::open(directory.path().join("database"), Options::new())
+    ///     .expect("open should not fail");
+    /// database.put(b"key", b"value").expect("put should not fail");
+    ///
+    /// let mut dump = Vec::new();
+    /// database.export(&mut dump).expect("export should not fail");
+    ///
+    /// let mut copy = Database::open(directory.path().join("copy"), Options::new())
+    ///     .expect("open should not fail");
+    /// assert_eq!(copy.import(dump.as_slice()).expect("import should not fail"), 1);
+    /// assert_eq!(copy.get(b"key").expect("get should not fail"), Some(b"value".to_vec()));
+    /// ```
+    pub fn export(&self, writer: impl Write) -> Result<u64, DatabaseError> {
+        let mut dump = DumpWriter::new(writer)?;
+        for entry in self.iter::<&[u8], _>(..) {
+            let (key, value) = entry?;
+            dump.write_entry(&key, &value)?;
+        }
+        dump.finish()
+    }
+
+    /// Writes the entries of a dump made by [Database::export] to the database, replacing the
+    /// values of the keys that exist, and returns the number of entries read. The entries are
+    /// compressed as the options of this database require. They are committed in batches of
+    /// [IMPORT_BATCH_SIZE] entries, so the entries read before an error stay written.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is opened for reading only, if the dump
+    /// can't be read, is truncated or corrupted, or if the writes fail.
+    pub fn import(&mut self, reader: impl Read) -> Result<u64, DatabaseError> {
+        self.check_writable()?;
+        let mut dump = DumpReader::new(reader)?;
+        let mut batch = WriteBatch::new();
+        let mut count = 0;
+        while let Some((key, value)) = dump.next_entry()? {
+            batch.put(&key, &value);
+            count += 1;
+            if batch.len() == IMPORT_BATCH_SIZE {
+                self.write(std::mem::take(&mut batch))?;
+            }
+        }
+        if !batch.is_empty() {
+            self.write(batch)?;
+        }
+        Ok(count)
+    }
+
+    /// Frees the pages replaced by the commits of the versions no open snapshot or transaction can
+    /// see anymore, then flushes the log to the data file (see [Database::flush]), so the space
+    /// of the old versions is reclaimed in both. Every commit frees the pages it can, so this is
+    /// only needed once snapshots were dropped without a later commit. Returns the versions and
+    /// the pages reclaimed, and those kept for the open snapshots.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is opened for reading only, if a page
+    /// can't be freed, or in the cases of [Database::flush].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// database.put(b"key", b"old").expect("put should not fail");
+    /// let snapshot = database.snapshot();
+    /// database.put(b"key", b"new").expect("put should not fail");
+    ///
+    /// let retained = database.collect_garbage().expect("collect_garbage should not fail");
+    /// drop(snapshot);
+    /// let reclaimed = database.collect_garbage().expect("collect_garbage should not fail");
+    ///
+    /// assert_eq!((retained.reclaimed_versions, retained.retained_versions), (0, 1));
+    /// assert_eq!((reclaimed.reclaimed_versions, reclaimed.retained_versions), (1, 0));
+    /// ```
+    pub fn collect_garbage(&mut self) -> Result<GcReport, DatabaseError> {
+        self.check_writable()?;
+        drop(self.release_old_snapshots());
+        let (reclaimed_versions, _) = self.tree.retired_versions();
+        let retired_pages = self.tree.retired_page_count();
+        self.tree.reclaim(&mut self.pager)?;
+        self.flush()?;
+        let (_, retained_versions) = self.tree.retired_versions();
+        let retained_pages = self.tree.retired_page_count();
+        Ok(GcReport {
+            reclaimed_versions,
+            reclaimed_pages: retired_pages - retained_pages,
+            retained_versions,
+            retained_pages,
+        })
+    }
+
+    /// Syncs the commits to the log and copies the log to the data file, which is synced, so the
+    /// data file holds every commit and the log is empty, as when the database is closed. This can
+    /// be called before copying the data file or before a maintenance window, so the next open
+    /// doesn't have to replay the log.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is opened for reading only, or if the
+    /// files can't be written or synced. The commits are then recovered from the log the next
+    /// time the database is opened.
+    pub fn flush(&mut self) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        self.pager.sync()?;
+        self.pager.file().inner().checkpoint()?;
+        Ok(())
+    }
+
+    /// Packs the leaves of the tree holding the keys within `start..end` into as few pages as
+    /// possible, and commits the result. `None` leaves the range unbounded on that side. The
+    /// deletions leave underfull leaves behind, which a scan of the range has to read; packing
+    /// them makes the scans of a hot range read fewer pages, and frees the others for the next
+    /// writes. The snapshots taken before keep reading the former leaves. Returns the number of
+    /// leaves removed.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is opened for reading only, or if a page
+    /// can't be read or written. Nothing is then modified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options, WriteBatch};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database = Database::open(directory.path(), Options::new())
+    ///     .expect("open should not fail");
+    /// let mut batch = WriteBatch::new();
+    /// for i in 0..10_000u32 {
+    ///     batch.put(&i.to_be_bytes(), b"value");
+    /// }
+    /// database.write(batch).expect("write should not fail");
+    /// let mut batch = WriteBatch::new();
+    /// for i in (0..10_000u32).filter(|i| i % 10 != 0) {
+    ///     batch.delete(&i.to_be_bytes());
+    /// }
+    /// database.write(batch).expect("write should not fail");
+    ///
+    /// let removed = database
+    ///     .compact_range(None, Some(&5_000u32.to_be_bytes()))
+    ///     .expect("compact_range should not fail");
+    /// assert!(removed > 0);
+    /// assert_eq!(database.iter::<[u8], _>(..).count(), 1_000);
+    /// database.flush().expect("flush should not fail");
+    /// ```
+    pub fn compact_range(
+        &mut self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> Result<usize, DatabaseError> {
+        self.check_writable()?;
+        let result = self.tree.compact_range(&mut self.pager, start, end);
+        let removed = result.as_ref().map_or(0, |removed| *removed);
+        self.commit_or_rollback(result)?;
+        Ok(removed)
+    }
+
+    /// Closes the database: the log is copied to the data file, which is synced, and the files are
+    /// closed.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the files can't be written, synced or closed. The
+    /// commits are then recovered from the log the next time the database is opened.
+    pub fn close(self) -> Result<(), DatabaseError> {
+        let mut file = self.pager.into_file();
+        file.close()?;
+        Ok(())
+    }
+
+    pub(super) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(super) fn pager(&self) -> &Pager<DatabaseFile> {
+        &self.pager
+    }
+
+    /// Releases the snapshots older than [Options::max_snapshot_age], so the next commit can
+    /// reclaim their versions, and forgets the dropped snapshots. Returns the snapshots left.
+    fn release_old_snapshots(&self) -> MutexGuard<'_, Vec<Weak<SnapshotSlot>>> {
+        let mut snapshots = self
+            .snapshots
+            .lock()
+            .expect("the snapshots lock should not be poisoned");
+        snapshots.retain(|slot| {
+            let Some(slot) = slot.upgrade() else {
+                return false;
+            };
+            if self
+                .options
+                .max_snapshot_age
+                .is_some_and(|max_age| slot.age() > max_age)
+            {
+                slot.release();
+            }
+            slot.is_held()
+        });
+        snapshots
+    }
+
+    /// Returns the keyspace named `name`.
+    fn keyspace(&self, name: &str) -> Result<&Keyspace, DatabaseError> {
+        self.catalog
+            .get(name)
+            .ok_or_else(|| DatabaseError::KeyspaceNotFound(name.to_string()))
+    }
+
+    /// Fails if the database is opened for reading only.
+    fn check_writable(&self) -> Result<(), DatabaseError> {
+        if self.options.read_only {
+            return Err(DatabaseError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Applies the writes of a batch to the tree, without committing them.
+    fn apply(&mut self, batch: &WriteBatch) -> Result<(), DatabaseError> {
+        for (key, operation) in batch.operations() {
+            match operation {
+                Envelope::Value(value) => self.insert(key, value)?,
+                Envelope::Tombstone => {
+                    self.tree.delete(&mut self.pager, key)?;
+                }
+                Envelope::Merge(operand) => {
+                    let existing = self.get(key)?;
+                    let value = self.merge(key, existing.as_deref(), operand)?;
+                    self.insert(key, &value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Commits the modifications of the tree if `result` is a success, and discards them
+    /// otherwise, as [Database::commit_tree].
+    fn commit_or_rollback<T, E: Into<DatabaseError>>(
+        &mut self,
+        result: Result<T, E>,
+    ) -> Result<(), DatabaseError> {
+        self.commit_tree(None, result)
+    }
+
+    /// Commits the modifications of the tree of a keyspace if `result` is a success, and discards
+    /// them otherwise, as [Database::commit_tree].
+    fn commit_or_rollback_in<T, E: Into<DatabaseError>>(
+        &mut self,
+        name: &str,
+        result: Result<T, E>,
+    ) -> Result<(), DatabaseError> {
+        self.commit_tree(Some(name), result)
+    }
+
+    /// Commits the modifications of the tree of the database, or of the keyspace `keyspace`, if
+    /// `result` is a success, and discards them otherwise. Before the commit, the snapshots too old
+    /// are released. The commit is written to the log, and the log synced, as the [Durability] of
+    /// the options requires, and the transaction identifiers are reserved further if needed.
+    fn commit_tree<T, E: Into<DatabaseError>>(
+        &mut self,
+        keyspace: Option<&str>,
+        result: Result<T, E>,
+    ) -> Result<(), DatabaseError> {
+        if let Err(error) = result {
+            let (tree, pager) = self.tree_and_pager(keyspace);
+            tree.rollback(pager)?;
+            return Err(error.into());
+        }
+        drop(self.release_old_snapshots());
+        let (tree, pager) = self.tree_and_pager(keyspace);
+        tree.commit(pager)?;
+        match self.options.durability {
+            Durability::Always => self.pager.sync()?,
+            Durability::EveryNCommits(commits) => {
+                self.unsynced_commits += 1;
+                if self.unsynced_commits >= commits {
+                    self.pager.sync()?;
+                    self.unsynced_commits = 0;
+                } else {
+                    self.pager.file().inner().append_commit()?;
+                }
+            }
+            Durability::Async => self.pager.file().inner().append_commit()?,
+            Durability::Off => {}
+        }
+        self.reserve_txn_ids()?;
+        self.throttle()
+    }
+
+    /// Returns the tree of the database, or of the keyspace `keyspace`, with the pager.
+    fn tree_and_pager(
+        &mut self,
+        keyspace: Option<&str>,
+    ) -> (&mut CowTree, &mut Pager<DatabaseFile>) {
+        let tree = match keyspace {
+            Some(name) => {
+                &mut self
+                    .catalog
+                    .get_mut(name)
+                    .expect("the keyspace written should be in the catalog")
+                    .tree
+            }
+            None => &mut self.tree,
+        };
+        (tree, &mut self.pager)
+    }
+
+    /// Stalls the commit that was just made if the writes not yet copied to the data file passed
+    /// a threshold of the options: it is delayed past the slowdown size, and the writes are synced
+    /// and copied to the data file past the stop size.
+    fn throttle(&mut self) -> Result<(), DatabaseError> {
+        let file = self.pager.file().inner();
+        let pending_size = file.pending_size() + file.log_size();
+        let start = Instant::now();
+        let kind = if pending_size >= self.options.stop_pending_size {
+            self.pager.sync()?;
+            self.pager.file().inner().checkpoint()?;
+            StallKind::Stop
+        } else if pending_size >= self.options.slowdown_pending_size {
+            std::thread::sleep(self.options.slowdown_delay);
+            StallKind::Slowdown
+        } else {
+            return Ok(());
+        };
+        let stall = WriteStall {
+            kind,
+            pending_size,
+            duration: start.elapsed(),
+        };
+        self.counters.record_stall(&stall);
+        if let Some(listener) = &self.stall_listener {
+            listener(&stall);
+        }
+        Ok(())
+    }
+}
+
+/// Collects the entries of the tree of a database from its possibly damaged file.
+fn salvage<F: File>(mut file: F) -> Result<SalvageReport, DatabaseError> {
+    file.open()?;
+    let pager = Pager::open(file)?;
+    Ok(CowTree::salvage(&pager, TREE_META)?)
+}
+
+/// Returns `true` if a key ordered by `comparator` is within bounds.
+fn contains(
+    comparator: &dyn KeyComparator,
+    key: &[u8],
+    bounds: (Bound<&[u8]>, Bound<&[u8]>),
+) -> bool {
+    let after_start = match bounds.0 {
+        Bound::Included(start) => comparator.compare(key, start).is_ge(),
+        Bound::Excluded(start) => comparator.compare(key, start).is_gt(),
+        Bound::Unbounded => true,
+    };
+    let before_end = match bounds.1 {
+        Bound::Included(end) => comparator.compare(key, end).is_le(),
+        Bound::Excluded(end) => comparator.compare(key, end).is_lt(),
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+/// Removes a file, if it exists.
+fn remove_file(path: &Path) -> Result<(), DatabaseError> {
+    if path.is_file() {
+        std::fs::remove_file(path).map_err(|source| io_error(path, source))?;
+    }
+    Ok(())
+}
+
+/// Creates a directory and its parents, if they are missing.
+fn create_directory(path: &Path) -> Result<(), DatabaseError> {
+    std::fs::create_dir_all(path).map_err(|source| io_error(path, source))
+}
+
+/// Returns the error of an operation on a file or directory of the file system at `path`.
+pub(super) fn io_error(path: &Path, source: std::io::Error) -> DatabaseError {
+    FileError::Io {
+        filename: path.display().to_string(),
+        source,
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::common::TempDir;
+    use crate::db::Compression;
+    use crate::lsm::U64AddOperator;
+
+    use super::*;
+
+    /// Opening a missing database creates its directory and files, and the database can then be
+    /// reopened, unless the options require a new one.
+    #[test]
+    fn open_creates_then_reopens_database() {
+        let directory = TempDir::new();
+        let path = directory.path().join("nested").join("database");
+
+        let database = Database::open(&path, Options::new()).expect("open should not fail");
+        database.close().expect("close should not fail");
+        let reopened = Database::open(&path, Options::new());
+        let options = Options::new().error_if_exists(true);
+        let result = Database::open(&path, options);
+
+        assert!(path.join(DATA_FILE_NAME).is_file());
+        assert!(path.join(LOG_FILE_NAME).is_file());
+        assert!(reopened.is_ok());
+        assert!(matches!(result, Err(DatabaseError::AlreadyExists(_))));
+    }
+
+    /// A missing database is not created without `create_if_missing`.
+    #[test]
+    fn open_missing_database_without_create_fails() {
+        let directory = TempDir::new();
+        let options = Options::new().create_if_missing(false);
+
+        let result = Database::open(directory.path().join("database"), options);
+
+        assert!(matches!(result, Err(DatabaseError::NotFound(_))));
+        assert!(!directory.path().join("database").exists());
+    }
+
+    /// A database created with another page size keeps its page size when it is reopened.
+    #[test]
+    fn open_keeps_page_size_of_existing_database() {
+        let directory = TempDir::new();
+        let options = Options::new().page_size(1024);
+        Database::open(directory.path(), options)
+            .expect("open should not fail")
+            .close()
+            .expect("close should not fail");
+
+        let database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+
+        assert_eq!(database.pager.page_size(), 1024);
+        assert_eq!(database.tree.meta(), TREE_META);
+        assert_eq!(database.version(), 0);
+    }
+
+    /// The writes are durable once they return: they are found after the database is dropped
+    /// without being closed.
+    #[test]
+    fn put_is_durable_without_close() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        for index in 0..500 {
+            let key = format!("key-{index:04}");
+            database
+                .put(key.as_bytes(), &[index as u8; 100])
+                .expect("put should not fail");
+        }
+        database
+            .delete(b"key-0007")
+            .expect("delete should not fail");
+        drop(database);
+
+        let database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+
+        assert_eq!(
+            database.get(b"key-0123").expect("get should not fail"),
+            Some(vec![123; 100])
+        );
+        assert_eq!(
+            database.get(b"key-0007").expect("get should not fail"),
+            None
+        );
+        assert_eq!(database.version(), 501);
+    }
+
+    /// With [Durability::Off], the writes are lost if the database is not closed, but kept if it
+    /// is.
+    #[test]
+    fn put_without_sync_is_durable_after_close() {
+        let directory = TempDir::new();
+        let options = Options::new().durability(Durability::Off);
+        let mut database =
+            Database::open(directory.path(), options.clone()).expect("open should not fail");
+        database
+            .put(b"lost", b"value")
+            .expect("put should not fail");
+        drop(database);
+        let mut database =
+            Database::open(directory.path(), options.clone()).expect("open should not fail");
+        let lost = database.get(b"lost").expect("get should not fail");
+        database
+            .put(b"kept", b"value")
+            .expect("put should not fail");
+        database.close().expect("close should not fail");
+
+        let database = Database::open(directory.path(), options).expect("open should not fail");
+
+        assert_eq!(lost, None);
+        assert_eq!(
+            database.get(b"kept").expect("get should not fail"),
+            Some(b"value".to_vec())
+        );
+    }
+
+    /// The durabilities writing each commit to the log keep the commits of a database dropped
+    /// without being closed, and sync the log as often as they require.
+    #[test]
+    fn durability_syncs_log_as_required() {
+        let directory = TempDir::new();
+        let durabilities = [
+            Durability::Always,
+            Durability::EveryNCommits(3),
+            Durability::Async,
+        ];
+
+        let mut results = Vec::new();
+        for (index, durability) in durabilities.into_iter().enumerate() {
+            let path = directory.path().join(index.to_string());
+            let options = Options::new().durability(durability);
+            let mut database =
+                Database::open(&path, options.clone()).expect("open should not fail");
+            let mut unsynced = Vec::new();
+            for key in [b"a", b"b", b"c"] {
+                database.put(key, b"value").expect("put should not fail");
+                unsynced.push(database.pager.file().inner().unsynced_size() > 0);
+            }
+            drop(database);
+            let database = Database::open(&path, options).expect("open should not fail");
+            results.push((unsynced, database.get(b"c").expect("get should not fail")));
+        }
+
+        let value = Some(b"value".to_vec());
+        assert_eq!(
+            results,
+            vec![
+                (vec![false, false, false], value.clone()),
+                (vec![true, true, false], value.clone()),
+                (vec![true, true, true], value),
+            ]
+        );
+    }
+
+    /// The writes of a batch are applied in order, in a single commit.
+    #[test]
+    fn write_applies_batch_in_one_commit() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.set_merge_operator(Box::new(U64AddOperator));
+        database.put(b"a", b"old").expect("put should not fail");
+        database
+            .put(b"counter", &5u64.to_le_bytes())
+            .expect("put should not fail");
+        let mut batch = WriteBatch::new();
+        batch.delete(b"a");
+        batch.put(b"b", b"1");
+        batch.put(b"b", b"2");
+        batch.merge(b"counter", &3u64.to_le_bytes());
+        batch.merge(b"counter", &4u64.to_le_bytes());
+
+        database.write(batch).expect("write should not fail");
+
+        assert_eq!(database.get(b"a").expect("get should not fail"), None);
+        assert_eq!(
+            database.get(b"b").expect("get should not fail"),
+            Some(b"2".to_vec())
+        );
+        assert_eq!(
+            database.get(b"counter").expect("get should not fail"),
+            Some(12u64.to_le_bytes().to_vec())
+        );
+        assert_eq!(database.version(), 3);
+    }
+
+    /// A batch that fails part way is not applied at all.
+    #[test]
+    fn write_failing_batch_applies_nothing() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"a", b"old").expect("put should not fail");
+        let mut batch = WriteBatch::new();
+        batch.put(b"a", b"new");
+        batch.put(b"b", b"new");
+        batch.merge(b"c", b"operand");
+
+        let result = database.write(batch);
+
+        assert!(matches!(result, Err(DatabaseError::MissingMergeOperator)));
+        assert_eq!(
+            database.get(b"a").expect("get should not fail"),
+            Some(b"old".to_vec())
+        );
+        assert_eq!(database.get(b"b").expect("get should not fail"), None);
+        assert_eq!(database.version(), 1);
+    }
+
+    /// An indexed batch is read over the database before it is written, while a batch without an
+    /// index can't be.
+    #[test]
+    fn get_from_batch_sees_batch_writes() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.set_merge_operator(Box::new(U64AddOperator));
+        database
+            .put(b"counter", &5u64.to_le_bytes())
+            .expect("put should not fail");
+        database
+            .put(b"kept", b"value")
+            .expect("put should not fail");
+        let mut batch = WriteBatch::indexed();
+        batch.merge(b"counter", &2u64.to_le_bytes());
+        batch.put(b"new", b"value");
+        batch.merge(b"new", &1u64.to_le_bytes());
+        batch.put(b"deleted", b"value");
+        batch.delete(b"deleted");
+
+        let get = |key: &[u8]| {
+            database
+                .get_from_batch(&batch, key)
+                .expect("get_from_batch should not fail")
+        };
+
+        assert_eq!(get(b"counter"), Some(7u64.to_le_bytes().to_vec()));
+        assert_eq!(get(b"new"), Some(1u64.to_le_bytes().to_vec()));
+        assert_eq!(get(b"deleted"), None);
+        assert_eq!(get(b"kept"), Some(b"value".to_vec()));
+        assert_eq!(
+            database.get(b"counter").expect("get should not fail"),
+            Some(5u64.to_le_bytes().to_vec())
+        );
+        assert!(matches!(
+            database.get_from_batch(&WriteBatch::new(), b"kept"),
+            Err(DatabaseError::UnindexedBatch)
+        ));
+    }
+
+    /// An iteration returns the entries of its range in key order, across many pages.
+    #[test]
+    fn iter_returns_range_in_key_order() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let mut batch = WriteBatch::new();
+        for index in (0..2000).rev() {
+            batch.put(format!("key-{index:04}").as_bytes(), &[0; 50]);
+        }
+        database.write(batch).expect("write should not fail");
+
+        let keys: Vec<Vec<u8>> = database
+            .iter(b"key-0500".as_slice()..=b"key-1499".as_slice())
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+        let count = database.iter::<[u8], _>(..).count();
+
+        assert_eq!(
+            keys,
+            (500..1500)
+                .map(|index| format!("key-{index:04}").into_bytes())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(count, 2000);
+    }
+
+    /// A prefix iteration returns the keys starting with the prefix, including when the prefix
+    /// ends with `0xff` bytes.
+    #[test]
+    fn iter_prefix_returns_keys_with_prefix() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let keys: [&[u8]; 6] = [b"a", b"ab", b"abc", b"b", &[b'a', 0xff], &[b'a', 0xff, 0]];
+        for key in keys {
+            database.put(key, b"").expect("put should not fail");
+        }
+
+        let iter_prefix = |prefix: &[u8]| -> Vec<Vec<u8>> {
+            database
+                .iter_prefix(prefix)
+                .map(|entry| entry.expect("iteration should not fail").0)
+                .collect()
+        };
+
+        assert_eq!(iter_prefix(b"ab"), vec![b"ab".to_vec(), b"abc".to_vec()]);
+        assert_eq!(
+            iter_prefix(&[b'a', 0xff]),
+            vec![vec![b'a', 0xff], vec![b'a', 0xff, 0]]
+        );
+        assert_eq!(iter_prefix(b"a").len(), 5);
+        assert_eq!(iter_prefix(b"").len(), 6);
+        assert!(iter_prefix(b"c").is_empty());
+    }
+
+    /// A snapshot keeps seeing the database as it was when it was taken, and its pages are reused
+    /// once it is dropped.
+    #[test]
+    fn snapshot_sees_database_when_taken() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let key = |index: usize| format!("key-{index:04}").into_bytes();
+        let mut batch = WriteBatch::new();
+        for index in 0..500 {
+            batch.put(&key(index), &[1; 100]);
+        }
+        database.write(batch).expect("write should not fail");
+        let snapshot = database.snapshot();
+
+        let mut batch = WriteBatch::new();
+        for index in 0..500 {
+            batch.put(&key(index), &[2; 100]);
+        }
+        database.write(batch).expect("write should not fail");
+        database.delete(&key(0)).expect("delete should not fail");
+        let retained = database
+            .pager
+            .free_pages()
+            .expect("free_pages should not fail")
+            .len();
+
+        let entries: Vec<_> = snapshot
+            .iter::<[u8], _>(&database, ..)
+            .map(|entry| entry.expect("iteration should not fail"))
+            .collect();
+        assert_eq!(snapshot.version(), 1);
+        assert_eq!(entries.len(), 500);
+        assert!(entries.iter().all(|(_, value)| *value == [1; 100]));
+        assert_eq!(
+            snapshot
+                .get(&database, &key(0))
+                .expect("get should not fail"),
+            Some(vec![1; 100])
+        );
+        assert_eq!(database.get(&key(0)).expect("get should not fail"), None);
+
+        drop(snapshot);
+        database
+            .put(&key(1), &[3; 100])
+            .expect("put should not fail");
+        assert!(
+            database
+                .pager
+                .free_pages()
+                .expect("free_pages should not fail")
+                .len()
+                > retained
+        );
+    }
+
+    /// The comparator, the compression and the log directory of the options are used, and the
+    /// database can't be reopened with another comparator.
+    #[test]
+    fn open_with_options_uses_them() {
+        let directory = TempDir::new();
+        let path = directory.path().join("database");
+        let wal_dir = directory.path().join("wal");
+        let options = Options::new()
+            .comparator("rouilledb.reverse-bytewise")
+            .compression(Compression::Lz)
+            .wal_dir(&wal_dir);
+        let mut database = Database::open(&path, options.clone()).expect("open should not fail");
+        for key in [b"a", b"b", b"c"] {
+            database.put(key, &[7; 1000]).expect("put should not fail");
+        }
+        let stored = database
+            .tree
+            .get(&database.pager, b"a")
+            .expect("get should not fail")
+            .expect("the key should be present");
+        database.close().expect("close should not fail");
+
+        let database = Database::open(&path, options).expect("open should not fail");
+        let entries: Vec<_> = database
+            .iter::<[u8], _>(..)
+            .map(|entry| entry.expect("iteration should not fail"))
+            .collect();
+        database.close().expect("close should not fail");
+        let bytewise = Database::open(&path, Options::new().wal_dir(&wal_dir));
+
+        assert!(stored.len() < 100);
+        assert!(wal_dir.join(LOG_FILE_NAME).is_file());
+        assert!(!path.join(LOG_FILE_NAME).exists());
+        assert_eq!(
+            entries,
+            vec![
+                (b"c".to_vec(), vec![7; 1000]),
+                (b"b".to_vec(), vec![7; 1000]),
+                (b"a".to_vec(), vec![7; 1000])
+            ]
+        );
+        assert!(matches!(
+            bytewise,
+            Err(DatabaseError::BTree(BTreeError::ComparatorMismatch { .. }))
+        ));
+    }
+
+    /// A database opened for reading only is read but not written, and is never created.
+    #[test]
+    fn open_read_only_refuses_writes() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"key", b"value").expect("put should not fail");
+        database.close().expect("close should not fail");
+
+        let mut database = Database::open(directory.path(), Options::new().read_only(true))
+            .expect("open should not fail");
+        let missing = Database::open(
+            directory.path().join("missing"),
+            Options::new().read_only(true),
+        );
+
+        assert_eq!(
+            database.get(b"key").expect("get should not fail"),
+            Some(b"value".to_vec())
+        );
+        assert!(matches!(
+            database.put(b"key", b"new"),
+            Err(DatabaseError::ReadOnly)
+        ));
+        assert!(matches!(
+            database.write(WriteBatch::new()),
+            Err(DatabaseError::ReadOnly)
+        ));
+        assert!(matches!(missing, Err(DatabaseError::NotFound(_))));
+    }
+
+    /// A database opened for writing can't be opened by a second writer until it is closed, but
+    /// can still be opened for reading only.
+    #[test]
+    fn open_refuses_second_writer() {
+        let directory = TempDir::new();
+        let database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+
+        let second = Database::open(directory.path(), Options::new());
+        let reader = Database::open(directory.path(), Options::new().read_only(true));
+        database.close().expect("close should not fail");
+        let reopened = Database::open(directory.path(), Options::new());
+
+        assert!(matches!(second, Err(DatabaseError::Locked(_))));
+        assert!(reader.is_ok());
+        assert!(reopened.is_ok());
+    }
+
+    /// Invalid options are rejected before anything is created.
+    #[test]
+    fn open_with_invalid_options_fails() {
+        let directory = TempDir::new();
+        let path = directory.path().join("database");
+
+        let result = Database::open(&path, Options::new().page_size(3000));
+
+        assert!(matches!(result, Err(DatabaseError::InvalidOptions(_))));
+        assert!(!path.exists());
+    }
+
+    /// The read options choose whether the checksums are verified, whether the pages read fill the
+    /// cache and which snapshot is read.
+    #[test]
+    fn get_with_options_follows_options() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"key", b"old").expect("put should not fail");
+        let snapshot = database.snapshot();
+        let mut damaged = encode_value(None, b"new");
+        *damaged.last_mut().expect("the value should not be empty") = b'W';
+        database
+            .tree
+            .insert(&mut database.pager, b"key", &damaged)
+            .expect("insert should not fail");
+        database
+            .tree
+            .commit(&mut database.pager)
+            .expect("commit should not fail");
+        database.close().expect("close should not fail");
+        drop(snapshot);
+
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let unverified = ReadOptions::new().verify_checksums(false).fill_cache(false);
+        let cached_before = database.pager.file().len();
+        let value = database
+            .get_with_options(b"key", &unverified)
+            .expect("get should not fail");
+        let cached_after = database.pager.file().len();
+        let verified = database.get(b"key");
+        database.put(b"key", b"value").expect("put should not fail");
+        let snapshot = database.snapshot();
+        database.delete(b"key").expect("delete should not fail");
+        let snapshot_value = database
+            .get_with_options(b"key", &ReadOptions::new().snapshot(&snapshot))
+            .expect("get should not fail");
+
+        assert_eq!(value, Some(b"neW".to_vec()));
+        assert_eq!(cached_after, cached_before);
+        assert!(matches!(verified, Err(DatabaseError::CorruptedValue)));
+        assert_eq!(snapshot_value, Some(b"value".to_vec()));
+    }
+
+    /// A compare-and-swap only writes if the current value is the expected one, and otherwise
+    /// returns the current value.
+    #[test]
+    fn compare_and_swap_writes_only_expected_value() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"key", b"1").expect("put should not fail");
+
+        let mismatch = database
+            .compare_and_swap(b"key", Some(b"0"), Some(b"2"))
+            .expect("compare_and_swap should not fail");
+        let missing = database
+            .compare_and_swap(b"other", Some(b"0"), None)
+            .expect("compare_and_swap should not fail");
+        let version = database.version();
+        let swapped = database
+            .compare_and_swap(b"key", Some(b"1"), Some(b"2"))
+            .expect("compare_and_swap should not fail");
+        let value = database.get(b"key").expect("get should not fail");
+        let deleted = database
+            .compare_and_swap(b"key", Some(b"2"), None)
+            .expect("compare_and_swap should not fail");
+
+        assert_eq!(mismatch, Err(Some(b"1".to_vec())));
+        assert_eq!(missing, Err(None));
+        assert_eq!(version, 1);
+        assert_eq!(swapped, Ok(()));
+        assert_eq!(value, Some(b"2".to_vec()));
+        assert_eq!(deleted, Ok(()));
+        assert_eq!(database.get(b"key").expect("get should not fail"), None);
+    }
+
+    /// Increments and decrements add to the integer value of a key, start from `0` and wrap
+    /// around, but fail on a value that is not an integer.
+    #[test]
+    fn increment_adds_to_integer_value() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database
+            .put(b"text", b"value")
+            .expect("put should not fail");
+
+        let first = database
+            .increment(b"counter", 10)
+            .expect("increment should not fail");
+        let second = database
+            .decrement(b"counter", 15)
+            .expect("decrement should not fail");
+        database
+            .put(b"max", &i64::MAX.to_le_bytes())
+            .expect("put should not fail");
+        let wrapped = database
+            .increment(b"max", 1)
+            .expect("increment should not fail");
+        let version = database.version();
+        let text = database.increment(b"text", 1);
+
+        assert_eq!(first, 10);
+        assert_eq!(second, -5);
+        assert_eq!(
+            database.get(b"counter").expect("get should not fail"),
+            Some((-5i64).to_le_bytes().to_vec())
+        );
+        assert_eq!(wrapped, i64::MIN);
+        assert!(matches!(text, Err(DatabaseError::NotAnInteger(5))));
+        assert_eq!(database.version(), version);
+    }
+
+    /// A multi-get returns the values of the keys in their order, and reads a snapshot if the
+    /// options give one.
+    #[test]
+    fn multi_get_returns_values_in_key_order() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        for index in 0..200u32 {
+            database
+                .put(&index.to_be_bytes(), &[index as u8; 50])
+                .expect("put should not fail");
+        }
+        let snapshot = database.snapshot();
+        database
+            .delete(&7u32.to_be_bytes())
+            .expect("delete should not fail");
+        let keys: Vec<[u8; 4]> = [150u32, 7, 300, 3, 150].map(u32::to_be_bytes).to_vec();
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+
+        let values = database
+            .multi_get(&keys)
+            .expect("multi_get should not fail");
+        let snapshot_values = database
+            .multi_get_with_options(&keys, &ReadOptions::new().snapshot(&snapshot))
+            .expect("multi_get should not fail");
+
+        assert_eq!(
+            values,
+            vec![
+                Some(vec![150; 50]),
+                None,
+                None,
+                Some(vec![3; 50]),
+                Some(vec![150; 50])
+            ]
+        );
+        assert_eq!(snapshot_values[1], Some(vec![7; 50]));
+    }
+
+    /// The approximate sizes of ranges grow with the entries they hold, and with the size of their
+    /// values.
+    #[test]
+    fn approximate_sizes_follow_entries() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        for index in 0..3000u32 {
+            let size = if index < 1000 { 10 } else { 200 };
+            database
+                .put(&index.to_be_bytes(), &vec![index as u8; size])
+                .expect("put should not fail");
+        }
+        let bounds = [0u32, 1000, 2000, 3000].map(u32::to_be_bytes);
+
+        let sizes = database
+            .approximate_sizes(&[
+                bounds[0]..bounds[1],
+                bounds[1]..bounds[2],
+                bounds[2]..bounds[3],
+                bounds[3]..bounds[3],
+            ])
+            .expect("approximate_sizes should not fail");
+
+        assert!(sizes[0] < sizes[1] / 5);
+        assert!(sizes[1].abs_diff(sizes[2]) < sizes[1] / 5);
+        assert_eq!(sizes[3], 0);
+    }
+
+    /// The statistics count the reads and writes, the pages kept for an open snapshot and the use
+    /// of the cache, and are also read as properties.
+    #[test]
+    fn stats_follow_activity() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        for index in 0..100u32 {
+            database
+                .put(&index.to_be_bytes(), &[0; 100])
+                .expect("put should not fail");
+        }
+        let snapshot = database.snapshot();
+        database
+            .delete(&0u32.to_be_bytes())
+            .expect("delete should not fail");
+        let entries = database.iter::<[u8], _>(..).count();
+        database
+            .get(&1u32.to_be_bytes())
+            .expect("get should not fail");
+
+        let stats = database.stats().expect("stats should not fail");
+        let keys_written = database
+            .property("rouilledb.keys-written")
+            .expect("property should not fail");
+        let unknown = database
+            .property("rouilledb.unknown")
+            .expect("property should not fail");
+        drop(snapshot);
+
+        assert_eq!(stats.keys_written, 101);
+        assert_eq!(stats.bytes_written, 100 * 104 + 4);
+        assert_eq!(stats.keys_read, entries as u64 + 1);
+        assert_eq!(stats.bytes_read, 100 * 100);
+        assert!(stats.retired_pages > 0);
+        assert!(stats.cache_hit_rate() > 0.5);
+        assert!(stats.wal_size > 0);
+        assert!(stats.live_data_size > 99 * 100 && stats.live_data_size <= stats.file_size);
+        assert_eq!(keys_written, Some("101".to_string()));
+        assert_eq!(unknown, None);
+    }
+
+    /// A checkpoint is opened as the database as of its last commit, including the commits not
+    /// copied to the data file, and never overwrites a database.
+    #[test]
+    fn checkpoint_creates_copy() {
+        let directory = TempDir::new();
+        let options = Options::new().durability(Durability::Off);
+        let mut database = Database::open(directory.path().join("database"), options)
+            .expect("open should not fail");
+        for index in 0..1000u32 {
+            database
+                .put(&index.to_be_bytes(), &index.to_le_bytes())
+                .expect("put should not fail");
+        }
+
+        database
+            .checkpoint(directory.path().join("copy"))
+            .expect("checkpoint should not fail");
+        database
+            .put(b"after", b"checkpoint")
+            .expect("put should not fail");
+        let again = database.checkpoint(directory.path().join("copy"));
+        let copy = Database::open(directory.path().join("copy"), Options::new())
+            .expect("open should not fail");
+
+        assert_eq!(copy.version(), 1000);
+        for index in 0..1000u32 {
+            assert_eq!(
+                copy.get(&index.to_be_bytes()).expect("get should not fail"),
+                Some(index.to_le_bytes().to_vec())
+            );
+        }
+        assert_eq!(copy.get(b"after").expect("get should not fail"), None);
+        assert!(matches!(again, Err(DatabaseError::AlreadyExists(_))));
+    }
+
+    /// An export imported in a database with another page size and compression holds the same
+    /// entries, and a read-only database refuses imports.
+    #[test]
+    fn import_export_copies_entries() {
+        let directory = TempDir::new();
+        let mut database = Database::open(directory.path().join("database"), Options::new())
+            .expect("open should not fail");
+        for index in 0..2500u32 {
+            database
+                .put(&index.to_be_bytes(), &[index as u8; 50])
+                .expect("put should not fail");
+        }
+        let options = Options::new().page_size(8192).compression(Compression::Lz);
+        let mut copy =
+            Database::open(directory.path().join("copy"), options).expect("open should not fail");
+
+        let mut dump = Vec::new();
+        let exported = database.export(&mut dump).expect("export should not fail");
+        let imported = copy
+            .import(dump.as_slice())
+            .expect("import should not fail");
+        database.close().expect("close should not fail");
+        let mut read_only = Database::open(
+            directory.path().join("database"),
+            Options::new().read_only(true),
+        )
+        .expect("open should not fail");
+        let refused = read_only.import(dump.as_slice());
+
+        assert_eq!((exported, imported), (2500, 2500));
+        assert_eq!(copy.version(), 3);
+        assert!(copy
+            .iter::<&[u8], _>(..)
+            .map(|entry| entry.expect("next should not fail"))
+            .eq(read_only
+                .iter::<&[u8], _>(..)
+                .map(|entry| entry.expect("next should not fail"))));
+        assert!(matches!(refused, Err(DatabaseError::ReadOnly)));
+    }
+
+    /// Destroying a database removes its files and directories, but not the other files.
+    #[test]
+    fn destroy_removes_database_files() {
+        let directory = TempDir::new();
+        let path = directory.path().join("database");
+        let wal_dir = directory.path().join("wal");
+        let options = Options::new().wal_dir(&wal_dir);
+        let database = Database::open(&path, options.clone()).expect("open should not fail");
+        database
+            .checkpoint(directory.path().join("copy"))
+            .expect("checkpoint should not fail");
+        database.close().expect("close should not fail");
+        std::fs::write(directory.path().join("copy").join("other"), b"other")
+            .expect("write should not fail");
+
+        Database::destroy(&path, &options).expect("destroy should not fail");
+        Database::destroy(directory.path().join("copy"), &Options::new())
+            .expect("destroy should not fail");
+        Database::destroy(&path, &options).expect("destroy should not fail");
+
+        assert!(!path.exists());
+        assert!(!wal_dir.exists());
+        assert!(directory.path().join("copy").join("other").is_file());
+        assert!(!directory.path().join("copy").join(DATA_FILE_NAME).exists());
+    }
+
+    /// A database opened for writing is neither destroyed nor repaired, and keeps its files.
+    #[test]
+    fn destroy_refuses_open_database() {
+        let directory = TempDir::new();
+        let database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+
+        let destroyed = Database::destroy(directory.path(), &Options::new());
+        let repaired = Database::repair(directory.path(), Options::new());
+        database.close().expect("close should not fail");
+
+        assert!(matches!(destroyed, Err(DatabaseError::Locked(_))));
+        assert!(matches!(repaired, Err(DatabaseError::Locked(_))));
+        assert!(directory.path().join(DATA_FILE_NAME).is_file());
+        assert!(directory.path().join(LOG_FILE_NAME).is_file());
+    }
+
+    /// Repairing a database keeps the entries of its undamaged leaves and values, and keeps the
+    /// damaged files aside.
+    #[test]
+    fn repair_salvages_undamaged_entries() {
+        let directory = TempDir::new();
+        let path = directory.path().join("database");
+        let mut database = Database::open(&path, Options::new()).expect("open should not fail");
+        let mut batch = WriteBatch::new();
+        for index in 0..2000u32 {
+            batch.put(
+                &index.to_be_bytes(),
+                format!("value-{index:06}").repeat(8).as_bytes(),
+            );
+        }
+        database.write(batch).expect("write should not fail");
+        let page_size = database.pager().page_size();
+        database.close().expect("close should not fail");
+
+        let mut data = std::fs::read(path.join(DATA_FILE_NAME)).expect("read should not fail");
+        let find = |data: &[u8], value: &[u8]| {
+            data.windows(value.len())
+                .position(|window| window == value)
+                .expect("the value should be in the data file")
+        };
+        let damaged_value = find(&data, b"value-001500");
+        data[damaged_value] ^= 1;
+        let damaged_page = find(&data, b"value-000123") / page_size * page_size;
+        data[damaged_page..damaged_page + page_size].fill(0xff);
+        std::fs::write(path.join(DATA_FILE_NAME), data).expect("write should not fail");
+
+        let count = Database::repair(&path, Options::new()).expect("repair should not fail");
+        let again = Database::repair(&path, Options::new());
+        let repaired = Database::open(&path, Options::new()).expect("open should not fail");
+
+        let entries: Vec<_> = repaired
+            .iter::<&[u8], _>(..)
+            .map(|entry| entry.expect("next should not fail"))
+            .collect();
+        assert_eq!(entries.len() as u64, count);
+        assert!(count > 1900 && count < 1999);
+        for (key, value) in entries {
+            let index = u32::from_be_bytes(key.try_into().expect("key should be 4 bytes"));
+            assert_eq!(value, format!("value-{index:06}").repeat(8).into_bytes());
+        }
+        for index in [123u32, 1500] {
+            assert_eq!(
+                repaired
+                    .get(&index.to_be_bytes())
+                    .expect("get should not fail"),
+                None
+            );
+        }
+        assert!(path.join("data.damaged").is_file());
+        assert!(matches!(again, Err(DatabaseError::AlreadyExists(_))));
+    }
+
+    /// A database opened for reading only reads the commits of its log if it is replayed, and
+    /// leaves its files unchanged.
+    #[test]
+    fn open_read_only_replays_log_as_required() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"key", b"old").expect("put should not fail");
+        database.close().expect("close should not fail");
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"key", b"new").expect("put should not fail");
+        drop(database);
+        let files = || {
+            [DATA_FILE_NAME, LOG_FILE_NAME].map(|name| {
+                std::fs::read(directory.path().join(name)).expect("read should not fail")
+            })
+        };
+        let before = files();
+
+        let replayed = Database::open(directory.path(), Options::new().read_only(true))
+            .expect("open should not fail");
+        let replayed_value = replayed.get(b"key").expect("get should not fail");
+        replayed.close().expect("close should not fail");
+        let ignored = Database::open(
+            directory.path(),
+            Options::new().read_only(true).replay_wal(false),
+        )
+        .expect("open should not fail");
+        let ignored_value = ignored.get(b"key").expect("get should not fail");
+        ignored.close().expect("close should not fail");
+        let after = files();
+
+        assert_eq!(replayed_value, Some(b"new".to_vec()));
+        assert_eq!(ignored_value, Some(b"old".to_vec()));
+        assert!(before == after);
+    }
+
+    /// A secondary sees the commits of the primary once it catches up, including the commits
+    /// copied to the data file by checkpoints, and a primary never moves.
+    #[test]
+    fn try_catch_up_reads_commits_of_primary() {
+        let directory = TempDir::new();
+        let options = Options::new().checkpoint_size(64 * 1024);
+        let mut primary =
+            Database::open(directory.path(), options.clone()).expect("open should not fail");
+        primary.put(b"key", b"old").expect("put should not fail");
+        let mut secondary = Database::open_as_secondary(directory.path(), options)
+            .expect("open_as_secondary should not fail");
+
+        let mut versions = Vec::new();
+        for round in 0..5u32 {
+            for index in 0..200u32 {
+                primary
+                    .put(&index.to_be_bytes(), &round.to_le_bytes())
+                    .expect("put should not fail");
+            }
+            assert!(secondary
+                .try_catch_up()
+                .expect("try_catch_up should not fail"));
+            versions.push(secondary.version());
+            for index in 0..200u32 {
+                assert_eq!(
+                    secondary
+                        .get(&index.to_be_bytes())
+                        .expect("get should not fail"),
+                    Some(round.to_le_bytes().to_vec())
+                );
+            }
+        }
+        let unchanged = secondary
+            .try_catch_up()
+            .expect("try_catch_up should not fail");
+        let primary_moved = primary
+            .try_catch_up()
+            .expect("try_catch_up should not fail");
+
+        assert_eq!(versions, vec![201, 401, 601, 801, 1001]);
+        assert!(!unchanged && !primary_moved);
+        assert_eq!(
+            secondary.get(b"key").expect("get should not fail"),
+            Some(b"old".to_vec())
+        );
+        assert!(matches!(
+            secondary.put(b"key", b"new"),
+            Err(DatabaseError::ReadOnly)
+        ));
+    }
+
+    /// The iterator of a batch merges its writes with the entries of the database in the order of
+    /// the comparator, within the range: the writes hide the entries, the removed keys are
+    /// skipped, and the merge operands are applied to the values read.
+    #[test]
+    fn iter_from_batch_merges_writes_with_entries() {
+        let directory = TempDir::new();
+        let options = Options::new().comparator("rouilledb.reverse-bytewise");
+        let mut database = Database::open(directory.path(), options).expect("open should not fail");
+        database.set_merge_operator(Box::new(U64AddOperator));
+        for key in [b"a", b"c", b"e", b"g"] {
+            database
+                .put(key, &1u64.to_le_bytes())
+                .expect("put should not fail");
+        }
+        let mut batch = WriteBatch::indexed();
+        batch.put(b"b", &2u64.to_le_bytes());
+        batch.delete(b"c");
+        batch.put(b"d", &3u64.to_le_bytes());
+        batch.delete(b"d");
+        batch.merge(b"e", &4u64.to_le_bytes());
+        batch.put(b"f", &6u64.to_le_bytes());
+        batch.put(b"h", &7u64.to_le_bytes());
+
+        let entries: Vec<_> = database
+            .iter_from_batch(&batch, b"g".as_slice()..b"a".as_slice())
+            .expect("iter_from_batch should not fail")
+            .map(|entry| {
+                let (key, value) = entry.expect("iteration should not fail");
+                let value = u64::from_le_bytes(value.try_into().expect("value should be 8 bytes"));
+                (key, value)
+            })
+            .collect();
+        let unindexed = database.iter_from_batch::<[u8], _>(&WriteBatch::new(), ..);
+
+        assert_eq!(
+            entries,
+            vec![
+                (b"g".to_vec(), 1),
+                (b"f".to_vec(), 6),
+                (b"e".to_vec(), 5),
+                (b"b".to_vec(), 2)
+            ]
+        );
+        assert!(matches!(unindexed, Err(DatabaseError::UnindexedBatch)));
+    }
+
+    /// The versions replaced while a snapshot is open are retained until it is dropped, then
+    /// reclaimed by a garbage collection.
+    #[test]
+    fn collect_garbage_reclaims_versions_of_dropped_snapshots() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        for index in 0..100u32 {
+            database
+                .put(&index.to_be_bytes(), &[0; 100])
+                .expect("put should not fail");
+        }
+        let snapshot = database.snapshot();
+        for index in 0..10u32 {
+            database
+                .put(&index.to_be_bytes(), &[1; 100])
+                .expect("put should not fail");
+        }
+
+        let retained = database
+            .collect_garbage()
+            .expect("collect_garbage should not fail");
+        drop(snapshot);
+        let stats = database.stats().expect("stats should not fail");
+        let reclaimed = database
+            .collect_garbage()
+            .expect("collect_garbage should not fail");
+
+        assert_eq!(retained.reclaimed_versions, 0);
+        assert_eq!(retained.retained_versions, 10);
+        assert_eq!(
+            (stats.reclaimable_versions, stats.retained_versions),
+            (10, 0)
+        );
+        assert_eq!(reclaimed.reclaimed_versions, 10);
+        assert_eq!(reclaimed.reclaimed_pages, retained.retained_pages);
+        assert_eq!(database.tree.retired_page_count(), 0);
+    }
+
+    /// A snapshot older than the maximum age is released by the next commit, which frees the
+    /// pages it pinned, and its reads then fail.
+    #[test]
+    fn max_snapshot_age_releases_old_snapshots() {
+        let directory = TempDir::new();
+        let options = Options::new().max_snapshot_age(Duration::from_millis(50));
+        let mut database = Database::open(directory.path(), options).expect("open should not fail");
+        database.put(b"a", b"1").expect("put should not fail");
+        let snapshot = database.snapshot();
+        database.put(b"a", b"2").expect("put should not fail");
+
+        let pinned = database.stats().expect("stats should not fail");
+        let read = snapshot.get(&database, b"a").expect("get should not fail");
+        std::thread::sleep(Duration::from_millis(60));
+        database.put(b"b", b"3").expect("put should not fail");
+        let released = database.stats().expect("stats should not fail");
+        let expired = snapshot.get(&database, b"a");
+
+        assert_eq!(pinned.open_snapshots, 1);
+        assert!(pinned.oldest_snapshot_age > Duration::ZERO);
+        assert!(pinned.pinned_size > 0);
+        assert_eq!(read, Some(b"1".to_vec()));
+        assert_eq!(released.open_snapshots, 0);
+        assert_eq!(released.oldest_snapshot_age, Duration::ZERO);
+        assert_eq!(released.pinned_size, 0);
+        assert!(matches!(expired, Err(DatabaseError::SnapshotTooOld(_))));
+    }
+
+    /// A write to a keyspace releases the snapshots older than the maximum age, as a write to the
+    /// database does.
+    #[test]
+    fn keyspace_write_releases_old_snapshots() {
+        let directory = TempDir::new();
+        let options = Options::new().max_snapshot_age(Duration::from_millis(50));
+        let mut database = Database::open(directory.path(), options).expect("open should not fail");
+        database
+            .create_keyspace("events", KeyspaceOptions::new())
+            .expect("create_keyspace should not fail");
+        database.put(b"a", b"1").expect("put should not fail");
+        let snapshot = database.snapshot();
+
+        std::thread::sleep(Duration::from_millis(60));
+        database
+            .put_in("events", b"b", b"2")
+            .expect("put_in should not fail");
+        let expired = snapshot.get(&database, b"a");
+
+        assert!(matches!(expired, Err(DatabaseError::SnapshotTooOld(_))));
+    }
+
+    /// The writes kept in memory by a relaxed durability are copied to the data file once they
+    /// pass the stop size, and the stalls are counted.
+    #[test]
+    fn write_past_stop_size_copies_writes() {
+        let directory = TempDir::new();
+        let options = Options::new()
+            .durability(Durability::Off)
+            .slowdown_pending_size(32 * 1024)
+            .stop_pending_size(128 * 1024)
+            .slowdown_delay(Duration::ZERO);
+        let mut database = Database::open(directory.path(), options).expect("open should not fail");
+
+        let mut largest = 0;
+        for i in 0..1000u32 {
+            database
+                .put(&i.to_be_bytes(), &[1; 500])
+                .expect("put should not fail");
+            let file = database.pager.file().inner();
+            largest = largest.max(file.pending_size() + file.log_size());
+        }
+        let stats = database.stats().expect("stats should not fail");
+
+        assert!(stats.slowdowns > 0 && stats.stops > 0);
+        assert!(largest < 128 * 1024 + 16 * 1024);
+        assert_eq!(database.iter::<[u8], _>(..).count(), 1000);
+    }
+
+    /// A compaction packs the leaves of a range left underfull by deletions while a snapshot
+    /// keeps reading the former leaves, and a flush empties the log.
+    #[test]
+    fn compact_range_packs_leaves_and_flush_empties_log() {
+        let directory = TempDir::new();
+        let options = Options::new().durability(Durability::Off);
+        let mut database = Database::open(directory.path(), options).expect("open should not fail");
+        let mut batch = WriteBatch::new();
+        for i in 0..2000u32 {
+            batch.put(&i.to_be_bytes(), &[1; 20]);
+        }
+        database.write(batch).expect("write should not fail");
+        let mut batch = WriteBatch::new();
+        for i in (0..2000u32).filter(|i| i % 8 != 0) {
+            batch.delete(&i.to_be_bytes());
+        }
+        database.write(batch).expect("write should not fail");
+        let snapshot = database.snapshot();
+
+        let removed = database
+            .compact_range(Some(&100u32.to_be_bytes()), Some(&1500u32.to_be_bytes()))
+            .expect("compact_range should not fail");
+        let again = database
+            .compact_range(Some(&100u32.to_be_bytes()), Some(&1500u32.to_be_bytes()))
+            .expect("compact_range should not fail");
+        let log_size = database.pager.file().inner().log_size();
+        database.flush().expect("flush should not fail");
+
+        assert!(removed > 0);
+        assert_eq!(again, 0);
+        assert!(database.pager.file().inner().log_size() < log_size);
+        assert_eq!(database.iter::<[u8], _>(..).count(), 250);
+        let options = ReadOptions::new().snapshot(&snapshot);
+        assert_eq!(
+            database.iter_with_options::<[u8], _>(.., &options).count(),
+            250
+        );
+        drop(snapshot);
+        database.close().expect("close should not fail");
+    }
+
+    /// A keyspace keeps its entries, its order and its compression across a reopen, apart from
+    /// the tree of the database and from the log recovery, and a dropped keyspace is gone.
+    #[test]
+    fn keyspace_survives_reopen_until_dropped() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let options = KeyspaceOptions::new()
+            .comparator("rouilledb.reverse-bytewise")
+            .compression(Compression::Lz);
+        database
+            .create_keyspace("events", options)
+            .expect("create_keyspace should not fail");
+        for key in [b"a", b"b", b"c"] {
+            database
+                .put_in("events", key, &[7; 100])
+                .expect("put_in should not fail");
+        }
+        database
+            .delete_in("events", b"b")
+            .expect("delete_in should not fail");
+        database.put(b"a", b"default").expect("put should not fail");
+        drop(database);
+
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let keys: Vec<Vec<u8>> = database
+            .iter_in::<[u8], _>("events", ..)
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+        let stored = database
+            .catalog
+            .get("events")
+            .expect("events should be loaded")
+            .tree
+            .get(&database.pager, b"a")
+            .expect("get should not fail")
+            .expect("a should be stored");
+        let infos: Vec<_> = database.keyspaces().cloned().collect();
+        database
+            .drop_keyspace("events")
+            .expect("drop_keyspace should not fail");
+        database.close().expect("close should not fail");
+        let database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+
+        assert_eq!(keys, vec![b"c".to_vec(), b"a".to_vec()]);
+        assert!(stored.len() < 100);
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].name(), "events");
+        assert_eq!(infos[0].compression(), Compression::Lz);
+        assert_eq!(
+            database.get(b"a").expect("get should not fail"),
+            Some(b"default".to_vec())
+        );
+        assert_eq!(database.keyspaces().count(), 0);
+        assert!(matches!(
+            database.get_in("events", b"a"),
+            Err(DatabaseError::KeyspaceNotFound(_))
+        ));
+        database.close().expect("close should not fail");
+    }
+}
@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use super::{Database, DatabaseError};
+use crate::db::{IsolationLevel, OptimisticTransaction, Transaction, TransactionOptions};
+
+impl Database {
+    /// Starts a pessimistic transaction, which locks the keys it writes until it is committed or
+    /// rolled back (see [Transaction]).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, DatabaseError, Options};
+    /// use std::time::Duration;
+    ///
+    /// let directory = TempDir::new();
+    /// let options = Options::new().lock_timeout(Duration::from_millis(10));
+    /// let mut database = Database::open(directory.path(), options).expect("open should not fail");
+    /// database.put(b"counter", b"1").expect("put should not fail");
+    ///
+    /// let mut first = database.begin_txn();
+    /// let mut second = database.begin_txn();
+    /// let value = first
+    ///     .get_for_update(&database, b"counter")
+    ///     .expect("get_for_update should not fail");
+    /// first.put(b"counter", b"2").expect("put should not fail");
+    /// assert_eq!(value, Some(b"1".to_vec()));
+    /// assert!(matches!(second.put(b"counter", b"3"), Err(DatabaseError::LockTimeout)));
+    ///
+    /// first.commit(&mut database).expect("commit should not fail");
+    /// second.put(b"counter", b"3").expect("put should not fail");
+    /// second.rollback();
+    /// assert_eq!(database.get(b"counter").expect("get should not fail"), Some(b"2".to_vec()));
+    /// ```
+    pub fn begin_txn(&self) -> Transaction {
+        self.begin_txn_with_options(&TransactionOptions::default())
+    }
+
+    /// Starts a pessimistic transaction configured by `options`, which may read at another
+    /// isolation level, expire or wait for locked keys for its own timeout (see
+    /// [TransactionOptions]).
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the 64-bit transaction identifiers of the database are exhausted,
+    /// which [DatabaseStats::remaining_txn_ids](crate::db::DatabaseStats::remaining_txn_ids) reports ahead. The entries of the database should
+    /// then be dumped and loaded into a new database, whose identifiers start over.
+    pub fn begin_txn_with_options(&self, options: &TransactionOptions) -> Transaction {
+        let id = self
+            .txn_ids
+            .allocate()
+            .expect("the transaction identifiers should not be exhausted");
+        let snapshot =
+            (options.isolation_level == IsolationLevel::SnapshotIsolation).then(|| self.snapshot());
+        Transaction::new(
+            self.id,
+            id,
+            Arc::clone(&self.locks),
+            snapshot,
+            &self.options,
+            options,
+        )
+    }
+
+    /// Starts an optimistic transaction, which takes no lock and fails to commit if a key it read
+    /// or wrote was modified since it started (see [OptimisticTransaction]).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, DatabaseError, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// database.put(b"counter", b"1").expect("put should not fail");
+    ///
+    /// let mut transaction = database.begin_optimistic_txn();
+    /// let value = transaction
+    ///     .get(&database, b"counter")
+    ///     .expect("get should not fail");
+    /// transaction.put(b"counter", b"2");
+    /// database.put(b"counter", b"5").expect("put should not fail");
+    ///
+    /// assert_eq!(value, Some(b"1".to_vec()));
+    /// assert!(matches!(transaction.commit(&mut database), Err(DatabaseError::Conflict)));
+    /// assert_eq!(database.get(b"counter").expect("get should not fail"), Some(b"5".to_vec()));
+    /// ```
+    pub fn begin_optimistic_txn(&self) -> OptimisticTransaction {
+        OptimisticTransaction::new(self.snapshot())
+    }
+
+    /// Moves the end of the transaction identifiers reserved in the file of the database a block
+    /// ahead, and syncs it, once half of the reservation was handed out (see [TxnIdAllocator]).
+    pub(super) fn reserve_txn_ids(&mut self) -> Result<(), DatabaseError> {
+        if let Some(reserved) = self.txn_ids.next_reservation() {
+            self.pager.set_reserved_txn_id(reserved)?;
+            self.pager.sync()?;
+            self.txn_ids.set_reserved(reserved);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::common::TempDir;
+
+    use super::*;
+    use crate::db::{Options, WriteBatch};
+
+    /// The writes of a transaction are only seen by its reads until it is committed, and a
+    /// rolled back transaction writes nothing and releases its locks.
+    #[test]
+    fn transaction_commits_or_rolls_back_writes() {
+        let directory = TempDir::new();
+        let options = Options::new().lock_timeout(Duration::from_millis(10));
+        let mut database = Database::open(directory.path(), options).expect("open should not fail");
+        database.put(b"a", b"1").expect("put should not fail");
+
+        let mut committed = database.begin_txn();
+        committed.put(b"b", b"2").expect("put should not fail");
+        committed.delete(b"a").expect("delete should not fail");
+        let seen = (
+            committed.get(&database, b"a").expect("get should not fail"),
+            committed.get(&database, b"b").expect("get should not fail"),
+        );
+        let unseen = database.get(b"b").expect("get should not fail");
+        let mut rolled_back = database.begin_txn();
+        let blocked = rolled_back.put(b"a", b"3");
+        committed
+            .commit(&mut database)
+            .expect("commit should not fail");
+        rolled_back.put(b"a", b"3").expect("put should not fail");
+        rolled_back.rollback();
+        let mut last = database.begin_txn();
+        last.put(b"a", b"4").expect("put should not fail");
+
+        assert_eq!(seen, (None, Some(b"2".to_vec())));
+        assert_eq!(unseen, None);
+        assert!(matches!(blocked, Err(DatabaseError::LockTimeout)));
+        assert_eq!(database.get(b"a").expect("get should not fail"), None);
+        assert_eq!(
+            database.get(b"b").expect("get should not fail"),
+            Some(b"2".to_vec())
+        );
+        assert_eq!(database.version(), 2);
+    }
+
+    /// A write outside of a transaction to a key locked by the transaction fails, so the commit
+    /// of the transaction can't overwrite it, and succeeds once the transaction ends.
+    #[test]
+    fn write_to_locked_key_fails() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let mut transaction = database.begin_txn();
+        transaction.put(b"a", b"txn").expect("put should not fail");
+        let mut batch = WriteBatch::new();
+        batch.put(b"b", b"batch");
+        batch.delete(b"a");
+
+        let put = database.put(b"a", b"plain");
+        let written = database.write(batch);
+        let unlocked = database.put(b"b", b"plain");
+        transaction
+            .commit(&mut database)
+            .expect("commit should not fail");
+        let after_commit = database.put(b"a", b"plain");
+
+        assert!(matches!(put, Err(DatabaseError::KeyLocked)));
+        assert!(matches!(written, Err(DatabaseError::KeyLocked)));
+        assert!(unlocked.is_ok());
+        assert!(after_commit.is_ok());
+        assert_eq!(
+            database.get(b"a").expect("get should not fail"),
+            Some(b"plain".to_vec())
+        );
+    }
+
+    /// The transaction identifiers increase, and are not handed out again once the database is
+    /// reopened, even without a commit since they were reserved.
+    #[test]
+    fn txn_ids_increase_across_reopen() {
+        let directory = TempDir::new();
+        let database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let first = database.begin_txn().id();
+        let second = database.begin_txn().id();
+        let remaining = database
+            .stats()
+            .expect("stats should not fail")
+            .remaining_txn_ids;
+        drop(database);
+        let database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let reopened = database.begin_txn().id();
+
+        assert!(first < second);
+        assert!(second < reopened);
+        assert!(remaining > u64::MAX / 2);
+    }
+
+    /// An optimistic transaction commits unless a key it read or wrote was modified since it
+    /// started, and reads the version of the database when it started.
+    #[test]
+    fn optimistic_transaction_detects_conflicts() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"a", b"1").expect("put should not fail");
+        database.put(b"b", b"1").expect("put should not fail");
+
+        let mut unrelated = database.begin_optimistic_txn();
+        let mut read = database.begin_optimistic_txn();
+        let mut written = database.begin_optimistic_txn();
+        unrelated.put(b"c", b"3");
+        let before = read.get(&database, b"a").expect("get should not fail");
+        read.put(b"c", b"4");
+        written.delete(b"b");
+        database.put(b"a", b"2").expect("put should not fail");
+        database.put(b"b", b"2").expect("put should not fail");
+        let after = read.get(&database, b"a").expect("get should not fail");
+
+        unrelated
+            .commit(&mut database)
+            .expect("commit should not fail");
+        assert_eq!((before, after), (Some(b"1".to_vec()), Some(b"1".to_vec())));
+        assert!(matches!(
+            read.commit(&mut database),
+            Err(DatabaseError::Conflict)
+        ));
+        assert!(matches!(
+            written.commit(&mut database),
+            Err(DatabaseError::Conflict)
+        ));
+        assert_eq!(
+            database.get(b"c").expect("get should not fail"),
+            Some(b"3".to_vec())
+        );
+        assert_eq!(
+            database.get(b"b").expect("get should not fail"),
+            Some(b"2".to_vec())
+        );
+    }
+}
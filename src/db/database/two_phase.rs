@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::common::envelope::Envelope;
+
+use super::{Database, DatabaseError};
+use crate::db::prepared;
+use crate::db::transaction::LockTable;
+use crate::db::{PreparedId, WriteBatch};
+
+impl Database {
+    /// Returns the identifiers of the transactions prepared with [Transaction::prepare](crate::db::Transaction::prepare) and not
+    /// yet committed or rolled back, in increasing order. Once the database is reopened after a
+    /// restart, these are the transactions a coordinator must resolve.
+    pub fn prepared_txns(&self) -> Vec<PreparedId> {
+        let mut ids: Vec<_> = self.prepared.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Writes the writes of a prepared transaction to the database in a single commit, then
+    /// removes its record and releases its locks.
+    ///
+    /// The commit is synced whatever the [Durability](crate::db::Durability), and the record is only removed after it,
+    /// so a crash in between leaves the transaction prepared. Committing it again rewrites the same
+    /// values: the merge operands of the transaction were replaced by the values they make when it
+    /// was prepared.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is read-only, if no transaction is
+    /// prepared with the identifier, if its record is corrupted or can't be removed, or in the
+    /// cases of [Database::write]. The transaction then stays prepared.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    ///
+    /// let mut transaction = database.begin_txn();
+    /// transaction.put(b"account", b"debited").expect("put should not fail");
+    /// let id = transaction.prepare(&mut database).expect("prepare should not fail");
+    /// database.close().expect("close should not fail");
+    ///
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// assert_eq!(database.prepared_txns(), vec![id]);
+    /// database.commit_prepared(id).expect("commit_prepared should not fail");
+    ///
+    /// let value = database.get(b"account").expect("get should not fail");
+    /// assert_eq!(value, Some(b"debited".to_vec()));
+    /// assert!(database.prepared_txns().is_empty());
+    /// ```
+    pub fn commit_prepared(&mut self, id: PreparedId) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        let batch = prepared::read(&self.path, id)?;
+        self.commit_locked(batch)?;
+        // The coordinator is told the transaction committed once its record is removed, so the
+        // commit must be durable first.
+        self.pager.sync()?;
+        prepared::remove(&self.path, id)?;
+        self.release_prepared(id);
+        Ok(())
+    }
+
+    /// Discards the writes of a prepared transaction, then removes its record and releases its
+    /// locks.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the database is read-only, if no transaction is
+    /// prepared with the identifier, or if its record can't be removed.
+    pub fn rollback_prepared(&mut self, id: PreparedId) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        prepared::remove(&self.path, id)?;
+        self.release_prepared(id);
+        Ok(())
+    }
+
+    /// Records the writes of a transaction as prepared, so they survive a restart until
+    /// [Database::commit_prepared] or [Database::rollback_prepared] is called. The keys locked by
+    /// the transaction are moved to the database, and stay locked until then.
+    pub(in crate::db) fn prepare(
+        &mut self,
+        id: PreparedId,
+        batch: &WriteBatch,
+        locked: &mut HashSet<Vec<u8>>,
+    ) -> Result<(), DatabaseError> {
+        self.check_writable()?;
+        for (key, operation) in batch.operations() {
+            match operation {
+                Envelope::Value(value) => self.check_sizes(key, value)?,
+                Envelope::Tombstone | Envelope::Merge(_) => self.check_sizes(key, &[])?,
+            }
+        }
+        self.reserve_txn_ids()?;
+        let batch = self.resolve_merges(batch)?;
+        prepared::write(&self.path, id, &batch)?;
+        self.prepared.insert(id, std::mem::take(locked));
+        Ok(())
+    }
+
+    /// Returns a batch making the same writes as an indexed batch, where the merge operands of each
+    /// key are replaced by the value they make, so writing the batch twice has the same effect as
+    /// writing it once.
+    fn resolve_merges(&self, batch: &WriteBatch) -> Result<WriteBatch, DatabaseError> {
+        let mut merged = Vec::new();
+        for (key, operation) in batch.operations() {
+            if matches!(operation, Envelope::Merge(_)) && !merged.contains(key) {
+                merged.push(key.clone());
+            }
+        }
+        let mut resolved = WriteBatch::new();
+        for (key, operation) in batch.operations() {
+            match operation {
+                // The keys with merge operands are written with their value below.
+                _ if merged.contains(key) => {}
+                Envelope::Merge(_) => {}
+                Envelope::Value(value) => resolved.put(key, value),
+                Envelope::Tombstone => resolved.delete(key),
+            }
+        }
+        for key in merged {
+            match self.get_from_batch(batch, &key)? {
+                Some(value) => resolved.put(&key, &value),
+                None => resolved.delete(&key),
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Stops tracking a prepared transaction and releases its locks.
+    fn release_prepared(&mut self, id: PreparedId) {
+        let locked = self.prepared.remove(&id).unwrap_or_default();
+        self.locks.unlock(locked, id.into());
+    }
+}
+
+/// Reads the records of the transactions prepared in the directory of the database at `path`, and
+/// locks their keys again. Returns the keys locked by each transaction.
+pub(super) fn relock_prepared(
+    path: &Path,
+    locks: &LockTable,
+) -> Result<HashMap<PreparedId, HashSet<Vec<u8>>>, DatabaseError> {
+    let mut prepared = HashMap::new();
+    for id in prepared::ids(path)? {
+        let batch = prepared::read(path, id)?;
+        let mut keys = HashSet::new();
+        for (key, _) in batch.operations() {
+            locks.lock(key, id.into(), Duration::ZERO)?;
+            keys.insert(key.clone());
+        }
+        prepared.insert(id, keys);
+    }
+    Ok(prepared)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::TempDir;
+    use crate::lsm::U64AddOperator;
+
+    use super::*;
+    use crate::db::{Options, WriteBatch};
+
+    /// A prepared transaction keeps its writes and its locks across a restart, until it is
+    /// committed or rolled back.
+    #[test]
+    fn prepared_transaction_survives_reopen() {
+        let directory = TempDir::new();
+        let options = Options::new().lock_timeout(Duration::ZERO);
+        let mut database =
+            Database::open(directory.path(), options.clone()).expect("open should not fail");
+        let mut committed = database.begin_txn();
+        committed.put(b"a", b"1").expect("put should not fail");
+        let committed = committed
+            .prepare(&mut database)
+            .expect("prepare should not fail");
+        let mut rolled_back = database.begin_txn();
+        rolled_back.put(b"b", b"2").expect("put should not fail");
+        let rolled_back = rolled_back
+            .prepare(&mut database)
+            .expect("prepare should not fail");
+        database.close().expect("close should not fail");
+
+        let mut database = Database::open(directory.path(), options).expect("open should not fail");
+        let prepared = database.prepared_txns();
+        let blocked = database.begin_txn().put(b"a", b"3");
+        database
+            .commit_prepared(committed)
+            .expect("commit_prepared should not fail");
+        database
+            .rollback_prepared(rolled_back)
+            .expect("rollback_prepared should not fail");
+        let resolved_again = database.commit_prepared(rolled_back);
+        database
+            .begin_txn()
+            .put(b"a", b"3")
+            .expect("put should not fail");
+
+        let mut expected = vec![committed, rolled_back];
+        expected.sort_unstable();
+        assert_eq!(prepared, expected);
+        assert!(matches!(blocked, Err(DatabaseError::LockTimeout)));
+        assert!(matches!(
+            resolved_again,
+            Err(DatabaseError::PreparedNotFound(_))
+        ));
+        assert_eq!(
+            database.get(b"a").expect("get should not fail"),
+            Some(b"1".to_vec())
+        );
+        assert_eq!(database.get(b"b").expect("get should not fail"), None);
+        assert!(database.prepared_txns().is_empty());
+    }
+
+    /// The merge operands of a prepared transaction are recorded as the values they make, so
+    /// committing its record again after a crash does not apply them twice.
+    #[test]
+    fn prepare_resolves_merge_operands() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.set_merge_operator(Box::new(U64AddOperator));
+        database
+            .put(b"counter", &5u64.to_le_bytes())
+            .expect("put should not fail");
+        let mut batch = WriteBatch::indexed();
+        batch.put(b"other", b"value");
+        batch.merge(b"counter", &2u64.to_le_bytes());
+        let id = PreparedId::from(7);
+
+        database
+            .prepare(id, &batch, &mut HashSet::new())
+            .expect("prepare should not fail");
+        let recorded = prepared::read(directory.path(), id).expect("read should not fail");
+        database
+            .write(recorded.clone())
+            .expect("write should not fail");
+        database
+            .commit_prepared(id)
+            .expect("commit_prepared should not fail");
+
+        assert!(recorded
+            .operations()
+            .iter()
+            .all(|(_, operation)| !matches!(operation, Envelope::Merge(_))));
+        assert_eq!(
+            database.get(b"counter").expect("get should not fail"),
+            Some(7u64.to_le_bytes().to_vec())
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use crate::btree::BTree;
+use crate::lsm::MergeOperator;
+
+use super::{Database, DatabaseError};
+use crate::db::compression::{encode_value, HEADER_SIZE};
+use crate::db::Compressor;
+
+impl Database {
+    /// Sets the operator applying the operands written with [WriteBatch::merge](crate::db::WriteBatch::merge). The operator is
+    /// not stored in the database and must be set again each time it is opened.
+    pub fn set_merge_operator(&mut self, operator: Box<dyn MergeOperator>) {
+        self.merge_operator = Some(operator);
+    }
+
+    /// Registers a compressor and compresses the values written from now on with it, instead of
+    /// the compressor of [Options::compression](crate::db::Options::compression). The values it compressed are only read back by a
+    /// compressor registered with the same identifier: it is not stored in the database and must
+    /// be set again each time it is opened. The compressors of this crate are always registered.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the identifier of the compressor is below
+    /// [MIN_CUSTOM_COMPRESSOR_ID](crate::db::MIN_CUSTOM_COMPRESSOR_ID), reserved for the compressors
+    /// of this crate, or above [MAX_COMPRESSOR_ID](crate::db::MAX_COMPRESSOR_ID). The compressor is
+    /// then not registered.
+    pub fn set_compressor(&mut self, compressor: Box<dyn Compressor>) -> Result<(), DatabaseError> {
+        self.compressors.set(compressor.into())
+    }
+
+    /// Fails if a key or its value is larger than the options allow, or than the page size
+    /// allows. The value is measured before compression, so whether it can be written doesn't
+    /// depend on how well it compresses.
+    pub(super) fn check_sizes(&self, key: &[u8], value: &[u8]) -> Result<(), DatabaseError> {
+        let page_size = self.pager.page_size();
+        let max_key_size = self
+            .options
+            .max_key_size
+            .unwrap_or(usize::MAX)
+            .min(BTree::max_key_size(page_size));
+        if key.len() > max_key_size {
+            return Err(DatabaseError::KeyTooLarge {
+                size: key.len(),
+                max_size: max_key_size,
+            });
+        }
+        let max_value_size = self
+            .options
+            .max_value_size
+            .unwrap_or(usize::MAX)
+            .min(BTree::max_entry_size(page_size).saturating_sub(HEADER_SIZE + key.len()));
+        if value.len() > max_value_size {
+            return Err(DatabaseError::ValueTooLarge {
+                size: value.len(),
+                max_size: max_value_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Inserts a key and its value, compressed as required by the options, in the tree, without
+    /// committing.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key or the value is too large (see
+    /// [Database::check_sizes]), or if a page can't be read, written or allocated.
+    pub(super) fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), DatabaseError> {
+        self.check_sizes(key, value)?;
+        let stored = encode_value(self.compressors.writer(), value);
+        self.tree.insert(&mut self.pager, key, &stored)?;
+        Ok(())
+    }
+
+    /// Applies a merge operand to the value of a key with the merge operator.
+    pub(super) fn merge(
+        &self,
+        key: &[u8],
+        existing: Option<&[u8]>,
+        operand: &[u8],
+    ) -> Result<Vec<u8>, DatabaseError> {
+        let operator = self
+            .merge_operator
+            .as_deref()
+            .ok_or(DatabaseError::MissingMergeOperator)?;
+        Ok(operator.merge(key, existing, operand))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::TempDir;
+
+    use super::*;
+    use crate::db::{Options, WriteBatch};
+
+    /// A failed write does not modify the database.
+    #[test]
+    fn put_too_large_entry_fails() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"key", b"value").expect("put should not fail");
+
+        let result = database.put(b"key", &[0; 10_000]);
+
+        assert!(matches!(
+            result,
+            Err(DatabaseError::ValueTooLarge { size: 10_000, .. })
+        ));
+        assert_eq!(
+            database.get(b"key").expect("get should not fail"),
+            Some(b"value".to_vec())
+        );
+        assert_eq!(database.version(), 1);
+    }
+
+    /// The keys and values larger than the options or the page size allow are refused with
+    /// their sizes, and a batch holding one of them writes nothing.
+    #[test]
+    fn put_larger_than_limits_fails() {
+        let directory = TempDir::new();
+        let options = Options::new().max_key_size(8).max_value_size(100);
+        let mut database = Database::open(directory.path(), options).expect("open should not fail");
+        let mut batch = WriteBatch::new();
+        batch.put(b"first", b"value");
+        batch.put(b"second", &[0; 101]);
+
+        let key_result = database.put(b"long-key!", b"value");
+        let value_result = database.put(b"key", &[0; 101]);
+        let batch_result = database.write(batch);
+        database
+            .put(b"key", &[0; 100])
+            .expect("put should not fail");
+
+        assert!(matches!(
+            key_result,
+            Err(DatabaseError::KeyTooLarge {
+                size: 9,
+                max_size: 8
+            })
+        ));
+        assert!(matches!(
+            value_result,
+            Err(DatabaseError::ValueTooLarge {
+                size: 101,
+                max_size: 100
+            })
+        ));
+        assert!(matches!(
+            batch_result,
+            Err(DatabaseError::ValueTooLarge { .. })
+        ));
+        assert_eq!(database.get(b"first").expect("get should not fail"), None);
+        assert_eq!(database.version(), 1);
+    }
+}
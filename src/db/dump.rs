@@ -0,0 +1,234 @@
+use std::io::{ErrorKind, Read, Write};
+
+use crate::common::checksum::crc32c;
+
+use super::DatabaseError;
+
+/// Bytes starting every dump.
+const MAGIC: &[u8; 8] = b"RLDBDUMP";
+
+/// Version of the format of the dumps written.
+const VERSION: u32 = 1;
+
+/// Tag of the record ending a dump.
+const END_TAG: u8 = 0;
+
+/// Tag of the record of an entry.
+const ENTRY_TAG: u8 = 1;
+
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// Writes the entries of a database as a dump, read back by a [DumpReader].
+///
+/// A dump starts with [MAGIC] and the version of the format, on 4 bytes. Each entry follows as a
+/// record: [ENTRY_TAG], the lengths of the key and of the value, on 4 bytes each, the key, the
+/// value and the CRC32C, on 4 bytes, of the rest of the record. The dump ends with [END_TAG], the
+/// number of entries, on 8 bytes, and its CRC32C, so a truncated dump is detected. The integers
+/// are little endian, and the values are stored uncompressed, so a dump does not depend on the
+/// page size, the compression or the architecture of the database.
+pub(super) struct DumpWriter<W: Write> {
+    writer: W,
+    count: u64,
+}
+
+impl<W: Write> DumpWriter<W> {
+    /// Starts a dump by writing its header.
+    pub(super) fn new(mut writer: W) -> Result<Self, DatabaseError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        Ok(DumpWriter { writer, count: 0 })
+    }
+
+    /// Writes the record of an entry.
+    pub(super) fn write_entry(&mut self, key: &[u8], value: &[u8]) -> Result<(), DatabaseError> {
+        let mut record = Vec::with_capacity(key.len() + value.len() + 13);
+        record.push(ENTRY_TAG);
+        record.extend_from_slice(&length(key)?.to_le_bytes());
+        record.extend_from_slice(&length(value)?.to_le_bytes());
+        record.extend_from_slice(key);
+        record.extend_from_slice(value);
+        record.extend_from_slice(&crc32c(&record).to_le_bytes());
+        self.writer.write_all(&record)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Ends the dump, flushes the writer and returns the number of entries written.
+    pub(super) fn finish(mut self) -> Result<u64, DatabaseError> {
+        let mut record = vec![END_TAG];
+        record.extend_from_slice(&self.count.to_le_bytes());
+        record.extend_from_slice(&crc32c(&record).to_le_bytes());
+        self.writer.write_all(&record)?;
+        self.writer.flush()?;
+        Ok(self.count)
+    }
+}
+
+/// Reads the entries of a dump written by a [DumpWriter], verifying their checksums.
+pub(super) struct DumpReader<R: Read> {
+    reader: R,
+    count: u64,
+    ended: bool,
+}
+
+impl<R: Read> DumpReader<R> {
+    /// Starts reading a dump by checking its header.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the header can't be read, or if it does not start a
+    /// dump of a known version.
+    pub(super) fn new(mut reader: R) -> Result<Self, DatabaseError> {
+        let mut header = [0; 12];
+        read_exact(&mut reader, &mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid("the stream is not a dump"));
+        }
+        let version = u32::from_le_bytes(header[8..].try_into().expect("slice should be 4 bytes"));
+        if version != VERSION {
+            return Err(invalid(&format!("the version {version} is not supported")));
+        }
+        Ok(DumpReader {
+            reader,
+            count: 0,
+            ended: false,
+        })
+    }
+
+    /// Returns the next entry of the dump, or `None` once the dump has ended.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a record can't be read, is truncated, or does not
+    /// match its checksum, or if the number of entries does not match the end of the dump.
+    pub(super) fn next_entry(&mut self) -> Result<Option<Entry>, DatabaseError> {
+        if self.ended {
+            return Ok(None);
+        }
+        let mut tag = [0];
+        read_exact(&mut self.reader, &mut tag)?;
+        match tag[0] {
+            ENTRY_TAG => {
+                let mut record = vec![ENTRY_TAG; 9];
+                read_exact(&mut self.reader, &mut record[1..])?;
+                let key_length =
+                    u32::from_le_bytes(record[1..5].try_into().expect("slice should be 4 bytes"));
+                let value_length =
+                    u32::from_le_bytes(record[5..9].try_into().expect("slice should be 4 bytes"));
+                let end = 9 + key_length as usize + value_length as usize;
+                record.resize(end + 4, 0);
+                read_exact(&mut self.reader, &mut record[9..])?;
+                verify(&record)?;
+                record.truncate(end);
+                let value = record.split_off(9 + key_length as usize);
+                let key = record.split_off(9);
+                self.count += 1;
+                Ok(Some((key, value)))
+            }
+            END_TAG => {
+                let mut record = vec![END_TAG; 13];
+                read_exact(&mut self.reader, &mut record[1..])?;
+                verify(&record)?;
+                let count =
+                    u64::from_le_bytes(record[1..9].try_into().expect("slice should be 8 bytes"));
+                if count != self.count {
+                    return Err(invalid(&format!(
+                        "the dump ends after {count} entries instead of {}",
+                        self.count
+                    )));
+                }
+                self.ended = true;
+                Ok(None)
+            }
+            tag => Err(invalid(&format!("the record tag {tag} is unknown"))),
+        }
+    }
+}
+
+/// Returns the length of a key or value, which must fit on 4 bytes.
+fn length(bytes: &[u8]) -> Result<u32, DatabaseError> {
+    u32::try_from(bytes.len()).map_err(|_| invalid("an entry is too long to be dumped"))
+}
+
+/// Verifies that a record, ending with its checksum, matches it.
+fn verify(record: &[u8]) -> Result<(), DatabaseError> {
+    let (bytes, stored) = record.split_at(record.len() - 4);
+    if crc32c(bytes) != u32::from_le_bytes(stored.try_into().expect("slice should be 4 bytes")) {
+        return Err(invalid("a record does not match its checksum"));
+    }
+    Ok(())
+}
+
+/// Fills `buffer` from the reader, reporting the end of the stream as a truncated dump.
+fn read_exact(reader: &mut impl Read, buffer: &mut [u8]) -> Result<(), DatabaseError> {
+    reader
+        .read_exact(buffer)
+        .map_err(|error| match error.kind() {
+            ErrorKind::UnexpectedEof => invalid("the dump is truncated"),
+            _ => error.into(),
+        })
+}
+
+fn invalid(problem: &str) -> DatabaseError {
+    DatabaseError::InvalidDump(problem.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dump(entries: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let mut dump = Vec::new();
+        let mut writer = DumpWriter::new(&mut dump).expect("new should not fail");
+        for (key, value) in entries {
+            writer
+                .write_entry(key, value)
+                .expect("write_entry should not fail");
+        }
+        writer.finish().expect("finish should not fail");
+        dump
+    }
+
+    fn read_all(dump: &[u8]) -> Result<Vec<Entry>, DatabaseError> {
+        let mut reader = DumpReader::new(dump)?;
+        let mut entries = Vec::new();
+        while let Some(entry) = reader.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// The entries written to a dump are read back in order.
+    #[test]
+    fn read_dump_returns_written_entries() {
+        let entries: [(&[u8], &[u8]); 3] = [(b"a", b"1"), (b"", b""), (b"key", &[7; 1000])];
+
+        let read = read_all(&dump(&entries)).expect("read_all should not fail");
+
+        assert_eq!(
+            read,
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_vec(), value.to_vec()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Damaged, truncated and foreign dumps are reported as invalid.
+    #[test]
+    fn read_invalid_dump_fails() {
+        let valid = dump(&[(b"key", b"value")]);
+        let mut damaged = valid.clone();
+        damaged[22] ^= 1;
+        let truncated = valid[..valid.len() - 13].to_vec();
+        let mut newer = valid.clone();
+        newer[8] = 2;
+
+        for invalid in [damaged, truncated, newer, b"not a dump at all".to_vec()] {
+            assert!(matches!(
+                read_all(&invalid),
+                Err(DatabaseError::InvalidDump(_))
+            ));
+        }
+    }
+}
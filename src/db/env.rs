@@ -0,0 +1,384 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+use super::DatabaseError;
+
+/// A job run by the background threads of an [Env].
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Represents the resources shared by the databases of a process: the file descriptors they keep
+/// open, the memory of their caches and the threads running their background jobs.
+///
+/// An environment is given to the [Options](super::Options) of each database that must share it,
+/// and its clones all refer to the same resources. A [Database](super::Database) opened with an
+/// environment reserves the two files it keeps open, its data file and its log, and the memory of
+/// its cache, for as long as it is open. The database can't be opened if the environment can't
+/// grant them: the reservations of the databases never exceed the limits of the environment. The
+/// files opened briefly, by a checkpoint or a backup, are not reserved.
+///
+/// The jobs given to [Env::spawn] run on at most [Env::background_threads] threads, started as
+/// they are needed, and wait in a queue while every thread is busy.
+///
+/// The limits are set with a builder, starting from no limit and a single background thread. Each
+/// method of the builder returns a new environment, which shares no resource with the former one.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::TempDir;
+/// use rouilledb::db::{Database, DatabaseError, Env, Options};
+///
+/// let directory = TempDir::new();
+/// let env = Env::new().max_open_files(2).memory_budget(1024 * 1024);
+/// let options = Options::new().cache_size(512 * 1024).env(env.clone());
+///
+/// let first = Database::open(directory.path().join("first"), options.clone())
+///     .expect("open should not fail");
+/// let second = Database::open(directory.path().join("second"), options.clone());
+/// assert!(matches!(second, Err(DatabaseError::ResourceLimit(_))));
+///
+/// first.close().expect("close should not fail");
+/// let second = Database::open(directory.path().join("second"), options)
+///     .expect("open should not fail");
+/// assert_eq!(env.open_files(), 2);
+/// assert_eq!(env.memory_used(), 512 * 1024);
+/// ```
+#[derive(Clone, Default)]
+pub struct Env {
+    shared: Arc<Shared>,
+}
+
+/// The limits of an [Env].
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    max_open_files: usize,
+    memory_budget: usize,
+    background_threads: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_open_files: usize::MAX,
+            memory_budget: usize::MAX,
+            background_threads: 1,
+        }
+    }
+}
+
+/// The resources of an [Env], shared by its clones.
+#[derive(Default)]
+struct Shared {
+    limits: Limits,
+    usage: Mutex<Usage>,
+    pool: Arc<Pool>,
+}
+
+/// The resources reserved by the databases of an [Env].
+#[derive(Default)]
+struct Usage {
+    open_files: usize,
+    memory: usize,
+}
+
+/// The background threads of an [Env] and their queue of jobs.
+#[derive(Default)]
+struct Pool {
+    state: Mutex<PoolState>,
+    /// Notified when a job is queued or the pool is shut down.
+    queued: Condvar,
+}
+
+#[derive(Default)]
+struct PoolState {
+    jobs: VecDeque<Job>,
+    threads: usize,
+    idle: usize,
+    /// Set when the [Env] is dropped: the threads exit once the queue is empty.
+    shutdown: bool,
+}
+
+impl Env {
+    /// Returns an environment without limits, with a single background thread.
+    pub fn new() -> Self {
+        Env::default()
+    }
+
+    /// Sets the largest number of files kept open by the databases. Unlimited by default.
+    pub fn max_open_files(self, max_open_files: usize) -> Self {
+        self.with_limits(|limits| limits.max_open_files = max_open_files)
+    }
+
+    /// Sets the largest number of bytes used by the caches of the databases. Unlimited by default.
+    pub fn memory_budget(self, memory_budget: usize) -> Self {
+        self.with_limits(|limits| limits.memory_budget = memory_budget)
+    }
+
+    /// Sets the largest number of threads running background jobs. `1` by default.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `background_threads` is `0`.
+    pub fn background_threads(self, background_threads: usize) -> Self {
+        assert!(background_threads > 0, "the pool should have a thread");
+        self.with_limits(|limits| limits.background_threads = background_threads)
+    }
+
+    /// Returns the number of files kept open by the databases of the environment.
+    pub fn open_files(&self) -> usize {
+        self.usage().open_files
+    }
+
+    /// Returns the number of bytes reserved by the caches of the databases of the environment.
+    pub fn memory_used(&self) -> usize {
+        self.usage().memory
+    }
+
+    /// Runs a job on a background thread of the environment, as soon as one is available, and
+    /// returns a handle to its result.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::db::Env;
+    ///
+    /// let env = Env::new().background_threads(2);
+    /// let jobs: Vec<_> = (0..10).map(|i| env.spawn(move || i * i)).collect();
+    ///
+    /// let results: Vec<_> = jobs.into_iter().map(|job| job.join()).collect();
+    /// assert_eq!(results[9], 81);
+    /// ```
+    pub fn spawn<T, F>(&self, job: F) -> BackgroundJob<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let job: Job = Box::new(move || {
+            // The handle may have been dropped, and the result with it.
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(job)));
+        });
+
+        let pool = &self.shared.pool;
+        let mut state = pool.state();
+        state.jobs.push_back(job);
+        if state.idle > 0 {
+            pool.queued.notify_one();
+        } else if state.threads < self.shared.limits.background_threads {
+            state.threads += 1;
+            let pool = Arc::clone(pool);
+            thread::spawn(move || pool.work());
+        }
+        BackgroundJob { receiver }
+    }
+
+    /// Reserves files and memory for a database, until the reservation is dropped.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the files or the memory would exceed the limits of
+    /// the environment. Nothing is then reserved.
+    pub(super) fn reserve(
+        &self,
+        files: usize,
+        memory: usize,
+    ) -> Result<Reservation, DatabaseError> {
+        let limits = self.shared.limits;
+        let mut usage = self.usage();
+        if usage.open_files.saturating_add(files) > limits.max_open_files {
+            return Err(DatabaseError::ResourceLimit(format!(
+                "{} files are open out of {}",
+                usage.open_files, limits.max_open_files
+            )));
+        }
+        if usage.memory.saturating_add(memory) > limits.memory_budget {
+            return Err(DatabaseError::ResourceLimit(format!(
+                "{} bytes of memory are used out of {}",
+                usage.memory, limits.memory_budget
+            )));
+        }
+        usage.open_files += files;
+        usage.memory += memory;
+        Ok(Reservation {
+            shared: Arc::clone(&self.shared),
+            files,
+            memory,
+        })
+    }
+
+    /// Returns a new environment with the limits of this one, modified by `update`.
+    fn with_limits(self, update: impl FnOnce(&mut Limits)) -> Self {
+        let mut limits = self.shared.limits;
+        update(&mut limits);
+        Env {
+            shared: Arc::new(Shared {
+                limits,
+                usage: Mutex::default(),
+                pool: Arc::default(),
+            }),
+        }
+    }
+
+    fn usage(&self) -> MutexGuard<'_, Usage> {
+        self.shared.usage()
+    }
+}
+
+impl PartialEq for Env {
+    /// Two environments are equal if they share their resources.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+impl Eq for Env {}
+
+impl fmt::Debug for Env {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("Env")
+            .field("limits", &self.shared.limits)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Shared {
+    fn usage(&self) -> MutexGuard<'_, Usage> {
+        self.usage
+            .lock()
+            .expect("the usage lock should not be poisoned")
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        self.pool.state().shutdown = true;
+        self.pool.queued.notify_all();
+    }
+}
+
+impl Pool {
+    /// Runs the queued jobs until the pool is shut down and the queue is empty.
+    fn work(&self) {
+        let mut state = self.state();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                job();
+                state = self.state();
+            } else if state.shutdown {
+                state.threads -= 1;
+                return;
+            } else {
+                state.idle += 1;
+                state = self
+                    .queued
+                    .wait(state)
+                    .expect("the pool lock should not be poisoned");
+                state.idle -= 1;
+            }
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, PoolState> {
+        self.state
+            .lock()
+            .expect("the pool lock should not be poisoned")
+    }
+}
+
+/// The files and memory reserved by a database in an [Env], released when it is dropped.
+pub(super) struct Reservation {
+    shared: Arc<Shared>,
+    files: usize,
+    memory: usize,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut usage = self.shared.usage();
+        usage.open_files -= self.files;
+        usage.memory -= self.memory;
+    }
+}
+
+/// A handle to the result of a job run by [Env::spawn].
+pub struct BackgroundJob<T> {
+    receiver: Receiver<thread::Result<T>>,
+}
+
+impl<T> BackgroundJob<T> {
+    /// Waits for the job to finish and returns its result, or resumes its panic if it panicked.
+    pub fn join(self) -> T {
+        match self.receiver.recv() {
+            Ok(Ok(result)) => result,
+            Ok(Err(payload)) => panic::resume_unwind(payload),
+            Err(_) => unreachable!("the queued jobs should all run"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    /// A reservation is refused past the limits, and its resources are granted again once an
+    /// earlier reservation is dropped.
+    #[test]
+    fn reserve_enforces_limits() {
+        let env = Env::new().max_open_files(4).memory_budget(1000);
+
+        let first = env.reserve(2, 600).expect("reserve should not fail");
+        let too_much_memory = env.reserve(2, 600);
+        let second = env.reserve(2, 400).expect("reserve should not fail");
+        let too_many_files = env.reserve(1, 0);
+        drop(first);
+        let third = env.reserve(1, 100).expect("reserve should not fail");
+
+        assert!(matches!(
+            too_much_memory,
+            Err(DatabaseError::ResourceLimit(_))
+        ));
+        assert!(matches!(
+            too_many_files,
+            Err(DatabaseError::ResourceLimit(_))
+        ));
+        assert_eq!((env.open_files(), env.memory_used()), (3, 500));
+        drop((second, third));
+        assert_eq!((env.open_files(), env.memory_used()), (0, 0));
+    }
+
+    /// The jobs run on no more threads than the environment allows.
+    #[test]
+    fn spawn_bounds_background_threads() {
+        let env = Env::new().background_threads(3);
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+
+        let jobs: Vec<_> = (0..12)
+            .map(|_| {
+                let running = Arc::clone(&running);
+                let most_running = Arc::clone(&most_running);
+                env.spawn(move || {
+                    let count = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_running.fetch_max(count, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for job in jobs {
+            job.join();
+        }
+
+        assert_eq!(most_running.load(Ordering::SeqCst), 3);
+        assert_eq!(env.shared.pool.state().threads, 3);
+    }
+}
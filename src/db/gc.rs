@@ -0,0 +1,118 @@
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{Database, DatabaseError};
+
+/// Describes a garbage collection of the old versions of a [Database], returned by
+/// [Database::collect_garbage].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// The number of versions whose replaced pages were freed.
+    pub reclaimed_versions: usize,
+    /// The number of pages freed.
+    pub reclaimed_pages: usize,
+    /// The number of versions whose replaced pages are kept, because an open snapshot may still
+    /// see them.
+    pub retained_versions: usize,
+    /// The number of pages kept.
+    pub retained_pages: usize,
+}
+
+/// A thread collecting the garbage of a shared [Database] at an interval, started by
+/// [SharedDatabase::start_garbage_collector](super::SharedDatabase::start_garbage_collector).
+///
+/// The collector does not keep the database open: it stops once every handle to the database is
+/// dropped, or at its first error. It is stopped when it is dropped.
+pub struct GarbageCollector {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<Result<(), DatabaseError>>>,
+}
+
+impl GarbageCollector {
+    /// Starts a thread collecting the garbage of a database every `interval`.
+    pub(super) fn start(database: Weak<RwLock<Database>>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let thread = thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            let Some(database) = database.upgrade() else {
+                return Ok(());
+            };
+            collect(&database)?;
+        });
+        GarbageCollector {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Stops the collector, waiting for a collection in progress to end.
+    ///
+    /// # Errors
+    ///
+    /// This method will return the error that stopped the collector, if any.
+    pub fn stop(mut self) -> Result<(), DatabaseError> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), DatabaseError> {
+        drop(self.stop.take());
+        match self.thread.take() {
+            Some(thread) => thread.join().expect("the collector should not panic"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for GarbageCollector {
+    fn drop(&mut self) {
+        // The error is returned by `stop`, and is lost if the collector is dropped instead.
+        let _ = self.join();
+    }
+}
+
+/// Collects the garbage of a shared database, holding it for writing.
+fn collect(database: &Arc<RwLock<Database>>) -> Result<GcReport, DatabaseError> {
+    database
+        .write()
+        .expect("the database lock should not be poisoned")
+        .collect_garbage()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::TempDir;
+    use crate::db::{Options, SharedDatabase};
+
+    use super::*;
+
+    /// The collector does not keep the database open once its handles are dropped, and stops at
+    /// its first error.
+    #[test]
+    fn garbage_collector_stops_with_database_or_error() {
+        let directory = TempDir::new();
+        let database =
+            SharedDatabase::open(directory.path(), Options::new()).expect("open should not fail");
+        let collector = database.start_garbage_collector(Duration::from_millis(1));
+        thread::sleep(Duration::from_millis(20));
+
+        drop(database);
+        let stopped = collector.stop();
+        Database::open(directory.path(), Options::new())
+            .expect("open should not fail")
+            .close()
+            .expect("close should not fail");
+        let read_only = SharedDatabase::open(directory.path(), Options::new().read_only(true))
+            .expect("open should not fail");
+        let failing = read_only.start_garbage_collector(Duration::from_millis(1));
+        thread::sleep(Duration::from_millis(20));
+        let failed = failing.stop();
+
+        assert!(stopped.is_ok());
+        assert!(matches!(failed, Err(DatabaseError::ReadOnly)));
+    }
+}
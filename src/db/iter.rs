@@ -0,0 +1,151 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::iter::Peekable;
+
+use crate::btree::{KeyComparator, Snapshot, SnapshotIter};
+use crate::pager::with_fill_cache;
+
+use super::compression::decode_value;
+use super::compressor::Compressors;
+use super::database::DatabaseFile;
+use super::stats::Counters;
+use super::DatabaseError;
+
+/// A key and its value.
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// An iterator over the entries of a [Database](super::Database) within a range of keys, in key
+/// order, returned by [Database::iter](super::Database::iter) and
+/// [Database::iter_with_options](super::Database::iter_with_options).
+///
+/// The iterator reads the database as it was when the iterator was created, and holds a snapshot
+/// of that version until it is dropped. The pages are read lazily: only the entries of the current
+/// leaf and the pages left to visit on the path to it are kept in memory. After an error is
+/// returned, the iterator does not return any more items.
+pub struct DatabaseIter<'a> {
+    counters: &'a Counters,
+    compressors: &'a Compressors,
+    /// The entries, or `None` if the iterator can't read its snapshot.
+    entries: Option<SnapshotIter<'a, DatabaseFile>>,
+    /// Keeps the pages read by the iterator from being reused.
+    _snapshot: Option<Snapshot>,
+    verify_checksums: bool,
+    fill_cache: bool,
+    /// The error returned first, if the iterator can't read its snapshot.
+    error: Option<DatabaseError>,
+}
+
+impl<'a> DatabaseIter<'a> {
+    pub(super) fn new(
+        counters: &'a Counters,
+        compressors: &'a Compressors,
+        snapshot: Snapshot,
+        entries: SnapshotIter<'a, DatabaseFile>,
+        verify_checksums: bool,
+        fill_cache: bool,
+    ) -> Self {
+        DatabaseIter {
+            counters,
+            compressors,
+            entries: Some(entries),
+            _snapshot: Some(snapshot),
+            verify_checksums,
+            fill_cache,
+            error: None,
+        }
+    }
+
+    /// Returns an iterator returning `error`, then nothing.
+    pub(super) fn failed(
+        counters: &'a Counters,
+        compressors: &'a Compressors,
+        error: DatabaseError,
+    ) -> Self {
+        DatabaseIter {
+            counters,
+            compressors,
+            entries: None,
+            _snapshot: None,
+            verify_checksums: false,
+            fill_cache: false,
+            error: Some(error),
+        }
+    }
+}
+
+impl Iterator for DatabaseIter<'_> {
+    type Item = Result<Entry, DatabaseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        let entries = self.entries.as_mut()?;
+        let entry =
+            with_fill_cache(self.fill_cache, || entries.next())?.map_err(DatabaseError::from);
+        Some(entry.and_then(|(key, stored)| {
+            let value = decode_value(stored, self.verify_checksums, self.compressors)?;
+            self.counters.record_read(Some(&value));
+            Ok((key, value))
+        }))
+    }
+}
+
+/// An iterator over the entries of a [Database](super::Database) within a range of keys, as a
+/// [WriteBatch](super::WriteBatch) would leave them, in key order, returned by
+/// [Database::iter_from_batch](super::Database::iter_from_batch) and by the iterators of the
+/// transactions.
+///
+/// The value each key of the batch would have is resolved when the iterator is created, and the
+/// iterator merges them with the entries of the database: a key written by the batch hides the
+/// entry of the database, and a key removed by the batch is skipped. After an error is returned,
+/// the iterator does not return any more items.
+pub struct BatchIter<'a> {
+    comparator: &'a dyn KeyComparator,
+    entries: Peekable<DatabaseIter<'a>>,
+    /// The keys of the batch within the range, in key order, with the value they would have, or
+    /// `None` if they would be removed.
+    writes: VecDeque<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl<'a> BatchIter<'a> {
+    pub(super) fn new(
+        comparator: &'a dyn KeyComparator,
+        entries: DatabaseIter<'a>,
+        writes: VecDeque<(Vec<u8>, Option<Vec<u8>>)>,
+    ) -> Self {
+        BatchIter {
+            comparator,
+            entries: entries.peekable(),
+            writes,
+        }
+    }
+}
+
+impl Iterator for BatchIter<'_> {
+    type Item = Result<Entry, DatabaseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let ordering = match (self.entries.peek(), self.writes.front()) {
+                (None, None) => return None,
+                (Some(Err(_)), _) => {
+                    self.writes.clear();
+                    return self.entries.next();
+                }
+                (Some(Ok(_)), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(Ok((key, _))), Some((written, _))) => self.comparator.compare(key, written),
+            };
+            if ordering.is_lt() {
+                return self.entries.next();
+            }
+            if ordering.is_eq() {
+                self.entries.next();
+            }
+            if let Some((key, Some(value))) = self.writes.pop_front() {
+                return Some(Ok((key, value)));
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+
+use super::database::io_error;
+use super::DatabaseError;
+
+/// Name of the file locked by the process writing to a database, in its directory.
+pub(super) const LOCK_FILE_NAME: &str = "lock";
+
+/// An exclusive lock on the lock file of a database, so a single [Database](super::Database)
+/// writes to it at a time. The lock is released when it is dropped, or when its process exits,
+/// but the file is left in the directory.
+///
+/// The lock is taken on the open file, so a second writer is refused even in the same process.
+#[derive(Debug)]
+pub(super) struct DatabaseLock {
+    _file: File,
+}
+
+impl DatabaseLock {
+    /// Takes the lock of the database in the directory at `path`, creating its file if needed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return [DatabaseError::Locked] if another writer holds the lock, or an
+    /// error if the file can't be created or locked.
+    pub(super) fn acquire(path: &Path) -> Result<Self, DatabaseError> {
+        let lock_path = path.join(LOCK_FILE_NAME);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .map_err(|source| io_error(&lock_path, source))?;
+        match file.try_lock() {
+            Ok(()) => Ok(DatabaseLock { _file: file }),
+            Err(TryLockError::WouldBlock) => Err(DatabaseError::Locked(path.display().to_string())),
+            Err(TryLockError::Error(source)) => Err(io_error(&lock_path, source)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::TempDir;
+
+    use super::*;
+
+    /// The lock is refused while it is held, and taken again once the holder drops it.
+    #[test]
+    fn acquire_refuses_held_lock() {
+        let directory = TempDir::new();
+
+        let lock = DatabaseLock::acquire(directory.path()).expect("acquire should not fail");
+        let held = DatabaseLock::acquire(directory.path());
+        drop(lock);
+        let released = DatabaseLock::acquire(directory.path());
+
+        assert!(matches!(held, Err(DatabaseError::Locked(_))));
+        assert!(released.is_ok());
+        assert!(directory.path().join(LOCK_FILE_NAME).is_file());
+    }
+}
@@ -0,0 +1,49 @@
+mod backup;
+mod catalog;
+mod compression;
+mod compressor;
+mod database;
+mod dump;
+mod env;
+mod gc;
+mod iter;
+mod lock;
+mod options;
+mod prepared;
+mod shared;
+mod snapshot;
+mod stall;
+mod stats;
+mod transaction;
+mod txn_ids;
+#[cfg(feature = "serde")]
+mod typed_tree;
+mod watch;
+mod write_batch;
+pub use backup::{BackupEngine, BackupInfo};
+pub use catalog::KeyspaceInfo;
+pub use compression::Compression;
+#[cfg(feature = "lz4")]
+pub use compressor::Lz4Compressor;
+#[cfg(feature = "snappy")]
+pub use compressor::SnappyCompressor;
+#[cfg(feature = "zstd")]
+pub use compressor::ZstdCompressor;
+pub use compressor::{Compressor, LzCompressor, MAX_COMPRESSOR_ID, MIN_CUSTOM_COMPRESSOR_ID};
+pub use database::{Database, DatabaseError};
+pub use env::{BackgroundJob, Env};
+pub use gc::{GarbageCollector, GcReport};
+pub use iter::{BatchIter, DatabaseIter};
+pub use options::{
+    Durability, IsolationLevel, KeyspaceOptions, Options, ReadOptions, TransactionOptions,
+};
+pub use prepared::PreparedId;
+pub use shared::SharedDatabase;
+pub use snapshot::DatabaseSnapshot;
+pub use stall::{StallKind, StallListener, WriteStall};
+pub use stats::DatabaseStats;
+pub use transaction::{OptimisticTransaction, Transaction};
+#[cfg(feature = "serde")]
+pub use typed_tree::{TypedIter, TypedTree};
+pub use watch::{Change, ChangeEvent};
+pub use write_batch::{CommitHook, WriteBatch};
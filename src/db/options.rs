@@ -0,0 +1,732 @@
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::btree::{comparator_by_name, BytewiseComparator, KeyComparator};
+use crate::fs::{FileError, OsFile};
+use crate::pager::Pager;
+use crate::wal::WalFile;
+
+use super::{Compression, DatabaseError, DatabaseSnapshot, Env};
+
+/// When the commits of a [Database](super::Database) become durable.
+///
+/// A commit is durable once it is written to the write-ahead log and the log is synced, which
+/// waits for the disk. The durabilities sync the log less often, so the commits return sooner but
+/// a crash may lose the latest ones: a crash of the process loses the commits not yet written to
+/// the log, and a loss of power also those not yet synced. Whatever the durability, a crash never
+/// leaves a commit half applied.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Durability {
+    /// The log is synced at each commit, so a commit is durable when it returns.
+    #[default]
+    Always,
+    /// Each commit is written to the log, and the log is synced every `n` commits. A crash of the
+    /// process loses no commit, and a loss of power at most the last `n - 1`.
+    EveryNCommits(u32),
+    /// Each commit is written to the log, which is only synced when it is copied to the data
+    /// file, when the database is closed, or by the operating system when it chooses. A crash of
+    /// the process loses no commit, and a loss of power those written since the log was synced.
+    Async,
+    /// The commits are kept in memory, and are only written to the log with a later commit that
+    /// syncs it, past [Options::stop_pending_size], or when the database is closed. A crash loses
+    /// them.
+    Off,
+}
+
+/// Configures how a [Database](super::Database) is opened.
+///
+/// The options are set with a builder, starting from the defaults, and are validated when the
+/// database is opened. They can be saved to, and loaded from, a configuration file in the TOML
+/// format, where the options left out keep their default.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::TempDir;
+/// use rouilledb::db::{Compression, Database, Durability, Options};
+///
+/// let directory = TempDir::new();
+/// let options = Options::new()
+///     .page_size(8192)
+///     .cache_size(1024 * 1024)
+///     .durability(Durability::EveryNCommits(8))
+///     .compression(Compression::Lz);
+/// options
+///     .save(directory.path().join("options.toml"))
+///     .expect("save should not fail");
+///
+/// let loaded =
+///     Options::load(directory.path().join("options.toml")).expect("load should not fail");
+/// let database = Database::open(directory.path().join("database"), loaded.clone())
+///     .expect("open should not fail");
+///
+/// assert_eq!(loaded, options);
+/// assert_eq!(database.options(), &options);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Options {
+    pub(super) create_if_missing: bool,
+    pub(super) error_if_exists: bool,
+    pub(super) read_only: bool,
+    pub(super) replay_wal: bool,
+    pub(super) page_size: usize,
+    pub(super) cache_size: usize,
+    pub(super) durability: Durability,
+    pub(super) compression: Compression,
+    pub(super) comparator: String,
+    pub(super) wal_dir: Option<PathBuf>,
+    pub(super) checkpoint_size: usize,
+    pub(super) lock_timeout: Duration,
+    pub(super) lock_escalation_threshold: usize,
+    pub(super) slowdown_pending_size: usize,
+    pub(super) stop_pending_size: usize,
+    pub(super) slowdown_delay: Duration,
+    pub(super) max_key_size: Option<usize>,
+    pub(super) max_value_size: Option<usize>,
+    pub(super) max_snapshot_age: Option<Duration>,
+    #[serde(skip)]
+    pub(super) env: Option<Env>,
+}
+
+impl Options {
+    /// Returns the default options.
+    pub fn new() -> Self {
+        Options::default()
+    }
+
+    /// Creates the database if it does not exist. Enabled by default.
+    pub fn create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+
+    /// Fails to open a database that already exists. Disabled by default.
+    pub fn error_if_exists(mut self, error_if_exists: bool) -> Self {
+        self.error_if_exists = error_if_exists;
+        self
+    }
+
+    /// Opens the database for reading only: a missing database is not created, the writes fail
+    /// and nothing is written to the files, which are opened for reading only. Disabled by
+    /// default.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Reads the commits found in the log of a database opened for reading only. They are kept
+    /// in memory, and the files are not written. When disabled, the log is ignored and the
+    /// database is read as of the last time its log was copied to the data file. Only a read-only
+    /// database can ignore its log. Enabled by default.
+    pub fn replay_wal(mut self, replay_wal: bool) -> Self {
+        self.replay_wal = replay_wal;
+        self
+    }
+
+    /// Sets the size of the pages of a new database, a power of two between
+    /// [Pager::MIN_PAGE_SIZE] and [Pager::MAX_PAGE_SIZE]. The page size of an existing database
+    /// can't be changed and this option is then ignored. 4 KiB by default.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Sets the size, in bytes, of the pages kept in memory by the buffer pool. 4 MiB by default.
+    pub fn cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = cache_size;
+        self
+    }
+
+    /// Sets when the commits become durable. [Durability::Always] by default.
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Sets how the values written from now on are compressed. [Compression::None] by default.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the name of the comparator ordering the keys, one of the comparators of the
+    /// [btree](crate::btree) module (see [comparator_by_name]). A database is always opened with
+    /// the comparator it was created with. [BytewiseComparator] by default.
+    pub fn comparator(mut self, comparator: impl Into<String>) -> Self {
+        self.comparator = comparator.into();
+        self
+    }
+
+    /// Sets the directory of the write-ahead log, which is the directory of the database by
+    /// default. A database must always be opened with the same log directory.
+    pub fn wal_dir(mut self, wal_dir: impl Into<PathBuf>) -> Self {
+        self.wal_dir = Some(wal_dir.into());
+        self
+    }
+
+    /// Sets the size, in bytes, the write-ahead log grows to before its pages are copied to the
+    /// data file. 4 MiB by default.
+    pub fn checkpoint_size(mut self, checkpoint_size: usize) -> Self {
+        self.checkpoint_size = checkpoint_size;
+        self
+    }
+
+    /// Sets how long a [Transaction](super::Transaction) waits for a key locked by another
+    /// transaction before it fails. 1 second by default.
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+
+    /// Sets the number of keys a [Transaction](super::Transaction) locks one by one before its
+    /// locks are escalated to a single lock on the range from the smallest to the largest of
+    /// them, unless another transaction holds a lock within that range. 1000 by default.
+    pub fn lock_escalation_threshold(mut self, lock_escalation_threshold: usize) -> Self {
+        self.lock_escalation_threshold = lock_escalation_threshold;
+        self
+    }
+
+    /// Sets the size, in bytes, of the writes not yet copied to the data file above which each
+    /// commit is delayed by the slowdown delay (see [WriteStall](super::WriteStall)). The writes
+    /// not copied are those kept in memory since the last synced commit and the log. 32 MiB by
+    /// default.
+    pub fn slowdown_pending_size(mut self, slowdown_pending_size: usize) -> Self {
+        self.slowdown_pending_size = slowdown_pending_size;
+        self
+    }
+
+    /// Sets the size, in bytes, of the writes not yet copied to the data file above which a
+    /// commit waits for them to be copied before it returns. 64 MiB by default.
+    pub fn stop_pending_size(mut self, stop_pending_size: usize) -> Self {
+        self.stop_pending_size = stop_pending_size;
+        self
+    }
+
+    /// Sets how long a commit is delayed once the writes not yet copied to the data file exceed
+    /// the slowdown size. 1 millisecond by default.
+    pub fn slowdown_delay(mut self, slowdown_delay: Duration) -> Self {
+        self.slowdown_delay = slowdown_delay;
+        self
+    }
+
+    /// Sets the size of the largest key that can be written. The keys can never be larger than
+    /// the page size allows (see [BTree::max_key_size](crate::btree::BTree::max_key_size)), which
+    /// is the limit by default.
+    pub fn max_key_size(mut self, max_key_size: usize) -> Self {
+        self.max_key_size = Some(max_key_size);
+        self
+    }
+
+    /// Sets the size of the largest value that can be written, before compression. A value can
+    /// never be larger than what the page size leaves beside its key (see
+    /// [BTree::max_entry_size](crate::btree::BTree::max_entry_size)), which is the limit by
+    /// default.
+    pub fn max_value_size(mut self, max_value_size: usize) -> Self {
+        self.max_value_size = Some(max_value_size);
+        self
+    }
+
+    /// Sets the age after which a snapshot is released, so an old snapshot can't keep the pages
+    /// of its version from being reused forever. The reads of a released snapshot, and the commit
+    /// of an optimistic transaction holding it, fail with
+    /// [DatabaseError::SnapshotTooOld](super::DatabaseError::SnapshotTooOld). The snapshots are
+    /// checked when a snapshot is taken, a commit is made or garbage is collected. The snapshots
+    /// are never released by default.
+    pub fn max_snapshot_age(mut self, max_snapshot_age: Duration) -> Self {
+        self.max_snapshot_age = Some(max_snapshot_age);
+        self
+    }
+
+    /// Sets the environment whose resources the database shares with the other databases opened
+    /// with it (see [Env]). The environment is not saved with the options. None by default: the
+    /// database is only limited by its own options.
+    pub fn env(mut self, env: Env) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    /// Loads options from a configuration file written by [Options::save].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read or is not a valid
+    /// configuration file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DatabaseError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|source| FileError::Io {
+            filename: path.display().to_string(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|error| DatabaseError::InvalidOptions(error.to_string()))
+    }
+
+    /// Saves the options to a configuration file, in the TOML format.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file can't be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), DatabaseError> {
+        let path = path.as_ref();
+        let text = toml::to_string(self)
+            .map_err(|error| DatabaseError::InvalidOptions(error.to_string()))?;
+        std::fs::write(path, text).map_err(|source| {
+            FileError::Io {
+                filename: path.display().to_string(),
+                source,
+            }
+            .into()
+        })
+    }
+
+    /// Checks that the options are consistent, and returns the comparator they name.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if an option is out of its bounds, if the comparator is
+    /// unknown or if the options contradict each other.
+    pub(super) fn validate(&self) -> Result<Box<dyn KeyComparator>, DatabaseError> {
+        let invalid = |message: String| Err(DatabaseError::InvalidOptions(message));
+        if !self.page_size.is_power_of_two()
+            || !(Pager::<OsFile>::MIN_PAGE_SIZE..=Pager::<OsFile>::MAX_PAGE_SIZE)
+                .contains(&self.page_size)
+        {
+            return invalid(format!(
+                "the page size ({}) is not a power of two between {} and {}",
+                self.page_size,
+                Pager::<OsFile>::MIN_PAGE_SIZE,
+                Pager::<OsFile>::MAX_PAGE_SIZE
+            ));
+        }
+        if self.checkpoint_size == 0 {
+            return invalid("the checkpoint size is 0".to_string());
+        }
+        if self.durability == Durability::EveryNCommits(0) {
+            return invalid("the log is synced every 0 commits".to_string());
+        }
+        if self.read_only && self.error_if_exists {
+            return invalid("a read-only database can't be required to be new".to_string());
+        }
+        if self.slowdown_pending_size > self.stop_pending_size {
+            return invalid(format!(
+                "the slowdown size ({}) is larger than the stop size ({})",
+                self.slowdown_pending_size, self.stop_pending_size
+            ));
+        }
+        if !self.read_only && !self.replay_wal {
+            return invalid("only a read-only database can ignore its log".to_string());
+        }
+        match comparator_by_name(&self.comparator) {
+            Some(comparator) => Ok(comparator),
+            None => invalid(format!("the comparator \"{}\" is unknown", self.comparator)),
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            create_if_missing: true,
+            error_if_exists: false,
+            read_only: false,
+            replay_wal: true,
+            page_size: Pager::<OsFile>::DEFAULT_PAGE_SIZE,
+            cache_size: 1024 * Pager::<OsFile>::DEFAULT_PAGE_SIZE,
+            durability: Durability::default(),
+            compression: Compression::default(),
+            comparator: BytewiseComparator.name().to_string(),
+            wal_dir: None,
+            checkpoint_size: WalFile::<OsFile, OsFile>::DEFAULT_CHECKPOINT_SIZE,
+            lock_timeout: Duration::from_secs(1),
+            lock_escalation_threshold: 1000,
+            slowdown_pending_size: 32 * 1024 * 1024,
+            stop_pending_size: 64 * 1024 * 1024,
+            slowdown_delay: Duration::from_millis(1),
+            max_key_size: None,
+            max_value_size: None,
+            max_snapshot_age: None,
+            env: None,
+        }
+    }
+}
+
+/// Configures a read of a [Database](super::Database), given to
+/// [Database::get_with_options](super::Database::get_with_options) and
+/// [Database::iter_with_options](super::Database::iter_with_options).
+///
+/// The options are set with a builder, starting from the defaults: the checksums of the values are
+/// verified, the pages read are added to the cache, the iterations are not bounded and the reads
+/// see the last commit.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::TempDir;
+/// use rouilledb::db::{Database, Options, ReadOptions};
+///
+/// let directory = TempDir::new();
+/// let mut database =
+///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+/// for key in [b"a", b"b", b"c", b"d"] {
+///     database.put(key, b"old").expect("put should not fail");
+/// }
+/// let snapshot = database.snapshot();
+/// database.put(b"b", b"new").expect("put should not fail");
+///
+/// let options = ReadOptions::new()
+///     .fill_cache(false)
+///     .lower_bound(b"b")
+///     .upper_bound(b"d")
+///     .snapshot(&snapshot);
+/// let entries: Vec<_> = database
+///     .iter_with_options::<[u8], _>(.., &options)
+///     .map(|entry| entry.expect("iteration should not fail"))
+///     .collect();
+///
+/// assert_eq!(
+///     entries,
+///     vec![(b"b".to_vec(), b"old".to_vec()), (b"c".to_vec(), b"old".to_vec())]
+/// );
+/// ```
+#[derive(Clone)]
+pub struct ReadOptions<'a> {
+    pub(super) verify_checksums: bool,
+    pub(super) fill_cache: bool,
+    pub(super) lower_bound: Option<Vec<u8>>,
+    pub(super) upper_bound: Option<Vec<u8>>,
+    pub(super) snapshot: Option<&'a DatabaseSnapshot>,
+}
+
+impl<'a> ReadOptions<'a> {
+    /// Returns the default options.
+    pub fn new() -> Self {
+        ReadOptions::default()
+    }
+
+    /// Compares the checksum of each value read with its stored bytes, and fails if they do not
+    /// match. Enabled by default.
+    pub fn verify_checksums(mut self, verify_checksums: bool) -> Self {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    /// Adds the pages read to the cache of the database. Disabling it keeps a large scan from
+    /// evicting the pages read most recently. Enabled by default.
+    pub fn fill_cache(mut self, fill_cache: bool) -> Self {
+        self.fill_cache = fill_cache;
+        self
+    }
+
+    /// Sets the smallest key returned by the iterations, whatever their range. The point lookups
+    /// are not bounded.
+    pub fn lower_bound(mut self, lower_bound: &[u8]) -> Self {
+        self.lower_bound = Some(lower_bound.to_vec());
+        self
+    }
+
+    /// Sets the key before which the iterations stop, whatever their range. The point lookups are
+    /// not bounded.
+    pub fn upper_bound(mut self, upper_bound: &[u8]) -> Self {
+        self.upper_bound = Some(upper_bound.to_vec());
+        self
+    }
+
+    /// Reads the database as it was when a snapshot was taken, instead of its last commit.
+    pub fn snapshot(mut self, snapshot: &'a DatabaseSnapshot) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Returns the bounds of `range` restricted to the iteration bounds, with the keys ordered by
+    /// `comparator`.
+    pub(super) fn restrict<'b, K, R>(
+        &'b self,
+        range: &'b R,
+        comparator: &dyn KeyComparator,
+    ) -> (Bound<&'b [u8]>, Bound<&'b [u8]>)
+    where
+        K: AsRef<[u8]> + ?Sized + 'b,
+        R: RangeBounds<K>,
+    {
+        let mut start = range.start_bound().map(|key| key.as_ref());
+        if let Some(lower_bound) = &self.lower_bound {
+            let before = match start {
+                Bound::Included(key) | Bound::Excluded(key) => {
+                    comparator.compare(key, lower_bound).is_lt()
+                }
+                Bound::Unbounded => true,
+            };
+            if before {
+                start = Bound::Included(lower_bound);
+            }
+        }
+        let mut end = range.end_bound().map(|key| key.as_ref());
+        if let Some(upper_bound) = &self.upper_bound {
+            let after = match end {
+                Bound::Included(key) | Bound::Excluded(key) => {
+                    comparator.compare(key, upper_bound).is_ge()
+                }
+                Bound::Unbounded => true,
+            };
+            if after {
+                end = Bound::Excluded(upper_bound);
+            }
+        }
+        (start, end)
+    }
+}
+
+impl Default for ReadOptions<'_> {
+    fn default() -> Self {
+        ReadOptions {
+            verify_checksums: true,
+            fill_cache: true,
+            lower_bound: None,
+            upper_bound: None,
+            snapshot: None,
+        }
+    }
+}
+
+/// What the reads of a [Transaction](super::Transaction) see of the transactions committed while
+/// it runs, chosen with [TransactionOptions::isolation_level].
+///
+/// The writes of a transaction always lock their keys, so two transactions never write the same
+/// key at the same time, and its reads always see its own writes. The levels differ by the
+/// anomalies they allow, from the most to the fewest.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    /// Each read sees the last committed version of the database, without locking the keys read.
+    /// Reading a key twice may return two values (a non-repeatable read), and iterating a range
+    /// twice may return new keys (a phantom).
+    #[default]
+    ReadCommitted,
+    /// The reads see a snapshot of the database taken when the transaction starts. The commit
+    /// fails with [DatabaseError::Conflict] if a key written by the transaction was modified since,
+    /// so the first transaction to commit wins and no update is lost. Two transactions can still
+    /// each read what the other writes, and write keys the other one read (a write skew).
+    SnapshotIsolation,
+    /// The reads lock the keys read, and the iterations the ranges iterated, before they see the
+    /// last committed version of the database. The keys read can't be written by another
+    /// transaction until this one ends, so the transactions are serializable, at the cost of
+    /// waiting for the locks, or of failing with a lock timeout or a deadlock.
+    Serializable,
+}
+
+/// Configures a [Transaction](super::Transaction), given to
+/// [Database::begin_txn_with_options](super::Database::begin_txn_with_options).
+///
+/// The options are set with a builder, starting from the defaults: the transaction reads at the
+/// [IsolationLevel::ReadCommitted] level, waits for a locked key as long as the lock timeout of
+/// the database [Options], and never expires.
+///
+/// # Example
+///
+/// ```
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// use rouilledb::common::TempDir;
+/// use rouilledb::db::{Database, DatabaseError, Options, TransactionOptions};
+///
+/// let directory = TempDir::new();
+/// let mut database =
+///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+///
+/// let options = TransactionOptions::new()
+///     .lock_timeout(Duration::from_millis(10))
+///     .expiration(Duration::from_millis(20));
+/// let mut abandoned = database.begin_txn_with_options(&options);
+/// abandoned.put(b"key", b"abandoned").expect("put should not fail");
+/// thread::sleep(Duration::from_millis(30));
+///
+/// let mut transaction = database.begin_txn_with_options(&options);
+/// transaction.put(b"key", b"value").expect("put should not fail");
+/// transaction.commit(&mut database).expect("commit should not fail");
+///
+/// let result = abandoned.commit(&mut database);
+/// assert!(matches!(result, Err(DatabaseError::TransactionExpired)));
+/// assert_eq!(database.get(b"key").expect("get should not fail"), Some(b"value".to_vec()));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct TransactionOptions {
+    pub(super) lock_timeout: Option<Duration>,
+    pub(super) expiration: Option<Duration>,
+    pub(super) isolation_level: IsolationLevel,
+}
+
+impl TransactionOptions {
+    /// Returns the default options.
+    pub fn new() -> Self {
+        TransactionOptions::default()
+    }
+
+    /// Sets how long the transaction waits for a key locked by another transaction before it
+    /// fails, instead of the lock timeout of the database [Options].
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = Some(lock_timeout);
+        self
+    }
+
+    /// Sets how long the transaction lives after it starts. Once it expired, the other
+    /// transactions waiting for one of its locks release them all, and it fails to lock a key or
+    /// to commit, so an abandoned transaction can't block the others (see
+    /// [DatabaseError::TransactionExpired]). A transaction never expires by default, and stops
+    /// expiring once it is prepared.
+    pub fn expiration(mut self, expiration: Duration) -> Self {
+        self.expiration = Some(expiration);
+        self
+    }
+
+    /// Sets what the reads of the transaction see of the transactions committed while it runs
+    /// (see [IsolationLevel]).
+    pub fn isolation_level(mut self, isolation_level: IsolationLevel) -> Self {
+        self.isolation_level = isolation_level;
+        self
+    }
+}
+
+/// Configures a keyspace when it is created with
+/// [Database::create_keyspace](super::Database::create_keyspace). The options are recorded in the
+/// catalog of the database with the keyspace, and never change afterward.
+///
+/// The options are set with a builder, starting from the defaults: the keys are ordered bytewise
+/// and the values are stored as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyspaceOptions {
+    pub(super) comparator: String,
+    pub(super) compression: Compression,
+}
+
+impl KeyspaceOptions {
+    /// Returns the default options.
+    pub fn new() -> Self {
+        KeyspaceOptions::default()
+    }
+
+    /// Sets the name of the comparator ordering the keys of the keyspace, one of the comparators
+    /// of the [btree](crate::btree) module (see [comparator_by_name]). The keyspaces of a database
+    /// can be ordered differently from each other and from the database.
+    pub fn comparator(mut self, comparator: impl Into<String>) -> Self {
+        self.comparator = comparator.into();
+        self
+    }
+
+    /// Sets how the values of the keyspace are compressed.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Returns the comparator named by the options.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the comparator is unknown.
+    pub(super) fn validate(&self) -> Result<Box<dyn KeyComparator>, DatabaseError> {
+        comparator_by_name(&self.comparator).ok_or_else(|| {
+            DatabaseError::InvalidOptions(format!(
+                "the comparator \"{}\" is unknown",
+                self.comparator
+            ))
+        })
+    }
+}
+
+impl Default for KeyspaceOptions {
+    fn default() -> Self {
+        KeyspaceOptions {
+            comparator: BytewiseComparator.name().to_string(),
+            compression: Compression::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::ReverseComparator;
+    use crate::common::TempDir;
+
+    use super::*;
+
+    /// Options saved to a file are loaded back, and the options left out of a file keep their
+    /// default.
+    #[test]
+    fn load_saved_options_returns_options() {
+        let directory = TempDir::new();
+        let path = directory.path().join("options.toml");
+        let options = Options::new()
+            .read_only(true)
+            .comparator("rouilledb.reverse-bytewise")
+            .wal_dir(directory.path().join("wal"))
+            .compression(Compression::Lz)
+            .lock_timeout(Duration::from_millis(1500));
+        options.save(&path).expect("save should not fail");
+
+        let loaded = Options::load(&path).expect("load should not fail");
+        std::fs::write(&path, "page_size = 1024\n").expect("write should not fail");
+        let partial = Options::load(&path).expect("load should not fail");
+        std::fs::write(&path, "page_sise = 1024\n").expect("write should not fail");
+        let unknown = Options::load(&path);
+
+        assert_eq!(loaded, options);
+        assert_eq!(partial, Options::new().page_size(1024));
+        assert!(matches!(unknown, Err(DatabaseError::InvalidOptions(_))));
+    }
+
+    /// Options out of their bounds, naming an unknown comparator or contradicting each other are
+    /// rejected.
+    #[test]
+    fn validate_invalid_options_fails() {
+        let invalid = [
+            Options::new().page_size(1000),
+            Options::new().page_size(256),
+            Options::new().checkpoint_size(0),
+            Options::new().durability(Durability::EveryNCommits(0)),
+            Options::new().read_only(true).error_if_exists(true),
+            Options::new().replay_wal(false),
+            Options::new().comparator("unknown"),
+            Options::new().slowdown_pending_size(2).stop_pending_size(1),
+        ];
+
+        for options in invalid {
+            assert!(matches!(
+                options.validate(),
+                Err(DatabaseError::InvalidOptions(_))
+            ));
+        }
+        assert!(Options::new().validate().is_ok());
+    }
+
+    /// The iteration bounds narrow a range, in the order of the comparator, but never widen it.
+    #[test]
+    fn restrict_range_returns_narrowest_bounds() {
+        let options = ReadOptions::new().lower_bound(b"b").upper_bound(b"d");
+        let reverse = ReadOptions::new().lower_bound(b"d").upper_bound(b"b");
+
+        assert_eq!(
+            options.restrict::<[u8], _>(&(..), &BytewiseComparator),
+            (Bound::Included(&b"b"[..]), Bound::Excluded(&b"d"[..]))
+        );
+        assert_eq!(
+            options.restrict(&(&b"a"[..]..=&b"d"[..]), &BytewiseComparator),
+            (Bound::Included(&b"b"[..]), Bound::Excluded(&b"d"[..]))
+        );
+        assert_eq!(
+            options.restrict(&(&b"bb"[..]..&b"c"[..]), &BytewiseComparator),
+            (Bound::Included(&b"bb"[..]), Bound::Excluded(&b"c"[..]))
+        );
+        assert_eq!(
+            reverse.restrict::<[u8], _>(&(..), &ReverseComparator),
+            (Bound::Included(&b"d"[..]), Bound::Excluded(&b"b"[..]))
+        );
+        assert_eq!(
+            ReadOptions::new().restrict(&(&b"a"[..]..), &BytewiseComparator),
+            (Bound::Included(&b"a"[..]), Bound::Unbounded)
+        );
+    }
+}
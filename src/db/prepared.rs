@@ -0,0 +1,221 @@
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::common::checksum::crc32c;
+use crate::common::envelope::Envelope;
+use crate::common::varint;
+use crate::fs::{File, OsFile};
+
+use super::database::io_error;
+use super::{DatabaseError, WriteBatch};
+
+/// Name of the directory holding the records of the prepared transactions, in the directory of a
+/// database.
+pub(super) const PREPARED_DIRECTORY: &str = "prepared";
+
+/// Extension of the record of a prepared transaction.
+const RECORD_EXTENSION: &str = "txn";
+
+/// Identifies a transaction prepared with [Transaction::prepare](super::Transaction::prepare),
+/// until it is committed or rolled back.
+///
+/// The identifier is kept by the coordinator of the distributed transaction, as a `u64`, so the
+/// transaction can be resolved after a restart of either side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PreparedId(u64);
+
+impl From<u64> for PreparedId {
+    fn from(id: u64) -> Self {
+        PreparedId(id)
+    }
+}
+
+impl From<PreparedId> for u64 {
+    fn from(id: PreparedId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for PreparedId {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{:016x}", self.0)
+    }
+}
+
+/// Writes the record of a prepared transaction, holding the writes of its batch, in the
+/// directory of the database at `path`. The record is written to a temporary file, synced, then
+/// renamed, so it is never partially written. The directories holding it are synced after it is
+/// created and renamed, so the record survives a crash once this function returns.
+///
+/// Each write is stored as the tag of its [Envelope], the key and the operand, each preceded by its
+/// length as a varint. The record ends with the CRC32C, on 4 little-endian bytes, of the writes.
+pub(super) fn write(path: &Path, id: PreparedId, batch: &WriteBatch) -> Result<(), DatabaseError> {
+    let mut record = Vec::new();
+    for (key, operation) in batch.operations() {
+        let operand = operation.payload();
+        record.push(operation.tag());
+        varint::encode_u64(key.len() as u64, &mut record);
+        record.extend_from_slice(key);
+        varint::encode_u64(operand.len() as u64, &mut record);
+        record.extend_from_slice(operand);
+    }
+    record.extend_from_slice(&crc32c(&record).to_le_bytes());
+
+    let directory = path.join(PREPARED_DIRECTORY);
+    std::fs::create_dir_all(&directory).map_err(|source| io_error(&directory, source))?;
+    sync_directory(path)?;
+    let record_path = record_path(path, id);
+    let temporary = record_path.with_extension("tmp");
+    let mut file = OsFile::new(&temporary);
+    file.create()?;
+    file.write(0, &record)?;
+    file.sync()?;
+    file.close()?;
+    std::fs::rename(&temporary, &record_path).map_err(|source| io_error(&record_path, source))?;
+    sync_directory(&directory)
+}
+
+/// Reads the writes of a prepared transaction from its record, in the directory of the database
+/// at `path`.
+///
+/// # Errors
+///
+/// This function will return an error if the transaction is not prepared, or if its record can't
+/// be read or does not match its checksum.
+pub(super) fn read(path: &Path, id: PreparedId) -> Result<WriteBatch, DatabaseError> {
+    let record_path = record_path(path, id);
+    let record = match std::fs::read(&record_path) {
+        Ok(record) => record,
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            return Err(DatabaseError::PreparedNotFound(id))
+        }
+        Err(error) => return Err(io_error(&record_path, error)),
+    };
+    let corrupted = || DatabaseError::CorruptedPrepared(id);
+    let Some(end) = record.len().checked_sub(4) else {
+        return Err(corrupted());
+    };
+    let (writes, stored) = record.split_at(end);
+    if crc32c(writes).to_le_bytes() != stored {
+        return Err(corrupted());
+    }
+
+    let mut batch = WriteBatch::new();
+    let mut rest = writes;
+    while let Some((&tag, tail)) = rest.split_first() {
+        let Some((key, tail)) = split_length_prefixed(tail) else {
+            return Err(corrupted());
+        };
+        let Some((operand, tail)) = split_length_prefixed(tail) else {
+            return Err(corrupted());
+        };
+        match Envelope::from_tag(tag, operand.to_vec()) {
+            Some(Envelope::Value(value)) => batch.put(key, &value),
+            Some(Envelope::Tombstone) => batch.delete(key),
+            Some(Envelope::Merge(operand)) => batch.merge(key, &operand),
+            None => return Err(corrupted()),
+        }
+        rest = tail;
+    }
+    Ok(batch)
+}
+
+/// Removes the record of a prepared transaction, in the directory of the database at `path`. The
+/// directory of the records is synced after, so the removal survives a crash once this function
+/// returns.
+///
+/// # Errors
+///
+/// This function will return an error if the transaction is not prepared, or if its record can't
+/// be removed.
+pub(super) fn remove(path: &Path, id: PreparedId) -> Result<(), DatabaseError> {
+    let record_path = record_path(path, id);
+    match std::fs::remove_file(&record_path) {
+        Ok(()) => sync_directory(&path.join(PREPARED_DIRECTORY)),
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            Err(DatabaseError::PreparedNotFound(id))
+        }
+        Err(error) => Err(io_error(&record_path, error)),
+    }
+}
+
+/// Returns the identifiers of the transactions prepared in the directory of the database at
+/// `path`, in increasing order.
+pub(super) fn ids(path: &Path) -> Result<Vec<PreparedId>, DatabaseError> {
+    let directory = path.join(PREPARED_DIRECTORY);
+    let entries = match std::fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(io_error(&directory, error)),
+    };
+    let mut ids = Vec::new();
+    for entry in entries {
+        let record_path = entry.map_err(|source| io_error(&directory, source))?.path();
+        if record_path
+            .extension()
+            .is_some_and(|extension| extension == RECORD_EXTENSION)
+        {
+            if let Some(id) = record_path
+                .file_stem()
+                .and_then(|stem| u64::from_str_radix(stem.to_str()?, 16).ok())
+            {
+                ids.push(PreparedId(id));
+            }
+        }
+    }
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+/// Syncs the entries of `directory`, so the files created, renamed or removed in it survive a
+/// crash.
+fn sync_directory(directory: &Path) -> Result<(), DatabaseError> {
+    std::fs::File::open(directory)
+        .and_then(|directory| directory.sync_all())
+        .map_err(|source| io_error(directory, source))
+}
+
+fn record_path(path: &Path, id: PreparedId) -> PathBuf {
+    path.join(PREPARED_DIRECTORY)
+        .join(format!("{id}.{RECORD_EXTENSION}"))
+}
+
+/// Splits the bytes of a key or an operand, preceded by their length, from the start of `bytes`.
+/// Returns `None` if `bytes` ends before them.
+fn split_length_prefixed(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (length, length_size) = varint::decode_u64(bytes)?;
+    bytes[length_size..].split_at_checked(usize::try_from(length).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::TempDir;
+
+    use super::*;
+
+    /// The writes of a batch are read back from the record of a prepared transaction, and a
+    /// damaged record is detected.
+    #[test]
+    fn read_returns_written_batch() {
+        let directory = TempDir::new();
+        let mut batch = WriteBatch::new();
+        batch.put(b"a", b"1");
+        batch.delete(b"b");
+        batch.merge(b"c", b"2");
+        let id = PreparedId::from(42);
+
+        write(directory.path(), id, &batch).expect("write should not fail");
+        let read_batch = read(directory.path(), id).expect("read should not fail");
+        let listed = ids(directory.path()).expect("ids should not fail");
+        let record_path = record_path(directory.path(), id);
+        let mut record = std::fs::read(&record_path).expect("read should not fail");
+        record[0] ^= 0xFF;
+        std::fs::write(&record_path, record).expect("write should not fail");
+        let damaged = read(directory.path(), id);
+
+        assert_eq!(read_batch.operations(), batch.operations());
+        assert_eq!(listed, vec![id]);
+        assert!(matches!(damaged, Err(DatabaseError::CorruptedPrepared(_))));
+    }
+}
@@ -0,0 +1,239 @@
+use std::path::Path;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+use super::{Database, DatabaseError, DatabaseSnapshot, GarbageCollector, Options, WriteBatch};
+
+/// Represents a handle to a [Database] shared by several threads.
+///
+/// The handle is cheap to clone, and every clone reaches the same database, so each worker thread
+/// of a server can keep its own. The handle is a single reader-writer lock around the whole
+/// database, not a lock per tree or per key. The reads of the threads take it for reading, so they
+/// run in parallel and only wait for each other on the shard of the buffer pool caching the page
+/// they read. Each write takes it for writing: it waits for the reads in progress, and every other
+/// read and write waits for it, since every commit publishes a new root of the same tree and is
+/// appended to the same log.
+///
+/// The methods of the handle each hold the database for a single operation. Several operations
+/// can be made with the database held once, through [SharedDatabase::read] and
+/// [SharedDatabase::lock].
+#[derive(Clone)]
+pub struct SharedDatabase {
+    database: Arc<RwLock<Database>>,
+}
+
+impl SharedDatabase {
+    /// Creates a handle sharing an opened database.
+    pub fn new(database: Database) -> Self {
+        SharedDatabase {
+            database: Arc::new(RwLock::new(database)),
+        }
+    }
+
+    /// Opens the database in the directory at `path` as [Database::open] does, and returns a
+    /// handle sharing it.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [Database::open].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::thread;
+    ///
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Options, SharedDatabase};
+    ///
+    /// let directory = TempDir::new();
+    /// let database =
+    ///     SharedDatabase::open(directory.path(), Options::new()).expect("open should not fail");
+    ///
+    /// let workers: Vec<_> = (0..4)
+    ///     .map(|worker| {
+    ///         let database = database.clone();
+    ///         thread::spawn(move || {
+    ///             let key = format!("worker-{worker}");
+    ///             database
+    ///                 .put(key.as_bytes(), b"done")
+    ///                 .expect("put should not fail");
+    ///         })
+    ///     })
+    ///     .collect();
+    /// for worker in workers {
+    ///     worker.join().expect("the worker should not panic");
+    /// }
+    ///
+    /// assert_eq!(
+    ///     database.get(b"worker-3").expect("get should not fail"),
+    ///     Some(b"done".to_vec())
+    /// );
+    /// ```
+    pub fn open(path: impl AsRef<Path>, options: Options) -> Result<Self, DatabaseError> {
+        Ok(SharedDatabase::new(Database::open(path, options)?))
+    }
+
+    /// Holds the database for reading, alongside the other readers, until the guard is dropped.
+    pub fn read(&self) -> RwLockReadGuard<'_, Database> {
+        self.database
+            .read()
+            .expect("the database lock should not be poisoned")
+    }
+
+    /// Holds the database exclusively, for writing, until the guard is dropped.
+    pub fn lock(&self) -> RwLockWriteGuard<'_, Database> {
+        self.database
+            .write()
+            .expect("the database lock should not be poisoned")
+    }
+
+    /// Returns the value associated with a key, as [Database::get] does.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [Database::get].
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DatabaseError> {
+        self.read().get(key)
+    }
+
+    /// Returns the values associated with keys, as [Database::multi_get] does.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [Database::multi_get].
+    pub fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, DatabaseError> {
+        self.read().multi_get(keys)
+    }
+
+    /// Returns a snapshot of the database, as [Database::snapshot] does. The snapshot is read by
+    /// giving it the database held with [SharedDatabase::read].
+    pub fn snapshot(&self) -> DatabaseSnapshot {
+        self.read().snapshot()
+    }
+
+    /// Sets the value of a key, as [Database::put] does.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [Database::put].
+    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<(), DatabaseError> {
+        self.lock().put(key, value)
+    }
+
+    /// Removes a key, as [Database::delete] does.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [Database::delete].
+    pub fn delete(&self, key: &[u8]) -> Result<(), DatabaseError> {
+        self.lock().delete(key)
+    }
+
+    /// Writes a batch in a single commit, as [Database::write] does.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [Database::write].
+    pub fn write(&self, batch: WriteBatch) -> Result<(), DatabaseError> {
+        self.lock().write(batch)
+    }
+
+    /// Starts a thread collecting the garbage of the database every `interval`, as
+    /// [Database::collect_garbage] does, until the returned collector is stopped or every handle
+    /// to the database is dropped. Each collection holds the database for writing.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Options, SharedDatabase};
+    ///
+    /// let directory = TempDir::new();
+    /// let database =
+    ///     SharedDatabase::open(directory.path(), Options::new()).expect("open should not fail");
+    /// let collector = database.start_garbage_collector(Duration::from_millis(10));
+    ///
+    /// database.put(b"key", b"old").expect("put should not fail");
+    /// let snapshot = database.snapshot();
+    /// database.put(b"key", b"new").expect("put should not fail");
+    /// drop(snapshot);
+    /// thread::sleep(Duration::from_millis(50));
+    ///
+    /// collector.stop().expect("stop should not fail");
+    /// let stats = database.read().stats().expect("stats should not fail");
+    /// assert_eq!(stats.retired_pages, 0);
+    /// ```
+    pub fn start_garbage_collector(&self, interval: Duration) -> GarbageCollector {
+        GarbageCollector::start(Arc::downgrade(&self.database), interval)
+    }
+
+    /// Returns the shared database if this handle is the last one, so it can be closed, or the
+    /// handle otherwise.
+    ///
+    /// A [GarbageCollector] holds a handle to the database for the time of each collection, so
+    /// this method can return the handle while the collector runs: stop the collector first.
+    pub fn into_inner(self) -> Result<Database, Self> {
+        Arc::try_unwrap(self.database)
+            .map(|database| {
+                database
+                    .into_inner()
+                    .expect("the database lock should not be poisoned")
+            })
+            .map_err(|database| SharedDatabase { database })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::common::TempDir;
+
+    use super::*;
+
+    /// Both the database and its shared handle can be sent to and shared by other threads.
+    #[test]
+    fn database_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<Database>();
+        assert_send_sync::<SharedDatabase>();
+    }
+
+    /// The writes and reads made by several threads through clones of a handle all reach the
+    /// same database, which can be closed once the other handles are dropped.
+    #[test]
+    fn clones_share_database() {
+        let directory = TempDir::new();
+        let database =
+            SharedDatabase::open(directory.path(), Options::new()).expect("open should not fail");
+
+        thread::scope(|scope| {
+            for worker in 0..8 {
+                let database = database.clone();
+                scope.spawn(move || {
+                    for i in 0..50 {
+                        let key = format!("key-{worker}-{i:03}");
+                        database
+                            .put(key.as_bytes(), &[worker; 10])
+                            .expect("put should not fail");
+                        let value = database.get(key.as_bytes()).expect("get should not fail");
+                        assert_eq!(value, Some(vec![worker; 10]));
+                    }
+                });
+            }
+        });
+        let clone = database.clone();
+        let Err(database) = database.into_inner() else {
+            panic!("a clone should be left");
+        };
+        drop(clone);
+        let database = database.into_inner().ok().expect("no clone should be left");
+
+        assert_eq!(database.iter::<[u8], _>(..).count(), 400);
+        database.close().expect("close should not fail");
+    }
+}
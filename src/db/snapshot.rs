@@ -0,0 +1,154 @@
+use std::ops::RangeBounds;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::btree::Snapshot;
+
+use super::{Database, DatabaseError, DatabaseIter, ReadOptions};
+
+/// Represents a consistent, point-in-time, view of a [Database], returned by
+/// [Database::snapshot].
+///
+/// The reads made through the snapshot see the database exactly as it was when the snapshot was
+/// taken, whatever is written to the database afterwards. The pages of that version are not
+/// reused until the snapshot is dropped, so a snapshot should not be kept longer than needed: the
+/// data file grows with the modifications made while it is open.
+///
+/// A snapshot older than the [Options::max_snapshot_age](super::Options::max_snapshot_age) of
+/// its database is released by the database, so its pages can be reused: its reads then fail with
+/// [DatabaseError::SnapshotTooOld].
+///
+/// A snapshot reads the pages of the database it was taken from, which must be given to its
+/// methods. It can also be given to the reads of the database with
+/// [ReadOptions::snapshot].
+pub struct DatabaseSnapshot {
+    database_id: u64,
+    version: u64,
+    slot: Arc<SnapshotSlot>,
+}
+
+/// The snapshot of the tree held by a [DatabaseSnapshot], shared with its database, which releases
+/// it once it is too old.
+pub(super) struct SnapshotSlot {
+    taken_at: Instant,
+    snapshot: Mutex<Option<Snapshot>>,
+}
+
+impl DatabaseSnapshot {
+    pub(super) fn new(database_id: u64, snapshot: Snapshot) -> Self {
+        DatabaseSnapshot {
+            database_id,
+            version: snapshot.version(),
+            slot: Arc::new(SnapshotSlot {
+                taken_at: Instant::now(),
+                snapshot: Mutex::new(Some(snapshot)),
+            }),
+        }
+    }
+
+    /// Returns the version of the database seen by the snapshot.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns the time since the snapshot was taken.
+    pub fn age(&self) -> Duration {
+        self.slot.age()
+    }
+
+    /// Returns the value associated with a key when the snapshot was taken, or `None` if the key
+    /// was not present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the snapshot was released for being too old, or if a
+    /// page can't be read or is corrupted.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the snapshot was not taken from `database`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// database.put(b"key", b"old").expect("put should not fail");
+    ///
+    /// let snapshot = database.snapshot();
+    /// database.put(b"key", b"new").expect("put should not fail");
+    ///
+    /// let value = snapshot.get(&database, b"key").expect("get should not fail");
+    /// assert_eq!(value, Some(b"old".to_vec()));
+    /// ```
+    pub fn get(&self, database: &Database, key: &[u8]) -> Result<Option<Vec<u8>>, DatabaseError> {
+        database.get_with_options(key, &ReadOptions::new().snapshot(self))
+    }
+
+    /// Returns an iterator over the entries whose keys were within `range` when the snapshot was
+    /// taken, in key order. The iterator returns [DatabaseError::SnapshotTooOld] if the snapshot
+    /// was released for being too old. Once created, it keeps reading the version of the snapshot
+    /// until it is dropped.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the snapshot was not taken from `database`.
+    pub fn iter<'a, K, R>(&self, database: &'a Database, range: R) -> DatabaseIter<'a>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        database.iter_with_options(range, &ReadOptions::new().snapshot(self))
+    }
+
+    /// Returns a copy of the snapshot of the tree of `database`.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the snapshot was released for being too old.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the snapshot was not taken from `database`.
+    pub(super) fn inner(&self, database: &Database) -> Result<Snapshot, DatabaseError> {
+        assert_eq!(
+            self.database_id,
+            database.id(),
+            "the snapshot should be read from the database it was taken from"
+        );
+        self.slot
+            .snapshot()
+            .clone()
+            .ok_or_else(|| DatabaseError::SnapshotTooOld(self.age()))
+    }
+
+    /// Returns the slot of the snapshot, shared with the database.
+    pub(super) fn slot(&self) -> &Arc<SnapshotSlot> {
+        &self.slot
+    }
+}
+
+impl SnapshotSlot {
+    /// Returns the time since the snapshot was taken.
+    pub(super) fn age(&self) -> Duration {
+        self.taken_at.elapsed()
+    }
+
+    /// Returns `true` if the snapshot is still held.
+    pub(super) fn is_held(&self) -> bool {
+        self.snapshot().is_some()
+    }
+
+    /// Releases the snapshot, so its version can be reclaimed.
+    pub(super) fn release(&self) {
+        self.snapshot().take();
+    }
+
+    fn snapshot(&self) -> MutexGuard<'_, Option<Snapshot>> {
+        self.snapshot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+/// A listener called after each write stall of a [Database](super::Database), set with
+/// [Database::set_stall_listener](super::Database::set_stall_listener).
+pub type StallListener = Box<dyn Fn(&WriteStall) + Send + Sync>;
+
+/// How a commit of a [Database](super::Database) was stalled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallKind {
+    /// The commit was delayed by the slowdown delay of the options.
+    Slowdown,
+    /// The commit waited for the writes not yet copied to the data file to be copied.
+    Stop,
+}
+
+/// Describes a commit of a [Database](super::Database) stalled because the writes not yet copied
+/// to the data file grew past a threshold of its [Options](super::Options).
+///
+/// The writes made with [Durability::Off](super::Durability::Off) are kept in memory until a
+/// commit writes them to the log, and the log grows until it is copied to the data file. Past
+/// [Options::slowdown_pending_size](super::Options::slowdown_pending_size), each commit is delayed
+/// so the writers slow down. Past [Options::stop_pending_size](super::Options::stop_pending_size),
+/// the commit syncs the log and copies it to the data file before it returns, which bounds the
+/// memory and the log used by the writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteStall {
+    /// How the commit was stalled.
+    pub kind: StallKind,
+    /// The size, in bytes, of the writes not yet copied to the data file when the stall started.
+    pub pending_size: usize,
+    /// How long the commit was stalled.
+    pub duration: Duration,
+}
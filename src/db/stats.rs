@@ -0,0 +1,253 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use super::{StallKind, WriteStall};
+
+/// Describes the state and the activity of a [Database](super::Database) since it was opened, as
+/// returned by [Database::stats](super::Database::stats).
+///
+/// Each statistic can also be read by name, as text, with
+/// [Database::property](super::Database::property): the names are listed in
+/// [DatabaseStats::PROPERTIES].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DatabaseStats {
+    /// The number of page reads served by the cache.
+    pub cache_hits: u64,
+    /// The number of page reads that reached the files.
+    pub cache_misses: u64,
+    /// The number of pages in the cache.
+    pub cached_pages: usize,
+    /// The size of the write-ahead log, in bytes.
+    pub wal_size: usize,
+    /// The size of the data file, in bytes, including the log not yet copied to it.
+    pub file_size: u64,
+    /// The number of pages of the data file that are free to be reused.
+    pub free_pages: usize,
+    /// The number of pages replaced by commits that are not free yet, because an open snapshot
+    /// may still read them. They are freed by the first commit or garbage collection after the
+    /// snapshots are dropped.
+    pub retired_pages: usize,
+    /// The number of versions whose replaced pages are retired but no open snapshot can see
+    /// anymore, so the next garbage collection frees them (see
+    /// [Database::collect_garbage](super::Database::collect_garbage)).
+    pub reclaimable_versions: usize,
+    /// The number of versions whose replaced pages are retired because an open snapshot may still
+    /// see them.
+    pub retained_versions: usize,
+    /// The bytes of the pages kept for the open snapshots and iterators, which can't be reused
+    /// until they are dropped.
+    pub pinned_size: u64,
+    /// The number of snapshots taken with [Database::snapshot](super::Database::snapshot) that are
+    /// still open, including those of the optimistic transactions.
+    pub open_snapshots: usize,
+    /// The time since the oldest open snapshot was taken, or zero if no snapshot is open.
+    pub oldest_snapshot_age: Duration,
+    /// The number of transaction identifiers left to hand out. The database can't start a
+    /// transaction once they are exhausted.
+    pub remaining_txn_ids: u64,
+    /// An estimate of the bytes of the data file used by the entries (see
+    /// [Database::approximate_sizes](super::Database::approximate_sizes)).
+    pub live_data_size: u64,
+    /// The number of keys read by gets and iterations.
+    pub keys_read: u64,
+    /// The number of bytes of the values read.
+    pub bytes_read: u64,
+    /// The number of keys written or removed.
+    pub keys_written: u64,
+    /// The number of bytes of the keys and values written.
+    pub bytes_written: u64,
+    /// The number of commits delayed because the writes not yet copied to the data file passed
+    /// the slowdown size.
+    pub slowdowns: u64,
+    /// The number of commits that copied the writes to the data file because they passed the stop
+    /// size.
+    pub stops: u64,
+    /// The time the commits were stalled, by slowdowns and stops.
+    pub stall_time: Duration,
+    /// The time since the database was opened.
+    pub uptime: Duration,
+}
+
+impl DatabaseStats {
+    /// The names of the properties of a database, one for each statistic.
+    pub const PROPERTIES: [&'static str; 24] = [
+        "rouilledb.cache-hits",
+        "rouilledb.cache-misses",
+        "rouilledb.cache-hit-rate",
+        "rouilledb.cached-pages",
+        "rouilledb.wal-size",
+        "rouilledb.file-size",
+        "rouilledb.free-pages",
+        "rouilledb.retired-pages",
+        "rouilledb.reclaimable-versions",
+        "rouilledb.retained-versions",
+        "rouilledb.pinned-size",
+        "rouilledb.open-snapshots",
+        "rouilledb.oldest-snapshot-micros",
+        "rouilledb.remaining-txn-ids",
+        "rouilledb.live-data-size",
+        "rouilledb.keys-read",
+        "rouilledb.bytes-read",
+        "rouilledb.keys-written",
+        "rouilledb.bytes-written",
+        "rouilledb.slowdowns",
+        "rouilledb.stops",
+        "rouilledb.stall-micros",
+        "rouilledb.read-throughput",
+        "rouilledb.write-throughput",
+    ];
+
+    /// Returns the fraction of the page reads served by the cache, or `0.0` if no page was read.
+    pub fn cache_hit_rate(&self) -> f64 {
+        let reads = self.cache_hits + self.cache_misses;
+        if reads == 0 {
+            return 0.0;
+        }
+        self.cache_hits as f64 / reads as f64
+    }
+
+    /// Returns the average number of keys read per second since the database was opened.
+    pub fn read_throughput(&self) -> f64 {
+        per_second(self.keys_read, self.uptime)
+    }
+
+    /// Returns the average number of keys written per second since the database was opened.
+    pub fn write_throughput(&self) -> f64 {
+        per_second(self.keys_written, self.uptime)
+    }
+
+    /// Returns the value of a property as text, or `None` if there is no property with that
+    /// name. The property `rouilledb.stats` holds every other property, one per line.
+    pub fn property(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "rouilledb.stats" => {
+                let lines: Vec<String> = Self::PROPERTIES
+                    .iter()
+                    .filter_map(|name| Some(format!("{name}: {}", self.property(name)?)))
+                    .collect();
+                return Some(lines.join("\n"));
+            }
+            "rouilledb.cache-hits" => self.cache_hits.to_string(),
+            "rouilledb.cache-misses" => self.cache_misses.to_string(),
+            "rouilledb.cache-hit-rate" => format!("{:.4}", self.cache_hit_rate()),
+            "rouilledb.cached-pages" => self.cached_pages.to_string(),
+            "rouilledb.wal-size" => self.wal_size.to_string(),
+            "rouilledb.file-size" => self.file_size.to_string(),
+            "rouilledb.free-pages" => self.free_pages.to_string(),
+            "rouilledb.retired-pages" => self.retired_pages.to_string(),
+            "rouilledb.reclaimable-versions" => self.reclaimable_versions.to_string(),
+            "rouilledb.retained-versions" => self.retained_versions.to_string(),
+            "rouilledb.pinned-size" => self.pinned_size.to_string(),
+            "rouilledb.open-snapshots" => self.open_snapshots.to_string(),
+            "rouilledb.oldest-snapshot-micros" => self.oldest_snapshot_age.as_micros().to_string(),
+            "rouilledb.remaining-txn-ids" => self.remaining_txn_ids.to_string(),
+            "rouilledb.live-data-size" => self.live_data_size.to_string(),
+            "rouilledb.keys-read" => self.keys_read.to_string(),
+            "rouilledb.bytes-read" => self.bytes_read.to_string(),
+            "rouilledb.keys-written" => self.keys_written.to_string(),
+            "rouilledb.bytes-written" => self.bytes_written.to_string(),
+            "rouilledb.slowdowns" => self.slowdowns.to_string(),
+            "rouilledb.stops" => self.stops.to_string(),
+            "rouilledb.stall-micros" => self.stall_time.as_micros().to_string(),
+            "rouilledb.read-throughput" => format!("{:.2}", self.read_throughput()),
+            "rouilledb.write-throughput" => format!("{:.2}", self.write_throughput()),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Returns the average number of events per second over a duration.
+fn per_second(count: u64, duration: Duration) -> f64 {
+    if duration.is_zero() {
+        return 0.0;
+    }
+    count as f64 / duration.as_secs_f64()
+}
+
+/// The counters of the reads and writes of a database behind [DatabaseStats], updated by reads
+/// that only borrow the database.
+#[derive(Debug, Default)]
+pub(super) struct Counters {
+    keys_read: AtomicU64,
+    bytes_read: AtomicU64,
+    keys_written: AtomicU64,
+    bytes_written: AtomicU64,
+    slowdowns: AtomicU64,
+    stops: AtomicU64,
+    stall_micros: AtomicU64,
+}
+
+impl Counters {
+    /// Counts a key read, and the size of its value if it was found.
+    pub(super) fn record_read(&self, value: Option<&[u8]>) {
+        self.keys_read.fetch_add(1, Ordering::Relaxed);
+        if let Some(value) = value {
+            self.bytes_read
+                .fetch_add(value.len() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a key written with its value, or removed if `value` is `None`.
+    pub(super) fn record_write(&self, key: &[u8], value: Option<&[u8]>) {
+        self.keys_written.fetch_add(1, Ordering::Relaxed);
+        let size = key.len() + value.map_or(0, <[u8]>::len);
+        self.bytes_written.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a stalled commit.
+    pub(super) fn record_stall(&self, stall: &WriteStall) {
+        match stall.kind {
+            StallKind::Slowdown => self.slowdowns.fetch_add(1, Ordering::Relaxed),
+            StallKind::Stop => self.stops.fetch_add(1, Ordering::Relaxed),
+        };
+        self.stall_micros
+            .fetch_add(stall.duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Sets the counters of `stats` to their current value.
+    pub(super) fn fill(&self, stats: &mut DatabaseStats) {
+        stats.keys_read = self.keys_read.load(Ordering::Relaxed);
+        stats.bytes_read = self.bytes_read.load(Ordering::Relaxed);
+        stats.keys_written = self.keys_written.load(Ordering::Relaxed);
+        stats.bytes_written = self.bytes_written.load(Ordering::Relaxed);
+        stats.slowdowns = self.slowdowns.load(Ordering::Relaxed);
+        stats.stops = self.stops.load(Ordering::Relaxed);
+        stats.stall_time = Duration::from_micros(self.stall_micros.load(Ordering::Relaxed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every listed property has a value, and `rouilledb.stats` holds them all.
+    #[test]
+    fn property_returns_listed_properties() {
+        let stats = DatabaseStats {
+            cache_hits: 3,
+            cache_misses: 1,
+            keys_written: 10,
+            uptime: Duration::from_secs(2),
+            ..DatabaseStats::default()
+        };
+
+        let all = stats
+            .property("rouilledb.stats")
+            .expect("the property should exist");
+
+        for name in DatabaseStats::PROPERTIES {
+            assert!(stats.property(name).is_some());
+        }
+        assert_eq!(all.lines().count(), DatabaseStats::PROPERTIES.len());
+        assert_eq!(
+            stats.property("rouilledb.cache-hit-rate"),
+            Some("0.7500".to_string())
+        );
+        assert_eq!(
+            stats.property("rouilledb.write-throughput"),
+            Some("5.00".to_string())
+        );
+        assert_eq!(stats.property("rouilledb.unknown"), None);
+    }
+}
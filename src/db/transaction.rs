@@ -0,0 +1,1407 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::btree::KeyComparator;
+use crate::common::KeyRange;
+
+use super::{
+    BatchIter, Database, DatabaseError, DatabaseSnapshot, IsolationLevel, Options, PreparedId,
+    ReadOptions, TransactionOptions, WriteBatch,
+};
+
+/// A closure called once a transaction ends.
+type Hook = Box<dyn FnOnce() + Send>;
+
+/// The identifier standing for the writes made outside of a transaction, which no transaction is
+/// given.
+const NO_TRANSACTION: u64 = u64::MAX;
+
+/// The locks held on the keys of a [Database] by its transactions.
+///
+/// A transaction locks either a single key or a range of keys, present or not. A lock excludes
+/// every lock of another transaction on a key it covers, or on an overlapping range, and the
+/// keys are compared with the comparator of the database. The point locks are kept in a hash
+/// map and the range locks in a list, so a range lock is checked against every lock of the table:
+/// the point locks of a transaction are escalated to a single range lock once they are too many.
+///
+/// The transactions waiting for a lock form a waits-for graph: each waits for the transaction
+/// holding a lock it conflicts with. A transaction only waits for one lock at a time, so a deadlock
+/// is a cycle in which each transaction waits for the next. A lock request that would close a
+/// cycle fails with [DatabaseError::Deadlock], which makes the requesting transaction the victim,
+/// and the other transactions of the cycle keep waiting for it to be rolled back.
+///
+/// A transaction may expire at a deadline. A transaction waiting for a lock held by an expired
+/// transaction releases every lock of the expired one, which then fails to lock a key or to
+/// commit.
+///
+/// The writes made outside of a transaction do not wait for the locks, since they hold the
+/// database the transactions must be given to commit: a write to a key locked by a transaction
+/// fails with [DatabaseError::KeyLocked] instead, so it can't be lost to the commit of the
+/// transaction.
+pub(super) struct LockTable {
+    comparator: Arc<dyn KeyComparator>,
+    state: Mutex<LockState>,
+    /// Notified when locks are released.
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct LockState {
+    /// The transaction holding the lock of each locked key.
+    owners: HashMap<Vec<u8>, u64>,
+    /// The ranges locked, with the transaction holding each.
+    ranges: Vec<(KeyRange, u64)>,
+    /// The transaction each waiting transaction waits for.
+    waits_for: HashMap<u64, u64>,
+    /// The deadline of each transaction that expires.
+    deadlines: HashMap<u64, Instant>,
+    /// The expired transactions whose locks were released.
+    expired: HashSet<u64>,
+}
+
+impl LockTable {
+    /// Creates an empty table, comparing the keys with `comparator`.
+    pub(super) fn new(comparator: Arc<dyn KeyComparator>) -> Self {
+        LockTable {
+            comparator,
+            state: Mutex::default(),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Locks a key for a transaction, waiting up to `timeout` for the transactions holding a
+    /// conflicting lock to release it. Returns `true` if the lock was acquired, `false` if the
+    /// transaction already held the key, or a range covering it.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [LockTable::acquire].
+    pub(super) fn lock(
+        &self,
+        key: &[u8],
+        transaction: u64,
+        timeout: Duration,
+    ) -> Result<bool, DatabaseError> {
+        let request = KeyRange::point(key);
+        let mut state = self.acquire(&request, transaction, timeout)?;
+        let covered = state.ranges.iter().any(|(range, owner)| {
+            *owner == transaction && range.contains_by(key, |a, b| self.comparator.compare(a, b))
+        });
+        if covered || state.owners.get(key) == Some(&transaction) {
+            return Ok(false);
+        }
+        state.owners.insert(key.to_vec(), transaction);
+        Ok(true)
+    }
+
+    /// Locks a range of keys for a transaction, waiting up to `timeout` for the transactions
+    /// holding a conflicting lock to release it.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [LockTable::acquire].
+    fn lock_range(
+        &self,
+        range: KeyRange,
+        transaction: u64,
+        timeout: Duration,
+    ) -> Result<(), DatabaseError> {
+        let mut state = self.acquire(&range, transaction, timeout)?;
+        state.ranges.push((range, transaction));
+        Ok(())
+    }
+
+    /// Fails if a transaction holds a lock on a key, which a write made outside of a transaction
+    /// would otherwise overwrite. The locks of the expired transactions are released first.
+    ///
+    /// # Errors
+    ///
+    /// This method will return [DatabaseError::KeyLocked] if a transaction that did not expire
+    /// holds a lock on the key, or on a range covering it.
+    pub(super) fn check_unlocked(&self, key: &[u8]) -> Result<(), DatabaseError> {
+        let request = KeyRange::point(key);
+        let mut state = self.state();
+        while let Some(owner) = state.blocker(self.comparator.as_ref(), &request, NO_TRANSACTION) {
+            let expired = state
+                .deadlines
+                .get(&owner)
+                .is_some_and(|deadline| *deadline <= Instant::now());
+            if !expired {
+                return Err(DatabaseError::KeyLocked);
+            }
+            state.expire(owner);
+            self.released.notify_all();
+        }
+        Ok(())
+    }
+
+    /// Replaces the point locks of a transaction on `keys` with a lock on the range from the
+    /// smallest to the largest of them, unless another transaction holds a lock within the range.
+    /// Returns `true` if the locks were escalated.
+    fn escalate<'a>(&self, keys: impl IntoIterator<Item = &'a Vec<u8>>, transaction: u64) -> bool {
+        let comparator = self.comparator.as_ref();
+        let mut keys = keys.into_iter();
+        let Some(first) = keys.next() else {
+            return false;
+        };
+        let (mut smallest, mut largest) = (first, first);
+        let mut escalated = vec![first];
+        for key in keys {
+            if comparator.compare(key, smallest).is_lt() {
+                smallest = key;
+            }
+            if comparator.compare(key, largest).is_gt() {
+                largest = key;
+            }
+            escalated.push(key);
+        }
+
+        let range = KeyRange::new(
+            Bound::Included(smallest.clone()),
+            Bound::Included(largest.clone()),
+        );
+        let mut state = self.state();
+        if state.blocker(comparator, &range, transaction).is_some() {
+            return false;
+        }
+        for key in escalated {
+            state.owners.remove(key);
+        }
+        state.ranges.push((range, transaction));
+        true
+    }
+
+    /// Releases the locks of a transaction on keys, and all its range locks, then forgets the
+    /// transaction.
+    pub(super) fn unlock(&self, keys: impl IntoIterator<Item = Vec<u8>>, transaction: u64) {
+        let mut state = self.state();
+        for key in keys {
+            if state.owners.get(&key) == Some(&transaction) {
+                state.owners.remove(&key);
+            }
+        }
+        state.ranges.retain(|(_, owner)| *owner != transaction);
+        state.deadlines.remove(&transaction);
+        state.expired.remove(&transaction);
+        self.released.notify_all();
+    }
+
+    /// Makes a transaction expire at `deadline`.
+    fn expire_at(&self, transaction: u64, deadline: Instant) {
+        self.state().deadlines.insert(transaction, deadline);
+    }
+
+    /// Stops the expiration of a transaction, whose locks are then kept until they are unlocked.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the transaction already expired.
+    fn keep(&self, transaction: u64) -> Result<(), DatabaseError> {
+        let mut state = self.state();
+        state.check_expired(transaction)?;
+        state.deadlines.remove(&transaction);
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for the locks of other transactions conflicting with a request to be
+    /// released, and returns the state of the table, where the request can be granted.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the transaction expired
+    /// - a conflicting lock is still held after `timeout`
+    /// - waiting for the owner of a conflicting lock would make a deadlock
+    fn acquire(
+        &self,
+        request: &KeyRange,
+        transaction: u64,
+        timeout: Duration,
+    ) -> Result<MutexGuard<'_, LockState>, DatabaseError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state();
+        let result = loop {
+            if let Err(error) = state.check_expired(transaction) {
+                break Err(error);
+            }
+            let Some(owner) = state.blocker(self.comparator.as_ref(), request, transaction) else {
+                break Ok(());
+            };
+            if state
+                .deadlines
+                .get(&owner)
+                .is_some_and(|deadline| *deadline <= Instant::now())
+            {
+                state.expire(owner);
+                self.released.notify_all();
+                continue;
+            }
+            if state.waits_on(owner, transaction) {
+                break Err(DatabaseError::Deadlock);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break Err(DatabaseError::LockTimeout);
+            }
+            // The owner's locks are released when it expires, even if it doesn't end.
+            let expiration = state.deadlines.get(&owner).map_or(remaining, |expiration| {
+                expiration.saturating_duration_since(Instant::now())
+            });
+            state.waits_for.insert(transaction, owner);
+            state = self
+                .released
+                .wait_timeout(state, remaining.min(expiration))
+                .expect("the lock table should not be poisoned")
+                .0;
+        };
+        state.waits_for.remove(&transaction);
+        result.map(|()| state)
+    }
+
+    fn state(&self) -> MutexGuard<'_, LockState> {
+        self.state
+            .lock()
+            .expect("the lock table should not be poisoned")
+    }
+}
+
+impl LockState {
+    /// Fails if a transaction expired.
+    fn check_expired(&self, transaction: u64) -> Result<(), DatabaseError> {
+        let expired = self.expired.contains(&transaction)
+            || self
+                .deadlines
+                .get(&transaction)
+                .is_some_and(|deadline| *deadline <= Instant::now());
+        if expired {
+            return Err(DatabaseError::TransactionExpired);
+        }
+        Ok(())
+    }
+
+    /// Releases every lock of an expired transaction.
+    fn expire(&mut self, transaction: u64) {
+        self.owners.retain(|_, owner| *owner != transaction);
+        self.ranges.retain(|(_, owner)| *owner != transaction);
+        self.deadlines.remove(&transaction);
+        self.expired.insert(transaction);
+    }
+
+    /// Returns a transaction, other than `transaction`, holding a lock that conflicts with a
+    /// request.
+    fn blocker(
+        &self,
+        comparator: &dyn KeyComparator,
+        request: &KeyRange,
+        transaction: u64,
+    ) -> Option<u64> {
+        let compare = |a: &[u8], b: &[u8]| comparator.compare(a, b);
+        let point = match (request.start(), request.end()) {
+            (Bound::Included(start), Bound::Included(end)) if start == end => Some(start),
+            _ => None,
+        };
+        let owners = match point {
+            // A point request only conflicts with the lock of its key.
+            Some(key) => self.owners.get(key).into_iter().collect::<Vec<_>>(),
+            None => self
+                .owners
+                .iter()
+                .filter(|(key, _)| request.contains_by(key, compare))
+                .map(|(_, owner)| owner)
+                .collect(),
+        };
+        owners
+            .into_iter()
+            .chain(
+                self.ranges
+                    .iter()
+                    .filter(|(range, _)| range.intersects_by(request, compare))
+                    .map(|(_, owner)| owner),
+            )
+            .find(|&&owner| owner != transaction)
+            .copied()
+    }
+
+    /// Returns `true` if `waiter` waits, directly or through other waiting transactions, for
+    /// `transaction`.
+    fn waits_on(&self, mut waiter: u64, transaction: u64) -> bool {
+        // Each transaction waits for at most one other, so the path has no branch, and it is at
+        // most as long as the number of waiting transactions unless it loops without `transaction`.
+        for _ in 0..=self.waits_for.len() {
+            if waiter == transaction {
+                return true;
+            }
+            match self.waits_for.get(&waiter) {
+                Some(&next) => waiter = next,
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+/// The savepoints of a transaction: the number of writes it held when each was set, in the order
+/// they were set.
+#[derive(Debug, Default)]
+struct Savepoints {
+    savepoints: Vec<(String, usize)>,
+}
+
+impl Savepoints {
+    fn set(&mut self, name: &str, len: usize) {
+        self.savepoints.push((name.to_string(), len));
+    }
+
+    /// Releases the savepoints set after the last one named `name`, and returns the number of
+    /// writes held when it was set.
+    fn rollback_to(&mut self, name: &str) -> Result<usize, DatabaseError> {
+        let position = self.position(name)?;
+        self.savepoints.truncate(position + 1);
+        Ok(self.savepoints[position].1)
+    }
+
+    /// Releases the last savepoint named `name` and the savepoints set after it.
+    fn release(&mut self, name: &str) -> Result<(), DatabaseError> {
+        let position = self.position(name)?;
+        self.savepoints.truncate(position);
+        Ok(())
+    }
+
+    fn position(&self, name: &str) -> Result<usize, DatabaseError> {
+        self.savepoints
+            .iter()
+            .rposition(|(savepoint, _)| savepoint == name)
+            .ok_or_else(|| DatabaseError::SavepointNotFound(name.to_string()))
+    }
+}
+
+/// The closures called once a transaction ends, in the order they were added. The rollback hooks
+/// are called when the hooks are dropped, unless the transaction committed.
+#[derive(Default)]
+struct Hooks {
+    on_commit: Vec<Hook>,
+    on_rollback: Vec<Hook>,
+}
+
+impl Hooks {
+    /// Calls the commit hooks, and forgets the rollback hooks.
+    fn committed(&mut self) {
+        self.on_rollback.clear();
+        for hook in self.on_commit.drain(..) {
+            hook();
+        }
+    }
+
+    /// Forgets the hooks without calling them.
+    fn clear(&mut self) {
+        self.on_commit.clear();
+        self.on_rollback.clear();
+    }
+}
+
+impl Drop for Hooks {
+    fn drop(&mut self) {
+        for hook in self.on_rollback.drain(..) {
+            hook();
+        }
+    }
+}
+
+/// Represents a pessimistic transaction on a [Database], returned by [Database::begin_txn].
+///
+/// The writes of the transaction are kept in an indexed [WriteBatch] until it is committed, and
+/// its reads see them. Each key written, or read with [Transaction::get_for_update], is first
+/// locked, so no other transaction can write it until this one is committed or rolled back, and
+/// the writes made to it outside of a transaction, such as [Database::put], fail with
+/// [DatabaseError::KeyLocked]. A transaction waits for a locked key up to the lock timeout of the
+/// options of the database, then fails with [DatabaseError::LockTimeout]. A transaction that
+/// would wait for a transaction waiting for it, as when two transactions lock the same keys in
+/// opposite orders, fails at once with [DatabaseError::Deadlock] and should be rolled back.
+///
+/// The locks are released when the transaction is committed, rolled back or dropped. The
+/// transaction does not borrow the database, so transactions can wait for each other from several
+/// threads, but the database must be given to its reads and to [Transaction::commit].
+///
+/// Closures added with [Transaction::on_commit] and [Transaction::on_rollback] are called once
+/// the transaction ends, for instance to invalidate a cache only once its writes are committed.
+///
+/// Part of the writes can be undone with savepoints, which can be nested:
+/// [Transaction::rollback_to] undoes the writes made since a savepoint set with
+/// [Transaction::savepoint]. The writes only reach the log of the database when the transaction is
+/// committed, so a savepoint is only a position in the writes kept by the transaction, and nothing
+/// is written to the log to undo them.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::TempDir;
+/// use rouilledb::db::{Database, Options};
+///
+/// let directory = TempDir::new();
+/// let mut database =
+///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+///
+/// let mut transaction = database.begin_txn();
+/// transaction.put(b"order", b"placed").expect("put should not fail");
+/// transaction.savepoint("shipping");
+/// transaction.put(b"shipping", b"express").expect("put should not fail");
+/// transaction.rollback_to("shipping").expect("rollback_to should not fail");
+/// transaction.commit(&mut database).expect("commit should not fail");
+///
+/// assert_eq!(database.get(b"order").expect("get should not fail"), Some(b"placed".to_vec()));
+/// assert_eq!(database.get(b"shipping").expect("get should not fail"), None);
+/// ```
+pub struct Transaction {
+    database_id: u64,
+    id: u64,
+    locks: Arc<LockTable>,
+    timeout: Duration,
+    escalation_threshold: usize,
+    batch: WriteBatch,
+    locked: HashSet<Vec<u8>>,
+    savepoints: Savepoints,
+    isolation_level: IsolationLevel,
+    /// The snapshot read by the transaction, at the [IsolationLevel::SnapshotIsolation] level.
+    snapshot: Option<DatabaseSnapshot>,
+    hooks: Hooks,
+    /// Set once the transaction is prepared: its locks are then held by the database.
+    prepared: bool,
+}
+
+impl Transaction {
+    pub(super) fn new(
+        database_id: u64,
+        id: u64,
+        locks: Arc<LockTable>,
+        snapshot: Option<DatabaseSnapshot>,
+        options: &Options,
+        transaction_options: &TransactionOptions,
+    ) -> Self {
+        if let Some(expiration) = transaction_options.expiration {
+            locks.expire_at(id, Instant::now() + expiration);
+        }
+        Transaction {
+            database_id,
+            id,
+            locks,
+            timeout: transaction_options
+                .lock_timeout
+                .unwrap_or(options.lock_timeout),
+            escalation_threshold: options.lock_escalation_threshold,
+            batch: WriteBatch::indexed(),
+            locked: HashSet::new(),
+            savepoints: Savepoints::default(),
+            isolation_level: transaction_options.isolation_level,
+            snapshot,
+            hooks: Hooks::default(),
+            prepared: false,
+        }
+    }
+
+    /// Returns the identifier of the transaction. The identifiers of the transactions of a
+    /// database increase in the order they started, and are never reused, even after a restart.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the number of writes of the transaction.
+    pub fn len(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Returns `true` if the transaction holds no write.
+    pub fn is_empty(&self) -> bool {
+        self.batch.is_empty()
+    }
+
+    /// Returns the value of a key as the transaction would leave it, read as its isolation level
+    /// requires (see [IsolationLevel]). The key is only locked at the
+    /// [IsolationLevel::Serializable] level.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted, if a merge
+    /// operand can't be applied, or if the snapshot of the transaction was released for being too
+    /// old. At the [IsolationLevel::Serializable] level, it also fails in the cases of
+    /// [Transaction::put].
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the transaction was not started on `database`.
+    pub fn get(
+        &mut self,
+        database: &Database,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, DatabaseError> {
+        self.check_database(database);
+        if self.isolation_level == IsolationLevel::Serializable {
+            self.lock(key)?;
+        }
+        database.get_from_batch_with_options(&self.batch, key, &self.read_options())
+    }
+
+    /// Returns an iterator over the entries whose keys are within `range`, in key order, as the
+    /// transaction would leave them: the writes of the transaction hide the entries of the
+    /// database, and the keys it removed are skipped. The entries of the database are read as of
+    /// the call, or from the snapshot of the transaction, and the writes of the transaction as
+    /// they are then. The range is only locked at the [IsolationLevel::Serializable] level.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [Transaction::get], for a key written by
+    /// the transaction within the range. At the [IsolationLevel::Serializable] level, it also
+    /// fails in the cases of [Transaction::lock_range].
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the transaction was not started on `database`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// database.put(b"item-1", b"book").expect("put should not fail");
+    /// database.put(b"item-2", b"pen").expect("put should not fail");
+    ///
+    /// let mut transaction = database.begin_txn();
+    /// transaction.delete(b"item-1").expect("delete should not fail");
+    /// transaction.put(b"item-3", b"ink").expect("put should not fail");
+    /// let keys: Vec<_> = transaction
+    ///     .iter(&database, b"item-".as_slice()..b"item.".as_slice())
+    ///     .expect("iter should not fail")
+    ///     .map(|entry| entry.expect("iteration should not fail").0)
+    ///     .collect();
+    ///
+    /// assert_eq!(keys, vec![b"item-2".to_vec(), b"item-3".to_vec()]);
+    /// ```
+    pub fn iter<'a, K, R>(
+        &mut self,
+        database: &'a Database,
+        range: R,
+    ) -> Result<BatchIter<'a>, DatabaseError>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        self.check_database(database);
+        if self.isolation_level == IsolationLevel::Serializable {
+            self.lock_range::<K, _>((range.start_bound(), range.end_bound()))?;
+        }
+        database.iter_from_batch_with_options(&self.batch, range, &self.read_options())
+    }
+
+    /// Locks a key, then returns its value as the transaction would leave it, read as its
+    /// isolation level requires. The value can't be changed by another transaction until this one
+    /// ends.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key is locked by another transaction past the lock
+    /// timeout, if the transaction expired, or in the cases of [Transaction::get].
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the transaction was not started on `database`.
+    pub fn get_for_update(
+        &mut self,
+        database: &Database,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, DatabaseError> {
+        self.check_database(database);
+        self.lock(key)?;
+        database.get_from_batch_with_options(&self.batch, key, &self.read_options())
+    }
+
+    /// Locks a key, then sets its value in the transaction.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key is locked by another transaction past the lock
+    /// timeout, or if the transaction expired.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), DatabaseError> {
+        self.lock(key)?;
+        self.batch.put(key, value);
+        Ok(())
+    }
+
+    /// Locks a key, then removes it in the transaction.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key is locked by another transaction past the lock
+    /// timeout, or if the transaction expired.
+    pub fn delete(&mut self, key: &[u8]) -> Result<(), DatabaseError> {
+        self.lock(key)?;
+        self.batch.delete(key);
+        Ok(())
+    }
+
+    /// Locks the keys within `range`, present or not, so no other transaction can write a key of
+    /// the range, or lock a key or a range within it, until this one ends. Locking a range before
+    /// reading it prevents phantoms: the keys read stay the only keys of the range.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a key or a range within `range` is locked by another
+    /// transaction past the lock timeout, if waiting for it would make a deadlock, or if the
+    /// transaction expired.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, DatabaseError, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let options = Options::new().lock_timeout(Duration::from_millis(10));
+    /// let database = Database::open(directory.path(), options).expect("open should not fail");
+    ///
+    /// let mut report = database.begin_txn();
+    /// report
+    ///     .lock_range(b"order-".as_slice()..b"order.".as_slice())
+    ///     .expect("lock_range should not fail");
+    /// let orders = database.iter_prefix(b"order-").count();
+    ///
+    /// let mut order = database.begin_txn();
+    /// let result = order.put(b"order-42", b"placed");
+    /// assert!(matches!(result, Err(DatabaseError::LockTimeout)));
+    /// assert_eq!(orders, 0);
+    /// ```
+    pub fn lock_range<K, R>(&mut self, range: R) -> Result<(), DatabaseError>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        self.locks
+            .lock_range(KeyRange::from_bounds(range), self.id, self.timeout)
+    }
+
+    /// Sets a savepoint named `name` at the current writes of the transaction. A savepoint can be
+    /// set with the name of an earlier one, which it hides until it is released.
+    pub fn savepoint(&mut self, name: &str) {
+        self.savepoints.set(name, self.batch.len());
+    }
+
+    /// Undoes the writes made since the savepoint named `name` was set, and releases the
+    /// savepoints set after it. The savepoint itself is kept, so the transaction can roll back to
+    /// it again. The keys locked since stay locked until the transaction
+    /// ends.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the transaction has no savepoint named `name`.
+    pub fn rollback_to(&mut self, name: &str) -> Result<(), DatabaseError> {
+        let len = self.savepoints.rollback_to(name)?;
+        self.batch.truncate(len);
+        Ok(())
+    }
+
+    /// Releases the savepoint named `name` and the savepoints set after it, keeping the writes
+    /// made since.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the transaction has no savepoint named `name`.
+    pub fn release(&mut self, name: &str) -> Result<(), DatabaseError> {
+        self.savepoints.release(name)
+    }
+
+    /// Adds a closure called once the transaction is committed, after its writes are committed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// let committed = Arc::new(AtomicBool::new(false));
+    /// let rolled_back = Arc::new(AtomicBool::new(false));
+    ///
+    /// let mut transaction = database.begin_txn();
+    /// transaction.put(b"user-1", b"alice").expect("put should not fail");
+    /// let flag = Arc::clone(&committed);
+    /// transaction.on_commit(move || flag.store(true, Ordering::SeqCst));
+    /// let flag = Arc::clone(&rolled_back);
+    /// transaction.on_rollback(move || flag.store(true, Ordering::SeqCst));
+    /// transaction.commit(&mut database).expect("commit should not fail");
+    ///
+    /// assert!(committed.load(Ordering::SeqCst));
+    /// assert!(!rolled_back.load(Ordering::SeqCst));
+    /// ```
+    pub fn on_commit<F>(&mut self, hook: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.hooks.on_commit.push(Box::new(hook));
+    }
+
+    /// Adds a closure called once the transaction is rolled back: by [Transaction::rollback], by
+    /// a failed commit or when it is dropped. Rolling back to a savepoint does not call it.
+    pub fn on_rollback<F>(&mut self, hook: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.hooks.on_rollback.push(Box::new(hook));
+    }
+
+    /// Writes the writes of the transaction to the database in a single commit, then releases its
+    /// locks. At the [IsolationLevel::SnapshotIsolation] level, the commit first checks that no
+    /// key written by the transaction was modified since its snapshot was taken.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the transaction expired, if a key it wrote was
+    /// modified since its snapshot was taken, or in the cases of [Database::write]. None of the
+    /// writes is then applied, and the locks are released.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the transaction was not started on `database`.
+    pub fn commit(mut self, database: &mut Database) -> Result<(), DatabaseError> {
+        self.check_database(database);
+        self.locks.keep(self.id)?;
+        if let Some(snapshot) = &self.snapshot {
+            if snapshot.inner(database)?.version() != database.version() {
+                let options = ReadOptions::new().snapshot(snapshot);
+                for key in self.batch.keys().expect("the batch should be indexed") {
+                    if database.get_with_options(key, &options)? != database.get(key)? {
+                        return Err(DatabaseError::Conflict);
+                    }
+                }
+            }
+        }
+        database.commit_locked(std::mem::take(&mut self.batch))?;
+        self.hooks.committed();
+        Ok(())
+    }
+
+    /// Prepares the transaction for a two-phase commit, and returns the identifier it is then
+    /// resolved by. The writes of the transaction are recorded durably in the directory of the
+    /// database, and its locks are held by the database, until [Database::commit_prepared] or
+    /// [Database::rollback_prepared] is called, even after a restart. A transaction reopened after
+    /// a restart only locks the keys it writes.
+    ///
+    /// Once prepared, the transaction is promised to commit: the sizes of its keys and values are
+    /// checked, and the other transactions can't write its keys. Its commit and rollback hooks are
+    /// dropped without being called, since it may be resolved after a restart; the commit hook of
+    /// the database is called by [Database::commit_prepared].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the transaction expired, if the database is
+    /// read-only, if a key or a value is too large, or if the record can't be written. The
+    /// transaction is then rolled back.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the transaction was not started on `database`.
+    pub fn prepare(mut self, database: &mut Database) -> Result<PreparedId, DatabaseError> {
+        self.check_database(database);
+        let id = PreparedId::from(self.id);
+        self.locks.keep(self.id)?;
+        database.prepare(id, &self.batch, &mut self.locked)?;
+        self.prepared = true;
+        self.hooks.clear();
+        Ok(id)
+    }
+
+    /// Discards the writes of the transaction and releases its locks.
+    pub fn rollback(self) {}
+
+    fn lock(&mut self, key: &[u8]) -> Result<(), DatabaseError> {
+        if self.locks.lock(key, self.id, self.timeout)? {
+            self.locked.insert(key.to_vec());
+            if self.locked.len() > self.escalation_threshold
+                && self.locks.escalate(&self.locked, self.id)
+            {
+                self.locked.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the options reading the snapshot of the transaction, if it has one.
+    fn read_options(&self) -> ReadOptions<'_> {
+        match &self.snapshot {
+            Some(snapshot) => ReadOptions::new().snapshot(snapshot),
+            None => ReadOptions::new(),
+        }
+    }
+
+    fn check_database(&self, database: &Database) {
+        assert_eq!(
+            self.database_id,
+            database.id(),
+            "the transaction should be used with the database it was started on"
+        );
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.prepared {
+            self.locks.unlock(self.locked.drain(), self.id);
+        }
+    }
+}
+
+/// Represents an optimistic transaction on a [Database], returned by
+/// [Database::begin_optimistic_txn].
+///
+/// The transaction reads a snapshot of the database taken when it started, with its own writes,
+/// which are kept in an indexed [WriteBatch] until it is committed. It takes no lock: instead, the
+/// keys it reads or writes are tracked, and the commit fails with [DatabaseError::Conflict] if the
+/// value of one of them changed since the transaction started. The transaction can then be
+/// retried, which suits workloads where few transactions write the same keys.
+///
+/// The snapshot keeps the pages of its version until the transaction ends, so a transaction
+/// should not be kept longer than needed. Part of the writes can be undone with savepoints, and
+/// closures can be called once the transaction ends, as in a [Transaction].
+pub struct OptimisticTransaction {
+    snapshot: DatabaseSnapshot,
+    batch: WriteBatch,
+    tracked: BTreeSet<Vec<u8>>,
+    savepoints: Savepoints,
+    hooks: Hooks,
+}
+
+impl OptimisticTransaction {
+    pub(super) fn new(snapshot: DatabaseSnapshot) -> Self {
+        OptimisticTransaction {
+            snapshot,
+            batch: WriteBatch::indexed(),
+            tracked: BTreeSet::new(),
+            savepoints: Savepoints::default(),
+            hooks: Hooks::default(),
+        }
+    }
+
+    /// Returns the number of writes of the transaction.
+    pub fn len(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Returns `true` if the transaction holds no write.
+    pub fn is_empty(&self) -> bool {
+        self.batch.is_empty()
+    }
+
+    /// Returns the value of a key as the transaction would leave it, read from the version of the
+    /// database when the transaction started. The key is tracked.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted, or if a merge
+    /// operand can't be applied.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the transaction was not started on `database`.
+    pub fn get(
+        &mut self,
+        database: &Database,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, DatabaseError> {
+        let options = ReadOptions::new().snapshot(&self.snapshot);
+        let value = database.get_from_batch_with_options(&self.batch, key, &options)?;
+        self.tracked.insert(key.to_vec());
+        Ok(value)
+    }
+
+    /// Returns an iterator over the entries whose keys are within `range`, in key order, as the
+    /// transaction would leave them, read from the version of the database when the transaction
+    /// started (see [Transaction::iter]). The keys iterated are not tracked: only the keys read
+    /// with [OptimisticTransaction::get] are checked for conflicts.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error in the cases of [OptimisticTransaction::get], for a key
+    /// written by the transaction within the range.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the transaction was not started on `database`.
+    pub fn iter<'a, K, R>(
+        &self,
+        database: &'a Database,
+        range: R,
+    ) -> Result<BatchIter<'a>, DatabaseError>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let options = ReadOptions::new().snapshot(&self.snapshot);
+        database.iter_from_batch_with_options(&self.batch, range, &options)
+    }
+
+    /// Sets the value of a key in the transaction. The key is tracked.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.batch.put(key, value);
+        self.tracked.insert(key.to_vec());
+    }
+
+    /// Removes a key in the transaction. The key is tracked.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.batch.delete(key);
+        self.tracked.insert(key.to_vec());
+    }
+
+    /// Sets a savepoint named `name` at the current writes of the transaction. A savepoint can be
+    /// set with the name of an earlier one, which it hides until it is released.
+    pub fn savepoint(&mut self, name: &str) {
+        self.savepoints.set(name, self.batch.len());
+    }
+
+    /// Undoes the writes made since the savepoint named `name` was set, and releases the
+    /// savepoints set after it. The savepoint itself is kept, so the transaction can roll back to
+    /// it again. The keys read or written since stay tracked.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the transaction has no savepoint named `name`.
+    pub fn rollback_to(&mut self, name: &str) -> Result<(), DatabaseError> {
+        let len = self.savepoints.rollback_to(name)?;
+        self.batch.truncate(len);
+        Ok(())
+    }
+
+    /// Releases the savepoint named `name` and the savepoints set after it, keeping the writes
+    /// made since.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the transaction has no savepoint named `name`.
+    pub fn release(&mut self, name: &str) -> Result<(), DatabaseError> {
+        self.savepoints.release(name)
+    }
+
+    /// Adds a closure called once the transaction is committed (see [Transaction::on_commit]).
+    pub fn on_commit<F>(&mut self, hook: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.hooks.on_commit.push(Box::new(hook));
+    }
+
+    /// Adds a closure called once the transaction is rolled back: by
+    /// [OptimisticTransaction::rollback], by a failed commit, as on a conflict, or when it is
+    /// dropped.
+    pub fn on_rollback<F>(&mut self, hook: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.hooks.on_rollback.push(Box::new(hook));
+    }
+
+    /// Checks that no tracked key was modified since the transaction started, then writes the
+    /// writes of the transaction to the database in a single commit.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a tracked key was modified, if the snapshot of the
+    /// transaction was released for being too old, or in the cases of [Database::write]. None of
+    /// the writes is then applied.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the transaction was not started on `database`.
+    pub fn commit(mut self, database: &mut Database) -> Result<(), DatabaseError> {
+        if self.snapshot.inner(database)?.version() != database.version() {
+            let options = ReadOptions::new().snapshot(&self.snapshot);
+            for key in &self.tracked {
+                if database.get_with_options(key, &options)? != database.get(key)? {
+                    return Err(DatabaseError::Conflict);
+                }
+            }
+        }
+        database.write(std::mem::take(&mut self.batch))?;
+        self.hooks.committed();
+        Ok(())
+    }
+
+    /// Discards the writes of the transaction.
+    pub fn rollback(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::btree::BytewiseComparator;
+    use crate::common::TempDir;
+
+    use super::*;
+
+    /// A locked key can't be locked by another transaction until it is released, and a waiting
+    /// transaction gets it as soon as it is.
+    #[test]
+    fn lock_waits_for_release() {
+        let locks = Arc::new(LockTable::new(Arc::new(BytewiseComparator)));
+        let timeout = Duration::from_millis(50);
+
+        let first = locks
+            .lock(b"key", 1, timeout)
+            .expect("lock should not fail");
+        let again = locks
+            .lock(b"key", 1, timeout)
+            .expect("lock should not fail");
+        let timed_out = locks.lock(b"key", 2, timeout);
+        let waiting = {
+            let locks = Arc::clone(&locks);
+            std::thread::spawn(move || locks.lock(b"key", 2, Duration::from_secs(10)))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        locks.unlock([b"key".to_vec()], 1);
+        let waited = waiting
+            .join()
+            .expect("the thread should not panic")
+            .expect("lock should not fail");
+
+        assert!(first && !again && waited);
+        assert!(matches!(timed_out, Err(DatabaseError::LockTimeout)));
+    }
+
+    /// A lock request closing a cycle of waiting transactions fails at once, and the other
+    /// transaction of the cycle gets the lock once the victim releases its locks.
+    #[test]
+    fn lock_detects_deadlock() {
+        let locks = Arc::new(LockTable::new(Arc::new(BytewiseComparator)));
+        let timeout = Duration::from_secs(10);
+        locks.lock(b"a", 1, timeout).expect("lock should not fail");
+        locks.lock(b"b", 2, timeout).expect("lock should not fail");
+
+        let waiting = {
+            let locks = Arc::clone(&locks);
+            std::thread::spawn(move || locks.lock(b"a", 2, timeout))
+        };
+        while !locks.state().waits_for.contains_key(&2) {
+            std::thread::yield_now();
+        }
+        let start = Instant::now();
+        let deadlock = locks.lock(b"b", 1, timeout);
+        let elapsed = start.elapsed();
+        locks.unlock([b"a".to_vec()], 1);
+        let waited = waiting
+            .join()
+            .expect("the thread should not panic")
+            .expect("lock should not fail");
+
+        assert!(matches!(deadlock, Err(DatabaseError::Deadlock)));
+        assert!(elapsed < timeout);
+        assert!(waited);
+        assert!(locks.state().waits_for.is_empty());
+    }
+
+    /// A transaction waiting for a lock of an expired transaction releases its locks when it
+    /// expires, and the expired transaction can't lock a key or commit anymore.
+    #[test]
+    fn lock_releases_locks_of_expired_transaction() {
+        let locks = LockTable::new(Arc::new(BytewiseComparator));
+        let timeout = Duration::from_secs(10);
+        locks.expire_at(1, Instant::now() + Duration::from_millis(20));
+        locks.lock(b"a", 1, timeout).expect("lock should not fail");
+        locks.lock(b"b", 1, timeout).expect("lock should not fail");
+
+        let start = Instant::now();
+        let waited = locks.lock(b"a", 2, timeout).expect("lock should not fail");
+        let elapsed = start.elapsed();
+        let expired_lock = locks.lock(b"c", 1, timeout);
+        let expired_keep = locks.keep(1);
+        let other_key = locks
+            .lock(b"b", 3, Duration::ZERO)
+            .expect("lock should not fail");
+
+        assert!(waited && other_key);
+        assert!(elapsed < timeout);
+        assert!(matches!(
+            expired_lock,
+            Err(DatabaseError::TransactionExpired)
+        ));
+        assert!(matches!(
+            expired_keep,
+            Err(DatabaseError::TransactionExpired)
+        ));
+        locks.unlock([], 1);
+        assert!(locks.state().expired.is_empty());
+    }
+
+    /// A range lock excludes the locks of other transactions on the keys and ranges it covers,
+    /// but not those next to it, nor the locks of its own transaction.
+    #[test]
+    fn lock_range_excludes_keys_within_range() {
+        let locks = LockTable::new(Arc::new(BytewiseComparator));
+        let timeout = Duration::ZERO;
+        locks
+            .lock_range(
+                KeyRange::new(
+                    Bound::Included(b"b".to_vec()),
+                    Bound::Excluded(b"d".to_vec()),
+                ),
+                1,
+                timeout,
+            )
+            .expect("lock_range should not fail");
+
+        let inside = locks.lock(b"c", 2, timeout);
+        let end = locks.lock(b"d", 2, timeout).expect("lock should not fail");
+        let overlapping = locks.lock_range(
+            KeyRange::new(Bound::Unbounded, Bound::Included(b"b".to_vec())),
+            2,
+            timeout,
+        );
+        let own = locks.lock(b"c", 1, timeout).expect("lock should not fail");
+        let covering = locks.lock_range(
+            KeyRange::new(Bound::Included(b"c".to_vec()), Bound::Unbounded),
+            1,
+            timeout,
+        );
+        locks.unlock([], 1);
+        let released = locks.lock(b"c", 2, timeout).expect("lock should not fail");
+
+        assert!(matches!(inside, Err(DatabaseError::LockTimeout)));
+        assert!(end && !own && released);
+        assert!(matches!(overlapping, Err(DatabaseError::LockTimeout)));
+        assert!(matches!(covering, Err(DatabaseError::LockTimeout)));
+    }
+
+    /// The keys locked one by one by a transaction past the threshold are escalated to a lock on
+    /// the range they span, unless another transaction holds a key within it.
+    #[test]
+    fn lock_escalates_point_locks() {
+        let directory = TempDir::new();
+        let options = Options::new()
+            .lock_timeout(Duration::ZERO)
+            .lock_escalation_threshold(3);
+        let database = Database::open(directory.path(), options).expect("open should not fail");
+        let mut first = database.begin_txn();
+        let mut second = database.begin_txn();
+
+        second.put(b"m", b"second").expect("put should not fail");
+        for key in [b"b", b"d", b"f", b"h"] {
+            first.put(key, b"first").expect("put should not fail");
+        }
+        let escalated = first.locked.is_empty();
+        let within = second.put(b"c", b"second");
+        second.put(b"i", b"second").expect("put should not fail");
+        for key in [b"k", b"l", b"n", b"p"] {
+            first.put(key, b"first").expect("put should not fail");
+        }
+
+        assert!(escalated);
+        assert!(matches!(within, Err(DatabaseError::LockTimeout)));
+        assert_eq!(first.locked.len(), 4);
+    }
+
+    /// A transaction reading committed data sees the commits made while it runs, so reading a key
+    /// twice can return two values.
+    #[test]
+    fn read_committed_allows_non_repeatable_reads() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"a", b"1").expect("put should not fail");
+        let mut transaction = database.begin_txn();
+
+        let first = transaction
+            .get(&database, b"a")
+            .expect("get should not fail");
+        database.put(b"a", b"2").expect("put should not fail");
+        let second = transaction
+            .get(&database, b"a")
+            .expect("get should not fail");
+
+        assert_eq!(first, Some(b"1".to_vec()));
+        assert_eq!(second, Some(b"2".to_vec()));
+    }
+
+    /// A transaction reading a snapshot repeats its reads and can't lose an update, but two
+    /// transactions can each write a key the other one read.
+    #[test]
+    fn snapshot_isolation_prevents_lost_updates_but_allows_write_skew() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database.put(b"a", b"1").expect("put should not fail");
+        database.put(b"b", b"1").expect("put should not fail");
+        let options = TransactionOptions::new().isolation_level(IsolationLevel::SnapshotIsolation);
+
+        let mut lost = database.begin_txn_with_options(&options);
+        let before = lost.get(&database, b"a").expect("get should not fail");
+        database.put(b"a", b"2").expect("put should not fail");
+        let after = lost.get(&database, b"a").expect("get should not fail");
+        lost.put(b"a", b"3").expect("put should not fail");
+        let lost_update = lost.commit(&mut database);
+
+        let mut first = database.begin_txn_with_options(&options);
+        let mut second = database.begin_txn_with_options(&options);
+        let first_read = first.get(&database, b"b").expect("get should not fail");
+        let second_read = second.get(&database, b"a").expect("get should not fail");
+        first.put(b"a", b"0").expect("put should not fail");
+        second.put(b"b", b"0").expect("put should not fail");
+        first.commit(&mut database).expect("commit should not fail");
+        second
+            .commit(&mut database)
+            .expect("commit should not fail");
+
+        assert_eq!(before, after);
+        assert!(matches!(lost_update, Err(DatabaseError::Conflict)));
+        assert_eq!(
+            (first_read, second_read),
+            (Some(b"1".to_vec()), Some(b"2".to_vec()))
+        );
+        assert_eq!(
+            database.get(b"a").expect("get should not fail"),
+            Some(b"0".to_vec())
+        );
+        assert_eq!(
+            database.get(b"b").expect("get should not fail"),
+            Some(b"0".to_vec())
+        );
+    }
+
+    /// A serializable transaction locks the keys and ranges it reads, so another transaction
+    /// can't write them, which prevents write skews and phantoms.
+    #[test]
+    fn serializable_prevents_write_skew_and_phantoms() {
+        let directory = TempDir::new();
+        let options = Options::new().lock_timeout(Duration::from_millis(10));
+        let mut database = Database::open(directory.path(), options).expect("open should not fail");
+        database.put(b"a", b"1").expect("put should not fail");
+        database.put(b"b", b"1").expect("put should not fail");
+        let options = TransactionOptions::new().isolation_level(IsolationLevel::Serializable);
+
+        let mut first = database.begin_txn_with_options(&options);
+        let mut second = database.begin_txn_with_options(&options);
+        first.get(&database, b"b").expect("get should not fail");
+        let count = first
+            .iter(&database, b"c".as_slice()..b"e".as_slice())
+            .expect("iter should not fail")
+            .count();
+        let skew = second.get(&database, b"b");
+        let phantom = second.put(b"d", b"1");
+        second.put(b"a", b"0").expect("put should not fail");
+        let read_locked = first.get(&database, b"a");
+
+        assert_eq!(count, 0);
+        assert!(matches!(skew, Err(DatabaseError::LockTimeout)));
+        assert!(matches!(phantom, Err(DatabaseError::LockTimeout)));
+        assert!(matches!(read_locked, Err(DatabaseError::LockTimeout)));
+    }
+
+    /// The hooks of a transaction are called once, as it is committed, rolled back, dropped or
+    /// fails to commit, and the commit hook of the database only for the commits made.
+    #[test]
+    fn hooks_are_called_once_transaction_ends() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let committed = Arc::clone(&calls);
+        database.set_commit_hook(move |batch| {
+            let mut calls = committed.lock().expect("the lock should not be poisoned");
+            calls.push(format!("database {}", batch.len()));
+        });
+        let hook = |name: &'static str| {
+            let calls = Arc::clone(&calls);
+            move || {
+                let mut calls = calls.lock().expect("the lock should not be poisoned");
+                calls.push(name.to_string());
+            }
+        };
+
+        let mut transaction = database.begin_txn();
+        transaction.put(b"a", b"1").expect("put should not fail");
+        transaction.on_commit(hook("commit"));
+        transaction.on_rollback(hook("not rolled back"));
+        transaction
+            .commit(&mut database)
+            .expect("commit should not fail");
+        let mut transaction = database.begin_txn();
+        transaction.on_commit(hook("not committed"));
+        transaction.on_rollback(hook("dropped"));
+        drop(transaction);
+        let mut transaction = database.begin_optimistic_txn();
+        transaction
+            .get(&database, b"a")
+            .expect("get should not fail");
+        transaction.put(b"b", b"2");
+        transaction.on_commit(hook("not committed"));
+        transaction.on_rollback(hook("conflict"));
+        database.put(b"a", b"3").expect("put should not fail");
+        let conflict = transaction.commit(&mut database);
+
+        assert!(matches!(conflict, Err(DatabaseError::Conflict)));
+        assert_eq!(
+            *calls.lock().expect("the lock should not be poisoned"),
+            vec!["database 1", "commit", "dropped", "database 1", "conflict"]
+        );
+    }
+
+    /// Rolling back to a savepoint undoes the later writes and releases the later savepoints,
+    /// while releasing a savepoint keeps the writes.
+    #[test]
+    fn rollback_to_savepoint_undoes_later_writes() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        let mut transaction = database.begin_optimistic_txn();
+
+        transaction.put(b"a", b"1");
+        transaction.savepoint("outer");
+        transaction.put(b"b", b"2");
+        transaction.savepoint("inner");
+        transaction.put(b"a", b"3");
+        transaction.savepoint("inner");
+        transaction.delete(b"b");
+        transaction
+            .rollback_to("inner")
+            .expect("rollback_to should not fail");
+        let b_after_inner = transaction
+            .get(&database, b"b")
+            .expect("get should not fail");
+        transaction
+            .release("inner")
+            .expect("release should not fail");
+        transaction
+            .rollback_to("inner")
+            .expect("rollback_to should not fail");
+        let a_after_inner = transaction
+            .get(&database, b"a")
+            .expect("get should not fail");
+        transaction
+            .rollback_to("outer")
+            .expect("rollback_to should not fail");
+        let released = transaction.rollback_to("inner");
+        transaction.put(b"c", b"4");
+        let len = transaction.len();
+        transaction
+            .commit(&mut database)
+            .expect("commit should not fail");
+
+        assert_eq!(b_after_inner, Some(b"2".to_vec()));
+        assert_eq!(a_after_inner, Some(b"1".to_vec()));
+        assert!(matches!(
+            released,
+            Err(DatabaseError::SavepointNotFound(name)) if name == "inner"
+        ));
+        assert_eq!(len, 2);
+        assert_eq!(database.get(b"b").expect("get should not fail"), None);
+        assert_eq!(
+            database.get(b"c").expect("get should not fail"),
+            Some(b"4".to_vec())
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The number of transaction identifiers reserved in the file of a database at once.
+const BLOCK_SIZE: u64 = 1 << 16;
+
+/// The last identifier handed out: the identifiers past it are never used, so a reservation can
+/// always end one block past the identifiers handed out.
+const LAST_TXN_ID: u64 = u64::MAX - BLOCK_SIZE;
+
+/// Allocates the identifiers of the transactions of a [Database](super::Database), in increasing
+/// order, so an identifier also orders the transactions by the time they started.
+///
+/// The identifiers are handed out from memory, and reserved in the header of the file of the
+/// database by blocks: the end of the reservation is moved a block ahead when half of it was
+/// handed out. Once the database is reopened, the identifiers start at the end of the
+/// reservation, so an identifier handed out before the restart is never handed out again. An
+/// identifier handed out past the reservation is reserved before its transaction leaves a trace
+/// in the database, when it is prepared or when any commit is made.
+pub(super) struct TxnIdAllocator {
+    /// The next identifier to hand out.
+    next: AtomicU64,
+    /// The end of the identifiers reserved in the file.
+    reserved: u64,
+}
+
+impl TxnIdAllocator {
+    /// Creates an allocator handing out the identifiers from `first`, reserved up to `reserved`.
+    pub(super) fn new(first: u64, reserved: u64) -> Self {
+        TxnIdAllocator {
+            next: AtomicU64::new(first.max(1)),
+            reserved,
+        }
+    }
+
+    /// Returns the next identifier, or `None` if the identifiers are exhausted.
+    pub(super) fn allocate(&self) -> Option<u64> {
+        self.next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| {
+                (next <= LAST_TXN_ID).then_some(next + 1)
+            })
+            .ok()
+    }
+
+    /// Returns the number of identifiers left to hand out.
+    pub(super) fn remaining(&self) -> u64 {
+        (LAST_TXN_ID + 1).saturating_sub(self.next.load(Ordering::Relaxed))
+    }
+
+    /// Returns the end the reservation must be moved to, or `None` if more than half a block of
+    /// reserved identifiers is left.
+    pub(super) fn next_reservation(&self) -> Option<u64> {
+        let next = self.next.load(Ordering::Relaxed);
+        (self.reserved.saturating_sub(next) < BLOCK_SIZE / 2)
+            .then(|| next.saturating_add(BLOCK_SIZE))
+    }
+
+    /// Records that the identifiers are reserved up to `reserved`.
+    pub(super) fn set_reserved(&mut self, reserved: u64) {
+        self.reserved = reserved;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The reservation is moved a block ahead once half of it was handed out, and no identifier
+    /// is handed out once they are exhausted.
+    #[test]
+    fn allocate_hands_out_increasing_identifiers() {
+        let mut allocator = TxnIdAllocator::new(0, 0);
+        let initial = allocator.next_reservation();
+        allocator.set_reserved(BLOCK_SIZE + 1);
+        let first = allocator.allocate();
+        let second = allocator.allocate();
+        let reserved = allocator.next_reservation();
+        for _ in 0..BLOCK_SIZE / 2 {
+            allocator.allocate();
+        }
+        let half = allocator.next_reservation();
+        let exhausted = TxnIdAllocator::new(LAST_TXN_ID, u64::MAX);
+        let last = exhausted.allocate();
+
+        assert_eq!(initial, Some(BLOCK_SIZE + 1));
+        assert_eq!((first, second), (Some(1), Some(2)));
+        assert_eq!(reserved, None);
+        assert_eq!(half, Some(BLOCK_SIZE / 2 + 3 + BLOCK_SIZE));
+        assert_eq!(last, Some(LAST_TXN_ID));
+        assert_eq!(exhausted.allocate(), None);
+        assert_eq!(exhausted.remaining(), 0);
+    }
+}
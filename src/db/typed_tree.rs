@@ -0,0 +1,206 @@
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{Database, DatabaseError, DatabaseIter};
+
+/// Encodes a value as MessagePack, with the names of the fields of its structs, so a field can
+/// be added to a struct stored in a database as long as it has a default value.
+pub(super) fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, DatabaseError> {
+    Ok(rmp_serde::to_vec_named(value)?)
+}
+
+/// Decodes a value encoded by [encode].
+pub(super) fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, DatabaseError> {
+    Ok(rmp_serde::from_slice(bytes)?)
+}
+
+/// A view of the entries of a [Database] whose keys start with a prefix, as keys of type `K`
+/// mapped to values of type `V`, returned by [Database::typed_tree].
+///
+/// The keys and the values are serialized with serde, in MessagePack, and the prefix is put before
+/// the encoded keys, so several trees can share a database. The entries are iterated in the order
+/// of their encoded keys: the unsigned integers are in increasing order, but the strings are
+/// ordered by length first, for example. A [KeyEncoder](crate::common::KeyEncoder) key and the
+/// raw methods of the database should be used when the keys must be iterated in their natural
+/// order.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::common::TempDir;
+/// use rouilledb::db::{Database, Options};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct User {
+///     name: String,
+///     admin: bool,
+/// }
+///
+/// let directory = TempDir::new();
+/// let mut database =
+///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+/// let mut users = database.typed_tree::<u64, User>(b"users/");
+///
+/// let alice = User { name: "alice".to_string(), admin: true };
+/// users.insert(&1, &alice).expect("insert should not fail");
+///
+/// assert_eq!(users.get(&1).expect("get should not fail"), Some(alice));
+/// assert_eq!(users.get(&2).expect("get should not fail"), None);
+/// ```
+pub struct TypedTree<'a, K, V> {
+    database: &'a mut Database,
+    prefix: Vec<u8>,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<'a, K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> TypedTree<'a, K, V> {
+    /// Creates a view of the entries of `database` whose keys start with `prefix`.
+    pub(super) fn new(database: &'a mut Database, prefix: &[u8]) -> Self {
+        TypedTree {
+            database,
+            prefix: prefix.to_vec(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the prefix of the keys of the tree.
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Returns the value associated with a key, or `None` if the key is not present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key can't be serialized, if the value can't be
+    /// read or if it is not a valid `V`.
+    pub fn get(&self, key: &K) -> Result<Option<V>, DatabaseError> {
+        self.database
+            .get(&self.key(key)?)?
+            .map(|value| decode(&value))
+            .transpose()
+    }
+
+    /// Associates a value with a key and commits, as [Database::put].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key or the value can't be serialized, or in the
+    /// cases of [Database::put].
+    pub fn insert(&mut self, key: &K, value: &V) -> Result<(), DatabaseError> {
+        let key = self.key(key)?;
+        self.database.put(&key, &encode(value)?)
+    }
+
+    /// Removes a key, if it is present, and commits, as [Database::delete].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key can't be serialized, or in the cases of
+    /// [Database::delete].
+    pub fn remove(&mut self, key: &K) -> Result<(), DatabaseError> {
+        let key = self.key(key)?;
+        self.database.delete(&key)
+    }
+
+    /// Returns an iterator over the entries of the tree, in the order of their encoded keys.
+    pub fn iter(&self) -> TypedIter<'_, K, V> {
+        TypedIter {
+            entries: self.database.iter_prefix(&self.prefix),
+            prefix_len: self.prefix.len(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the key of the database of a key of the tree.
+    fn key(&self, key: &K) -> Result<Vec<u8>, DatabaseError> {
+        let mut encoded = self.prefix.clone();
+        rmp_serde::encode::write_named(&mut encoded, key)?;
+        Ok(encoded)
+    }
+}
+
+/// An iterator over the entries of a [TypedTree], returned by [TypedTree::iter].
+pub struct TypedIter<'a, K, V> {
+    entries: DatabaseIter<'a>,
+    prefix_len: usize,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K: DeserializeOwned, V: DeserializeOwned> Iterator for TypedIter<'_, K, V> {
+    type Item = Result<(K, V), DatabaseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.next()?;
+        Some(entry.and_then(|(key, value)| {
+            let key = decode(&key[self.prefix_len..])?;
+            Ok((key, decode(&value)?))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::common::TempDir;
+    use crate::db::Options;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        item: String,
+        quantity: u32,
+    }
+
+    /// The entries of a tree are read back as they were written, and iterated without the
+    /// entries of the database outside of its prefix, which are not valid values.
+    #[test]
+    fn typed_tree_reads_written_entries() {
+        let directory = TempDir::new();
+        let mut database =
+            Database::open(directory.path(), Options::new()).expect("open should not fail");
+        database
+            .put(b"other", b"not msgpack")
+            .expect("put should not fail");
+        let book = Order {
+            item: "book".to_string(),
+            quantity: 2,
+        };
+        let pen = Order {
+            item: "pen".to_string(),
+            quantity: 10,
+        };
+
+        let mut orders = database.typed_tree::<(u64, u64), Order>(b"orders/");
+        orders
+            .insert(&(1, 300), &book)
+            .expect("insert should not fail");
+        orders
+            .insert(&(1, 2), &pen)
+            .expect("insert should not fail");
+        orders
+            .insert(&(2, 1), &book)
+            .expect("insert should not fail");
+        orders.remove(&(2, 1)).expect("remove should not fail");
+        let entries: Vec<_> = orders
+            .iter()
+            .collect::<Result<_, _>>()
+            .expect("iter should not fail");
+
+        assert_eq!(
+            orders.get(&(1, 300)).expect("get should not fail"),
+            Some(book.clone())
+        );
+        assert_eq!(orders.get(&(2, 1)).expect("get should not fail"), None);
+        assert_eq!(entries, vec![((1, 2), pen), ((1, 300), book)]);
+        assert!(matches!(
+            database.get_serde::<String>(b"other"),
+            Err(DatabaseError::Deserialize(_))
+        ));
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::common::envelope::Envelope;
+
+use super::WriteBatch;
+
+/// How a committed write changed a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The value of the key was set.
+    Put(Vec<u8>),
+    /// The key was removed.
+    Delete,
+    /// An operand was applied to the value of the key with the merge operator of the database.
+    Merge(Vec<u8>),
+}
+
+/// A write committed to a [Database](super::Database), delivered to the receivers returned by
+/// [Database::watch](super::Database::watch) for a prefix of its key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    /// The version of the database made by the commit of the write.
+    pub version: u64,
+    /// The key written.
+    pub key: Vec<u8>,
+    /// How the key was changed.
+    pub change: Change,
+}
+
+/// The subscribers to the writes of a database, each to the keys starting with a prefix.
+#[derive(Debug, Default)]
+pub(super) struct Watchers {
+    watchers: Mutex<Vec<(Vec<u8>, Sender<ChangeEvent>)>>,
+}
+
+impl Watchers {
+    /// Subscribes to the writes of the keys starting with `prefix`.
+    pub(super) fn subscribe(&self, prefix: &[u8]) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.lock().push((prefix.to_vec(), sender));
+        receiver
+    }
+
+    /// Returns `true` if no one subscribed.
+    pub(super) fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Sends the writes of a batch committed as `version` to the subscribers of their keys, in
+    /// order. The subscribers whose receiver was dropped are forgotten.
+    pub(super) fn notify(&self, version: u64, batch: &WriteBatch) {
+        let mut watchers = self.lock();
+        for (key, operation) in batch.operations() {
+            watchers.retain(|(prefix, sender)| {
+                if !key.starts_with(prefix) {
+                    return true;
+                }
+                let change = match operation {
+                    Envelope::Value(value) => Change::Put(value.clone()),
+                    Envelope::Tombstone => Change::Delete,
+                    Envelope::Merge(operand) => Change::Merge(operand.clone()),
+                };
+                let event = ChangeEvent {
+                    version,
+                    key: key.clone(),
+                    change,
+                };
+                sender.send(event).is_ok()
+            });
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<(Vec<u8>, Sender<ChangeEvent>)>> {
+        self.watchers
+            .lock()
+            .expect("the watchers lock should not be poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The writes are sent in order to the subscribers of a prefix of their key, until their
+    /// receiver is dropped.
+    #[test]
+    fn notify_sends_writes_matching_prefix() {
+        let watchers = Watchers::default();
+        let users = watchers.subscribe(b"user-");
+        let dropped = watchers.subscribe(b"");
+        drop(dropped);
+        let mut batch = WriteBatch::new();
+        batch.put(b"user-1", b"alice");
+        batch.delete(b"order-1");
+        batch.merge(b"user-1", b"admin");
+
+        watchers.notify(7, &batch);
+        let events: Vec<_> = users.try_iter().collect();
+
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent {
+                    version: 7,
+                    key: b"user-1".to_vec(),
+                    change: Change::Put(b"alice".to_vec()),
+                },
+                ChangeEvent {
+                    version: 7,
+                    key: b"user-1".to_vec(),
+                    change: Change::Merge(b"admin".to_vec()),
+                },
+            ]
+        );
+        assert_eq!(watchers.lock().len(), 1);
+    }
+}
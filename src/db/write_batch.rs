@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+
+use crate::common::envelope::Envelope;
+
+/// A hook called after each commit of a [Database](super::Database), with the writes committed,
+/// set with [Database::set_commit_hook](super::Database::set_commit_hook).
+pub type CommitHook = Box<dyn Fn(&WriteBatch) + Send + Sync>;
+
+/// Collects puts, deletes and merges to apply to a [Database](super::Database) atomically, in a
+/// single commit, with [Database::write](super::Database::write).
+///
+/// The writes are applied in the order they are added. An indexed batch, created with
+/// [WriteBatch::indexed], also keeps the position of the writes of each key, so that
+/// [Database::get_from_batch](super::Database::get_from_batch) can read the database as if the
+/// batch was already written.
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatch {
+    operations: Vec<(Vec<u8>, Envelope)>,
+    index: Option<BTreeMap<Vec<u8>, Vec<usize>>>,
+}
+
+impl WriteBatch {
+    /// Creates an empty batch without an index.
+    pub fn new() -> Self {
+        WriteBatch::default()
+    }
+
+    /// Creates an empty batch indexing its writes by key, so that it can be read before it is
+    /// written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::db::{Database, Options, WriteBatch};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut database =
+    ///     Database::open(directory.path(), Options::new()).expect("open should not fail");
+    /// database.put(b"a", b"1").expect("put should not fail");
+    ///
+    /// let mut batch = WriteBatch::indexed();
+    /// batch.delete(b"a");
+    /// batch.put(b"b", b"2");
+    ///
+    /// assert_eq!(database.get_from_batch(&batch, b"a").expect("get should not fail"), None);
+    /// assert_eq!(database.get(b"a").expect("get should not fail"), Some(b"1".to_vec()));
+    ///
+    /// database.write(batch).expect("write should not fail");
+    ///
+    /// assert_eq!(database.get(b"a").expect("get should not fail"), None);
+    /// assert_eq!(database.get(b"b").expect("get should not fail"), Some(b"2".to_vec()));
+    /// ```
+    pub fn indexed() -> Self {
+        WriteBatch {
+            operations: Vec::new(),
+            index: Some(BTreeMap::new()),
+        }
+    }
+
+    /// Returns `true` if the batch indexes its writes by key.
+    pub fn is_indexed(&self) -> bool {
+        self.index.is_some()
+    }
+
+    /// Returns the number of writes in the batch.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Returns `true` if the batch holds no write.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Sets the value of a key.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.push(key, Envelope::Value(value.to_vec()));
+    }
+
+    /// Removes a key.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.push(key, Envelope::Tombstone);
+    }
+
+    /// Applies an operand to the value of a key with the merge operator of the database (see
+    /// [Database::set_merge_operator](super::Database::set_merge_operator)).
+    pub fn merge(&mut self, key: &[u8], operand: &[u8]) {
+        self.push(key, Envelope::Merge(operand.to_vec()));
+    }
+
+    /// Removes every write from the batch, which keeps its index option.
+    pub fn clear(&mut self) {
+        self.operations.clear();
+        if let Some(index) = &mut self.index {
+            index.clear();
+        }
+    }
+
+    /// Removes the writes added after the first `len` writes, so the batch is left as it was when
+    /// it held `len` writes. Does nothing if the batch holds at most `len` writes.
+    pub fn truncate(&mut self, len: usize) {
+        if let Some(index) = &mut self.index {
+            for (key, _) in self.operations.iter().skip(len) {
+                if let Some(positions) = index.get_mut(key) {
+                    positions.retain(|&position| position < len);
+                    if positions.is_empty() {
+                        index.remove(key);
+                    }
+                }
+            }
+        }
+        self.operations.truncate(len);
+    }
+
+    /// Returns the writes of the batch, in order.
+    pub(super) fn operations(&self) -> &[(Vec<u8>, Envelope)] {
+        &self.operations
+    }
+
+    /// Returns the keys written by the batch, or `None` if the batch is not indexed.
+    pub(super) fn keys(&self) -> Option<impl Iterator<Item = &Vec<u8>>> {
+        Some(self.index.as_ref()?.keys())
+    }
+
+    /// Returns the writes of a key, in order, or `None` if the batch is not indexed.
+    pub(super) fn operations_of<'a>(
+        &'a self,
+        key: &[u8],
+    ) -> Option<impl Iterator<Item = &'a Envelope> + 'a> {
+        let positions = self.index.as_ref()?.get(key).map_or(&[][..], Vec::as_slice);
+        Some(
+            positions
+                .iter()
+                .map(|&position| &self.operations[position].1),
+        )
+    }
+
+    fn push(&mut self, key: &[u8], operation: Envelope) {
+        if let Some(index) = &mut self.index {
+            index
+                .entry(key.to_vec())
+                .or_default()
+                .push(self.operations.len());
+        }
+        self.operations.push((key.to_vec(), operation));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An indexed batch returns the writes of a key in order, while a batch without an index
+    /// returns none.
+    #[test]
+    fn operations_of_returns_writes_of_key() {
+        let mut indexed = WriteBatch::indexed();
+        let mut unindexed = WriteBatch::new();
+        for batch in [&mut indexed, &mut unindexed] {
+            batch.put(b"a", b"1");
+            batch.put(b"b", b"2");
+            batch.merge(b"a", b"3");
+            batch.delete(b"a");
+        }
+
+        let writes: Vec<_> = indexed
+            .operations_of(b"a")
+            .expect("the batch should be indexed")
+            .cloned()
+            .collect();
+
+        assert_eq!(
+            writes,
+            vec![
+                Envelope::Value(b"1".to_vec()),
+                Envelope::Merge(b"3".to_vec()),
+                Envelope::Tombstone
+            ]
+        );
+        assert_eq!(
+            indexed
+                .operations_of(b"c")
+                .expect("the batch should be indexed")
+                .count(),
+            0
+        );
+        assert!(unindexed.operations_of(b"a").is_none());
+        assert_eq!(unindexed.len(), 4);
+    }
+
+    /// A truncated batch returns the writes it held at that length, and its index forgets the
+    /// keys only written after.
+    #[test]
+    fn truncate_removes_later_writes() {
+        let mut batch = WriteBatch::indexed();
+        batch.put(b"a", b"1");
+        batch.put(b"b", b"2");
+        batch.put(b"a", b"3");
+        batch.put(b"c", b"4");
+
+        batch.truncate(2);
+        batch.truncate(3);
+
+        assert_eq!(batch.len(), 2);
+        let writes: Vec<_> = batch
+            .operations_of(b"a")
+            .expect("the batch should be indexed")
+            .cloned()
+            .collect();
+        assert_eq!(writes, vec![Envelope::Value(b"1".to_vec())]);
+        assert_eq!(batch.index.as_ref().map(BTreeMap::len), Some(2));
+    }
+}
@@ -25,6 +25,31 @@ pub enum FileError {
     #[error("The file ({0}) is opened.")]
     FileOpened(String),
 
+    /// Indicates that an operation failed because the file does not exist.
+    ///
+    /// # Fields
+    /// - `0` - A string representing the path of the file that caused the error.
+    #[error("The file ({0}) does not exist.")]
+    FileNotFound(String),
+
+    /// Indicates that the operating system failed to perform an operation on the file.
+    ///
+    /// # Fields
+    /// - `filename` - A string representing the path of the file that caused the error.
+    /// - `source` - The error returned by the operating system.
+    #[error("An I/O error occurred on the file ({filename}).")]
+    Io {
+        filename: String,
+        source: std::io::Error,
+    },
+
+    /// Indicates that an operation tried to write to a file opened for reading only.
+    ///
+    /// # Fields
+    /// - `0` - A string representing the path of the file that caused the error.
+    #[error("The file ({0}) is opened for reading only.")]
+    ReadOnly(String),
+
     /// Indicates that an operation tried to read past the end of the file.
     #[error("Cannot read past the end of the file ({filename}). The file is {file_size} bytes. An attempt was made to read {read_size} bytes from position {offset}.")]
     EndOfFileRead {
@@ -86,15 +111,15 @@ pub trait File {
 
     /// Read a block of data in the file at a specified offset into a buffer. The size of the data
     /// read is based on the size of the buffer.
-    fn read(self, offset: usize, buffer: &mut [u8]) -> Result<(), FileError>;
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<(), FileError>;
 
     // Flush all changes to the disk so it will not be lost in case of a crash or power failure.
-    fn sync(self) -> Result<(), FileError>;
+    fn sync(&self) -> Result<(), FileError>;
 
     // Get the size of the file.
     //
     // # Errors
     //
     // This method will return an error if the file is not opened.
-    fn size(self) -> Result<usize, FileError>;
+    fn size(&self) -> Result<usize, FileError>;
 }
@@ -1,5 +1,3 @@
-use std::u8;
-
 use crate::fs::file::*;
 
 /// Represents a file in memory.
@@ -30,6 +28,12 @@ impl MemoryFile {
     }
 }
 
+impl Default for MemoryFile {
+    fn default() -> Self {
+        MemoryFile::new()
+    }
+}
+
 impl File for MemoryFile {
     /// Creates and open the file
     ///
@@ -143,7 +147,6 @@ impl File for MemoryFile {
             return Err(FileError::FileNotOpened(String::from("MemoryFile")));
         }
 
-        let data = data.as_ref();
         let end_offset: usize = offset + data.len();
         if self.data.len() < end_offset {
             self.data.resize(end_offset, 0);
@@ -156,7 +159,7 @@ impl File for MemoryFile {
 
     /// Read a block of data in the file at a specified offset into a buffer. The size of the data
     /// read is based on the size of the buffer.
-    fn read(self, offset: usize, buffer: &mut [u8]) -> Result<(), FileError> {
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<(), FileError> {
         if !self.is_opened {
             return Err(FileError::FileNotOpened(String::from("MemoryFile")));
         }
@@ -193,7 +196,7 @@ impl File for MemoryFile {
     ///
     /// assert!(result.is_ok());
     /// ```
-    fn sync(self) -> Result<(), FileError> {
+    fn sync(&self) -> Result<(), FileError> {
         Ok(())
     }
 
@@ -202,7 +205,7 @@ impl File for MemoryFile {
     /// # Errors
     ///
     /// This method will return an error if the file is not opened.
-    fn size(self) -> Result<usize, FileError> {
+    fn size(&self) -> Result<usize, FileError> {
         if !self.is_opened {
             return Err(FileError::FileNotOpened(String::from("MemoryFile")));
         }
@@ -3,3 +3,6 @@ pub use file::{File, FileError};
 
 mod memory_file;
 pub use memory_file::MemoryFile;
+
+mod os_file;
+pub use os_file::OsFile;
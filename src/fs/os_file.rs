@@ -0,0 +1,264 @@
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::fs::file::*;
+
+/// Represents a file of the file system of the operating system.
+///
+/// The file is identified by its path when the [OsFile] is created, and is only accessed once it
+/// is created or opened.
+pub struct OsFile {
+    path: PathBuf,
+    file: Option<std::fs::File>,
+    read_only: bool,
+}
+
+impl OsFile {
+    /// Creates a new [OsFile] for the file at `path`. The file itself is not created or opened.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        OsFile {
+            path: path.into(),
+            file: None,
+            read_only: false,
+        }
+    }
+
+    /// Creates a new [OsFile] for the file at `path`, which is opened for reading only: the
+    /// writes fail, and the file can be opened without the permission to write to it.
+    pub fn new_read_only(path: impl Into<PathBuf>) -> Self {
+        OsFile {
+            read_only: true,
+            ..OsFile::new(path)
+        }
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns `true` if a file exists at the path of the [OsFile].
+    pub fn exists(&self) -> bool {
+        self.path.is_file()
+    }
+
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn opened(&self) -> Result<&std::fs::File, FileError> {
+        self.file
+            .as_ref()
+            .ok_or_else(|| FileError::FileNotOpened(self.name()))
+    }
+
+    fn io_error(&self, source: std::io::Error) -> FileError {
+        match source.kind() {
+            ErrorKind::NotFound => FileError::FileNotFound(self.name()),
+            ErrorKind::AlreadyExists => FileError::FileAlreadyExists(self.name()),
+            _ => FileError::Io {
+                filename: self.name(),
+                source,
+            },
+        }
+    }
+}
+
+impl File for OsFile {
+    /// Creates and open the file
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - The file is already opened.
+    /// - The file already exists
+    /// - The operating system fails to create the file
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::common::TempDir;
+    /// use rouilledb::fs::{File, OsFile};
+    ///
+    /// let directory = TempDir::new();
+    /// let mut file = OsFile::new(directory.path().join("file"));
+    ///
+    /// let result = file.create();
+    ///
+    /// assert!(result.is_ok());
+    /// assert!(file.exists());
+    /// ```
+    fn create(&mut self) -> Result<(), FileError> {
+        if self.file.is_some() {
+            return Err(FileError::FileOpened(self.name()));
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&self.path)
+            .map_err(|error| self.io_error(error))?;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), FileError> {
+        match self.file.take() {
+            Some(_) => Ok(()),
+            None => Err(FileError::FileNotOpened(self.name())),
+        }
+    }
+
+    fn open(&mut self) -> Result<(), FileError> {
+        if self.file.is_some() {
+            return Err(FileError::FileOpened(self.name()));
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(!self.read_only)
+            .open(&self.path)
+            .map_err(|error| self.io_error(error))?;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn delete(&mut self) -> Result<(), FileError> {
+        if self.file.is_some() {
+            return Err(FileError::FileOpened(self.name()));
+        }
+        std::fs::remove_file(&self.path).map_err(|error| self.io_error(error))
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), FileError> {
+        if self.read_only {
+            return Err(FileError::ReadOnly(self.name()));
+        }
+        let mut file = self.opened()?;
+        file.seek(SeekFrom::Start(offset as u64))
+            .and_then(|_| file.write_all(data))
+            .map_err(|error| self.io_error(error))
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<(), FileError> {
+        let file_size = self.size()?;
+        if offset + buffer.len() > file_size {
+            return Err(FileError::EndOfFileRead {
+                filename: self.name(),
+                file_size,
+                offset,
+                read_size: buffer.len(),
+            });
+        }
+
+        let mut file = self.opened()?;
+        file.seek(SeekFrom::Start(offset as u64))
+            .and_then(|_| file.read_exact(buffer))
+            .map_err(|error| self.io_error(error))
+    }
+
+    fn sync(&self) -> Result<(), FileError> {
+        self.opened()?
+            .sync_all()
+            .map_err(|error| self.io_error(error))
+    }
+
+    fn size(&self) -> Result<usize, FileError> {
+        let metadata = self
+            .opened()?
+            .metadata()
+            .map_err(|error| self.io_error(error))?;
+        Ok(metadata.len() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{RandomBlob, TempDir};
+
+    use super::*;
+
+    #[test]
+    fn create_existing_file_fails() {
+        let directory = TempDir::new();
+        let mut file = OsFile::new(directory.path().join("file"));
+        file.create().expect("create should not fail");
+        file.close().expect("close should not fail");
+
+        let result = file.create();
+
+        assert!(matches!(result, Err(FileError::FileAlreadyExists(_))));
+    }
+
+    #[test]
+    fn open_missing_file_fails() {
+        let directory = TempDir::new();
+        let mut file = OsFile::new(directory.path().join("file"));
+
+        let result = file.open();
+
+        assert!(matches!(result, Err(FileError::FileNotFound(_))));
+    }
+
+    #[test]
+    fn write_close_open_read_returns_data() {
+        let directory = TempDir::new();
+        let blob = RandomBlob::new(300);
+        let mut file = OsFile::new(directory.path().join("file"));
+        file.create().expect("create should not fail");
+        file.write(100, blob.data()).expect("write should not fail");
+        file.sync().expect("sync should not fail");
+        file.close().expect("close should not fail");
+
+        file.open().expect("open should not fail");
+        let mut buffer = vec![0u8; 300];
+        file.read(100, &mut buffer).expect("read should not fail");
+
+        assert_eq!(&buffer, blob.data());
+        assert!(matches!(file.size(), Ok(400)));
+    }
+
+    #[test]
+    fn read_past_the_end_of_the_file_fails() {
+        let directory = TempDir::new();
+        let mut file = OsFile::new(directory.path().join("file"));
+        file.create().expect("create should not fail");
+        file.write(0, &[1; 32]).expect("write should not fail");
+
+        let result = file.read(16, &mut [0; 32]);
+
+        assert!(matches!(result, Err(FileError::EndOfFileRead { .. })));
+    }
+
+    #[test]
+    fn delete_removes_file() {
+        let directory = TempDir::new();
+        let mut file = OsFile::new(directory.path().join("file"));
+        file.create().expect("create should not fail");
+        file.close().expect("close should not fail");
+
+        file.delete().expect("delete should not fail");
+
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn write_read_only_file_fails() {
+        let directory = TempDir::new();
+        let mut file = OsFile::new(directory.path().join("file"));
+        file.create().expect("create should not fail");
+        file.write(0, &[1; 32]).expect("write should not fail");
+        file.close().expect("close should not fail");
+        let mut read_only = OsFile::new_read_only(directory.path().join("file"));
+        read_only.open().expect("open should not fail");
+
+        let result = read_only.write(0, &[2; 32]);
+        let mut buffer = [0; 32];
+        read_only
+            .read(0, &mut buffer)
+            .expect("read should not fail");
+
+        assert!(matches!(result, Err(FileError::ReadOnly(_))));
+        assert_eq!(buffer, [1; 32]);
+    }
+}
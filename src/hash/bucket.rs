@@ -0,0 +1,169 @@
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+use super::HashIndexError;
+
+/// Size of the header of a bucket page: the number of cells and the next overflow page.
+pub(super) const BUCKET_HEADER_SIZE: usize = 6;
+
+/// Size of the fixed part of a bucket cell: the key length and the value length.
+pub(super) const CELL_OVERHEAD: usize = 4;
+
+/// A key-value pair stored in a bucket.
+pub(super) type Entry = (Vec<u8>, Vec<u8>);
+
+/// Reads the entries of the bucket whose primary page is `first`, following its chain of overflow
+/// pages. Returns the entries and the pages of the chain, starting with `first`.
+///
+/// # Errors
+///
+/// This function will return an error if a page can't be read or is not a valid bucket page.
+pub(super) fn read_chain<F: File>(
+    pager: &Pager<F>,
+    first: PageId,
+) -> Result<(Vec<Entry>, Vec<PageId>), HashIndexError> {
+    let mut entries = Vec::new();
+    let mut pages = Vec::new();
+    let mut next = first;
+    while next != 0 {
+        let id = next;
+        let page = pager.read_page(id)?;
+        next = decode_page(&page, &mut entries).ok_or(HashIndexError::CorruptedPage(id))?;
+        pages.push(id);
+    }
+    Ok((entries, pages))
+}
+
+/// Writes the entries of a bucket to the pages of its chain. The pages are reused in order, more
+/// overflow pages are allocated if needed and the pages left unused are freed. The first page is
+/// always kept, even if the bucket is empty. Returns the pages of the new chain.
+///
+/// # Errors
+///
+/// This function will return an error if a page can't be allocated, written or freed.
+pub(super) fn write_chain<F: File>(
+    pager: &mut Pager<F>,
+    mut pages: Vec<PageId>,
+    entries: &[Entry],
+) -> Result<Vec<PageId>, HashIndexError> {
+    debug_assert!(!pages.is_empty());
+    let page_size = pager.page_size();
+
+    let mut groups = Vec::new();
+    let mut rest = entries;
+    loop {
+        let mut size = BUCKET_HEADER_SIZE;
+        let count = rest
+            .iter()
+            .take_while(|(key, value)| {
+                size += CELL_OVERHEAD + key.len() + value.len();
+                size <= page_size
+            })
+            .count();
+        let (group, remaining) = rest.split_at(count);
+        groups.push(group);
+        rest = remaining;
+        if rest.is_empty() {
+            break;
+        }
+    }
+
+    while pages.len() < groups.len() {
+        pages.push(pager.allocate_page()?);
+    }
+    for id in pages.split_off(groups.len()) {
+        pager.free_page(id)?;
+    }
+
+    for (index, group) in groups.iter().enumerate() {
+        let next = pages.get(index + 1).copied().unwrap_or(0);
+        let mut page = Vec::with_capacity(page_size);
+        page.extend_from_slice(&(group.len() as u16).to_le_bytes());
+        page.extend_from_slice(&next.to_le_bytes());
+        for (key, value) in *group {
+            page.extend_from_slice(&(key.len() as u16).to_le_bytes());
+            page.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            page.extend_from_slice(key);
+            page.extend_from_slice(value);
+        }
+        page.resize(page_size, 0);
+        pager.write_page(pages[index], &page)?;
+    }
+    Ok(pages)
+}
+
+/// Decodes a bucket page, appending its cells to `entries`. Returns the next overflow page, or
+/// `0`.
+fn decode_page(page: &[u8], entries: &mut Vec<Entry>) -> Option<PageId> {
+    let count = u16::from_le_bytes(page.get(0..2)?.try_into().ok()?) as usize;
+    let next = u32::from_le_bytes(page.get(2..6)?.try_into().ok()?);
+    let mut offset = BUCKET_HEADER_SIZE;
+    for _ in 0..count {
+        let key_len = u16::from_le_bytes(page.get(offset..offset + 2)?.try_into().ok()?) as usize;
+        let value_len =
+            u16::from_le_bytes(page.get(offset + 2..offset + 4)?.try_into().ok()?) as usize;
+        offset += CELL_OVERHEAD;
+        let key = page.get(offset..offset + key_len)?.to_vec();
+        offset += key_len;
+        let value = page.get(offset..offset + value_len)?.to_vec();
+        offset += value_len;
+        entries.push((key, value));
+    }
+    Some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_pager() -> Pager<MemoryFile> {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        Pager::create(file, 512).expect("create should not fail")
+    }
+
+    fn entry(index: usize) -> Entry {
+        (
+            format!("key-{index:06}").into_bytes(),
+            format!("value-{index}").into_bytes(),
+        )
+    }
+
+    /// Entries spanning several overflow pages are read back in order.
+    #[test]
+    fn write_then_read_chain_returns_entries() {
+        let mut pager = create_pager();
+        let first = pager
+            .allocate_page()
+            .expect("allocate_page should not fail");
+        let entries: Vec<Entry> = (0..100).map(entry).collect();
+
+        let pages = write_chain(&mut pager, vec![first], &entries).expect("write should not fail");
+        let (read, read_pages) = read_chain(&pager, first).expect("read should not fail");
+
+        assert!(pages.len() > 1);
+        assert_eq!(read_pages, pages);
+        assert_eq!(read, entries);
+    }
+
+    /// The overflow pages that are no longer needed are freed, but the first page is kept.
+    #[test]
+    fn write_shorter_chain_frees_overflow_pages() {
+        let mut pager = create_pager();
+        let first = pager
+            .allocate_page()
+            .expect("allocate_page should not fail");
+        let entries: Vec<Entry> = (0..100).map(entry).collect();
+        let pages = write_chain(&mut pager, vec![first], &entries).expect("write should not fail");
+        let page_count = pager.page_count();
+
+        let pages = write_chain(&mut pager, pages, &[]).expect("write should not fail");
+        let (read, _) = read_chain(&pager, first).expect("read should not fail");
+        write_chain(&mut pager, pages, &entries).expect("write should not fail");
+
+        assert!(read.is_empty());
+        assert_eq!(pager.page_count(), page_count);
+    }
+}
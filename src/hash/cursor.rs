@@ -0,0 +1,295 @@
+use crate::fs::File;
+use crate::pager::Pager;
+
+use super::bucket::{self, Entry};
+use super::{HashIndex, HashIndexError};
+
+/// Represents a position in a [HashIndex] that can be moved forward and backward.
+///
+/// The cursor has the same interface as the [Cursor](crate::btree::Cursor) of a
+/// [BTree](crate::btree::BTree), but the entries are visited bucket by bucket instead of in key
+/// order, and [Cursor::seek] only finds the exact key. A cursor is either positioned on an entry
+/// of the index or invalid. A newly created cursor is invalid until one of the seek methods is
+/// called.
+///
+/// The cursor keeps a copy of the entries of the bucket it is positioned on. It does not keep any
+/// page borrowed from the pager between calls.
+pub struct Cursor<'a, F: File> {
+    pager: &'a Pager<F>,
+    index: &'a HashIndex,
+    bucket: usize,
+    entries: Vec<Entry>,
+    position: Option<usize>,
+}
+
+impl<'a, F: File> Cursor<'a, F> {
+    /// Creates a new, invalid, cursor on an index.
+    pub(super) fn new(pager: &'a Pager<F>, index: &'a HashIndex) -> Self {
+        Cursor {
+            pager,
+            index,
+            bucket: 0,
+            entries: Vec::new(),
+            position: None,
+        }
+    }
+
+    /// Returns `true` if the cursor is positioned on an entry.
+    pub fn is_valid(&self) -> bool {
+        self.position.is_some()
+    }
+
+    /// Returns the key of the entry the cursor is positioned on.
+    pub fn key(&self) -> Option<&[u8]> {
+        self.position.map(|index| self.entries[index].0.as_slice())
+    }
+
+    /// Returns the value of the entry the cursor is positioned on.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.position.map(|index| self.entries[index].1.as_slice())
+    }
+
+    /// Positions the cursor on the entry whose key is `key`. Since the entries are not ordered by
+    /// key, the cursor is invalid if the key is not in the index.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek(&mut self, key: &[u8]) -> Result<(), HashIndexError> {
+        self.load(self.index.bucket_of(key))?;
+        self.position = self.entries.iter().position(|(k, _)| k == key);
+        Ok(())
+    }
+
+    /// Positions the cursor on the first entry of the first non-empty bucket. The cursor is
+    /// invalid if the index is empty.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_to_first(&mut self) -> Result<(), HashIndexError> {
+        self.forward_from(0)
+    }
+
+    /// Positions the cursor on the last entry of the last non-empty bucket. The cursor is invalid
+    /// if the index is empty.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn seek_to_last(&mut self) -> Result<(), HashIndexError> {
+        self.backward_from(self.index.bucket_count())
+    }
+
+    /// Moves the cursor to the next entry. The cursor becomes invalid if it was positioned on the
+    /// last entry. Does nothing if the cursor is invalid.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<(), HashIndexError> {
+        match self.position {
+            Some(index) if index + 1 < self.entries.len() => {
+                self.position = Some(index + 1);
+                Ok(())
+            }
+            Some(_) => self.forward_from(self.bucket + 1),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves the cursor to the previous entry. The cursor becomes invalid if it was positioned on
+    /// the first entry. Does nothing if the cursor is invalid.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn prev(&mut self) -> Result<(), HashIndexError> {
+        match self.position {
+            Some(index) if index > 0 => {
+                self.position = Some(index - 1);
+                Ok(())
+            }
+            Some(_) => self.backward_from(self.bucket),
+            None => Ok(()),
+        }
+    }
+
+    /// Positions the cursor on the first entry of the first non-empty bucket starting at
+    /// `bucket`.
+    fn forward_from(&mut self, bucket: usize) -> Result<(), HashIndexError> {
+        for bucket in bucket..self.index.bucket_count() {
+            self.load(bucket)?;
+            if !self.entries.is_empty() {
+                self.position = Some(0);
+                return Ok(());
+            }
+        }
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Positions the cursor on the last entry of the last non-empty bucket before `bucket`.
+    fn backward_from(&mut self, bucket: usize) -> Result<(), HashIndexError> {
+        for bucket in (0..bucket).rev() {
+            self.load(bucket)?;
+            if !self.entries.is_empty() {
+                self.position = Some(self.entries.len() - 1);
+                return Ok(());
+            }
+        }
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Reads the entries of a bucket. The cursor is left invalid.
+    fn load(&mut self, bucket: usize) -> Result<(), HashIndexError> {
+        self.invalidate();
+        let (entries, _) = bucket::read_chain(self.pager, self.index.buckets()[bucket])?;
+        self.bucket = bucket;
+        self.entries = entries;
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.entries.clear();
+        self.position = None;
+    }
+}
+
+/// An iterator over the entries of a [HashIndex], in bucket order.
+///
+/// The buckets are read lazily, as the iteration progresses. After an error is returned, the
+/// iterator does not return any more items.
+pub struct Iter<'a, F: File> {
+    cursor: Cursor<'a, F>,
+    started: bool,
+    finished: bool,
+}
+
+impl<'a, F: File> Iter<'a, F> {
+    /// Creates an iterator moving a cursor from the first entry of the index.
+    pub(super) fn new(cursor: Cursor<'a, F>) -> Self {
+        Iter {
+            cursor,
+            started: false,
+            finished: false,
+        }
+    }
+}
+
+impl<F: File> Iterator for Iter<'_, F> {
+    type Item = Result<(Vec<u8>, Vec<u8>), HashIndexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let moved = if self.started {
+            self.cursor.next()
+        } else {
+            self.started = true;
+            self.cursor.seek_to_first()
+        };
+        if let Err(error) = moved {
+            self.finished = true;
+            return Some(Err(error));
+        }
+        match (self.cursor.key(), self.cursor.value()) {
+            (Some(key), Some(value)) => Some(Ok((key.to_vec(), value.to_vec()))),
+            _ => {
+                self.finished = true;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_index(count: usize) -> (Pager<MemoryFile>, HashIndex) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut index = HashIndex::create(&mut pager).expect("create should not fail");
+        for i in 0..count {
+            index
+                .insert(&mut pager, &key(i), &i.to_le_bytes())
+                .expect("insert should not fail");
+        }
+        (pager, index)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:06}").into_bytes()
+    }
+
+    /// Iterating visits every entry exactly once.
+    #[test]
+    fn iter_visits_every_entry_once() {
+        let (pager, index) = create_index(1000);
+
+        let keys: HashSet<Vec<u8>> = index
+            .iter(&pager)
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+
+        assert_eq!(keys, (0..1000).map(key).collect());
+        assert_eq!(index.iter(&pager).count(), 1000);
+    }
+
+    /// Seeking finds an existing key and leaves the cursor invalid for a missing one.
+    #[test]
+    fn seek_finds_exact_key() {
+        let (pager, index) = create_index(100);
+        let mut cursor = index.cursor(&pager);
+
+        cursor.seek(&key(42)).expect("seek should not fail");
+        let value = cursor.value().map(<[u8]>::to_vec);
+        cursor.seek(&key(100)).expect("seek should not fail");
+
+        assert_eq!(value, Some(42usize.to_le_bytes().to_vec()));
+        assert!(!cursor.is_valid());
+    }
+
+    /// Moving backward from the last entry visits the entries in the reverse order.
+    #[test]
+    fn prev_from_last_reverses_order() {
+        let (pager, index) = create_index(300);
+        let forward: Vec<Vec<u8>> = index
+            .iter(&pager)
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+        let mut cursor = index.cursor(&pager);
+        let mut backward = Vec::new();
+
+        cursor.seek_to_last().expect("seek_to_last should not fail");
+        while let Some(key) = cursor.key() {
+            backward.push(key.to_vec());
+            cursor.prev().expect("prev should not fail");
+        }
+
+        backward.reverse();
+        assert_eq!(backward, forward);
+    }
+
+    /// A cursor on an empty index is invalid.
+    #[test]
+    fn seek_to_first_in_empty_index_is_invalid() {
+        let (pager, index) = create_index(0);
+        let mut cursor = index.cursor(&pager);
+
+        cursor
+            .seek_to_first()
+            .expect("seek_to_first should not fail");
+
+        assert!(!cursor.is_valid());
+    }
+}
@@ -0,0 +1,527 @@
+use thiserror::Error;
+
+use crate::common::hash64;
+use crate::fs::File;
+use crate::pager::{PageId, Pager, PagerError};
+
+use super::bucket::{self, BUCKET_HEADER_SIZE, CELL_OVERHEAD};
+use super::cursor::{Cursor, Iter};
+
+/// Magic bytes at the start of the metadata page of a hash index.
+const META_MAGIC: [u8; 8] = *b"ROUILHSH";
+
+/// Size of the header of a directory page: the next directory page.
+const DIRECTORY_HEADER_SIZE: usize = 4;
+
+/// Represents errors that can occur during hash index operations.
+#[derive(Error, Debug)]
+pub enum HashIndexError {
+    /// Indicates that an operation on the underlying pager failed.
+    #[error(transparent)]
+    Pager(#[from] PagerError),
+
+    /// Indicates that a page does not contain a valid hash index page.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page that could not be decoded.
+    #[error("The page ({0}) does not contain a valid hash index page.")]
+    CorruptedPage(PageId),
+
+    /// Indicates that a key-value pair is too large to be stored in a bucket page.
+    #[error("The entry is too large. The key is {key_size} bytes and the value is {value_size} bytes, but key and value must be at most {max_entry_size} bytes.")]
+    EntryTooLarge {
+        key_size: usize,
+        value_size: usize,
+        max_entry_size: usize,
+    },
+}
+
+/// Represents a hash index stored in the pages of a [Pager], using linear hashing.
+///
+/// Like a [BTree](crate::btree::BTree), the index maps byte-string keys to byte-string values, but
+/// the entries are spread over buckets by the hash of their key instead of being kept in key
+/// order. A point lookup reads a single bucket, without going through interior nodes, which suits
+/// workloads that never scan ranges of keys.
+///
+/// Each bucket is a primary page followed by a chain of overflow pages. When an insertion adds an
+/// overflow page, the next bucket in turn is split: its entries are shared between itself and a
+/// new bucket appended at the end. The number of buckets grows one at a time with the number of
+/// entries, so overflow chains stay short without ever rehashing the whole index. Buckets are not
+/// merged when entries are deleted.
+///
+/// The metadata page of the index never moves, so it can be stored to later reopen the index with
+/// [HashIndex::open]. It is followed by a directory, in a chain of pages, listing the primary page
+/// of each bucket, which is loaded in memory.
+pub struct HashIndex {
+    meta: PageId,
+    level: u32,
+    split: u32,
+    entry_count: u64,
+    buckets: Vec<PageId>,
+    directory: Vec<PageId>,
+}
+
+impl HashIndex {
+    /// Creates a new, empty, index with a single bucket, with its metadata in a newly allocated
+    /// page.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::hash::HashIndex;
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut index = HashIndex::create(&mut pager).expect("create should not fail");
+    /// index.insert(&mut pager, b"key", b"value").expect("insert should not fail");
+    ///
+    /// let value = index.get(&pager, b"key").expect("get should not fail");
+    /// assert_eq!(value, Some(b"value".to_vec()));
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, HashIndexError> {
+        let meta = pager.allocate_page()?;
+        let first_bucket = pager.allocate_page()?;
+        bucket::write_chain(pager, vec![first_bucket], &[])?;
+        let mut index = HashIndex {
+            meta,
+            level: 0,
+            split: 0,
+            entry_count: 0,
+            buckets: vec![first_bucket],
+            directory: Vec::new(),
+        };
+        index.write_directory(pager, 0)?;
+        index.write_meta(pager)?;
+        Ok(index)
+    }
+
+    /// Opens an index previously created with [HashIndex::create].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the metadata or the directory can't be read or is
+    /// corrupted.
+    pub fn open<F: File>(pager: &Pager<F>, meta: PageId) -> Result<Self, HashIndexError> {
+        let corrupted = HashIndexError::CorruptedPage;
+        let page = pager.read_page(meta)?;
+        if page[..META_MAGIC.len()] != META_MAGIC {
+            return Err(corrupted(meta));
+        }
+        let level = read_u32(&page, 8);
+        let split = read_u32(&page, 12);
+        let entry_count =
+            u64::from_le_bytes(page[16..24].try_into().expect("slice should be 8 bytes"));
+        let mut next = read_u32(&page, 24);
+        if level >= 32 || split >= 1 << level {
+            return Err(corrupted(meta));
+        }
+
+        let bucket_count = (1usize << level) + split as usize;
+        let mut buckets = Vec::with_capacity(bucket_count);
+        let mut directory = Vec::new();
+        while buckets.len() < bucket_count {
+            if next == 0 {
+                return Err(corrupted(meta));
+            }
+            let page = pager.read_page(next)?;
+            directory.push(next);
+            let count = (bucket_count - buckets.len()).min(directory_capacity(pager.page_size()));
+            buckets
+                .extend((0..count).map(|slot| read_u32(&page, DIRECTORY_HEADER_SIZE + slot * 4)));
+            next = read_u32(&page, 0);
+        }
+
+        Ok(HashIndex {
+            meta,
+            level,
+            split,
+            entry_count,
+            buckets,
+            directory,
+        })
+    }
+
+    /// Returns the identifier of the metadata page of the index.
+    pub fn meta(&self) -> PageId {
+        self.meta
+    }
+
+    /// Returns the number of entries in the index.
+    pub fn len(&self) -> u64 {
+        self.entry_count
+    }
+
+    /// Returns `true` if the index does not contain any entry.
+    pub fn is_empty(&self) -> bool {
+        self.entry_count == 0
+    }
+
+    /// Returns the number of buckets of the index.
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Returns the largest combined size of a key and its value that can be stored in an index
+    /// using pages of the given size.
+    pub fn max_entry_size(page_size: usize) -> usize {
+        page_size - BUCKET_HEADER_SIZE - CELL_OVERHEAD
+    }
+
+    /// Returns the value associated with a key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, HashIndexError> {
+        let (entries, _) = bucket::read_chain(pager, self.buckets[self.bucket_of(key)])?;
+        Ok(entries
+            .into_iter()
+            .find_map(|(k, value)| (k == key).then_some(value)))
+    }
+
+    /// Returns a new [Cursor] on the index. The cursor is not positioned on any entry until one of
+    /// its seek methods is called.
+    pub fn cursor<'a, F: File>(&'a self, pager: &'a Pager<F>) -> Cursor<'a, F> {
+        Cursor::new(pager, self)
+    }
+
+    /// Returns an iterator over all the entries of the index, in bucket order. The entries are
+    /// not ordered by key.
+    pub fn iter<'a, F: File>(&'a self, pager: &'a Pager<F>) -> Iter<'a, F> {
+        Iter::new(self.cursor(pager))
+    }
+
+    /// Inserts a key-value pair in the index, replacing the previous value of the key. Returns the
+    /// previous value, if there was one.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the key and the value are too large (see [HashIndex::max_entry_size])
+    /// - a page can't be read, written or allocated
+    pub fn insert<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>, HashIndexError> {
+        Self::check_entry_size(pager.page_size(), key, value)?;
+
+        let (mut entries, pages) = bucket::read_chain(pager, self.buckets[self.bucket_of(key)])?;
+        let previous = match entries.iter_mut().find(|(k, _)| k == key) {
+            Some((_, old)) => Some(std::mem::replace(old, value.to_vec())),
+            None => {
+                entries.push((key.to_vec(), value.to_vec()));
+                self.entry_count += 1;
+                None
+            }
+        };
+        let page_count = pages.len();
+        let pages = bucket::write_chain(pager, pages, &entries)?;
+
+        if pages.len() > page_count {
+            self.split_next(pager)?;
+        }
+        self.write_meta(pager)?;
+        Ok(previous)
+    }
+
+    /// Removes a key from the index. Returns the value of the key, if it was present. The
+    /// overflow pages no longer needed by the bucket are freed.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read, written or freed.
+    pub fn delete<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, HashIndexError> {
+        let (mut entries, pages) = bucket::read_chain(pager, self.buckets[self.bucket_of(key)])?;
+        let Some(position) = entries.iter().position(|(k, _)| k == key) else {
+            return Ok(None);
+        };
+        let (_, previous) = entries.remove(position);
+        bucket::write_chain(pager, pages, &entries)?;
+        self.entry_count -= 1;
+        self.write_meta(pager)?;
+        Ok(Some(previous))
+    }
+
+    /// Returns the primary page of each bucket.
+    pub(super) fn buckets(&self) -> &[PageId] {
+        &self.buckets
+    }
+
+    /// Returns the bucket of a key. The buckets before the split pointer were already split and
+    /// are addressed with one more bit of the hash.
+    pub(super) fn bucket_of(&self, key: &[u8]) -> usize {
+        let hash = hash64(key);
+        let bucket = hash & ((1u64 << self.level) - 1);
+        let bucket = if bucket < u64::from(self.split) {
+            hash & ((1u64 << (self.level + 1)) - 1)
+        } else {
+            bucket
+        };
+        bucket as usize
+    }
+
+    /// Splits the bucket at the split pointer into itself and a new bucket at the end, then moves
+    /// the split pointer to the next bucket.
+    fn split_next<F: File>(&mut self, pager: &mut Pager<F>) -> Result<(), HashIndexError> {
+        let split = self.split as usize;
+        let new_bucket = self.buckets.len();
+        let (entries, pages) = bucket::read_chain(pager, self.buckets[split])?;
+        let mask = (1u64 << (self.level + 1)) - 1;
+        let (moved, kept): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .partition(|(key, _)| (hash64(key) & mask) as usize == new_bucket);
+
+        let first = pager.allocate_page()?;
+        bucket::write_chain(pager, pages, &kept)?;
+        bucket::write_chain(pager, vec![first], &moved)?;
+        self.buckets.push(first);
+        self.write_directory(pager, new_bucket)?;
+
+        self.split += 1;
+        if self.split == 1 << self.level {
+            self.level += 1;
+            self.split = 0;
+        }
+        Ok(())
+    }
+
+    /// Writes the directory page holding the primary page of a bucket, allocating it if it is
+    /// the first bucket of the page.
+    fn write_directory<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        bucket: usize,
+    ) -> Result<(), HashIndexError> {
+        let capacity = directory_capacity(pager.page_size());
+        let page_index = bucket / capacity;
+        if page_index == self.directory.len() {
+            self.directory.push(pager.allocate_page()?);
+            if page_index > 0 {
+                self.write_directory_page(pager, page_index - 1)?;
+            }
+        }
+        self.write_directory_page(pager, page_index)
+    }
+
+    /// Writes a page of the directory: the next directory page and the primary pages of its
+    /// buckets.
+    fn write_directory_page<F: File>(
+        &self,
+        pager: &mut Pager<F>,
+        page_index: usize,
+    ) -> Result<(), HashIndexError> {
+        let capacity = directory_capacity(pager.page_size());
+        let next = self.directory.get(page_index + 1).copied().unwrap_or(0);
+        let mut page = Vec::with_capacity(pager.page_size());
+        page.extend_from_slice(&next.to_le_bytes());
+        for id in self
+            .buckets
+            .iter()
+            .skip(page_index * capacity)
+            .take(capacity)
+        {
+            page.extend_from_slice(&id.to_le_bytes());
+        }
+        page.resize(pager.page_size(), 0);
+        pager.write_page(self.directory[page_index], &page)?;
+        Ok(())
+    }
+
+    /// Writes the metadata page: the magic bytes, the level, the split pointer, the number of
+    /// entries and the first directory page.
+    fn write_meta<F: File>(&self, pager: &mut Pager<F>) -> Result<(), HashIndexError> {
+        let mut page = Vec::with_capacity(pager.page_size());
+        page.extend_from_slice(&META_MAGIC);
+        page.extend_from_slice(&self.level.to_le_bytes());
+        page.extend_from_slice(&self.split.to_le_bytes());
+        page.extend_from_slice(&self.entry_count.to_le_bytes());
+        page.extend_from_slice(&self.directory[0].to_le_bytes());
+        page.resize(pager.page_size(), 0);
+        pager.write_page(self.meta, &page)?;
+        Ok(())
+    }
+
+    /// Checks that a key-value pair is small enough to be stored in an index using pages of the
+    /// given size.
+    fn check_entry_size(page_size: usize, key: &[u8], value: &[u8]) -> Result<(), HashIndexError> {
+        if key.len() + value.len() > Self::max_entry_size(page_size) {
+            return Err(HashIndexError::EntryTooLarge {
+                key_size: key.len(),
+                value_size: value.len(),
+                max_entry_size: Self::max_entry_size(page_size),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Returns the number of bucket pages listed in a directory page.
+fn directory_capacity(page_size: usize) -> usize {
+    (page_size - DIRECTORY_HEADER_SIZE) / 4
+}
+
+fn read_u32(page: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(
+        page[offset..offset + 4]
+            .try_into()
+            .expect("slice should be 4 bytes"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::seq::SliceRandom;
+
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_index() -> (Pager<MemoryFile>, HashIndex) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let index = HashIndex::create(&mut pager).expect("create should not fail");
+        (pager, index)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:06}").into_bytes()
+    }
+
+    fn value(index: usize) -> Vec<u8> {
+        format!("value-{index}").into_bytes()
+    }
+
+    /// Inserting an existing key replaces its value and returns the previous one.
+    #[test]
+    fn insert_existing_key_returns_previous_value() {
+        let (mut pager, mut index) = create_index();
+        index
+            .insert(&mut pager, b"key", b"first")
+            .expect("insert should not fail");
+
+        let previous = index
+            .insert(&mut pager, b"key", b"second")
+            .expect("insert should not fail");
+
+        assert_eq!(previous, Some(b"first".to_vec()));
+        assert_eq!(
+            index.get(&pager, b"key").expect("get should not fail"),
+            Some(b"second".to_vec())
+        );
+        assert_eq!(index.len(), 1);
+    }
+
+    /// Many insertions split the buckets, and every key is still found in its bucket.
+    #[test]
+    fn insert_many_keys_splits_buckets() {
+        let (mut pager, mut index) = create_index();
+
+        for i in 0..2000 {
+            index
+                .insert(&mut pager, &key(i), &value(i))
+                .expect("insert should not fail");
+        }
+
+        assert!(index.bucket_count() > 50);
+        assert_eq!(index.len(), 2000);
+        for i in 0..2000 {
+            assert_eq!(
+                index.get(&pager, &key(i)).expect("get should not fail"),
+                Some(value(i))
+            );
+        }
+        assert_eq!(
+            index.get(&pager, &key(2000)).expect("get should not fail"),
+            None
+        );
+    }
+
+    /// Random insertions and deletions match a reference map.
+    #[test]
+    fn random_writes_match_reference() {
+        let (mut pager, mut index) = create_index();
+        let mut reference = HashMap::new();
+        let mut indexes: Vec<usize> = (0..1000).chain(0..500).collect();
+        indexes.shuffle(&mut rand::thread_rng());
+
+        for (step, &i) in indexes.iter().enumerate() {
+            if step % 5 == 0 {
+                let deleted = index
+                    .delete(&mut pager, &key(i))
+                    .expect("delete should not fail");
+                assert_eq!(deleted, reference.remove(&key(i)));
+            } else {
+                let previous = index
+                    .insert(&mut pager, &key(i), &value(step))
+                    .expect("insert should not fail");
+                assert_eq!(previous, reference.insert(key(i), value(step)));
+            }
+        }
+
+        assert_eq!(index.len(), reference.len() as u64);
+        for i in 0..1000 {
+            let value = index.get(&pager, &key(i)).expect("get should not fail");
+            assert_eq!(value.as_ref(), reference.get(&key(i)));
+        }
+    }
+
+    /// An index can be reopened from its metadata page, with a directory spanning several pages.
+    #[test]
+    fn open_existing_index_finds_keys() {
+        let (mut pager, mut index) = create_index();
+        for i in 0..5000 {
+            index
+                .insert(&mut pager, &key(i), &value(i))
+                .expect("insert should not fail");
+        }
+
+        let pager = Pager::open(pager.into_file()).expect("open should not fail");
+        let opened = HashIndex::open(&pager, index.meta()).expect("open should not fail");
+
+        assert!(index.directory.len() > 1);
+        assert_eq!(opened.buckets, index.buckets);
+        assert_eq!(opened.len(), 5000);
+        assert_eq!(
+            opened.get(&pager, &key(4321)).expect("get should not fail"),
+            Some(value(4321))
+        );
+    }
+
+    /// An entry larger than a bucket page is rejected.
+    #[test]
+    fn insert_too_large_entry_fails() {
+        let (mut pager, mut index) = create_index();
+
+        let result = index.insert(&mut pager, b"key", &[0; 600]);
+
+        assert!(matches!(
+            result,
+            Err(HashIndexError::EntryTooLarge {
+                key_size: 3,
+                value_size: 600,
+                ..
+            })
+        ));
+    }
+}
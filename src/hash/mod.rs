@@ -0,0 +1,5 @@
+mod bucket;
+mod cursor;
+mod index;
+pub use cursor::{Cursor, Iter};
+pub use index::{HashIndex, HashIndexError};
@@ -0,0 +1,586 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::fs::File;
+use crate::pager::{PageId, Pager, PagerError};
+
+use super::slotted_page::SlottedPage;
+
+/// Kind of a cell holding a record at the address of the record.
+const RECORD_KIND: u8 = 0;
+
+/// Kind of a cell holding the address where a record was moved.
+const FORWARD_KIND: u8 = 1;
+
+/// Kind of a cell holding a record moved away from its address. It is only reached through the
+/// forwarding cell left at the address of the record.
+const MOVED_KIND: u8 = 2;
+
+/// Represents errors that can occur during heap file operations.
+#[derive(Error, Debug)]
+pub enum HeapError {
+    /// Indicates that an operation on the underlying pager failed.
+    #[error(transparent)]
+    Pager(#[from] PagerError),
+
+    /// Indicates that a page does not contain a valid heap page.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page that could not be decoded.
+    #[error("The page ({0}) does not contain a valid heap page.")]
+    CorruptedPage(PageId),
+
+    /// Indicates that a record is too large to be stored in a page.
+    ///
+    /// # Fields
+    /// - `size` - The size of the record.
+    /// - `max_record_size` - The size of the largest record that can be stored.
+    #[error("The record is too large. The record is {size} bytes, but it must be at most {max_record_size} bytes.")]
+    RecordTooLarge { size: usize, max_record_size: usize },
+}
+
+/// Identifies a record of a [HeapFile]: the page and the slot where it was inserted.
+///
+/// The identifier of a record never changes, even when the record is moved, so it can be stored in
+/// an index to reference the record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RecordId {
+    /// The page where the record was inserted.
+    pub page: PageId,
+
+    /// The slot of the record in its page.
+    pub slot: u16,
+}
+
+impl RecordId {
+    /// The size of an encoded record identifier.
+    pub const SIZE: usize = 6;
+
+    /// Encodes the identifier so it can be stored, for example as the value of an index. The
+    /// encoding sorts like the identifiers.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..4].copy_from_slice(&self.page.to_be_bytes());
+        bytes[4..].copy_from_slice(&self.slot.to_be_bytes());
+        bytes
+    }
+
+    /// Decodes an identifier encoded by [RecordId::to_bytes]. Returns `None` if the bytes do not
+    /// have the right length.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; Self::SIZE] = bytes.try_into().ok()?;
+        Some(RecordId {
+            page: u32::from_be_bytes(bytes[..4].try_into().ok()?),
+            slot: u16::from_be_bytes(bytes[4..].try_into().ok()?),
+        })
+    }
+}
+
+impl fmt::Display for RecordId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.page, self.slot)
+    }
+}
+
+/// Represents an unordered collection of records stored in slotted pages of a [Pager].
+///
+/// Each record is addressed by a [RecordId] returned when it is inserted. A record that no longer
+/// fits in its page after an update is moved to another page and a forwarding address is left in
+/// its slot, so its identifier stays valid and a lookup reads at most two pages.
+///
+/// The pages of the file are linked in a chain starting at the first page, which never moves, so
+/// it can be stored to later reopen the file with [HeapFile::open]. The chain and the free space
+/// of each page are loaded in memory when the file is opened, to find a page with enough space for
+/// a new record without reading the pages.
+pub struct HeapFile {
+    pages: Vec<PageId>,
+    free_space: Vec<usize>,
+}
+
+impl HeapFile {
+    /// Creates a new, empty, heap file with its first page newly allocated.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the first page can't be allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::heap::HeapFile;
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut heap = HeapFile::create(&mut pager).expect("create should not fail");
+    /// let id = heap.insert(&mut pager, b"record").expect("insert should not fail");
+    ///
+    /// let record = heap.get(&pager, id).expect("get should not fail");
+    /// assert_eq!(record, Some(b"record".to_vec()));
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, HeapError> {
+        let first = pager.allocate_page()?;
+        let page = SlottedPage::new(pager.page_size());
+        pager.write_page(first, page.bytes())?;
+        Ok(HeapFile {
+            pages: vec![first],
+            free_space: vec![page.free_space()],
+        })
+    }
+
+    /// Opens a heap file previously created with [HeapFile::create] from its first page.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page of the chain can't be read or is corrupted.
+    pub fn open<F: File>(pager: &Pager<F>, first: PageId) -> Result<Self, HeapError> {
+        let mut pages = Vec::new();
+        let mut free_space = Vec::new();
+        let mut next = first;
+        while next != 0 {
+            let page = read_page(pager, next)?;
+            pages.push(next);
+            free_space.push(page.free_space());
+            next = page.next();
+        }
+        Ok(HeapFile { pages, free_space })
+    }
+
+    /// Returns the identifier of the first page of the file.
+    pub fn first_page(&self) -> PageId {
+        self.pages[0]
+    }
+
+    /// Returns the number of pages of the file.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns the size of the largest record that can be stored in a file using pages of the
+    /// given size.
+    pub fn max_record_size(page_size: usize) -> usize {
+        SlottedPage::max_cell_size(page_size) - 1
+    }
+
+    /// Returns a record, or `None` if there is no record with this identifier.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        id: RecordId,
+    ) -> Result<Option<Vec<u8>>, HeapError> {
+        Ok(self.locate(pager, id)?.map(|(_, record)| record))
+    }
+
+    /// Returns an iterator over the records of the file, with their identifier, in page and slot
+    /// order.
+    pub fn iter<'a, F: File>(&'a self, pager: &'a Pager<F>) -> HeapIter<'a, F> {
+        HeapIter {
+            pager,
+            heap: self,
+            page_index: 0,
+            cells: Vec::new().into_iter(),
+            finished: false,
+        }
+    }
+
+    /// Inserts a record in the first page with enough free space, or in a new page. Returns the
+    /// identifier of the record.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the record is too large (see [HeapFile::max_record_size])
+    /// - a page can't be read, written or allocated
+    pub fn insert<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        record: &[u8],
+    ) -> Result<RecordId, HeapError> {
+        Self::check_record_size(pager.page_size(), record)?;
+        self.insert_cell(pager, &cell(RECORD_KIND, record), None)
+    }
+
+    /// Replaces a record, keeping its identifier. Returns the previous record, or `None`, without
+    /// writing anything, if there is no record with this identifier.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the record is too large (see [HeapFile::max_record_size])
+    /// - a page can't be read, written, allocated or freed
+    pub fn update<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        id: RecordId,
+        record: &[u8],
+    ) -> Result<Option<Vec<u8>>, HeapError> {
+        Self::check_record_size(pager.page_size(), record)?;
+        let Some((location, previous)) = self.locate(pager, id)? else {
+            return Ok(None);
+        };
+
+        // The record is updated where it is if it still fits in its page.
+        let kind = if location == id {
+            RECORD_KIND
+        } else {
+            MOVED_KIND
+        };
+        let mut page = read_page(pager, location.page)?;
+        if page.update(location.slot as usize, &cell(kind, record)) {
+            self.write(pager, location.page, &page)?;
+            return Ok(Some(previous));
+        }
+
+        // Otherwise, it is moved to another page and the forwarding address is updated.
+        if location != id {
+            page.remove(location.slot as usize);
+            self.write(pager, location.page, &page)?;
+        }
+        let moved = self.insert_cell(pager, &cell(MOVED_KIND, record), Some(id.page))?;
+        let mut page = read_page(pager, id.page)?;
+        let updated = page.update(id.slot as usize, &cell(FORWARD_KIND, &moved.to_bytes()));
+        debug_assert!(updated, "a forwarding address should always fit in place");
+        self.write(pager, id.page, &page)?;
+        Ok(Some(previous))
+    }
+
+    /// Removes a record. Returns the record, or `None` if there is no record with this identifier.
+    /// The slot of the record may be reused by a later insertion.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or written.
+    pub fn delete<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        id: RecordId,
+    ) -> Result<Option<Vec<u8>>, HeapError> {
+        let Some((location, previous)) = self.locate(pager, id)? else {
+            return Ok(None);
+        };
+        if location != id {
+            let mut page = read_page(pager, location.page)?;
+            page.remove(location.slot as usize);
+            self.write(pager, location.page, &page)?;
+        }
+        let mut page = read_page(pager, id.page)?;
+        page.remove(id.slot as usize);
+        self.write(pager, id.page, &page)?;
+        Ok(Some(previous))
+    }
+
+    /// Returns where a record is stored, following its forwarding address if it was moved, and
+    /// the record.
+    fn locate<F: File>(
+        &self,
+        pager: &Pager<F>,
+        id: RecordId,
+    ) -> Result<Option<(RecordId, Vec<u8>)>, HeapError> {
+        if !self.pages.contains(&id.page) {
+            return Ok(None);
+        }
+        let page = read_page(pager, id.page)?;
+        let Some((&kind, payload)) = page.get(id.slot as usize).and_then(<[u8]>::split_first)
+        else {
+            return Ok(None);
+        };
+        match kind {
+            RECORD_KIND => Ok(Some((id, payload.to_vec()))),
+            FORWARD_KIND => {
+                let corrupted = || HeapError::CorruptedPage(id.page);
+                let target = RecordId::from_bytes(payload).ok_or_else(corrupted)?;
+                let page = read_page(pager, target.page)?;
+                match page.get(target.slot as usize).and_then(<[u8]>::split_first) {
+                    Some((&MOVED_KIND, record)) => Ok(Some((target, record.to_vec()))),
+                    _ => Err(HeapError::CorruptedPage(target.page)),
+                }
+            }
+            MOVED_KIND => Ok(None),
+            _ => Err(HeapError::CorruptedPage(id.page)),
+        }
+    }
+
+    /// Inserts a cell in the first page with enough free space other than `excluded`, appending a
+    /// new page to the chain if there is none.
+    fn insert_cell<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        cell: &[u8],
+        excluded: Option<PageId>,
+    ) -> Result<RecordId, HeapError> {
+        let candidate = (0..self.pages.len()).find(|&index| {
+            Some(self.pages[index]) != excluded
+                && self.free_space[index] >= SlottedPage::required_space(cell.len())
+        });
+        let (id, mut page) = match candidate {
+            Some(index) => (self.pages[index], read_page(pager, self.pages[index])?),
+            None => self.append_page(pager)?,
+        };
+        let slot = page
+            .insert(cell)
+            .expect("the page should have enough free space");
+        self.write(pager, id, &page)?;
+        Ok(RecordId {
+            page: id,
+            slot: slot as u16,
+        })
+    }
+
+    /// Allocates a new page and links it at the end of the chain.
+    fn append_page<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+    ) -> Result<(PageId, SlottedPage), HeapError> {
+        let id = pager.allocate_page()?;
+        let last = *self.pages.last().expect("a heap file should have a page");
+        let mut last_page = read_page(pager, last)?;
+        last_page.set_next(id);
+        pager.write_page(last, last_page.bytes())?;
+
+        let page = SlottedPage::new(pager.page_size());
+        self.pages.push(id);
+        self.free_space.push(page.free_space());
+        Ok((id, page))
+    }
+
+    /// Writes a page and records its free space.
+    fn write<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        id: PageId,
+        page: &SlottedPage,
+    ) -> Result<(), HeapError> {
+        pager.write_page(id, page.bytes())?;
+        if let Some(index) = self.pages.iter().position(|&page| page == id) {
+            self.free_space[index] = page.free_space();
+        }
+        Ok(())
+    }
+
+    /// Checks that a record is small enough to be stored in a file using pages of the given size.
+    fn check_record_size(page_size: usize, record: &[u8]) -> Result<(), HeapError> {
+        if record.len() > Self::max_record_size(page_size) {
+            return Err(HeapError::RecordTooLarge {
+                size: record.len(),
+                max_record_size: Self::max_record_size(page_size),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// An iterator over the records of a [HeapFile], with their identifier. The pages are read as the
+/// iteration progresses. After an error is returned, the iterator does not return any more items.
+pub struct HeapIter<'a, F: File> {
+    pager: &'a Pager<F>,
+    heap: &'a HeapFile,
+    page_index: usize,
+    cells: std::vec::IntoIter<RecordId>,
+    finished: bool,
+}
+
+impl<F: File> Iterator for HeapIter<'_, F> {
+    type Item = Result<(RecordId, Vec<u8>), HeapError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            if let Some(id) = self.cells.next() {
+                match self.heap.locate(self.pager, id) {
+                    Ok(Some((_, record))) => return Some(Ok((id, record))),
+                    Ok(None) => continue,
+                    Err(error) => {
+                        self.finished = true;
+                        return Some(Err(error));
+                    }
+                }
+            }
+
+            let Some(&page_id) = self.heap.pages.get(self.page_index) else {
+                self.finished = true;
+                break;
+            };
+            self.page_index += 1;
+            match read_page(self.pager, page_id) {
+                Ok(page) => {
+                    self.cells = (0..page.slot_count())
+                        .map(|slot| RecordId {
+                            page: page_id,
+                            slot: slot as u16,
+                        })
+                        .collect::<Vec<_>>()
+                        .into_iter();
+                }
+                Err(error) => {
+                    self.finished = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Returns a cell holding a kind followed by its payload.
+fn cell(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut cell = Vec::with_capacity(1 + payload.len());
+    cell.push(kind);
+    cell.extend_from_slice(payload);
+    cell
+}
+
+fn read_page<F: File>(pager: &Pager<F>, id: PageId) -> Result<SlottedPage, HeapError> {
+    let data = pager.read_page(id)?;
+    SlottedPage::from_bytes(data).ok_or(HeapError::CorruptedPage(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::Rng;
+
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_heap() -> (Pager<MemoryFile>, HeapFile) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let heap = HeapFile::create(&mut pager).expect("create should not fail");
+        (pager, heap)
+    }
+
+    fn record(index: usize, len: usize) -> Vec<u8> {
+        let mut record = format!("record-{index}-").into_bytes();
+        record.resize(len.max(record.len()), b'.');
+        record
+    }
+
+    /// Records inserted over several pages are read back from their identifier.
+    #[test]
+    fn insert_then_get_returns_records() {
+        let (mut pager, mut heap) = create_heap();
+
+        let ids: Vec<RecordId> = (0..100)
+            .map(|index| {
+                heap.insert(&mut pager, &record(index, 30))
+                    .expect("insert should not fail")
+            })
+            .collect();
+
+        assert!(heap.page_count() > 1);
+        for (index, id) in ids.into_iter().enumerate() {
+            let found = heap.get(&pager, id).expect("get should not fail");
+            assert_eq!(found, Some(record(index, 30)));
+        }
+    }
+
+    /// A record that grows past the free space of its page is moved but keeps its identifier.
+    #[test]
+    fn update_larger_record_keeps_identifier() {
+        let (mut pager, mut heap) = create_heap();
+        let ids: Vec<RecordId> = (0..10)
+            .map(|index| {
+                heap.insert(&mut pager, &record(index, 40))
+                    .expect("insert should not fail")
+            })
+            .collect();
+
+        let previous = heap
+            .update(&mut pager, ids[3], &record(3, 300))
+            .expect("update should not fail");
+        heap.update(&mut pager, ids[3], &record(3, 400))
+            .expect("update should not fail");
+
+        assert_eq!(previous, Some(record(3, 40)));
+        assert_eq!(
+            heap.get(&pager, ids[3]).expect("get should not fail"),
+            Some(record(3, 400))
+        );
+        assert_eq!(heap.iter(&pager).count(), 10);
+    }
+
+    /// A deleted record is no longer found, including when it was moved.
+    #[test]
+    fn delete_moved_record_removes_it() {
+        let (mut pager, mut heap) = create_heap();
+        let ids: Vec<RecordId> = (0..10)
+            .map(|index| {
+                heap.insert(&mut pager, &record(index, 40))
+                    .expect("insert should not fail")
+            })
+            .collect();
+        heap.update(&mut pager, ids[5], &record(5, 400))
+            .expect("update should not fail");
+
+        let deleted = heap
+            .delete(&mut pager, ids[5])
+            .expect("delete should not fail");
+
+        assert_eq!(deleted, Some(record(5, 400)));
+        assert_eq!(heap.get(&pager, ids[5]).expect("get should not fail"), None);
+        assert_eq!(heap.iter(&pager).count(), 9);
+    }
+
+    /// Random insertions, updates and deletions match a reference map, and the file can be
+    /// reopened from its first page.
+    #[test]
+    fn random_writes_match_reference() {
+        let (mut pager, mut heap) = create_heap();
+        let mut rng = rand::thread_rng();
+        let mut reference: HashMap<RecordId, Vec<u8>> = HashMap::new();
+
+        for step in 0..2000 {
+            let ids: Vec<RecordId> = reference.keys().copied().collect();
+            let data = record(step, rng.gen_range(0..200));
+            match rng.gen_range(0..3) {
+                0 if !ids.is_empty() => {
+                    let id = ids[rng.gen_range(0..ids.len())];
+                    let deleted = heap.delete(&mut pager, id).expect("delete should not fail");
+                    assert_eq!(deleted, reference.remove(&id));
+                }
+                1 if !ids.is_empty() => {
+                    let id = ids[rng.gen_range(0..ids.len())];
+                    let previous = heap
+                        .update(&mut pager, id, &data)
+                        .expect("update should not fail");
+                    assert_eq!(previous, reference.insert(id, data));
+                }
+                _ => {
+                    let id = heap
+                        .insert(&mut pager, &data)
+                        .expect("insert should not fail");
+                    assert_eq!(reference.insert(id, data), None);
+                }
+            }
+        }
+
+        let heap = HeapFile::open(&pager, heap.first_page()).expect("open should not fail");
+        let records: HashMap<RecordId, Vec<u8>> = heap
+            .iter(&pager)
+            .map(|entry| entry.expect("iteration should not fail"))
+            .collect();
+        assert_eq!(records, reference);
+    }
+
+    /// A record identifier is decoded back from its encoding.
+    #[test]
+    fn record_id_from_bytes_returns_id() {
+        let id = RecordId {
+            page: 1234,
+            slot: 56,
+        };
+
+        assert_eq!(RecordId::from_bytes(&id.to_bytes()), Some(id));
+        assert_eq!(RecordId::from_bytes(&[1, 2, 3]), None);
+    }
+}
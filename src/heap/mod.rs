@@ -0,0 +1,3 @@
+mod heap_file;
+mod slotted_page;
+pub use heap_file::{HeapError, HeapFile, HeapIter, RecordId};
@@ -0,0 +1,289 @@
+use crate::pager::PageId;
+
+/// Size of the header of a slotted page: the next page, the number of slots and the start of the
+/// cell area.
+const PAGE_HEADER_SIZE: usize = 10;
+
+/// Size of a slot: the offset and the length of its cell.
+const SLOT_SIZE: usize = 4;
+
+/// Smallest number of bytes reserved for a cell, so any cell can be replaced in place by a
+/// forwarding address (see [HeapFile](super::HeapFile)).
+const MIN_CELL_SIZE: usize = 7;
+
+/// Represents a page holding variable-length cells addressed by slot numbers.
+///
+/// The slots are stored after the header and grow toward the end of the page, while the cells are
+/// stored from the end of the page and grow toward the slots. A cell keeps its slot number for
+/// its whole life, even when it is moved within the page to gather the free space, so it can be
+/// referenced from outside the page. The slot of a removed cell is reused by the next insertion.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct SlottedPage {
+    data: Vec<u8>,
+}
+
+impl SlottedPage {
+    /// Creates an empty page of the given size, without a next page.
+    pub(super) fn new(page_size: usize) -> Self {
+        let mut page = SlottedPage {
+            data: vec![0; page_size],
+        };
+        page.set_cell_start(page_size);
+        page
+    }
+
+    /// Wraps the content of a page. Returns `None` if it is not a valid slotted page.
+    pub(super) fn from_bytes(data: Vec<u8>) -> Option<Self> {
+        let page = SlottedPage { data };
+        let slots_end = PAGE_HEADER_SIZE + page.slot_count() * SLOT_SIZE;
+        if slots_end > page.cell_start() || page.cell_start() > page.data.len() {
+            return None;
+        }
+        let valid = (0..page.slot_count()).all(|slot| {
+            let (offset, len) = page.slot(slot);
+            offset == 0 || (offset >= page.cell_start() && offset + len <= page.data.len())
+        });
+        valid.then_some(page)
+    }
+
+    /// Returns the content of the page.
+    pub(super) fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the next page of the chain, or `0`.
+    pub(super) fn next(&self) -> PageId {
+        u32::from_le_bytes(self.data[0..4].try_into().expect("slice should be 4 bytes"))
+    }
+
+    /// Sets the next page of the chain.
+    pub(super) fn set_next(&mut self, next: PageId) {
+        self.data[0..4].copy_from_slice(&next.to_le_bytes());
+    }
+
+    /// Returns the number of slots, including the free ones.
+    pub(super) fn slot_count(&self) -> usize {
+        u16::from_le_bytes([self.data[4], self.data[5]]) as usize
+    }
+
+    /// Returns the cell of a slot, or `None` if the slot is free or does not exist.
+    pub(super) fn get(&self, slot: usize) -> Option<&[u8]> {
+        if slot >= self.slot_count() {
+            return None;
+        }
+        let (offset, len) = self.slot(slot);
+        (offset != 0).then(|| &self.data[offset..offset + len])
+    }
+
+    /// Returns the number of bytes available for new cells and slots, once the cells are
+    /// gathered.
+    pub(super) fn free_space(&self) -> usize {
+        let used: usize = (0..self.slot_count())
+            .filter(|&slot| self.slot(slot).0 != 0)
+            .map(|slot| reserved(self.slot(slot).1))
+            .sum();
+        self.data.len() - PAGE_HEADER_SIZE - self.slot_count() * SLOT_SIZE - used
+    }
+
+    /// Returns the free space a page needs to be sure to accept a cell of the given length.
+    pub(super) fn required_space(len: usize) -> usize {
+        reserved(len) + SLOT_SIZE
+    }
+
+    /// Returns the largest cell that can be inserted in an empty page of the given size.
+    pub(super) fn max_cell_size(page_size: usize) -> usize {
+        page_size - PAGE_HEADER_SIZE - SLOT_SIZE
+    }
+
+    /// Inserts a cell, reusing a free slot if there is one. Returns the slot of the cell, or `None`
+    /// if the page does not have enough space.
+    pub(super) fn insert(&mut self, cell: &[u8]) -> Option<usize> {
+        let free_slot = (0..self.slot_count()).find(|&slot| self.slot(slot).0 == 0);
+        let slot_size = if free_slot.is_some() { 0 } else { SLOT_SIZE };
+        if self.free_space() < reserved(cell.len()) + slot_size {
+            return None;
+        }
+        // The slot array must not grow over the cells.
+        if self.contiguous_space() < reserved(cell.len()) + slot_size {
+            self.compact();
+        }
+
+        let slot = free_slot.unwrap_or_else(|| {
+            let slot = self.slot_count();
+            self.set_slot_count(slot + 1);
+            self.set_slot(slot, 0, 0);
+            slot
+        });
+        self.place(slot, cell);
+        Some(slot)
+    }
+
+    /// Replaces the cell of a slot, keeping its slot number. Returns `false`, without modifying
+    /// the page, if the slot is free or if the page does not have enough space for the new cell.
+    pub(super) fn update(&mut self, slot: usize, cell: &[u8]) -> bool {
+        let Some(old) = self.get(slot) else {
+            return false;
+        };
+        let old_reserved = reserved(old.len());
+        if reserved(cell.len()) <= old_reserved {
+            let (offset, _) = self.slot(slot);
+            self.data[offset..offset + cell.len()].copy_from_slice(cell);
+            self.set_slot(slot, offset, cell.len());
+            return true;
+        }
+        if self.free_space() + old_reserved < reserved(cell.len()) {
+            return false;
+        }
+        self.set_slot(slot, 0, 0);
+        self.place(slot, cell);
+        true
+    }
+
+    /// Removes the cell of a slot and returns it. The slot becomes free. Returns `None` if the
+    /// slot was already free.
+    pub(super) fn remove(&mut self, slot: usize) -> Option<Vec<u8>> {
+        let cell = self.get(slot)?.to_vec();
+        self.set_slot(slot, 0, 0);
+        let mut slot_count = self.slot_count();
+        while slot_count > 0 && self.slot(slot_count - 1).0 == 0 {
+            slot_count -= 1;
+        }
+        self.set_slot_count(slot_count);
+        Some(cell)
+    }
+
+    /// Moves all the cells to the end of the page so the free space is contiguous. The slot
+    /// numbers do not change.
+    pub(super) fn compact(&mut self) {
+        let cells: Vec<(usize, Vec<u8>)> = (0..self.slot_count())
+            .filter_map(|slot| Some((slot, self.get(slot)?.to_vec())))
+            .collect();
+        let mut cell_start = self.data.len();
+        for (slot, cell) in cells {
+            cell_start -= reserved(cell.len());
+            self.data[cell_start..cell_start + cell.len()].copy_from_slice(&cell);
+            self.set_slot(slot, cell_start, cell.len());
+        }
+        self.set_cell_start(cell_start);
+    }
+
+    /// Writes a cell in the free space, gathering it first if needed, and points a slot to it.
+    /// The page must have enough free space.
+    fn place(&mut self, slot: usize, cell: &[u8]) {
+        if self.contiguous_space() < reserved(cell.len()) {
+            self.compact();
+        }
+        let offset = self.cell_start() - reserved(cell.len());
+        self.data[offset..offset + cell.len()].copy_from_slice(cell);
+        self.set_slot(slot, offset, cell.len());
+        self.set_cell_start(offset);
+    }
+
+    /// Returns the number of free bytes between the slots and the cells.
+    fn contiguous_space(&self) -> usize {
+        self.cell_start() - PAGE_HEADER_SIZE - self.slot_count() * SLOT_SIZE
+    }
+
+    fn set_slot_count(&mut self, count: usize) {
+        self.data[4..6].copy_from_slice(&(count as u16).to_le_bytes());
+    }
+
+    fn cell_start(&self) -> usize {
+        u32::from_le_bytes(
+            self.data[6..10]
+                .try_into()
+                .expect("slice should be 4 bytes"),
+        ) as usize
+    }
+
+    fn set_cell_start(&mut self, start: usize) {
+        self.data[6..10].copy_from_slice(&(start as u32).to_le_bytes());
+    }
+
+    /// Returns the offset and the length of the cell of a slot. The offset is `0` for a free
+    /// slot.
+    fn slot(&self, slot: usize) -> (usize, usize) {
+        let start = PAGE_HEADER_SIZE + slot * SLOT_SIZE;
+        let offset = u16::from_le_bytes([self.data[start], self.data[start + 1]]);
+        let len = u16::from_le_bytes([self.data[start + 2], self.data[start + 3]]);
+        (offset as usize, len as usize)
+    }
+
+    fn set_slot(&mut self, slot: usize, offset: usize, len: usize) {
+        let start = PAGE_HEADER_SIZE + slot * SLOT_SIZE;
+        self.data[start..start + 2].copy_from_slice(&(offset as u16).to_le_bytes());
+        self.data[start + 2..start + 4].copy_from_slice(&(len as u16).to_le_bytes());
+    }
+}
+
+/// Returns the number of bytes reserved in a page for a cell of the given length.
+fn reserved(len: usize) -> usize {
+    len.max(MIN_CELL_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A removed slot is reused by the next insertion and the other cells keep their slot.
+    #[test]
+    fn insert_after_remove_reuses_slot() {
+        let mut page = SlottedPage::new(512);
+        let first = page.insert(b"first").expect("the cell should fit");
+        let second = page.insert(b"second").expect("the cell should fit");
+
+        page.remove(first);
+        let third = page.insert(b"third").expect("the cell should fit");
+
+        assert_eq!(third, first);
+        assert_eq!(page.get(second), Some(&b"second"[..]));
+        assert_eq!(page.get(third), Some(&b"third"[..]));
+    }
+
+    /// The space of removed cells is gathered when a new cell does not fit in the contiguous free
+    /// space.
+    #[test]
+    fn insert_in_fragmented_page_compacts_cells() {
+        let mut page = SlottedPage::new(512);
+        let slots: Vec<usize> = (0..10)
+            .map(|_| page.insert(&[1; 40]).expect("the cell should fit"))
+            .collect();
+        for slot in slots.iter().step_by(2) {
+            page.remove(*slot);
+        }
+
+        let inserted = page.insert(&[2; 150]);
+
+        assert!(inserted.is_some());
+        assert_eq!(page.get(slots[1]), Some(&[1; 40][..]));
+        assert_eq!(page.get(inserted.unwrap()), Some(&[2; 150][..]));
+    }
+
+    /// A cell can grow in place while the page has space, and is left unchanged otherwise.
+    #[test]
+    fn update_grows_cell_when_space_allows() {
+        let mut page = SlottedPage::new(512);
+        let slot = page.insert(b"small").expect("the cell should fit");
+        page.insert(&[0; 300]).expect("the cell should fit");
+
+        let grown = page.update(slot, &[1; 150]);
+        let too_large = page.update(slot, &[2; 250]);
+
+        assert!(grown);
+        assert!(!too_large);
+        assert_eq!(page.get(slot), Some(&[1; 150][..]));
+    }
+
+    /// A page is decoded back from its bytes.
+    #[test]
+    fn from_bytes_returns_same_page() {
+        let mut page = SlottedPage::new(512);
+        page.insert(b"cell").expect("the cell should fit");
+        page.set_next(42);
+
+        let decoded = SlottedPage::from_bytes(page.bytes().to_vec());
+
+        assert_eq!(decoded, Some(page));
+        assert_eq!(SlottedPage::from_bytes(vec![0xff; 512]), None);
+    }
+}
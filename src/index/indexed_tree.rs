@@ -0,0 +1,518 @@
+use thiserror::Error;
+
+use crate::btree::{BTree, BTreeError};
+use crate::common::KeyEncoder;
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+/// Extracts the secondary key of a value, or returns `None` if the value is not indexed.
+pub type KeyExtractor = Box<dyn Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+/// A primary key and its value.
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// Represents errors that can occur during operations on an [IndexedTree].
+#[derive(Error, Debug)]
+pub enum IndexError {
+    /// Indicates that an operation on the primary tree or on the tree of an index failed.
+    #[error(transparent)]
+    BTree(#[from] BTreeError),
+
+    /// Indicates that no index with the given name was created or opened.
+    ///
+    /// # Fields
+    /// - `0` - The name of the index.
+    #[error("There is no index named \"{0}\".")]
+    UnknownIndex(String),
+
+    /// Indicates that an index with the given name already exists.
+    ///
+    /// # Fields
+    /// - `0` - The name of the index.
+    #[error("An index named \"{0}\" already exists.")]
+    DuplicateIndex(String),
+
+    /// Indicates that a secondary key and its primary key are too large to be stored as a key of
+    /// the tree of an index.
+    ///
+    /// # Fields
+    /// - `index` - The name of the index.
+    /// - `key_size` - The size of the encoded secondary key followed by the primary key.
+    /// - `max_key_size` - The largest key that can be stored in the tree of the index.
+    #[error("The key of the index \"{index}\" is too large. It is {key_size} bytes, but must be at most {max_key_size} bytes.")]
+    KeyTooLarge {
+        index: String,
+        key_size: usize,
+        max_key_size: usize,
+    },
+
+    /// Indicates that an index refers to a primary key that is not in the primary tree.
+    ///
+    /// # Fields
+    /// - `0` - The name of the index.
+    #[error("The index \"{0}\" refers to a primary key that does not exist.")]
+    Inconsistent(String),
+}
+
+/// A secondary index: the extractor of its keys and the tree mapping them to primary keys.
+struct SecondaryIndex {
+    name: String,
+    extractor: KeyExtractor,
+    tree: BTree,
+}
+
+/// Represents a [BTree] whose entries can also be looked up by secondary keys extracted from their
+/// values.
+///
+/// Each secondary index is stored in a tree of its own. Its keys are the encoded secondary key
+/// followed by the primary key, so many entries can share the same secondary key, and its values
+/// are empty. Every insertion and deletion through the [IndexedTree] updates all the indexes, so
+/// they always refer to the current values of the primary tree.
+///
+/// The indexes are not listed in the file: like the primary tree, each index is reopened from its
+/// root page with [IndexedTree::open_index]. All the indexes must be opened before the tree is
+/// modified, or the ones left out become stale. The trees are ordered bytewise.
+pub struct IndexedTree {
+    primary: BTree,
+    indexes: Vec<SecondaryIndex>,
+}
+
+impl IndexedTree {
+    /// Creates a new, empty, primary tree without any index.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if the root page
+    /// can't be allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::index::IndexedTree;
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let city = |value: &[u8]| value.split(|&b| b == b',').nth(1).map(<[u8]>::to_vec);
+    /// let mut tree = IndexedTree::create(&mut pager).expect("create should not fail");
+    /// tree.create_index(&mut pager, "city", city)
+    ///     .expect("create_index should not fail");
+    /// tree.insert(&mut pager, b"alice", b"alice,paris").expect("insert should not fail");
+    /// tree.insert(&mut pager, b"bob", b"bob,lyon").expect("insert should not fail");
+    ///
+    /// let keys = tree.lookup(&pager, "city", b"paris").expect("lookup should not fail");
+    /// assert_eq!(keys, vec![b"alice".to_vec()]);
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, IndexError> {
+        Ok(IndexedTree {
+            primary: BTree::create(pager)?,
+            indexes: Vec::new(),
+        })
+    }
+
+    /// Opens a primary tree previously created with [IndexedTree::create]. Its indexes must then
+    /// be opened with [IndexedTree::open_index].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if the root page
+    /// can't be read or does not contain a valid node.
+    pub fn open<F: File>(pager: &Pager<F>, root: PageId) -> Result<Self, IndexError> {
+        Ok(IndexedTree {
+            primary: BTree::open(pager, root)?,
+            indexes: Vec::new(),
+        })
+    }
+
+    /// Returns the identifier of the root page of the primary tree.
+    pub fn root(&self) -> PageId {
+        self.primary.root()
+    }
+
+    /// Returns the primary tree. It must not be modified directly, or the indexes become stale.
+    pub fn primary(&self) -> &BTree {
+        &self.primary
+    }
+
+    /// Returns the identifier of the root page of an index, or `None` if there is no index with
+    /// this name.
+    pub fn index_root(&self, name: &str) -> Option<PageId> {
+        self.indexes
+            .iter()
+            .find(|index| index.name == name)
+            .map(|index| index.tree.root())
+    }
+
+    /// Creates a new index whose keys are extracted from the values by `extractor`, and fills it
+    /// with the entries already in the primary tree. Returns the root page of the index, needed to
+    /// open it again.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - an index with the same name already exists
+    /// - the key extracted from an existing entry is too large
+    /// - a page can't be read, written or allocated
+    pub fn create_index<F, E>(
+        &mut self,
+        pager: &mut Pager<F>,
+        name: &str,
+        extractor: E,
+    ) -> Result<PageId, IndexError>
+    where
+        F: File,
+        E: Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync + 'static,
+    {
+        if self.index_root(name).is_some() {
+            return Err(IndexError::DuplicateIndex(name.to_string()));
+        }
+        let mut index = SecondaryIndex {
+            name: name.to_string(),
+            extractor: Box::new(extractor),
+            tree: BTree::create(pager)?,
+        };
+
+        let entries = self.primary.iter(pager).collect::<Result<Vec<_>, _>>()?;
+        for (key, value) in entries {
+            if let Some(index_key) = index.key(pager.page_size(), &key, &value)? {
+                index.tree.insert(pager, &index_key, &[])?;
+            }
+        }
+
+        let root = index.tree.root();
+        self.indexes.push(index);
+        Ok(root)
+    }
+
+    /// Opens an index previously created with [IndexedTree::create_index]. The extractor must
+    /// return the same keys as the one the index was created with.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if an index with the same name is already open, or if the
+    /// root page can't be read or does not contain a valid node.
+    pub fn open_index<F, E>(
+        &mut self,
+        pager: &Pager<F>,
+        name: &str,
+        root: PageId,
+        extractor: E,
+    ) -> Result<(), IndexError>
+    where
+        F: File,
+        E: Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync + 'static,
+    {
+        if self.index_root(name).is_some() {
+            return Err(IndexError::DuplicateIndex(name.to_string()));
+        }
+        self.indexes.push(SecondaryIndex {
+            name: name.to_string(),
+            extractor: Box::new(extractor),
+            tree: BTree::open(pager, root)?,
+        });
+        Ok(())
+    }
+
+    /// Returns the value associated with a primary key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, IndexError> {
+        Ok(self.primary.get(pager, key)?)
+    }
+
+    /// Returns the primary keys of the entries whose secondary key in the index `name` is
+    /// `secondary_key`, in primary key order.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if there is no index with this name or if a page can't be
+    /// read or is corrupted.
+    pub fn lookup<F: File>(
+        &self,
+        pager: &Pager<F>,
+        name: &str,
+        secondary_key: &[u8],
+    ) -> Result<Vec<Vec<u8>>, IndexError> {
+        let index = self.index(name)?;
+        let prefix = encode(secondary_key);
+        let mut keys = Vec::new();
+        for entry in index.tree.range(pager, prefix.as_slice()..) {
+            let (index_key, _) = entry?;
+            match index_key.strip_prefix(prefix.as_slice()) {
+                Some(key) => keys.push(key.to_vec()),
+                None => break,
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Returns the entries of the primary tree whose secondary key in the index `name` is
+    /// `secondary_key`, in primary key order.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - there is no index with this name
+    /// - the index refers to a primary key that is not in the primary tree
+    /// - a page can't be read or is corrupted
+    pub fn lookup_values<F: File>(
+        &self,
+        pager: &Pager<F>,
+        name: &str,
+        secondary_key: &[u8],
+    ) -> Result<Vec<Entry>, IndexError> {
+        self.lookup(pager, name, secondary_key)?
+            .into_iter()
+            .map(|key| match self.primary.get(pager, &key)? {
+                Some(value) => Ok((key, value)),
+                None => Err(IndexError::Inconsistent(name.to_string())),
+            })
+            .collect()
+    }
+
+    /// Inserts a key-value pair in the primary tree, replacing the previous value of the key, and
+    /// updates every index. Returns the previous value, if there was one.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the key or the value is too large for the primary tree
+    /// - the key extracted for an index is too large, in which case nothing is modified
+    /// - a page can't be read, written or allocated
+    pub fn insert<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>, IndexError> {
+        let page_size = pager.page_size();
+        let new_keys = self
+            .indexes
+            .iter()
+            .map(|index| index.key(page_size, key, value))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let previous = self.primary.insert(pager, key, value)?;
+        for (index, new_key) in self.indexes.iter_mut().zip(new_keys) {
+            let old_key = match &previous {
+                Some(previous) => index.key(page_size, key, previous)?,
+                None => None,
+            };
+            if old_key == new_key {
+                continue;
+            }
+            if let Some(old_key) = old_key {
+                index.tree.delete(pager, &old_key)?;
+            }
+            if let Some(new_key) = new_key {
+                index.tree.insert(pager, &new_key, &[])?;
+            }
+        }
+        Ok(previous)
+    }
+
+    /// Removes a key from the primary tree and its entries from every index. Returns the value of
+    /// the key, if it was present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or written.
+    pub fn delete<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, IndexError> {
+        let page_size = pager.page_size();
+        let previous = self.primary.delete(pager, key)?;
+        if let Some(previous) = &previous {
+            for index in &mut self.indexes {
+                if let Some(old_key) = index.key(page_size, key, previous)? {
+                    index.tree.delete(pager, &old_key)?;
+                }
+            }
+        }
+        Ok(previous)
+    }
+
+    fn index(&self, name: &str) -> Result<&SecondaryIndex, IndexError> {
+        self.indexes
+            .iter()
+            .find(|index| index.name == name)
+            .ok_or_else(|| IndexError::UnknownIndex(name.to_string()))
+    }
+}
+
+impl SecondaryIndex {
+    /// Returns the key of the entry of the index for a primary key and its value, or `None` if
+    /// the value is not indexed.
+    fn key(
+        &self,
+        page_size: usize,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>, IndexError> {
+        let Some(secondary_key) = (self.extractor)(value) else {
+            return Ok(None);
+        };
+        let mut index_key = encode(&secondary_key);
+        index_key.extend_from_slice(key);
+
+        let max_key_size = BTree::max_key_size(page_size);
+        if index_key.len() > max_key_size {
+            return Err(IndexError::KeyTooLarge {
+                index: self.name.clone(),
+                key_size: index_key.len(),
+                max_key_size,
+            });
+        }
+        Ok(Some(index_key))
+    }
+}
+
+/// Encodes a secondary key so that no encoded key is a prefix of another and the bytewise order is
+/// kept, as a [KeyEncoder] encodes bytes: each `0x00` byte is escaped as `0x00 0xff` and the key
+/// ends with `0x00 0x01`.
+fn encode(secondary_key: &[u8]) -> Vec<u8> {
+    KeyEncoder::encode(secondary_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_pager() -> Pager<MemoryFile> {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        Pager::create(file, 512).expect("create should not fail")
+    }
+
+    /// Indexes the values by their first byte.
+    fn first_byte(value: &[u8]) -> Option<Vec<u8>> {
+        value.first().map(|&byte| vec![byte])
+    }
+
+    /// Entries sharing a secondary key are all returned, in primary key order.
+    #[test]
+    fn lookup_returns_all_primary_keys() {
+        let mut pager = create_pager();
+        let mut tree = IndexedTree::create(&mut pager).expect("create should not fail");
+        tree.create_index(&mut pager, "first", first_byte)
+            .expect("create_index should not fail");
+        for i in 0..200 {
+            let value = [b'a' + (i % 3) as u8, i as u8];
+            tree.insert(&mut pager, format!("key-{i:03}").as_bytes(), &value)
+                .expect("insert should not fail");
+        }
+
+        let keys = tree
+            .lookup(&pager, "first", b"b")
+            .expect("lookup should not fail");
+
+        let expected: Vec<Vec<u8>> = (0..200)
+            .filter(|i| i % 3 == 1)
+            .map(|i| format!("key-{i:03}").into_bytes())
+            .collect();
+        assert_eq!(keys, expected);
+    }
+
+    /// Replacing or deleting an entry moves or removes its entry in the index.
+    #[test]
+    fn insert_and_delete_update_index() {
+        let mut pager = create_pager();
+        let mut tree = IndexedTree::create(&mut pager).expect("create should not fail");
+        tree.create_index(&mut pager, "first", first_byte)
+            .expect("create_index should not fail");
+        tree.insert(&mut pager, b"k1", b"a1")
+            .expect("insert should not fail");
+        tree.insert(&mut pager, b"k2", b"a2")
+            .expect("insert should not fail");
+
+        tree.insert(&mut pager, b"k1", b"b1")
+            .expect("insert should not fail");
+        tree.delete(&mut pager, b"k2")
+            .expect("delete should not fail");
+
+        let a = tree
+            .lookup(&pager, "first", b"a")
+            .expect("lookup should not fail");
+        let b = tree
+            .lookup_values(&pager, "first", b"b")
+            .expect("lookup_values should not fail");
+        assert!(a.is_empty());
+        assert_eq!(b, vec![(b"k1".to_vec(), b"b1".to_vec())]);
+    }
+
+    /// A secondary key that is a prefix of another one does not match the longer key.
+    #[test]
+    fn lookup_does_not_match_longer_keys() {
+        let mut pager = create_pager();
+        let mut tree = IndexedTree::create(&mut pager).expect("create should not fail");
+        tree.create_index(&mut pager, "value", |value| Some(value.to_vec()))
+            .expect("create_index should not fail");
+        tree.insert(&mut pager, b"k1", b"a")
+            .expect("insert should not fail");
+        tree.insert(&mut pager, b"k2", b"a\0")
+            .expect("insert should not fail");
+        tree.insert(&mut pager, b"k3", b"ab")
+            .expect("insert should not fail");
+
+        let keys = tree
+            .lookup(&pager, "value", b"a")
+            .expect("lookup should not fail");
+
+        assert_eq!(keys, vec![b"k1".to_vec()]);
+    }
+
+    /// An index created after the entries is filled with them, and can be opened again.
+    #[test]
+    fn create_index_fills_existing_entries() {
+        let mut pager = create_pager();
+        let mut tree = IndexedTree::create(&mut pager).expect("create should not fail");
+        tree.insert(&mut pager, b"k1", b"a1")
+            .expect("insert should not fail");
+        tree.insert(&mut pager, b"k2", b"")
+            .expect("insert should not fail");
+        let index_root = tree
+            .create_index(&mut pager, "first", first_byte)
+            .expect("create_index should not fail");
+
+        let mut reopened = IndexedTree::open(&pager, tree.root()).expect("open should not fail");
+        reopened
+            .open_index(&pager, "first", index_root, first_byte)
+            .expect("open_index should not fail");
+        let keys = reopened
+            .lookup(&pager, "first", b"a")
+            .expect("lookup should not fail");
+
+        assert_eq!(keys, vec![b"k1".to_vec()]);
+        assert!(matches!(
+            reopened.lookup(&pager, "other", b"a"),
+            Err(IndexError::UnknownIndex(_))
+        ));
+    }
+
+    /// An insertion whose secondary key is too large is refused without modifying the tree.
+    #[test]
+    fn insert_with_too_large_index_key_fails() {
+        let mut pager = create_pager();
+        let mut tree = IndexedTree::create(&mut pager).expect("create should not fail");
+        tree.create_index(&mut pager, "value", |value| Some(value.to_vec()))
+            .expect("create_index should not fail");
+
+        let result = tree.insert(&mut pager, b"key", &[1; 200]);
+
+        assert!(matches!(result, Err(IndexError::KeyTooLarge { .. })));
+        assert_eq!(tree.get(&pager, b"key").expect("get should not fail"), None);
+    }
+}
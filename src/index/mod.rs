@@ -0,0 +1,2 @@
+mod indexed_tree;
+pub use indexed_tree::{IndexError, IndexedTree, KeyExtractor};
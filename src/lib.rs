@@ -1,2 +1,18 @@
+pub mod art;
+#[cfg(feature = "async")]
+pub mod r#async;
+pub mod btree;
 pub mod common;
+pub mod db;
 pub mod fs;
+pub mod hash;
+pub mod heap;
+pub mod index;
+pub mod lsm;
+pub mod mvcc;
+pub mod pager;
+pub mod partition;
+pub mod record;
+pub mod ttl;
+pub mod vlog;
+pub mod wal;
@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+/// Number of slots in the first chunk of an arena. Each following chunk is twice as large.
+const FIRST_CHUNK_SIZE: usize = 64;
+
+/// Maximum number of chunks of an arena, which bounds the number of values it can hold.
+const MAX_CHUNKS: usize = 32;
+
+/// An append-only collection whose values never move once they are pushed.
+///
+/// The values are stored in chunks that are allocated as needed and never reallocated, so a
+/// reference to a value stays valid while other values are pushed. Values are addressed by the
+/// index returned by [Arena::push] and can be read from any thread without locking.
+pub(super) struct Arena<T> {
+    chunks: [OnceLock<Box<[OnceLock<T>]>>; MAX_CHUNKS],
+    len: AtomicUsize,
+}
+
+impl<T> Arena<T> {
+    /// Creates an empty arena. No chunk is allocated until a value is pushed.
+    pub(super) fn new() -> Self {
+        Arena {
+            chunks: std::array::from_fn(|_| OnceLock::new()),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of values pushed to the arena.
+    pub(super) fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Returns `true` if no value was pushed to the arena.
+    pub(super) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a value to the arena and returns its index.
+    pub(super) fn push(&self, value: T) -> usize {
+        let index = self.len.fetch_add(1, Ordering::AcqRel);
+        let (chunk, offset) = locate(index);
+        let slots = self.chunks[chunk].get_or_init(|| {
+            (0..FIRST_CHUNK_SIZE << chunk)
+                .map(|_| OnceLock::new())
+                .collect()
+        });
+        if slots[offset].set(value).is_err() {
+            unreachable!("each index should only be used once");
+        }
+        index
+    }
+
+    /// Returns the value at `index`, if it was pushed.
+    pub(super) fn get(&self, index: usize) -> Option<&T> {
+        let (chunk, offset) = locate(index);
+        self.chunks.get(chunk)?.get()?[offset].get()
+    }
+}
+
+/// Returns the chunk holding the value at `index` and the offset of the value in the chunk.
+fn locate(index: usize) -> (usize, usize) {
+    let position = index / FIRST_CHUNK_SIZE + 1;
+    let chunk = (usize::BITS - 1 - position.leading_zeros()) as usize;
+    let offset = index - FIRST_CHUNK_SIZE * ((1 << chunk) - 1);
+    (chunk, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The values pushed across several chunks can all be read back at their index.
+    #[test]
+    fn push_then_get_returns_values() {
+        let arena = Arena::new();
+
+        let indexes: Vec<usize> = (0..1000).map(|value| arena.push(value)).collect();
+
+        assert_eq!(arena.len(), 1000);
+        for (value, index) in indexes.into_iter().enumerate() {
+            assert_eq!(arena.get(index), Some(&value));
+        }
+        assert_eq!(arena.get(1000), None);
+    }
+
+    /// Each chunk starts right after the previous one.
+    #[test]
+    fn locate_fills_chunks_in_order() {
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(FIRST_CHUNK_SIZE - 1), (0, FIRST_CHUNK_SIZE - 1));
+        assert_eq!(locate(FIRST_CHUNK_SIZE), (1, 0));
+        assert_eq!(locate(3 * FIRST_CHUNK_SIZE), (2, 0));
+    }
+}
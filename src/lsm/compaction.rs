@@ -0,0 +1,286 @@
+use std::ops::Bound;
+
+use crate::common::KeyRange;
+
+/// Describes a table of an [LsmTree](super::LsmTree) to a [CompactionPolicy].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableInfo {
+    /// The number of data blocks (pages) of the table.
+    pub block_count: usize,
+
+    /// The number of entries of the table, tombstones included.
+    pub entry_count: u64,
+
+    /// The smallest key of the table.
+    pub smallest_key: Vec<u8>,
+
+    /// The largest key of the table.
+    pub largest_key: Vec<u8>,
+}
+
+impl TableInfo {
+    /// Returns `true` if the keys of the table may overlap the range from `smallest` to
+    /// `largest`, inclusive.
+    pub fn overlaps(&self, smallest: &[u8], largest: &[u8]) -> bool {
+        let range = KeyRange::new(
+            Bound::Included(smallest.to_vec()),
+            Bound::Included(largest.to_vec()),
+        );
+        self.key_range().intersects(&range)
+    }
+
+    /// Returns the range of the keys of the table, from its smallest to its largest key.
+    pub fn key_range(&self) -> KeyRange {
+        KeyRange::new(
+            Bound::Included(self.smallest_key.clone()),
+            Bound::Included(self.largest_key.clone()),
+        )
+    }
+}
+
+/// A compaction picked by a [CompactionPolicy]: tables of a level merged with tables of the next
+/// level.
+///
+/// The merged tables are replaced by new tables at the front of the next level. The data of a level
+/// is always more recent than the data of the levels below it, and the tables of a level are
+/// ordered from the most recent to the oldest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compaction {
+    /// The level of the tables to merge into the next level.
+    pub level: usize,
+
+    /// The indexes, in `level`, of the tables to merge. All the tables of the level older than a
+    /// merged table and overlapping it must also be merged, or they would hide its entries once
+    /// they are moved down.
+    pub inputs: Vec<usize>,
+
+    /// The indexes, in the next level, of the tables to merge with the inputs. All the tables of
+    /// the next level overlapping the inputs and more recent than a merged table of that level
+    /// must be included.
+    pub overlapping: Vec<usize>,
+
+    /// The number of data blocks after which a new output table is started, or `None` to write a
+    /// single table.
+    pub max_table_blocks: Option<usize>,
+}
+
+/// Decides when and how the tables of an [LsmTree](super::LsmTree) are merged.
+///
+/// After each flush, the tree asks the policy for a compaction until it returns `None`, or a
+/// [CompactionScheduler](super::CompactionScheduler) does so in the background. Merging more
+/// eagerly keeps fewer tables to search on reads (read and space amplification) at the cost of
+/// rewriting the same entries more often (write amplification).
+pub trait CompactionPolicy: Send + Sync {
+    /// Returns the next compaction to run given the tables of each level, from the most recent
+    /// to the oldest, or `None` if the tree does not need to be compacted. Level `0` holds the
+    /// tables written by the flushes.
+    fn pick(&self, levels: &[Vec<TableInfo>]) -> Option<Compaction>;
+}
+
+/// Keeps each level below level `0` as a single sorted run of non-overlapping tables, each level
+/// about `multiplier` times larger than the previous one.
+///
+/// A key is found in at most one table per level, which keeps reads and space amplification low,
+/// but each entry is rewritten about `multiplier` times per level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeveledCompaction {
+    /// The number of tables in level `0` above which they are merged into level `1`.
+    pub level0_max_tables: usize,
+
+    /// The number of data blocks above which level `1` is compacted into level `2`.
+    pub base_level_blocks: usize,
+
+    /// The size ratio between a level and the previous one.
+    pub multiplier: usize,
+
+    /// The number of data blocks after which a new output table is started.
+    pub max_table_blocks: usize,
+}
+
+impl Default for LeveledCompaction {
+    fn default() -> Self {
+        LeveledCompaction {
+            level0_max_tables: 4,
+            base_level_blocks: 256,
+            multiplier: 10,
+            max_table_blocks: 64,
+        }
+    }
+}
+
+impl LeveledCompaction {
+    /// Returns the number of data blocks above which a level below level `0` is compacted.
+    fn max_level_blocks(&self, level: usize) -> usize {
+        (1..level).fold(self.base_level_blocks, |blocks, _| {
+            blocks.saturating_mul(self.multiplier)
+        })
+    }
+}
+
+impl CompactionPolicy for LeveledCompaction {
+    fn pick(&self, levels: &[Vec<TableInfo>]) -> Option<Compaction> {
+        let level0 = levels.first().map_or(&[][..], Vec::as_slice);
+        let (level, inputs) = if level0.len() > self.level0_max_tables {
+            (0, (0..level0.len()).collect::<Vec<_>>())
+        } else {
+            let level = (1..levels.len()).find(|&level| {
+                let blocks: usize = levels[level].iter().map(|table| table.block_count).sum();
+                blocks > self.max_level_blocks(level)
+            })?;
+            // The tables of a level do not overlap: the largest one is moved down.
+            let largest =
+                (0..levels[level].len()).max_by_key(|&index| levels[level][index].block_count)?;
+            (level, vec![largest])
+        };
+
+        let tables = &levels[level];
+        let smallest = inputs
+            .iter()
+            .map(|&index| &tables[index].smallest_key)
+            .min()?;
+        let largest = inputs
+            .iter()
+            .map(|&index| &tables[index].largest_key)
+            .max()?;
+        let overlapping = levels
+            .get(level + 1)
+            .map(|next| {
+                (0..next.len())
+                    .filter(|&index| next[index].overlaps(smallest, largest))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(Compaction {
+            level,
+            inputs,
+            overlapping,
+            max_table_blocks: Some(self.max_table_blocks),
+        })
+    }
+}
+
+/// Lets up to `max_runs` overlapping tables (runs) accumulate in each level, then merges them all
+/// into a single run of the next level.
+///
+/// Each entry is rewritten once per level, which keeps write amplification low, but a key may be
+/// found in several tables per level and older values take longer to be dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeTieredCompaction {
+    /// The number of runs in a level at which they are merged into the next level.
+    pub max_runs: usize,
+}
+
+impl Default for SizeTieredCompaction {
+    fn default() -> Self {
+        SizeTieredCompaction { max_runs: 4 }
+    }
+}
+
+impl CompactionPolicy for SizeTieredCompaction {
+    fn pick(&self, levels: &[Vec<TableInfo>]) -> Option<Compaction> {
+        let level = (0..levels.len()).find(|&level| levels[level].len() >= self.max_runs.max(2))?;
+        Some(Compaction {
+            level,
+            inputs: (0..levels[level].len()).collect(),
+            overlapping: Vec::new(),
+            max_table_blocks: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(block_count: usize, smallest: &[u8], largest: &[u8]) -> TableInfo {
+        TableInfo {
+            block_count,
+            entry_count: block_count as u64 * 10,
+            smallest_key: smallest.to_vec(),
+            largest_key: largest.to_vec(),
+        }
+    }
+
+    /// Leveled compaction merges all of level 0 with the overlapping tables of level 1.
+    #[test]
+    fn leveled_picks_level0_with_overlapping_tables() {
+        let policy = LeveledCompaction {
+            level0_max_tables: 2,
+            ..LeveledCompaction::default()
+        };
+        let levels = vec![
+            vec![
+                table(1, b"c", b"d"),
+                table(1, b"b", b"e"),
+                table(1, b"d", b"f"),
+            ],
+            vec![
+                table(4, b"a", b"a"),
+                table(4, b"b", b"c"),
+                table(4, b"x", b"z"),
+            ],
+        ];
+
+        let compaction = policy.pick(&levels);
+
+        assert_eq!(
+            compaction,
+            Some(Compaction {
+                level: 0,
+                inputs: vec![0, 1, 2],
+                overlapping: vec![1],
+                max_table_blocks: Some(policy.max_table_blocks),
+            })
+        );
+    }
+
+    /// Leveled compaction moves the largest table of a level that grew past its size down.
+    #[test]
+    fn leveled_picks_oversized_level() {
+        let policy = LeveledCompaction {
+            base_level_blocks: 10,
+            ..LeveledCompaction::default()
+        };
+        let levels = vec![
+            vec![],
+            vec![table(4, b"a", b"b"), table(8, b"c", b"d")],
+            vec![table(20, b"a", b"c"), table(20, b"d", b"e")],
+        ];
+
+        let compaction = policy.pick(&levels).expect("a compaction should be picked");
+
+        assert_eq!(compaction.level, 1);
+        assert_eq!(compaction.inputs, vec![1]);
+        assert_eq!(compaction.overlapping, vec![0, 1]);
+    }
+
+    /// Leveled compaction does nothing while every level is within its size.
+    #[test]
+    fn leveled_small_levels_picks_nothing() {
+        let levels = vec![vec![table(1, b"a", b"b")], vec![table(4, b"a", b"z")]];
+
+        assert_eq!(LeveledCompaction::default().pick(&levels), None);
+    }
+
+    /// Size-tiered compaction merges the runs of the first full level into a single run.
+    #[test]
+    fn size_tiered_picks_full_level() {
+        let policy = SizeTieredCompaction { max_runs: 2 };
+        let levels = vec![
+            vec![table(1, b"a", b"b")],
+            vec![table(4, b"a", b"z"), table(4, b"a", b"z")],
+        ];
+
+        let compaction = policy.pick(&levels);
+
+        assert_eq!(
+            compaction,
+            Some(Compaction {
+                level: 1,
+                inputs: vec![0, 1],
+                overlapping: vec![],
+                max_table_blocks: None,
+            })
+        );
+    }
+}
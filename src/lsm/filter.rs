@@ -0,0 +1,247 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::common::hash64;
+
+/// Largest number of hash functions of a filter.
+const MAX_HASH_COUNT: u32 = 30;
+
+/// Smallest number of bits of a filter, so filters of tables with few keys stay useful.
+const MIN_BIT_COUNT: usize = 64;
+
+/// Size of the header of an encoded filter: the number of hash functions and the prefix length.
+const HEADER_SIZE: usize = 3;
+
+/// A bloom filter over the keys of a [Table](super::table::Table).
+///
+/// The filter answers whether a key may be in the table without reading any data block. It never
+/// misses a key of the table, but may claim that an absent key is present (a false positive). With
+/// `b` bits per key, about `b * ln(2)` hash functions are used and the false positive rate is about
+/// `0.6185^b`: 1% with 10 bits per key.
+///
+/// A filter with a prefix length also holds the first `prefix_length` bytes of the keys at least
+/// that long, so it answers whether a key with a given prefix may be in the table.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct BloomFilter {
+    hash_count: u32,
+    prefix_length: usize,
+    bits: Vec<u8>,
+}
+
+impl BloomFilter {
+    /// Builds a filter from the hashes of the keys, as returned by [hash], using `bits_per_key`
+    /// bits for each key. If `prefix_length` is not `0`, the hashes of the prefixes of that length
+    /// of the keys must be included.
+    pub(super) fn new(key_hashes: &[u64], bits_per_key: usize, prefix_length: usize) -> Self {
+        let hash_count = ((bits_per_key as f64 * std::f64::consts::LN_2).round() as u32)
+            .clamp(1, MAX_HASH_COUNT);
+        let bit_count = (key_hashes.len() * bits_per_key).max(MIN_BIT_COUNT);
+        let mut filter = BloomFilter {
+            hash_count,
+            prefix_length,
+            bits: vec![0; bit_count.div_ceil(8)],
+        };
+        for &hash in key_hashes {
+            for bit in filter.bit_positions(hash) {
+                filter.bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        filter
+    }
+
+    /// Returns `false` if the key is certainly not in the filter, and `true` if it may be.
+    pub(super) fn may_contain(&self, key: &[u8]) -> bool {
+        self.bit_positions(hash(key))
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Returns `false` if no key starting with `prefix` is certainly in the filter, and `true` if
+    /// one may be. A filter without a prefix length, or a prefix shorter than it, can't rule any
+    /// key out.
+    pub(super) fn may_contain_prefix(&self, prefix: &[u8]) -> bool {
+        if self.prefix_length == 0 || prefix.len() < self.prefix_length {
+            return true;
+        }
+        self.may_contain(&prefix[..self.prefix_length])
+    }
+
+    /// Encodes the filter: the number of hash functions and the prefix length followed by the
+    /// bits.
+    pub(super) fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.bits.len());
+        bytes.push(self.hash_count as u8);
+        bytes.extend_from_slice(&(self.prefix_length as u16).to_le_bytes());
+        bytes.extend_from_slice(&self.bits);
+        bytes
+    }
+
+    /// Decodes a filter encoded by [BloomFilter::encode]. Returns `None` if the bytes are not a
+    /// valid filter.
+    pub(super) fn decode(bytes: &[u8]) -> Option<Self> {
+        let (&hash_count, bytes) = bytes.split_first()?;
+        let (prefix_length, bits) = bytes.split_first_chunk::<2>()?;
+        let prefix_length = usize::from(u16::from_le_bytes(*prefix_length));
+        if hash_count == 0 || u32::from(hash_count) > MAX_HASH_COUNT || bits.is_empty() {
+            return None;
+        }
+        Some(BloomFilter {
+            hash_count: u32::from(hash_count),
+            prefix_length,
+            bits: bits.to_vec(),
+        })
+    }
+
+    /// Returns the bits set for a key hash. The positions are derived from the two halves of
+    /// the hash (double hashing), so a single hash of the key is computed.
+    fn bit_positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let bit_count = self.bits.len() as u64 * 8;
+        let delta = (hash >> 32) | 1;
+        (0..u64::from(self.hash_count))
+            .map(move |index| (hash.wrapping_add(index.wrapping_mul(delta)) % bit_count) as usize)
+    }
+}
+
+/// Returns the hash of a key used by the filters.
+pub(super) fn hash(key: &[u8]) -> u64 {
+    hash64(key)
+}
+
+/// Counts how the filters of an [LsmTree](super::LsmTree) were used by point lookups.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilterStats {
+    /// The number of times a filter was consulted.
+    pub checks: u64,
+
+    /// The number of data block reads avoided because a filter ruled the key out.
+    pub skipped_reads: u64,
+
+    /// The number of data blocks read because a filter claimed the key may be present, but that
+    /// did not contain it.
+    pub false_positives: u64,
+}
+
+impl FilterStats {
+    /// Returns the fraction of the absent keys that the filters did not rule out, or `0.0` if no
+    /// absent key was looked up.
+    pub fn false_positive_rate(&self) -> f64 {
+        let absent = self.skipped_reads + self.false_positives;
+        if absent == 0 {
+            return 0.0;
+        }
+        self.false_positives as f64 / absent as f64
+    }
+}
+
+/// The counters behind [FilterStats], updated by lookups that only borrow the tree.
+#[derive(Debug, Default)]
+pub(super) struct FilterCounters {
+    checks: AtomicU64,
+    skipped_reads: AtomicU64,
+    false_positives: AtomicU64,
+}
+
+impl FilterCounters {
+    /// Counts a filter check. `may_contain` is the answer of the filter, and `found` whether the
+    /// table contained the key when it was read.
+    pub(super) fn record(&self, may_contain: bool, found: bool) {
+        self.checks.fetch_add(1, Ordering::Relaxed);
+        if !may_contain {
+            self.skipped_reads.fetch_add(1, Ordering::Relaxed);
+        } else if !found {
+            self.false_positives.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the current value of the counters.
+    pub(super) fn stats(&self) -> FilterStats {
+        FilterStats {
+            checks: self.checks.load(Ordering::Relaxed),
+            skipped_reads: self.skipped_reads.load(Ordering::Relaxed),
+            false_positives: self.false_positives.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:06}").into_bytes()
+    }
+
+    fn build(count: usize, bits_per_key: usize) -> BloomFilter {
+        let hashes: Vec<u64> = (0..count).map(|index| hash(&key(index))).collect();
+        BloomFilter::new(&hashes, bits_per_key, 0)
+    }
+
+    /// A filter never rules out a key it was built with.
+    #[test]
+    fn may_contain_added_keys_returns_true() {
+        let filter = build(1000, 10);
+
+        assert!((0..1000).all(|index| filter.may_contain(&key(index))));
+    }
+
+    /// The false positive rate stays close to the rate expected for the bits per key.
+    #[test]
+    fn may_contain_absent_keys_has_low_false_positive_rate() {
+        let filter = build(10_000, 10);
+
+        let false_positives = (10_000..20_000)
+            .filter(|&index| filter.may_contain(&key(index)))
+            .count();
+
+        assert!(false_positives < 200, "{false_positives} false positives");
+    }
+
+    /// A filter is decoded back from its encoding.
+    #[test]
+    fn decode_encoded_filter_returns_filter() {
+        let filter = build(100, 8);
+
+        let decoded = BloomFilter::decode(&filter.encode());
+
+        assert_eq!(decoded, Some(filter));
+        assert_eq!(BloomFilter::decode(&[0, 0, 0, 2]), None);
+        assert_eq!(BloomFilter::decode(&[1, 0]), None);
+    }
+
+    /// A filter with a prefix length never rules out a prefix of its keys, and rules out most
+    /// absent prefixes.
+    #[test]
+    fn may_contain_prefix_checks_prefixes() {
+        let hashes: Vec<u64> = (0..1000)
+            .flat_map(|index| {
+                let key = key(index);
+                [hash(&key), hash(&key[..8])]
+            })
+            .collect();
+        let filter = BloomFilter::new(&hashes, 10, 8);
+
+        let absent = (0..1000)
+            .filter(|&index| filter.may_contain_prefix(format!("other-{index:02}").as_bytes()))
+            .count();
+
+        assert!((0..1000).all(|index| filter.may_contain_prefix(&key(index)[..9])));
+        assert!(filter.may_contain_prefix(b"short"));
+        assert!(absent < 50, "{absent} false positives");
+    }
+
+    /// The false positive rate only counts the lookups of absent keys.
+    #[test]
+    fn false_positive_rate_counts_absent_keys() {
+        let counters = FilterCounters::default();
+
+        counters.record(true, true);
+        counters.record(false, false);
+        counters.record(false, false);
+        counters.record(false, false);
+        counters.record(true, false);
+
+        let stats = counters.stats();
+        assert_eq!(stats.checks, 5);
+        assert_eq!(stats.skipped_reads, 3);
+        assert_eq!(stats.false_positives, 1);
+        assert_eq!(stats.false_positive_rate(), 0.25);
+    }
+}
@@ -0,0 +1,402 @@
+use std::cmp::Ordering as KeyOrdering;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use crate::common::envelope::{Envelope, Versioned};
+
+use super::arena::Arena;
+use super::table::Entry;
+
+/// Number of bytes counted for each entry of the memtable in addition to its key and value.
+const ENTRY_OVERHEAD: usize = 16;
+
+/// Maximum number of levels of the skiplist.
+const MAX_HEIGHT: usize = 12;
+
+/// Inverse of the probability that a node reaches the next level of the skiplist.
+const BRANCHING: u32 = 4;
+
+/// A version of a key in the skiplist.
+struct Node {
+    key: Vec<u8>,
+    version: Versioned,
+    /// The next node at each level of the node, as an index in the arena plus one. `0` marks the
+    /// end of the level.
+    next: Box<[AtomicU32]>,
+}
+
+/// Holds the most recent writes of an [LsmTree](super::LsmTree) in memory, in key order.
+///
+/// The memtable is a skiplist whose nodes are allocated in an [Arena] and never move or change
+/// once they are linked. Writers are serialized by a lock, but readers never lock: a node is
+/// fully written before it is published with a release store, so a reader either sees it
+/// complete or not at all.
+///
+/// A write never modifies an existing node. Each write adds a new version of its key, numbered
+/// with an increasing sequence number that goes on from the memtables flushed before, and the
+/// versions of a key are ordered from the most recent to the oldest, so the first version found is
+/// the current one. A deletion is stored as a [Envelope::Tombstone] so it hides the older values
+/// of the key stored in the tables. A merge operand written by the tree is already combined with
+/// the previous version of its key in the memtable, if there is one.
+pub(super) struct Memtable {
+    arena: Arena<Node>,
+    head: [AtomicU32; MAX_HEIGHT],
+    height: AtomicUsize,
+    size: AtomicUsize,
+    last_sequence: AtomicU64,
+    writer: Mutex<()>,
+}
+
+impl Memtable {
+    /// Creates an empty memtable.
+    pub(super) fn new() -> Self {
+        Memtable::with_last_sequence(0)
+    }
+
+    /// Creates an empty memtable whose first write is numbered after `last_sequence`.
+    pub(super) fn with_last_sequence(last_sequence: u64) -> Self {
+        Memtable {
+            arena: Arena::new(),
+            head: std::array::from_fn(|_| AtomicU32::new(0)),
+            height: AtomicUsize::new(1),
+            size: AtomicUsize::new(0),
+            last_sequence: AtomicU64::new(last_sequence),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Returns the sequence number of the last write.
+    pub(super) fn last_sequence(&self) -> u64 {
+        self.last_sequence.load(Ordering::Acquire)
+    }
+
+    /// Returns the approximate number of bytes used by the entries of the memtable, including
+    /// the versions hidden by more recent writes.
+    pub(super) fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the memtable does not hold any entry.
+    pub(super) fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+
+    /// Returns the current version of a key, or `None` if the memtable does not know the key.
+    pub(super) fn get(&self, key: &[u8]) -> Option<&Versioned> {
+        let node = self.node(self.seek(key, u64::MAX, None))?;
+        (node.key == key).then_some(&node.version)
+    }
+
+    /// Adds a new version of a key, and returns its sequence number. Concurrent writers wait for
+    /// each other, but readers are never blocked.
+    pub(super) fn put(&self, key: &[u8], envelope: Envelope) -> u64 {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let sequence = self.last_sequence.load(Ordering::Relaxed) + 1;
+
+        let mut previous = [None; MAX_HEIGHT];
+        self.seek(key, sequence, Some(&mut previous));
+        let height = random_height();
+        let size = ENTRY_OVERHEAD + key.len() + envelope.payload().len();
+        let node = Node {
+            key: key.to_vec(),
+            version: Versioned::new(sequence, envelope),
+            next: (0..height)
+                .map(|level| {
+                    AtomicU32::new(self.link(previous[level], level).load(Ordering::Acquire))
+                })
+                .collect(),
+        };
+        let index = self.arena.push(node) as u32 + 1;
+
+        // The node is linked bottom-up, so a reader finding it at a level can follow it down.
+        if height > self.height.load(Ordering::Relaxed) {
+            self.height.store(height, Ordering::Release);
+        }
+        for (level, previous) in previous.iter().enumerate().take(height) {
+            self.link(*previous, level).store(index, Ordering::Release);
+        }
+        self.size.fetch_add(size, Ordering::Relaxed);
+        self.last_sequence.store(sequence, Ordering::Release);
+        sequence
+    }
+
+    /// Returns an iterator over the current entries whose keys are within the bounds, in key
+    /// order.
+    pub(super) fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> MemtableRange<'_> {
+        let next = match start {
+            Bound::Included(key) | Bound::Excluded(key) => self.seek(key, u64::MAX, None),
+            Bound::Unbounded => self.head[0].load(Ordering::Acquire),
+        };
+        MemtableRange {
+            memtable: self,
+            next,
+            excluded: match start {
+                Bound::Excluded(key) => Some(key.to_vec()),
+                _ => None,
+            },
+            end: end.map(<[u8]>::to_vec),
+        }
+    }
+
+    /// Removes all the entries of the memtable. The sequence numbers go on from the last write.
+    pub(super) fn clear(&mut self) {
+        *self = Memtable::with_last_sequence(self.last_sequence());
+    }
+
+    /// Returns the first node that is not ordered before the version `sequence` of `key`, as an
+    /// index in the arena plus one, or `0` if there is none. If `previous` is given, it receives
+    /// the last node before that position at each level (`None` for the head).
+    fn seek(
+        &self,
+        key: &[u8],
+        sequence: u64,
+        mut previous: Option<&mut [Option<u32>; MAX_HEIGHT]>,
+    ) -> u32 {
+        let mut current = None;
+        let mut level = self.height.load(Ordering::Acquire) - 1;
+        loop {
+            let next = self.link(current, level).load(Ordering::Acquire);
+            let before = self
+                .node(next)
+                .is_some_and(|node| match node.key.as_slice().cmp(key) {
+                    KeyOrdering::Less => true,
+                    KeyOrdering::Equal => node.version.sequence > sequence,
+                    KeyOrdering::Greater => false,
+                });
+            if before {
+                current = Some(next);
+                continue;
+            }
+            if let Some(previous) = previous.as_deref_mut() {
+                previous[level] = current;
+            }
+            if level == 0 {
+                return next;
+            }
+            level -= 1;
+        }
+    }
+
+    /// Returns the link to the next node at a level, from a node or from the head if `node` is
+    /// `None`.
+    fn link(&self, node: Option<u32>, level: usize) -> &AtomicU32 {
+        match node.and_then(|index| self.node(index)) {
+            Some(node) => &node.next[level],
+            None => &self.head[level],
+        }
+    }
+
+    /// Returns the node at an index in the arena plus one, or `None` for `0`.
+    fn node(&self, index: u32) -> Option<&Node> {
+        let index = (index as usize).checked_sub(1)?;
+        Some(
+            self.arena
+                .get(index)
+                .expect("a linked node should be in the arena"),
+        )
+    }
+}
+
+impl Default for Memtable {
+    fn default() -> Self {
+        Memtable::new()
+    }
+}
+
+/// An iterator over the current entries of a [Memtable], in key order.
+pub(super) struct MemtableRange<'a> {
+    memtable: &'a Memtable,
+    next: u32,
+    excluded: Option<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+impl Iterator for MemtableRange<'_> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.memtable.node(self.next)?;
+
+            // Only the first version of a key is current: the older ones are skipped.
+            self.next = node.next[0].load(Ordering::Acquire);
+            while let Some(older) = self.memtable.node(self.next) {
+                if older.key != node.key {
+                    break;
+                }
+                self.next = older.next[0].load(Ordering::Acquire);
+            }
+
+            if self.excluded.take_if(|key| *key == node.key).is_some() {
+                continue;
+            }
+            let before_end = match &self.end {
+                Bound::Included(end) => node.key <= *end,
+                Bound::Excluded(end) => node.key < *end,
+                Bound::Unbounded => true,
+            };
+            if !before_end {
+                self.next = 0;
+                return None;
+            }
+            return Some((node.key.clone(), node.version.clone()));
+        }
+    }
+}
+
+/// Returns a random height for a new node: each level is reached with a probability of
+/// `1 / BRANCHING`.
+fn random_height() -> usize {
+    let mut height = 1;
+    while height < MAX_HEIGHT && rand::random::<u32>().is_multiple_of(BRANCHING) {
+        height += 1;
+    }
+    height
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::seq::SliceRandom;
+
+    use super::*;
+
+    fn value(bytes: &[u8]) -> Envelope {
+        Envelope::Value(bytes.to_vec())
+    }
+
+    /// Returns the envelope of the current version of a key.
+    fn get<'a>(memtable: &'a Memtable, key: &[u8]) -> Option<&'a Envelope> {
+        memtable.get(key).map(|version| &version.envelope)
+    }
+
+    /// A deleted key is remembered as a tombstone.
+    #[test]
+    fn put_none_stores_tombstone() {
+        let memtable = Memtable::new();
+        memtable.put(b"key", value(b"value"));
+
+        memtable.put(b"key", Envelope::Tombstone);
+
+        assert_eq!(get(&memtable, b"key"), Some(&Envelope::Tombstone));
+        assert_eq!(get(&memtable, b"missing"), None);
+    }
+
+    /// Each write is numbered after the previous one, and the numbers go on after the memtable is
+    /// cleared or from the last sequence number it is created with.
+    #[test]
+    fn put_numbers_versions_in_order() {
+        let mut memtable = Memtable::with_last_sequence(41);
+
+        let first = memtable.put(b"key", value(b"value"));
+        let second = memtable.put(b"key", Envelope::Tombstone);
+        memtable.clear();
+        let third = memtable.put(b"other", value(b""));
+
+        assert_eq!((first, second, third), (42, 43, 44));
+        assert_eq!(memtable.last_sequence(), 44);
+        assert_eq!(
+            memtable.get(b"other"),
+            Some(&Versioned::new(44, value(b"")))
+        );
+    }
+
+    /// The last value written for a key hides the previous ones.
+    #[test]
+    fn put_existing_key_replaces_value() {
+        let memtable = Memtable::new();
+
+        for index in 0..100u32 {
+            memtable.put(b"key", value(&index.to_le_bytes()));
+        }
+
+        assert_eq!(get(&memtable, b"key"), Some(&value(&99u32.to_le_bytes())));
+        assert_eq!(
+            memtable.range(Bound::Unbounded, Bound::Unbounded).count(),
+            1
+        );
+    }
+
+    /// The size counts every version written and is reset when the memtable is cleared.
+    #[test]
+    fn size_tracks_entries() {
+        let mut memtable = Memtable::new();
+
+        memtable.put(b"key", value(b"value"));
+        memtable.put(b"key", value(b"v"));
+        let size = memtable.size();
+        memtable.clear();
+
+        assert_eq!(size, 2 * ENTRY_OVERHEAD + 12);
+        assert_eq!(memtable.size(), 0);
+        assert!(memtable.is_empty());
+    }
+
+    /// A range returns the entries within its bounds, in key order.
+    #[test]
+    fn range_returns_entries_within_bounds() {
+        let memtable = Memtable::new();
+        for key in [b"d", b"b", b"a", b"c", b"a"] {
+            memtable.put(key, value(b""));
+        }
+
+        let keys: Vec<Vec<u8>> = memtable
+            .range(Bound::Excluded(b"a"), Bound::Included(b"c"))
+            .map(|(key, _)| key)
+            .collect();
+
+        assert_eq!(keys, vec![b"b".to_vec(), b"c".to_vec()]);
+    }
+
+    /// Many keys inserted in random order are iterated in key order.
+    #[test]
+    fn put_random_keys_iterates_in_order() {
+        let memtable = Memtable::new();
+        let mut keys: Vec<u32> = (0..5000).collect();
+        keys.shuffle(&mut rand::thread_rng());
+
+        for key in &keys {
+            memtable.put(&key.to_be_bytes(), value(b""));
+        }
+
+        let iterated: Vec<Vec<u8>> = memtable
+            .range(Bound::Unbounded, Bound::Unbounded)
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort();
+        assert_eq!(
+            iterated,
+            keys.iter()
+                .map(|key| key.to_be_bytes().to_vec())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Readers find every key written before they look while a writer keeps inserting.
+    #[test]
+    fn get_during_concurrent_put_finds_written_keys() {
+        let memtable = Memtable::new();
+        let written = AtomicU32::new(0);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for key in 0..5000u32 {
+                    memtable.put(&key.to_be_bytes(), value(&key.to_le_bytes()));
+                    written.store(key + 1, Ordering::Release);
+                }
+            });
+            for _ in 0..2 {
+                scope.spawn(|| loop {
+                    let count = written.load(Ordering::Acquire);
+                    for key in (0..count).step_by(97) {
+                        let envelope = get(&memtable, &key.to_be_bytes());
+                        assert_eq!(envelope, Some(&value(&key.to_le_bytes())));
+                    }
+                    if count == 5000 {
+                        break;
+                    }
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,146 @@
+use super::LsmError;
+use crate::common::envelope::{Envelope, Versioned};
+
+/// Combines the operands written with [LsmTree::merge](super::LsmTree::merge) with the value of
+/// their key.
+///
+/// A merge only records its operand. The operands are applied when the key is read or when the
+/// tables holding them are compacted, which turns a read-modify-write, like incrementing a
+/// counter, into a single cheap write.
+///
+/// The tree combines consecutive operands together before the value they apply to is known, by
+/// calling [MergeOperator::merge] with the older operand as the existing value. The operator must
+/// therefore be associative: applying `a` then `b` to a value must give the same result as
+/// applying the result of `merge(key, Some(a), b)` to it. An operand applied to a missing or
+/// deleted key receives `None` as the existing value.
+pub trait MergeOperator: Send + Sync {
+    /// Returns the value of `key` after `operand` is applied to `existing`, its previous value.
+    fn merge(&self, key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Vec<u8>;
+}
+
+/// Adds 64-bit unsigned counters stored in little-endian. The addition wraps around on overflow.
+///
+/// An operand or a value that is not 8 bytes long counts as `0`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct U64AddOperator;
+
+impl MergeOperator for U64AddOperator {
+    fn merge(&self, _key: &[u8], existing: Option<&[u8]>, operand: &[u8]) -> Vec<u8> {
+        let decode = |bytes: &[u8]| bytes.try_into().map_or(0, u64::from_le_bytes);
+        let existing = existing.map_or(0, decode);
+        existing
+            .wrapping_add(decode(operand))
+            .to_le_bytes()
+            .to_vec()
+    }
+}
+
+/// Applies an envelope to the older envelope of the same key. A value or a tombstone hides the
+/// older envelope, while merge operands are applied to it.
+///
+/// # Errors
+///
+/// This function will return an error if operands must be applied but no operator is given.
+pub(super) fn combine(
+    operator: Option<&dyn MergeOperator>,
+    key: &[u8],
+    newer: Envelope,
+    older: &Envelope,
+) -> Result<Envelope, LsmError> {
+    let Envelope::Merge(operand) = newer else {
+        return Ok(newer);
+    };
+    let operator = operator.ok_or(LsmError::MissingMergeOperator)?;
+    Ok(match older {
+        Envelope::Value(value) => Envelope::Value(operator.merge(key, Some(value), &operand)),
+        Envelope::Tombstone => Envelope::Value(operator.merge(key, None, &operand)),
+        Envelope::Merge(older) => Envelope::Merge(operator.merge(key, Some(older), &operand)),
+    })
+}
+
+/// Applies a version to the older version of the same key, as [combine], keeping the sequence
+/// number of the newer version.
+///
+/// # Errors
+///
+/// This function will return an error if operands must be applied but no operator is given.
+pub(super) fn combine_versions(
+    operator: Option<&dyn MergeOperator>,
+    key: &[u8],
+    newer: Versioned,
+    older: &Envelope,
+) -> Result<Versioned, LsmError> {
+    let envelope = combine(operator, key, newer.envelope, older)?;
+    Ok(Versioned::new(newer.sequence, envelope))
+}
+
+/// Returns the value of a key given its most recent envelope, once no older envelope is left:
+/// operands are applied to a missing value.
+///
+/// # Errors
+///
+/// This function will return an error if operands must be applied but no operator is given.
+pub(super) fn resolve(
+    operator: Option<&dyn MergeOperator>,
+    key: &[u8],
+    envelope: Envelope,
+) -> Result<Option<Vec<u8>>, LsmError> {
+    match combine(operator, key, envelope, &Envelope::Tombstone)? {
+        Envelope::Value(value) => Ok(Some(value)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter(value: u64) -> Vec<u8> {
+        value.to_le_bytes().to_vec()
+    }
+
+    /// Operands combined together before being applied give the same value as operands applied
+    /// one by one.
+    #[test]
+    fn combine_operands_is_associative() {
+        let operator: &dyn MergeOperator = &U64AddOperator;
+        let base = Envelope::Value(counter(10));
+
+        let one_by_one = combine(Some(operator), b"key", Envelope::Merge(counter(2)), &base)
+            .and_then(|value| combine(Some(operator), b"key", Envelope::Merge(counter(3)), &value))
+            .expect("combine should not fail");
+        let combined = combine(
+            Some(operator),
+            b"key",
+            Envelope::Merge(counter(3)),
+            &Envelope::Merge(counter(2)),
+        )
+        .and_then(|operand| combine(Some(operator), b"key", operand, &base))
+        .expect("combine should not fail");
+
+        assert_eq!(one_by_one, Envelope::Value(counter(15)));
+        assert_eq!(combined, one_by_one);
+    }
+
+    /// A value or a tombstone hides the older envelope, and operands without an older value are
+    /// applied to a missing value.
+    #[test]
+    fn resolve_applies_operands_to_missing_value() {
+        let operator: &dyn MergeOperator = &U64AddOperator;
+
+        let hidden = combine(
+            None,
+            b"key",
+            Envelope::Tombstone,
+            &Envelope::Value(counter(1)),
+        )
+        .expect("combine should not fail");
+        let resolved = resolve(Some(operator), b"key", Envelope::Merge(counter(4)))
+            .expect("resolve should not fail");
+        let missing = resolve(None, b"key", Envelope::Merge(counter(4)));
+
+        assert_eq!(hidden, Envelope::Tombstone);
+        assert_eq!(resolved, Some(counter(4)));
+        assert!(matches!(missing, Err(LsmError::MissingMergeOperator)));
+    }
+}
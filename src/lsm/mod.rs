@@ -0,0 +1,20 @@
+mod arena;
+mod compaction;
+mod filter;
+mod memtable;
+mod merge;
+mod range;
+mod range_tombstone;
+mod scheduler;
+mod stats;
+mod table;
+mod tree;
+pub use compaction::{
+    Compaction, CompactionPolicy, LeveledCompaction, SizeTieredCompaction, TableInfo,
+};
+pub use filter::FilterStats;
+pub use merge::{MergeOperator, U64AddOperator};
+pub use range::Range;
+pub use scheduler::CompactionScheduler;
+pub use stats::LevelStats;
+pub use tree::{LsmError, LsmTree};
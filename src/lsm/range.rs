@@ -0,0 +1,167 @@
+use std::ops::Bound;
+
+use super::merge::{self, MergeOperator};
+use super::range_tombstone::{self, RangeTombstone};
+use crate::common::envelope::{Envelope, Versioned};
+
+use super::table::Entry;
+use super::LsmError;
+
+/// A sorted source of entries merged by a [Range].
+pub(super) type Source<'a> = Box<dyn Iterator<Item = Result<Entry, LsmError>> + 'a>;
+
+/// An iterator over the entries of an [LsmTree](super::LsmTree) within a range of keys, in key
+/// order.
+///
+/// The memtable and the tables are merged as the iteration progresses. When a key is found in
+/// several of them, the most recent entry hides the others, unless it is a merge operand applied
+/// to them, and deleted keys are skipped, including the keys within a range tombstone more recent
+/// than their entry. After an error is returned, the iterator does not return
+/// any more items.
+pub struct Range<'a> {
+    sources: Vec<Source<'a>>,
+    range_tombstones: Vec<&'a [RangeTombstone]>,
+    operator: Option<&'a dyn MergeOperator>,
+    heads: Vec<Option<Entry>>,
+    end: Bound<Vec<u8>>,
+    started: bool,
+    finished: bool,
+}
+
+impl<'a> Range<'a> {
+    /// Creates an iterator merging sources ordered from the most recent to the oldest, up to
+    /// `end`. Each source must already start at the beginning of the range and comes with its
+    /// normalized range tombstones, which hide the entries of the older sources. The merge
+    /// operands are applied with `operator`.
+    pub(super) fn new(
+        sources: Vec<Source<'a>>,
+        range_tombstones: Vec<&'a [RangeTombstone]>,
+        end: Bound<Vec<u8>>,
+        operator: Option<&'a dyn MergeOperator>,
+    ) -> Self {
+        debug_assert_eq!(sources.len(), range_tombstones.len());
+        let heads = sources.iter().map(|_| None).collect();
+        Range {
+            sources,
+            range_tombstones,
+            operator,
+            heads,
+            end,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Returns the next entry of the merged sources, including tombstones. A merge operand is
+    /// applied to the entries of the same key in older sources and is only returned as such if
+    /// none of them holds a value or a tombstone. A key hidden by a range tombstone more recent
+    /// than all its entries is skipped, and a range tombstone older than the entry of a key acts
+    /// as a tombstone of the key.
+    pub(super) fn next_entry(&mut self) -> Result<Option<Entry>, LsmError> {
+        if !self.started {
+            self.started = true;
+            for index in 0..self.sources.len() {
+                self.advance(index)?;
+            }
+        }
+
+        loop {
+            // The first source holding the smallest key is the most recent one.
+            let Some(newest) = (0..self.heads.len())
+                .filter(|&index| self.heads[index].is_some())
+                .min_by(|&a, &b| self.key(a).cmp(self.key(b)))
+            else {
+                return Ok(None);
+            };
+            let key = self.key(newest).to_vec();
+            let before_end = match &self.end {
+                Bound::Included(end) => key <= *end,
+                Bound::Excluded(end) => key < *end,
+                Bound::Unbounded => true,
+            };
+            if !before_end {
+                return Ok(None);
+            }
+
+            let mut masked = self.range_tombstones[..newest]
+                .iter()
+                .any(|tombstones| range_tombstone::covers(tombstones, &key));
+            let mut version: Option<Versioned> = None;
+            for index in newest..self.heads.len() {
+                if self.heads[index]
+                    .as_ref()
+                    .is_some_and(|(older_key, _)| *older_key == key)
+                {
+                    let (_, older) = self.heads[index].take().expect("the head should exist");
+                    self.advance(index)?;
+                    if !masked {
+                        version = Some(match version {
+                            Some(newer) => merge::combine_versions(
+                                self.operator,
+                                &key,
+                                newer,
+                                &older.envelope,
+                            )?,
+                            None => older,
+                        });
+                    }
+                }
+                if !masked && range_tombstone::covers(self.range_tombstones[index], &key) {
+                    masked = true;
+                    if let Some(newer) = version.take() {
+                        version = Some(merge::combine_versions(
+                            self.operator,
+                            &key,
+                            newer,
+                            &Envelope::Tombstone,
+                        )?);
+                    }
+                }
+            }
+
+            if let Some(version) = version {
+                return Ok(Some((key, version)));
+            }
+        }
+    }
+
+    /// Replaces the head of a source by its next entry.
+    fn advance(&mut self, index: usize) -> Result<(), LsmError> {
+        self.heads[index] = self.sources[index].next().transpose()?;
+        Ok(())
+    }
+
+    fn key(&self, index: usize) -> &[u8] {
+        self.heads[index]
+            .as_ref()
+            .map(|(key, _)| key.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+impl Iterator for Range<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>), LsmError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            let operator = self.operator;
+            let entry = self.next_entry().and_then(|entry| match entry {
+                Some((key, version)) => {
+                    let value = merge::resolve(operator, &key, version.envelope)?;
+                    Ok(Some((key, value)))
+                }
+                None => Ok(None),
+            });
+            match entry {
+                Ok(Some((key, Some(value)))) => return Some(Ok((key, value))),
+                Ok(Some((_, None))) => continue,
+                Ok(None) => self.finished = true,
+                Err(error) => {
+                    self.finished = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+        None
+    }
+}
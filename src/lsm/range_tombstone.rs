@@ -0,0 +1,162 @@
+use std::ops::Bound;
+
+use crate::common::KeyRange;
+
+/// A deletion of all the keys from `start`, included, to `end`, excluded, recorded by
+/// [LsmTree::delete_range](super::LsmTree::delete_range).
+///
+/// A range tombstone of the memtable or of a table hides the entries of its range in the older
+/// tables. It never hides the entries of its own memtable or table, which are always more recent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct RangeTombstone {
+    pub(super) start: Vec<u8>,
+    pub(super) end: Vec<u8>,
+}
+
+impl RangeTombstone {
+    /// Returns `true` if the key is within the range of the tombstone.
+    pub(super) fn contains(&self, key: &[u8]) -> bool {
+        self.start.as_slice() <= key && key < self.end.as_slice()
+    }
+
+    /// Returns the range of the keys deleted by the tombstone.
+    pub(super) fn key_range(&self) -> KeyRange {
+        KeyRange::new(
+            Bound::Included(self.start.clone()),
+            Bound::Excluded(self.end.clone()),
+        )
+    }
+}
+
+/// Sorts range tombstones and merges the ones that overlap or touch, so that no two tombstones of
+/// the result cover the same key. The empty ranges are dropped.
+pub(super) fn normalize(mut tombstones: Vec<RangeTombstone>) -> Vec<RangeTombstone> {
+    tombstones.retain(|tombstone| tombstone.start < tombstone.end);
+    tombstones.sort_unstable_by(|a, b| a.start.cmp(&b.start));
+    let mut normalized: Vec<RangeTombstone> = Vec::with_capacity(tombstones.len());
+    for tombstone in tombstones {
+        match normalized.last_mut() {
+            Some(last) if tombstone.start <= last.end => {
+                if tombstone.end > last.end {
+                    last.end = tombstone.end;
+                }
+            }
+            _ => normalized.push(tombstone),
+        }
+    }
+    normalized
+}
+
+/// Returns `true` if a key is within one of the normalized range tombstones.
+pub(super) fn covers(tombstones: &[RangeTombstone], key: &[u8]) -> bool {
+    let index = tombstones.partition_point(|tombstone| tombstone.start.as_slice() <= key);
+    index > 0 && tombstones[index - 1].contains(key)
+}
+
+/// Returns the parts of the normalized range tombstones within `range`, which starts at an
+/// included key or is unbounded, and ends at an excluded key or is unbounded.
+pub(super) fn clip(tombstones: &[RangeTombstone], range: &KeyRange) -> Vec<RangeTombstone> {
+    tombstones
+        .iter()
+        .filter_map(|tombstone| tombstone.key_range().intersection(range))
+        .filter_map(|part| match part.into_bounds() {
+            (Bound::Included(start), Bound::Excluded(end)) => Some(RangeTombstone { start, end }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Encodes range tombstones: their number, then the length and the bytes of the start and the
+/// end of each one.
+pub(super) fn encode(tombstones: &[RangeTombstone]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(tombstones.len() as u32).to_le_bytes());
+    for tombstone in tombstones {
+        for bound in [&tombstone.start, &tombstone.end] {
+            bytes.extend_from_slice(&(bound.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(bound);
+        }
+    }
+    bytes
+}
+
+/// Decodes range tombstones encoded by [encode]. Returns `None` if the bytes are truncated.
+pub(super) fn decode(bytes: &[u8]) -> Option<Vec<RangeTombstone>> {
+    let count = u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?) as usize;
+    let mut offset = 4;
+    let mut read_bound = || {
+        let len = u16::from_le_bytes(bytes.get(offset..offset + 2)?.try_into().ok()?) as usize;
+        let bound = bytes.get(offset + 2..offset + 2 + len)?.to_vec();
+        offset += 2 + len;
+        Some(bound)
+    };
+    let mut tombstones = Vec::new();
+    for _ in 0..count {
+        let start = read_bound()?;
+        let end = read_bound()?;
+        tombstones.push(RangeTombstone { start, end });
+    }
+    Some(tombstones)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tombstone(start: &str, end: &str) -> RangeTombstone {
+        RangeTombstone {
+            start: start.as_bytes().to_vec(),
+            end: end.as_bytes().to_vec(),
+        }
+    }
+
+    /// Overlapping and touching tombstones are merged, and a key is covered up to the end of the
+    /// merged range, excluded.
+    #[test]
+    fn normalize_merges_overlapping_tombstones() {
+        let tombstones = normalize(vec![
+            tombstone("m", "p"),
+            tombstone("c", "f"),
+            tombstone("e", "h"),
+            tombstone("h", "j"),
+            tombstone("x", "x"),
+        ]);
+
+        assert_eq!(tombstones, vec![tombstone("c", "j"), tombstone("m", "p")]);
+        assert!(covers(&tombstones, b"c"));
+        assert!(covers(&tombstones, b"iz"));
+        assert!(!covers(&tombstones, b"j"));
+        assert!(!covers(&tombstones, b"b"));
+        assert!(covers(&tombstones, b"o"));
+        assert!(!covers(&tombstones, b"x"));
+    }
+
+    /// Clipping keeps the parts of the tombstones within the bounds.
+    #[test]
+    fn clip_keeps_parts_within_bounds() {
+        let tombstones = vec![tombstone("c", "j"), tombstone("m", "p")];
+        let within = KeyRange::new(
+            Bound::Included(b"e".to_vec()),
+            Bound::Excluded(b"n".to_vec()),
+        );
+        let after = KeyRange::new(Bound::Included(b"p".to_vec()), Bound::Unbounded);
+
+        assert_eq!(
+            clip(&tombstones, &within),
+            vec![tombstone("e", "j"), tombstone("m", "n")]
+        );
+        assert_eq!(clip(&tombstones, &after), Vec::new());
+        assert_eq!(clip(&tombstones, &KeyRange::all()), tombstones);
+    }
+
+    /// Decoding encoded tombstones returns them, and truncated bytes are rejected.
+    #[test]
+    fn encode_then_decode_returns_tombstones() {
+        let tombstones = vec![tombstone("", "b"), tombstone("key-1", "key-9")];
+
+        let bytes = encode(&tombstones);
+
+        assert_eq!(decode(&bytes), Some(tombstones));
+        assert_eq!(decode(&bytes[..bytes.len() - 1]), None);
+    }
+}
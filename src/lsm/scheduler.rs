@@ -0,0 +1,247 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread::{self, JoinHandle};
+
+use crate::fs::File;
+use crate::pager::Pager;
+
+use super::{LsmError, LsmTree};
+
+/// A request sent to the thread of a [CompactionScheduler].
+enum Request {
+    /// Runs the compactions picked by the policy of the tree until it does not pick any.
+    Compact,
+    /// Stops the thread.
+    Stop,
+}
+
+/// The number of requests of a [CompactionScheduler] that are not done yet, shared with its
+/// thread and its [SchedulerHandle].
+#[derive(Default)]
+struct Pending {
+    count: Mutex<usize>,
+    /// Notified when the count drops to `0`.
+    done: Condvar,
+}
+
+impl Pending {
+    fn add(&self) {
+        *self.lock() += 1;
+    }
+
+    fn remove(&self, count: usize) {
+        let mut pending = self.lock();
+        *pending = pending.saturating_sub(count);
+        if *pending == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.count
+            .lock()
+            .expect("the pending count lock should not be poisoned")
+    }
+}
+
+/// The side of a [CompactionScheduler] held by its [LsmTree], which requests the compactions after
+/// each flush.
+#[derive(Clone)]
+pub(super) struct SchedulerHandle {
+    requests: Sender<Request>,
+    pending: Arc<Pending>,
+}
+
+impl SchedulerHandle {
+    /// Requests the compactions picked by the policy of the tree. Returns `false` if the scheduler
+    /// is stopped, in which case the caller must run them itself.
+    pub(super) fn request(&self) -> bool {
+        self.pending.add();
+        if self.requests.send(Request::Compact).is_ok() {
+            return true;
+        }
+        self.pending.remove(1);
+        false
+    }
+}
+
+/// A thread running the compactions of a shared [LsmTree], so its flushes return without waiting
+/// for them.
+///
+/// Once the scheduler is started, each flush of the tree only requests the compactions picked by
+/// its [CompactionPolicy](super::CompactionPolicy), and the thread runs them one at a time, locking
+/// the tree and its pager for each compaction. The writes go on between two compactions, and the
+/// tables waiting to be compacted are read meanwhile.
+///
+/// The scheduler does not keep the tree alive: it stops once every handle to the tree is dropped,
+/// or at its first error. It is stopped when it is dropped, and the flushes then run the
+/// compactions again.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// use rouilledb::fs::{File, MemoryFile};
+/// use rouilledb::lsm::{CompactionScheduler, LsmTree};
+/// use rouilledb::pager::Pager;
+///
+/// let mut file = MemoryFile::new();
+/// file.create().expect("create should not fail");
+/// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+/// let tree = LsmTree::create(&mut pager).expect("create should not fail");
+/// let shared = Arc::new(Mutex::new((pager, tree)));
+/// let scheduler = CompactionScheduler::start(&shared);
+///
+/// for i in 0..10u32 {
+///     let (pager, tree) = &mut *shared.lock().expect("the lock should not be poisoned");
+///     tree.insert(pager, &i.to_be_bytes(), b"value").expect("insert should not fail");
+///     tree.flush(pager).expect("flush should not fail");
+/// }
+///
+/// scheduler.wait();
+/// scheduler.stop().expect("stop should not fail");
+/// ```
+pub struct CompactionScheduler {
+    handle: SchedulerHandle,
+    thread: Option<JoinHandle<Result<(), LsmError>>>,
+}
+
+impl CompactionScheduler {
+    /// Starts a thread running the compactions of a tree shared with its pager, and makes the
+    /// flushes of the tree request them from the thread. The compactions the tree already needs
+    /// are requested at once.
+    pub fn start<F: File + Send + 'static>(tree: &Arc<Mutex<(Pager<F>, LsmTree)>>) -> Self {
+        let (requests, received) = mpsc::channel();
+        let handle = SchedulerHandle {
+            requests,
+            pending: Arc::default(),
+        };
+        let pending = Arc::clone(&handle.pending);
+        let weak = Arc::downgrade(tree);
+        let thread = thread::spawn(move || {
+            let result = run(&weak, &received, &pending);
+            // The requests left are never run: the waiters are released.
+            pending.remove(usize::MAX);
+            result
+        });
+        lock(tree).1.set_scheduler(handle.clone());
+        handle.request();
+        CompactionScheduler {
+            handle,
+            thread: Some(thread),
+        }
+    }
+
+    /// Waits until the compactions requested so far are done, or the scheduler is stopped.
+    pub fn wait(&self) {
+        let pending = &self.handle.pending;
+        let count = pending.lock();
+        drop(
+            pending
+                .done
+                .wait_while(count, |count| *count > 0)
+                .expect("the pending count lock should not be poisoned"),
+        );
+    }
+
+    /// Stops the scheduler, waiting for a compaction in progress to end. The compactions requested
+    /// but not started are left to the next flush.
+    ///
+    /// # Errors
+    ///
+    /// This method will return the error that stopped the scheduler, if any.
+    pub fn stop(mut self) -> Result<(), LsmError> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), LsmError> {
+        // The thread may already be stopped, and the request then fails.
+        let _ = self.handle.requests.send(Request::Stop);
+        match self.thread.take() {
+            Some(thread) => thread.join().expect("the scheduler should not panic"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for CompactionScheduler {
+    fn drop(&mut self) {
+        // The error is returned by `stop`, and is lost if the scheduler is dropped instead.
+        let _ = self.join();
+    }
+}
+
+/// Runs the compactions requested to the scheduler of a shared tree until it is stopped, the tree
+/// is dropped or a compaction fails.
+fn run<F: File>(
+    tree: &Weak<Mutex<(Pager<F>, LsmTree)>>,
+    received: &Receiver<Request>,
+    pending: &Pending,
+) -> Result<(), LsmError> {
+    while let Ok(Request::Compact) = received.recv() {
+        let Some(tree) = tree.upgrade() else {
+            return Ok(());
+        };
+        loop {
+            let (pager, tree) = &mut *lock(&tree);
+            if !tree.compact_once(pager)? {
+                break;
+            }
+        }
+        pending.remove(1);
+    }
+    Ok(())
+}
+
+fn lock<F: File>(tree: &Mutex<(Pager<F>, LsmTree)>) -> MutexGuard<'_, (Pager<F>, LsmTree)> {
+    tree.lock().expect("the tree lock should not be poisoned")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    /// The flushes return before the compactions they request, which the scheduler runs once the
+    /// writer releases the tree. Once the scheduler is stopped, the flushes run them again.
+    #[test]
+    fn flush_returns_before_compaction() {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 4096).expect("create should not fail");
+        let tree = LsmTree::create(&mut pager).expect("create should not fail");
+        let shared = Arc::new(Mutex::new((pager, tree)));
+        let scheduler = CompactionScheduler::start(&shared);
+        scheduler.wait();
+
+        let mut guard = lock(&shared);
+        let (pager, tree) = &mut *guard;
+        for index in 0..5u32 {
+            tree.insert(pager, &index.to_be_bytes(), b"value")
+                .expect("insert should not fail");
+            tree.flush(pager).expect("flush should not fail");
+        }
+        let flushed = tree.level_table_counts();
+        drop(guard);
+        scheduler.wait();
+        let compacted = lock(&shared).1.level_table_counts();
+        scheduler.stop().expect("stop should not fail");
+        let (pager, tree) = &mut *lock(&shared);
+        for index in 0..5u32 {
+            tree.insert(pager, &index.to_be_bytes(), b"other")
+                .expect("insert should not fail");
+            tree.flush(pager).expect("flush should not fail");
+        }
+
+        assert_eq!(flushed, vec![5]);
+        assert_eq!(compacted, vec![0, 1]);
+        assert_eq!(tree.level_table_counts(), vec![0, 1]);
+        assert_eq!(
+            tree.get(pager, &4u32.to_be_bytes())
+                .expect("get should not fail"),
+            Some(b"other".to_vec())
+        );
+    }
+}
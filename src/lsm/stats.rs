@@ -0,0 +1,60 @@
+use crate::fs::File;
+use crate::pager::Pager;
+
+use crate::common::envelope::Envelope;
+
+use super::table::Table;
+use super::LsmError;
+
+/// Describes the tables of a level of an [LsmTree](super::LsmTree), as measured by
+/// [LsmTree::analyze](super::LsmTree::analyze).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LevelStats {
+    /// The number of tables of the level.
+    pub table_count: usize,
+    /// The number of data blocks (pages) of the tables.
+    pub block_count: usize,
+    /// The number of entries of the tables, tombstones and merge operands included.
+    pub entry_count: u64,
+    /// The number of tombstones.
+    pub tombstone_count: u64,
+    /// The number of merge operands.
+    pub merge_count: u64,
+    /// The number of range tombstones, each deleting a range of keys.
+    pub range_tombstone_count: u64,
+    /// The total size of the keys of the entries.
+    pub key_bytes: u64,
+    /// The total size of the values and merge operands.
+    pub value_bytes: u64,
+}
+
+/// Reads every entry of the tables of a level and measures them.
+///
+/// # Errors
+///
+/// This function will return an error if a data block can't be read or is corrupted.
+pub(super) fn analyze_level<F: File>(
+    pager: &Pager<F>,
+    tables: &[Table],
+) -> Result<LevelStats, LsmError> {
+    let mut stats = LevelStats {
+        table_count: tables.len(),
+        ..LevelStats::default()
+    };
+    for table in tables {
+        stats.block_count += table.info().block_count;
+        stats.range_tombstone_count += table.range_tombstones().len() as u64;
+        for entry in table.iter(pager, std::ops::Bound::Unbounded) {
+            let (key, version) = entry?;
+            stats.entry_count += 1;
+            stats.key_bytes += key.len() as u64;
+            stats.value_bytes += version.envelope.payload().len() as u64;
+            match version.envelope {
+                Envelope::Tombstone => stats.tombstone_count += 1,
+                Envelope::Merge(_) => stats.merge_count += 1,
+                Envelope::Value(_) => {}
+            }
+        }
+    }
+    Ok(stats)
+}
@@ -0,0 +1,995 @@
+use std::ops::Bound;
+
+use crate::common::checksum::crc32c;
+use crate::common::envelope::Versioned;
+use crate::common::layout::Layout;
+use crate::common::{varint, KeyRange};
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+use crate::{assert_layout_size, disk_layout};
+
+use super::filter::{self, BloomFilter};
+use super::range_tombstone::{self, RangeTombstone};
+use super::{LsmError, TableInfo};
+
+/// A key and the version recorded for it by the memtable or a table.
+pub(super) type Entry = (Vec<u8>, Versioned);
+
+/// Magic bytes at the start of the footer page of a table.
+const FOOTER_MAGIC: [u8; 8] = *b"ROUILSST";
+
+/// Version of the table format written by [TableWriter], the only one read.
+const FORMAT_VERSION: u16 = 1;
+
+disk_layout! {
+    /// The start of the footer page of a table, which tells the version of the rest of the table.
+    struct FooterTag {
+        magic: [u8; 8],
+        version: u16,
+    }
+}
+
+assert_layout_size!(FooterTag, 10);
+
+disk_layout! {
+    /// The fixed part of the footer, after its [FooterTag], followed by the smallest key. The first
+    /// index page, the filter page and the range tombstone page are `0` if there are none.
+    struct Footer {
+        entry_count: u64,
+        block_count: u32,
+        index_page: PageId,
+        filter_page: PageId,
+        range_tombstone_page: PageId,
+        smallest_key_len: u16,
+    }
+}
+
+assert_layout_size!(Footer, 26);
+
+/// Size of the fixed part of the footer page.
+const FOOTER_SIZE: usize = FooterTag::SIZE + Footer::SIZE;
+
+disk_layout! {
+    /// The header of a data block, followed by its cells.
+    struct DataHeader {
+        count: u16,
+        /// The CRC32C of the rest of the page.
+        checksum: u32,
+    }
+}
+
+assert_layout_size!(DataHeader, 6);
+
+/// Size of the header of a data block.
+const DATA_HEADER_SIZE: usize = DataHeader::SIZE;
+
+/// Size of the header of an index page: the number of cells and the next index page.
+const INDEX_HEADER_SIZE: usize = 6;
+
+/// Size of the fixed part of an index cell: the key length and the data block page.
+const INDEX_CELL_OVERHEAD: usize = 6;
+
+/// Size of the header of a filter or range tombstone page: the number of bytes in the page and the
+/// next page of the chain.
+const CHAIN_HEADER_SIZE: usize = 6;
+
+/// Returns the largest combined size of a key and its value that can be stored in a table using
+/// pages of the given size. The key must also fit in an index page and in the footer.
+pub(super) fn max_entry_size(page_size: usize) -> usize {
+    page_size - FOOTER_SIZE.max(INDEX_HEADER_SIZE + INDEX_CELL_OVERHEAD)
+}
+
+/// Represents an immutable sorted string table of an [LsmTree](super::LsmTree).
+///
+/// A table is made of:
+/// - data blocks, one per page, holding the entries in key order. Deleted keys are stored as
+///   tombstones so they hide the values of older tables, and merge operands are stored until
+///   they can be applied to the value of an older table. Each entry keeps the sequence number of
+///   its write.
+/// - an index, in a chain of pages, holding the last key and the page of each data block.
+/// - an optional [BloomFilter] of the keys, and of their prefixes of a fixed length, in a chain of
+///   pages.
+/// - the [RangeTombstone]s of the table, if it has any, in a chain of pages.
+/// - a footer page, identifying the table, with magic bytes, the version of the format, the
+///   location of the index, the filter and the range tombstones and the smallest key of the table.
+///
+/// The index and the filter are loaded in memory when the table is opened, so a point lookup reads
+/// at most a single data block found with a binary search, and none when the filter rules the key
+/// out. The range tombstones are also loaded in memory. A table holding only range tombstones has
+/// no data block.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Table {
+    footer: PageId,
+    entry_count: u64,
+    index_pages: Vec<PageId>,
+    filter_pages: Vec<PageId>,
+    filter: Option<BloomFilter>,
+    range_tombstone_pages: Vec<PageId>,
+    range_tombstones: Vec<RangeTombstone>,
+    smallest_key: Vec<u8>,
+    largest_key: Vec<u8>,
+    index: Vec<(Vec<u8>, PageId)>,
+}
+
+impl Table {
+    /// Opens the table whose footer is at `footer` and loads its index.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - the footer or an index page can't be read or is corrupted
+    /// - the table was written with an unsupported version of the format
+    pub(super) fn open<F: File>(pager: &Pager<F>, footer: PageId) -> Result<Self, LsmError> {
+        let page = pager.read_page(footer)?;
+        let tag = FooterTag::read(&page).map_err(|_| LsmError::CorruptedPage(footer))?;
+        if tag.magic != FOOTER_MAGIC {
+            return Err(LsmError::CorruptedPage(footer));
+        }
+        if tag.version != FORMAT_VERSION {
+            return Err(LsmError::UnsupportedTableVersion {
+                page: footer,
+                version: tag.version,
+            });
+        }
+        let fields =
+            Footer::read(&page[FooterTag::SIZE..]).map_err(|_| LsmError::CorruptedPage(footer))?;
+        let entry_count = fields.entry_count;
+        let block_count = fields.block_count as usize;
+        let mut next_index_page = fields.index_page;
+        let next_filter_page = fields.filter_page;
+        let next_range_tombstone_page = fields.range_tombstone_page;
+        let smallest_len = usize::from(fields.smallest_key_len);
+        let smallest_key = page
+            .get(FOOTER_SIZE..FOOTER_SIZE + smallest_len)
+            .ok_or(LsmError::CorruptedPage(footer))?
+            .to_vec();
+
+        let mut index = Vec::with_capacity(block_count);
+        let mut index_pages = Vec::new();
+        while next_index_page != 0 {
+            let id = next_index_page;
+            let page = pager.read_page(id)?;
+            next_index_page =
+                decode_index_page(&page, &mut index).ok_or(LsmError::CorruptedPage(id))?;
+            index_pages.push(id);
+        }
+        if index.len() != block_count {
+            return Err(LsmError::CorruptedPage(footer));
+        }
+
+        let (filter_bytes, filter_pages) = read_chain(pager, next_filter_page)?;
+        let filter = match filter_pages.first() {
+            Some(&first) => {
+                Some(BloomFilter::decode(&filter_bytes).ok_or(LsmError::CorruptedPage(first))?)
+            }
+            None => None,
+        };
+
+        let (range_tombstone_bytes, range_tombstone_pages) =
+            read_chain(pager, next_range_tombstone_page)?;
+        let range_tombstones = match range_tombstone_pages.first() {
+            Some(&first) => range_tombstone::decode(&range_tombstone_bytes)
+                .ok_or(LsmError::CorruptedPage(first))?,
+            None => Vec::new(),
+        };
+        if index.is_empty() && range_tombstones.is_empty() {
+            return Err(LsmError::CorruptedPage(footer));
+        }
+        let largest_key = largest_key(&index, &range_tombstones);
+
+        Ok(Table {
+            footer,
+            entry_count,
+            index_pages,
+            filter_pages,
+            filter,
+            range_tombstone_pages,
+            range_tombstones,
+            smallest_key,
+            largest_key,
+            index,
+        })
+    }
+
+    /// Writes entries sorted by strictly increasing keys and normalized range tombstones to a new
+    /// table, with a filter using `bits_per_key` bits per key, or no filter if it is `0`, which
+    /// also holds the prefixes of `prefix_length` bytes of the keys, unless it is `0`. Returns
+    /// `None` if there are no entries and no range tombstones.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a page can't be allocated or written.
+    pub(super) fn write<F: File>(
+        pager: &mut Pager<F>,
+        entries: impl IntoIterator<Item = Entry>,
+        range_tombstones: Vec<RangeTombstone>,
+        bits_per_key: usize,
+        prefix_length: usize,
+    ) -> Result<Option<Table>, LsmError> {
+        let mut writer = TableWriter::new(bits_per_key, prefix_length);
+        for (key, version) in entries {
+            writer.add(pager, &key, &version)?;
+        }
+        writer.finish(pager, range_tombstones)
+    }
+
+    /// Returns the footer page of the table, which identifies it.
+    pub(super) fn footer(&self) -> PageId {
+        self.footer
+    }
+
+    /// Returns the description of the table given to a
+    /// [CompactionPolicy](super::CompactionPolicy).
+    pub(super) fn info(&self) -> TableInfo {
+        TableInfo {
+            block_count: self.index.len(),
+            entry_count: self.entry_count,
+            smallest_key: self.smallest_key.clone(),
+            largest_key: self.largest_key().to_vec(),
+        }
+    }
+
+    /// Returns the number of entries in the table, tombstones and merge operands included.
+    pub(super) fn entry_count(&self) -> u64 {
+        self.entry_count
+    }
+
+    /// Returns the smallest key of the table.
+    pub(super) fn smallest_key(&self) -> &[u8] {
+        &self.smallest_key
+    }
+
+    /// Returns the largest key of the table. The range tombstones are included, with their
+    /// excluded end.
+    pub(super) fn largest_key(&self) -> &[u8] {
+        &self.largest_key
+    }
+
+    /// Returns the range tombstones of the table, sorted and without overlaps.
+    pub(super) fn range_tombstones(&self) -> &[RangeTombstone] {
+        &self.range_tombstones
+    }
+
+    /// Returns the filter of the keys of the table, if it has one.
+    pub(super) fn filter(&self) -> Option<&BloomFilter> {
+        self.filter.as_ref()
+    }
+
+    /// Returns `false` if the table certainly holds no entry whose key starts with `prefix`, and
+    /// `true` if it may. Only the key range and the filter of the table are checked: the range
+    /// tombstones are not.
+    pub(super) fn may_contain_prefix(&self, prefix: &[u8]) -> bool {
+        let keys = KeyRange::new(
+            Bound::Included(self.smallest_key().to_vec()),
+            Bound::Included(self.largest_key().to_vec()),
+        );
+        KeyRange::prefix(prefix).intersects(&keys)
+            && self
+                .filter()
+                .is_none_or(|filter| filter.may_contain_prefix(prefix))
+    }
+
+    /// Returns `true` if the key is between the smallest and the largest key of the table.
+    pub(super) fn may_contain(&self, key: &[u8]) -> bool {
+        self.smallest_key() <= key && key <= self.largest_key()
+    }
+
+    /// Returns the version of a key in the table, or `None` if the table does not contain the key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the data block can't be read or is corrupted.
+    pub(super) fn get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Versioned>, LsmError> {
+        let Some(&(_, id)) = self.index.get(self.block_index(key)) else {
+            return Ok(None);
+        };
+        let mut entries = read_block(pager, id)?;
+        match entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) {
+            Ok(index) => Ok(Some(entries.swap_remove(index).1)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Returns the versions of keys sorted in increasing order, in the same order, with `None` for
+    /// the keys the table does not contain. The data block holding several of the keys is only
+    /// read once.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a data block can't be read or is corrupted.
+    pub(super) fn multi_get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Versioned>>, LsmError> {
+        let mut records = Vec::with_capacity(keys.len());
+        let mut block: Option<(usize, Vec<Entry>)> = None;
+        for key in keys {
+            let block_index = self.block_index(key);
+            let Some(&(_, id)) = self.index.get(block_index) else {
+                records.push(None);
+                continue;
+            };
+            let entries = match &mut block {
+                Some((index, entries)) if *index == block_index => entries,
+                _ => &mut block.insert((block_index, read_block(pager, id)?)).1,
+            };
+            let position = entries.binary_search_by(|(k, _)| k.as_slice().cmp(key));
+            records.push(position.ok().map(|position| entries[position].1.clone()));
+        }
+        Ok(records)
+    }
+
+    /// Returns an iterator over the entries of the table, starting at the first key within
+    /// `start`.
+    pub(super) fn iter<'a, F: File>(
+        &'a self,
+        pager: &'a Pager<F>,
+        start: Bound<&[u8]>,
+    ) -> TableIter<'a, F> {
+        let next_block = match start {
+            Bound::Included(key) | Bound::Excluded(key) => self.block_index(key),
+            Bound::Unbounded => 0,
+        };
+        TableIter {
+            pager,
+            table: self,
+            start: start.map(<[u8]>::to_vec),
+            next_block,
+            entries: Vec::new().into_iter(),
+        }
+    }
+
+    /// Returns all the pages of the table to the pager.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be freed.
+    pub(super) fn free<F: File>(&self, pager: &mut Pager<F>) -> Result<(), LsmError> {
+        let data_pages = self.index.iter().map(|(_, id)| *id);
+        for id in data_pages
+            .chain(self.index_pages.iter().copied())
+            .chain(self.filter_pages.iter().copied())
+            .chain(self.range_tombstone_pages.iter().copied())
+            .chain([self.footer])
+        {
+            pager.free_page(id)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the first data block whose last key is greater or equal to `key`.
+    fn block_index(&self, key: &[u8]) -> usize {
+        self.index
+            .partition_point(|(last_key, _)| last_key.as_slice() < key)
+    }
+}
+
+/// Writes the entries of a new [Table], in key order, as they are added.
+///
+/// A data block is written as soon as the next entry does not fit in it. The index and the footer
+/// are written by [TableWriter::finish].
+pub(super) struct TableWriter {
+    block: Vec<Entry>,
+    block_size: usize,
+    entry_count: u64,
+    smallest_key: Option<Vec<u8>>,
+    index: Vec<(Vec<u8>, PageId)>,
+    bits_per_key: usize,
+    prefix_length: usize,
+    key_hashes: Vec<u64>,
+    /// The prefix of the last key added whose hash was added.
+    last_prefix: Option<Vec<u8>>,
+}
+
+impl TableWriter {
+    /// Creates a writer for an empty table, with a filter using `bits_per_key` bits per key, or
+    /// no filter if it is `0`. The filter also holds the prefixes of `prefix_length` bytes of the
+    /// keys, unless it is `0`.
+    pub(super) fn new(bits_per_key: usize, prefix_length: usize) -> Self {
+        TableWriter {
+            block: Vec::new(),
+            block_size: DATA_HEADER_SIZE,
+            entry_count: 0,
+            smallest_key: None,
+            index: Vec::new(),
+            bits_per_key,
+            prefix_length,
+            key_hashes: Vec::new(),
+            last_prefix: None,
+        }
+    }
+
+    /// Returns the number of data blocks written so far.
+    pub(super) fn block_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Adds an entry to the table. The keys must be added in strictly increasing order and each
+    /// entry must be at most [max_entry_size] bytes.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a full data block can't be written.
+    pub(super) fn add<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        version: &Versioned,
+    ) -> Result<(), LsmError> {
+        debug_assert!(
+            key.len() + version.envelope.payload().len() <= max_entry_size(pager.page_size())
+        );
+        debug_assert!(self
+            .block
+            .last()
+            .is_none_or(|(last, _)| last.as_slice() < key));
+
+        let cell_size = varint::encoded_len(key.len() as u64) + key.len() + version.encoded_len();
+        if self.block_size + cell_size > pager.page_size() {
+            self.write_block(pager)?;
+        }
+        self.block_size += cell_size;
+        self.entry_count += 1;
+        if self.smallest_key.is_none() {
+            self.smallest_key = Some(key.to_vec());
+        }
+        if self.bits_per_key > 0 {
+            self.key_hashes.push(filter::hash(key));
+            // The keys are sorted, so the keys sharing a prefix follow each other.
+            if self.prefix_length > 0 && key.len() >= self.prefix_length {
+                let prefix = &key[..self.prefix_length];
+                if self.last_prefix.as_deref() != Some(prefix) {
+                    self.key_hashes.push(filter::hash(prefix));
+                    self.last_prefix = Some(prefix.to_vec());
+                }
+            }
+        }
+        self.block.push((key.to_vec(), version.clone()));
+        Ok(())
+    }
+
+    /// Writes the last data block, the index, the filter, the normalized range tombstones and the
+    /// footer. Returns `None`, without writing anything, if no entry was added and there are no
+    /// range tombstones.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be allocated or written.
+    pub(super) fn finish<F: File>(
+        mut self,
+        pager: &mut Pager<F>,
+        range_tombstones: Vec<RangeTombstone>,
+    ) -> Result<Option<Table>, LsmError> {
+        if !self.block.is_empty() {
+            self.write_block(pager)?;
+        }
+        if self.index.is_empty() && range_tombstones.is_empty() {
+            return Ok(None);
+        }
+
+        let index_pages = write_index(pager, &self.index)?;
+        let filter = (self.bits_per_key > 0)
+            .then(|| BloomFilter::new(&self.key_hashes, self.bits_per_key, self.prefix_length));
+        let filter_pages = match &filter {
+            Some(filter) => write_chain(pager, &filter.encode())?,
+            None => Vec::new(),
+        };
+        let range_tombstone_pages = if range_tombstones.is_empty() {
+            Vec::new()
+        } else {
+            write_chain(pager, &range_tombstone::encode(&range_tombstones))?
+        };
+        let footer = pager.allocate_page()?;
+        let smallest_key = match (self.smallest_key, range_tombstones.first()) {
+            (Some(key), Some(tombstone)) => key.min(tombstone.start.clone()),
+            (Some(key), None) => key,
+            (None, Some(tombstone)) => tombstone.start.clone(),
+            (None, None) => Vec::new(),
+        };
+        let mut page = Vec::with_capacity(pager.page_size());
+        let tag = FooterTag {
+            magic: FOOTER_MAGIC,
+            version: FORMAT_VERSION,
+        };
+        tag.append(&mut page);
+        let fields = Footer {
+            entry_count: self.entry_count,
+            block_count: self.index.len() as u32,
+            index_page: index_pages[0],
+            filter_page: filter_pages.first().copied().unwrap_or(0),
+            range_tombstone_page: range_tombstone_pages.first().copied().unwrap_or(0),
+            smallest_key_len: smallest_key.len() as u16,
+        };
+        fields.append(&mut page);
+        page.extend_from_slice(&smallest_key);
+        page.resize(pager.page_size(), 0);
+        pager.write_page(footer, &page)?;
+
+        Ok(Some(Table {
+            footer,
+            entry_count: self.entry_count,
+            index_pages,
+            filter_pages,
+            filter,
+            range_tombstone_pages,
+            largest_key: largest_key(&self.index, &range_tombstones),
+            range_tombstones,
+            smallest_key,
+            index: self.index,
+        }))
+    }
+
+    /// Writes the current data block to a new page and adds it to the index.
+    fn write_block<F: File>(&mut self, pager: &mut Pager<F>) -> Result<(), LsmError> {
+        let id = pager.allocate_page()?;
+        let mut page = vec![0; DATA_HEADER_SIZE];
+        page.reserve(pager.page_size() - DATA_HEADER_SIZE);
+        for (key, version) in &self.block {
+            varint::encode_u64(key.len() as u64, &mut page);
+            page.extend_from_slice(key);
+            version.encode(&mut page);
+        }
+        page.resize(pager.page_size(), 0);
+        let header = DataHeader {
+            count: self.block.len() as u16,
+            checksum: crc32c(&page[DATA_HEADER_SIZE..]),
+        };
+        header.write(&mut page);
+        pager.write_page(id, &page)?;
+
+        let (last_key, _) = self.block.pop().expect("the block should not be empty");
+        self.index.push((last_key, id));
+        self.block.clear();
+        self.block_size = DATA_HEADER_SIZE;
+        Ok(())
+    }
+}
+
+/// An iterator over the entries of a [Table], in key order. The data blocks are read as the
+/// iteration progresses.
+pub(super) struct TableIter<'a, F: File> {
+    pager: &'a Pager<F>,
+    table: &'a Table,
+    start: Bound<Vec<u8>>,
+    next_block: usize,
+    entries: std::vec::IntoIter<Entry>,
+}
+
+impl<F: File> Iterator for TableIter<'_, F> {
+    type Item = Result<Entry, LsmError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, value)) = self.entries.next() {
+                let after_start = match &self.start {
+                    Bound::Included(start) => key >= *start,
+                    Bound::Excluded(start) => key > *start,
+                    Bound::Unbounded => true,
+                };
+                if after_start {
+                    self.start = Bound::Unbounded;
+                    return Some(Ok((key, value)));
+                }
+                continue;
+            }
+
+            let &(_, id) = self.table.index.get(self.next_block)?;
+            self.next_block += 1;
+            match read_block(self.pager, id) {
+                Ok(entries) => self.entries = entries.into_iter(),
+                Err(error) => {
+                    self.next_block = self.table.index.len();
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+/// Writes the index of a table in a chain of pages. Returns the pages, in order.
+fn write_index<F: File>(
+    pager: &mut Pager<F>,
+    index: &[(Vec<u8>, PageId)],
+) -> Result<Vec<PageId>, LsmError> {
+    let page_size = pager.page_size();
+    let mut id = pager.allocate_page()?;
+    let mut pages = vec![id];
+    let mut cells = index;
+    loop {
+        let mut size = INDEX_HEADER_SIZE;
+        let count = cells
+            .iter()
+            .take_while(|(key, _)| {
+                size += INDEX_CELL_OVERHEAD + key.len();
+                size <= page_size
+            })
+            .count();
+        let (page_cells, rest) = cells.split_at(count);
+        let next = if rest.is_empty() {
+            0
+        } else {
+            pager.allocate_page()?
+        };
+
+        let mut page = Vec::with_capacity(page_size);
+        page.extend_from_slice(&(count as u16).to_le_bytes());
+        page.extend_from_slice(&next.to_le_bytes());
+        for (key, block) in page_cells {
+            page.extend_from_slice(&(key.len() as u16).to_le_bytes());
+            page.extend_from_slice(&block.to_le_bytes());
+            page.extend_from_slice(key);
+        }
+        page.resize(page_size, 0);
+        pager.write_page(id, &page)?;
+
+        if next == 0 {
+            return Ok(pages);
+        }
+        pages.push(next);
+        id = next;
+        cells = rest;
+    }
+}
+
+/// Decodes an index page, appending its cells to `index`. Returns the next index page, or `0`.
+fn decode_index_page(page: &[u8], index: &mut Vec<(Vec<u8>, PageId)>) -> Option<PageId> {
+    let count = u16::from_le_bytes(page.get(0..2)?.try_into().ok()?) as usize;
+    let next = u32::from_le_bytes(page.get(2..6)?.try_into().ok()?);
+    let mut offset = INDEX_HEADER_SIZE;
+    for _ in 0..count {
+        let key_len = u16::from_le_bytes(page.get(offset..offset + 2)?.try_into().ok()?) as usize;
+        let block = u32::from_le_bytes(page.get(offset + 2..offset + 6)?.try_into().ok()?);
+        offset += INDEX_CELL_OVERHEAD;
+        index.push((page.get(offset..offset + key_len)?.to_vec(), block));
+        offset += key_len;
+    }
+    Some(next)
+}
+
+/// Writes the encoded filter or range tombstones of a table in a chain of pages. Returns the
+/// pages, in order.
+fn write_chain<F: File>(pager: &mut Pager<F>, bytes: &[u8]) -> Result<Vec<PageId>, LsmError> {
+    let page_size = pager.page_size();
+    let chunks: Vec<&[u8]> = bytes.chunks(page_size - CHAIN_HEADER_SIZE).collect();
+    let pages = (0..chunks.len())
+        .map(|_| pager.allocate_page())
+        .collect::<Result<Vec<_>, _>>()?;
+    for (index, chunk) in chunks.iter().enumerate() {
+        let next = pages.get(index + 1).copied().unwrap_or(0);
+        let mut page = Vec::with_capacity(page_size);
+        page.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+        page.extend_from_slice(&next.to_le_bytes());
+        page.extend_from_slice(chunk);
+        page.resize(page_size, 0);
+        pager.write_page(pages[index], &page)?;
+    }
+    Ok(pages)
+}
+
+/// Reads the chain of pages starting at `first`, or no page if it is `0`. Returns the bytes of the
+/// chain and its pages, in order.
+fn read_chain<F: File>(
+    pager: &Pager<F>,
+    first: PageId,
+) -> Result<(Vec<u8>, Vec<PageId>), LsmError> {
+    let mut bytes = Vec::new();
+    let mut pages = Vec::new();
+    let mut next = first;
+    while next != 0 {
+        let id = next;
+        let page = pager.read_page(id)?;
+        next = decode_chain_page(&page, &mut bytes).ok_or(LsmError::CorruptedPage(id))?;
+        pages.push(id);
+    }
+    Ok((bytes, pages))
+}
+
+/// Decodes a page of a chain, appending its bytes to `bytes`. Returns the next page, or `0`.
+fn decode_chain_page(page: &[u8], bytes: &mut Vec<u8>) -> Option<PageId> {
+    let len = u16::from_le_bytes(page.get(0..2)?.try_into().ok()?) as usize;
+    let next = u32::from_le_bytes(page.get(2..6)?.try_into().ok()?);
+    bytes.extend_from_slice(page.get(CHAIN_HEADER_SIZE..CHAIN_HEADER_SIZE + len)?);
+    Some(next)
+}
+
+/// Returns the largest key of a table: the last key of its index or the end of its last range
+/// tombstone, whichever is larger.
+fn largest_key(index: &[(Vec<u8>, PageId)], range_tombstones: &[RangeTombstone]) -> Vec<u8> {
+    let last_key = index.last().map(|(key, _)| key.as_slice());
+    let last_end = range_tombstones
+        .last()
+        .map(|tombstone| tombstone.end.as_slice());
+    last_key.max(last_end).unwrap_or_default().to_vec()
+}
+
+/// Reads a data block of a table.
+fn read_block<F: File>(pager: &Pager<F>, id: PageId) -> Result<Vec<Entry>, LsmError> {
+    let page = pager.read_page(id)?;
+    decode_block(&page).ok_or(LsmError::CorruptedPage(id))
+}
+
+/// Decodes a data block, or returns `None` if it is corrupted.
+fn decode_block(page: &[u8]) -> Option<Vec<Entry>> {
+    let header = DataHeader::read(page).ok()?;
+    if crc32c(&page[DATA_HEADER_SIZE..]) != header.checksum {
+        return None;
+    }
+    let mut offset = DATA_HEADER_SIZE;
+    let count = usize::from(header.count);
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (key_len, key_len_size) = varint::decode_u64(page.get(offset..)?)?;
+        offset += key_len_size;
+        let key_end = offset.checked_add(usize::try_from(key_len).ok()?)?;
+        let key = page.get(offset..key_end)?.to_vec();
+        let (stored, stored_len) = Versioned::decode(page.get(key_end..)?)?;
+        offset = key_end + stored_len;
+        entries.push((key, stored));
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::envelope::Envelope;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_pager() -> Pager<MemoryFile> {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        Pager::create(file, 512).expect("create should not fail")
+    }
+
+    fn entry(index: usize) -> Entry {
+        let envelope = match index % 3 {
+            0 => Envelope::Tombstone,
+            1 => Envelope::Value(format!("value-{index}").into_bytes()),
+            _ => Envelope::Merge(format!("operand-{index}").into_bytes()),
+        };
+        (
+            format!("key-{index:06}").into_bytes(),
+            Versioned::new(index as u64 * 1000, envelope),
+        )
+    }
+
+    fn write_table(pager: &mut Pager<MemoryFile>, entries: impl Iterator<Item = Entry>) -> Table {
+        Table::write(pager, entries, Vec::new(), 10, 0)
+            .expect("write should not fail")
+            .expect("the table should not be empty")
+    }
+
+    /// The entries written to a table spanning several blocks are iterated back in order.
+    #[test]
+    fn write_then_iter_returns_entries() {
+        let mut pager = create_pager();
+        let table = write_table(&mut pager, (0..200).map(entry));
+
+        let entries: Vec<Entry> = table
+            .iter(&pager, Bound::Unbounded)
+            .map(|entry| entry.expect("iteration should not fail"))
+            .collect();
+
+        assert!(table.index.len() > 3);
+        assert_eq!(table.entry_count(), 200);
+        assert_eq!(entries, (0..200).map(entry).collect::<Vec<_>>());
+    }
+
+    /// Getting a key distinguishes values, tombstones, merge operands and missing keys.
+    #[test]
+    fn get_returns_values_and_tombstones() {
+        let mut pager = create_pager();
+        let table = write_table(&mut pager, (0..200).step_by(2).map(entry));
+
+        let get = |index| {
+            table
+                .get(&pager, &entry(index).0)
+                .expect("get should not fail")
+        };
+
+        assert_eq!(get(100), Some(entry(100).1));
+        assert_eq!(get(102), Some(Versioned::new(102_000, Envelope::Tombstone)));
+        assert_eq!(get(104), Some(entry(104).1));
+        assert_eq!(get(101), None);
+        assert_eq!(get(1000), None);
+    }
+
+    /// Iterating from an excluded start skips the entries up to and including the start.
+    #[test]
+    fn iter_from_excluded_start_skips_start() {
+        let mut pager = create_pager();
+        let table = write_table(&mut pager, (0..200).map(entry));
+
+        let first = table
+            .iter(&pager, Bound::Excluded(&entry(150).0))
+            .next()
+            .expect("the iterator should not be empty")
+            .expect("iteration should not fail");
+
+        assert_eq!(first, entry(151));
+    }
+
+    /// A table reopened from its footer has the same index, even when the index spans several
+    /// pages.
+    #[test]
+    fn open_reads_footer_and_index() {
+        let mut pager = create_pager();
+        let table = write_table(&mut pager, (0..3000).map(entry));
+
+        let opened = Table::open(&pager, table.footer()).expect("open should not fail");
+
+        assert!(table.index_pages.len() > 1);
+        assert_eq!(opened, table);
+    }
+
+    /// The filter of a table spanning several pages is reloaded when the table is opened and
+    /// rules out most absent keys.
+    #[test]
+    fn open_reads_filter() {
+        let mut pager = create_pager();
+        let table = write_table(&mut pager, (0..3000).step_by(2).map(entry));
+
+        let opened = Table::open(&pager, table.footer()).expect("open should not fail");
+        let filter = opened.filter().expect("the table should have a filter");
+
+        assert!(table.filter_pages.len() > 1);
+        assert!((0..3000)
+            .step_by(2)
+            .all(|index| filter.may_contain(&entry(index).0)));
+        let false_positives = (1..3000)
+            .step_by(2)
+            .filter(|&index| filter.may_contain(&entry(index).0))
+            .count();
+        assert!(false_positives < 60, "{false_positives} false positives");
+    }
+
+    /// A table with a prefix filter rules out the prefixes outside of its key range and most of
+    /// the absent prefixes within it, and the prefix length is kept when the table is reopened.
+    #[test]
+    fn may_contain_prefix_uses_key_range_and_filter() {
+        let mut pager = create_pager();
+        let entries = (0..600).filter(|index| index / 100 != 3).map(entry);
+        let table = Table::write(&mut pager, entries, Vec::new(), 10, 8)
+            .expect("write should not fail")
+            .expect("the table should not be empty");
+
+        let opened = Table::open(&pager, table.footer()).expect("open should not fail");
+
+        assert_eq!(opened, table);
+        assert!(opened.may_contain_prefix(b"key-0001"));
+        assert!(opened.may_contain_prefix(b"key-00"));
+        assert!(!opened.may_contain_prefix(b"key-0003"));
+        assert!(!opened.may_contain_prefix(b"key-0006"));
+        assert!(!opened.may_contain_prefix(b"aaa"));
+    }
+
+    /// The description of a table gives its size and the range of its keys.
+    #[test]
+    fn info_describes_table() {
+        let mut pager = create_pager();
+        let table = write_table(&mut pager, (10..200).map(entry));
+
+        let info = table.info();
+
+        assert_eq!(info.block_count, table.index.len());
+        assert_eq!(info.entry_count, 190);
+        assert_eq!(info.smallest_key, entry(10).0);
+        assert_eq!(info.largest_key, entry(199).0);
+        assert!(table.may_contain(&entry(50).0));
+        assert!(!table.may_contain(&entry(5).0));
+    }
+
+    /// Opening a table written with another version of the format fails.
+    #[test]
+    fn open_unsupported_version_fails() {
+        let mut pager = create_pager();
+        let table = write_table(&mut pager, (0..10).map(entry));
+        let mut footer = pager
+            .read_page(table.footer())
+            .expect("read_page should not fail");
+        footer[8] = 99;
+        pager
+            .write_page(table.footer(), &footer)
+            .expect("write_page should not fail");
+
+        let result = Table::open(&pager, table.footer());
+
+        assert!(matches!(
+            result,
+            Err(LsmError::UnsupportedTableVersion { version: 99, .. })
+        ));
+    }
+
+    /// A data block whose bytes no longer match its checksum is rejected.
+    #[test]
+    fn decode_block_verifies_checksum() {
+        let mut block = vec![0; DATA_HEADER_SIZE];
+        block.extend([1, b'a', Envelope::VALUE_TAG, 5, 2]);
+        block.extend(b"12");
+        block.resize(64, 0);
+        let header = DataHeader {
+            count: 1,
+            checksum: crc32c(&block[DATA_HEADER_SIZE..]),
+        };
+        header.write(&mut block);
+        let mut corrupted = block.clone();
+        corrupted[40] = 1;
+
+        assert_eq!(
+            decode_block(&block),
+            Some(vec![(
+                b"a".to_vec(),
+                Versioned::new(5, Envelope::Value(b"12".to_vec()))
+            )])
+        );
+        assert_eq!(decode_block(&corrupted), None);
+    }
+
+    /// A table holding only range tombstones is reopened with them, and its keys span their
+    /// ranges. Its pages are freed with it.
+    #[test]
+    fn write_range_tombstones_only() {
+        let mut pager = create_pager();
+        let range_tombstones = vec![
+            RangeTombstone {
+                start: entry(10).0,
+                end: entry(20).0,
+            },
+            RangeTombstone {
+                start: entry(50).0,
+                end: entry(60).0,
+            },
+        ];
+
+        let table = Table::write(&mut pager, Vec::new(), range_tombstones.clone(), 10, 0)
+            .expect("write should not fail")
+            .expect("the table should not be empty");
+        let opened = Table::open(&pager, table.footer()).expect("open should not fail");
+
+        assert_eq!(opened, table);
+        assert_eq!(opened.range_tombstones(), range_tombstones.as_slice());
+        assert_eq!(opened.smallest_key(), entry(10).0);
+        assert_eq!(opened.largest_key(), entry(60).0);
+        assert_eq!(opened.iter(&pager, Bound::Unbounded).count(), 0);
+        let page_count = pager.page_count();
+        opened.free(&mut pager).expect("free should not fail");
+        assert_eq!(
+            pager
+                .free_pages()
+                .expect("free_pages should not fail")
+                .len(),
+            page_count as usize - 1
+        );
+    }
+
+    /// Writing no entries does not create a table.
+    #[test]
+    fn write_empty_returns_none() {
+        let mut pager = create_pager();
+
+        let table =
+            Table::write(&mut pager, Vec::new(), Vec::new(), 10, 0).expect("write should not fail");
+
+        assert_eq!(table, None);
+        assert_eq!(pager.page_count(), 1);
+    }
+
+    /// The pages of a freed table are reused by the next table.
+    #[test]
+    fn free_returns_pages_to_pager() {
+        let mut pager = create_pager();
+        let table = write_table(&mut pager, (0..200).map(entry));
+        let page_count = pager.page_count();
+
+        table.free(&mut pager).expect("free should not fail");
+        write_table(&mut pager, (0..200).map(entry));
+
+        assert_eq!(pager.page_count(), page_count);
+    }
+}
@@ -0,0 +1,1638 @@
+use std::ops::{Bound, RangeBounds};
+
+use thiserror::Error;
+
+use crate::btree::{BytewiseComparator, KeyComparator};
+use crate::common::envelope::{Envelope, Versioned};
+use crate::common::KeyRange;
+use crate::fs::File;
+use crate::pager::{PageId, Pager, PagerError};
+
+use super::filter::FilterCounters;
+use super::memtable::Memtable;
+use super::merge::{self, MergeOperator};
+use super::range::{Range, Source};
+use super::range_tombstone::{self, RangeTombstone};
+use super::scheduler::SchedulerHandle;
+use super::stats::{self, LevelStats};
+use super::table::{self, Entry, Table, TableWriter};
+use super::{Compaction, CompactionPolicy, FilterStats, LeveledCompaction, TableInfo};
+
+/// Magic bytes at the start of the manifest page of an LSM tree, which records the sequence
+/// number of its last write after the number of levels.
+const MANIFEST_MAGIC: [u8; 8] = *b"ROUILSM1";
+
+/// Represents errors that can occur during LSM tree operations.
+#[derive(Error, Debug)]
+pub enum LsmError {
+    /// Indicates that an operation on the underlying pager failed.
+    #[error(transparent)]
+    Pager(#[from] PagerError),
+
+    /// Indicates that a page does not contain a valid table page or manifest.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page that could not be decoded.
+    #[error("The page ({0}) does not contain a valid LSM tree page.")]
+    CorruptedPage(PageId),
+
+    /// Indicates that a table was written with a version of the table format that is not
+    /// supported.
+    ///
+    /// # Fields
+    /// - `page` - The footer page of the table.
+    /// - `version` - The version of the format of the table.
+    #[error("The table at page {page} uses the unsupported format version {version}.")]
+    UnsupportedTableVersion { page: PageId, version: u16 },
+
+    /// Indicates that the file is ordered by a comparator other than the bytewise order used by
+    /// LSM trees.
+    ///
+    /// # Fields
+    /// - `0` - The name of the comparator stored in the file.
+    #[error("The file is ordered by the comparator \"{0}\", but LSM trees are ordered bytewise.")]
+    ComparatorMismatch(String),
+
+    /// Indicates that the list of tables does not fit in the manifest page.
+    ///
+    /// # Fields
+    /// - `0` - The number of tables of the tree.
+    #[error("The manifest page can't list the {0} tables of the tree.")]
+    TooManyTables(usize),
+
+    /// Indicates that merge operands must be applied but no [MergeOperator] was set with
+    /// [LsmTree::set_merge_operator].
+    #[error("The tree holds merge operands, but no merge operator is set.")]
+    MissingMergeOperator,
+
+    /// Indicates that a key-value pair is too large to be stored in a table page.
+    #[error("The entry is too large. The key is {key_size} bytes and the value is {value_size} bytes, but key and value must be at most {max_entry_size} bytes.")]
+    EntryTooLarge {
+        key_size: usize,
+        value_size: usize,
+        max_entry_size: usize,
+    },
+}
+
+/// Represents a log-structured merge tree stored in the pages of a [Pager].
+///
+/// Like a [BTree](crate::btree::BTree), the tree maps byte-string keys to byte-string values,
+/// ordered bytewise. Instead of updating pages in place, writes go to an in-memory memtable. When
+/// the memtable grows past [LsmTree::memtable_size], it is written as a new immutable sorted table
+/// of level `0`. The tables are then merged into the deeper levels as decided by the
+/// [CompactionPolicy] of the tree. This trades slower reads for cheaper writes, which suits
+/// write-heavy workloads.
+///
+/// Each write is recorded as an [Envelope] numbered with a sequence number, in the memtable then in
+/// the tables, so a deletion is a tombstone that hides the older values of its key until a
+/// compaction proves no older table holds one. The tables are listed in a manifest page that never
+/// moves, so the tree can be reopened with [LsmTree::open]. The memtable only lives in memory:
+/// writes that were not flushed with [LsmTree::flush] are lost when the tree is dropped.
+pub struct LsmTree {
+    manifest: PageId,
+    memtable: Memtable,
+    /// The range tombstones of the memtable, sorted and without overlaps.
+    range_tombstones: Vec<RangeTombstone>,
+    /// The tables of each level, from the most recent to the oldest.
+    levels: Vec<Vec<Table>>,
+    memtable_size: usize,
+    compaction_policy: Box<dyn CompactionPolicy>,
+    filter_bits_per_key: usize,
+    prefix_length: usize,
+    filter_counters: FilterCounters,
+    merge_operator: Option<Box<dyn MergeOperator>>,
+    /// The scheduler running the compactions in the background, if one is started.
+    scheduler: Option<SchedulerHandle>,
+}
+
+impl LsmTree {
+    /// The memtable size used when none is set.
+    pub const DEFAULT_MEMTABLE_SIZE: usize = 1024 * 1024;
+
+    /// The number of bits per key of the table filters used when none is set, for a false
+    /// positive rate of about 1%.
+    pub const DEFAULT_FILTER_BITS_PER_KEY: usize = 10;
+
+    /// Creates a new, empty, tree with its manifest in a newly allocated page.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if the manifest
+    /// page can't be allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::lsm::LsmTree;
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut tree = LsmTree::create(&mut pager).expect("create should not fail");
+    /// tree.insert(&mut pager, b"key", b"value").expect("insert should not fail");
+    /// tree.flush(&mut pager).expect("flush should not fail");
+    ///
+    /// let value = tree.get(&pager, b"key").expect("get should not fail");
+    /// assert_eq!(value, Some(b"value".to_vec()));
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, LsmError> {
+        if pager.comparator().is_empty() {
+            pager.set_comparator(BytewiseComparator.name())?;
+        }
+        Self::check_comparator(pager)?;
+
+        let tree = LsmTree {
+            manifest: pager.allocate_page()?,
+            memtable: Memtable::new(),
+            range_tombstones: Vec::new(),
+            levels: vec![Vec::new()],
+            memtable_size: Self::DEFAULT_MEMTABLE_SIZE,
+            compaction_policy: Box::new(LeveledCompaction::default()),
+            filter_bits_per_key: Self::DEFAULT_FILTER_BITS_PER_KEY,
+            prefix_length: 0,
+            filter_counters: FilterCounters::default(),
+            merge_operator: None,
+            scheduler: None,
+        };
+        tree.write_manifest(pager)?;
+        Ok(tree)
+    }
+
+    /// Opens a tree previously created with [LsmTree::create].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the file is not ordered bytewise
+    /// - the manifest or a table can't be read or is corrupted
+    /// - a table uses an unsupported version of the table format
+    pub fn open<F: File>(pager: &Pager<F>, manifest: PageId) -> Result<Self, LsmError> {
+        Self::check_comparator(pager)?;
+
+        let corrupted = || LsmError::CorruptedPage(manifest);
+        let page = pager.read_page(manifest)?;
+        if page[..MANIFEST_MAGIC.len()] != MANIFEST_MAGIC {
+            return Err(corrupted());
+        }
+        let mut offset = MANIFEST_MAGIC.len();
+        let mut read_u32 = || {
+            let bytes = page.get(offset..offset + 4).ok_or_else(corrupted)?;
+            offset += 4;
+            Ok::<_, LsmError>(u32::from_le_bytes(
+                bytes.try_into().expect("slice should be 4 bytes"),
+            ))
+        };
+        let level_count = read_u32()?;
+        let last_sequence = u64::from(read_u32()?) | u64::from(read_u32()?) << 32;
+        let mut levels = Vec::new();
+        for _ in 0..level_count {
+            let table_count = read_u32()?;
+            let tables = (0..table_count)
+                .map(|_| Table::open(pager, read_u32()?))
+                .collect::<Result<Vec<_>, LsmError>>()?;
+            levels.push(tables);
+        }
+        if levels.is_empty() {
+            return Err(corrupted());
+        }
+        Ok(LsmTree {
+            manifest,
+            memtable: Memtable::with_last_sequence(last_sequence),
+            range_tombstones: Vec::new(),
+            levels,
+            memtable_size: Self::DEFAULT_MEMTABLE_SIZE,
+            compaction_policy: Box::new(LeveledCompaction::default()),
+            filter_bits_per_key: Self::DEFAULT_FILTER_BITS_PER_KEY,
+            prefix_length: 0,
+            filter_counters: FilterCounters::default(),
+            merge_operator: None,
+            scheduler: None,
+        })
+    }
+
+    /// Returns the identifier of the manifest page of the tree.
+    pub fn manifest(&self) -> PageId {
+        self.manifest
+    }
+
+    /// Returns the sequence number of the last write. Each insertion, deletion or merge operand
+    /// is numbered after the previous one, and the numbering goes on from the tables when the
+    /// tree is reopened.
+    pub fn last_sequence(&self) -> u64 {
+        self.memtable.last_sequence()
+    }
+
+    /// Returns the size, in bytes, above which the memtable is written to a new table.
+    pub fn memtable_size(&self) -> usize {
+        self.memtable_size
+    }
+
+    /// Sets the size, in bytes, above which the memtable is written to a new table. A larger
+    /// memtable produces fewer, larger, tables. The memtable size is not stored in the file and
+    /// must be set again after the tree is opened.
+    pub fn set_memtable_size(&mut self, memtable_size: usize) {
+        self.memtable_size = memtable_size;
+    }
+
+    /// Returns the number of bits per key of the filters of the new tables.
+    pub fn filter_bits_per_key(&self) -> usize {
+        self.filter_bits_per_key
+    }
+
+    /// Sets the number of bits per key of the filters of the tables written from now on, or `0`
+    /// to write tables without a filter. More bits make the filters larger but rule out more
+    /// absent keys. The existing tables keep their filter until they are compacted. The setting is
+    /// not stored in the file and must be set again after the tree is opened.
+    pub fn set_filter_bits_per_key(&mut self, bits_per_key: usize) {
+        self.filter_bits_per_key = bits_per_key;
+    }
+
+    /// Returns the length of the key prefixes added to the filters of the new tables, or `0` if
+    /// none are.
+    pub fn prefix_length(&self) -> usize {
+        self.prefix_length
+    }
+
+    /// Sets the length of the key prefixes added to the filters of the tables written from now on,
+    /// or `0` to add none. [LsmTree::prefix] skips the tables whose filter rules out a prefix at
+    /// least that long. The existing tables keep their filter until they are compacted. The setting
+    /// is not stored in the file and must be set again after the tree is opened.
+    pub fn set_prefix_length(&mut self, prefix_length: usize) {
+        self.prefix_length = prefix_length;
+    }
+
+    /// Returns how the table filters were used by the lookups since the tree was created or
+    /// opened.
+    pub fn filter_stats(&self) -> FilterStats {
+        self.filter_counters.stats()
+    }
+
+    /// Sets the policy deciding when and how the tables are merged. The default is a
+    /// [LeveledCompaction]. The policy is not stored in the file and must be set again after the
+    /// tree is opened.
+    ///
+    /// The compactions picked by the policy run at the end of [LsmTree::flush], before it returns,
+    /// since every operation writing to the tree borrows the pager exclusively.
+    ///
+    /// # Panics
+    ///
+    /// The next flush will panic if the policy picks a compaction referring to a level or a table
+    /// that does not exist.
+    pub fn set_compaction_policy(&mut self, policy: Box<dyn CompactionPolicy>) {
+        self.compaction_policy = policy;
+    }
+
+    /// Sets the operator applying the operands written with [LsmTree::merge]. The operator is not
+    /// stored in the file and must be set again, before the operands are read or compacted, after
+    /// the tree is opened.
+    pub fn set_merge_operator(&mut self, operator: Box<dyn MergeOperator>) {
+        self.merge_operator = Some(operator);
+    }
+
+    /// Returns the number of tables on disk.
+    pub fn table_count(&self) -> usize {
+        self.levels.iter().map(Vec::len).sum()
+    }
+
+    /// Reads every entry of the tables and measures each level, starting with level `0`: its
+    /// tables, their entries and their size. The entries of the memtable are not counted.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a data block can't be read or is corrupted.
+    pub fn analyze<F: File>(&self, pager: &Pager<F>) -> Result<Vec<LevelStats>, LsmError> {
+        self.levels
+            .iter()
+            .map(|tables| stats::analyze_level(pager, tables))
+            .collect()
+    }
+
+    /// Returns the number of tables of each level, starting with level `0`.
+    pub fn level_table_counts(&self) -> Vec<usize> {
+        self.levels.iter().map(Vec::len).collect()
+    }
+
+    /// Returns the largest combined size of a key and its value that can be stored in a tree
+    /// using pages of the given size.
+    pub fn max_entry_size(page_size: usize) -> usize {
+        table::max_entry_size(page_size)
+    }
+
+    /// Returns the value associated with a key. The memtable is searched first, then the tables
+    /// whose keys span the key, from the most recent to the oldest. The data block of a table is
+    /// only read if the filter of the table does not rule the key out. The search stops at the
+    /// first value, tombstone or range tombstone covering the key found, and the merge operands
+    /// found before it are applied to it.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted, or if merge
+    /// operands must be applied but no merge operator is set.
+    pub fn get<F: File>(&self, pager: &Pager<F>, key: &[u8]) -> Result<Option<Vec<u8>>, LsmError> {
+        let mut found = self
+            .memtable
+            .get(key)
+            .map(|version| version.envelope.clone());
+        let mut masked = range_tombstone::covers(&self.range_tombstones, key);
+        for table in self.tables().filter(|table| table.may_contain(key)) {
+            if masked || !matches!(found, None | Some(Envelope::Merge(_))) {
+                break;
+            }
+            if let Some(older) = self.get_from_table(pager, table, key)? {
+                found = Some(match found {
+                    Some(newer) => {
+                        merge::combine(self.merge_operator(), key, newer, &older.envelope)?
+                    }
+                    None => older.envelope,
+                });
+            }
+            masked = range_tombstone::covers(table.range_tombstones(), key);
+        }
+        match found {
+            Some(envelope) => merge::resolve(self.merge_operator(), key, envelope),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the values associated with several keys, in the order of the keys, as
+    /// [LsmTree::get] would. The keys are sorted and each table is searched once for all the keys
+    /// it may hold: its filter is checked for each of them, and each data block holding some of
+    /// them is read once.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted, or if merge
+    /// operands must be applied but no merge operator is set.
+    pub fn multi_get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Vec<u8>>>, LsmError> {
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by_key(|&index| keys[index]);
+        let mut found: Vec<Option<Envelope>> = keys
+            .iter()
+            .map(|key| {
+                self.memtable
+                    .get(key)
+                    .map(|version| version.envelope.clone())
+            })
+            .collect();
+        let mut masked: Vec<bool> = keys
+            .iter()
+            .map(|key| range_tombstone::covers(&self.range_tombstones, key))
+            .collect();
+        for table in self.tables() {
+            // The keys the table may hold whose search is not over yet, in key order.
+            let searched: Vec<usize> = order
+                .iter()
+                .copied()
+                .filter(|&index| {
+                    table.may_contain(keys[index])
+                        && !masked[index]
+                        && matches!(found[index], None | Some(Envelope::Merge(_)))
+                })
+                .collect();
+            let filter = table.filter();
+            let read: Vec<usize> = searched
+                .iter()
+                .copied()
+                .filter(|&index| {
+                    let may_contain = filter.is_none_or(|filter| filter.may_contain(keys[index]));
+                    if !may_contain {
+                        self.filter_counters.record(false, false);
+                    }
+                    may_contain
+                })
+                .collect();
+            let read_keys: Vec<&[u8]> = read.iter().map(|&index| keys[index]).collect();
+            let records = table.multi_get(pager, &read_keys)?;
+            for (&index, record) in read.iter().zip(records) {
+                if filter.is_some() {
+                    self.filter_counters.record(true, record.is_some());
+                }
+                if let Some(older) = record {
+                    found[index] = Some(match found[index].take() {
+                        Some(newer) => merge::combine(
+                            self.merge_operator(),
+                            keys[index],
+                            newer,
+                            &older.envelope,
+                        )?,
+                        None => older.envelope,
+                    });
+                }
+            }
+            for &index in &searched {
+                masked[index] = range_tombstone::covers(table.range_tombstones(), keys[index]);
+            }
+        }
+        keys.iter()
+            .zip(found)
+            .map(|(key, envelope)| match envelope {
+                Some(envelope) => merge::resolve(self.merge_operator(), key, envelope),
+                None => Ok(None),
+            })
+            .collect()
+    }
+
+    /// Returns an iterator over the entries whose keys are within `range`, in key order.
+    pub fn range<'a, F, K, R>(&'a self, pager: &'a Pager<F>, range: R) -> Range<'a>
+    where
+        F: File,
+        K: AsRef<[u8]> + ?Sized,
+        R: RangeBounds<K>,
+    {
+        let start = range.start_bound().map(|key| key.as_ref());
+        let end = range.end_bound().map(|key| key.as_ref().to_vec());
+        self.range_of_tables(pager, start, end, |_| true)
+    }
+
+    /// Returns an iterator over the entries whose keys start with `prefix`, in key order.
+    ///
+    /// The tables whose key range or filter rule the prefix out are not read, only their range
+    /// tombstones are used. The filters only rule out prefixes at least as long as the prefix
+    /// length of the tree (see [LsmTree::set_prefix_length]).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::lsm::LsmTree;
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    /// let mut tree = LsmTree::create(&mut pager).expect("create should not fail");
+    /// tree.set_prefix_length(4);
+    /// for key in [b"user:1", b"user:2", b"page:1"] {
+    ///     tree.insert(&mut pager, key, b"").expect("insert should not fail");
+    /// }
+    /// tree.flush(&mut pager).expect("flush should not fail");
+    ///
+    /// let keys: Vec<Vec<u8>> = tree
+    ///     .prefix(&pager, b"user")
+    ///     .map(|entry| entry.expect("iteration should not fail").0)
+    ///     .collect();
+    ///
+    /// assert_eq!(keys, vec![b"user:1".to_vec(), b"user:2".to_vec()]);
+    /// ```
+    pub fn prefix<'a, F: File>(&'a self, pager: &'a Pager<F>, prefix: &[u8]) -> Range<'a> {
+        let (_, end) = KeyRange::prefix(prefix).into_bounds();
+        self.range_of_tables(pager, Bound::Included(prefix), end, |table| {
+            table.may_contain_prefix(prefix)
+        })
+    }
+
+    /// Returns an iterator over the entries within a range, reading the memtable and the tables
+    /// for which `read_table` returns `true`. The range tombstones of every table are used.
+    fn range_of_tables<'a, F: File>(
+        &'a self,
+        pager: &'a Pager<F>,
+        start: Bound<&[u8]>,
+        end: Bound<Vec<u8>>,
+        read_table: impl Fn(&Table) -> bool,
+    ) -> Range<'a> {
+        let mut sources: Vec<Source<'a>> = Vec::with_capacity(self.table_count() + 1);
+        let mut range_tombstones = Vec::with_capacity(self.table_count() + 1);
+        sources.push(Box::new(
+            self.memtable
+                .range(start, end.as_ref().map(Vec::as_slice))
+                .map(Ok),
+        ));
+        range_tombstones.push(self.range_tombstones.as_slice());
+        for table in self.tables() {
+            if read_table(table) {
+                sources.push(Box::new(table.iter(pager, start)));
+            } else {
+                sources.push(Box::new(std::iter::empty()));
+            }
+            range_tombstones.push(table.range_tombstones());
+        }
+        Range::new(sources, range_tombstones, end, self.merge_operator())
+    }
+
+    /// Returns an iterator over all the entries of the tree, in key order.
+    pub fn iter<'a, F: File>(&'a self, pager: &'a Pager<F>) -> Range<'a> {
+        self.range::<F, [u8], _>(pager, ..)
+    }
+
+    /// Sets the value of a key. The previous value is not read, which keeps writes cheap.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the key and the value are too large (see [LsmTree::max_entry_size])
+    /// - the memtable is full and can't be written to a new table
+    pub fn insert<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), LsmError> {
+        Self::check_entry_size(pager.page_size(), key, value)?;
+        self.memtable.put(key, Envelope::Value(value.to_vec()));
+        self.flush_if_full(pager)
+    }
+
+    /// Removes a key from the tree. A tombstone hiding the older values of the key is written
+    /// without reading them.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key is too large (see [LsmTree::max_entry_size])
+    /// or if the memtable is full and can't be written to a new table.
+    pub fn delete<F: File>(&mut self, pager: &mut Pager<F>, key: &[u8]) -> Result<(), LsmError> {
+        Self::check_entry_size(pager.page_size(), key, &[])?;
+        self.memtable.put(key, Envelope::Tombstone);
+        self.flush_if_full(pager)
+    }
+
+    /// Removes all the keys from `start`, included, to `end`, excluded, without reading them. A
+    /// single range tombstone hiding the older values of the keys of the range is written, and
+    /// the keys of the range in the memtable are replaced by tombstones. Nothing is removed if
+    /// `start` is not smaller than `end`.
+    ///
+    /// The range tombstone is written to the tables with the memtable and is carried by the
+    /// compactions until no older table holds keys of its range.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if `start` and `end` are too large (see
+    /// [LsmTree::max_entry_size]) or if the memtable is full and can't be written to a new table.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::lsm::LsmTree;
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut tree = LsmTree::create(&mut pager).expect("create should not fail");
+    /// tree.insert(&mut pager, b"log/1", b"a").expect("insert should not fail");
+    /// tree.insert(&mut pager, b"log/2", b"b").expect("insert should not fail");
+    /// tree.insert(&mut pager, b"user/1", b"c").expect("insert should not fail");
+    /// tree.flush(&mut pager).expect("flush should not fail");
+    /// tree.delete_range(&mut pager, b"log/", b"log0").expect("delete_range should not fail");
+    ///
+    /// let keys: Vec<Vec<u8>> = tree
+    ///     .iter(&pager)
+    ///     .map(|entry| entry.expect("iteration should not fail").0)
+    ///     .collect();
+    /// assert_eq!(keys, vec![b"user/1".to_vec()]);
+    /// ```
+    pub fn delete_range<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        start: &[u8],
+        end: &[u8],
+    ) -> Result<(), LsmError> {
+        if start >= end {
+            return Ok(());
+        }
+        // Both bounds may become the smallest or the largest key of a table.
+        Self::check_entry_size(pager.page_size(), start, end)?;
+
+        // The entries of the memtable are more recent than its range tombstones.
+        let keys: Vec<Vec<u8>> = self
+            .memtable
+            .range(Bound::Included(start), Bound::Excluded(end))
+            .filter(|(_, version)| !version.envelope.is_tombstone())
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            self.memtable.put(&key, Envelope::Tombstone);
+        }
+        let mut range_tombstones = std::mem::take(&mut self.range_tombstones);
+        range_tombstones.push(RangeTombstone {
+            start: start.to_vec(),
+            end: end.to_vec(),
+        });
+        self.range_tombstones = range_tombstone::normalize(range_tombstones);
+        self.flush_if_full(pager)
+    }
+
+    /// Applies an operand to the value of a key with the [MergeOperator] of the tree, without
+    /// reading the value from the tables. The operand is combined with the record of the key in
+    /// the memtable, if there is one, and is otherwise applied when the key is read or compacted.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - no merge operator is set
+    /// - the key and the operand, or the key and the combined operand, are too large (see
+    ///   [LsmTree::max_entry_size])
+    /// - the memtable is full and can't be written to a new table
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::lsm::{LsmTree, U64AddOperator};
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut tree = LsmTree::create(&mut pager).expect("create should not fail");
+    /// tree.set_merge_operator(Box::new(U64AddOperator));
+    /// tree.merge(&mut pager, b"visits", &1u64.to_le_bytes()).expect("merge should not fail");
+    /// tree.flush(&mut pager).expect("flush should not fail");
+    /// tree.merge(&mut pager, b"visits", &2u64.to_le_bytes()).expect("merge should not fail");
+    ///
+    /// let value = tree.get(&pager, b"visits").expect("get should not fail");
+    /// assert_eq!(value, Some(3u64.to_le_bytes().to_vec()));
+    /// ```
+    pub fn merge<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        operand: &[u8],
+    ) -> Result<(), LsmError> {
+        let operator = self
+            .merge_operator()
+            .ok_or(LsmError::MissingMergeOperator)?;
+        Self::check_entry_size(pager.page_size(), key, operand)?;
+        let mut envelope = Envelope::Merge(operand.to_vec());
+        let older = match self.memtable.get(key) {
+            Some(older) => Some(&older.envelope),
+            None if range_tombstone::covers(&self.range_tombstones, key) => {
+                Some(&Envelope::Tombstone)
+            }
+            None => None,
+        };
+        if let Some(older) = older {
+            envelope = merge::combine(Some(operator), key, envelope, older)?;
+            Self::check_entry_size(pager.page_size(), key, envelope.payload())?;
+        }
+        self.memtable.put(key, envelope);
+        self.flush_if_full(pager)
+    }
+
+    /// Writes the memtable and its range tombstones to a new table of level `0`, then runs the
+    /// compactions picked by the [CompactionPolicy] until it does not pick any. If a
+    /// [CompactionScheduler](super::CompactionScheduler) is started, the compactions are only
+    /// requested and the scheduler runs them after the flush returns.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - a page can't be read, written, allocated or freed
+    /// - the tables no longer fit in the manifest page
+    pub fn flush<F: File>(&mut self, pager: &mut Pager<F>) -> Result<(), LsmError> {
+        if self.memtable.is_empty() && self.range_tombstones.is_empty() {
+            return Ok(());
+        }
+
+        let entries = self.memtable.range(Bound::Unbounded, Bound::Unbounded);
+        let range_tombstones = std::mem::take(&mut self.range_tombstones);
+        if let Some(table) = Table::write(
+            pager,
+            entries,
+            range_tombstones,
+            self.filter_bits_per_key,
+            self.prefix_length,
+        )? {
+            self.levels[0].insert(0, table);
+        }
+        self.memtable.clear();
+        self.write_manifest(pager)?;
+
+        if self
+            .scheduler
+            .as_ref()
+            .is_some_and(|scheduler| scheduler.request())
+        {
+            return Ok(());
+        }
+        while self.compact_once(pager)? {}
+        Ok(())
+    }
+
+    /// Runs the next compaction picked by the [CompactionPolicy], and returns `false` if it does not
+    /// pick any.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - a page can't be read, written, allocated or freed
+    /// - merge operands must be applied but no merge operator is set
+    /// - the tables no longer fit in the manifest page
+    pub fn compact_once<F: File>(&mut self, pager: &mut Pager<F>) -> Result<bool, LsmError> {
+        let Some(compaction) = self.compaction_policy.pick(&self.table_infos()) else {
+            return Ok(false);
+        };
+        self.run_compaction(pager, compaction)?;
+        Ok(true)
+    }
+
+    /// Sets the scheduler the flushes request the compactions from.
+    pub(super) fn set_scheduler(&mut self, scheduler: SchedulerHandle) {
+        self.scheduler = Some(scheduler);
+    }
+
+    /// Merges all the tables into a single one in the deepest level. Only the most recent value
+    /// of each key is kept, the merge operands are applied and the deleted keys and the range
+    /// tombstones are dropped. The memtable is not flushed.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - a page can't be read, written, allocated or freed
+    /// - merge operands must be applied but no merge operator is set
+    /// - a value produced by the merge operator is too large (see [LsmTree::max_entry_size])
+    pub fn compact<F: File>(&mut self, pager: &mut Pager<F>) -> Result<(), LsmError> {
+        if self.table_count() < 2 {
+            return Ok(());
+        }
+
+        let tables: Vec<&Table> = self.tables().collect();
+        let (entries, range_tombstones) = self.merge_tables(pager, &tables, false)?;
+        let merged = self.write_tables(pager, entries, range_tombstones, None)?;
+
+        let depth = self.levels.len().max(2);
+        let mut levels = vec![Vec::new(); depth];
+        levels[depth - 1] = merged;
+        let old_levels = std::mem::replace(&mut self.levels, levels);
+        self.write_manifest(pager)?;
+        for table in old_levels.iter().flatten() {
+            table.free(pager)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the tables of all the levels, from the most recent to the oldest.
+    fn tables(&self) -> impl Iterator<Item = &Table> {
+        self.levels.iter().flatten()
+    }
+
+    /// Returns the description of the tables of each level given to the [CompactionPolicy].
+    fn table_infos(&self) -> Vec<Vec<TableInfo>> {
+        self.levels
+            .iter()
+            .map(|tables| tables.iter().map(Table::info).collect())
+            .collect()
+    }
+
+    /// Merges tables of a level with tables of the next level and replaces them by the new
+    /// tables at the front of the next level.
+    fn run_compaction<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        compaction: Compaction,
+    ) -> Result<(), LsmError> {
+        let Compaction {
+            level,
+            mut inputs,
+            mut overlapping,
+            max_table_blocks,
+        } = compaction;
+        inputs.sort_unstable();
+        inputs.dedup();
+        overlapping.sort_unstable();
+        overlapping.dedup();
+        if self.levels.len() == level + 1 {
+            self.levels.push(Vec::new());
+        }
+
+        // The inputs are more recent than the tables of the next level they are merged with.
+        let tables: Vec<&Table> = inputs
+            .iter()
+            .map(|&index| &self.levels[level][index])
+            .chain(
+                overlapping
+                    .iter()
+                    .map(|&index| &self.levels[level + 1][index]),
+            )
+            .collect();
+        let (Some(smallest), Some(largest)) = (
+            tables.iter().map(|table| table.smallest_key()).min(),
+            tables.iter().map(|table| table.largest_key()).max(),
+        ) else {
+            return Ok(());
+        };
+
+        // A tombstone can only be dropped if no older table left out of the merge may hold a
+        // value of its key.
+        let mut left_out = self.levels[level + 1]
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !overlapping.contains(index))
+            .map(|(_, table)| table)
+            .chain(self.levels[level + 2..].iter().flatten());
+        let keep_tombstones = left_out
+            .any(|table| table.smallest_key() <= largest && table.largest_key() >= smallest);
+        let (entries, range_tombstones) = self.merge_tables(pager, &tables, keep_tombstones)?;
+        let merged = self.write_tables(pager, entries, range_tombstones, max_table_blocks)?;
+
+        let mut old_tables = Vec::with_capacity(inputs.len() + overlapping.len());
+        for &index in inputs.iter().rev() {
+            old_tables.push(self.levels[level].remove(index));
+        }
+        let next_level = &mut self.levels[level + 1];
+        for &index in overlapping.iter().rev() {
+            old_tables.push(next_level.remove(index));
+        }
+        next_level.splice(0..0, merged);
+        self.write_manifest(pager)?;
+        for table in old_tables {
+            table.free(pager)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the entries of tables ordered from the most recent to the oldest, keeping only the
+    /// most recent entry of each key, and returns them with the range tombstones of the tables.
+    /// Without `keep_tombstones`, no older table may hold a value of the keys: the tombstones and
+    /// the range tombstones are dropped and the merge operands are applied to a missing value.
+    fn merge_tables<F: File>(
+        &self,
+        pager: &Pager<F>,
+        tables: &[&Table],
+        keep_tombstones: bool,
+    ) -> Result<(Vec<Entry>, Vec<RangeTombstone>), LsmError> {
+        let sources: Vec<Source<'_>> = tables
+            .iter()
+            .map(|table| Box::new(table.iter(pager, Bound::Unbounded)) as Source<'_>)
+            .collect();
+        let range_tombstones = tables
+            .iter()
+            .map(|table| table.range_tombstones())
+            .collect();
+        let entry_count: u64 = tables.iter().map(|table| table.entry_count()).sum();
+        let mut entries = Vec::with_capacity(entry_count as usize);
+        let mut merged = Range::new(
+            sources,
+            range_tombstones,
+            Bound::Unbounded,
+            self.merge_operator(),
+        );
+        while let Some((key, version)) = merged.next_entry()? {
+            if keep_tombstones {
+                entries.push((key, version));
+            } else if let Some(value) =
+                merge::resolve(self.merge_operator(), &key, version.envelope)?
+            {
+                entries.push((
+                    key,
+                    Versioned::new(version.sequence, Envelope::Value(value)),
+                ));
+            }
+        }
+        let range_tombstones = if keep_tombstones {
+            range_tombstone::normalize(
+                tables
+                    .iter()
+                    .flat_map(|table| table.range_tombstones().iter().cloned())
+                    .collect(),
+            )
+        } else {
+            Vec::new()
+        };
+        Ok((entries, range_tombstones))
+    }
+
+    /// Writes sorted entries and normalized range tombstones to new tables, starting a new table
+    /// after `max_table_blocks` data blocks. Each table holds the parts of the range tombstones
+    /// from its first key to the first key of the next table, so the new tables do not overlap.
+    fn write_tables<F: File>(
+        &self,
+        pager: &mut Pager<F>,
+        entries: Vec<Entry>,
+        range_tombstones: Vec<RangeTombstone>,
+        max_table_blocks: Option<usize>,
+    ) -> Result<Vec<Table>, LsmError> {
+        let mut tables = Vec::new();
+        let mut writer = TableWriter::new(self.filter_bits_per_key, self.prefix_length);
+        let mut first_key: Option<Vec<u8>> = None;
+        for (key, version) in entries {
+            // The merge operator may produce values too large for a page.
+            Self::check_entry_size(pager.page_size(), &key, version.envelope.payload())?;
+            if max_table_blocks.is_some_and(|max| writer.block_count() >= max.max(1)) {
+                let full = std::mem::replace(
+                    &mut writer,
+                    TableWriter::new(self.filter_bits_per_key, self.prefix_length),
+                );
+                let range = KeyRange::new(
+                    first_key.map_or(Bound::Unbounded, Bound::Included),
+                    Bound::Excluded(key.clone()),
+                );
+                let parts = range_tombstone::clip(&range_tombstones, &range);
+                tables.extend(full.finish(pager, parts)?);
+                first_key = Some(key.clone());
+            }
+            writer.add(pager, &key, &version)?;
+        }
+        let range = KeyRange::new(
+            first_key.map_or(Bound::Unbounded, Bound::Included),
+            Bound::Unbounded,
+        );
+        let parts = range_tombstone::clip(&range_tombstones, &range);
+        tables.extend(writer.finish(pager, parts)?);
+        Ok(tables)
+    }
+
+    /// Returns the version of a key in a table, or `None` if the table does not contain the key.
+    /// The data block is not read if the filter of the table rules the key out.
+    fn get_from_table<F: File>(
+        &self,
+        pager: &Pager<F>,
+        table: &Table,
+        key: &[u8],
+    ) -> Result<Option<Versioned>, LsmError> {
+        let filter = table.filter();
+        if filter.is_some_and(|filter| !filter.may_contain(key)) {
+            self.filter_counters.record(false, false);
+            return Ok(None);
+        }
+        let entry = table.get(pager, key)?;
+        if filter.is_some() {
+            self.filter_counters.record(true, entry.is_some());
+        }
+        Ok(entry)
+    }
+
+    fn merge_operator(&self) -> Option<&dyn MergeOperator> {
+        self.merge_operator.as_deref()
+    }
+
+    fn flush_if_full<F: File>(&mut self, pager: &mut Pager<F>) -> Result<(), LsmError> {
+        if self.memtable.size() >= self.memtable_size {
+            self.flush(pager)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the sequence number of the last write and the list of tables of each level, from the
+    /// most recent to the oldest, to the manifest page.
+    fn write_manifest<F: File>(&self, pager: &mut Pager<F>) -> Result<(), LsmError> {
+        let mut page = Vec::with_capacity(pager.page_size());
+        page.extend_from_slice(&MANIFEST_MAGIC);
+        page.extend_from_slice(&(self.levels.len() as u32).to_le_bytes());
+        page.extend_from_slice(&self.memtable.last_sequence().to_le_bytes());
+        for tables in &self.levels {
+            page.extend_from_slice(&(tables.len() as u32).to_le_bytes());
+            for table in tables {
+                page.extend_from_slice(&table.footer().to_le_bytes());
+            }
+        }
+        if page.len() > pager.page_size() {
+            return Err(LsmError::TooManyTables(self.table_count()));
+        }
+        page.resize(pager.page_size(), 0);
+        pager.write_page(self.manifest, &page)?;
+        Ok(())
+    }
+
+    /// Checks that the keys of the file are ordered bytewise.
+    fn check_comparator<F: File>(pager: &Pager<F>) -> Result<(), LsmError> {
+        if pager.comparator() != BytewiseComparator.name() {
+            return Err(LsmError::ComparatorMismatch(pager.comparator().to_string()));
+        }
+        Ok(())
+    }
+
+    /// Checks that a key-value pair is small enough to be stored in a tree using pages of the given
+    /// size.
+    fn check_entry_size(page_size: usize, key: &[u8], value: &[u8]) -> Result<(), LsmError> {
+        if key.len() + value.len() > Self::max_entry_size(page_size) {
+            return Err(LsmError::EntryTooLarge {
+                key_size: key.len(),
+                value_size: value.len(),
+                max_entry_size: Self::max_entry_size(page_size),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rand::seq::SliceRandom;
+
+    use crate::btree::{BTree, ReverseComparator};
+    use crate::fs::MemoryFile;
+    use crate::lsm::{SizeTieredCompaction, U64AddOperator};
+
+    use super::*;
+
+    fn create_tree() -> (Pager<MemoryFile>, LsmTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = LsmTree::create(&mut pager).expect("create should not fail");
+        tree.set_memtable_size(1024);
+        (pager, tree)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:06}").into_bytes()
+    }
+
+    fn value(index: usize) -> Vec<u8> {
+        format!("value-{index}").into_bytes()
+    }
+
+    /// A value can be read back from the memtable and after it is flushed.
+    #[test]
+    fn insert_then_get_returns_value() {
+        let (mut pager, mut tree) = create_tree();
+
+        tree.insert(&mut pager, b"key", b"value")
+            .expect("insert should not fail");
+        let before_flush = tree.get(&pager, b"key").expect("get should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+        let after_flush = tree.get(&pager, b"key").expect("get should not fail");
+
+        assert_eq!(before_flush, Some(b"value".to_vec()));
+        assert_eq!(after_flush, Some(b"value".to_vec()));
+        assert_eq!(tree.table_count(), 1);
+    }
+
+    /// A deletion in the memtable hides the value stored in an older table.
+    #[test]
+    fn delete_hides_flushed_value() {
+        let (mut pager, mut tree) = create_tree();
+        tree.insert(&mut pager, b"key", b"value")
+            .expect("insert should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+
+        tree.delete(&mut pager, b"key")
+            .expect("delete should not fail");
+
+        assert_eq!(tree.get(&pager, b"key").expect("get should not fail"), None);
+        assert_eq!(tree.iter(&pager).count(), 0);
+    }
+
+    /// Writes random insertions and deletions, then checks that the tree returns the latest
+    /// value of each key.
+    fn check_random_writes(tree: &mut LsmTree, pager: &mut Pager<MemoryFile>) {
+        let mut reference = BTreeMap::new();
+        let mut indexes: Vec<usize> = (0..1000).chain(0..500).collect();
+        indexes.shuffle(&mut rand::thread_rng());
+
+        for (step, &index) in indexes.iter().enumerate() {
+            if step % 7 == 0 {
+                tree.delete(pager, &key(index))
+                    .expect("delete should not fail");
+                reference.remove(&key(index));
+            } else {
+                let value = [value(index), step.to_le_bytes().to_vec()].concat();
+                tree.insert(pager, &key(index), &value)
+                    .expect("insert should not fail");
+                reference.insert(key(index), value);
+            }
+        }
+
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = tree
+            .iter(pager)
+            .map(|entry| entry.expect("iteration should not fail"))
+            .collect();
+        assert_eq!(entries, reference.clone().into_iter().collect::<Vec<_>>());
+        for index in 0..1000 {
+            let value = tree.get(pager, &key(index)).expect("get should not fail");
+            assert_eq!(value.as_ref(), reference.get(&key(index)));
+        }
+    }
+
+    /// Random writes spread over many levels of the leveled compaction return the latest value of
+    /// each key.
+    #[test]
+    fn random_writes_with_leveled_compaction_match_reference() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_compaction_policy(Box::new(LeveledCompaction {
+            level0_max_tables: 2,
+            base_level_blocks: 8,
+            multiplier: 2,
+            max_table_blocks: 2,
+        }));
+
+        check_random_writes(&mut tree, &mut pager);
+
+        let counts = tree.level_table_counts();
+        assert!(counts[0] <= 2);
+        assert!(counts.len() > 2);
+    }
+
+    /// Random writes merged by the size-tiered compaction return the latest value of each key.
+    #[test]
+    fn random_writes_with_size_tiered_compaction_match_reference() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_compaction_policy(Box::new(SizeTieredCompaction { max_runs: 3 }));
+
+        check_random_writes(&mut tree, &mut pager);
+
+        let counts = tree.level_table_counts();
+        assert!(counts.iter().all(|&count| count < 3));
+        assert!(counts.len() > 2);
+    }
+
+    /// A range only returns the keys within its bounds, from the memtable and the tables.
+    #[test]
+    fn range_merges_memtable_and_tables() {
+        let (mut pager, mut tree) = create_tree();
+        for index in (0..100).step_by(2) {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+        for index in (1..100).step_by(2) {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+
+        let keys: Vec<Vec<u8>> = tree
+            .range(&pager, key(10).as_slice()..key(20).as_slice())
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+
+        assert_eq!(keys, (10..20).map(key).collect::<Vec<_>>());
+    }
+
+    /// Compacting merges all the tables into one and frees the pages of the old tables.
+    #[test]
+    fn compact_merges_tables() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_memtable_size(LsmTree::DEFAULT_MEMTABLE_SIZE);
+        for round in 0..3 {
+            for index in 0..50 {
+                tree.insert(&mut pager, &key(index), &value(index + round))
+                    .expect("insert should not fail");
+            }
+            tree.flush(&mut pager).expect("flush should not fail");
+        }
+
+        tree.compact(&mut pager).expect("compact should not fail");
+        let page_count = pager.page_count();
+        for index in 0..50 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+
+        assert_eq!(tree.level_table_counts(), vec![1, 1]);
+        assert_eq!(pager.page_count(), page_count);
+        assert_eq!(
+            tree.get(&pager, &key(7)).expect("get should not fail"),
+            Some(value(7))
+        );
+    }
+
+    /// A tree can be reopened from its manifest page after it is flushed.
+    #[test]
+    fn open_existing_tree_finds_keys() {
+        let (mut pager, mut tree) = create_tree();
+        for index in 0..500 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+        let manifest = tree.manifest();
+
+        let pager = Pager::open(pager.into_file()).expect("open should not fail");
+        let tree = LsmTree::open(&pager, manifest).expect("open should not fail");
+
+        assert_eq!(
+            tree.get(&pager, &key(123)).expect("get should not fail"),
+            Some(value(123))
+        );
+    }
+
+    /// The filters rule out most absent keys without reading the tables, and their use is
+    /// counted.
+    #[test]
+    fn get_absent_keys_uses_filters() {
+        let (mut pager, mut tree) = create_tree();
+        for index in (0..1000).step_by(2) {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+
+        for index in (1..1000).step_by(2) {
+            assert_eq!(
+                tree.get(&pager, &key(index)).expect("get should not fail"),
+                None
+            );
+        }
+        let stats = tree.filter_stats();
+
+        assert!(stats.skipped_reads > 0);
+        assert_eq!(stats.skipped_reads + stats.false_positives, stats.checks);
+        assert!(stats.false_positive_rate() < 0.05);
+    }
+
+    /// Tables written without a filter are still searched.
+    #[test]
+    fn get_without_filters_finds_keys() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_filter_bits_per_key(0);
+        for index in 0..500 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+
+        let found = tree.get(&pager, &key(42)).expect("get should not fail");
+
+        assert_eq!(found, Some(value(42)));
+        assert_eq!(tree.filter_stats(), FilterStats::default());
+    }
+
+    /// The writes are numbered in order, and a reopened tree numbers its writes after the last one,
+    /// even when a compaction dropped it.
+    #[test]
+    fn open_existing_tree_resumes_sequence() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_compaction_policy(Box::new(SizeTieredCompaction { max_runs: 2 }));
+        for index in 0..300 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+            if index % 100 == 99 {
+                tree.flush(&mut pager).expect("flush should not fail");
+            }
+        }
+        tree.delete(&mut pager, &key(5))
+            .expect("delete should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+        tree.compact(&mut pager).expect("compact should not fail");
+
+        let mut opened = LsmTree::open(&pager, tree.manifest()).expect("open should not fail");
+        let last_sequence = opened.last_sequence();
+        opened
+            .insert(&mut pager, &key(5), &value(5))
+            .expect("insert should not fail");
+
+        assert_eq!(tree.last_sequence(), 301);
+        assert_eq!(last_sequence, 301);
+        assert_eq!(opened.last_sequence(), 302);
+        assert_eq!(
+            opened.get(&pager, &key(5)).expect("get should not fail"),
+            Some(value(5))
+        );
+    }
+
+    /// The levels of the tables are restored when the tree is reopened.
+    #[test]
+    fn open_existing_tree_restores_levels() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_compaction_policy(Box::new(SizeTieredCompaction { max_runs: 2 }));
+        for index in 0..500 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+
+        let opened = LsmTree::open(&pager, tree.manifest()).expect("open should not fail");
+
+        assert_eq!(opened.level_table_counts(), tree.level_table_counts());
+        assert_eq!(opened.iter(&pager).count(), 500);
+    }
+
+    /// An LSM tree can't be created in a file ordered by another comparator.
+    #[test]
+    fn create_in_file_with_other_comparator_fails() {
+        let (mut pager, _) = create_tree();
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut other = Pager::create(file, 512).expect("create should not fail");
+        BTree::create_with_comparator(&mut other, Box::new(ReverseComparator))
+            .expect("create_with_comparator should not fail");
+
+        let result = LsmTree::create(&mut other);
+
+        assert!(LsmTree::create(&mut pager).is_ok());
+        assert!(matches!(result, Err(LsmError::ComparatorMismatch(_))));
+    }
+
+    fn counter(value: u64) -> Vec<u8> {
+        value.to_le_bytes().to_vec()
+    }
+
+    /// Operands written across the memtable and several tables are applied to the value they
+    /// follow, by lookups, iterations and compactions.
+    #[test]
+    fn merge_applies_operands_across_tables() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_merge_operator(Box::new(U64AddOperator));
+        tree.insert(&mut pager, b"counter", &counter(100))
+            .expect("insert should not fail");
+        for round in 0..4 {
+            tree.flush(&mut pager).expect("flush should not fail");
+            for _ in 0..=round {
+                tree.merge(&mut pager, b"counter", &counter(1))
+                    .expect("merge should not fail");
+            }
+        }
+
+        let before_compaction = tree.get(&pager, b"counter").expect("get should not fail");
+        let iterated: Vec<(Vec<u8>, Vec<u8>)> = tree
+            .iter(&pager)
+            .map(|entry| entry.expect("iteration should not fail"))
+            .collect();
+        tree.flush(&mut pager).expect("flush should not fail");
+        tree.compact(&mut pager).expect("compact should not fail");
+        let after_compaction = tree.get(&pager, b"counter").expect("get should not fail");
+
+        assert_eq!(before_compaction, Some(counter(110)));
+        assert_eq!(iterated, vec![(b"counter".to_vec(), counter(110))]);
+        assert_eq!(after_compaction, Some(counter(110)));
+    }
+
+    /// Operands written after a deletion start from a missing value, even when the deleted value
+    /// is in an older table.
+    #[test]
+    fn merge_after_delete_ignores_deleted_value() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_merge_operator(Box::new(U64AddOperator));
+        tree.insert(&mut pager, b"counter", &counter(100))
+            .expect("insert should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+        tree.delete(&mut pager, b"counter")
+            .expect("delete should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+
+        tree.merge(&mut pager, b"counter", &counter(5))
+            .expect("merge should not fail");
+
+        assert_eq!(
+            tree.get(&pager, b"counter").expect("get should not fail"),
+            Some(counter(5))
+        );
+    }
+
+    /// Merging or reading operands fails while no merge operator is set.
+    #[test]
+    fn merge_without_operator_fails() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_merge_operator(Box::new(U64AddOperator));
+        tree.merge(&mut pager, b"counter", &counter(1))
+            .expect("merge should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+        let manifest = tree.manifest();
+
+        let mut tree = LsmTree::open(&pager, manifest).expect("open should not fail");
+        let merged = tree.merge(&mut pager, b"counter", &counter(1));
+        let read = tree.get(&pager, b"counter");
+
+        assert!(matches!(merged, Err(LsmError::MissingMergeOperator)));
+        assert!(matches!(read, Err(LsmError::MissingMergeOperator)));
+    }
+
+    /// The statistics of the levels count the entries of the tables by kind.
+    #[test]
+    fn analyze_counts_entries_of_levels() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_memtable_size(LsmTree::DEFAULT_MEMTABLE_SIZE);
+        for index in 0..50 {
+            tree.insert(&mut pager, &key(index), b"value")
+                .expect("insert should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+        for index in 0..10 {
+            tree.delete(&mut pager, &key(index))
+                .expect("delete should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+
+        let stats = tree.analyze(&pager).expect("analyze should not fail");
+
+        assert_eq!(stats[0].table_count, 2);
+        assert_eq!(stats[0].entry_count, 60);
+        assert_eq!(stats[0].tombstone_count, 10);
+        assert_eq!(stats[0].key_bytes, 60 * 10);
+        assert_eq!(stats[0].value_bytes, 50 * 5);
+    }
+
+    /// A prefix iteration returns the keys with the prefix, and applies the range tombstones of
+    /// the tables whose filter rules the prefix out.
+    #[test]
+    fn prefix_returns_keys_with_prefix() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_prefix_length(5);
+        for index in 0..5 {
+            tree.insert(&mut pager, format!("user:{index}").as_bytes(), b"old")
+                .expect("insert should not fail");
+        }
+        tree.insert(&mut pager, &[b'u', 0xff, 0xff], b"last")
+            .expect("insert should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+        tree.insert(&mut pager, b"page:1", b"new")
+            .expect("insert should not fail");
+        tree.delete_range(&mut pager, b"user:1", b"user:3")
+            .expect("delete_range should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+
+        let keys = |prefix: &[u8]| -> Vec<Vec<u8>> {
+            tree.prefix(&pager, prefix)
+                .map(|entry| entry.expect("iteration should not fail").0)
+                .collect()
+        };
+
+        assert!(tree.tables().all(|table| table.filter().is_some()));
+        assert!(!tree
+            .tables()
+            .next()
+            .expect("the tree should have tables")
+            .may_contain_prefix(b"user:"));
+        assert_eq!(
+            keys(b"user:"),
+            vec![b"user:0".to_vec(), b"user:3".to_vec(), b"user:4".to_vec()]
+        );
+        assert_eq!(keys(&[b'u', 0xff]), vec![vec![b'u', 0xff, 0xff]]);
+        assert_eq!(keys(b"page"), vec![b"page:1".to_vec()]);
+        assert_eq!(keys(b"").len(), 5);
+    }
+
+    /// A range deletion hides the keys of its range in the older tables, but not the keys written
+    /// after it, and is kept when the tree is reopened.
+    #[test]
+    fn delete_range_hides_older_keys() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_memtable_size(LsmTree::DEFAULT_MEMTABLE_SIZE);
+        for index in 0..100 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+        tree.insert(&mut pager, &key(30), b"memtable")
+            .expect("insert should not fail");
+
+        tree.delete_range(&mut pager, &key(20), &key(40))
+            .expect("delete_range should not fail");
+        tree.insert(&mut pager, &key(25), &value(25))
+            .expect("insert should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+        let manifest = tree.manifest();
+        let tree = LsmTree::open(&pager, manifest).expect("open should not fail");
+
+        let keys: Vec<Vec<u8>> = tree
+            .range(&pager, key(10).as_slice()..key(50).as_slice())
+            .map(|entry| entry.expect("iteration should not fail").0)
+            .collect();
+        let expected: Vec<Vec<u8>> = (10..20).chain([25]).chain(40..50).map(key).collect();
+        assert_eq!(keys, expected);
+        assert_eq!(
+            tree.get(&pager, &key(20)).expect("get should not fail"),
+            None
+        );
+        assert_eq!(
+            tree.get(&pager, &key(30)).expect("get should not fail"),
+            None
+        );
+        assert_eq!(
+            tree.get(&pager, &key(25)).expect("get should not fail"),
+            Some(value(25))
+        );
+        assert_eq!(
+            tree.get(&pager, &key(40)).expect("get should not fail"),
+            Some(value(40))
+        );
+        let stats = tree.analyze(&pager).expect("analyze should not fail");
+        assert_eq!(stats[0].range_tombstone_count, 1);
+    }
+
+    /// Compacting applies the range deletions to the older tables and drops them.
+    #[test]
+    fn compact_drops_range_tombstones() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_memtable_size(LsmTree::DEFAULT_MEMTABLE_SIZE);
+        for index in 0..100 {
+            tree.insert(&mut pager, &key(index), &value(index))
+                .expect("insert should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+        tree.delete_range(&mut pager, &key(10), &key(90))
+            .expect("delete_range should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+
+        tree.compact(&mut pager).expect("compact should not fail");
+
+        assert!(tree
+            .tables()
+            .all(|table| table.range_tombstones().is_empty()));
+        assert_eq!(tree.tables().map(Table::entry_count).sum::<u64>(), 20);
+        assert_eq!(tree.iter(&pager).count(), 20);
+    }
+
+    /// Random writes and range deletions spread over many levels of the leveled compaction return
+    /// the latest value of each key.
+    #[test]
+    fn random_range_deletions_with_leveled_compaction_match_reference() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_compaction_policy(Box::new(LeveledCompaction {
+            level0_max_tables: 2,
+            base_level_blocks: 8,
+            multiplier: 2,
+            max_table_blocks: 2,
+        }));
+        let mut reference = BTreeMap::new();
+        let mut indexes: Vec<usize> = (0..1000).chain(0..1000).collect();
+        indexes.shuffle(&mut rand::thread_rng());
+
+        for (step, &index) in indexes.iter().enumerate() {
+            if step % 50 == 0 {
+                tree.delete_range(&mut pager, &key(index), &key(index + 30))
+                    .expect("delete_range should not fail");
+                reference.retain(|k, _| *k < key(index) || *k >= key(index + 30));
+            } else {
+                let value = [value(index), step.to_le_bytes().to_vec()].concat();
+                tree.insert(&mut pager, &key(index), &value)
+                    .expect("insert should not fail");
+                reference.insert(key(index), value);
+            }
+        }
+
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = tree
+            .iter(&pager)
+            .map(|entry| entry.expect("iteration should not fail"))
+            .collect();
+        assert_eq!(entries, reference.clone().into_iter().collect::<Vec<_>>());
+        for index in 0..1000 {
+            let value = tree.get(&pager, &key(index)).expect("get should not fail");
+            assert_eq!(value.as_ref(), reference.get(&key(index)));
+        }
+        assert!(tree.level_table_counts().len() > 2);
+    }
+
+    /// Operands written after a range deletion start from a missing value.
+    #[test]
+    fn merge_after_delete_range_ignores_deleted_value() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_merge_operator(Box::new(U64AddOperator));
+        tree.insert(&mut pager, b"counter", &counter(100))
+            .expect("insert should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+        tree.delete_range(&mut pager, b"a", b"z")
+            .expect("delete_range should not fail");
+
+        tree.merge(&mut pager, b"counter", &counter(5))
+            .expect("merge should not fail");
+        let in_memtable = tree.get(&pager, b"counter").expect("get should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+        let in_table = tree.get(&pager, b"counter").expect("get should not fail");
+
+        assert_eq!(in_memtable, Some(counter(5)));
+        assert_eq!(in_table, Some(counter(5)));
+    }
+
+    /// A multi-get returns the same values as a get of each key, across the memtable and tables
+    /// holding values, tombstones, range tombstones and merge operands, and checks the filters as
+    /// often.
+    #[test]
+    fn multi_get_returns_values_of_get() {
+        let (mut pager, mut tree) = create_tree();
+        tree.set_merge_operator(Box::new(U64AddOperator));
+        for index in 0..600 {
+            tree.insert(&mut pager, &key(index), &(index as u64).to_le_bytes())
+                .expect("insert should not fail");
+        }
+        tree.flush(&mut pager).expect("flush should not fail");
+        for index in (0..600).step_by(3) {
+            tree.merge(&mut pager, &key(index), &1u64.to_le_bytes())
+                .expect("merge should not fail");
+        }
+        tree.delete_range(&mut pager, &key(100), &key(200))
+            .expect("delete_range should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+        for index in (0..600).step_by(5) {
+            tree.delete(&mut pager, &key(index))
+                .expect("delete should not fail");
+        }
+        let mut keys: Vec<Vec<u8>> = (0..700).map(key).collect();
+        keys.push(key(7));
+        keys.shuffle(&mut rand::thread_rng());
+        let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+
+        let values = tree
+            .multi_get(&pager, &keys)
+            .expect("multi_get should not fail");
+        let multi_get_stats = tree.filter_stats();
+        let expected: Vec<_> = keys
+            .iter()
+            .map(|key| tree.get(&pager, key).expect("get should not fail"))
+            .collect();
+        let stats = tree.filter_stats();
+
+        assert_eq!(values, expected);
+        assert_eq!(stats.checks, 2 * multi_get_stats.checks);
+        assert_eq!(
+            values[keys
+                .iter()
+                .position(|k| *k == key(3))
+                .expect("key 3 should be searched")],
+            Some(4u64.to_le_bytes().to_vec())
+        );
+    }
+}
@@ -0,0 +1,3 @@
+mod mvcc_tree;
+mod ssi;
+pub use mvcc_tree::{Isolation, MvccError, MvccTransaction, MvccTree};
@@ -0,0 +1,913 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use thiserror::Error;
+
+use crate::btree::{BTree, BTreeError};
+use crate::common::clock::{Clock, HybridClock};
+use crate::common::envelope::Envelope;
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+use super::ssi::Conflicts;
+
+/// A key and its value.
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// The number of open transactions reading at each timestamp.
+type Readers = Arc<Mutex<BTreeMap<u64, usize>>>;
+
+/// Key of the record holding the timestamp of the last commit. The keys of the versions start
+/// with [VERSION_TAG], so they never collide with it.
+const LAST_COMMIT_KEY: &[u8] = &[0];
+
+/// First byte of the key of each version.
+const VERSION_TAG: u8 = 1;
+
+/// Size of the commit timestamp stored after the key of a version.
+const TIMESTAMP_SIZE: usize = 8;
+
+/// Represents errors that can occur during operations on a [MvccTree].
+#[derive(Error, Debug)]
+pub enum MvccError {
+    /// Indicates that an operation on the tree of the versions failed.
+    #[error(transparent)]
+    BTree(#[from] BTreeError),
+
+    /// Indicates that a transaction can't be committed because a key it wrote was written by a
+    /// transaction committed since it started. The transaction can be retried.
+    ///
+    /// # Fields
+    /// - `0` - The key written by both transactions.
+    #[error("The key {0:?} was written by a transaction committed since this one started.")]
+    Conflict(Vec<u8>),
+
+    /// Indicates that a record of the tree can't be decoded.
+    ///
+    /// # Fields
+    /// - `0` - The stored key of the record.
+    #[error("The record {0:?} can't be decoded.")]
+    CorruptedRecord(Vec<u8>),
+
+    /// Indicates that a serializable transaction can't be committed because the result might not
+    /// match any serial order of the transactions. The transaction can be retried.
+    #[error("The transaction can't be serialized with the concurrent transactions.")]
+    SerializationFailure,
+}
+
+/// How a [MvccTransaction] is isolated from the concurrent transactions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Isolation {
+    /// The transaction reads the keys as committed when it started, and its commit fails if a key
+    /// it writes was committed since. Two transactions reading keys the other writes can both
+    /// commit, which no serial order allows.
+    #[default]
+    Snapshot,
+    /// The transaction is isolated by snapshot, and its commit also fails if it could make the
+    /// result differ from every serial order of the serializable transactions: the keys it reads
+    /// are tracked, with the keys written by the concurrent serializable transactions.
+    Serializable,
+}
+
+/// Represents a [BTree] keeping several versions of each key, so that each transaction reads the
+/// keys as they were committed when it started, while other transactions commit.
+///
+/// Each commit is given a timestamp by a [HybridClock], reading the system clock by default, so
+/// the timestamps follow the time but always increase, even if the clock goes backward. Every key
+/// written by the commit is stored as a new version: the key followed by the complement of the
+/// timestamp, so the versions of a key follow each other in the tree, newest first. A version
+/// holds the value of the key or marks its removal. A [MvccTransaction] reads at the timestamp of
+/// the last commit when it started: it sees the latest version of each key committed at or before
+/// it, and ignores the versions committed later. Reads take no lock and are never delayed by the
+/// writes, which are kept by the transaction until it is committed.
+///
+/// The transactions are isolated by snapshot: a commit fails with [MvccError::Conflict] if a key
+/// it writes was committed by another transaction since it started, so the first of two
+/// concurrent writers of a key wins. The transactions started with [Isolation::Serializable] are
+/// also checked for the read-write dependencies that could make a cycle, and fail with
+/// [MvccError::SerializationFailure] rather than commit a result no serial order gives. The
+/// versions that no open transaction can read anymore are
+/// removed by [MvccTree::vacuum].
+///
+/// The timestamp of the last commit is stored in the tree, which is reopened from its root page.
+/// The tree is ordered bytewise.
+pub struct MvccTree {
+    tree: BTree,
+    last_commit: u64,
+    clock: HybridClock,
+    readers: Readers,
+    conflicts: Arc<Mutex<Conflicts>>,
+}
+
+impl MvccTree {
+    /// Creates a new, empty, tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if the root page
+    /// can't be allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::mvcc::MvccTree;
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    /// let mut tree = MvccTree::create(&mut pager).expect("create should not fail");
+    ///
+    /// let mut writer = tree.begin();
+    /// writer.put(b"color", b"red");
+    /// tree.commit(&mut pager, writer).expect("commit should not fail");
+    ///
+    /// let reader = tree.begin();
+    /// let mut writer = tree.begin();
+    /// writer.put(b"color", b"blue");
+    /// tree.commit(&mut pager, writer).expect("commit should not fail");
+    ///
+    /// let color = reader.get(&tree, &pager, b"color").expect("get should not fail");
+    /// assert_eq!(color, Some(b"red".to_vec()));
+    /// let color = tree.begin().get(&tree, &pager, b"color").expect("get should not fail");
+    /// assert_eq!(color, Some(b"blue".to_vec()));
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, MvccError> {
+        let mut tree = BTree::create(pager)?;
+        tree.insert(pager, LAST_COMMIT_KEY, &0u64.to_be_bytes())?;
+        Ok(MvccTree {
+            tree,
+            last_commit: 0,
+            clock: HybridClock::default(),
+            readers: Readers::default(),
+            conflicts: Arc::default(),
+        })
+    }
+
+    /// Opens a tree previously created with [MvccTree::create], from its root page.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise, if the root page
+    /// can't be read or does not contain a valid node, or if the timestamp of the last commit
+    /// can't be decoded.
+    pub fn open<F: File>(pager: &Pager<F>, root: PageId) -> Result<Self, MvccError> {
+        let tree = BTree::open(pager, root)?;
+        let last_commit = tree
+            .get(pager, LAST_COMMIT_KEY)?
+            .and_then(|stored| stored.try_into().ok())
+            .map(u64::from_be_bytes)
+            .ok_or_else(|| MvccError::CorruptedRecord(LAST_COMMIT_KEY.to_vec()))?;
+        let clock = HybridClock::default();
+        clock.observe(last_commit);
+        Ok(MvccTree {
+            tree,
+            last_commit,
+            clock,
+            readers: Readers::default(),
+            conflicts: Arc::default(),
+        })
+    }
+
+    /// Returns the identifier of the root page of the tree.
+    pub fn root(&self) -> PageId {
+        self.tree.root()
+    }
+
+    /// Returns the timestamp of the last commit, `0` if nothing was committed.
+    pub fn last_commit(&self) -> u64 {
+        self.last_commit
+    }
+
+    /// Replaces the clock giving the time of the commits, in milliseconds since the Unix epoch.
+    /// The next commits are still given timestamps after the last one.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock.set_clock(clock);
+    }
+
+    /// Starts a transaction isolated by snapshot, reading at the timestamp of the last commit.
+    pub fn begin(&self) -> MvccTransaction {
+        self.begin_with_isolation(Isolation::Snapshot)
+    }
+
+    /// Starts a transaction with the given isolation, reading at the timestamp of the last
+    /// commit.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::mvcc::{Isolation, MvccError, MvccTree};
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    /// let mut tree = MvccTree::create(&mut pager).expect("create should not fail");
+    ///
+    /// // Amy and Bob are on call, and each of them leaves if the other one stays.
+    /// let mut amy = tree.begin_with_isolation(Isolation::Serializable);
+    /// if amy.get(&tree, &pager, b"bob-left").expect("get should not fail").is_none() {
+    ///     amy.put(b"amy-left", b"yes");
+    /// }
+    /// let mut bob = tree.begin_with_isolation(Isolation::Serializable);
+    /// if bob.get(&tree, &pager, b"amy-left").expect("get should not fail").is_none() {
+    ///     bob.put(b"bob-left", b"yes");
+    /// }
+    ///
+    /// tree.commit(&mut pager, amy).expect("commit should not fail");
+    /// let result = tree.commit(&mut pager, bob);
+    /// assert!(matches!(result, Err(MvccError::SerializationFailure)));
+    /// ```
+    pub fn begin_with_isolation(&self, isolation: Isolation) -> MvccTransaction {
+        let timestamp = self.last_commit;
+        *self.readers().entry(timestamp).or_insert(0) += 1;
+        let tracking = (isolation == Isolation::Serializable).then(|| {
+            let id = self.conflicts().begin(timestamp);
+            (Arc::clone(&self.conflicts), id)
+        });
+        MvccTransaction {
+            timestamp,
+            writes: BTreeMap::new(),
+            readers: Arc::clone(&self.readers),
+            tracking,
+        }
+    }
+
+    /// Returns the value of a key as it was committed at `timestamp`, or `None` if the key was not
+    /// present.
+    ///
+    /// The versions removed by [MvccTree::vacuum] are not found: only the timestamps at or after
+    /// the oldest open transaction, when the tree was vacuumed, are read reliably.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+        timestamp: u64,
+    ) -> Result<Option<Vec<u8>>, MvccError> {
+        let mut cursor = self.tree.cursor(pager);
+        cursor.seek(&version_key(key, timestamp))?;
+        let (Some(stored_key), Some(stored)) = (cursor.key(), cursor.value()) else {
+            return Ok(None);
+        };
+        if !stored_key.starts_with(&key_prefix(key)) {
+            return Ok(None);
+        }
+        Ok(decode_value(stored_key, stored)?.map(<[u8]>::to_vec))
+    }
+
+    /// Returns the entries as they were committed at `timestamp`, in key order.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn entries<F: File>(
+        &self,
+        pager: &Pager<F>,
+        timestamp: u64,
+    ) -> Result<Vec<Entry>, MvccError> {
+        let mut entries = Vec::new();
+        let mut previous: Option<Vec<u8>> = None;
+        for record in self.tree.range(pager, [VERSION_TAG].as_slice()..) {
+            let (stored_key, stored) = record?;
+            let (key, version) = decode_key(&stored_key)?;
+            if version > timestamp || previous.as_ref() == Some(&key) {
+                continue;
+            }
+            if let Some(value) = decode_value(&stored_key, &stored)? {
+                entries.push((key.clone(), value.to_vec()));
+            }
+            previous = Some(key);
+        }
+        Ok(entries)
+    }
+
+    /// Commits the writes of a transaction as new versions of their keys, and returns the
+    /// timestamp of the commit. A transaction that wrote nothing commits nothing, and its
+    /// timestamp is returned. A serializable transaction that only read must still be committed
+    /// for its reads to be known serializable.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error, and write nothing, if a key written by the transaction
+    /// was written by a transaction committed since it started, or if a serializable transaction
+    /// can't be serialized. It will also return an error if a page can't be read, written or
+    /// allocated, after removing the versions the commit already wrote.
+    pub fn commit<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        transaction: MvccTransaction,
+    ) -> Result<u64, MvccError> {
+        for key in transaction.writes.keys() {
+            if self.latest_version(pager, key)? > transaction.timestamp {
+                return Err(MvccError::Conflict(key.clone()));
+            }
+        }
+        let written: BTreeSet<Vec<u8>> = transaction.writes.keys().cloned().collect();
+        let dependencies = match &transaction.tracking {
+            Some((conflicts, id)) => Some(
+                lock(conflicts)
+                    .validate(*id, &written)
+                    .ok_or(MvccError::SerializationFailure)?,
+            ),
+            None => None,
+        };
+
+        let timestamp = if transaction.writes.is_empty() {
+            transaction.timestamp
+        } else {
+            self.write_versions(pager, &transaction.writes)?
+        };
+        if let (Some((conflicts, id)), Some(dependencies)) = (&transaction.tracking, dependencies) {
+            // A transaction that only read ended after the last commit.
+            let end = timestamp.max(self.last_commit + 1);
+            lock(conflicts).commit(*id, dependencies, written, end);
+        }
+        drop(transaction);
+        let oldest_start = self.readers().keys().next().copied();
+        self.conflicts().prune(oldest_start);
+        Ok(timestamp)
+    }
+
+    /// Writes new versions of the keys, in a commit whose timestamp is returned. If a version
+    /// can't be written, the versions already written are removed, so none is left to become
+    /// visible with a later commit.
+    fn write_versions<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        writes: &BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    ) -> Result<u64, MvccError> {
+        let timestamp = self.clock.timestamp();
+        let mut written = Vec::with_capacity(writes.len());
+        if let Err(error) = self.insert_versions(pager, writes, timestamp, &mut written) {
+            for stored_key in &written {
+                // The error of the commit is returned, even if a version can't be removed.
+                let _ = self.tree.delete(pager, stored_key);
+            }
+            return Err(error);
+        }
+        self.last_commit = timestamp;
+        Ok(timestamp)
+    }
+
+    /// Inserts the versions of the keys committed at `timestamp`, then the timestamp of the last
+    /// commit, adding the stored key of each version inserted to `written`.
+    fn insert_versions<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        writes: &BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+        timestamp: u64,
+        written: &mut Vec<Vec<u8>>,
+    ) -> Result<(), MvccError> {
+        for (key, value) in writes {
+            let stored = match value {
+                Some(value) => [&[Envelope::VALUE_TAG], value.as_slice()].concat(),
+                None => vec![Envelope::TOMBSTONE_TAG],
+            };
+            let stored_key = version_key(key, timestamp);
+            self.tree.insert(pager, &stored_key, &stored)?;
+            written.push(stored_key);
+        }
+        self.tree
+            .insert(pager, LAST_COMMIT_KEY, &timestamp.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Removes the versions that no open transaction can read: for each key, the versions older
+    /// than the one the oldest open transaction reads, and that version too if it marks the
+    /// removal of the key. Without open transaction, only the latest version of each key is kept.
+    /// Returns the number of versions removed.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or written.
+    pub fn vacuum<F: File>(&mut self, pager: &mut Pager<F>) -> Result<usize, MvccError> {
+        let horizon = self
+            .readers()
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or(self.last_commit);
+
+        let mut removed = Vec::new();
+        let mut visible: Option<Vec<u8>> = None;
+        for record in self.tree.range(pager, [VERSION_TAG].as_slice()..) {
+            let (stored_key, stored) = record?;
+            let (key, version) = decode_key(&stored_key)?;
+            if version > horizon {
+                continue;
+            }
+            if visible.as_ref() == Some(&key) {
+                removed.push(stored_key);
+                continue;
+            }
+            if decode_value(&stored_key, &stored)?.is_none() {
+                removed.push(stored_key);
+            }
+            visible = Some(key);
+        }
+
+        for stored_key in &removed {
+            self.tree.delete(pager, stored_key)?;
+        }
+        Ok(removed.len())
+    }
+
+    /// Returns the timestamp of the latest version of a key, `0` if it has none.
+    fn latest_version<F: File>(&self, pager: &Pager<F>, key: &[u8]) -> Result<u64, MvccError> {
+        let mut cursor = self.tree.cursor(pager);
+        cursor.seek(&key_prefix(key))?;
+        match cursor.key() {
+            Some(stored_key) if stored_key.starts_with(&key_prefix(key)) => {
+                Ok(decode_key(stored_key)?.1)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    fn readers(&self) -> MutexGuard<'_, BTreeMap<u64, usize>> {
+        lock(&self.readers)
+    }
+
+    fn conflicts(&self) -> MutexGuard<'_, Conflicts> {
+        lock(&self.conflicts)
+    }
+}
+
+/// Represents a transaction of a [MvccTree], reading the keys as they were committed when it
+/// started, and keeping its writes until it is given to [MvccTree::commit]. A transaction that is
+/// dropped is rolled back.
+pub struct MvccTransaction {
+    timestamp: u64,
+    /// The values written by the transaction, `None` for a removed key.
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    readers: Readers,
+    /// The dependencies tracked and the identifier of a serializable transaction.
+    tracking: Option<(Arc<Mutex<Conflicts>>, u64)>,
+}
+
+impl MvccTransaction {
+    /// Returns the timestamp the transaction reads at: the timestamp of the last commit when it
+    /// started.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Returns the isolation of the transaction.
+    pub fn isolation(&self) -> Isolation {
+        match self.tracking {
+            Some(_) => Isolation::Serializable,
+            None => Isolation::Snapshot,
+        }
+    }
+
+    /// Returns the value of a key written by the transaction, or else as it was committed when
+    /// the transaction started. Returns `None` if the key is not present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(
+        &self,
+        tree: &MvccTree,
+        pager: &Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, MvccError> {
+        if let Some(value) = self.writes.get(key) {
+            return Ok(value.clone());
+        }
+        if let Some((conflicts, id)) = &self.tracking {
+            lock(conflicts).read(*id, key);
+        }
+        tree.get(pager, key, self.timestamp)
+    }
+
+    /// Returns the entries as they were committed when the transaction started, with the writes
+    /// of the transaction, in key order. A serializable transaction is then considered to have
+    /// read every key.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn entries<F: File>(
+        &self,
+        tree: &MvccTree,
+        pager: &Pager<F>,
+    ) -> Result<Vec<Entry>, MvccError> {
+        if let Some((conflicts, id)) = &self.tracking {
+            lock(conflicts).scan(*id);
+        }
+        let mut entries: BTreeMap<Vec<u8>, Vec<u8>> =
+            tree.entries(pager, self.timestamp)?.into_iter().collect();
+        for (key, value) in &self.writes {
+            match value {
+                Some(value) => entries.insert(key.clone(), value.clone()),
+                None => entries.remove(key),
+            };
+        }
+        Ok(entries.into_iter().collect())
+    }
+
+    /// Sets the value of a key when the transaction is committed.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+    }
+
+    /// Removes a key when the transaction is committed.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.insert(key.to_vec(), None);
+    }
+}
+
+impl Drop for MvccTransaction {
+    fn drop(&mut self) {
+        {
+            let mut readers = lock(&self.readers);
+            if let Some(count) = readers.get_mut(&self.timestamp) {
+                *count -= 1;
+                if *count == 0 {
+                    readers.remove(&self.timestamp);
+                }
+            }
+        }
+        if let Some((conflicts, id)) = &self.tracking {
+            lock(conflicts).abort(*id);
+        }
+    }
+}
+
+/// Locks a mutex, whose data stays consistent if a thread panicked while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Returns the start of the stored keys of the versions of `key`: the tag of the versions, the key
+/// with each `0` byte followed by `0xff`, and two `0` bytes. The prefixes are ordered as their
+/// keys, and the prefix of a key never starts with the prefix of another key.
+fn key_prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(key.len() + 3 + TIMESTAMP_SIZE);
+    prefix.push(VERSION_TAG);
+    for &byte in key {
+        prefix.push(byte);
+        if byte == 0 {
+            prefix.push(0xff);
+        }
+    }
+    prefix.extend_from_slice(&[0, 0]);
+    prefix
+}
+
+/// Returns the stored key of the version of `key` committed at `timestamp`: its prefix (see
+/// [key_prefix]) followed by the complement of the timestamp, in big-endian, so the newest
+/// version comes first.
+fn version_key(key: &[u8], timestamp: u64) -> Vec<u8> {
+    let mut stored_key = key_prefix(key);
+    stored_key.extend_from_slice(&(!timestamp).to_be_bytes());
+    stored_key
+}
+
+/// Returns the key and the timestamp of the version stored under `stored_key`.
+fn decode_key(stored_key: &[u8]) -> Result<(Vec<u8>, u64), MvccError> {
+    let corrupted = || MvccError::CorruptedRecord(stored_key.to_vec());
+    let Some((&VERSION_TAG, rest)) = stored_key.split_first() else {
+        return Err(corrupted());
+    };
+    let Some(split) = rest.len().checked_sub(TIMESTAMP_SIZE) else {
+        return Err(corrupted());
+    };
+    let (escaped, timestamp) = rest.split_at(split);
+    let mut key = Vec::with_capacity(escaped.len());
+    let mut index = 0;
+    while index < escaped.len() {
+        match (escaped[index], escaped.get(index + 1)) {
+            (0, Some(0xff)) => {
+                key.push(0);
+                index += 2;
+            }
+            (0, Some(0)) if index + 2 == escaped.len() => {
+                let timestamp = timestamp.try_into().expect("slice should be 8 bytes");
+                return Ok((key, !u64::from_be_bytes(timestamp)));
+            }
+            (0, _) => return Err(corrupted()),
+            (byte, _) => {
+                key.push(byte);
+                index += 1;
+            }
+        }
+    }
+    Err(corrupted())
+}
+
+/// Returns the value held by a version, or `None` if it marks the removal of its key.
+fn decode_value<'a>(stored_key: &[u8], stored: &'a [u8]) -> Result<Option<&'a [u8]>, MvccError> {
+    match stored.split_first() {
+        Some((&Envelope::VALUE_TAG, value)) => Ok(Some(value)),
+        Some((&Envelope::TOMBSTONE_TAG, [])) => Ok(None),
+        _ => Err(MvccError::CorruptedRecord(stored_key.to_vec())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::clock::ManualClock;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    /// Creates a tree whose clock always reads `0`, so its commits are numbered from `1`.
+    fn create_tree() -> (Pager<MemoryFile>, MvccTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = MvccTree::create(&mut pager).expect("create should not fail");
+        tree.set_clock(|| 0);
+        (pager, tree)
+    }
+
+    /// Commits a transaction writing `value` to each key, or removing them if `value` is `None`.
+    fn write(
+        pager: &mut Pager<MemoryFile>,
+        tree: &mut MvccTree,
+        keys: &[&[u8]],
+        value: Option<&[u8]>,
+    ) {
+        let mut transaction = tree.begin();
+        for key in keys {
+            match value {
+                Some(value) => transaction.put(key, value),
+                None => transaction.delete(key),
+            }
+        }
+        tree.commit(pager, transaction)
+            .expect("commit should not fail");
+    }
+
+    /// A transaction reads the versions committed when it started and its own writes, and the
+    /// keys sharing bytes with the escaping of another key are kept apart.
+    #[test]
+    fn transaction_reads_its_snapshot() {
+        let (mut pager, mut tree) = create_tree();
+        write(
+            &mut pager,
+            &mut tree,
+            &[b"a", b"a\0", b"a\0\0", b"b"],
+            Some(b"1"),
+        );
+        let reader = tree.begin();
+        let mut own = tree.begin();
+        own.put(b"c", b"own");
+        write(&mut pager, &mut tree, &[b"a\0", b"d"], Some(b"2"));
+        write(&mut pager, &mut tree, &[b"b"], None);
+
+        assert_eq!(
+            reader
+                .get(&tree, &pager, b"a\0")
+                .expect("get should not fail"),
+            Some(b"1".to_vec())
+        );
+        assert_eq!(
+            reader
+                .get(&tree, &pager, b"d")
+                .expect("get should not fail"),
+            None
+        );
+        assert_eq!(
+            own.get(&tree, &pager, b"c").expect("get should not fail"),
+            Some(b"own".to_vec())
+        );
+        assert_eq!(
+            tree.entries(&pager, reader.timestamp())
+                .expect("entries should not fail"),
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"a\0".to_vec(), b"1".to_vec()),
+                (b"a\0\0".to_vec(), b"1".to_vec()),
+                (b"b".to_vec(), b"1".to_vec()),
+            ]
+        );
+        assert_eq!(
+            tree.entries(&pager, tree.last_commit())
+                .expect("entries should not fail"),
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"a\0".to_vec(), b"2".to_vec()),
+                (b"a\0\0".to_vec(), b"1".to_vec()),
+                (b"d".to_vec(), b"2".to_vec()),
+            ]
+        );
+    }
+
+    /// Of two transactions writing the same key, the one committed last fails and writes
+    /// nothing, while a transaction writing other keys commits.
+    #[test]
+    fn commit_detects_write_conflicts() {
+        let (mut pager, mut tree) = create_tree();
+        let mut first = tree.begin();
+        let mut second = tree.begin();
+        let mut other = tree.begin();
+        first.put(b"key", b"first");
+        second.put(b"key", b"second");
+        second.put(b"more", b"second");
+        other.put(b"other", b"other");
+
+        tree.commit(&mut pager, first)
+            .expect("commit should not fail");
+        let result = tree.commit(&mut pager, second);
+        tree.commit(&mut pager, other)
+            .expect("commit should not fail");
+        let retry = tree.begin();
+
+        assert!(matches!(result, Err(MvccError::Conflict(key)) if key == b"key"));
+        assert_eq!(tree.last_commit(), 2);
+        assert_eq!(
+            retry
+                .get(&tree, &pager, b"key")
+                .expect("get should not fail"),
+            Some(b"first".to_vec())
+        );
+        assert_eq!(
+            retry
+                .get(&tree, &pager, b"more")
+                .expect("get should not fail"),
+            None
+        );
+    }
+
+    /// A vacuum keeps the versions read by the open transactions, and removes the older
+    /// versions and the removals once the transactions are dropped.
+    #[test]
+    fn vacuum_keeps_versions_of_open_transactions() {
+        let (mut pager, mut tree) = create_tree();
+        write(&mut pager, &mut tree, &[b"kept", b"removed"], Some(b"1"));
+        let reader = tree.begin();
+        write(&mut pager, &mut tree, &[b"kept"], Some(b"2"));
+        write(&mut pager, &mut tree, &[b"removed"], None);
+        write(&mut pager, &mut tree, &[b"kept"], Some(b"3"));
+
+        let removed_while_open = tree.vacuum(&mut pager).expect("vacuum should not fail");
+        let value = reader
+            .get(&tree, &pager, b"removed")
+            .expect("get should not fail");
+        drop(reader);
+        let removed = tree.vacuum(&mut pager).expect("vacuum should not fail");
+
+        assert_eq!(removed_while_open, 0);
+        assert_eq!(value, Some(b"1".to_vec()));
+        assert_eq!(removed, 4);
+        assert_eq!(tree.tree.iter(&pager).count(), 2);
+        assert_eq!(
+            tree.entries(&pager, tree.last_commit())
+                .expect("entries should not fail"),
+            vec![(b"kept".to_vec(), b"3".to_vec())]
+        );
+    }
+
+    /// A commit failing midway leaves none of its versions, even once a later commit passes its
+    /// timestamp.
+    #[test]
+    fn failed_commit_leaves_no_version() {
+        let (mut pager, mut tree) = create_tree();
+        let mut transaction = tree.begin();
+        transaction.put(b"a", b"1");
+        transaction.put(b"b", &[0; 1024]);
+
+        let result = tree.commit(&mut pager, transaction);
+        write(&mut pager, &mut tree, &[b"c"], Some(b"3"));
+
+        assert!(matches!(
+            result,
+            Err(MvccError::BTree(BTreeError::EntryTooLarge { .. }))
+        ));
+        assert_eq!(tree.last_commit(), 2);
+        let reader = tree.begin();
+        assert_eq!(
+            reader
+                .get(&tree, &pager, b"a")
+                .expect("get should not fail"),
+            None
+        );
+    }
+
+    /// A reopened tree resumes the timestamps after its last commit.
+    #[test]
+    fn open_restores_last_commit() {
+        let (mut pager, mut tree) = create_tree();
+        write(&mut pager, &mut tree, &[b"key"], Some(b"1"));
+        write(&mut pager, &mut tree, &[b"key"], Some(b"2"));
+
+        let mut tree = MvccTree::open(&pager, tree.root()).expect("open should not fail");
+        tree.set_clock(|| 0);
+        write(&mut pager, &mut tree, &[b"key"], Some(b"3"));
+
+        assert_eq!(tree.last_commit(), 3);
+        assert_eq!(
+            tree.get(&pager, b"key", 2).expect("get should not fail"),
+            Some(b"2".to_vec())
+        );
+    }
+
+    /// The commits are given the time of the clock, and later timestamps when it goes backward,
+    /// also after the tree is reopened.
+    #[test]
+    fn commit_timestamps_follow_the_clock() {
+        let (mut pager, mut tree) = create_tree();
+        let time = ManualClock::new(100);
+        tree.set_clock(time.clone());
+        write(&mut pager, &mut tree, &[b"key"], Some(b"1"));
+        let first = tree.last_commit();
+        time.set(50);
+        write(&mut pager, &mut tree, &[b"key"], Some(b"2"));
+        let second = tree.last_commit();
+
+        let mut tree = MvccTree::open(&pager, tree.root()).expect("open should not fail");
+        tree.set_clock(time);
+        write(&mut pager, &mut tree, &[b"key"], Some(b"3"));
+
+        assert_eq!(HybridClock::physical(first), 100);
+        assert_eq!(HybridClock::physical(second), 100);
+        assert!(second > first);
+        assert!(tree.last_commit() > second);
+        assert_eq!(
+            tree.get(&pager, b"key", second)
+                .expect("get should not fail"),
+            Some(b"2".to_vec())
+        );
+    }
+
+    /// Two transactions each reading the key the other writes both commit when isolated by
+    /// snapshot, but the second one fails when they are serializable.
+    #[test]
+    fn serializable_commit_detects_write_skew() {
+        let (mut pager, mut tree) = create_tree();
+        let mut commits = Vec::new();
+        for isolation in [Isolation::Snapshot, Isolation::Serializable] {
+            let mut first = tree.begin_with_isolation(isolation);
+            let mut second = tree.begin_with_isolation(isolation);
+            let a = first.get(&tree, &pager, b"a").expect("get should not fail");
+            first.put(b"b", &[a.map_or(0, |a| a[0]) + 1]);
+            let b = second
+                .get(&tree, &pager, b"b")
+                .expect("get should not fail");
+            second.put(b"a", &[b.map_or(0, |b| b[0]) + 1]);
+
+            let first = tree.commit(&mut pager, first);
+            let second = tree.commit(&mut pager, second);
+            commits.push((first.is_ok(), second));
+        }
+
+        assert!(matches!(commits[0], (true, Ok(_))));
+        assert!(matches!(
+            commits[1],
+            (true, Err(MvccError::SerializationFailure))
+        ));
+    }
+
+    /// Serializable transactions writing keys no concurrent transaction reads all commit, and a
+    /// transaction rolled back adds no dependency, while a scan depends on every concurrent
+    /// write.
+    #[test]
+    fn serializable_commit_tracks_reads_and_scans() {
+        let (mut pager, mut tree) = create_tree();
+        write(&mut pager, &mut tree, &[b"a", b"b"], Some(b"1"));
+        let mut first = tree.begin_with_isolation(Isolation::Serializable);
+        let mut second = tree.begin_with_isolation(Isolation::Serializable);
+        let mut rolled_back = tree.begin_with_isolation(Isolation::Serializable);
+        first.get(&tree, &pager, b"a").expect("get should not fail");
+        first.put(b"a", b"2");
+        second
+            .get(&tree, &pager, b"b")
+            .expect("get should not fail");
+        second.put(b"b", b"2");
+        rolled_back
+            .get(&tree, &pager, b"a")
+            .expect("get should not fail");
+        rolled_back.put(b"b", b"3");
+        drop(rolled_back);
+        let first = tree.commit(&mut pager, first);
+        let second = tree.commit(&mut pager, second);
+
+        let mut scanner = tree.begin_with_isolation(Isolation::Serializable);
+        let entries = scanner
+            .entries(&tree, &pager)
+            .expect("entries should not fail");
+        scanner.put(b"c", b"2");
+        let reader = tree.begin_with_isolation(Isolation::Serializable);
+        reader
+            .get(&tree, &pager, b"c")
+            .expect("get should not fail");
+        let mut writer = tree.begin_with_isolation(Isolation::Serializable);
+        writer.put(b"a", b"3");
+        tree.commit(&mut pager, writer)
+            .expect("commit should not fail");
+        let scanner = tree.commit(&mut pager, scanner);
+
+        assert!(first.is_ok() && second.is_ok());
+        assert_eq!(entries.len(), 2);
+        assert!(matches!(scanner, Err(MvccError::SerializationFailure)));
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The read-write dependencies between the serializable transactions of a
+/// [MvccTree](super::MvccTree), which detect the transactions that can't be serialized.
+///
+/// A transaction that reads a key, and a concurrent transaction that writes it, have a read-write
+/// dependency: the reader must come before the writer in a serial order, since it did not see the
+/// write. A transaction with both an incoming and an outgoing dependency, called a pivot, may
+/// close a cycle, which no serial order can satisfy. A commit that would make a pivot of the
+/// committing transaction, or of a committed transaction, fails. This may fail transactions that
+/// could have been serialized, but never lets a cycle form.
+///
+/// The transactions are tracked from their start, and a committed transaction is kept as long as
+/// a transaction that started before its commit is open.
+#[derive(Default)]
+pub(super) struct Conflicts {
+    next_id: u64,
+    transactions: BTreeMap<u64, Tracked>,
+}
+
+/// A serializable transaction tracked by [Conflicts].
+struct Tracked {
+    /// The timestamp the transaction reads at.
+    start: u64,
+    /// The timestamp after which the transaction ended, once it is committed.
+    commit: Option<u64>,
+    reads: BTreeSet<Vec<u8>>,
+    /// Set when the transaction read every key.
+    scanned: bool,
+    writes: BTreeSet<Vec<u8>>,
+    /// Set when a concurrent transaction read a key this one wrote.
+    in_conflict: bool,
+    /// Set when this transaction read a key a concurrent transaction wrote.
+    out_conflict: bool,
+}
+
+/// The dependencies a commit adds: the committed transactions that wrote a key read by the
+/// committing transaction, and the transactions that read a key it writes.
+pub(super) struct Dependencies {
+    writers: Vec<u64>,
+    readers: Vec<u64>,
+}
+
+impl Conflicts {
+    /// Starts tracking a transaction reading at `start`, and returns its identifier.
+    pub(super) fn begin(&mut self, start: u64) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.transactions.insert(
+            id,
+            Tracked {
+                start,
+                commit: None,
+                reads: BTreeSet::new(),
+                scanned: false,
+                writes: BTreeSet::new(),
+                in_conflict: false,
+                out_conflict: false,
+            },
+        );
+        id
+    }
+
+    /// Records that a transaction read a key.
+    pub(super) fn read(&mut self, id: u64, key: &[u8]) {
+        if let Some(tracked) = self.transactions.get_mut(&id) {
+            if !tracked.scanned && !tracked.reads.contains(key) {
+                tracked.reads.insert(key.to_vec());
+            }
+        }
+    }
+
+    /// Records that a transaction read every key.
+    pub(super) fn scan(&mut self, id: u64) {
+        if let Some(tracked) = self.transactions.get_mut(&id) {
+            tracked.scanned = true;
+            tracked.reads.clear();
+        }
+    }
+
+    /// Returns the dependencies a transaction writing `writes` would add by committing, or `None`
+    /// if the commit would make a pivot.
+    pub(super) fn validate(&self, id: u64, writes: &BTreeSet<Vec<u8>>) -> Option<Dependencies> {
+        let tracked = &self.transactions[&id];
+        let mut dependencies = Dependencies {
+            writers: Vec::new(),
+            readers: Vec::new(),
+        };
+        for (&other_id, other) in &self.transactions {
+            if other_id == id {
+                continue;
+            }
+            if other.commit.is_some_and(|commit| commit > tracked.start)
+                && tracked.read_any(&other.writes)
+            {
+                dependencies.writers.push(other_id);
+            }
+            if other.commit.is_none_or(|commit| commit > tracked.start) && other.read_any(writes) {
+                dependencies.readers.push(other_id);
+            }
+        }
+
+        let committed_pivot = dependencies
+            .writers
+            .iter()
+            .any(|writer| self.transactions[writer].out_conflict)
+            || dependencies.readers.iter().any(|reader| {
+                let reader = &self.transactions[reader];
+                reader.commit.is_some() && reader.in_conflict
+            });
+        let in_conflict = tracked.in_conflict || !dependencies.readers.is_empty();
+        let out_conflict = tracked.out_conflict || !dependencies.writers.is_empty();
+        if committed_pivot || (in_conflict && out_conflict) {
+            return None;
+        }
+        Some(dependencies)
+    }
+
+    /// Records the commit of a transaction validated by [Conflicts::validate], ending after
+    /// `commit`.
+    pub(super) fn commit(
+        &mut self,
+        id: u64,
+        dependencies: Dependencies,
+        writes: BTreeSet<Vec<u8>>,
+        commit: u64,
+    ) {
+        for writer in &dependencies.writers {
+            if let Some(writer) = self.transactions.get_mut(writer) {
+                writer.in_conflict = true;
+            }
+        }
+        for reader in &dependencies.readers {
+            if let Some(reader) = self.transactions.get_mut(reader) {
+                reader.out_conflict = true;
+            }
+        }
+        if let Some(tracked) = self.transactions.get_mut(&id) {
+            tracked.in_conflict |= !dependencies.readers.is_empty();
+            tracked.out_conflict |= !dependencies.writers.is_empty();
+            tracked.commit = Some(commit);
+            tracked.writes = writes;
+        }
+    }
+
+    /// Stops tracking a transaction that was not committed.
+    pub(super) fn abort(&mut self, id: u64) {
+        if self
+            .transactions
+            .get(&id)
+            .is_some_and(|tracked| tracked.commit.is_none())
+        {
+            self.transactions.remove(&id);
+        }
+    }
+
+    /// Stops tracking the committed transactions that can't conflict anymore, since every open
+    /// transaction reads at or after `oldest_start`, or at all if there is none.
+    pub(super) fn prune(&mut self, oldest_start: Option<u64>) {
+        self.transactions.retain(|_, tracked| match tracked.commit {
+            Some(commit) => oldest_start.is_some_and(|start| start < commit),
+            None => true,
+        });
+    }
+}
+
+impl Tracked {
+    /// Returns `true` if the transaction read one of `keys`.
+    fn read_any(&self, keys: &BTreeSet<Vec<u8>>) -> bool {
+        !keys.is_empty() && (self.scanned || keys.iter().any(|key| self.reads.contains(key)))
+    }
+}
@@ -0,0 +1,411 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::fs::{File, FileError};
+
+/// The largest number of shards of a pool.
+const MAX_SHARDS: usize = 16;
+
+/// The smallest number of blocks held by each shard of a pool, so the blocks of a small pool are
+/// evicted in the order they were used.
+const MIN_SHARD_CAPACITY: usize = 64;
+
+thread_local! {
+    /// Whether the blocks read by the current thread are added to the pools, set by
+    /// [with_fill_cache].
+    static FILL_CACHE: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Runs `read` with the blocks that the current thread reads through a [BufferPool] added to the
+/// pool only if `fill_cache` is set, so that a pager can read without filling its cache. The reads
+/// of the other threads are not affected.
+pub fn with_fill_cache<T>(fill_cache: bool, read: impl FnOnce() -> T) -> T {
+    /// Restores the previous setting, even if `read` panics.
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            FILL_CACHE.set(self.0);
+        }
+    }
+
+    let _restore = Restore(FILL_CACHE.replace(fill_cache));
+    read()
+}
+
+/// Keeps the most recently read blocks of a [File] in memory, so that reading them again does not
+/// reach the file.
+///
+/// The [BufferPool] is itself a [File] wrapping another one, so it can be given to a
+/// [Pager](super::Pager). Only the reads of a whole block, `block_size` bytes starting at a
+/// multiple of `block_size`, are cached, which covers every page read by a pager using pages of
+/// that size. Writes go through to the file and update the cached blocks they overlap. The blocks
+/// read with [BufferPool::read_with] without filling the cache, or within [with_fill_cache]
+/// disabled, are not added to the pool, so that a large scan does not evict the blocks read most
+/// recently.
+///
+/// The blocks are spread over up to [MAX_SHARDS] shards by index, each with its own lock and an
+/// equal share of `capacity`, so threads reading different blocks rarely wait for each other. When
+/// a shard is full, its least recently used block is evicted.
+pub struct BufferPool<F: File> {
+    file: F,
+    block_size: usize,
+    capacity: usize,
+    shards: Vec<Mutex<Cache>>,
+}
+
+/// The cached blocks of a shard and their use.
+#[derive(Default)]
+struct Cache {
+    capacity: usize,
+    /// The cached blocks by offset, with the tick of their last use.
+    blocks: BTreeMap<usize, (Vec<u8>, u64)>,
+    /// The offset of the cached blocks by tick of their last use.
+    uses: BTreeMap<u64, usize>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Cache {
+    /// Returns a cached block and marks it as the most recently used.
+    fn get(&mut self, offset: usize) -> Option<&[u8]> {
+        self.tick += 1;
+        let (block, last_use) = self.blocks.get_mut(&offset)?;
+        self.uses.remove(last_use);
+        self.uses.insert(self.tick, offset);
+        *last_use = self.tick;
+        Some(block)
+    }
+
+    /// Adds a block, evicting the least recently used block if the cache is full.
+    fn insert(&mut self, offset: usize, block: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.blocks.len() >= self.capacity {
+            if let Some((_, evicted)) = self.uses.pop_first() {
+                self.blocks.remove(&evicted);
+            }
+        }
+        self.tick += 1;
+        self.uses.insert(self.tick, offset);
+        self.blocks.insert(offset, (block, self.tick));
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear();
+        self.uses.clear();
+    }
+}
+
+impl<F: File> BufferPool<F> {
+    /// Creates a pool caching up to `capacity` blocks of `block_size` bytes of a file.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `block_size` is `0`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::{BufferPool, Pager};
+    ///
+    /// let mut file = BufferPool::new(MemoryFile::new(), 4096, 64);
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let page = pager.allocate_page().expect("allocate_page should not fail");
+    /// pager.write_page(page, &[7; 4096]).expect("write_page should not fail");
+    /// pager.read_page(page).expect("read_page should not fail");
+    /// let data = pager.read_page(page).expect("read_page should not fail");
+    ///
+    /// assert_eq!(data, vec![7; 4096]);
+    /// assert_eq!(pager.into_file().hits(), 1);
+    /// ```
+    pub fn new(file: F, block_size: usize, capacity: usize) -> Self {
+        assert!(block_size > 0, "the blocks should not be empty");
+        let count = (capacity / MIN_SHARD_CAPACITY).clamp(1, MAX_SHARDS);
+        let shards = (0..count)
+            .map(|shard| {
+                Mutex::new(Cache {
+                    capacity: capacity / count + usize::from(shard < capacity % count),
+                    ..Cache::default()
+                })
+            })
+            .collect();
+        BufferPool {
+            file,
+            block_size,
+            capacity,
+            shards,
+        }
+    }
+
+    /// Returns the size of the cached blocks.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns the largest number of blocks kept in memory.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Reads bytes like [File::read], adding the block read from the file to the pool only if
+    /// `fill_cache` is set. The blocks already in memory are still read from it.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file can't be read.
+    pub fn read_with(
+        &self,
+        offset: usize,
+        buffer: &mut [u8],
+        fill_cache: bool,
+    ) -> Result<(), FileError> {
+        if buffer.len() != self.block_size || !offset.is_multiple_of(self.block_size) {
+            return self.file.read(offset, buffer);
+        }
+
+        let mut cache = self.cache(offset);
+        if let Some(block) = cache.get(offset) {
+            buffer.copy_from_slice(block);
+            cache.hits += 1;
+            return Ok(());
+        }
+        self.file.read(offset, buffer)?;
+        cache.misses += 1;
+        if fill_cache {
+            cache.insert(offset, buffer.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Returns the number of blocks in memory.
+    pub fn len(&self) -> usize {
+        self.sum(|cache| cache.blocks.len() as u64) as usize
+    }
+
+    /// Returns `true` if no block is in memory.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of block reads served from memory.
+    pub fn hits(&self) -> u64 {
+        self.sum(|cache| cache.hits)
+    }
+
+    /// Returns the number of block reads that reached the file.
+    pub fn misses(&self) -> u64 {
+        self.sum(|cache| cache.misses)
+    }
+
+    /// Removes every block from memory, after the file was modified by another writer. The hits
+    /// and misses are kept.
+    pub fn clear(&self) {
+        for shard in &self.shards {
+            Self::lock(shard).clear();
+        }
+    }
+
+    /// Returns the wrapped file.
+    pub fn inner(&self) -> &F {
+        &self.file
+    }
+
+    /// Consumes the pool and returns the wrapped file.
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+
+    /// Returns the shard caching the block at `offset`, locked.
+    fn cache(&self, offset: usize) -> MutexGuard<'_, Cache> {
+        Self::lock(&self.shards[offset / self.block_size % self.shards.len()])
+    }
+
+    /// Returns the sum of a count over the shards.
+    fn sum(&self, count: impl Fn(&Cache) -> u64) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| count(&Self::lock(shard)))
+            .sum()
+    }
+
+    fn lock(shard: &Mutex<Cache>) -> MutexGuard<'_, Cache> {
+        shard.lock().expect("the cache lock should not be poisoned")
+    }
+}
+
+impl<F: File> File for BufferPool<F> {
+    fn create(&mut self) -> Result<(), FileError> {
+        self.clear();
+        self.file.create()
+    }
+
+    fn close(&mut self) -> Result<(), FileError> {
+        self.clear();
+        self.file.close()
+    }
+
+    fn open(&mut self) -> Result<(), FileError> {
+        self.clear();
+        self.file.open()
+    }
+
+    fn delete(&mut self) -> Result<(), FileError> {
+        self.file.delete()
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), FileError> {
+        self.file.write(offset, data)?;
+
+        let end = offset + data.len();
+        let first_block = offset - offset % self.block_size;
+        for block_offset in (first_block..end).step_by(self.block_size) {
+            let mut cache = self.cache(block_offset);
+            if let Some((block, _)) = cache.blocks.get_mut(&block_offset) {
+                let start = offset.max(block_offset);
+                let stop = end.min(block_offset + self.block_size);
+                block[start - block_offset..stop - block_offset]
+                    .copy_from_slice(&data[start - offset..stop - offset]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads bytes, adding the block read from the file to the pool unless the read is made
+    /// within [with_fill_cache] disabled.
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<(), FileError> {
+        self.read_with(offset, buffer, FILL_CACHE.get())
+    }
+
+    fn sync(&self) -> Result<(), FileError> {
+        self.file.sync()
+    }
+
+    fn size(&self) -> Result<usize, FileError> {
+        self.file.size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_pool(capacity: usize) -> BufferPool<MemoryFile> {
+        let mut pool = BufferPool::new(MemoryFile::new(), 512, capacity);
+        pool.create().expect("create should not fail");
+        pool.write(0, &[0; 4096]).expect("write should not fail");
+        pool
+    }
+
+    fn read_block(pool: &BufferPool<MemoryFile>, index: usize) -> Vec<u8> {
+        let mut buffer = vec![0; 512];
+        pool.read(index * 512, &mut buffer)
+            .expect("read should not fail");
+        buffer
+    }
+
+    /// A block read again is served from memory, and the least recently used block is evicted
+    /// when the pool is full.
+    #[test]
+    fn read_evicts_least_recently_used_block() {
+        let pool = create_pool(2);
+
+        read_block(&pool, 0);
+        read_block(&pool, 1);
+        read_block(&pool, 0);
+        read_block(&pool, 2);
+        read_block(&pool, 0);
+        read_block(&pool, 1);
+
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.hits(), 2);
+        assert_eq!(pool.misses(), 4);
+    }
+
+    /// The blocks read without filling the cache are not added to it, but the cached blocks are
+    /// still read from it.
+    #[test]
+    fn read_without_fill_cache_keeps_cached_blocks() {
+        let pool = create_pool(2);
+        read_block(&pool, 0);
+
+        let mut buffer = vec![0; 512];
+        pool.read_with(512, &mut buffer, false)
+            .expect("read_with should not fail");
+        with_fill_cache(false, || {
+            read_block(&pool, 1);
+            read_block(&pool, 0);
+        });
+        read_block(&pool, 1);
+
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.hits(), 1);
+        assert_eq!(pool.misses(), 4);
+    }
+
+    /// Reading without filling the cache on a thread does not change the reads of the other
+    /// threads.
+    #[test]
+    fn with_fill_cache_only_affects_current_thread() {
+        let pool = create_pool(8);
+
+        with_fill_cache(false, || {
+            std::thread::scope(|scope| {
+                scope.spawn(|| read_block(&pool, 0));
+            });
+            read_block(&pool, 1);
+        });
+        read_block(&pool, 2);
+
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.misses(), 3);
+    }
+
+    /// A write overlapping cached blocks updates them.
+    #[test]
+    fn write_updates_cached_blocks() {
+        let mut pool = create_pool(8);
+        read_block(&pool, 0);
+        read_block(&pool, 1);
+
+        pool.write(500, &[9; 20]).expect("write should not fail");
+
+        assert_eq!(read_block(&pool, 0)[500..], [9; 12]);
+        assert_eq!(read_block(&pool, 1)[..8], [9; 8]);
+        assert_eq!(read_block(&pool, 1)[8], 0);
+        assert_eq!(pool.misses(), 2);
+    }
+
+    /// A large pool spreads its blocks over shards sharing its capacity, which threads read
+    /// concurrently.
+    #[test]
+    fn read_spreads_blocks_over_shards() {
+        let mut pool = BufferPool::new(MemoryFile::new(), 512, 4 * MIN_SHARD_CAPACITY);
+        pool.create().expect("create should not fail");
+        pool.write(0, &vec![0; 1000 * 512])
+            .expect("write should not fail");
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let pool = &pool;
+                scope.spawn(move || {
+                    for index in (thread * 250)..(thread + 1) * 250 {
+                        read_block(pool, index);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(pool.shards.len(), 4);
+        assert_eq!(pool.len(), pool.capacity());
+        assert_eq!((pool.hits(), pool.misses()), (0, 1000));
+    }
+}
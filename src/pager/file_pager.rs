@@ -0,0 +1,639 @@
+use thiserror::Error;
+
+use crate::common::layout::Layout;
+use crate::fs::{File, FileError};
+use crate::{assert_layout_size, disk_layout};
+
+/// Identifies a page inside a paged file.
+///
+/// Page `0` always holds the file header, so it is never handed out by [Pager::allocate_page].
+pub type PageId = u32;
+
+const MIN_PAGE_SIZE: usize = 512;
+const MAX_PAGE_SIZE: usize = 65536;
+
+const MAGIC: [u8; 8] = *b"ROUILLDB";
+const MAX_COMPARATOR_LEN: usize = 63;
+
+disk_layout! {
+    /// The header of the file, at the start of page `0`.
+    struct FileHeader {
+        magic: [u8; 8],
+        page_size: u32,
+        page_count: u32,
+        freelist_head: PageId,
+        comparator_len: u8,
+        /// The name of the comparator, padded with zeros.
+        comparator: [u8; MAX_COMPARATOR_LEN],
+        reserved_txn_id: u64,
+        /// The page of the catalog of the file, `0` until one is set.
+        catalog: PageId,
+    }
+}
+
+assert_layout_size!(FileHeader, 96);
+
+/// Represents errors that can occur during pager operations.
+#[derive(Error, Debug)]
+pub enum PagerError {
+    /// Indicates that an operation on the underlying file failed.
+    #[error(transparent)]
+    File(#[from] FileError),
+
+    /// Indicates that the requested page size is not supported.
+    ///
+    /// # Fields
+    /// - `0` - The page size that was requested.
+    #[error(
+        "The page size ({0}) is not a power of two between {MIN_PAGE_SIZE} and {MAX_PAGE_SIZE}."
+    )]
+    InvalidPageSize(usize),
+
+    /// Indicates that the file does not start with a valid header.
+    #[error("The file does not contain a valid RouilleDB header.")]
+    InvalidHeader,
+
+    /// Indicates that a page outside of the file, or the header page, was accessed.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page that was accessed.
+    #[error("The page ({0}) is not a valid data page.")]
+    InvalidPageId(PageId),
+
+    /// Indicates that a comparator name is too long to be stored in the header.
+    ///
+    /// # Fields
+    /// - `0` - The name that was too long.
+    #[error("The comparator name ({0}) is longer than {MAX_COMPARATOR_LEN} bytes.")]
+    ComparatorNameTooLong(String),
+
+    /// Indicates that the list of freed pages links to an invalid page or loops back on itself.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the invalid link.
+    #[error("The list of free pages is corrupted at page {0}.")]
+    CorruptedFreelist(PageId),
+
+    /// Indicates that a buffer of the wrong size was written to a page.
+    #[error("Cannot write {buffer_size} bytes to a page of {page_size} bytes.")]
+    InvalidBufferSize {
+        page_size: usize,
+        buffer_size: usize,
+    },
+}
+
+/// Divides a [File] into fixed-size pages.
+///
+/// The first page of the file is a header that records the page size, the number of pages, the
+/// head of the list of freed pages, the name of the comparator ordering the keys of the file and
+/// the end of the transaction identifiers reserved by its users and the page of the catalog of the
+/// trees stored in the file.
+/// Freed pages are chained together through their first four bytes and are reused by
+/// [Pager::allocate_page] before the file is grown.
+///
+/// The [Pager] does not open or create the file itself: it expects to receive an opened file.
+pub struct Pager<F: File> {
+    file: F,
+    page_size: usize,
+    page_count: u32,
+    freelist_head: PageId,
+    comparator: String,
+    reserved_txn_id: u64,
+    catalog: PageId,
+}
+
+impl<F: File> Pager<F> {
+    /// The page size used when no specific size is required.
+    pub const DEFAULT_PAGE_SIZE: usize = 4096;
+
+    /// The smallest supported page size.
+    pub const MIN_PAGE_SIZE: usize = MIN_PAGE_SIZE;
+
+    /// The largest supported page size.
+    pub const MAX_PAGE_SIZE: usize = MAX_PAGE_SIZE;
+
+    /// Initializes a new paged file in an opened, empty, file.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the page size is not a power of two between [Pager::MIN_PAGE_SIZE] and
+    ///   [Pager::MAX_PAGE_SIZE]
+    /// - the header can't be written to the file
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    ///
+    /// let pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// assert_eq!(pager.page_count(), 1);
+    /// ```
+    pub fn create(file: F, page_size: usize) -> Result<Self, PagerError> {
+        if !page_size.is_power_of_two()
+            || !(Self::MIN_PAGE_SIZE..=Self::MAX_PAGE_SIZE).contains(&page_size)
+        {
+            return Err(PagerError::InvalidPageSize(page_size));
+        }
+
+        let mut pager = Pager {
+            file,
+            page_size,
+            page_count: 1,
+            freelist_head: 0,
+            comparator: String::new(),
+            reserved_txn_id: 0,
+            catalog: 0,
+        };
+        pager.write_header()?;
+        Ok(pager)
+    }
+
+    /// Opens a paged file previously initialized with [Pager::create].
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the file can't be read
+    /// - the file does not start with a valid header
+    pub fn open(file: F) -> Result<Self, PagerError> {
+        let mut pager = Pager {
+            file,
+            page_size: 0,
+            page_count: 0,
+            freelist_head: 0,
+            comparator: String::new(),
+            reserved_txn_id: 0,
+            catalog: 0,
+        };
+        pager.read_header()?;
+        Ok(pager)
+    }
+
+    /// Reads the header of the file again, after the file was modified by another writer. The
+    /// pager is left unchanged if the header is invalid.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the file can't be read
+    /// - the file does not start with a valid header
+    pub fn reload(&mut self) -> Result<(), PagerError> {
+        self.read_header()
+    }
+
+    /// Returns the size of the pages in bytes.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Returns the number of pages in the file, including the header page.
+    pub fn page_count(&self) -> u32 {
+        self.page_count
+    }
+
+    /// Returns the name of the comparator ordering the keys stored in the file. The name is empty
+    /// until one is set with [Pager::set_comparator].
+    pub fn comparator(&self) -> &str {
+        &self.comparator
+    }
+
+    /// Records the name of the comparator ordering the keys stored in the file.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the name is longer than 63 bytes
+    /// - the header can't be written
+    pub fn set_comparator(&mut self, name: &str) -> Result<(), PagerError> {
+        if name.len() > MAX_COMPARATOR_LEN {
+            return Err(PagerError::ComparatorNameTooLong(name.to_string()));
+        }
+        self.comparator = name.to_string();
+        self.write_header()
+    }
+
+    /// Returns the end of the transaction identifiers reserved in the file: the identifiers
+    /// below it may have been handed out. A new file reserves none.
+    pub fn reserved_txn_id(&self) -> u64 {
+        self.reserved_txn_id
+    }
+
+    /// Records the end of the transaction identifiers reserved in the file.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the header can't be written.
+    pub fn set_reserved_txn_id(&mut self, reserved_txn_id: u64) -> Result<(), PagerError> {
+        self.reserved_txn_id = reserved_txn_id;
+        self.write_header()
+    }
+
+    /// Returns the page of the catalog of the trees stored in the file, or `0` if there is none.
+    pub fn catalog(&self) -> PageId {
+        self.catalog
+    }
+
+    /// Records the page of the catalog of the trees stored in the file, or `0` if there is none.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the page is the header page or is past the end of the file
+    /// - the header can't be written
+    pub fn set_catalog(&mut self, catalog: PageId) -> Result<(), PagerError> {
+        if catalog != 0 {
+            self.check_page_id(catalog)?;
+        }
+        self.catalog = catalog;
+        self.write_header()
+    }
+
+    /// Reads the content of a page.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the page is the header page or is past the end of the file
+    /// - the file can't be read
+    pub fn read_page(&self, id: PageId) -> Result<Vec<u8>, PagerError> {
+        self.check_page_id(id)?;
+
+        let mut buffer = vec![0u8; self.page_size];
+        self.file.read(self.page_offset(id), &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Writes the content of a page.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the page is the header page or is past the end of the file
+    /// - the buffer is not exactly one page long
+    /// - the file can't be written
+    pub fn write_page(&mut self, id: PageId, data: &[u8]) -> Result<(), PagerError> {
+        self.check_page_id(id)?;
+        if data.len() != self.page_size {
+            return Err(PagerError::InvalidBufferSize {
+                page_size: self.page_size,
+                buffer_size: data.len(),
+            });
+        }
+
+        self.file.write(self.page_offset(id), data)?;
+        Ok(())
+    }
+
+    /// Allocates a page, reusing a freed page if there is one. The content of the returned page is
+    /// unspecified.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file can't be read or written.
+    pub fn allocate_page(&mut self) -> Result<PageId, PagerError> {
+        let id = if self.freelist_head != 0 {
+            let id = self.freelist_head;
+            let mut next = [0u8; 4];
+            self.file.read(self.page_offset(id), &mut next)?;
+            self.freelist_head = u32::from_le_bytes(next);
+            id
+        } else {
+            let id = self.page_count;
+            self.page_count += 1;
+            self.file
+                .write(self.page_offset(id), &vec![0u8; self.page_size])?;
+            id
+        };
+
+        self.write_header()?;
+        Ok(id)
+    }
+
+    /// Returns a page to the list of free pages so it can be reused by a later allocation.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the page is the header page or is past the end of the file
+    /// - the file can't be written
+    pub fn free_page(&mut self, id: PageId) -> Result<(), PagerError> {
+        self.check_page_id(id)?;
+
+        let mut buffer = vec![0u8; self.page_size];
+        buffer[..4].copy_from_slice(&self.freelist_head.to_le_bytes());
+        self.file.write(self.page_offset(id), &buffer)?;
+        self.freelist_head = id;
+        self.write_header()
+    }
+
+    /// Returns the freed pages, in the order they will be reused.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file can't be read or if the list of free pages
+    /// links to an invalid page or is longer than the file.
+    pub fn free_pages(&self) -> Result<Vec<PageId>, PagerError> {
+        let mut pages = Vec::new();
+        let mut next = self.freelist_head;
+        while next != 0 {
+            if self.check_page_id(next).is_err() || pages.len() >= self.page_count as usize {
+                return Err(PagerError::CorruptedFreelist(next));
+            }
+            pages.push(next);
+            let mut link = [0u8; 4];
+            self.file.read(self.page_offset(next), &mut link)?;
+            next = u32::from_le_bytes(link);
+        }
+        Ok(pages)
+    }
+
+    /// Flushes all changes to the underlying file.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file can't be synced.
+    pub fn sync(&self) -> Result<(), PagerError> {
+        self.file.sync()?;
+        Ok(())
+    }
+
+    /// Returns the underlying file.
+    pub fn file(&self) -> &F {
+        &self.file
+    }
+
+    /// Consumes the [Pager] and returns the underlying file.
+    pub fn into_file(self) -> F {
+        self.file
+    }
+
+    fn page_offset(&self, id: PageId) -> usize {
+        id as usize * self.page_size
+    }
+
+    /// Reads and checks the header of the file.
+    fn read_header(&mut self) -> Result<(), PagerError> {
+        if self.file.size()? < FileHeader::SIZE {
+            return Err(PagerError::InvalidHeader);
+        }
+
+        let mut bytes = [0u8; FileHeader::SIZE];
+        self.file.read(0, &mut bytes)?;
+        let header = FileHeader::read(&bytes).map_err(|_| PagerError::InvalidHeader)?;
+        let page_size = header.page_size as usize;
+        let comparator_len = usize::from(header.comparator_len);
+        if header.magic != MAGIC
+            || !page_size.is_power_of_two()
+            || !(Self::MIN_PAGE_SIZE..=Self::MAX_PAGE_SIZE).contains(&page_size)
+            || header.page_count == 0
+            || header.freelist_head >= header.page_count
+            || comparator_len > MAX_COMPARATOR_LEN
+            || header.catalog >= header.page_count
+        {
+            return Err(PagerError::InvalidHeader);
+        }
+        let comparator = String::from_utf8(header.comparator[..comparator_len].to_vec())
+            .map_err(|_| PagerError::InvalidHeader)?;
+
+        self.page_size = page_size;
+        self.page_count = header.page_count;
+        self.freelist_head = header.freelist_head;
+        self.comparator = comparator;
+        self.reserved_txn_id = header.reserved_txn_id;
+        self.catalog = header.catalog;
+        Ok(())
+    }
+
+    fn check_page_id(&self, id: PageId) -> Result<(), PagerError> {
+        if id == 0 || id >= self.page_count {
+            return Err(PagerError::InvalidPageId(id));
+        }
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> Result<(), PagerError> {
+        let mut comparator = [0u8; MAX_COMPARATOR_LEN];
+        comparator[..self.comparator.len()].copy_from_slice(self.comparator.as_bytes());
+        let header = FileHeader {
+            magic: MAGIC,
+            page_size: self.page_size as u32,
+            page_count: self.page_count,
+            freelist_head: self.freelist_head,
+            comparator_len: self.comparator.len() as u8,
+            comparator,
+            reserved_txn_id: self.reserved_txn_id,
+            catalog: self.catalog,
+        };
+        let mut page = vec![0u8; self.page_size];
+        header.write(&mut page);
+        self.file.write(0, &page)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::RandomBlob;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_pager() -> Pager<MemoryFile> {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        Pager::create(file, 512).expect("create should not fail")
+    }
+
+    /// Creating a pager with a page size that is not a power of two fails.
+    #[test]
+    fn create_page_size_not_power_of_two_fails() {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+
+        let result = Pager::create(file, 1000);
+
+        assert!(matches!(result, Err(PagerError::InvalidPageSize(1000))));
+    }
+
+    /// Allocated pages are numbered after the header page.
+    #[test]
+    fn allocate_page_returns_new_pages() {
+        let mut pager = create_pager();
+
+        let first = pager.allocate_page().expect("allocate should not fail");
+        let second = pager.allocate_page().expect("allocate should not fail");
+
+        assert_eq!(first, 1);
+        assert_eq!(second, 2);
+        assert_eq!(pager.page_count(), 3);
+    }
+
+    /// The data written to a page is read back unchanged.
+    #[test]
+    fn write_page_then_read_page_returns_data() {
+        let blob = RandomBlob::new(512);
+        let mut pager = create_pager();
+        let id = pager.allocate_page().expect("allocate should not fail");
+
+        pager
+            .write_page(id, blob.data())
+            .expect("write should not fail");
+        let result = pager.read_page(id);
+
+        assert!(matches!(result, Ok(data) if &data == blob.data()));
+    }
+
+    /// Writing a buffer that is not exactly one page fails.
+    #[test]
+    fn write_page_wrong_buffer_size_fails() {
+        let mut pager = create_pager();
+        let id = pager.allocate_page().expect("allocate should not fail");
+
+        let result = pager.write_page(id, &[0u8; 12]);
+
+        assert!(matches!(result, Err(PagerError::InvalidBufferSize { .. })));
+    }
+
+    /// Reading the header page through the pager fails.
+    #[test]
+    fn read_page_header_page_fails() {
+        let pager = create_pager();
+
+        let result = pager.read_page(0);
+
+        assert!(matches!(result, Err(PagerError::InvalidPageId(0))));
+    }
+
+    /// Reading a page past the end of the file fails.
+    #[test]
+    fn read_page_past_the_end_fails() {
+        let mut pager = create_pager();
+        pager.allocate_page().expect("allocate should not fail");
+
+        let result = pager.read_page(2);
+
+        assert!(matches!(result, Err(PagerError::InvalidPageId(2))));
+    }
+
+    /// Freed pages are reused before the file grows.
+    #[test]
+    fn allocate_page_reuses_freed_pages() {
+        let mut pager = create_pager();
+        let first = pager.allocate_page().expect("allocate should not fail");
+        let second = pager.allocate_page().expect("allocate should not fail");
+        pager.free_page(first).expect("free should not fail");
+        pager.free_page(second).expect("free should not fail");
+
+        let reused_second = pager.allocate_page().expect("allocate should not fail");
+        let reused_first = pager.allocate_page().expect("allocate should not fail");
+
+        assert_eq!(reused_second, second);
+        assert_eq!(reused_first, first);
+        assert_eq!(pager.page_count(), 3);
+    }
+
+    /// The freed pages are listed from the most recently freed.
+    #[test]
+    fn free_pages_lists_freed_pages() {
+        let mut pager = create_pager();
+        let first = pager.allocate_page().expect("allocate should not fail");
+        let second = pager.allocate_page().expect("allocate should not fail");
+        pager.allocate_page().expect("allocate should not fail");
+
+        pager.free_page(first).expect("free should not fail");
+        pager.free_page(second).expect("free should not fail");
+        let free_pages = pager.free_pages().expect("free_pages should not fail");
+
+        assert_eq!(free_pages, vec![second, first]);
+    }
+
+    /// Reopening a file restores the page size, the page count and the free pages.
+    #[test]
+    fn open_restores_header() {
+        let mut pager = create_pager();
+        let first = pager.allocate_page().expect("allocate should not fail");
+        pager.allocate_page().expect("allocate should not fail");
+        pager.free_page(first).expect("free should not fail");
+
+        let mut pager = Pager::open(pager.into_file()).expect("open should not fail");
+
+        assert_eq!(pager.page_size(), 512);
+        assert_eq!(pager.page_count(), 3);
+        assert_eq!(pager.allocate_page().ok(), Some(first));
+    }
+
+    /// The comparator name is restored when the file is reopened.
+    #[test]
+    fn set_comparator_persists_name() {
+        let mut pager = create_pager();
+
+        pager
+            .set_comparator("rouilledb.bytewise")
+            .expect("set_comparator should not fail");
+        let pager = Pager::open(pager.into_file()).expect("open should not fail");
+
+        assert_eq!(pager.comparator(), "rouilledb.bytewise");
+    }
+
+    /// The reserved transaction identifiers are kept in the header when the file is reopened.
+    #[test]
+    fn set_reserved_txn_id_persists_reservation() {
+        let mut pager = create_pager();
+        let initial = pager.reserved_txn_id();
+
+        pager
+            .set_reserved_txn_id(u64::MAX - 1)
+            .expect("set_reserved_txn_id should not fail");
+        let pager = Pager::open(pager.into_file()).expect("open should not fail");
+
+        assert_eq!(initial, 0);
+        assert_eq!(pager.reserved_txn_id(), u64::MAX - 1);
+    }
+
+    /// The page of the catalog is kept in the header when the file is reopened, and must be a
+    /// page of the file.
+    #[test]
+    fn set_catalog_persists_page() {
+        let mut pager = create_pager();
+        let page = pager
+            .allocate_page()
+            .expect("allocate_page should not fail");
+
+        let past_end = pager.set_catalog(page + 1);
+        pager
+            .set_catalog(page)
+            .expect("set_catalog should not fail");
+        let pager = Pager::open(pager.into_file()).expect("open should not fail");
+
+        assert!(matches!(past_end, Err(PagerError::InvalidPageId(_))));
+        assert_eq!(pager.catalog(), page);
+    }
+
+    /// Setting a comparator name that does not fit in the header fails.
+    #[test]
+    fn set_comparator_name_too_long_fails() {
+        let mut pager = create_pager();
+
+        let result = pager.set_comparator(&"x".repeat(64));
+
+        assert!(matches!(result, Err(PagerError::ComparatorNameTooLong(_))));
+    }
+
+    /// Opening a file that was not initialized by a pager fails.
+    #[test]
+    fn open_invalid_header_fails() {
+        let blob = RandomBlob::new(512);
+        let mut file = MemoryFile::new_with_data(blob.data().clone());
+        file.open().expect("open should not fail");
+
+        let result = Pager::open(file);
+
+        assert!(matches!(result, Err(PagerError::InvalidHeader)));
+    }
+}
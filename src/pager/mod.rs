@@ -0,0 +1,4 @@
+mod buffer_pool;
+mod file_pager;
+pub use buffer_pool::{with_fill_cache, BufferPool};
+pub use file_pager::{PageId, Pager, PagerError};
@@ -0,0 +1,4 @@
+mod partitioned_tree;
+mod partitioner;
+pub use partitioned_tree::{PartitionError, PartitionedTree};
+pub use partitioner::{HashPartitioner, Partitioner, PrefixPartitioner};
@@ -0,0 +1,330 @@
+use thiserror::Error;
+
+use crate::btree::{BTree, BTreeError};
+use crate::fs::File;
+use crate::pager::{PageId, Pager, PagerError};
+
+use super::Partitioner;
+
+/// Magic bytes at the start of the directory page of a partitioned tree.
+const DIRECTORY_MAGIC: [u8; 8] = *b"ROUIPRT1";
+
+/// Size of the header of the directory page: the magic bytes and the number of partitions.
+const DIRECTORY_HEADER_SIZE: usize = 12;
+
+/// Represents errors that can occur during operations on a [PartitionedTree].
+#[derive(Error, Debug)]
+pub enum PartitionError {
+    /// Indicates that an operation on the underlying pager failed.
+    #[error(transparent)]
+    Pager(#[from] PagerError),
+
+    /// Indicates that an operation on a partition failed.
+    #[error(transparent)]
+    BTree(#[from] BTreeError),
+
+    /// Indicates that a page does not contain a valid directory page.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page that could not be decoded.
+    #[error("The page ({0}) does not contain a valid partition directory.")]
+    CorruptedPage(PageId),
+
+    /// Indicates that the partitioner of a reopened tree does not have as many partitions as the
+    /// tree.
+    ///
+    /// # Fields
+    /// - `expected` - The number of partitions of the tree.
+    /// - `actual` - The number of partitions of the partitioner.
+    #[error("The tree has {expected} partitions, but the partitioner has {actual} partitions.")]
+    PartitionCountMismatch { expected: usize, actual: usize },
+
+    /// Indicates that the roots of the partitions do not fit in the directory page.
+    ///
+    /// # Fields
+    /// - `0` - The number of partitions.
+    #[error("The directory page can't list the {0} partitions of the tree.")]
+    TooManyPartitions(usize),
+}
+
+/// Represents a logical keyspace divided among several [BTree]s, its partitions, by a
+/// [Partitioner].
+///
+/// Each key is routed to the partition chosen by the partitioner, so the partitions are
+/// independent trees: they can be verified, analyzed or defragmented one at a time, through
+/// [PartitionedTree::partition_mut], which keeps the maintenance of a large dataset in small
+/// steps.
+///
+/// The roots of the partitions are listed in a directory page that never moves, so the tree can be
+/// reopened with [PartitionedTree::open]. The partitioner is not stored: the tree must be reopened
+/// with a partitioner routing the keys like the one it was created with. The partitions are
+/// ordered bytewise.
+pub struct PartitionedTree {
+    directory: PageId,
+    partitions: Vec<BTree>,
+    partitioner: Box<dyn Partitioner>,
+}
+
+impl PartitionedTree {
+    /// Creates a new, empty, tree with an empty partition for each partition of the partitioner.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the file is not ordered bytewise
+    /// - the roots of the partitions do not fit in the directory page
+    /// - a page can't be allocated or written
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    /// use rouilledb::partition::{PartitionedTree, PrefixPartitioner};
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let partitioner = PrefixPartitioner::new(vec![b"order/".to_vec(), b"user/".to_vec()]);
+    /// let mut tree = PartitionedTree::create(&mut pager, Box::new(partitioner))
+    ///     .expect("create should not fail");
+    /// tree.insert(&mut pager, b"user/alice", b"admin").expect("insert should not fail");
+    ///
+    /// let value = tree.get(&pager, b"user/alice").expect("get should not fail");
+    /// assert_eq!(value, Some(b"admin".to_vec()));
+    /// assert_eq!(tree.partition_of(b"user/alice"), 2);
+    /// ```
+    pub fn create<F: File>(
+        pager: &mut Pager<F>,
+        partitioner: Box<dyn Partitioner>,
+    ) -> Result<Self, PartitionError> {
+        let partition_count = partitioner.partition_count();
+        if DIRECTORY_HEADER_SIZE + partition_count * 4 > pager.page_size() {
+            return Err(PartitionError::TooManyPartitions(partition_count));
+        }
+
+        let directory = pager.allocate_page()?;
+        let partitions = (0..partition_count)
+            .map(|_| BTree::create(pager))
+            .collect::<Result<Vec<_>, BTreeError>>()?;
+        let tree = PartitionedTree {
+            directory,
+            partitions,
+            partitioner,
+        };
+        tree.write_directory(pager)?;
+        Ok(tree)
+    }
+
+    /// Opens a tree previously created with [PartitionedTree::create] from its directory page.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the directory page can't be read or is corrupted
+    /// - the partitioner does not have as many partitions as the tree
+    /// - a partition can't be opened
+    pub fn open<F: File>(
+        pager: &Pager<F>,
+        directory: PageId,
+        partitioner: Box<dyn Partitioner>,
+    ) -> Result<Self, PartitionError> {
+        let page = pager.read_page(directory)?;
+        if page[..DIRECTORY_MAGIC.len()] != DIRECTORY_MAGIC {
+            return Err(PartitionError::CorruptedPage(directory));
+        }
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes(
+                page[offset..offset + 4]
+                    .try_into()
+                    .expect("slice should be 4 bytes"),
+            )
+        };
+        let partition_count = read_u32(8) as usize;
+        if DIRECTORY_HEADER_SIZE + partition_count * 4 > page.len() {
+            return Err(PartitionError::CorruptedPage(directory));
+        }
+        if partition_count != partitioner.partition_count() {
+            return Err(PartitionError::PartitionCountMismatch {
+                expected: partition_count,
+                actual: partitioner.partition_count(),
+            });
+        }
+
+        let partitions = (0..partition_count)
+            .map(|index| BTree::open(pager, read_u32(DIRECTORY_HEADER_SIZE + index * 4)))
+            .collect::<Result<Vec<_>, BTreeError>>()?;
+        Ok(PartitionedTree {
+            directory,
+            partitions,
+            partitioner,
+        })
+    }
+
+    /// Returns the identifier of the directory page of the tree.
+    pub fn directory(&self) -> PageId {
+        self.directory
+    }
+
+    /// Returns the number of partitions of the tree.
+    pub fn partition_count(&self) -> usize {
+        self.partitions.len()
+    }
+
+    /// Returns the index of the partition holding a key.
+    pub fn partition_of(&self, key: &[u8]) -> usize {
+        self.partitioner.partition(key)
+    }
+
+    /// Returns a partition of the tree.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `index` is not smaller than [PartitionedTree::partition_count].
+    pub fn partition(&self, index: usize) -> &BTree {
+        &self.partitions[index]
+    }
+
+    /// Returns a partition of the tree, to maintain it, for example with
+    /// [BTree::defragment]. The keys inserted directly in a partition must belong to it.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `index` is not smaller than [PartitionedTree::partition_count].
+    pub fn partition_mut(&mut self, index: usize) -> &mut BTree {
+        &mut self.partitions[index]
+    }
+
+    /// Returns the value associated with a key, or `None` if the key is not present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, PartitionError> {
+        Ok(self.partitions[self.partition_of(key)].get(pager, key)?)
+    }
+
+    /// Inserts a key-value pair in the partition of the key, replacing the previous value of the
+    /// key. Returns the previous value, if there was one.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key or the value is too large, or if a page can't
+    /// be read, written or allocated.
+    pub fn insert<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>, PartitionError> {
+        let index = self.partition_of(key);
+        Ok(self.partitions[index].insert(pager, key, value)?)
+    }
+
+    /// Removes a key from its partition. Returns the value of the key, if it was present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read, written or freed.
+    pub fn delete<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, PartitionError> {
+        let index = self.partition_of(key);
+        Ok(self.partitions[index].delete(pager, key)?)
+    }
+
+    fn write_directory<F: File>(&self, pager: &mut Pager<F>) -> Result<(), PartitionError> {
+        let mut page = Vec::with_capacity(pager.page_size());
+        page.extend_from_slice(&DIRECTORY_MAGIC);
+        page.extend_from_slice(&(self.partitions.len() as u32).to_le_bytes());
+        for partition in &self.partitions {
+            page.extend_from_slice(&partition.root().to_le_bytes());
+        }
+        page.resize(pager.page_size(), 0);
+        pager.write_page(self.directory, &page)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::MemoryFile;
+    use crate::partition::{HashPartitioner, PrefixPartitioner};
+
+    use super::*;
+
+    fn create_pager() -> Pager<MemoryFile> {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        Pager::create(file, 512).expect("create should not fail")
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("{}/{index:04}", ["a", "m", "z"][index % 3]).into_bytes()
+    }
+
+    /// Each key is stored in its partition only, and is found after the tree is reopened.
+    #[test]
+    fn insert_routes_keys_to_partitions() {
+        let mut pager = create_pager();
+        let partitioner = || Box::new(PrefixPartitioner::new(vec![b"m".to_vec(), b"z".to_vec()]));
+        let mut tree =
+            PartitionedTree::create(&mut pager, partitioner()).expect("create should not fail");
+        for index in 0..300 {
+            tree.insert(&mut pager, &key(index), b"value")
+                .expect("insert should not fail");
+        }
+        tree.delete(&mut pager, &key(0))
+            .expect("delete should not fail");
+
+        let opened = PartitionedTree::open(&pager, tree.directory(), partitioner())
+            .expect("open should not fail");
+
+        for index in 0..3 {
+            let keys: Vec<Vec<u8>> = opened
+                .partition(index)
+                .iter(&pager)
+                .map(|entry| entry.expect("iteration should not fail").0)
+                .collect();
+            assert_eq!(keys.len(), if index == 0 { 99 } else { 100 });
+            assert!(keys.iter().all(|key| opened.partition_of(key) == index));
+        }
+        assert_eq!(
+            opened.get(&pager, &key(0)).expect("get should not fail"),
+            None
+        );
+        assert_eq!(
+            opened.get(&pager, &key(1)).expect("get should not fail"),
+            Some(b"value".to_vec())
+        );
+    }
+
+    /// A tree can't be reopened with a partitioner having another number of partitions.
+    #[test]
+    fn open_with_other_partition_count_fails() {
+        let mut pager = create_pager();
+        let tree = PartitionedTree::create(&mut pager, Box::new(HashPartitioner::new(4, 2)))
+            .expect("create should not fail");
+
+        let result = PartitionedTree::open(
+            &pager,
+            tree.directory(),
+            Box::new(HashPartitioner::new(8, 2)),
+        );
+
+        assert!(matches!(
+            result,
+            Err(PartitionError::PartitionCountMismatch {
+                expected: 4,
+                actual: 8
+            })
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::common::hash64;
+
+/// Chooses the partition of a [PartitionedTree](super::PartitionedTree) that holds each key.
+///
+/// The partition of a key must never change, and the number of partitions must stay the same for
+/// the life of a tree, since the keys are not moved between partitions.
+pub trait Partitioner: Send + Sync {
+    /// Returns the number of partitions.
+    fn partition_count(&self) -> usize;
+
+    /// Returns the index of the partition holding a key, smaller than
+    /// [Partitioner::partition_count].
+    fn partition(&self, key: &[u8]) -> usize;
+}
+
+/// Partitions the keys by ranges of prefixes: the partition `i` holds the keys from the boundary
+/// `i - 1`, included, to the boundary `i`, excluded. The first partition holds the keys smaller
+/// than the first boundary and the last one the keys from the last boundary.
+///
+/// The partitions follow the bytewise order of the keys, so a range of keys is held by
+/// consecutive partitions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixPartitioner {
+    boundaries: Vec<Vec<u8>>,
+}
+
+impl PrefixPartitioner {
+    /// Creates a partitioner from the keys starting each partition but the first. The boundaries
+    /// are sorted and the duplicates are removed.
+    pub fn new(mut boundaries: Vec<Vec<u8>>) -> Self {
+        boundaries.sort_unstable();
+        boundaries.dedup();
+        PrefixPartitioner { boundaries }
+    }
+
+    /// Returns the keys starting each partition but the first.
+    pub fn boundaries(&self) -> &[Vec<u8>] {
+        &self.boundaries
+    }
+}
+
+impl Partitioner for PrefixPartitioner {
+    fn partition_count(&self) -> usize {
+        self.boundaries.len() + 1
+    }
+
+    fn partition(&self, key: &[u8]) -> usize {
+        self.boundaries
+            .partition_point(|boundary| boundary.as_slice() <= key)
+    }
+}
+
+/// Partitions the keys by the hash of their first bytes, which spreads the keys evenly among the
+/// partitions. The keys sharing their first bytes are held by the same partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashPartitioner {
+    partition_count: usize,
+    prefix_len: usize,
+}
+
+impl HashPartitioner {
+    /// Creates a partitioner hashing the first `prefix_len` bytes of the keys, or the whole key
+    /// if it is shorter, into `partition_count` partitions.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `partition_count` is `0`.
+    pub fn new(partition_count: usize, prefix_len: usize) -> Self {
+        assert!(partition_count > 0, "a tree needs at least one partition");
+        HashPartitioner {
+            partition_count,
+            prefix_len,
+        }
+    }
+}
+
+impl Partitioner for HashPartitioner {
+    fn partition_count(&self) -> usize {
+        self.partition_count
+    }
+
+    fn partition(&self, key: &[u8]) -> usize {
+        let prefix = &key[..key.len().min(self.prefix_len)];
+        (hash64(prefix) % self.partition_count as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each key goes to the range of boundaries it belongs to.
+    #[test]
+    fn prefix_partitioner_follows_boundaries() {
+        let partitioner = PrefixPartitioner::new(vec![b"m".to_vec(), b"f".to_vec()]);
+
+        assert_eq!(partitioner.partition_count(), 3);
+        assert_eq!(partitioner.partition(b""), 0);
+        assert_eq!(partitioner.partition(b"apple"), 0);
+        assert_eq!(partitioner.partition(b"f"), 1);
+        assert_eq!(partitioner.partition(b"kiwi"), 1);
+        assert_eq!(partitioner.partition(b"mango"), 2);
+    }
+
+    /// The keys sharing a prefix go to the same partition, and the keys are spread among all the
+    /// partitions.
+    #[test]
+    fn hash_partitioner_groups_prefixes() {
+        let partitioner = HashPartitioner::new(4, 3);
+        let mut used = [false; 4];
+        for index in 0..100 {
+            used[partitioner.partition(format!("{index:03}").as_bytes())] = true;
+        }
+
+        assert_eq!(
+            partitioner.partition(b"user-1"),
+            partitioner.partition(b"use")
+        );
+        assert!(used.iter().all(|used| *used));
+    }
+}
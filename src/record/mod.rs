@@ -0,0 +1,4 @@
+mod row;
+mod value;
+pub use row::{Column, Record, RecordError, Schema};
+pub use value::{ColumnType, Value};
@@ -0,0 +1,565 @@
+use std::ops::Range;
+
+use thiserror::Error;
+
+use super::{ColumnType, Value};
+use crate::common::varint;
+
+/// The tag of a null column in the header of a record.
+const NULL_TAG: u64 = 0;
+
+/// The tag of an integer column in the header of a record.
+const INTEGER_TAG: u64 = 1;
+
+/// The tag of a float column in the header of a record.
+const FLOAT_TAG: u64 = 2;
+
+/// The tag of a text column in the header of a record.
+const TEXT_TAG: u64 = 3;
+
+/// The tag of a blob column in the header of a record.
+const BLOB_TAG: u64 = 4;
+
+/// The number of bits of a column descriptor holding the tag of the column.
+const TAG_BITS: u32 = 3;
+
+/// The size of the body of a float column.
+const FLOAT_SIZE: usize = 8;
+
+/// Represents errors that can occur when encoding a [Record] or reading its columns.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RecordError {
+    /// Indicates that the bytes are not a valid record.
+    #[error("The record is corrupted.")]
+    Corrupted,
+
+    /// Indicates that a column past the last column of the record was read.
+    ///
+    /// # Fields
+    /// - `index` - The index of the column read.
+    /// - `count` - The number of columns of the record.
+    #[error("Cannot read the column {index} of a record of {count} columns.")]
+    ColumnOutOfRange { index: usize, count: usize },
+
+    /// Indicates that a column was read as a type other than the type of its value.
+    ///
+    /// # Fields
+    /// - `index` - The index of the column read.
+    /// - `expected` - The type the column was read as.
+    /// - `actual` - The type of the value of the column.
+    #[error("The column {index} was read as {expected}, but its value is {actual}.")]
+    TypeMismatch {
+        index: usize,
+        expected: ColumnType,
+        actual: ColumnType,
+    },
+
+    /// Indicates that the number of values does not match the number of columns of the schema.
+    ///
+    /// # Fields
+    /// - `expected` - The number of columns of the schema.
+    /// - `actual` - The number of values.
+    #[error("The schema has {expected} columns, but the record has {actual} values.")]
+    ColumnCount { expected: usize, actual: usize },
+
+    /// Indicates that the value of a column does not have the type declared by the schema.
+    ///
+    /// # Fields
+    /// - `column` - The name of the column.
+    /// - `expected` - The type declared by the schema.
+    /// - `actual` - The type of the value.
+    #[error("The column \"{column}\" is {expected}, but its value is {actual}.")]
+    InvalidType {
+        column: String,
+        expected: ColumnType,
+        actual: ColumnType,
+    },
+
+    /// Indicates that a column that is not nullable is null.
+    ///
+    /// # Fields
+    /// - `0` - The name of the column.
+    #[error("The column \"{0}\" is not nullable, but its value is null.")]
+    NotNullable(String),
+}
+
+/// A column of a [Schema].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    /// The name of the column.
+    pub name: String,
+    /// The type of the values of the column.
+    pub column_type: ColumnType,
+    /// Whether the column can be null.
+    pub nullable: bool,
+}
+
+/// Describes the columns of records: their names, their types and whether they can be null.
+///
+/// The records themselves do not refer to their schema. A schema validates the values before they
+/// are encoded, and the records before they are read, so the columns can then be read with the
+/// type they are declared with.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::record::{ColumnType, Schema, Value};
+///
+/// let schema = Schema::new()
+///     .column("id", ColumnType::Integer)
+///     .column("name", ColumnType::Text)
+///     .nullable_column("score", ColumnType::Float);
+///
+/// let bytes = schema
+///     .encode(&[Value::from(7), Value::from("alice"), Value::Null])
+///     .expect("encode should not fail");
+/// let record = schema.decode(&bytes).expect("decode should not fail");
+///
+/// let name = schema.index_of("name").expect("the column should exist");
+/// assert_eq!(record.text(name), Ok(Some("alice")));
+/// assert_eq!(record.float(2), Ok(None));
+/// assert!(schema.encode(&[Value::Null, Value::from("bob"), Value::Null]).is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    columns: Vec<Column>,
+}
+
+impl Schema {
+    /// Creates a schema with no column.
+    pub fn new() -> Self {
+        Schema::default()
+    }
+
+    /// Adds a column that cannot be null.
+    pub fn column(self, name: &str, column_type: ColumnType) -> Self {
+        self.add_column(name, column_type, false)
+    }
+
+    /// Adds a column that can be null.
+    pub fn nullable_column(self, name: &str, column_type: ColumnType) -> Self {
+        self.add_column(name, column_type, true)
+    }
+
+    fn add_column(mut self, name: &str, column_type: ColumnType, nullable: bool) -> Self {
+        self.columns.push(Column {
+            name: name.to_string(),
+            column_type,
+            nullable,
+        });
+        self
+    }
+
+    /// Returns the columns, in order.
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Returns the index of the column named `name`, or `None` if there is none.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+
+    /// Encodes a record with a value for each column.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if there is not one value per column, or if a value does
+    /// not have the type of its column or is null in a column that is not nullable.
+    pub fn encode(&self, values: &[Value]) -> Result<Vec<u8>, RecordError> {
+        self.check(values.iter().map(Value::column_type), values.len())?;
+        Ok(Record::encode(values))
+    }
+
+    /// Reads a record encoded with the schema, checking the types of its columns but not decoding
+    /// their values.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the record is corrupted, or if its columns do not match
+    /// the schema.
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Result<Record<'a>, RecordError> {
+        let record = Record::new(bytes)?;
+        self.check(
+            record.columns.iter().map(|(column_type, _)| *column_type),
+            record.len(),
+        )?;
+        Ok(record)
+    }
+
+    /// Checks the types of the values of a record against the columns.
+    fn check(
+        &self,
+        types: impl Iterator<Item = Option<ColumnType>>,
+        count: usize,
+    ) -> Result<(), RecordError> {
+        if count != self.columns.len() {
+            return Err(RecordError::ColumnCount {
+                expected: self.columns.len(),
+                actual: count,
+            });
+        }
+        for (column, column_type) in self.columns.iter().zip(types) {
+            match column_type {
+                None if !column.nullable => {
+                    return Err(RecordError::NotNullable(column.name.clone()))
+                }
+                Some(actual) if actual != column.column_type => {
+                    return Err(RecordError::InvalidType {
+                        column: column.name.clone(),
+                        expected: column.column_type,
+                        actual,
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A record of typed columns, read from its encoding without decoding the columns that are not
+/// read.
+///
+/// A record starts with a header: the number of columns, then a descriptor for each column. The
+/// descriptor is a varint holding the size of the value of the column shifted left by 3 bits,
+/// and the tag of its type in the low bits. The values follow the header, in order, so reading the
+/// header is enough to find the value of any column:
+/// - a null has no bytes,
+/// - an integer is a zigzag encoded varint, so small integers take a single byte,
+/// - a float is 8 little-endian bytes,
+/// - a text is its UTF-8 bytes and a blob its bytes.
+///
+/// # Example
+///
+/// ```
+/// use rouilledb::record::{ColumnType, Record, Value};
+///
+/// let bytes = Record::encode(&[Value::from(-3), Value::from(b"\x00\x01".as_slice())]);
+/// let record = Record::new(&bytes).expect("new should not fail");
+///
+/// assert_eq!(record.len(), 2);
+/// assert_eq!(record.column_type(1), Ok(Some(ColumnType::Blob)));
+/// assert_eq!(record.integer(0), Ok(Some(-3)));
+/// assert_eq!(record.blob(1), Ok(Some(b"\x00\x01".as_slice())));
+/// assert!(record.text(1).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Record<'a> {
+    bytes: &'a [u8],
+    /// The type and the range of the bytes of the value of each column.
+    columns: Vec<(Option<ColumnType>, Range<usize>)>,
+}
+
+impl<'a> Record<'a> {
+    /// Encodes a record with the given values.
+    pub fn encode(values: &[Value]) -> Vec<u8> {
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        varint::encode_u64(values.len() as u64, &mut header);
+        for value in values {
+            let start = body.len();
+            let tag = match value {
+                Value::Null => NULL_TAG,
+                Value::Integer(integer) => {
+                    varint::encode_i64(*integer, &mut body);
+                    INTEGER_TAG
+                }
+                Value::Float(float) => {
+                    body.extend_from_slice(&float.to_le_bytes());
+                    FLOAT_TAG
+                }
+                Value::Text(text) => {
+                    body.extend_from_slice(text.as_bytes());
+                    TEXT_TAG
+                }
+                Value::Blob(blob) => {
+                    body.extend_from_slice(blob);
+                    BLOB_TAG
+                }
+            };
+            let size = (body.len() - start) as u64;
+            varint::encode_u64(size << TAG_BITS | tag, &mut header);
+        }
+        header.extend_from_slice(&body);
+        header
+    }
+
+    /// Reads the header of an encoded record.
+    ///
+    /// # Errors
+    ///
+    /// This method will return [RecordError::Corrupted] if the header is invalid or if the sizes
+    /// of the values do not add up to the size of the record.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, RecordError> {
+        let (count, mut offset) = varint::decode_u64(bytes).ok_or(RecordError::Corrupted)?;
+        // Each column takes at least a byte of the header.
+        if count > bytes.len() as u64 {
+            return Err(RecordError::Corrupted);
+        }
+        let mut descriptors = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (descriptor, length) =
+                varint::decode_u64(&bytes[offset..]).ok_or(RecordError::Corrupted)?;
+            offset += length;
+            descriptors.push(descriptor);
+        }
+
+        let mut columns = Vec::with_capacity(descriptors.len());
+        for descriptor in descriptors {
+            let size =
+                usize::try_from(descriptor >> TAG_BITS).map_err(|_| RecordError::Corrupted)?;
+            let column_type = match descriptor & ((1 << TAG_BITS) - 1) {
+                NULL_TAG if size == 0 => None,
+                INTEGER_TAG if (1..=varint::MAX_LEN).contains(&size) => Some(ColumnType::Integer),
+                FLOAT_TAG if size == FLOAT_SIZE => Some(ColumnType::Float),
+                TEXT_TAG => Some(ColumnType::Text),
+                BLOB_TAG => Some(ColumnType::Blob),
+                _ => return Err(RecordError::Corrupted),
+            };
+            let end = offset
+                .checked_add(size)
+                .filter(|&end| end <= bytes.len())
+                .ok_or(RecordError::Corrupted)?;
+            columns.push((column_type, offset..end));
+            offset = end;
+        }
+        if offset != bytes.len() {
+            return Err(RecordError::Corrupted);
+        }
+        Ok(Record { bytes, columns })
+    }
+
+    /// Returns the number of columns.
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Returns `true` if the record has no column.
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Returns the type of the value of a column, or `None` if it is null.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the record has no column `index`.
+    pub fn column_type(&self, index: usize) -> Result<Option<ColumnType>, RecordError> {
+        Ok(self.column(index)?.0)
+    }
+
+    /// Returns `true` if the value of a column is null.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the record has no column `index`.
+    pub fn is_null(&self, index: usize) -> Result<bool, RecordError> {
+        Ok(self.column_type(index)?.is_none())
+    }
+
+    /// Returns the value of an integer column, or `None` if it is null.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the record has no column `index`, if its value is not
+    /// an integer, or if the value is corrupted.
+    pub fn integer(&self, index: usize) -> Result<Option<i64>, RecordError> {
+        self.typed(index, ColumnType::Integer)?
+            .map(|bytes| match varint::decode_i64(bytes) {
+                Some((integer, length)) if length == bytes.len() => Ok(integer),
+                _ => Err(RecordError::Corrupted),
+            })
+            .transpose()
+    }
+
+    /// Returns the value of a float column, or `None` if it is null.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the record has no column `index` or if its value is not
+    /// a float.
+    pub fn float(&self, index: usize) -> Result<Option<f64>, RecordError> {
+        Ok(self.typed(index, ColumnType::Float)?.map(|bytes| {
+            f64::from_le_bytes(
+                bytes
+                    .try_into()
+                    .expect("the size of a float column is checked by Record::new"),
+            )
+        }))
+    }
+
+    /// Returns the value of a text column, or `None` if it is null.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the record has no column `index`, if its value is not
+    /// a text, or if the value is not valid UTF-8.
+    pub fn text(&self, index: usize) -> Result<Option<&'a str>, RecordError> {
+        self.typed(index, ColumnType::Text)?
+            .map(|bytes| std::str::from_utf8(bytes).map_err(|_| RecordError::Corrupted))
+            .transpose()
+    }
+
+    /// Returns the value of a blob column, or `None` if it is null.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the record has no column `index` or if its value is not
+    /// a blob.
+    pub fn blob(&self, index: usize) -> Result<Option<&'a [u8]>, RecordError> {
+        self.typed(index, ColumnType::Blob)
+    }
+
+    /// Decodes the value of a column, whatever its type.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the record has no column `index` or if its value is
+    /// corrupted.
+    pub fn value(&self, index: usize) -> Result<Value, RecordError> {
+        Ok(match self.column_type(index)? {
+            None => Value::Null,
+            Some(ColumnType::Integer) => Value::from(self.integer(index)?),
+            Some(ColumnType::Float) => Value::from(self.float(index)?),
+            Some(ColumnType::Text) => Value::from(self.text(index)?),
+            Some(ColumnType::Blob) => Value::from(self.blob(index)?.map(<[u8]>::to_vec)),
+        })
+    }
+
+    /// Decodes the values of all the columns.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a value is corrupted.
+    pub fn values(&self) -> Result<Vec<Value>, RecordError> {
+        (0..self.len()).map(|index| self.value(index)).collect()
+    }
+
+    fn column(&self, index: usize) -> Result<&(Option<ColumnType>, Range<usize>), RecordError> {
+        self.columns
+            .get(index)
+            .ok_or(RecordError::ColumnOutOfRange {
+                index,
+                count: self.len(),
+            })
+    }
+
+    /// Returns the bytes of the value of a column of the `expected` type, or `None` if it is null.
+    fn typed(&self, index: usize, expected: ColumnType) -> Result<Option<&'a [u8]>, RecordError> {
+        let (column_type, range) = self.column(index)?;
+        match column_type {
+            None => Ok(None),
+            Some(actual) if *actual == expected => Ok(Some(&self.bytes[range.clone()])),
+            Some(actual) => Err(RecordError::TypeMismatch {
+                index,
+                expected,
+                actual: *actual,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    /// The values of every type are read back from their encoding, by column and all at once.
+    #[test]
+    fn encode_returns_readable_record() {
+        let values = vec![
+            Value::Null,
+            Value::from(i64::MIN),
+            Value::from(-1),
+            Value::from(f64::NEG_INFINITY),
+            Value::from(""),
+            Value::from("héllo"),
+            Value::from(vec![0x00, 0xff]),
+        ];
+
+        let bytes = Record::encode(&values);
+        let record = Record::new(&bytes).expect("new should not fail");
+
+        assert_eq!(record.values(), Ok(values));
+        assert_eq!(record.integer(0), Ok(None));
+        assert_eq!(record.integer(2), Ok(Some(-1)));
+        assert_eq!(record.text(5), Ok(Some("héllo")));
+        assert_eq!(
+            record.float(1),
+            Err(RecordError::TypeMismatch {
+                index: 1,
+                expected: ColumnType::Float,
+                actual: ColumnType::Integer,
+            })
+        );
+        assert_eq!(
+            record.blob(7),
+            Err(RecordError::ColumnOutOfRange { index: 7, count: 7 })
+        );
+        // The column count, a byte per descriptor, and the bodies.
+        assert_eq!(bytes.len(), 1 + 7 + (10 + 1 + 8 + 6 + 2));
+    }
+
+    /// A schema rejects the values and the records that do not match its columns.
+    #[test]
+    fn schema_checks_columns() {
+        let schema = Schema::new()
+            .column("id", ColumnType::Integer)
+            .nullable_column("name", ColumnType::Text);
+        let other = Schema::new()
+            .column("id", ColumnType::Integer)
+            .column("name", ColumnType::Blob);
+        let bytes = schema
+            .encode(&[Value::from(1), Value::Null])
+            .expect("encode should not fail");
+
+        assert_eq!(
+            schema.encode(&[Value::from(1)]),
+            Err(RecordError::ColumnCount {
+                expected: 2,
+                actual: 1
+            })
+        );
+        assert_eq!(
+            schema.encode(&[Value::Null, Value::Null]),
+            Err(RecordError::NotNullable("id".to_string()))
+        );
+        assert_eq!(
+            schema.encode(&[Value::from(1.5), Value::Null]),
+            Err(RecordError::InvalidType {
+                column: "id".to_string(),
+                expected: ColumnType::Integer,
+                actual: ColumnType::Float,
+            })
+        );
+        assert!(schema.decode(&bytes).is_ok());
+        assert_eq!(
+            other.decode(&bytes).map(|record| record.len()),
+            Err(RecordError::NotNullable("name".to_string()))
+        );
+        assert_eq!(schema.index_of("name"), Some(1));
+        assert_eq!(schema.index_of("score"), None);
+    }
+
+    /// Truncated and random bytes are rejected or read without panicking.
+    #[test]
+    fn new_rejects_corrupted_records() {
+        let mut rng = rand::thread_rng();
+        let bytes = Record::encode(&[Value::from(300), Value::from("text")]);
+
+        for length in 0..bytes.len() {
+            assert_eq!(
+                Record::new(&bytes[..length]).map(|record| record.len()),
+                Err(RecordError::Corrupted)
+            );
+        }
+        for _ in 0..10_000 {
+            let random: Vec<u8> = (0..rng.gen_range(0..16)).map(|_| rng.gen()).collect();
+            if let Ok(record) = Record::new(&random) {
+                let _ = record.values();
+            }
+        }
+    }
+}
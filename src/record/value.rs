@@ -0,0 +1,96 @@
+use std::fmt;
+
+/// The type of the values of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColumnType {
+    /// Signed 64-bit integers.
+    Integer,
+    /// 64-bit floats.
+    Float,
+    /// UTF-8 strings.
+    Text,
+    /// Byte strings.
+    Blob,
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Text => "text",
+            ColumnType::Blob => "blob",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The value of a column of a [Record](super::Record).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// The absence of a value, allowed in any column declared nullable.
+    Null,
+    /// A signed 64-bit integer.
+    Integer(i64),
+    /// A 64-bit float.
+    Float(f64),
+    /// A UTF-8 string.
+    Text(String),
+    /// A byte string.
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    /// Returns the type of the value, or `None` if it is [Value::Null].
+    pub fn column_type(&self) -> Option<ColumnType> {
+        match self {
+            Value::Null => None,
+            Value::Integer(_) => Some(ColumnType::Integer),
+            Value::Float(_) => Some(ColumnType::Float),
+            Value::Text(_) => Some(ColumnType::Text),
+            Value::Blob(_) => Some(ColumnType::Blob),
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(value: &[u8]) -> Self {
+        Value::Blob(value.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        Value::Blob(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
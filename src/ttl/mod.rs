@@ -0,0 +1,4 @@
+mod purger;
+mod ttl_tree;
+pub use purger::TtlPurger;
+pub use ttl_tree::{TtlError, TtlTree};
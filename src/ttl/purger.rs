@@ -0,0 +1,189 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::fs::File;
+use crate::pager::Pager;
+
+use super::{TtlError, TtlTree};
+
+/// A thread removing the expired keys of a shared [TtlTree] at an interval, so the tree needs no
+/// cleanup job of its own.
+///
+/// Every `interval`, the purger calls [TtlTree::purge] until no expired key is left, locking the
+/// tree and its pager for each step of at most `step` keys, so the other operations go on between
+/// two steps.
+///
+/// The purger does not keep the tree alive: it stops once every handle to the tree is dropped, or
+/// at its first error. It is stopped when it is dropped.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use std::time::Duration;
+///
+/// use rouilledb::fs::{File, MemoryFile};
+/// use rouilledb::pager::Pager;
+/// use rouilledb::ttl::{TtlPurger, TtlTree};
+///
+/// let mut file = MemoryFile::new();
+/// file.create().expect("create should not fail");
+/// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+/// let tree = TtlTree::create(&mut pager).expect("create should not fail");
+/// let shared = Arc::new(Mutex::new((pager, tree)));
+///
+/// let purger = TtlPurger::start(&shared, Duration::from_secs(1), 100);
+/// {
+///     let (pager, tree) = &mut *shared.lock().expect("the lock should not be poisoned");
+///     tree.insert_with_ttl(pager, b"session", b"alice", Duration::from_secs(60))
+///         .expect("insert_with_ttl should not fail");
+/// }
+/// purger.stop().expect("stop should not fail");
+/// ```
+pub struct TtlPurger {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<Result<(), TtlError>>>,
+    purged: Arc<AtomicU64>,
+}
+
+impl TtlPurger {
+    /// Starts a thread purging the expired keys of a tree shared with its pager every `interval`,
+    /// `step` keys at a time.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `step` is `0`.
+    pub fn start<F: File + Send + 'static>(
+        tree: &Arc<Mutex<(Pager<F>, TtlTree)>>,
+        interval: Duration,
+        step: usize,
+    ) -> Self {
+        assert!(step > 0, "a step should purge at least one key");
+        let (stop, stopped) = mpsc::channel();
+        let purged = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&purged);
+        let tree = Arc::downgrade(tree);
+        let thread = thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            let Some(tree) = tree.upgrade() else {
+                return Ok(());
+            };
+            purge(&tree, step, &counter)?;
+        });
+        TtlPurger {
+            stop: Some(stop),
+            thread: Some(thread),
+            purged,
+        }
+    }
+
+    /// Returns the number of keys removed by the purger since it was started.
+    pub fn purged(&self) -> u64 {
+        self.purged.load(Ordering::Relaxed)
+    }
+
+    /// Stops the purger, waiting for a step in progress to end.
+    ///
+    /// # Errors
+    ///
+    /// This method will return the error that stopped the purger, if any.
+    pub fn stop(mut self) -> Result<(), TtlError> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), TtlError> {
+        drop(self.stop.take());
+        match self.thread.take() {
+            Some(thread) => thread.join().expect("the purger should not panic"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TtlPurger {
+    fn drop(&mut self) {
+        // The error is returned by `stop`, and is lost if the purger is dropped instead.
+        let _ = self.join();
+    }
+}
+
+/// Purges the expired keys of a shared tree, `step` keys at a time, and adds their number to
+/// `purged`.
+fn purge<F: File>(
+    tree: &Mutex<(Pager<F>, TtlTree)>,
+    step: usize,
+    purged: &AtomicU64,
+) -> Result<(), TtlError> {
+    loop {
+        let (pager, tree) = &mut *lock(tree);
+        let removed = tree.purge(pager, step)?;
+        purged.fetch_add(removed as u64, Ordering::Relaxed);
+        if removed < step {
+            return Ok(());
+        }
+    }
+}
+
+fn lock<F: File>(tree: &Mutex<(Pager<F>, TtlTree)>) -> MutexGuard<'_, (Pager<F>, TtlTree)> {
+    tree.lock().expect("the tree lock should not be poisoned")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::common::clock::ManualClock;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    /// The purger removes the keys once the clock of the tree passes their expiration, in several
+    /// steps, and leaves the keys that have not expired.
+    #[test]
+    fn purger_removes_expired_keys() {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = TtlTree::create(&mut pager).expect("create should not fail");
+        let clock = ManualClock::new(1_000);
+        tree.set_clock(clock.clone());
+        for index in 0..10u32 {
+            tree.insert_with_ttl(
+                &mut pager,
+                &index.to_be_bytes(),
+                b"value",
+                Duration::from_millis(10),
+            )
+            .expect("insert_with_ttl should not fail");
+        }
+        tree.insert(&mut pager, b"forever", b"value")
+            .expect("insert should not fail");
+        let shared = Arc::new(Mutex::new((pager, tree)));
+
+        let purger = TtlPurger::start(&shared, Duration::from_millis(1), 3);
+        thread::sleep(Duration::from_millis(20));
+        let before = purger.purged();
+        clock.advance(Duration::from_millis(10));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while purger.purged() < 10 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let after = purger.purged();
+        purger.stop().expect("stop should not fail");
+        let (pager, tree) = &mut *lock(&shared);
+
+        assert_eq!(before, 0);
+        assert_eq!(after, 10);
+        assert_eq!(tree.purge(pager, 100).expect("purge should not fail"), 0);
+        assert_eq!(
+            tree.entries(pager).expect("entries should not fail"),
+            vec![(b"forever".to_vec(), b"value".to_vec())]
+        );
+    }
+}
@@ -0,0 +1,480 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::btree::{BTree, BTreeError};
+use crate::common::clock::{Clock, HybridClock};
+use crate::fs::File;
+use crate::pager::{PageId, Pager};
+
+/// A key and its value.
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// Size of the expiration time stored before each value.
+const EXPIRATION_SIZE: usize = 8;
+
+/// Expiration time stored for the keys that never expire.
+const NEVER: u64 = 0;
+
+/// Represents errors that can occur during operations on a [TtlTree].
+#[derive(Error, Debug)]
+pub enum TtlError {
+    /// Indicates that an operation on the tree of the values or on the expiration index failed.
+    #[error(transparent)]
+    BTree(#[from] BTreeError),
+
+    /// Indicates that a value of the tree does not start with an expiration time.
+    ///
+    /// # Fields
+    /// - `0` - The key of the value.
+    #[error("The value of the key {0:?} does not start with an expiration time.")]
+    CorruptedValue(Vec<u8>),
+
+    /// Indicates that a key is too large to be listed in the expiration index.
+    ///
+    /// # Fields
+    /// - `key_size` - The size of the key.
+    /// - `max_key_size` - The largest key that can be stored in the tree.
+    #[error(
+        "The key is too large. It is {key_size} bytes, but must be at most {max_key_size} bytes."
+    )]
+    KeyTooLarge {
+        key_size: usize,
+        max_key_size: usize,
+    },
+}
+
+/// Represents a [BTree] whose keys can expire after a time to live.
+///
+/// Each value is stored after the time it expires at, in milliseconds since the Unix epoch, or `0`
+/// if it never expires. The keys that expire are also listed in an expiration index: a second tree
+/// whose keys are the expiration time, in big-endian, followed by the key.
+///
+/// An expired key is never returned, even before it is removed. The expired keys are removed by
+/// [TtlTree::purge], in expiration order, a few at a time so the purge can be interleaved with
+/// other operations and run periodically, as a [TtlPurger](super::TtlPurger) does. The time is
+/// read from a [Clock], the system clock by default, through a [HybridClock], so a key that
+/// expired does not come back when the time of the clock goes backward.
+///
+/// Like the tree of the values, the expiration index is reopened from its root page. The trees are
+/// ordered bytewise.
+pub struct TtlTree {
+    values: BTree,
+    expirations: BTree,
+    clock: HybridClock,
+}
+
+impl TtlTree {
+    /// Creates a new, empty, tree with an empty expiration index.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if the root pages
+    /// can't be allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    /// use rouilledb::ttl::TtlTree;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut tree = TtlTree::create(&mut pager).expect("create should not fail");
+    /// tree.insert_with_ttl(&mut pager, b"session", b"alice", Duration::from_secs(60))
+    ///     .expect("insert_with_ttl should not fail");
+    ///
+    /// let value = tree.get(&pager, b"session").expect("get should not fail");
+    /// assert_eq!(value, Some(b"alice".to_vec()));
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, TtlError> {
+        Ok(TtlTree {
+            values: BTree::create(pager)?,
+            expirations: BTree::create(pager)?,
+            clock: HybridClock::default(),
+        })
+    }
+
+    /// Opens a tree previously created with [TtlTree::create], from the root pages of its values
+    /// and of its expiration index.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if a root page
+    /// can't be read or does not contain a valid node.
+    pub fn open<F: File>(
+        pager: &Pager<F>,
+        root: PageId,
+        expiration_root: PageId,
+    ) -> Result<Self, TtlError> {
+        Ok(TtlTree {
+            values: BTree::open(pager, root)?,
+            expirations: BTree::open(pager, expiration_root)?,
+            clock: HybridClock::default(),
+        })
+    }
+
+    /// Returns the identifier of the root page of the tree of the values.
+    pub fn root(&self) -> PageId {
+        self.values.root()
+    }
+
+    /// Returns the identifier of the root page of the expiration index.
+    pub fn expiration_root(&self) -> PageId {
+        self.expirations.root()
+    }
+
+    /// Replaces the clock giving the current time, in milliseconds since the Unix epoch.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock.set_clock(clock);
+    }
+
+    /// Returns the largest key that can be stored in a tree using pages of the given size. The key
+    /// must fit in the expiration index after the expiration time.
+    pub fn max_key_size(page_size: usize) -> usize {
+        BTree::max_key_size(page_size) - EXPIRATION_SIZE
+    }
+
+    /// Returns the value associated with a key, or `None` if the key is not in the tree or has
+    /// expired.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn get<F: File>(&self, pager: &Pager<F>, key: &[u8]) -> Result<Option<Vec<u8>>, TtlError> {
+        Ok(self.live(pager, key)?.map(|(_, value)| value))
+    }
+
+    /// Returns the time a key expires at, in milliseconds since the Unix epoch. Returns
+    /// `Some(None)` if the key never expires, and `None` if the key is not in the tree or has
+    /// expired.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn expiration<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Option<u64>>, TtlError> {
+        Ok(self
+            .live(pager, key)?
+            .map(|(expiration, _)| (expiration != NEVER).then_some(expiration)))
+    }
+
+    /// Returns the entries that have not expired, in key order.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub fn entries<F: File>(&self, pager: &Pager<F>) -> Result<Vec<Entry>, TtlError> {
+        let now = self.clock.now_millis();
+        let mut entries = Vec::new();
+        for entry in self.values.iter(pager) {
+            let (key, stored) = entry?;
+            let (expiration, value) = decode(&key, &stored)?;
+            if !is_expired(expiration, now) {
+                entries.push((key, value.to_vec()));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Inserts a key-value pair that never expires, replacing the previous value of the key.
+    /// Returns the previous value, if there was one and it had not expired.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key or the value is too large (see
+    /// [TtlTree::max_key_size]), or if a page can't be read, written or allocated.
+    pub fn insert<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>, TtlError> {
+        self.insert_expiring_at(pager, key, value, NEVER)
+    }
+
+    /// Inserts a key-value pair that expires after `ttl`, replacing the previous value of the key.
+    /// Returns the previous value, if there was one and it had not expired.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the key or the value is too large (see
+    /// [TtlTree::max_key_size]), or if a page can't be read, written or allocated.
+    pub fn insert_with_ttl<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<Option<Vec<u8>>, TtlError> {
+        let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+        let expiration = self.clock.now_millis().saturating_add(ttl).max(NEVER + 1);
+        self.insert_expiring_at(pager, key, value, expiration)
+    }
+
+    /// Removes a key from the tree. Returns the value of the key, if it was present and had not
+    /// expired.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or written.
+    pub fn delete<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, TtlError> {
+        let Some(stored) = self.values.delete(pager, key)? else {
+            return Ok(None);
+        };
+        let (expiration, value) = decode(key, &stored)?;
+        if expiration != NEVER {
+            self.expirations
+                .delete(pager, &expiration_key(expiration, key))?;
+        }
+        Ok((!is_expired(expiration, self.clock.now_millis())).then(|| value.to_vec()))
+    }
+
+    /// Removes up to `max_keys` expired keys, in expiration order. Returns the number of keys
+    /// removed: when it is smaller than `max_keys`, no expired key is left.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or written.
+    pub fn purge<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        max_keys: usize,
+    ) -> Result<usize, TtlError> {
+        let now = self.clock.now_millis();
+        let mut expired = Vec::new();
+        for entry in self.expirations.iter(pager).take(max_keys) {
+            let (index_key, _) = entry?;
+            let (expiration, _) = decode(&index_key, &index_key)?;
+            if !is_expired(expiration, now) {
+                break;
+            }
+            expired.push(index_key);
+        }
+
+        for index_key in &expired {
+            self.expirations.delete(pager, index_key)?;
+            self.values.delete(pager, &index_key[EXPIRATION_SIZE..])?;
+        }
+        Ok(expired.len())
+    }
+
+    /// Stores a value with its expiration time and updates the expiration index.
+    fn insert_expiring_at<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+        expiration: u64,
+    ) -> Result<Option<Vec<u8>>, TtlError> {
+        let max_key_size = Self::max_key_size(pager.page_size());
+        if key.len() > max_key_size {
+            return Err(TtlError::KeyTooLarge {
+                key_size: key.len(),
+                max_key_size,
+            });
+        }
+
+        let mut stored = Vec::with_capacity(EXPIRATION_SIZE + value.len());
+        stored.extend_from_slice(&expiration.to_be_bytes());
+        stored.extend_from_slice(value);
+        let Some(previous) = self.values.insert(pager, key, &stored)? else {
+            if expiration != NEVER {
+                self.expirations
+                    .insert(pager, &expiration_key(expiration, key), &[])?;
+            }
+            return Ok(None);
+        };
+
+        let (previous_expiration, previous_value) = decode(key, &previous)?;
+        if previous_expiration != expiration {
+            if previous_expiration != NEVER {
+                self.expirations
+                    .delete(pager, &expiration_key(previous_expiration, key))?;
+            }
+            if expiration != NEVER {
+                self.expirations
+                    .insert(pager, &expiration_key(expiration, key), &[])?;
+            }
+        }
+        Ok((!is_expired(previous_expiration, self.clock.now_millis()))
+            .then(|| previous_value.to_vec()))
+    }
+
+    /// Returns the expiration time and the value of a key that has not expired.
+    fn live<F: File>(
+        &self,
+        pager: &Pager<F>,
+        key: &[u8],
+    ) -> Result<Option<(u64, Vec<u8>)>, TtlError> {
+        let Some(stored) = self.values.get(pager, key)? else {
+            return Ok(None);
+        };
+        let (expiration, value) = decode(key, &stored)?;
+        if is_expired(expiration, self.clock.now_millis()) {
+            return Ok(None);
+        }
+        Ok(Some((expiration, value.to_vec())))
+    }
+}
+
+/// Returns `true` if a key expiring at `expiration` has expired at `now`.
+fn is_expired(expiration: u64, now: u64) -> bool {
+    expiration != NEVER && expiration <= now
+}
+
+/// Returns the key of the expiration index listing a key that expires at `expiration`.
+fn expiration_key(expiration: u64, key: &[u8]) -> Vec<u8> {
+    let mut index_key = Vec::with_capacity(EXPIRATION_SIZE + key.len());
+    index_key.extend_from_slice(&expiration.to_be_bytes());
+    index_key.extend_from_slice(key);
+    index_key
+}
+
+/// Splits bytes starting with an expiration time, stored for `key`, in the expiration time and
+/// the rest of the bytes.
+fn decode<'a>(key: &[u8], bytes: &'a [u8]) -> Result<(u64, &'a [u8]), TtlError> {
+    if bytes.len() < EXPIRATION_SIZE {
+        return Err(TtlError::CorruptedValue(key.to_vec()));
+    }
+    let (expiration, rest) = bytes.split_at(EXPIRATION_SIZE);
+    let expiration = u64::from_be_bytes(expiration.try_into().expect("slice should be 8 bytes"));
+    Ok((expiration, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    /// Creates a tree whose clock is set with the returned counter.
+    fn create_tree() -> (Pager<MemoryFile>, TtlTree, Arc<AtomicU64>) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = TtlTree::create(&mut pager).expect("create should not fail");
+        let now = Arc::new(AtomicU64::new(1_000));
+        let clock = Arc::clone(&now);
+        tree.set_clock(move || clock.load(Ordering::Relaxed));
+        (pager, tree, now)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:06}").into_bytes()
+    }
+
+    /// An expired key is no longer returned, before it is purged.
+    #[test]
+    fn get_expired_key_returns_none() {
+        let (mut pager, mut tree, now) = create_tree();
+        tree.insert_with_ttl(&mut pager, b"short", b"value", Duration::from_millis(10))
+            .expect("insert_with_ttl should not fail");
+        tree.insert(&mut pager, b"forever", b"value")
+            .expect("insert should not fail");
+
+        let before = tree.get(&pager, b"short").expect("get should not fail");
+        let expiration = tree
+            .expiration(&pager, b"short")
+            .expect("expiration should not fail");
+        now.store(1_010, Ordering::Relaxed);
+        let after = tree.get(&pager, b"short").expect("get should not fail");
+        let entries = tree.entries(&pager).expect("entries should not fail");
+
+        assert_eq!(before, Some(b"value".to_vec()));
+        assert_eq!(expiration, Some(Some(1_010)));
+        assert_eq!(after, None);
+        assert_eq!(entries, vec![(b"forever".to_vec(), b"value".to_vec())]);
+        assert_eq!(
+            tree.expiration(&pager, b"forever")
+                .expect("expiration should not fail"),
+            Some(None)
+        );
+    }
+
+    /// Purging removes the expired keys in expiration order, a few at a time, and leaves the
+    /// others.
+    #[test]
+    fn purge_removes_expired_keys_in_steps() {
+        let (mut pager, mut tree, now) = create_tree();
+        for index in 0..300 {
+            let ttl = Duration::from_millis(index as u64 + 1);
+            tree.insert_with_ttl(&mut pager, &key(index), b"value", ttl)
+                .expect("insert_with_ttl should not fail");
+        }
+        now.store(1_200, Ordering::Relaxed);
+
+        let mut steps = Vec::new();
+        loop {
+            let purged = tree.purge(&mut pager, 64).expect("purge should not fail");
+            steps.push(purged);
+            if purged < 64 {
+                break;
+            }
+        }
+
+        assert_eq!(steps, vec![64, 64, 64, 8]);
+        assert_eq!(
+            tree.entries(&pager).expect("entries should not fail").len(),
+            100
+        );
+        assert_eq!(tree.expirations.iter(&pager).count(), 100);
+        assert_eq!(tree.values.iter(&pager).count(), 100);
+    }
+
+    /// Replacing a value replaces its entry in the expiration index.
+    #[test]
+    fn insert_replaces_expiration() {
+        let (mut pager, mut tree, now) = create_tree();
+        tree.insert_with_ttl(&mut pager, b"key", b"old", Duration::from_millis(10))
+            .expect("insert_with_ttl should not fail");
+
+        let previous = tree
+            .insert(&mut pager, b"key", b"new")
+            .expect("insert should not fail");
+        now.store(2_000, Ordering::Relaxed);
+        let purged = tree.purge(&mut pager, 10).expect("purge should not fail");
+
+        assert_eq!(previous, Some(b"old".to_vec()));
+        assert_eq!(purged, 0);
+        assert_eq!(
+            tree.get(&pager, b"key").expect("get should not fail"),
+            Some(b"new".to_vec())
+        );
+        assert_eq!(tree.expirations.iter(&pager).count(), 0);
+    }
+
+    /// A tree reopened from its root pages keeps the expiration times.
+    #[test]
+    fn open_existing_tree_keeps_expirations() {
+        let (mut pager, mut tree, _) = create_tree();
+        tree.insert_with_ttl(&mut pager, b"key", b"value", Duration::from_millis(10))
+            .expect("insert_with_ttl should not fail");
+
+        let mut opened = TtlTree::open(&pager, tree.root(), tree.expiration_root())
+            .expect("open should not fail");
+        opened.set_clock(|| 5_000);
+        let purged = opened.purge(&mut pager, 10).expect("purge should not fail");
+
+        assert_eq!(purged, 1);
+        assert_eq!(
+            opened.get(&pager, b"key").expect("get should not fail"),
+            None
+        );
+    }
+}
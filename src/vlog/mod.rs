@@ -0,0 +1,4 @@
+mod value_log;
+mod vlog_tree;
+pub use value_log::{ValueLog, ValuePointer, VlogError};
+pub use vlog_tree::{GarbageReport, VlogTree};
@@ -0,0 +1,622 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::fs::File;
+use crate::lsm::LsmError;
+use crate::pager::{PageId, Pager, PagerError};
+
+/// Magic bytes at the start of the meta page of a value log.
+const META_MAGIC: [u8; 8] = *b"ROUIVLG1";
+
+/// Size of the header of the meta page: the magic bytes and the number of segments.
+const META_HEADER_SIZE: usize = 12;
+
+/// Size of a segment in the meta page: its first and last pages, its page count and its length.
+const SEGMENT_SIZE: usize = 16;
+
+/// Size of the link to the next page of the segment at the start of each log page.
+const NEXT_SIZE: usize = 4;
+
+/// Size of the header of a record: the length of the key and the length of the value.
+const RECORD_HEADER_SIZE: usize = 6;
+
+/// A key and its value.
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// A record of a segment: where it is stored, its key and its value.
+pub(super) type LogRecord = (ValuePointer, Vec<u8>, Vec<u8>);
+
+/// Represents errors that can occur during operations on a [ValueLog] or a
+/// [VlogTree](super::VlogTree).
+#[derive(Error, Debug)]
+pub enum VlogError {
+    /// Indicates that an operation on the underlying pager failed.
+    #[error(transparent)]
+    Pager(#[from] PagerError),
+
+    /// Indicates that an operation on the tree of the keys failed.
+    #[error(transparent)]
+    Lsm(#[from] LsmError),
+
+    /// Indicates that a page does not contain a valid value log page or meta page.
+    ///
+    /// # Fields
+    /// - `0` - The identifier of the page that could not be decoded.
+    #[error("The page ({0}) does not contain a valid value log page.")]
+    CorruptedPage(PageId),
+
+    /// Indicates that the value stored in the tree for a key is neither a value nor a pointer to
+    /// the record of the key in the value log.
+    ///
+    /// # Fields
+    /// - `0` - The key of the value.
+    #[error("The value of the key {0:?} is not a valid value or value log pointer.")]
+    CorruptedValue(Vec<u8>),
+
+    /// Indicates that a key is too large to be stored in the value log.
+    ///
+    /// # Fields
+    /// - `key_size` - The size of the key.
+    /// - `max_key_size` - The largest key that can be stored in the log.
+    #[error(
+        "The key is too large. It is {key_size} bytes, but must be at most {max_key_size} bytes."
+    )]
+    KeyTooLarge {
+        key_size: usize,
+        max_key_size: usize,
+    },
+
+    /// Indicates that the list of segments does not fit in the meta page.
+    ///
+    /// # Fields
+    /// - `0` - The number of segments of the log.
+    #[error("The meta page can't list the {0} segments of the value log.")]
+    TooManySegments(usize),
+}
+
+/// Identifies a record of a [ValueLog]: the page and the offset, in the data of the page, where it
+/// starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ValuePointer {
+    /// The page where the record starts.
+    pub page: PageId,
+
+    /// The offset of the record in the data of its page.
+    pub offset: u16,
+}
+
+impl ValuePointer {
+    /// The size of an encoded pointer.
+    pub const SIZE: usize = 6;
+
+    /// Encodes the pointer so it can be stored, for example as the value of a tree.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..4].copy_from_slice(&self.page.to_be_bytes());
+        bytes[4..].copy_from_slice(&self.offset.to_be_bytes());
+        bytes
+    }
+
+    /// Decodes a pointer encoded by [ValuePointer::to_bytes]. Returns `None` if the bytes do not
+    /// have the right length.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; Self::SIZE] = bytes.try_into().ok()?;
+        Some(ValuePointer {
+            page: u32::from_be_bytes(bytes[..4].try_into().ok()?),
+            offset: u16::from_be_bytes(bytes[4..].try_into().ok()?),
+        })
+    }
+}
+
+impl fmt::Display for ValuePointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.page, self.offset)
+    }
+}
+
+/// A chain of pages of the log, written one after the other.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    first: PageId,
+    last: PageId,
+    pages: u32,
+    /// The number of bytes written in the segment.
+    length: u32,
+}
+
+/// Represents an append-only log of key-value records stored in the pages of a [Pager].
+///
+/// The records are appended to the pages of the last segment of the log, and can span several
+/// pages, so values of any size can be stored. A segment is a chain of pages: each page starts with
+/// the identifier of the next page of the segment. Once the last segment has
+/// [ValueLog::segment_pages] pages, a new segment is started. The records are never modified: the
+/// space of the records that are no longer needed is reclaimed by rewriting the records still
+/// needed of the oldest segment at the end of the log and freeing the segment.
+///
+/// The segments are listed in a meta page that never moves, so the log can be reopened with
+/// [ValueLog::open]. The records appended since the last [ValueLog::flush] are lost when the log
+/// is dropped.
+pub struct ValueLog {
+    meta: PageId,
+    /// The segments of the log, from the oldest to the one records are appended to.
+    segments: Vec<Segment>,
+    segment_pages: usize,
+}
+
+impl ValueLog {
+    /// The number of pages of a segment used when none is set.
+    pub const DEFAULT_SEGMENT_PAGES: usize = 256;
+
+    /// Creates a new, empty, log with its meta page and its first page newly allocated.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    /// use rouilledb::vlog::ValueLog;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut log = ValueLog::create(&mut pager).expect("create should not fail");
+    /// let pointer = log.append(&mut pager, b"key", b"value").expect("append should not fail");
+    ///
+    /// let record = log.read(&pager, pointer).expect("read should not fail");
+    /// assert_eq!(record, (b"key".to_vec(), b"value".to_vec()));
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, VlogError> {
+        let meta = pager.allocate_page()?;
+        let mut log = ValueLog {
+            meta,
+            segments: Vec::new(),
+            segment_pages: Self::DEFAULT_SEGMENT_PAGES,
+        };
+        log.start_segment(pager)?;
+        log.flush(pager)?;
+        Ok(log)
+    }
+
+    /// Opens a log previously created with [ValueLog::create], as of its last flush.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the meta page can't be read or is corrupted.
+    pub fn open<F: File>(pager: &Pager<F>, meta: PageId) -> Result<Self, VlogError> {
+        let page = pager.read_page(meta)?;
+        if page[..META_MAGIC.len()] != META_MAGIC {
+            return Err(VlogError::CorruptedPage(meta));
+        }
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes(
+                page[offset..offset + 4]
+                    .try_into()
+                    .expect("slice should be 4 bytes"),
+            )
+        };
+        let segment_count = read_u32(8) as usize;
+        if segment_count == 0 || META_HEADER_SIZE + segment_count * SEGMENT_SIZE > page.len() {
+            return Err(VlogError::CorruptedPage(meta));
+        }
+        let segments = (0..segment_count)
+            .map(|index| {
+                let offset = META_HEADER_SIZE + index * SEGMENT_SIZE;
+                Segment {
+                    first: read_u32(offset),
+                    last: read_u32(offset + 4),
+                    pages: read_u32(offset + 8),
+                    length: read_u32(offset + 12),
+                }
+            })
+            .collect();
+        Ok(ValueLog {
+            meta,
+            segments,
+            segment_pages: Self::DEFAULT_SEGMENT_PAGES,
+        })
+    }
+
+    /// Returns the identifier of the meta page of the log.
+    pub fn meta(&self) -> PageId {
+        self.meta
+    }
+
+    /// Returns the number of pages after which a new segment is started.
+    pub fn segment_pages(&self) -> usize {
+        self.segment_pages
+    }
+
+    /// Sets the number of pages after which a new segment is started. Smaller segments let the
+    /// space of the records no longer needed be reclaimed sooner, but need more room in the meta
+    /// page.
+    pub fn set_segment_pages(&mut self, segment_pages: usize) {
+        self.segment_pages = segment_pages.max(1);
+    }
+
+    /// Returns the number of segments of the log, including the one records are appended to.
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Returns the number of pages used by the segments of the log.
+    pub fn page_count(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| segment.pages as usize)
+            .sum()
+    }
+
+    /// Returns the size of the largest key that can be stored in a log.
+    pub fn max_key_size() -> usize {
+        u16::MAX as usize
+    }
+
+    /// Appends a record at the end of the log. Returns the pointer to read it back.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the key is too large (see [ValueLog::max_key_size])
+    /// - a new segment can't be listed in the meta page
+    /// - a page can't be read, written or allocated
+    pub fn append<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<ValuePointer, VlogError> {
+        if key.len() > Self::max_key_size() {
+            return Err(VlogError::KeyTooLarge {
+                key_size: key.len(),
+                max_key_size: Self::max_key_size(),
+            });
+        }
+
+        let active = self.segments.last().expect("a log should have a segment");
+        if active.pages as usize >= self.segment_pages {
+            self.start_segment(pager)?;
+        }
+        let data_size = pager.page_size() - NEXT_SIZE;
+        if self.tail_offset(data_size) == data_size {
+            self.append_page(pager)?;
+        }
+        let active = self.segments.last().expect("a log should have a segment");
+        let pointer = ValuePointer {
+            page: active.last,
+            offset: self.tail_offset(data_size) as u16,
+        };
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + key.len() + value.len());
+        record.extend_from_slice(&(key.len() as u16).to_le_bytes());
+        record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        record.extend_from_slice(key);
+        record.extend_from_slice(value);
+        self.write(pager, &record)?;
+        Ok(pointer)
+    }
+
+    /// Returns the key and the value of a record.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or if the pointer does not point
+    /// to a record.
+    pub fn read<F: File>(
+        &self,
+        pager: &Pager<F>,
+        pointer: ValuePointer,
+    ) -> Result<Entry, VlogError> {
+        let mut reader = LogReader::new(pager, pointer.page, pointer.offset as usize)?;
+        reader.read_record()
+    }
+
+    /// Writes the list of segments in the meta page, so the records appended until now are found
+    /// when the log is reopened.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the meta page can't be written.
+    pub fn flush<F: File>(&self, pager: &mut Pager<F>) -> Result<(), VlogError> {
+        let mut page = Vec::with_capacity(pager.page_size());
+        page.extend_from_slice(&META_MAGIC);
+        page.extend_from_slice(&(self.segments.len() as u32).to_le_bytes());
+        for segment in &self.segments {
+            page.extend_from_slice(&segment.first.to_le_bytes());
+            page.extend_from_slice(&segment.last.to_le_bytes());
+            page.extend_from_slice(&segment.pages.to_le_bytes());
+            page.extend_from_slice(&segment.length.to_le_bytes());
+        }
+        page.resize(pager.page_size(), 0);
+        pager.write_page(self.meta, &page)?;
+        Ok(())
+    }
+
+    /// Returns the records of the oldest segment, or `None` if records are still appended to it.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read or is corrupted.
+    pub(super) fn oldest_records<F: File>(
+        &self,
+        pager: &Pager<F>,
+    ) -> Result<Option<Vec<LogRecord>>, VlogError> {
+        if self.segments.len() < 2 {
+            return Ok(None);
+        }
+
+        let segment = self.segments[0];
+        let mut reader = LogReader::new(pager, segment.first, 0)?;
+        let mut records = Vec::new();
+        while reader.consumed < segment.length as usize {
+            let pointer = reader.position()?;
+            let (key, value) = reader.read_record()?;
+            records.push((pointer, key, value));
+        }
+        Ok(Some(records))
+    }
+
+    /// Frees the pages of the oldest segment, which must not be the one records are appended to,
+    /// and writes the meta page. Returns the number of freed pages.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if a page can't be read, written or freed.
+    pub(super) fn free_oldest<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+    ) -> Result<usize, VlogError> {
+        debug_assert!(self.segments.len() > 1, "the active segment can't be freed");
+
+        let segment = self.segments.remove(0);
+        self.flush(pager)?;
+        let mut next = segment.first;
+        for _ in 0..segment.pages {
+            let page = pager.read_page(next)?;
+            pager.free_page(next)?;
+            next = read_next(&page);
+        }
+        Ok(segment.pages as usize)
+    }
+
+    /// Returns the offset, in the data of the last page of the log, where the next record starts.
+    fn tail_offset(&self, data_size: usize) -> usize {
+        let active = self.segments.last().expect("a log should have a segment");
+        active.length as usize - (active.pages as usize - 1) * data_size
+    }
+
+    /// Starts a new segment in a newly allocated page.
+    fn start_segment<F: File>(&mut self, pager: &mut Pager<F>) -> Result<(), VlogError> {
+        let max_segments = (pager.page_size() - META_HEADER_SIZE) / SEGMENT_SIZE;
+        if self.segments.len() >= max_segments {
+            return Err(VlogError::TooManySegments(self.segments.len() + 1));
+        }
+
+        let page = pager.allocate_page()?;
+        pager.write_page(page, &vec![0; pager.page_size()])?;
+        self.segments.push(Segment {
+            first: page,
+            last: page,
+            pages: 1,
+            length: 0,
+        });
+        Ok(())
+    }
+
+    /// Links a newly allocated page after the last page of the log. The data left unused at the end
+    /// of the last page is counted in the length of the segment.
+    fn append_page<F: File>(&mut self, pager: &mut Pager<F>) -> Result<(), VlogError> {
+        let data_size = pager.page_size() - NEXT_SIZE;
+        let next = pager.allocate_page()?;
+        pager.write_page(next, &vec![0; pager.page_size()])?;
+        let tail_offset = self.tail_offset(data_size);
+        let active = self
+            .segments
+            .last_mut()
+            .expect("a log should have a segment");
+        let mut page = pager.read_page(active.last)?;
+        page[..NEXT_SIZE].copy_from_slice(&next.to_le_bytes());
+        pager.write_page(active.last, &page)?;
+        active.last = next;
+        active.pages += 1;
+        active.length += (data_size - tail_offset) as u32;
+        Ok(())
+    }
+
+    /// Writes bytes at the end of the log, linking new pages as needed.
+    fn write<F: File>(&mut self, pager: &mut Pager<F>, mut bytes: &[u8]) -> Result<(), VlogError> {
+        let data_size = pager.page_size() - NEXT_SIZE;
+        loop {
+            let offset = self.tail_offset(data_size);
+            let active = self
+                .segments
+                .last_mut()
+                .expect("a log should have a segment");
+            let mut page = pager.read_page(active.last)?;
+            let written = bytes.len().min(data_size - offset);
+            page[NEXT_SIZE + offset..NEXT_SIZE + offset + written]
+                .copy_from_slice(&bytes[..written]);
+            pager.write_page(active.last, &page)?;
+            active.length += written as u32;
+            bytes = &bytes[written..];
+            if bytes.is_empty() {
+                return Ok(());
+            }
+            self.append_page(pager)?;
+        }
+    }
+}
+
+/// Reads the data of a segment from a position, following the links to the next pages.
+struct LogReader<'a, F: File> {
+    pager: &'a Pager<F>,
+    page: PageId,
+    data: Vec<u8>,
+    offset: usize,
+    /// The number of bytes read since the reader was created.
+    consumed: usize,
+}
+
+impl<'a, F: File> LogReader<'a, F> {
+    fn new(pager: &'a Pager<F>, page: PageId, offset: usize) -> Result<Self, VlogError> {
+        let data = pager.read_page(page)?;
+        if NEXT_SIZE + offset > data.len() {
+            return Err(VlogError::CorruptedPage(page));
+        }
+        Ok(LogReader {
+            pager,
+            page,
+            data,
+            offset,
+            consumed: 0,
+        })
+    }
+
+    /// Returns the pointer to the next byte to read.
+    fn position(&mut self) -> Result<ValuePointer, VlogError> {
+        if NEXT_SIZE + self.offset == self.data.len() {
+            self.next_page()?;
+        }
+        Ok(ValuePointer {
+            page: self.page,
+            offset: self.offset as u16,
+        })
+    }
+
+    fn read_record(&mut self) -> Result<Entry, VlogError> {
+        let header = self.read(RECORD_HEADER_SIZE)?;
+        let key_size = u16::from_le_bytes(header[..2].try_into().expect("slice should be 2 bytes"));
+        let value_size =
+            u32::from_le_bytes(header[2..].try_into().expect("slice should be 4 bytes"));
+        let key = self.read(key_size as usize)?;
+        let value = self.read(value_size as usize)?;
+        Ok((key, value))
+    }
+
+    fn read(&mut self, mut size: usize) -> Result<Vec<u8>, VlogError> {
+        let mut bytes = Vec::with_capacity(size);
+        while size > 0 {
+            if NEXT_SIZE + self.offset == self.data.len() {
+                self.next_page()?;
+            }
+            let read = size.min(self.data.len() - NEXT_SIZE - self.offset);
+            let start = NEXT_SIZE + self.offset;
+            bytes.extend_from_slice(&self.data[start..start + read]);
+            self.offset += read;
+            self.consumed += read;
+            size -= read;
+        }
+        Ok(bytes)
+    }
+
+    /// Moves to the start of the next page of the segment. The data left unused at the end of a
+    /// page counts as read.
+    fn next_page(&mut self) -> Result<(), VlogError> {
+        let next = read_next(&self.data);
+        if next == 0 {
+            return Err(VlogError::CorruptedPage(self.page));
+        }
+        self.consumed += self.data.len() - NEXT_SIZE - self.offset;
+        self.page = next;
+        self.data = self.pager.read_page(next)?;
+        self.offset = 0;
+        Ok(())
+    }
+}
+
+/// Returns the next page of a segment linked from a log page.
+fn read_next(page: &[u8]) -> PageId {
+    u32::from_le_bytes(
+        page[..NEXT_SIZE]
+            .try_into()
+            .expect("slice should be 4 bytes"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::RandomBlob;
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_log() -> (Pager<MemoryFile>, ValueLog) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let log = ValueLog::create(&mut pager).expect("create should not fail");
+        (pager, log)
+    }
+
+    /// Records of any size are read back, including after the log is reopened.
+    #[test]
+    fn append_and_read_records_spanning_pages() {
+        let (mut pager, mut log) = create_log();
+        let mut appended = Vec::new();
+        for index in 0..50 {
+            let key = format!("key-{index:03}").into_bytes();
+            let value = RandomBlob::new(index * 97).data().clone();
+            let pointer = log
+                .append(&mut pager, &key, &value)
+                .expect("append should not fail");
+            appended.push((pointer, key, value));
+        }
+        log.flush(&mut pager).expect("flush should not fail");
+
+        let opened = ValueLog::open(&pager, log.meta()).expect("open should not fail");
+        for (pointer, key, value) in appended {
+            let record = opened.read(&pager, pointer).expect("read should not fail");
+            assert_eq!(record, (key, value));
+        }
+        assert_eq!(opened.page_count(), log.page_count());
+    }
+
+    /// The records of the oldest segment are listed in order and its pages are freed.
+    #[test]
+    fn free_oldest_segment() {
+        let (mut pager, mut log) = create_log();
+        log.set_segment_pages(4);
+        let mut pointers = Vec::new();
+        for index in 0..40 {
+            let key = format!("key-{index:03}").into_bytes();
+            pointers.push(
+                log.append(&mut pager, &key, &[index as u8; 100])
+                    .expect("append should not fail"),
+            );
+        }
+
+        let records = log
+            .oldest_records(&pager)
+            .expect("oldest_records should not fail")
+            .expect("the oldest segment should be sealed");
+        let segment_count = log.segment_count();
+        let freed = log
+            .free_oldest(&mut pager)
+            .expect("free_oldest should not fail");
+
+        assert!(segment_count > 2);
+        assert_eq!(
+            records
+                .iter()
+                .map(|(pointer, _, _)| *pointer)
+                .collect::<Vec<_>>(),
+            pointers[..records.len()]
+        );
+        assert_eq!(records[1].1, b"key-001");
+        assert_eq!(records[1].2, [1; 100]);
+        assert_eq!(freed, 4);
+        assert_eq!(log.segment_count(), segment_count - 1);
+        assert_eq!(
+            pager
+                .free_pages()
+                .expect("free_pages should not fail")
+                .len(),
+            4
+        );
+    }
+}
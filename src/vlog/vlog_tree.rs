@@ -0,0 +1,371 @@
+use crate::fs::File;
+use crate::lsm::LsmTree;
+use crate::pager::{PageId, Pager};
+
+use super::value_log::{ValueLog, ValuePointer, VlogError};
+
+/// Tag of a value stored in the tree of the keys.
+const INLINE_TAG: u8 = 0;
+
+/// Tag of a pointer to a record of the value log stored in the tree of the keys.
+const POINTER_TAG: u8 = 1;
+
+/// Describes the work done by a call to [VlogTree::collect_garbage].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GarbageReport {
+    /// The number of records of the collected segment.
+    pub examined_records: usize,
+    /// The number of records still needed, which were rewritten at the end of the log.
+    pub relocated_records: usize,
+    /// The number of pages returned to the pager.
+    pub freed_pages: usize,
+}
+
+/// Represents an [LsmTree] whose large values are stored in a [ValueLog], apart from their keys.
+///
+/// A value at least [VlogTree::value_threshold] bytes long is appended to the value log, and the
+/// tree only maps its key to a pointer to the record. The compactions of the tree then rewrite
+/// the small pointers instead of the values, which cuts the amount of data written for large
+/// values, at the cost of a second read to get them. Values of any size can be stored.
+///
+/// The records of the values that were replaced or deleted are left in the log until
+/// [VlogTree::collect_garbage] collects the oldest segment of the log: the records of the segment
+/// the tree still points to are appended again at the end of the log, and the segment is freed.
+///
+/// The tree and the log are reopened from the manifest page of the tree and the meta page of the
+/// log. Like the memtable of the tree, the records appended to the log are only found after a
+/// reopen once [VlogTree::flush] is called.
+pub struct VlogTree {
+    tree: LsmTree,
+    log: ValueLog,
+    value_threshold: usize,
+}
+
+impl VlogTree {
+    /// The size from which values are stored in the value log when no threshold is set.
+    pub const DEFAULT_VALUE_THRESHOLD: usize = 256;
+
+    /// Creates a new, empty, tree and its value log.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not ordered bytewise or if a page can't be
+    /// allocated or written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::pager::Pager;
+    /// use rouilledb::vlog::VlogTree;
+    ///
+    /// let mut file = MemoryFile::new();
+    /// file.create().expect("create should not fail");
+    /// let mut pager = Pager::create(file, 4096).expect("create should not fail");
+    ///
+    /// let mut tree = VlogTree::create(&mut pager).expect("create should not fail");
+    /// tree.insert(&mut pager, b"key", &[7; 10_000]).expect("insert should not fail");
+    /// tree.flush(&mut pager).expect("flush should not fail");
+    ///
+    /// let value = tree.get(&pager, b"key").expect("get should not fail");
+    /// assert_eq!(value, Some(vec![7; 10_000]));
+    /// ```
+    pub fn create<F: File>(pager: &mut Pager<F>) -> Result<Self, VlogError> {
+        Ok(VlogTree {
+            tree: LsmTree::create(pager)?,
+            log: ValueLog::create(pager)?,
+            value_threshold: Self::DEFAULT_VALUE_THRESHOLD,
+        })
+    }
+
+    /// Opens a tree previously created with [VlogTree::create] from the manifest page of the tree
+    /// and the meta page of its value log.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the tree or the log can't be opened.
+    pub fn open<F: File>(
+        pager: &Pager<F>,
+        manifest: PageId,
+        log_meta: PageId,
+    ) -> Result<Self, VlogError> {
+        Ok(VlogTree {
+            tree: LsmTree::open(pager, manifest)?,
+            log: ValueLog::open(pager, log_meta)?,
+            value_threshold: Self::DEFAULT_VALUE_THRESHOLD,
+        })
+    }
+
+    /// Returns the identifier of the manifest page of the tree of the keys.
+    pub fn manifest(&self) -> PageId {
+        self.tree.manifest()
+    }
+
+    /// Returns the identifier of the meta page of the value log.
+    pub fn log_meta(&self) -> PageId {
+        self.log.meta()
+    }
+
+    /// Returns the size from which values are stored in the value log.
+    pub fn value_threshold(&self) -> usize {
+        self.value_threshold
+    }
+
+    /// Sets the size from which values are stored in the value log. The values already stored are
+    /// not moved.
+    pub fn set_value_threshold(&mut self, value_threshold: usize) {
+        self.value_threshold = value_threshold;
+    }
+
+    /// Returns the value log of the tree.
+    pub fn log(&self) -> &ValueLog {
+        &self.log
+    }
+
+    /// Returns the value log of the tree, to configure it.
+    pub fn log_mut(&mut self) -> &mut ValueLog {
+        &mut self.log
+    }
+
+    /// Returns the value associated with a key, or `None` if the key is not present.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - a page of the tree or of the log can't be read or is corrupted
+    /// - the value stored for the key is not a value nor a pointer to a record of the key
+    pub fn get<F: File>(&self, pager: &Pager<F>, key: &[u8]) -> Result<Option<Vec<u8>>, VlogError> {
+        let Some(stored) = self.tree.get(pager, key)? else {
+            return Ok(None);
+        };
+        match decode(key, &stored)? {
+            Stored::Inline(value) => Ok(Some(value.to_vec())),
+            Stored::Pointer(pointer) => {
+                let (record_key, value) = self.log.read(pager, pointer)?;
+                if record_key != key {
+                    return Err(VlogError::CorruptedValue(key.to_vec()));
+                }
+                Ok(Some(value))
+            }
+        }
+    }
+
+    /// Inserts a key-value pair, replacing the previous value of the key. The value is appended
+    /// to the value log if it is at least [VlogTree::value_threshold] bytes long or too large to
+    /// be stored in the tree.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if:
+    /// - the key is too large to be stored in the tree or the log
+    /// - the tree or the log can't be written
+    pub fn insert<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), VlogError> {
+        let inline_size = key.len() + 1 + value.len();
+        if value.len() < self.value_threshold
+            && inline_size <= LsmTree::max_entry_size(pager.page_size())
+        {
+            let mut stored = Vec::with_capacity(value.len() + 1);
+            stored.push(INLINE_TAG);
+            stored.extend_from_slice(value);
+            self.tree.insert(pager, key, &stored)?;
+        } else {
+            let pointer = self.log.append(pager, key, value)?;
+            self.insert_pointer(pager, key, pointer)?;
+        }
+        Ok(())
+    }
+
+    /// Removes a key. Its record in the value log is reclaimed when its segment is collected.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the tree can't be written.
+    pub fn delete<F: File>(&mut self, pager: &mut Pager<F>, key: &[u8]) -> Result<(), VlogError> {
+        self.tree.delete(pager, key)?;
+        Ok(())
+    }
+
+    /// Writes the records appended to the value log and the memtable of the tree, so they are
+    /// found when the tree is reopened.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the log or the tree can't be written.
+    pub fn flush<F: File>(&mut self, pager: &mut Pager<F>) -> Result<(), VlogError> {
+        // The log is written first so the tree never points to records missing after a reopen.
+        self.log.flush(pager)?;
+        self.tree.flush(pager)?;
+        Ok(())
+    }
+
+    /// Reclaims the space of the oldest segment of the value log: its records the tree still
+    /// points to are appended again at the end of the log, the tree is flushed and the segment is
+    /// freed. Nothing is done while the log has a single segment.
+    ///
+    /// This can be called periodically, for example after a number of writes, and each call
+    /// collects at most one segment.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the tree or the log can't be read or written.
+    pub fn collect_garbage<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+    ) -> Result<GarbageReport, VlogError> {
+        let Some(records) = self.log.oldest_records(pager)? else {
+            return Ok(GarbageReport::default());
+        };
+
+        let mut report = GarbageReport {
+            examined_records: records.len(),
+            ..GarbageReport::default()
+        };
+        for (pointer, key, value) in records {
+            let Some(stored) = self.tree.get(pager, &key)? else {
+                continue;
+            };
+            if !matches!(decode(&key, &stored)?, Stored::Pointer(current) if current == pointer) {
+                continue;
+            }
+            let pointer = self.log.append(pager, &key, &value)?;
+            self.insert_pointer(pager, &key, pointer)?;
+            report.relocated_records += 1;
+        }
+        self.flush(pager)?;
+        report.freed_pages = self.log.free_oldest(pager)?;
+        Ok(report)
+    }
+
+    fn insert_pointer<F: File>(
+        &mut self,
+        pager: &mut Pager<F>,
+        key: &[u8],
+        pointer: ValuePointer,
+    ) -> Result<(), VlogError> {
+        let mut stored = Vec::with_capacity(ValuePointer::SIZE + 1);
+        stored.push(POINTER_TAG);
+        stored.extend_from_slice(&pointer.to_bytes());
+        self.tree.insert(pager, key, &stored)?;
+        Ok(())
+    }
+}
+
+/// A value as stored in the tree of the keys.
+enum Stored<'a> {
+    Inline(&'a [u8]),
+    Pointer(ValuePointer),
+}
+
+/// Decodes the value stored in the tree for a key.
+fn decode<'a>(key: &[u8], stored: &'a [u8]) -> Result<Stored<'a>, VlogError> {
+    let corrupted = || VlogError::CorruptedValue(key.to_vec());
+    match stored.split_first().ok_or_else(corrupted)? {
+        (&INLINE_TAG, value) => Ok(Stored::Inline(value)),
+        (&POINTER_TAG, pointer) => ValuePointer::from_bytes(pointer)
+            .map(Stored::Pointer)
+            .ok_or_else(corrupted),
+        _ => Err(corrupted()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_tree() -> (Pager<MemoryFile>, VlogTree) {
+        let mut file = MemoryFile::new();
+        file.create().expect("create should not fail");
+        let mut pager = Pager::create(file, 512).expect("create should not fail");
+        let mut tree = VlogTree::create(&mut pager).expect("create should not fail");
+        tree.set_value_threshold(64);
+        tree.log_mut().set_segment_pages(8);
+        (pager, tree)
+    }
+
+    fn key(index: usize) -> Vec<u8> {
+        format!("key-{index:04}").into_bytes()
+    }
+
+    /// Small values are stored in the tree and large values in the log, and both are found after
+    /// the tree is reopened.
+    #[test]
+    fn insert_small_and_large_values() {
+        let (mut pager, mut tree) = create_tree();
+        tree.insert(&mut pager, b"small", b"value")
+            .expect("insert should not fail");
+        tree.insert(&mut pager, b"large", &[1; 2000])
+            .expect("insert should not fail");
+        tree.insert(&mut pager, b"deleted", &[2; 100])
+            .expect("insert should not fail");
+        tree.delete(&mut pager, b"deleted")
+            .expect("delete should not fail");
+        tree.flush(&mut pager).expect("flush should not fail");
+
+        let opened =
+            VlogTree::open(&pager, tree.manifest(), tree.log_meta()).expect("open should not fail");
+
+        assert_eq!(tree.log().page_count(), opened.log().page_count());
+        assert!(tree.log().page_count() > 4);
+        assert_eq!(
+            opened.get(&pager, b"small").expect("get should not fail"),
+            Some(b"value".to_vec())
+        );
+        assert_eq!(
+            opened.get(&pager, b"large").expect("get should not fail"),
+            Some(vec![1; 2000])
+        );
+        assert_eq!(
+            opened.get(&pager, b"deleted").expect("get should not fail"),
+            None
+        );
+    }
+
+    /// Collecting garbage keeps the values still needed and frees the pages of the records of
+    /// replaced and deleted values.
+    #[test]
+    fn collect_garbage_relocates_live_records() {
+        let (mut pager, mut tree) = create_tree();
+        for round in 0..4 {
+            for index in 0..50 {
+                tree.insert(&mut pager, &key(index), &[round; 100])
+                    .expect("insert should not fail");
+            }
+        }
+        for index in 40..50 {
+            tree.delete(&mut pager, &key(index))
+                .expect("delete should not fail");
+        }
+        let pages_before = tree.log().page_count();
+
+        let mut relocated = 0;
+        let mut freed = 0;
+        for _ in 0..tree.log().segment_count() {
+            let report = tree
+                .collect_garbage(&mut pager)
+                .expect("collect_garbage should not fail");
+            relocated += report.relocated_records;
+            freed += report.freed_pages;
+        }
+
+        assert!(freed > 0);
+        assert!(relocated > 0);
+        assert!(tree.log().page_count() < pages_before);
+        for index in 0..40 {
+            assert_eq!(
+                tree.get(&pager, &key(index)).expect("get should not fail"),
+                Some(vec![3; 100])
+            );
+        }
+        assert_eq!(
+            tree.get(&pager, &key(45)).expect("get should not fail"),
+            None
+        );
+    }
+}
@@ -0,0 +1,2 @@
+mod wal_file;
+pub use wal_file::WalFile;
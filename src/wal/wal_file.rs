@@ -0,0 +1,805 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::common::checksum::xxhash64;
+use crate::common::layout::Layout;
+use crate::fs::{File, FileError};
+use crate::{assert_layout_size, disk_layout};
+
+/// Magic bytes at the start of the log.
+const LOG_MAGIC: [u8; 8] = *b"ROUIWAL1";
+
+disk_layout! {
+    /// The header at the start of the log.
+    struct LogHeader {
+        magic: [u8; 8],
+        /// The salt of the checksums of the frames, chosen at random for a new log and changed
+        /// by each checkpoint, so the frames of the previous logs are ignored.
+        salt: u64,
+    }
+}
+
+assert_layout_size!(LogHeader, 16);
+
+disk_layout! {
+    /// The start of a frame of the log, followed by the content of the block for a write frame,
+    /// then by the checksum of the frame.
+    struct FrameHeader {
+        kind: u8,
+        /// The index of the block of a write frame, or the size of the file after a commit.
+        value: u64,
+    }
+}
+
+assert_layout_size!(FrameHeader, 9);
+
+/// Size of the blocks written to the log. Every page size of a [Pager](crate::pager::Pager) is a
+/// multiple of it.
+const BLOCK_SIZE: usize = 512;
+
+/// Kind of the frame holding the new content of a block.
+const WRITE_FRAME: u8 = 1;
+
+/// Kind of the frame ending a commit.
+const COMMIT_FRAME: u8 = 2;
+
+/// Size of a write frame: the header, the content of the block and the checksum.
+const WRITE_FRAME_SIZE: usize = FrameHeader::SIZE + BLOCK_SIZE + 8;
+
+/// Size of a commit frame: the header and the checksum.
+const COMMIT_FRAME_SIZE: usize = FrameHeader::SIZE + 8;
+
+/// Makes the writes to a data file atomic and durable with a write-ahead log.
+///
+/// The [WalFile] is itself a [File] wrapping the data file and the log, so it can be given to a
+/// [Pager](crate::pager::Pager). The writes are kept in memory, by blocks of 512 bytes, and the
+/// data file is not modified by them. [File::sync] commits them: the blocks written since the last
+/// commit are appended to the log, followed by a commit frame, and the log is synced.
+/// [WalFile::append_commit] commits them without syncing the log, so the commit survives a crash
+/// of the process but may be lost with the power, until a later sync or checkpoint. Once the log
+/// grows past [WalFile::checkpoint_size], a checkpoint copies the committed blocks to the data
+/// file, syncs it and empties the log.
+///
+/// When the file is opened, the blocks of the commits found complete in the log are copied to the
+/// data file, and the writes that were not committed are lost: after a crash, the data file holds
+/// the state of the last commit. Each frame of the log has a checksum, its XXH64 hash seeded with
+/// the salt of the log, so the frames left by a torn write or by an older log are ignored.
+///
+/// The reads of several threads share the state of the file and run in parallel, while the writes,
+/// commits and checkpoints hold it exclusively.
+pub struct WalFile<F: File, L: File> {
+    state: RwLock<State<F, L>>,
+}
+
+/// The files of a [WalFile] and the blocks written since the last checkpoint.
+struct State<F: File, L: File> {
+    data: F,
+    log: L,
+    opened: bool,
+    /// The blocks written since the last checkpoint, by index.
+    blocks: BTreeMap<u64, Vec<u8>>,
+    /// The blocks written since the last commit.
+    dirty: BTreeSet<u64>,
+    /// The size of the file, including the blocks that were not copied to the data file.
+    size: usize,
+    /// The size of the data file.
+    data_size: usize,
+    /// The size of the log, up to the end of the last commit.
+    log_size: usize,
+    /// The size of the log when it was last synced.
+    synced_log_size: usize,
+    salt: u64,
+    checkpoint_size: usize,
+    /// Set when the files are opened for reading only: nothing is written to them.
+    read_only: bool,
+    /// Unset when the files are opened for reading only without the log.
+    log_opened: bool,
+}
+
+impl<F: File, L: File> WalFile<F, L> {
+    /// The log size above which a commit is followed by a checkpoint when none is set.
+    pub const DEFAULT_CHECKPOINT_SIZE: usize = 4 * 1024 * 1024;
+
+    /// Creates a new [WalFile] writing to `data` through the log `log`. The files are not created
+    /// or opened: they are created or opened, and the log is recovered, by [File::create] and
+    /// [File::open].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rouilledb::fs::{File, MemoryFile};
+    /// use rouilledb::wal::WalFile;
+    ///
+    /// let mut file = WalFile::new(MemoryFile::new(), MemoryFile::new());
+    /// file.create().expect("create should not fail");
+    /// file.write(0, b"committed").expect("write should not fail");
+    /// file.sync().expect("sync should not fail");
+    ///
+    /// let mut buffer = [0u8; 9];
+    /// file.read(0, &mut buffer).expect("read should not fail");
+    /// assert_eq!(&buffer, b"committed");
+    /// assert!(file.log_size() > 0);
+    /// ```
+    pub fn new(data: F, log: L) -> Self {
+        WalFile {
+            state: RwLock::new(State {
+                data,
+                log,
+                opened: false,
+                blocks: BTreeMap::new(),
+                dirty: BTreeSet::new(),
+                size: 0,
+                data_size: 0,
+                log_size: 0,
+                synced_log_size: 0,
+                salt: 0,
+                checkpoint_size: Self::DEFAULT_CHECKPOINT_SIZE,
+                read_only: false,
+                log_opened: false,
+            }),
+        }
+    }
+
+    /// Returns the log size above which a commit is followed by a checkpoint.
+    pub fn checkpoint_size(&self) -> usize {
+        self.state().checkpoint_size
+    }
+
+    /// Sets the log size above which a commit is followed by a checkpoint. A larger log makes the
+    /// checkpoints less frequent, but keeps more blocks in memory and makes the recovery longer.
+    pub fn set_checkpoint_size(&mut self, checkpoint_size: usize) {
+        self.state_mut().checkpoint_size = checkpoint_size;
+    }
+
+    /// Returns the size of the log, in bytes.
+    pub fn log_size(&self) -> usize {
+        self.state().log_size
+    }
+
+    /// Returns the size, in bytes, of the commits appended to the log since it was last synced,
+    /// which a loss of power may lose.
+    pub fn unsynced_size(&self) -> usize {
+        let state = self.state();
+        state.log_size - state.synced_log_size
+    }
+
+    /// Returns the size, in bytes, of the blocks written since the last commit, which are only
+    /// kept in memory.
+    pub fn pending_size(&self) -> usize {
+        self.state().dirty.len() * BLOCK_SIZE
+    }
+
+    /// Opens the data file, and the log if `replay` is set, for reading only. The commits found in
+    /// the log are kept in memory, and they are not copied to the data file, so nothing is written
+    /// to the files and they can be opened by another [WalFile] writing to them. If `replay` is
+    /// unset, the log is not read, and the file holds the state of the last checkpoint.
+    ///
+    /// The writes then fail, and [File::close] and [File::sync] only close the files.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is already opened or if the data file or the
+    /// log can't be opened or read.
+    pub fn open_read_only(&mut self, replay: bool) -> Result<(), FileError> {
+        let state = self
+            .state
+            .get_mut()
+            .expect("the state lock should not be poisoned");
+        if state.opened {
+            return Err(FileError::FileOpened(String::from("WalFile")));
+        }
+        state.data.open()?;
+        if replay {
+            state.log.open()?;
+        }
+        state.opened = true;
+        state.read_only = true;
+        state.log_opened = replay;
+        self.refresh()
+    }
+
+    /// Reads the data file and the log again, if they are opened for reading only, to see the
+    /// commits made since by another [WalFile] writing to them. A file opened for writing is always
+    /// up to date and is left unchanged.
+    ///
+    /// The blocks of the log are kept in memory until the next refresh, and they are the blocks
+    /// the writer copies to the data file at its next checkpoint, so the content read does not
+    /// change until then. The blocks written to the data file by later checkpoints are read as
+    /// soon as they are written, so the file should be refreshed before the writer checkpoints.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not opened, or if the data file or the log
+    /// can't be read.
+    pub fn refresh(&self) -> Result<(), FileError> {
+        let mut state = self.state_mut();
+        state.check_opened()?;
+        if !state.read_only {
+            return Ok(());
+        }
+        if state.log_opened {
+            state.read_log()?;
+        } else {
+            state.data_size = state.data.size()?;
+            state.size = state.data_size;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the file is opened for reading only.
+    pub fn is_read_only(&self) -> bool {
+        self.state().read_only
+    }
+
+    /// Commits the pending writes by appending them to the log, as [File::sync] does, but without
+    /// syncing the log, then checkpoints if the log grew past [WalFile::checkpoint_size]. The
+    /// commit survives a crash of the process, but a loss of power may lose it until the log is
+    /// synced by [File::sync] or emptied by a checkpoint.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not opened or is opened for reading only,
+    /// or if the log or the data file can't be written, or synced by a checkpoint.
+    pub fn append_commit(&self) -> Result<(), FileError> {
+        let mut state = self.state_mut();
+        state.check_writable()?;
+        state.append_commit()?;
+        if state.log_size >= state.checkpoint_size {
+            state.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    /// Commits the pending writes, then copies the committed blocks to the data file and empties
+    /// the log.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not opened or is opened for reading only,
+    /// or if the log or the data file can't be written or synced.
+    pub fn checkpoint(&self) -> Result<(), FileError> {
+        let mut state = self.state_mut();
+        state.check_writable()?;
+        state.commit()?;
+        state.checkpoint()
+    }
+
+    /// Consumes the [WalFile] and returns the data file and the log, without committing the
+    /// pending writes or copying the committed blocks to the data file, as a crash would.
+    pub fn into_files(self) -> (F, L) {
+        let state = self
+            .state
+            .into_inner()
+            .expect("the state lock should not be poisoned");
+        (state.data, state.log)
+    }
+
+    fn state(&self) -> RwLockReadGuard<'_, State<F, L>> {
+        self.state
+            .read()
+            .expect("the state lock should not be poisoned")
+    }
+
+    fn state_mut(&self) -> RwLockWriteGuard<'_, State<F, L>> {
+        self.state
+            .write()
+            .expect("the state lock should not be poisoned")
+    }
+}
+
+impl<F: File, L: File> State<F, L> {
+    fn check_opened(&self) -> Result<(), FileError> {
+        if !self.opened {
+            return Err(FileError::FileNotOpened(String::from("WalFile")));
+        }
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<(), FileError> {
+        self.check_opened()?;
+        if self.read_only {
+            return Err(FileError::ReadOnly(String::from("WalFile")));
+        }
+        Ok(())
+    }
+
+    /// Appends the blocks written since the last commit and a commit frame to the log, then syncs
+    /// the log if it holds commits not yet synced.
+    fn commit(&mut self) -> Result<(), FileError> {
+        self.append_commit()?;
+        if self.synced_log_size < self.log_size {
+            self.log.sync()?;
+            self.synced_log_size = self.log_size;
+        }
+        Ok(())
+    }
+
+    /// Appends the blocks written since the last commit and a commit frame to the log, without
+    /// syncing it.
+    fn append_commit(&mut self) -> Result<(), FileError> {
+        if self.dirty.is_empty() {
+            return Ok(());
+        }
+
+        let mut frames =
+            Vec::with_capacity(self.dirty.len() * WRITE_FRAME_SIZE + COMMIT_FRAME_SIZE);
+        for index in &self.dirty {
+            let start = frames.len();
+            let header = FrameHeader {
+                kind: WRITE_FRAME,
+                value: *index,
+            };
+            header.append(&mut frames);
+            frames.extend_from_slice(&self.blocks[index]);
+            let checksum = xxhash64(&frames[start..], self.salt);
+            frames.extend_from_slice(&checksum.to_le_bytes());
+        }
+        let start = frames.len();
+        let header = FrameHeader {
+            kind: COMMIT_FRAME,
+            value: self.size as u64,
+        };
+        header.append(&mut frames);
+        let checksum = xxhash64(&frames[start..], self.salt);
+        frames.extend_from_slice(&checksum.to_le_bytes());
+
+        self.log.write(self.log_size, &frames)?;
+        self.log_size += frames.len();
+        self.dirty.clear();
+        Ok(())
+    }
+
+    /// Copies the committed blocks to the data file, syncs it and starts a new, empty, log.
+    fn checkpoint(&mut self) -> Result<(), FileError> {
+        debug_assert!(self.dirty.is_empty());
+        for (&index, block) in &self.blocks {
+            let start = index as usize * BLOCK_SIZE;
+            let len = BLOCK_SIZE.min(self.size - start);
+            self.data.write(start, &block[..len])?;
+        }
+        self.data.sync()?;
+        self.blocks.clear();
+        self.data_size = self.data_size.max(self.size);
+
+        // The frames left in the log have the previous salt, so they are ignored.
+        self.salt = self.salt.wrapping_add(1);
+        self.write_log_header()
+    }
+
+    fn write_log_header(&mut self) -> Result<(), FileError> {
+        let header = LogHeader {
+            magic: LOG_MAGIC,
+            salt: self.salt,
+        };
+        let mut bytes = Vec::with_capacity(LogHeader::SIZE);
+        header.append(&mut bytes);
+        self.log.write(0, &bytes)?;
+        self.log.sync()?;
+        self.log_size = LogHeader::SIZE;
+        self.synced_log_size = LogHeader::SIZE;
+        Ok(())
+    }
+
+    /// Reads the complete commits of the log, then copies their blocks to the data file with a
+    /// checkpoint.
+    fn recover(&mut self) -> Result<(), FileError> {
+        self.log_opened = true;
+        if !self.read_log()? {
+            // The salt of the frames left in the log is unknown, so a fixed one could match them.
+            self.salt = rand::random();
+            return self.write_log_header();
+        }
+        self.checkpoint()
+    }
+
+    /// Reads the blocks of the complete commits of the log. Returns `false` if the log does not
+    /// start with a valid header, and then holds no commit.
+    fn read_log(&mut self) -> Result<bool, FileError> {
+        self.data_size = self.data.size()?;
+        self.size = self.data_size;
+        self.blocks.clear();
+        self.dirty.clear();
+
+        let log_size = self.log.size()?;
+        let mut log = vec![0u8; log_size];
+        self.log.read(0, &mut log)?;
+        let Ok(header) = LogHeader::read(&log) else {
+            return Ok(false);
+        };
+        if header.magic != LOG_MAGIC {
+            return Ok(false);
+        }
+        self.salt = header.salt;
+
+        let mut pending = BTreeMap::new();
+        let mut offset = LogHeader::SIZE;
+        while let Some(&kind) = log.get(offset) {
+            let frame_size = match kind {
+                WRITE_FRAME => WRITE_FRAME_SIZE,
+                COMMIT_FRAME => COMMIT_FRAME_SIZE,
+                _ => break,
+            };
+            let Some(frame) = log.get(offset..offset + frame_size) else {
+                break;
+            };
+            let (content, checksum) = frame.split_at(frame_size - 8);
+            let checksum =
+                u64::from_le_bytes(checksum.try_into().expect("slice should be 8 bytes"));
+            if xxhash64(content, self.salt) != checksum {
+                break;
+            }
+            let header = FrameHeader::read(content).expect("the frame should hold its header");
+            if kind == WRITE_FRAME {
+                pending.insert(header.value, content[FrameHeader::SIZE..].to_vec());
+            } else {
+                self.blocks.append(&mut pending);
+                self.size = self.size.max(header.value as usize);
+            }
+            offset += frame_size;
+        }
+        self.log_size = offset;
+        self.synced_log_size = offset;
+        Ok(true)
+    }
+
+    /// Reads bytes from the data file. The bytes past its end are zeros.
+    fn read_data(&self, offset: usize, buffer: &mut [u8]) -> Result<(), FileError> {
+        let available = self.data_size.saturating_sub(offset).min(buffer.len());
+        if available > 0 {
+            self.data.read(offset, &mut buffer[..available])?;
+        }
+        buffer[available..].fill(0);
+        Ok(())
+    }
+}
+
+impl<F: File, L: File> File for WalFile<F, L> {
+    fn create(&mut self) -> Result<(), FileError> {
+        let state = self
+            .state
+            .get_mut()
+            .expect("the state lock should not be poisoned");
+        if state.opened {
+            return Err(FileError::FileOpened(String::from("WalFile")));
+        }
+        state.data.create()?;
+        state.log.create()?;
+        state.opened = true;
+        state.recover()
+    }
+
+    /// Commits the pending writes, copies the committed blocks to the data file and closes the
+    /// data file and the log. A file opened for reading only is closed without writing.
+    fn close(&mut self) -> Result<(), FileError> {
+        if !self.is_read_only() {
+            self.checkpoint()?;
+        }
+        let state = self
+            .state
+            .get_mut()
+            .expect("the state lock should not be poisoned");
+        state.opened = false;
+        state.read_only = false;
+        state.data.close()?;
+        if std::mem::take(&mut state.log_opened) {
+            state.log.close()?;
+        }
+        Ok(())
+    }
+
+    /// Opens the data file and the log, then recovers the commits found in the log.
+    fn open(&mut self) -> Result<(), FileError> {
+        let state = self
+            .state
+            .get_mut()
+            .expect("the state lock should not be poisoned");
+        if state.opened {
+            return Err(FileError::FileOpened(String::from("WalFile")));
+        }
+        state.data.open()?;
+        state.log.open()?;
+        state.opened = true;
+        state.recover()
+    }
+
+    fn delete(&mut self) -> Result<(), FileError> {
+        let state = self
+            .state
+            .get_mut()
+            .expect("the state lock should not be poisoned");
+        if state.opened {
+            return Err(FileError::FileOpened(String::from("WalFile")));
+        }
+        state.data.delete()?;
+        state.log.delete()
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), FileError> {
+        let state = self
+            .state
+            .get_mut()
+            .expect("the state lock should not be poisoned");
+        state.check_writable()?;
+
+        let end = offset + data.len();
+        let mut position = offset;
+        while position < end {
+            let index = (position / BLOCK_SIZE) as u64;
+            let block_start = index as usize * BLOCK_SIZE;
+            let stop = end.min(block_start + BLOCK_SIZE);
+            let mut block = match state.blocks.remove(&index) {
+                Some(block) => block,
+                None if position == block_start && stop == block_start + BLOCK_SIZE => {
+                    vec![0; BLOCK_SIZE]
+                }
+                None => {
+                    let mut block = vec![0; BLOCK_SIZE];
+                    state.read_data(block_start, &mut block)?;
+                    block
+                }
+            };
+            block[position - block_start..stop - block_start]
+                .copy_from_slice(&data[position - offset..stop - offset]);
+            state.blocks.insert(index, block);
+            state.dirty.insert(index);
+            position = stop;
+        }
+        state.size = state.size.max(end);
+        Ok(())
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> Result<(), FileError> {
+        let state = self.state();
+        state.check_opened()?;
+        let end = offset + buffer.len();
+        if end > state.size {
+            return Err(FileError::EndOfFileRead {
+                filename: String::from("WalFile"),
+                file_size: state.size,
+                offset,
+                read_size: buffer.len(),
+            });
+        }
+
+        let mut position = offset;
+        while position < end {
+            let index = (position / BLOCK_SIZE) as u64;
+            let block_start = index as usize * BLOCK_SIZE;
+            if let Some(block) = state.blocks.get(&index) {
+                let stop = end.min(block_start + BLOCK_SIZE);
+                buffer[position - offset..stop - offset]
+                    .copy_from_slice(&block[position - block_start..stop - block_start]);
+                position = stop;
+                continue;
+            }
+            // The blocks up to the next one written since the checkpoint are read at once.
+            let stop = state
+                .blocks
+                .range(index..)
+                .next()
+                .map_or(end, |(&next, _)| end.min(next as usize * BLOCK_SIZE));
+            state.read_data(position, &mut buffer[position - offset..stop - offset])?;
+            position = stop;
+        }
+        Ok(())
+    }
+
+    /// Commits the writes made since the last commit, then checkpoints if the log grew past
+    /// [WalFile::checkpoint_size].
+    fn sync(&self) -> Result<(), FileError> {
+        let mut state = self.state_mut();
+        state.check_opened()?;
+        if state.read_only {
+            return Ok(());
+        }
+        state.commit()?;
+        if state.log_size >= state.checkpoint_size {
+            state.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Result<usize, FileError> {
+        let state = self.state();
+        state.check_opened()?;
+        Ok(state.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::MemoryFile;
+
+    use super::*;
+
+    fn create_file() -> WalFile<MemoryFile, MemoryFile> {
+        let mut file = WalFile::new(MemoryFile::new(), MemoryFile::new());
+        file.create().expect("create should not fail");
+        file
+    }
+
+    /// Simulates a crash: the files are closed without a commit or a checkpoint, then reopened.
+    fn crash_and_reopen(file: WalFile<MemoryFile, MemoryFile>) -> WalFile<MemoryFile, MemoryFile> {
+        let (mut data, mut log) = file.into_files();
+        data.close().expect("close should not fail");
+        log.close().expect("close should not fail");
+        let mut file = WalFile::new(data, log);
+        file.open().expect("open should not fail");
+        file
+    }
+
+    fn read(file: &WalFile<MemoryFile, MemoryFile>, offset: usize, len: usize) -> Vec<u8> {
+        let mut buffer = vec![0; len];
+        file.read(offset, &mut buffer)
+            .expect("read should not fail");
+        buffer
+    }
+
+    /// The committed writes are recovered after a crash, but not the writes made after the last
+    /// commit.
+    #[test]
+    fn open_recovers_committed_writes() {
+        let mut file = create_file();
+        file.write(0, &[1; 1000]).expect("write should not fail");
+        file.sync().expect("sync should not fail");
+        file.write(600, &[2; 1000]).expect("write should not fail");
+
+        let file = crash_and_reopen(file);
+
+        assert_eq!(file.size().expect("size should not fail"), 1000);
+        assert_eq!(read(&file, 0, 1000), vec![1; 1000]);
+        assert_eq!(file.log_size(), LogHeader::SIZE);
+    }
+
+    /// A commit appended to the log without syncing it is recovered after a crash of the process,
+    /// and is synced by the next sync.
+    #[test]
+    fn append_commit_survives_crash() {
+        let mut file = create_file();
+        file.write(0, &[6; 1000]).expect("write should not fail");
+        file.append_commit().expect("append_commit should not fail");
+        let unsynced = file.unsynced_size();
+        file.sync().expect("sync should not fail");
+        let synced = file.unsynced_size();
+        file.write(0, &[7; 1000]).expect("write should not fail");
+        file.append_commit().expect("append_commit should not fail");
+
+        let file = crash_and_reopen(file);
+
+        assert_eq!(unsynced, 2 * WRITE_FRAME_SIZE + COMMIT_FRAME_SIZE);
+        assert_eq!(synced, 0);
+        assert_eq!(read(&file, 0, 1000), vec![7; 1000]);
+    }
+
+    /// The writes are not copied to the data file before a checkpoint.
+    #[test]
+    fn checkpoint_copies_blocks_to_data_file() {
+        let mut file = create_file();
+        file.write(100, &[3; 2000]).expect("write should not fail");
+        file.sync().expect("sync should not fail");
+        let log_size = file.log_size();
+
+        file.checkpoint().expect("checkpoint should not fail");
+        let (data, _) = file.into_files();
+
+        assert!(log_size > 4 * WRITE_FRAME_SIZE);
+        assert_eq!(data.size().expect("size should not fail"), 2100);
+        let mut buffer = vec![0; 2000];
+        data.read(100, &mut buffer).expect("read should not fail");
+        assert_eq!(buffer, vec![3; 2000]);
+    }
+
+    /// A commit whose frames were not completely written to the log is discarded, and so are
+    /// the frames of an older log.
+    #[test]
+    fn open_ignores_torn_commit() {
+        let mut file = create_file();
+        file.write(0, &[4; 512]).expect("write should not fail");
+        file.sync().expect("sync should not fail");
+        file.write(0, &[5; 512]).expect("write should not fail");
+        file.sync().expect("sync should not fail");
+        let log_size = file.log_size();
+        let (data, mut log) = file.into_files();
+        log.write(log_size - 1, &[0xff])
+            .expect("write should not fail");
+        let file = WalFile::new(data, log);
+        let mut file = crash_and_reopen(file);
+
+        let recovered = read(&file, 0, 512);
+        file.write(512, &[6; 512]).expect("write should not fail");
+        file.sync().expect("sync should not fail");
+        let file = crash_and_reopen(file);
+
+        assert_eq!(recovered, vec![4; 512]);
+        assert_eq!(read(&file, 0, 1024), [[4; 512], [6; 512]].concat());
+    }
+
+    /// A read spanning written and unwritten blocks returns the written bytes, and the bytes that
+    /// were never written are zeros.
+    #[test]
+    fn read_merges_written_blocks_and_data_file() {
+        let mut file = create_file();
+        file.write(0, &[7; 1536]).expect("write should not fail");
+        file.checkpoint().expect("checkpoint should not fail");
+        file.write(700, &[8; 10]).expect("write should not fail");
+        file.write(3000, &[9; 10]).expect("write should not fail");
+
+        let data = read(&file, 0, 3010);
+
+        assert_eq!(data[..700], [7; 700]);
+        assert_eq!(data[700..710], [8; 10]);
+        assert_eq!(data[710..1536], [7; 826]);
+        assert!(data[1536..3000].iter().all(|&byte| byte == 0));
+        assert_eq!(data[3000..], [9; 10]);
+    }
+
+    /// A file opened for reading only reads the commits of the log if they are replayed, and
+    /// writes nothing to the files.
+    #[test]
+    fn open_read_only_writes_nothing() {
+        let mut file = create_file();
+        file.write(0, &[1; 512]).expect("write should not fail");
+        file.checkpoint().expect("checkpoint should not fail");
+        file.write(0, &[2; 1024]).expect("write should not fail");
+        file.sync().expect("sync should not fail");
+        let log_size = file.log_size();
+        let (mut data, mut log) = file.into_files();
+        data.close().expect("close should not fail");
+        log.close().expect("close should not fail");
+
+        let mut replayed = WalFile::new(data, log);
+        replayed
+            .open_read_only(true)
+            .expect("open_read_only should not fail");
+        let replayed_data = read(&replayed, 0, 1024);
+        let write = replayed.write(0, &[3; 512]);
+        replayed.close().expect("close should not fail");
+        let (data, log) = replayed.into_files();
+        let mut ignored = WalFile::new(data, log);
+        ignored
+            .open_read_only(false)
+            .expect("open_read_only should not fail");
+        let ignored_size = ignored.size().expect("size should not fail");
+        let ignored_data = read(&ignored, 0, 512);
+        ignored.close().expect("close should not fail");
+        let (data, log) = ignored.into_files();
+
+        assert_eq!(replayed_data, vec![2; 1024]);
+        assert!(matches!(write, Err(FileError::ReadOnly(_))));
+        assert_eq!((ignored_size, ignored_data), (512, vec![1; 512]));
+        let mut file = WalFile::new(data, log);
+        file.open().expect("open should not fail");
+        assert_eq!(read(&file, 0, 1024), vec![2; 1024]);
+        assert!(log_size > LogHeader::SIZE);
+    }
+
+    /// The frames left in a log whose header is lost are ignored, even if their checksums were
+    /// seeded with the salt of a new log.
+    #[test]
+    fn open_ignores_frames_of_lost_log() {
+        let mut data = MemoryFile::new();
+        data.create().expect("create should not fail");
+        let mut log = MemoryFile::new();
+        log.create().expect("create should not fail");
+        let mut frames = vec![0; LogHeader::SIZE];
+        for (kind, value, content) in [
+            (WRITE_FRAME, 0, &[9; BLOCK_SIZE][..]),
+            (COMMIT_FRAME, 512, &[]),
+        ] {
+            let start = frames.len();
+            FrameHeader { kind, value }.append(&mut frames);
+            frames.extend_from_slice(content);
+            let checksum = xxhash64(&frames[start..], 0);
+            frames.extend_from_slice(&checksum.to_le_bytes());
+        }
+        log.write(0, &frames).expect("write should not fail");
+
+        let file = crash_and_reopen(WalFile::new(data, log));
+        let recovered = file.size().expect("size should not fail");
+        let file = crash_and_reopen(file);
+
+        assert_eq!(recovered, 0);
+        assert_eq!(file.size().expect("size should not fail"), 0);
+        assert_eq!(file.log_size(), LogHeader::SIZE);
+    }
+}